@@ -0,0 +1,361 @@
+use btree::BTreeSet as _;
+use btree::btree::{
+    BEpsilonTreeSet, BPlusTreeSet, Eytzinger, EytzingerLayout, FrozenBTreeSet, Global, OlcBTreeSet,
+    OrdComparator, SimpleBTreeSet,
+};
+use btree::workload::{Kind, Op, Workload};
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::hint::black_box;
+use std::sync::RwLock;
+use std::thread;
+
+const SIZE: usize = 10_000;
+
+/// The workloads the benches are run with. The random keys span the whole
+/// `u64` range, and the zipfian keys have as many ranks as there are keys.
+fn distributions() -> [(&'static str, Vec<u64>); 4] {
+    let keys = |kind, seed, key_space| {
+        Workload::new(kind, seed)
+            .with_key_space(key_space)
+            .keys(SIZE)
+    };
+    [
+        ("sequential", keys(Kind::Sequential, 0, u64::MAX)),
+        (
+            "random",
+            keys(Kind::Uniform, 0x9e37_79b9_7f4a_7c15, u64::MAX),
+        ),
+        (
+            "zipfian",
+            keys(Kind::Zipfian, 0x2545_f491_4f6c_dd1d, SIZE as u64),
+        ),
+        (
+            "clustered",
+            keys(Kind::Clustered, 0x853c_49e6_748f_ea9b, u64::MAX),
+        ),
+    ]
+}
+
+fn simple_from(keys: &[u64]) -> SimpleBTreeSet<u64> {
+    let mut set = SimpleBTreeSet::new();
+    for &key in keys {
+        let _ = set.insert(key);
+    }
+    set
+}
+
+/// A `SimpleBTreeSet` whose nodes keep their keys in Eytzinger order.
+type EytzingerTree = SimpleBTreeSet<u64, 6, Global, OrdComparator, EytzingerLayout>;
+
+fn eytzinger_from(keys: &[u64]) -> EytzingerTree {
+    let mut set = EytzingerTree::default();
+    for &key in keys {
+        let _ = set.insert(key);
+    }
+    set
+}
+
+fn bplus_from(keys: &[u64]) -> BPlusTreeSet<u64> {
+    let mut set = BPlusTreeSet::new();
+    for &key in keys {
+        let _ = set.insert(key);
+    }
+    set
+}
+
+fn std_from(keys: &[u64]) -> BTreeSet<u64> {
+    keys.iter().copied().collect()
+}
+
+fn bench_insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert");
+
+    for (name, keys) in distributions() {
+        group.bench_with_input(BenchmarkId::new("simple", name), &keys, |b, keys| {
+            b.iter(|| simple_from(keys))
+        });
+        group.bench_with_input(BenchmarkId::new("std", name), &keys, |b, keys| {
+            b.iter(|| std_from(keys))
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lookup");
+
+    for (name, keys) in distributions() {
+        let simple = simple_from(&keys);
+        let eytzinger = eytzinger_from(&keys);
+        let frozen: FrozenBTreeSet<u64> = simple_from(&keys).freeze();
+        let std = std_from(&keys);
+
+        group.bench_with_input(BenchmarkId::new("simple", name), &keys, |b, keys| {
+            b.iter(|| keys.iter().filter(|key| simple.contains(key)).count())
+        });
+        group.bench_with_input(BenchmarkId::new("eytzinger", name), &keys, |b, keys| {
+            b.iter(|| keys.iter().filter(|key| eytzinger.contains(key)).count())
+        });
+        group.bench_with_input(BenchmarkId::new("frozen", name), &keys, |b, keys| {
+            b.iter(|| keys.iter().filter(|key| frozen.contains(key)).count())
+        });
+        group.bench_with_input(BenchmarkId::new("std", name), &keys, |b, keys| {
+            b.iter(|| keys.iter().filter(|key| std.contains(key)).count())
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_remove(c: &mut Criterion) {
+    let mut group = c.benchmark_group("remove");
+
+    for (name, keys) in distributions() {
+        group.bench_with_input(BenchmarkId::new("simple", name), &keys, |b, keys| {
+            b.iter_batched(
+                || simple_from(keys),
+                |mut set| {
+                    for key in keys {
+                        let _ = set.remove(key);
+                    }
+                    set
+                },
+                criterion::BatchSize::LargeInput,
+            )
+        });
+        group.bench_with_input(BenchmarkId::new("std", name), &keys, |b, keys| {
+            b.iter_batched(
+                || std_from(keys),
+                |mut set| {
+                    for key in keys {
+                        set.remove(key);
+                    }
+                    set
+                },
+                criterion::BatchSize::LargeInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_iter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("iter");
+
+    for (name, keys) in distributions() {
+        let simple = simple_from(&keys);
+        let bplus = bplus_from(&keys);
+        let std = std_from(&keys);
+
+        group.bench_function(BenchmarkId::new("simple", name), |b| {
+            b.iter(|| {
+                simple
+                    .iter()
+                    .fold(0u64, |sum, &key| sum.wrapping_add(black_box(key)))
+            })
+        });
+        group.bench_function(BenchmarkId::new("bplus", name), |b| {
+            b.iter(|| {
+                bplus
+                    .iter()
+                    .fold(0u64, |sum, &key| sum.wrapping_add(black_box(key)))
+            })
+        });
+        group.bench_function(BenchmarkId::new("bplus_chunks", name), |b| {
+            b.iter(|| {
+                bplus.iter_chunks().fold(0u64, |sum, chunk| {
+                    chunk
+                        .iter()
+                        .fold(sum, |sum, &key| sum.wrapping_add(black_box(key)))
+                })
+            })
+        });
+        group.bench_function(BenchmarkId::new("std", name), |b| {
+            b.iter(|| {
+                std.iter()
+                    .fold(0u64, |sum, &key| sum.wrapping_add(black_box(key)))
+            })
+        });
+    }
+
+    group.finish();
+}
+
+/// Compares the Bε-tree against the plain trees on streams of writes: random
+/// insertions, and a workload which removes far more often than it inserts.
+/// The Bε-tree is run both with blind writes, which is what it is built for,
+/// and through `BTreeSet`, whose writes search the tree first.
+///
+/// Buffering only pays off once the tree outgrows the caches, so the streams
+/// are a hundred times longer than the other benches.
+fn bench_write_heavy(c: &mut Criterion) {
+    let mut group = c.benchmark_group("write_heavy");
+    group.sample_size(10);
+    let workloads = [
+        (
+            "random",
+            Workload::new(Kind::Uniform, 0x9e37_79b9_7f4a_7c15),
+        ),
+        (
+            "delete_heavy",
+            Workload::new(Kind::DeleteHeavy, 0x2545_f491_4f6c_dd1d),
+        ),
+    ];
+
+    for (name, workload) in workloads {
+        let ops: Vec<Op> = workload
+            .ops()
+            .filter(|op| !matches!(op, Op::Search(_)))
+            .take(SIZE * 100)
+            .collect();
+
+        group.bench_with_input(BenchmarkId::new("bepsilon_blind", name), &ops, |b, ops| {
+            b.iter(|| {
+                let mut set = BEpsilonTreeSet::<u64>::new();
+                for op in ops {
+                    match *op {
+                        Op::Insert(key) => set.put(key),
+                        Op::Remove(key) => set.delete(key),
+                        Op::Search(_) => {}
+                    }
+                }
+                set
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("bepsilon", name), &ops, |b, ops| {
+            b.iter(|| apply(BEpsilonTreeSet::<u64>::new(), ops))
+        });
+        group.bench_with_input(BenchmarkId::new("bplus", name), &ops, |b, ops| {
+            b.iter(|| apply(BPlusTreeSet::<u64>::new(), ops))
+        });
+        group.bench_with_input(BenchmarkId::new("simple", name), &ops, |b, ops| {
+            b.iter(|| apply(SimpleBTreeSet::<u64>::new(), ops))
+        });
+    }
+
+    group.finish();
+}
+
+fn apply<T: btree::BTreeSet<Key = u64>>(mut set: T, ops: &[Op]) -> T {
+    for op in ops {
+        let _ = match *op {
+            Op::Insert(key) => set.insert(key),
+            Op::Remove(key) => set.remove(&key).map(|_| ()),
+            Op::Search(_) => Ok(()),
+        };
+    }
+    set
+}
+
+/// Scans the keys from the left, as the nodes of a `SimpleBTreeSet` with the
+/// `SortedLayout<N>` layout do when they hold at most `N` keys.
+fn linear_search(keys: &[u64], key: u64) -> Result<usize, usize> {
+    for (idx, &k) in keys.iter().enumerate() {
+        match k.cmp(&key) {
+            Ordering::Less => {}
+            Ordering::Equal => return Ok(idx),
+            Ordering::Greater => return Err(idx),
+        }
+    }
+    Err(keys.len())
+}
+
+/// Compares linear and binary search within a single node, to find the node
+/// size below which a `SortedLayout` should scan its keys, and both against
+/// the branchless search of the Eytzinger layout.
+fn bench_node_search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("node_search");
+
+    for len in [2, 4, 8, 12, 16, 32, 64, 256] {
+        // Even keys, probed with every key up to the largest one plus one, so
+        // that half of the probes hit and the rest miss.
+        let keys: Vec<u64> = (0..len as u64).map(|k| k * 2).collect();
+        let probes: Vec<u64> = Workload::new(Kind::Uniform, len as u64)
+            .with_key_space(len as u64 * 2 + 1)
+            .keys(SIZE);
+
+        group.bench_with_input(BenchmarkId::new("linear", len), &probes, |b, probes| {
+            b.iter(|| {
+                probes
+                    .iter()
+                    .filter(|&&key| linear_search(black_box(&keys), key).is_ok())
+                    .count()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("binary", len), &probes, |b, probes| {
+            b.iter(|| {
+                probes
+                    .iter()
+                    .filter(|&&key| black_box(&keys).binary_search(&key).is_ok())
+                    .count()
+            })
+        });
+
+        let layout = Eytzinger::from_sorted(keys.iter().copied());
+        group.bench_with_input(BenchmarkId::new("eytzinger", len), &probes, |b, probes| {
+            b.iter(|| {
+                probes
+                    .iter()
+                    .filter(|&&key| black_box(&layout).search_by(|k| k.cmp(&key)).is_ok())
+                    .count()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+/// Compares the lock-free reads of the optimistically coupled tree against
+/// reads under a lock, by looking up every key from several threads at once.
+/// The pessimistic tree is a `SimpleBTreeSet` behind an `RwLock`, so every
+/// read takes the same reader lock, and contends on its counter.
+fn bench_concurrent_lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("concurrent_lookup");
+    let (_, keys) = &distributions()[1];
+    let olc = OlcBTreeSet::<u64>::new();
+    for &key in keys {
+        let _ = olc.insert(key);
+    }
+    let locked = RwLock::new(simple_from(keys));
+
+    for threads in [1, 2, 4, 8] {
+        group.bench_with_input(BenchmarkId::new("olc", threads), keys, |b, keys| {
+            b.iter(|| read_from(threads, keys, |key| olc.contains(key)))
+        });
+        group.bench_with_input(BenchmarkId::new("rwlock", threads), keys, |b, keys| {
+            b.iter(|| read_from(threads, keys, |key| locked.read().unwrap().contains(key)))
+        });
+    }
+
+    group.finish();
+}
+
+/// Looks up every key from each of the threads, and returns how many were
+/// found.
+fn read_from(threads: usize, keys: &[u64], contains: impl Fn(&u64) -> bool + Sync) -> usize {
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|_| scope.spawn(|| keys.iter().filter(|key| contains(key)).count()))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .sum()
+    })
+}
+
+criterion_group!(
+    benches,
+    bench_insert,
+    bench_lookup,
+    bench_remove,
+    bench_iter,
+    bench_write_heavy,
+    bench_node_search,
+    bench_concurrent_lookup
+);
+criterion_main!(benches);
@@ -0,0 +1,86 @@
+//! Compares `UringPager` with the `FilePager` it batches the I/O of, on
+//! reads of single pages, and on range scans of a tree, which read pages
+//! ahead.
+
+use btree::BTreeSet as _;
+use btree::btree::DiskBTreeSet;
+use btree::storage::{FilePager, Pager, UringPager};
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+use std::path::PathBuf;
+
+const PAGE_SIZE: usize = 4096;
+const SIZE: u64 = 100_000;
+
+type Tree<P> = DiskBTreeSet<u64, 16, P>;
+
+fn path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("btree-bench-{name}-{}", std::process::id()))
+}
+
+/// Writes a tree of `SIZE` keys into a new file, and returns its path.
+fn tree_file(name: &str) -> PathBuf {
+    let path = path(name);
+    let _ = std::fs::remove_file(&path);
+    let mut tree = Tree::open(FilePager::open(&path, PAGE_SIZE).unwrap()).unwrap();
+    for key in 0..SIZE {
+        tree.insert(key * 7919 % SIZE).unwrap();
+    }
+    tree.sync().unwrap();
+    path
+}
+
+fn bench_read_page(c: &mut Criterion) {
+    let path = tree_file("read");
+    let mut group = c.benchmark_group("read_page");
+    let mut buf = vec![0; PAGE_SIZE];
+
+    let pager = FilePager::open(&path, PAGE_SIZE).unwrap();
+    let count = pager.page_count();
+    group.bench_function("file", |b| {
+        let mut id = 0;
+        b.iter(|| {
+            id = (id + 7919) % count;
+            pager.read_page(black_box(id), &mut buf).unwrap();
+        })
+    });
+
+    let pager = UringPager::open(&path, PAGE_SIZE).unwrap();
+    group.bench_function("uring", |b| {
+        let mut id = 0;
+        b.iter(|| {
+            id = (id + 7919) % count;
+            pager.read_page(black_box(id), &mut buf).unwrap();
+        })
+    });
+
+    group.finish();
+    std::fs::remove_file(&path).unwrap();
+}
+
+fn bench_range_scan(c: &mut Criterion) {
+    let path = tree_file("scan");
+    let mut group = c.benchmark_group("range_scan");
+
+    // Every scan opens the tree again, so no node is in memory yet.
+    for len in [100, 10_000] {
+        group.bench_with_input(BenchmarkId::new("file", len), &len, |b, &len| {
+            b.iter(|| {
+                let tree = Tree::open(FilePager::open(&path, PAGE_SIZE).unwrap()).unwrap();
+                black_box(tree.range(1000..1000 + len).unwrap().len());
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("uring", len), &len, |b, &len| {
+            b.iter(|| {
+                let tree = Tree::open(UringPager::open(&path, PAGE_SIZE).unwrap()).unwrap();
+                black_box(tree.range(1000..1000 + len).unwrap().len());
+            })
+        });
+    }
+
+    group.finish();
+    std::fs::remove_file(&path).unwrap();
+}
+
+criterion_group!(benches, bench_read_page, bench_range_scan);
+criterion_main!(benches);
@@ -0,0 +1,66 @@
+//! Decodes the fuzzer input into a stream of tree operations, and runs them
+//! against `SimpleBTreeSet` and the standard library's `BTreeSet`, checking
+//! the invariants of the tree after every operation.
+//!
+//! Run with `cargo fuzz run operations` from the repository root.
+
+#![no_main]
+
+use btree::BTreeSet;
+use btree::btree::SimpleBTreeSet;
+use libfuzzer_sys::fuzz_target;
+use std::collections::BTreeSet as StdBTreeSet;
+
+#[derive(Debug)]
+enum Op {
+    Insert(u8),
+    Remove(u8),
+    Search(u8),
+    /// Splits the tree at the key, and appends the upper half back.
+    SplitOffAndAppend(u8),
+}
+
+impl Op {
+    /// Decodes an operation from two bytes: the first one selects the kind of
+    /// the operation, the second one is the key. Keys are kept to a single byte
+    /// so that the operations revisit the same keys often.
+    fn decode(bytes: &[u8]) -> Op {
+        let key = bytes[1];
+        match bytes[0] % 8 {
+            0..=2 => Op::Insert(key),
+            3..=5 => Op::Remove(key),
+            6 => Op::Search(key),
+            _ => Op::SplitOffAndAppend(key),
+        }
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut tree = SimpleBTreeSet::<u8, 2>::new();
+    let mut reference = StdBTreeSet::new();
+
+    for op in data.chunks_exact(2).map(Op::decode) {
+        match op {
+            Op::Insert(key) => {
+                assert_eq!(tree.insert(key).is_ok(), reference.insert(key), "{op:?}")
+            }
+            Op::Remove(key) => assert_eq!(tree.remove(&key).ok(), reference.take(&key), "{op:?}"),
+            Op::Search(key) => assert_eq!(tree.search(&key).ok(), reference.get(&key), "{op:?}"),
+            Op::SplitOffAndAppend(key) => {
+                let upper = tree.split_off(&key);
+                upper
+                    .check_invariants()
+                    .unwrap_or_else(|e| panic!("{op:?}: {e}\n{upper:#?}"));
+                tree.check_invariants()
+                    .unwrap_or_else(|e| panic!("{op:?}: {e}\n{tree:#?}"));
+                tree.append(upper);
+            }
+        }
+
+        if let Err(error) = tree.check_invariants() {
+            panic!("{op:?} broke the tree: {error}\n{tree:#?}");
+        }
+    }
+
+    assert!(tree.iter().eq(reference.iter()));
+});
@@ -0,0 +1,63 @@
+/*
+ * C interface to the btree crate, built with the `ffi` feature:
+ *
+ *     cargo rustc --release --features ffi --crate-type staticlib
+ *
+ * Every handle returned by a `_new` function must be released with the
+ * matching `_free` function. Iterators remember the last key they returned,
+ * so the tree may be modified while iterating, but an iterator must be freed
+ * before its tree.
+ */
+
+#ifndef BTREE_H
+#define BTREE_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum BTreeStatus {
+    BTREE_OK = 0,
+    BTREE_KEY_NOT_FOUND = 1,
+    BTREE_KEY_ALREADY_EXISTS = 2,
+    BTREE_INVALID_ARGUMENT = 3,
+} BTreeStatus;
+
+/* A tree of 64-bit integers. */
+typedef struct BTreeI64 BTreeI64;
+typedef struct BTreeI64Iter BTreeI64Iter;
+
+BTreeI64 *btree_i64_new(void);
+void btree_i64_free(BTreeI64 *tree);
+BTreeStatus btree_i64_insert(BTreeI64 *tree, int64_t key);
+bool btree_i64_contains(const BTreeI64 *tree, int64_t key);
+BTreeStatus btree_i64_remove(BTreeI64 *tree, int64_t key);
+
+BTreeI64Iter *btree_i64_iter_new(const BTreeI64 *tree);
+bool btree_i64_iter_next(BTreeI64Iter *iter, int64_t *key);
+void btree_i64_iter_free(BTreeI64Iter *iter);
+
+/* A tree of byte strings, ordered like memcmp. Keys are copied in. */
+typedef struct BTreeBytes BTreeBytes;
+typedef struct BTreeBytesIter BTreeBytesIter;
+
+BTreeBytes *btree_bytes_new(void);
+void btree_bytes_free(BTreeBytes *tree);
+BTreeStatus btree_bytes_insert(BTreeBytes *tree, const uint8_t *key, size_t len);
+bool btree_bytes_contains(const BTreeBytes *tree, const uint8_t *key, size_t len);
+BTreeStatus btree_bytes_remove(BTreeBytes *tree, const uint8_t *key, size_t len);
+
+BTreeBytesIter *btree_bytes_iter_new(const BTreeBytes *tree);
+/* The key stays valid until the next call, or until the iterator is freed. */
+bool btree_bytes_iter_next(BTreeBytesIter *iter, const uint8_t **key, size_t *len);
+void btree_bytes_iter_free(BTreeBytesIter *iter);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* BTREE_H */
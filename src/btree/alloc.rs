@@ -0,0 +1,69 @@
+//! The allocator the nodes of a tree are placed in.
+//!
+//! With the `allocator_api` feature, which requires a nightly compiler, these
+//! are the unstable `Allocator` trait and `Global` allocator of the standard
+//! library, so any allocator can be passed to `SimpleBTreeSet::new_in`.
+//! Without it, they are stand-ins which only allow the global allocator, so
+//! that the allocator parameter of the trees exists on stable as well.
+
+#[cfg(feature = "allocator_api")]
+pub use std::alloc::{Allocator, Global};
+
+#[cfg(not(feature = "allocator_api"))]
+pub use stable::{Allocator, Global};
+
+#[cfg(not(feature = "allocator_api"))]
+mod stable {
+    /// A stand-in for the unstable `Allocator` trait, which is only
+    /// implemented by `Global`.
+    pub trait Allocator {}
+
+    /// The global memory allocator.
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct Global;
+
+    impl Allocator for Global {}
+}
+
+/// Moves the value into a box placed in the given allocator.
+#[cfg(feature = "allocator_api")]
+pub(super) fn boxed_in<T, A: Allocator>(value: T, alloc: A) -> Box<T, A> {
+    Box::new_in(value, alloc)
+}
+
+/// Moves the value into a box placed in the given allocator.
+#[cfg(not(feature = "allocator_api"))]
+pub(super) fn boxed_in<T, A: Allocator>(value: T, _alloc: A) -> Box<T> {
+    Box::new(value)
+}
+
+/// Moves the value into a box placed in the given allocator, failing with
+/// `Error::AllocFailed` instead of aborting if the allocator is out of memory.
+#[cfg(feature = "allocator_api")]
+pub(super) fn try_boxed_in<T, A: Allocator>(value: T, alloc: A) -> crate::Result<Box<T, A>> {
+    Box::try_new_in(value, alloc).map_err(|_| crate::Error::AllocFailed)
+}
+
+/// Moves the value into a box placed in the given allocator, failing with
+/// `Error::AllocFailed` instead of aborting if the allocator is out of memory.
+#[cfg(not(feature = "allocator_api"))]
+pub(super) fn try_boxed_in<T, A: Allocator>(value: T, _alloc: A) -> crate::Result<Box<T>> {
+    use std::alloc::{Layout, alloc};
+
+    let layout = Layout::new::<T>();
+    if layout.size() == 0 {
+        return Ok(Box::new(value));
+    }
+
+    // SAFETY: The layout has a non-zero size, and a non-null pointer returned
+    // by the global allocator for the layout of `T` may be owned by a box once
+    // the value is written to it.
+    unsafe {
+        let ptr = alloc(layout).cast::<T>();
+        if ptr.is_null() {
+            return Err(crate::Error::AllocFailed);
+        }
+        ptr.write(value);
+        Ok(Box::from_raw(ptr))
+    }
+}
@@ -0,0 +1,325 @@
+use super::array::Array;
+use crate::{BTreeSet, Error, Result};
+use std::mem;
+
+/// An in-memory B-tree, whose nodes live in a single arena instead of being
+/// boxed one by one. Children are referenced by their index in the arena.
+///
+/// Freed nodes are kept on a free list and reused by later insertions, so a
+/// tree which stays about the same size stops allocating altogether. Dropping
+/// the tree releases the whole arena at once.
+///
+/// The K type parameter represents the key type, and B is the branching factor.
+pub struct ArenaBTreeSet<K, const B: usize = 6> {
+    nodes: Vec<Node<K, B>>,
+    free: Vec<NodeId>,
+    root: Option<NodeId>,
+}
+
+/// The index of a node in the arena.
+type NodeId = usize;
+
+/// A node in the arena. Leaf nodes are the ones with no children.
+struct Node<K, const B: usize> {
+    keys: Array<K, B>,
+    children: Array<NodeId, B>,
+}
+
+impl<K, const B: usize> Default for Node<K, B> {
+    fn default() -> Self {
+        Node {
+            keys: Array::new(),
+            children: Array::new(),
+        }
+    }
+}
+
+impl<K, const B: usize> Node<K, B> {
+    const MIN_KEYS: usize = B - 1;
+    const MAX_KEYS: usize = 2 * B - 1;
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+}
+
+impl<K: Ord, const B: usize> ArenaBTreeSet<K, B> {
+    pub fn new() -> Self {
+        ArenaBTreeSet {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            root: None,
+        }
+    }
+
+    /// Returns the number of bytes the tree has allocated: the whole arena,
+    /// including the slots on the free list and unused capacity, and the free
+    /// list itself. Memory owned by the keys themselves is not included.
+    pub fn heap_size(&self) -> usize {
+        self.nodes.capacity() * mem::size_of::<Node<K, B>>()
+            + self.free.capacity() * mem::size_of::<NodeId>()
+    }
+
+    fn alloc(&mut self, node: Node<K, B>) -> NodeId {
+        match self.free.pop() {
+            Some(id) => {
+                self.nodes[id] = node;
+                id
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    /// Takes the node out of the arena, and puts its slot on the free list.
+    fn release(&mut self, id: NodeId) -> Node<K, B> {
+        self.free.push(id);
+        mem::take(&mut self.nodes[id])
+    }
+
+    /// Inserts the key into the subtree, returning the hoisted key and the new
+    /// sibling when the node had to be split.
+    fn insert_into(&mut self, id: NodeId, key: K) -> Result<Option<(K, NodeId)>> {
+        let node = &self.nodes[id];
+        let Err(idx) = node.keys.binary_search(&key) else {
+            return Err(Error::KeyAlreadyExists);
+        };
+
+        if node.is_leaf() {
+            self.nodes[id].keys.insert(idx, key);
+        } else if let Some((hoist, sibling)) = self.insert_into(node.children[idx], key)? {
+            let node = &mut self.nodes[id];
+            node.keys.insert(idx, hoist);
+            node.children.insert(idx + 1, sibling);
+        }
+
+        if self.nodes[id].keys.len() > Node::<K, B>::MAX_KEYS {
+            Ok(Some(self.split(id)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Splits the overflowed node, returning the hoisted key and the new sibling.
+    fn split(&mut self, id: NodeId) -> (K, NodeId) {
+        let node = &mut self.nodes[id];
+        let keys = node.keys.split_off(B);
+        let hoist = node.keys.pop_back().unwrap();
+        let children = if node.is_leaf() {
+            Array::new()
+        } else {
+            node.children.split_off(B)
+        };
+
+        (hoist, self.alloc(Node { keys, children }))
+    }
+
+    /// Removes the key from the subtree, leaving the node at `id` possibly
+    /// deficient, but all of its descendants valid.
+    fn remove_from(&mut self, id: NodeId, key: &K) -> Option<K> {
+        let node = &self.nodes[id];
+        let result = node.keys.binary_search(key);
+
+        if node.is_leaf() {
+            return result
+                .ok()
+                .map(|idx| self.nodes[id].keys.remove(idx).unwrap());
+        }
+
+        let (removed, idx) = match result {
+            Ok(idx) => {
+                let predecessor = self.remove_last(node.children[idx]);
+                (
+                    mem::replace(&mut self.nodes[id].keys[idx], predecessor),
+                    idx,
+                )
+            }
+            Err(idx) => (self.remove_from(node.children[idx], key)?, idx),
+        };
+
+        self.fix_deficient_child(id, idx);
+        Some(removed)
+    }
+
+    /// Removes the greatest key of the subtree.
+    fn remove_last(&mut self, id: NodeId) -> K {
+        let node = &self.nodes[id];
+        if node.is_leaf() {
+            return self.nodes[id].keys.pop_back().unwrap();
+        }
+
+        let idx = node.children.len() - 1;
+        let key = self.remove_last(node.children[idx]);
+        self.fix_deficient_child(id, idx);
+        key
+    }
+
+    /// Refills the child at the given index if it became deficient, either by
+    /// rotating a key from one of its siblings, or by merging it with one.
+    fn fix_deficient_child(&mut self, id: NodeId, idx: usize) {
+        let children = &self.nodes[id].children;
+        let child = children[idx];
+        if self.nodes[child].keys.len() >= Node::<K, B>::MIN_KEYS {
+            return;
+        }
+
+        let left = idx.checked_sub(1).map(|i| children[i]);
+        let right = children.get(idx + 1).copied();
+        let can_spare = |sibling: NodeId| self.nodes[sibling].keys.len() > Node::<K, B>::MIN_KEYS;
+
+        match (left, right) {
+            (Some(left), _) if can_spare(left) => self.rotate_right(id, idx, left, child),
+            (_, Some(right)) if can_spare(right) => self.rotate_left(id, idx, child, right),
+            (Some(_), _) => self.merge(id, idx - 1),
+            (None, Some(_)) => self.merge(id, idx),
+            (None, None) => unreachable!("intermediate nodes have at least two children"),
+        }
+    }
+
+    /// Moves the last key of the left sibling up into the parent, and the
+    /// separating parent key down into the child.
+    fn rotate_right(&mut self, id: NodeId, idx: usize, left: NodeId, child: NodeId) {
+        let key = self.nodes[left].keys.pop_back().unwrap();
+        let grandchild = self.nodes[left].children.pop_back();
+        let separator = mem::replace(&mut self.nodes[id].keys[idx - 1], key);
+
+        let child = &mut self.nodes[child];
+        child.keys.push_front(separator);
+        if let Some(grandchild) = grandchild {
+            child.children.push_front(grandchild);
+        }
+    }
+
+    /// Moves the first key of the right sibling up into the parent, and the
+    /// separating parent key down into the child.
+    fn rotate_left(&mut self, id: NodeId, idx: usize, child: NodeId, right: NodeId) {
+        let key = self.nodes[right].keys.pop_front().unwrap();
+        let grandchild = self.nodes[right].children.pop_front();
+        let separator = mem::replace(&mut self.nodes[id].keys[idx], key);
+
+        let child = &mut self.nodes[child];
+        child.keys.push_back(separator);
+        if let Some(grandchild) = grandchild {
+            child.children.push_back(grandchild);
+        }
+    }
+
+    /// Merges the child after the given index into the child at it, together
+    /// with the separating parent key.
+    fn merge(&mut self, id: NodeId, idx: usize) {
+        let separator = self.nodes[id].keys.remove(idx).unwrap();
+        let right = self.nodes[id].children.remove(idx + 1).unwrap();
+        let left = self.nodes[id].children[idx];
+        let right = self.release(right);
+
+        let left = &mut self.nodes[left];
+        left.keys.push_back(separator);
+        left.keys.extend(right.keys);
+        left.children.extend(right.children);
+    }
+}
+
+impl<K: Ord, const B: usize> Default for ArenaBTreeSet<K, B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord, const B: usize> BTreeSet for ArenaBTreeSet<K, B> {
+    type Key = K;
+    const B: usize = B;
+
+    fn search(&self, key: &Self::Key) -> Result<&Self::Key> {
+        let mut id = self.root.ok_or(Error::KeyNotFound)?;
+        loop {
+            let node = &self.nodes[id];
+            match node.keys.binary_search(key) {
+                Ok(idx) => return Ok(&node.keys[idx]),
+                Err(_) if node.is_leaf() => return Err(Error::KeyNotFound),
+                Err(idx) => id = node.children[idx],
+            }
+        }
+    }
+
+    fn insert(&mut self, key: Self::Key) -> Result<()> {
+        let Some(root) = self.root else {
+            let mut keys = Array::new();
+            keys.push_back(key);
+            self.root = Some(self.alloc(Node {
+                keys,
+                children: Array::new(),
+            }));
+            return Ok(());
+        };
+
+        if let Some((hoist, sibling)) = self.insert_into(root, key)? {
+            let mut node = Node::default();
+            node.keys.push_back(hoist);
+            node.children.extend([root, sibling]);
+            self.root = Some(self.alloc(node));
+        }
+
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &Self::Key) -> Result<Self::Key> {
+        let root = self.root.ok_or(Error::KeyNotFound)?;
+        let removed = self.remove_from(root, key).ok_or(Error::KeyNotFound)?;
+
+        let node = &self.nodes[root];
+        if node.keys.is_empty() {
+            self.root = node.children.first().copied();
+            self.release(root);
+        }
+
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::{DifferentialTester, ReferenceBTreeSet};
+    use crate::test_btree_impl;
+
+    test_btree_impl!(ArenaBTreeSet);
+
+    #[test]
+    fn test_agrees_with_reference_on_mixed_operations() {
+        let mut tester =
+            DifferentialTester::new(ArenaBTreeSet::<usize, 2>::new(), ReferenceBTreeSet::new());
+
+        for i in 0..20_000usize {
+            let key = (i * 7919) % 503;
+            let _ = match i % 3 {
+                0 => tester.remove(&key).map(|_| ()),
+                _ => tester.insert(key),
+            };
+        }
+    }
+
+    #[test]
+    fn test_freed_nodes_are_reused() {
+        let mut tree = ArenaBTreeSet::<usize, 2>::new();
+
+        for key in 0..1000 {
+            tree.insert(key).unwrap();
+        }
+        let allocated = tree.nodes.len();
+
+        for _ in 0..10 {
+            for key in 0..1000 {
+                tree.remove(&key).unwrap();
+            }
+            assert!(tree.root.is_none());
+            assert_eq!(tree.free.len(), allocated);
+
+            for key in 0..1000 {
+                tree.insert(key).unwrap();
+            }
+            assert_eq!(tree.nodes.len(), allocated);
+        }
+    }
+}
@@ -0,0 +1,313 @@
+use core::mem::MaybeUninit;
+use core::ops::{Deref, DerefMut};
+use core::{fmt, ptr, slice};
+
+/// A fixed-capacity array stored inline, which holds up to `2B + 1` elements.
+///
+/// This is one more than a node ever holds children, so that a node can
+/// overflow by a single key or child before it is split. The slots live in
+/// the node itself, so a node costs one allocation instead of three.
+pub(super) struct Array<T, const B: usize> {
+    len: usize,
+    slots: Slots<T, B>,
+}
+
+/// The backing storage of an `Array`. Stable Rust cannot express an array of
+/// length `2 * B + 1` for a generic `B`, so the slots are split into fields
+/// which `repr(C)` lays out back to back, with no padding in between.
+#[repr(C)]
+struct Slots<T, const B: usize> {
+    head: [MaybeUninit<T>; B],
+    tail: [MaybeUninit<T>; B],
+    spare: MaybeUninit<T>,
+}
+
+impl<T, const B: usize> Array<T, B> {
+    pub(super) const CAPACITY: usize = 2 * B + 1;
+
+    pub(super) fn new() -> Self {
+        Array {
+            len: 0,
+            slots: Slots {
+                head: [const { MaybeUninit::uninit() }; B],
+                tail: [const { MaybeUninit::uninit() }; B],
+                spare: MaybeUninit::uninit(),
+            },
+        }
+    }
+
+    fn as_ptr(&self) -> *const T {
+        (&raw const self.slots).cast()
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut T {
+        (&raw mut self.slots).cast()
+    }
+
+    fn assert_not_full(&self) {
+        assert!(self.len < Self::CAPACITY, "array is full");
+    }
+
+    pub(super) fn push_back(&mut self, value: T) {
+        self.assert_not_full();
+        // SAFETY: the slot at `len` is within capacity and uninitialized.
+        unsafe { self.as_mut_ptr().add(self.len).write(value) };
+        self.len += 1;
+    }
+
+    pub(super) fn push_front(&mut self, value: T) {
+        self.insert(0, value);
+    }
+
+    pub(super) fn pop_back(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.len -= 1;
+        // SAFETY: the slot at the old `len - 1` is initialized, and is no
+        // longer considered part of the array.
+        Some(unsafe { self.as_ptr().add(self.len).read() })
+    }
+
+    pub(super) fn pop_front(&mut self) -> Option<T> {
+        self.remove(0)
+    }
+
+    /// Inserts the value at the given index, shifting the following elements
+    /// to the right.
+    ///
+    /// Panics if the index is greater than the length, or the array is full.
+    pub(super) fn insert(&mut self, idx: usize, value: T) {
+        assert!(idx <= self.len, "insertion index {idx} is out of bounds");
+        self.assert_not_full();
+
+        // SAFETY: both ranges are within capacity, and the slot at `idx` is
+        // overwritten after its element has been moved to the right.
+        unsafe {
+            let at = self.as_mut_ptr().add(idx);
+            ptr::copy(at, at.add(1), self.len - idx);
+            at.write(value);
+        }
+        self.len += 1;
+    }
+
+    /// Removes the element at the given index, shifting the following
+    /// elements to the left.
+    pub(super) fn remove(&mut self, idx: usize) -> Option<T> {
+        if idx >= self.len {
+            return None;
+        }
+
+        // SAFETY: the slot at `idx` is initialized, and the following
+        // elements are moved over it after it has been read.
+        unsafe {
+            let at = self.as_mut_ptr().add(idx);
+            let value = at.read();
+            ptr::copy(at.add(1), at, self.len - idx - 1);
+            self.len -= 1;
+            Some(value)
+        }
+    }
+
+    /// Moves the elements from the given index onwards into a new array.
+    ///
+    /// Panics if the index is greater than the length.
+    pub(super) fn split_off(&mut self, at: usize) -> Self {
+        assert!(at <= self.len, "split index {at} is out of bounds");
+
+        let mut other = Array::new();
+        let count = self.len - at;
+
+        // SAFETY: the elements in `at..len` are initialized, and are moved
+        // into the empty array before they are forgotten by this one.
+        unsafe { ptr::copy_nonoverlapping(self.as_ptr().add(at), other.as_mut_ptr(), count) };
+        self.len = at;
+        other.len = count;
+
+        other
+    }
+}
+
+impl<T, const B: usize> Deref for Array<T, B> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        // SAFETY: the first `len` slots are initialized.
+        unsafe { slice::from_raw_parts(self.as_ptr(), self.len) }
+    }
+}
+
+impl<T, const B: usize> DerefMut for Array<T, B> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        // SAFETY: the first `len` slots are initialized.
+        unsafe { slice::from_raw_parts_mut(self.as_mut_ptr(), self.len) }
+    }
+}
+
+impl<T, const B: usize> Drop for Array<T, B> {
+    fn drop(&mut self) {
+        // SAFETY: the first `len` slots are initialized, and are never read
+        // again.
+        unsafe { ptr::drop_in_place(&mut **self) };
+    }
+}
+
+impl<T, const B: usize> Default for Array<T, B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const B: usize> Extend<T> for Array<T, B> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push_back(value);
+        }
+    }
+}
+
+impl<T, const B: usize> FromIterator<T> for Array<T, B> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut array = Array::new();
+        array.extend(iter);
+        array
+    }
+}
+
+impl<T: Clone, const B: usize> Clone for Array<T, B> {
+    fn clone(&self) -> Self {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T: fmt::Debug, const B: usize> fmt::Debug for Array<T, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T, const B: usize> IntoIterator for Array<T, B> {
+    type Item = T;
+    type IntoIter = IntoIter<T, B>;
+
+    fn into_iter(self) -> IntoIter<T, B> {
+        IntoIter {
+            start: 0,
+            array: self,
+        }
+    }
+}
+
+impl<'a, T, const B: usize> IntoIterator for &'a Array<T, B> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> slice::Iter<'a, T> {
+        self.iter()
+    }
+}
+
+/// An owning iterator over the elements of an `Array`.
+pub(super) struct IntoIter<T, const B: usize> {
+    start: usize,
+    array: Array<T, B>,
+}
+
+impl<T, const B: usize> Iterator for IntoIter<T, B> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.start == self.array.len {
+            return None;
+        }
+
+        // SAFETY: the slot at `start` is initialized, and is skipped from now
+        // on, including when the iterator is dropped.
+        let value = unsafe { self.array.as_ptr().add(self.start).read() };
+        self.start += 1;
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.array.len - self.start;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T, const B: usize> Drop for IntoIter<T, B> {
+    fn drop(&mut self) {
+        let remaining = self.array.len - self.start;
+
+        // SAFETY: the elements in `start..len` are initialized and have not
+        // been yielded. The array is emptied first, so it drops nothing.
+        unsafe {
+            self.array.len = 0;
+            let rest = self.array.as_mut_ptr().add(self.start);
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(rest, remaining));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_holds_two_b_plus_one_elements() {
+        let mut array = Array::<usize, 2>::new();
+        array.extend(0..5);
+        assert_eq!(&*array, &[0, 1, 2, 3, 4]);
+        assert_eq!(Array::<usize, 2>::CAPACITY, 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "array is full")]
+    fn test_pushing_into_full_array_panics() {
+        let mut array = Array::<usize, 2>::new();
+        array.extend(0..6);
+    }
+
+    #[test]
+    fn test_insert_and_remove_shift_elements() {
+        let mut array = Array::<usize, 3>::new();
+        array.extend([1, 3]);
+        array.insert(1, 2);
+        array.push_front(0);
+        array.insert(4, 4);
+        assert_eq!(&*array, &[0, 1, 2, 3, 4]);
+
+        assert_eq!(array.remove(2), Some(2));
+        assert_eq!(array.pop_front(), Some(0));
+        assert_eq!(array.pop_back(), Some(4));
+        assert_eq!(array.remove(2), None);
+        assert_eq!(&*array, &[1, 3]);
+    }
+
+    #[test]
+    fn test_split_off_moves_the_tail() {
+        let mut array: Array<usize, 3> = (0..7).collect();
+        let tail = array.split_off(3);
+        assert_eq!(&*array, &[0, 1, 2]);
+        assert_eq!(&*tail, &[3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_elements_are_dropped_exactly_once() {
+        let counter = Rc::new(());
+        let mut array: Array<Rc<()>, 3> = (0..7).map(|_| counter.clone()).collect();
+
+        let tail = array.split_off(4);
+        drop(array.remove(0));
+        assert_eq!(Rc::strong_count(&counter), 7);
+
+        let mut iter = tail.into_iter();
+        drop(iter.next());
+        drop(iter);
+        assert_eq!(Rc::strong_count(&counter), 4);
+
+        drop(array);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+}
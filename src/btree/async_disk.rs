@@ -0,0 +1,446 @@
+use crate::storage::{
+    AsyncPager, Compression, FileHeader, FixedSizeKey, NodePage, PAGE_HEADER_SIZE, PageId,
+    decode_page, encode_page_with,
+};
+use crate::{Error, Result};
+use std::mem;
+
+const HEADER_PAGE: PageId = 0;
+
+/// A B-tree set whose nodes are stored in the pages of an `AsyncPager`, and
+/// whose operations wait for the pages without blocking.
+///
+/// The pages are laid out like the ones of a `DiskBTreeSet`, so either tree
+/// can open a file written by the other. Nodes are not kept in memory
+/// between operations, so every operation reads the pages on its path; a
+/// cache belongs in the pager. Every insertion and removal is committed
+/// through the pager together with the new root.
+///
+/// The list of free pages stored in the file is left as it is, so the pages
+/// it lists are only reused once a `DiskBTreeSet` opens the file.
+pub struct AsyncDiskBTreeSet<K, const B: usize, P> {
+    pager: P,
+    root: Option<PageId>,
+    free_list: Option<PageId>,
+    compression: Compression,
+    _keys: std::marker::PhantomData<fn() -> K>,
+}
+
+/// A node read on the way down, along with the index of the child which was
+/// taken, or of the key which was found.
+type Step<K> = (PageId, NodePage<K>, usize);
+
+impl<K: FixedSizeKey + Ord + Send, const B: usize, P: AsyncPager + Send>
+    AsyncDiskBTreeSet<K, B, P>
+{
+    const MIN_KEYS: usize = B - 1;
+    const MAX_KEYS: usize = 2 * B - 1;
+
+    /// Opens the tree stored through the given pager, or creates an empty
+    /// one if the pager holds no pages yet.
+    pub async fn open(mut pager: P) -> Result<Self> {
+        let page_size = pager.page_size();
+        if NodePage::<K>::capacity(page_size) < Self::MAX_KEYS {
+            let needed = PAGE_HEADER_SIZE
+                + Self::MAX_KEYS * K::SIZE
+                + (Self::MAX_KEYS + 1) * size_of::<PageId>();
+            return Err(Error::PageOverflow { needed, page_size });
+        }
+
+        if pager.page_count() == 0 {
+            let id = pager.allocate().await?;
+            debug_assert_eq!(id, HEADER_PAGE);
+
+            let mut tree = AsyncDiskBTreeSet {
+                pager,
+                root: None,
+                free_list: None,
+                compression: Compression::None,
+                _keys: std::marker::PhantomData,
+            };
+            tree.write_header().await?;
+            tree.pager.commit().await?;
+            return Ok(tree);
+        }
+
+        let header = FileHeader::decode(&pager.read_page(HEADER_PAGE).await?)?;
+        if header.page_size as usize != page_size || header.key_size as usize != K::SIZE {
+            return Err(Error::CorruptPage {
+                reason: format!(
+                    "file has {} byte pages and {} byte keys, expected {} and {}",
+                    header.page_size,
+                    header.key_size,
+                    page_size,
+                    K::SIZE
+                ),
+            });
+        }
+
+        Ok(AsyncDiskBTreeSet {
+            pager,
+            root: header.root,
+            free_list: header.free_list,
+            compression: Compression::None,
+            _keys: std::marker::PhantomData,
+        })
+    }
+
+    /// Sets how the nodes are compressed when they are written.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// Returns the pager the pages are stored through.
+    pub fn pager(&self) -> &P {
+        &self.pager
+    }
+
+    /// Consumes the tree, returning its pager.
+    pub fn into_pager(self) -> P {
+        self.pager
+    }
+
+    /// Makes sure every committed write reached the backing store.
+    pub async fn sync(&mut self) -> Result<()> {
+        self.pager.sync().await
+    }
+
+    /// Returns the key equal to the given key.
+    pub async fn search(&self, key: &K) -> Result<K> {
+        let mut id = self.root.ok_or(Error::KeyNotFound)?;
+        loop {
+            let mut node = self.read(id).await?;
+            match node.keys.binary_search(key) {
+                Ok(idx) => return Ok(node.keys.swap_remove(idx)),
+                Err(_) if node.is_leaf() => return Err(Error::KeyNotFound),
+                Err(idx) => id = node.children[idx],
+            }
+        }
+    }
+
+    /// Returns whether the tree holds a key equal to the given key.
+    pub async fn contains(&self, key: &K) -> Result<bool> {
+        match self.search(key).await {
+            Ok(_) => Ok(true),
+            Err(Error::KeyNotFound) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Inserts the key, which must not be in the tree yet.
+    pub async fn insert(&mut self, key: K) -> Result<()> {
+        let Some(mut id) = self.root else {
+            let root = self.allocate().await?;
+            let leaf = NodePage {
+                keys: vec![key],
+                children: Vec::new(),
+            };
+            self.write(root, &leaf).await?;
+            return self.commit(Some(root)).await;
+        };
+
+        let mut path = Vec::new();
+        loop {
+            let node = self.read(id).await?;
+            let idx = match node.keys.binary_search(&key) {
+                Ok(_) => return Err(Error::KeyAlreadyExists),
+                Err(idx) => idx,
+            };
+            let child = node.children.get(idx).copied();
+            path.push((id, node, idx));
+            match child {
+                Some(child) => id = child,
+                None => break,
+            }
+        }
+
+        // Insert the key into the leaf, and carry the splits up the path.
+        let mut key = key;
+        let mut sibling = None;
+        while let Some((id, mut node, idx)) = path.pop() {
+            node.keys.insert(idx, key);
+            if let Some(sibling) = sibling {
+                node.children.insert(idx + 1, sibling);
+            }
+            if node.keys.len() <= Self::MAX_KEYS {
+                self.write(id, &node).await?;
+                return self.commit(self.root).await;
+            }
+
+            let keys = node.keys.split_off(B);
+            key = node.keys.pop().unwrap();
+            let children = match node.is_leaf() {
+                true => Vec::new(),
+                false => node.children.split_off(B),
+            };
+            self.write(id, &node).await?;
+
+            let right = self.allocate().await?;
+            self.write(right, &NodePage { keys, children }).await?;
+            sibling = Some(right);
+        }
+
+        let root = self.allocate().await?;
+        let node = NodePage {
+            keys: vec![key],
+            children: vec![self.root.unwrap(), sibling.unwrap()],
+        };
+        self.write(root, &node).await?;
+        self.commit(Some(root)).await
+    }
+
+    /// Removes the key equal to the given key, and returns it.
+    pub async fn remove(&mut self, key: &K) -> Result<K> {
+        let mut id = self.root.ok_or(Error::KeyNotFound)?;
+        let mut path: Vec<Step<K>> = Vec::new();
+        let removed = loop {
+            let mut node = self.read(id).await?;
+            match node.keys.binary_search(key) {
+                Ok(idx) if node.is_leaf() => {
+                    let removed = node.keys.remove(idx);
+                    path.push((id, node, idx));
+                    break removed;
+                }
+                Err(_) if node.is_leaf() => return Err(Error::KeyNotFound),
+                Ok(idx) => {
+                    // The key is replaced by its predecessor, the greatest
+                    // key of the subtree on its left.
+                    let found = path.len();
+                    let child = node.children[idx];
+                    path.push((id, node, idx));
+                    let predecessor = self.remove_last(child, &mut path).await?;
+                    let (_, node, _) = &mut path[found];
+                    break mem::replace(&mut node.keys[idx], predecessor);
+                }
+                Err(idx) => {
+                    let child = node.children[idx];
+                    path.push((id, node, idx));
+                    id = child;
+                }
+            }
+        };
+
+        // Write the nodes back up the path, refilling the deficient ones.
+        let (mut id, mut node, _) = path.pop().unwrap();
+        while let Some((parent_id, mut parent, idx)) = path.pop() {
+            if node.keys.len() >= Self::MIN_KEYS {
+                self.write(id, &node).await?;
+            } else {
+                self.fix_deficient_child(&mut parent, idx, id, node).await?;
+            }
+            (id, node) = (parent_id, parent);
+        }
+
+        if node.keys.is_empty() {
+            let root = node.children.first().copied();
+            self.pager.free(id).await?;
+            self.commit(root).await?;
+        } else {
+            self.write(id, &node).await?;
+            self.commit(self.root).await?;
+        }
+        Ok(removed)
+    }
+
+    /// Descends to the greatest key of the subtree, adding the nodes on the
+    /// way to the path, and removes the key from its leaf.
+    async fn remove_last(&mut self, mut id: PageId, path: &mut Vec<Step<K>>) -> Result<K> {
+        loop {
+            let mut node = self.read(id).await?;
+            if node.is_leaf() {
+                let key = node.keys.pop().unwrap();
+                let idx = node.keys.len();
+                path.push((id, node, idx));
+                return Ok(key);
+            }
+            let idx = node.children.len() - 1;
+            let child = node.children[idx];
+            path.push((id, node, idx));
+            id = child;
+        }
+    }
+
+    /// Refills the deficient child at the given index of the parent, either
+    /// by rotating a key from one of its siblings, or by merging it with one,
+    /// and writes the children. The parent is written by the caller.
+    async fn fix_deficient_child(
+        &mut self,
+        parent: &mut NodePage<K>,
+        idx: usize,
+        id: PageId,
+        mut child: NodePage<K>,
+    ) -> Result<()> {
+        if idx > 0 {
+            let left_id = parent.children[idx - 1];
+            let mut left = self.read(left_id).await?;
+            if left.keys.len() > Self::MIN_KEYS {
+                let key = left.keys.pop().unwrap();
+                child
+                    .keys
+                    .insert(0, mem::replace(&mut parent.keys[idx - 1], key));
+                if let Some(grandchild) = left.children.pop() {
+                    child.children.insert(0, grandchild);
+                }
+                self.write(left_id, &left).await?;
+                return self.write(id, &child).await;
+            }
+
+            // Merge the child into its left sibling.
+            left.keys.push(parent.keys.remove(idx - 1));
+            left.keys.extend(child.keys);
+            left.children.extend(child.children);
+            parent.children.remove(idx);
+            self.write(left_id, &left).await?;
+            return self.pager.free(id).await;
+        }
+
+        let right_id = parent.children[idx + 1];
+        let mut right = self.read(right_id).await?;
+        if right.keys.len() > Self::MIN_KEYS {
+            let key = right.keys.remove(0);
+            child.keys.push(mem::replace(&mut parent.keys[idx], key));
+            if !right.is_leaf() {
+                child.children.push(right.children.remove(0));
+            }
+            self.write(right_id, &right).await?;
+            return self.write(id, &child).await;
+        }
+
+        // Merge the right sibling into the child.
+        child.keys.push(parent.keys.remove(idx));
+        child.keys.extend(right.keys);
+        child.children.extend(right.children);
+        parent.children.remove(idx + 1);
+        self.write(id, &child).await?;
+        self.pager.free(right_id).await
+    }
+
+    async fn read(&self, id: PageId) -> Result<NodePage<K>> {
+        decode_page(&self.pager.read_page(id).await?)
+    }
+
+    async fn write(&mut self, id: PageId, node: &NodePage<K>) -> Result<()> {
+        let mut page = vec![0; self.pager.page_size()];
+        encode_page_with(node, &mut page, self.compression)?;
+        self.pager.write_page(id, page).await
+    }
+
+    async fn allocate(&mut self) -> Result<PageId> {
+        self.pager.allocate().await
+    }
+
+    /// Stores the root in the header if it changed, and commits every write
+    /// made since the last commit.
+    async fn commit(&mut self, root: Option<PageId>) -> Result<()> {
+        if root != self.root {
+            self.root = root;
+            self.write_header().await?;
+        }
+        self.pager.commit().await
+    }
+
+    async fn write_header(&mut self) -> Result<()> {
+        let header = FileHeader {
+            page_size: self.pager.page_size() as u32,
+            key_size: K::SIZE as u32,
+            root: self.root,
+            page_count: self.pager.page_count(),
+            free_list: self.free_list,
+        };
+        let mut page = vec![0; self.pager.page_size()];
+        header.encode(&mut page);
+        self.pager.write_page(HEADER_PAGE, page).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BTreeSet;
+    use crate::btree::DiskBTreeSet;
+    use crate::storage::{BlockingPager, MemoryPager};
+    use std::future::Future;
+
+    type MemoryBTreeSet = AsyncDiskBTreeSet<u32, 3, BlockingPager<MemoryPager>>;
+
+    fn block_on<T>(future: impl Future<Output = T>) -> T {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    fn scrambled(n: u32) -> impl Iterator<Item = u32> {
+        // 7919 is prime, so this visits every key below `n` once.
+        (0..n).map(move |i| i * 7919 % n)
+    }
+
+    #[test]
+    fn test_insert_search_remove() {
+        // The tree is used from a spawned task, which needs its futures to be
+        // `Send`.
+        block_on(async {
+            let task = tokio::spawn(async {
+                let pager = BlockingPager::new(MemoryPager::default());
+                let mut tree = MemoryBTreeSet::open(pager).await.unwrap();
+                for key in scrambled(1000) {
+                    tree.insert(key).await.unwrap();
+                }
+                assert!(matches!(
+                    tree.insert(10).await,
+                    Err(Error::KeyAlreadyExists)
+                ));
+                for key in 0..1000 {
+                    assert_eq!(tree.search(&key).await.unwrap(), key);
+                }
+
+                for key in scrambled(1000).filter(|key| key % 3 != 0) {
+                    assert_eq!(tree.remove(&key).await.unwrap(), key);
+                }
+                assert!(matches!(tree.remove(&1).await, Err(Error::KeyNotFound)));
+                for key in 0..1000 {
+                    assert_eq!(tree.contains(&key).await.unwrap(), key % 3 == 0);
+                }
+
+                for key in (0..1000).step_by(3) {
+                    tree.remove(&key).await.unwrap();
+                }
+                assert!(!tree.contains(&0).await.unwrap());
+            });
+            task.await.unwrap();
+        });
+    }
+
+    #[test]
+    fn test_pages_are_shared_with_the_disk_tree() {
+        let pager = block_on(async {
+            let pager = BlockingPager::new(MemoryPager::default());
+            let mut tree = MemoryBTreeSet::open(pager).await.unwrap();
+            for key in scrambled(500) {
+                tree.insert(key).await.unwrap();
+            }
+            for key in (0..500).step_by(2) {
+                tree.remove(&key).await.unwrap();
+            }
+            tree.into_pager().into_inner()
+        });
+
+        let mut tree = DiskBTreeSet::<u32, 3, MemoryPager>::open(pager).unwrap();
+        assert_eq!(
+            tree.range(..)
+                .unwrap()
+                .into_iter()
+                .copied()
+                .collect::<Vec<_>>(),
+            (1..500).step_by(2).collect::<Vec<_>>()
+        );
+        tree.insert(0).unwrap();
+
+        block_on(async {
+            let pager = BlockingPager::new(tree.into_pager());
+            let tree = MemoryBTreeSet::open(pager).await.unwrap();
+            assert_eq!(tree.search(&0).await.unwrap(), 0);
+            assert!(tree.search(&2).await.is_err());
+        });
+    }
+}
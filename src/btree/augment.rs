@@ -0,0 +1,837 @@
+use super::array::Array;
+use crate::{BTreeSet, Error, Result};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Add, Bound, RangeBounds};
+
+/// A summary of the keys of a subtree, which an `AugmentedBTreeSet` keeps in
+/// every node.
+///
+/// Summaries form a monoid: `combine` must be associative, and combining any
+/// value with `identity` must leave it unchanged. Summaries are combined in
+/// ascending key order, so `combine` does not need to be commutative.
+pub trait Augment<K> {
+    type Value: Clone;
+
+    /// Returns the summary of no keys at all.
+    fn identity() -> Self::Value;
+
+    /// Returns the summary of a single key.
+    fn summarize(key: &K) -> Self::Value;
+
+    /// Returns the summary of the keys of `left` followed by those of `right`.
+    fn combine(left: &Self::Value, right: &Self::Value) -> Self::Value;
+}
+
+/// Counts the keys.
+pub struct Count;
+
+impl<K> Augment<K> for Count {
+    type Value = usize;
+
+    fn identity() -> usize {
+        0
+    }
+
+    fn summarize(_: &K) -> usize {
+        1
+    }
+
+    fn combine(left: &usize, right: &usize) -> usize {
+        left + right
+    }
+}
+
+/// Adds the keys up, starting from their default value.
+pub struct Sum;
+
+impl<K: Copy + Default + Add<Output = K>> Augment<K> for Sum {
+    type Value = K;
+
+    fn identity() -> K {
+        K::default()
+    }
+
+    fn summarize(key: &K) -> K {
+        *key
+    }
+
+    fn combine(left: &K, right: &K) -> K {
+        *left + *right
+    }
+}
+
+/// Keeps the smallest key, or `None` for no keys.
+pub struct Min;
+
+impl<K: Ord + Clone> Augment<K> for Min {
+    type Value = Option<K>;
+
+    fn identity() -> Option<K> {
+        None
+    }
+
+    fn summarize(key: &K) -> Option<K> {
+        Some(key.clone())
+    }
+
+    fn combine(left: &Option<K>, right: &Option<K>) -> Option<K> {
+        match (left, right) {
+            (Some(left), Some(right)) => Some(left.min(right).clone()),
+            _ => left.clone().or_else(|| right.clone()),
+        }
+    }
+}
+
+/// Keeps the greatest key, or `None` for no keys.
+pub struct Max;
+
+impl<K: Ord + Clone> Augment<K> for Max {
+    type Value = Option<K>;
+
+    fn identity() -> Option<K> {
+        None
+    }
+
+    fn summarize(key: &K) -> Option<K> {
+        Some(key.clone())
+    }
+
+    fn combine(left: &Option<K>, right: &Option<K>) -> Option<K> {
+        match (left, right) {
+            (Some(left), Some(right)) => Some(left.max(right).clone()),
+            _ => left.clone().or_else(|| right.clone()),
+        }
+    }
+}
+
+/// A B-tree which keeps a summary of its subtree in every node, computed by
+/// the augmentation A. The summaries are kept up to date through every
+/// insertion, removal, split, rotation and merge, so a single tree type can
+/// answer counts, sums, extremes and the like for any subtree.
+///
+/// Every node is boxed on its own, so that splitting the tree hands whole
+/// subtrees over to the other half instead of copying them.
+///
+/// The K type parameter represents the key type, A is the augmentation, and
+/// B is the branching factor.
+pub struct AugmentedBTreeSet<K, A: Augment<K>, const B: usize = 6> {
+    pub(super) root: Option<Box<Node<K, A, B>>>,
+    augment: PhantomData<A>,
+}
+
+/// A tree given by its root, which is `None` when the tree is empty, and its
+/// height.
+pub(super) type Tree<K, A, const B: usize> = (Option<Box<Node<K, A, B>>>, usize);
+
+/// A node of the tree. Leaf nodes are the ones with no children.
+pub(super) struct Node<K, A: Augment<K>, const B: usize> {
+    pub(super) keys: Array<K, B>,
+    pub(super) children: Array<Box<Node<K, A, B>>, B>,
+    /// The summary of the keys in the subtree rooted at this node.
+    pub(super) summary: A::Value,
+}
+
+impl<K, A: Augment<K>, const B: usize> AugmentedBTreeSet<K, A, B> {
+    pub fn new() -> Self {
+        AugmentedBTreeSet {
+            root: None,
+            augment: PhantomData,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Returns the summary of every key in the tree.
+    pub fn summary(&self) -> A::Value {
+        match &self.root {
+            Some(root) => root.summary.clone(),
+            None => A::identity(),
+        }
+    }
+
+    /// Returns a tree with the given root.
+    pub(super) fn with_root(root: Option<Box<Node<K, A, B>>>) -> Self {
+        AugmentedBTreeSet {
+            root,
+            augment: PhantomData,
+        }
+    }
+}
+
+impl<K: Ord, A: Augment<K>, const B: usize> AugmentedBTreeSet<K, A, B> {
+    /// Returns the summary of the keys within the range.
+    ///
+    /// Subtrees which lie entirely within the range contribute their stored
+    /// summary, so only the nodes along the two edges of the range are
+    /// visited, and the query takes logarithmic time.
+    pub fn range_aggregate<R: RangeBounds<K>>(&self, range: R) -> A::Value {
+        match &self.root {
+            Some(root) => root.aggregate(range.start_bound(), range.end_bound()),
+            None => A::identity(),
+        }
+    }
+}
+
+impl<K: Ord, A: Augment<K>, const B: usize> Node<K, A, B> {
+    /// Returns the summary of the keys of the subtree within the bounds.
+    fn aggregate(&self, start: Bound<&K>, end: Bound<&K>) -> A::Value {
+        if let (Bound::Unbounded, Bound::Unbounded) = (start, end) {
+            return self.summary.clone();
+        }
+
+        let after_start = |key: &K| match start {
+            Bound::Included(start) => key >= start,
+            Bound::Excluded(start) => key > start,
+            Bound::Unbounded => true,
+        };
+        let before_end = |key: &K| match end {
+            Bound::Included(end) => key <= end,
+            Bound::Excluded(end) => key < end,
+            Bound::Unbounded => true,
+        };
+
+        let mut summary = A::identity();
+        for idx in 0..=self.keys.len() {
+            // The keys of the child lie strictly between its neighbouring keys,
+            // so those decide whether the child is outside the range, or which
+            // of the bounds it still needs.
+            if let Some(child) = self.children.get(idx) {
+                let lower = idx.checked_sub(1).map(|i| &self.keys[i]);
+                let upper = self.keys.get(idx);
+
+                let outside = upper.is_some_and(|key| !after_start(key))
+                    || lower.is_some_and(|key| !before_end(key));
+                if !outside {
+                    let start = match lower.is_some_and(after_start) {
+                        true => Bound::Unbounded,
+                        false => start,
+                    };
+                    let end = match upper.is_some_and(before_end) {
+                        true => Bound::Unbounded,
+                        false => end,
+                    };
+                    summary = A::combine(&summary, &child.aggregate(start, end));
+                }
+            }
+
+            if let Some(key) = self.keys.get(idx) {
+                if !before_end(key) {
+                    break;
+                }
+                if after_start(key) {
+                    summary = A::combine(&summary, &A::summarize(key));
+                }
+            }
+        }
+
+        summary
+    }
+
+    /// Inserts the key into the subtree, returning the hoisted key and the new
+    /// sibling when the node had to be split.
+    fn insert_into(&mut self, key: K) -> Result<Option<(K, Box<Self>)>> {
+        let Err(idx) = self.keys.binary_search(&key) else {
+            return Err(Error::KeyAlreadyExists);
+        };
+
+        if self.is_leaf() {
+            self.keys.insert(idx, key);
+        } else if let Some((hoist, sibling)) = self.children[idx].insert_into(key)? {
+            self.keys.insert(idx, hoist);
+            self.children.insert(idx + 1, sibling);
+        }
+
+        Ok(self.split_if_overflowed())
+    }
+
+    /// Removes the key from the subtree, leaving the node possibly deficient,
+    /// but all of its descendants valid.
+    fn remove_from(&mut self, key: &K) -> Option<K> {
+        let result = self.keys.binary_search(key);
+
+        if self.is_leaf() {
+            let removed = self.keys.remove(result.ok()?).unwrap();
+            self.update();
+            return Some(removed);
+        }
+
+        let (removed, idx) = match result {
+            Ok(idx) => {
+                let predecessor = self.children[idx].remove_last();
+                (mem::replace(&mut self.keys[idx], predecessor), idx)
+            }
+            Err(idx) => (self.children[idx].remove_from(key)?, idx),
+        };
+
+        self.fix_deficient_child(idx);
+        self.update();
+        Some(removed)
+    }
+}
+
+impl<K, A: Augment<K>, const B: usize> Node<K, A, B> {
+    const MIN_KEYS: usize = B - 1;
+    const MAX_KEYS: usize = 2 * B - 1;
+
+    /// Returns a node with the given keys and children, and its summary.
+    pub(super) fn new(keys: Array<K, B>, children: Array<Box<Self>, B>) -> Box<Self> {
+        let mut node = Box::new(Node {
+            keys,
+            children,
+            summary: A::identity(),
+        });
+        node.update();
+        node
+    }
+
+    /// Returns a leaf holding the single key.
+    pub(super) fn leaf(key: K) -> Box<Self> {
+        let mut keys = Array::new();
+        keys.push_back(key);
+        Self::new(keys, Array::new())
+    }
+
+    pub(super) fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// Recomputes the summary of the node from its keys and the summaries of
+    /// its children.
+    pub(super) fn update(&mut self) {
+        let mut summary = A::identity();
+
+        for (idx, key) in self.keys.iter().enumerate() {
+            if let Some(child) = self.children.get(idx) {
+                summary = A::combine(&summary, &child.summary);
+            }
+            summary = A::combine(&summary, &A::summarize(key));
+        }
+        if let Some(child) = self.children.get(self.keys.len()) {
+            summary = A::combine(&summary, &child.summary);
+        }
+
+        self.summary = summary;
+    }
+
+    /// Splits the overflowed node, returning the hoisted key and the new sibling.
+    fn split(&mut self) -> (K, Box<Self>) {
+        let keys = self.keys.split_off(B);
+        let hoist = self.keys.pop_back().unwrap();
+        let children = if self.is_leaf() {
+            Array::new()
+        } else {
+            self.children.split_off(B)
+        };
+
+        self.update();
+        (hoist, Self::new(keys, children))
+    }
+
+    /// Removes the greatest key of the subtree.
+    pub(super) fn remove_last(&mut self) -> K {
+        if self.is_leaf() {
+            let key = self.keys.pop_back().unwrap();
+            self.update();
+            return key;
+        }
+
+        let idx = self.children.len() - 1;
+        let key = self.children[idx].remove_last();
+        self.fix_deficient_child(idx);
+        self.update();
+        key
+    }
+
+    /// Refills the child at the given index if it became deficient, either by
+    /// rotating a key from one of its siblings, or by merging it with one.
+    /// The children involved are updated, but the node is left to the
+    /// caller.
+    pub(super) fn fix_deficient_child(&mut self, idx: usize) {
+        if self.children[idx].keys.len() >= Self::MIN_KEYS {
+            return;
+        }
+
+        let can_spare = |sibling: &Self| sibling.keys.len() > Self::MIN_KEYS;
+        let left = idx.checked_sub(1).map(|i| can_spare(&self.children[i]));
+        let right = self.children.get(idx + 1).map(|sibling| can_spare(sibling));
+
+        match (left, right) {
+            (Some(true), _) => self.rotate_right(idx),
+            (_, Some(true)) => self.rotate_left(idx),
+            (Some(false), _) => self.merge(idx - 1),
+            (None, Some(false)) => self.merge(idx),
+            (None, None) => unreachable!("intermediate nodes have at least two children"),
+        }
+    }
+
+    /// Moves the last key of the left sibling of the child at the given index
+    /// up into the node, and the separating key down into the child.
+    fn rotate_right(&mut self, idx: usize) {
+        let (before, after) = self.children.split_at_mut(idx);
+        let (left, child) = (&mut before[idx - 1], &mut after[0]);
+
+        let key = left.keys.pop_back().unwrap();
+        let grandchild = left.children.pop_back();
+        let separator = mem::replace(&mut self.keys[idx - 1], key);
+
+        child.keys.push_front(separator);
+        if let Some(grandchild) = grandchild {
+            child.children.push_front(grandchild);
+        }
+
+        left.update();
+        child.update();
+    }
+
+    /// Moves the first key of the right sibling of the child at the given
+    /// index up into the node, and the separating key down into the child.
+    fn rotate_left(&mut self, idx: usize) {
+        let (before, after) = self.children.split_at_mut(idx + 1);
+        let (child, right) = (&mut before[idx], &mut after[0]);
+
+        let key = right.keys.pop_front().unwrap();
+        let grandchild = right.children.pop_front();
+        let separator = mem::replace(&mut self.keys[idx], key);
+
+        child.keys.push_back(separator);
+        if let Some(grandchild) = grandchild {
+            child.children.push_back(grandchild);
+        }
+
+        right.update();
+        child.update();
+    }
+
+    /// Merges the child after the given index into the child at it, together
+    /// with the separating key.
+    fn merge(&mut self, idx: usize) {
+        let separator = self.keys.remove(idx).unwrap();
+        let right = self.children.remove(idx + 1).unwrap();
+
+        let left = &mut self.children[idx];
+        left.keys.push_back(separator);
+        left.keys.extend(right.keys);
+        left.children.extend(right.children);
+        left.update();
+    }
+
+    /// Returns the number of levels below the node.
+    pub(super) fn height(&self) -> usize {
+        let mut node = self;
+        let mut height = 0;
+        while let Some(child) = node.children.first() {
+            node = child;
+            height += 1;
+        }
+        height
+    }
+
+    /// Builds a tree out of the given keys and children cut from a node at the
+    /// given height. A fragment without keys collapses into its only child,
+    /// or into an empty tree if it was cut from a leaf.
+    pub(super) fn fragment(
+        keys: Array<K, B>,
+        children: Array<Box<Self>, B>,
+        height: usize,
+    ) -> Tree<K, A, B> {
+        if !keys.is_empty() {
+            return (Some(Self::new(keys, children)), height);
+        }
+        match children.into_iter().next() {
+            Some(child) => (Some(child), height - 1),
+            None => (None, 0),
+        }
+    }
+
+    /// Joins two trees into one, given a separator key which is greater than
+    /// all keys of the left tree, and smaller than all keys of the right tree.
+    /// The roots of both trees are allowed to be deficient.
+    ///
+    /// This takes time proportional to the difference of the heights.
+    pub(super) fn join(left: Tree<K, A, B>, separator: K, right: Tree<K, A, B>) -> Tree<K, A, B> {
+        let (mut left, left_height) = match left {
+            (Some(node), height) => (node, height),
+            (None, _) => return Self::push_along_edge(right, separator, Edge::First),
+        };
+        let (mut right, right_height) = match right {
+            (Some(node), height) => (node, height),
+            (None, _) => {
+                return Self::push_along_edge((Some(left), left_height), separator, Edge::Last);
+            }
+        };
+
+        if left_height == right_height {
+            let mut root = Self::grow(left, separator, right);
+            root.rebalance_children_at(0);
+            if root.keys.is_empty() {
+                return (root.children.pop_back(), left_height);
+            }
+            root.update();
+            (Some(root), left_height + 1)
+        } else if left_height > right_height {
+            match left.join_right(left_height, separator, right, right_height) {
+                Some((hoist, sibling)) => (Some(Self::grow(left, hoist, sibling)), left_height + 1),
+                None => (Some(left), left_height),
+            }
+        } else {
+            match right.join_left(right_height, left, separator, left_height) {
+                Some((hoist, sibling)) => {
+                    (Some(Self::grow(right, hoist, sibling)), right_height + 1)
+                }
+                None => (Some(right), right_height),
+            }
+        }
+    }
+
+    /// Attaches a shorter tree to the right spine of the subtree rooted at
+    /// the node, which is at the given height. Returns the hoisted key and
+    /// the new sibling if the node had to be split.
+    fn join_right(
+        &mut self,
+        height: usize,
+        separator: K,
+        right: Box<Self>,
+        right_height: usize,
+    ) -> Option<(K, Box<Self>)> {
+        if height == right_height + 1 {
+            self.keys.push_back(separator);
+            self.children.push_back(right);
+            self.rebalance_children_at(self.keys.len() - 1);
+        } else {
+            let last = self.children.last_mut().unwrap();
+            if let Some((hoist, sibling)) =
+                last.join_right(height - 1, separator, right, right_height)
+            {
+                self.keys.push_back(hoist);
+                self.children.push_back(sibling);
+            }
+        }
+        self.split_if_overflowed()
+    }
+
+    /// Attaches a shorter tree to the left spine of the subtree rooted at the
+    /// node, which is at the given height. Returns the hoisted key and the
+    /// new sibling if the node had to be split.
+    fn join_left(
+        &mut self,
+        height: usize,
+        left: Box<Self>,
+        separator: K,
+        left_height: usize,
+    ) -> Option<(K, Box<Self>)> {
+        if height == left_height + 1 {
+            self.keys.push_front(separator);
+            self.children.push_front(left);
+            self.rebalance_children_at(0);
+        } else {
+            let first = &mut self.children[0];
+            if let Some((hoist, sibling)) =
+                first.join_left(height - 1, left, separator, left_height)
+            {
+                self.keys.push_front(hoist);
+                self.children.insert(1, sibling);
+            }
+        }
+        self.split_if_overflowed()
+    }
+
+    /// Adds a key before or after every key of the tree.
+    fn push_along_edge((root, height): Tree<K, A, B>, key: K, edge: Edge) -> Tree<K, A, B> {
+        let Some(mut root) = root else {
+            return (Some(Self::leaf(key)), 0);
+        };
+
+        match root.insert_along_edge(key, edge) {
+            Some((hoist, sibling)) => (Some(Self::grow(root, hoist, sibling)), height + 1),
+            None => (Some(root), height),
+        }
+    }
+
+    fn insert_along_edge(&mut self, key: K, edge: Edge) -> Option<(K, Box<Self>)> {
+        let idx = match edge {
+            Edge::First => 0,
+            Edge::Last => self.keys.len(),
+        };
+
+        if self.is_leaf() {
+            self.keys.insert(idx, key);
+        } else if let Some((hoist, sibling)) = self.children[idx].insert_along_edge(key, edge) {
+            self.keys.insert(idx, hoist);
+            self.children.insert(idx + 1, sibling);
+        }
+        self.split_if_overflowed()
+    }
+
+    /// Splits the node if it holds too many keys, returning the hoisted key
+    /// and the new sibling. Otherwise, the node is only updated.
+    pub(super) fn split_if_overflowed(&mut self) -> Option<(K, Box<Self>)> {
+        if self.keys.len() > Self::MAX_KEYS {
+            Some(self.split())
+        } else {
+            self.update();
+            None
+        }
+    }
+
+    /// Places a new root above the split halves of the old one.
+    pub(super) fn grow(left: Box<Self>, hoist: K, right: Box<Self>) -> Box<Self> {
+        let mut keys = Array::new();
+        keys.push_back(hoist);
+        Self::new(keys, [left, right].into_iter().collect())
+    }
+
+    /// Makes sure that both children around the key at the given index hold
+    /// the minimum number of keys. The children are merged if they fit into
+    /// a single node, otherwise keys are rotated from one to the other. The
+    /// children are updated, but the node is left to the caller.
+    fn rebalance_children_at(&mut self, idx: usize) {
+        let len = |node: &Self, child: usize| node.children[child].keys.len();
+
+        if len(self, idx) + len(self, idx + 1) < Self::MAX_KEYS {
+            self.merge(idx);
+            return;
+        }
+
+        while len(self, idx) < Self::MIN_KEYS {
+            self.rotate_left(idx);
+        }
+        while len(self, idx + 1) < Self::MIN_KEYS {
+            self.rotate_right(idx + 1);
+        }
+    }
+}
+
+/// The edge of a tree a key is added to.
+#[derive(Clone, Copy)]
+enum Edge {
+    First,
+    Last,
+}
+
+impl<K: Ord, A: Augment<K>, const B: usize> Default for AugmentedBTreeSet<K, A, B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord, A: Augment<K>, const B: usize> BTreeSet for AugmentedBTreeSet<K, A, B> {
+    type Key = K;
+    const B: usize = B;
+
+    fn search(&self, key: &Self::Key) -> Result<&Self::Key> {
+        let mut node = self.root.as_deref().ok_or(Error::KeyNotFound)?;
+        loop {
+            match node.keys.binary_search(key) {
+                Ok(idx) => return Ok(&node.keys[idx]),
+                Err(_) if node.is_leaf() => return Err(Error::KeyNotFound),
+                Err(idx) => node = &node.children[idx],
+            }
+        }
+    }
+
+    fn insert(&mut self, key: Self::Key) -> Result<()> {
+        let Some(root) = &mut self.root else {
+            self.root = Some(Node::leaf(key));
+            return Ok(());
+        };
+
+        if let Some((hoist, sibling)) = root.insert_into(key)? {
+            let root = self.root.take().unwrap();
+            self.root = Some(Node::grow(root, hoist, sibling));
+        }
+
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &Self::Key) -> Result<Self::Key> {
+        let root = self.root.as_mut().ok_or(Error::KeyNotFound)?;
+        let removed = root.remove_from(key).ok_or(Error::KeyNotFound)?;
+
+        if root.keys.is_empty() {
+            self.root = root.children.pop_front();
+        }
+
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::{DifferentialTester, ReferenceBTreeSet};
+    use crate::test_btree_impl;
+
+    type MaxBTreeSet<K> = AugmentedBTreeSet<K, Max>;
+
+    test_btree_impl!(MaxBTreeSet);
+
+    /// Keeps the first and last key, which only combines correctly in order.
+    struct Ends;
+
+    impl Augment<usize> for Ends {
+        type Value = Option<(usize, usize)>;
+
+        fn identity() -> Self::Value {
+            None
+        }
+
+        fn summarize(key: &usize) -> Self::Value {
+            Some((*key, *key))
+        }
+
+        fn combine(left: &Self::Value, right: &Self::Value) -> Self::Value {
+            match (left, right) {
+                (Some((first, _)), Some((_, last))) => Some((*first, *last)),
+                _ => left.or(*right),
+            }
+        }
+    }
+
+    /// Checks that the summary of every node matches its subtree, returning
+    /// the keys of the subtree in order.
+    fn check_summaries<A>(node: &Node<usize, A, 2>) -> Vec<usize>
+    where
+        A: Augment<usize, Value: PartialEq + std::fmt::Debug>,
+    {
+        let mut keys = Vec::new();
+        for (idx, key) in node.keys.iter().enumerate() {
+            if let Some(child) = node.children.get(idx) {
+                keys.extend(check_summaries(child));
+            }
+            keys.push(*key);
+        }
+        if let Some(child) = node.children.get(node.keys.len()) {
+            keys.extend(check_summaries(child));
+        }
+
+        let expected = keys.iter().fold(A::identity(), |acc, key| {
+            A::combine(&acc, &A::summarize(key))
+        });
+        assert_eq!(
+            node.summary,
+            expected,
+            "node with keys {:?}",
+            &node.keys[..]
+        );
+        keys
+    }
+
+    fn exercise<A>()
+    where
+        A: Augment<usize, Value: PartialEq + std::fmt::Debug>,
+    {
+        let mut tester = DifferentialTester::new(
+            AugmentedBTreeSet::<usize, A, 2>::new(),
+            ReferenceBTreeSet::new(),
+        );
+
+        for i in 0..5000usize {
+            let key = (i * 7919) % 503;
+            let _ = match i % 3 {
+                0 => tester.remove(&key).map(|_| ()),
+                _ => tester.insert(key),
+            };
+
+            if i % 97 == 0 {
+                let tree = tester.first();
+                let keys = tree.root.as_deref().map(check_summaries);
+                let expected = tester.second().iter().copied().collect::<Vec<_>>();
+                assert_eq!(keys.unwrap_or_default(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_summaries_survive_rebalancing() {
+        exercise::<Count>();
+        exercise::<Sum>();
+        exercise::<Min>();
+        exercise::<Max>();
+        exercise::<Ends>();
+    }
+
+    #[test]
+    fn test_summary_of_the_whole_tree() {
+        let mut tree = AugmentedBTreeSet::<u64, Sum, 3>::new();
+        assert_eq!(tree.summary(), 0);
+
+        for key in 1..=100 {
+            tree.insert(key).unwrap();
+        }
+        assert_eq!(tree.summary(), 5050);
+
+        for key in 1..=50 {
+            tree.remove(&key).unwrap();
+        }
+        assert_eq!(tree.summary(), 3775);
+    }
+
+    #[test]
+    fn test_range_aggregate_agrees_with_filtered_keys() {
+        let mut sums = AugmentedBTreeSet::<u64, Sum, 2>::new();
+        let mut maxes = AugmentedBTreeSet::<u64, Max, 2>::new();
+        let mut keys = Vec::new();
+        for i in 0..300u64 {
+            let key = i * 7919 % 1000;
+            sums.insert(key).unwrap();
+            maxes.insert(key).unwrap();
+            keys.push(key);
+        }
+
+        let bounds = |i: u64| match i % 3 {
+            0 => Bound::Included(i * 37 % 1010),
+            1 => Bound::Excluded(i * 37 % 1010),
+            _ => Bound::Unbounded,
+        };
+
+        for i in 0..500 {
+            let range = (bounds(i), bounds(i * 13 + 1));
+            let within = keys.iter().filter(|key| range.contains(key));
+
+            assert_eq!(sums.range_aggregate(range), within.clone().sum::<u64>());
+            assert_eq!(maxes.range_aggregate(range), within.max().copied());
+        }
+    }
+
+    thread_local! {
+        static SUMMARIZED: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    /// Counts the keys, and how many keys were summarized on this thread.
+    struct Watched;
+
+    impl Augment<usize> for Watched {
+        type Value = usize;
+
+        fn identity() -> usize {
+            0
+        }
+
+        fn summarize(_: &usize) -> usize {
+            SUMMARIZED.with(|count| count.set(count.get() + 1));
+            1
+        }
+
+        fn combine(left: &usize, right: &usize) -> usize {
+            left + right
+        }
+    }
+
+    #[test]
+    fn test_range_aggregate_visits_only_the_edges() {
+        let mut tree = AugmentedBTreeSet::<usize, Watched, 2>::new();
+        for key in 0..10_000 {
+            tree.insert(key).unwrap();
+        }
+
+        SUMMARIZED.with(|count| count.set(0));
+        assert_eq!(tree.range_aggregate(1234..8765), 8765 - 1234);
+
+        // At most 3 keys per node on each of the two edges.
+        let summarized = SUMMARIZED.with(|count| count.get());
+        assert!(summarized < 2 * 3 * 14, "{summarized} keys summarized");
+    }
+}
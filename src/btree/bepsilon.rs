@@ -0,0 +1,548 @@
+use crate::{BTreeSet, Error, Result};
+use std::mem;
+use std::ops::Range;
+
+/// A Bε-tree, a B+ tree whose intermediate nodes buffer the changes headed
+/// for their subtrees, and hand them down in batches.
+///
+/// A change is recorded as a message in the buffer of the root. Once a
+/// buffer holds more than `4 * B * B` messages, the largest batch of messages
+/// bound for a single child moves down into the buffer of the child, and
+/// only messages which reach a leaf change its keys. Every node a batch
+/// moves through is touched once for the whole batch, instead of once per
+/// key, which makes random writes cheaper once the tree no longer fits in
+/// the caches. Searches in turn look into the buffers on the way down, as
+/// the latest message for a key is the one closest to the root.
+///
+/// `put` and `delete` write blindly, without looking whether the key is in
+/// the tree. `insert` and `remove` of `BTreeSet` have to search the tree
+/// first, to report whether the key was there.
+///
+/// Like `BPlusTreeSet`, the nodes live in a single arena.
+///
+/// The K type parameter represents the key type, and B is the branching factor.
+pub struct BEpsilonTreeSet<K, const B: usize = 6> {
+    nodes: Vec<Node<K>>,
+    free: Vec<NodeId>,
+    root: Option<NodeId>,
+}
+
+/// The index of a node in the arena.
+type NodeId = usize;
+
+/// A change to a key, on its way down to the leaf of the key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Message {
+    Insert,
+    Remove,
+}
+
+/// A node in the arena. Leaf nodes are the ones with no children, and only
+/// intermediate nodes buffer messages.
+///
+/// The keys of an intermediate node are pivots: every key in the subtree of
+/// `children[i]` is less than `keys[i]`, and every key in the subtree of
+/// `children[i + 1]` is greater than or equal to it. The buffer holds the
+/// latest message for every key it has one for, sorted by key, and they are
+/// newer than any message for the same key further down.
+struct Node<K> {
+    keys: Vec<K>,
+    children: Vec<NodeId>,
+    buffer: Vec<(K, Message)>,
+}
+
+impl<K> Default for Node<K> {
+    fn default() -> Self {
+        Node {
+            keys: Vec::new(),
+            children: Vec::new(),
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl<K: Ord> Node<K> {
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// Returns the index of the child whose subtree may contain the key.
+    fn child_index(&self, key: &K) -> usize {
+        match self.keys.binary_search(key) {
+            Ok(idx) => idx + 1,
+            Err(idx) => idx,
+        }
+    }
+
+    /// Returns the range of the buffer holding the messages for the child at
+    /// the given index.
+    fn batch_range(&self, idx: usize) -> Range<usize> {
+        let before = |pivot: &K| self.buffer.partition_point(|(key, _)| key < pivot);
+        let start = idx.checked_sub(1).map_or(0, |idx| before(&self.keys[idx]));
+        let end = self.keys.get(idx).map_or(self.buffer.len(), before);
+        start..end
+    }
+}
+
+impl<K: Ord + Clone, const B: usize> BEpsilonTreeSet<K, B> {
+    const MIN_KEYS: usize = B - 1;
+    const MAX_KEYS: usize = 2 * B - 1;
+
+    /// The number of messages an intermediate node buffers before it flushes
+    /// some of them to a child.
+    const BUFFER: usize = 4 * B * B;
+
+    pub fn new() -> Self {
+        BEpsilonTreeSet {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            root: None,
+        }
+    }
+
+    /// Inserts the key, without looking whether it is already in the tree.
+    pub fn put(&mut self, key: K) {
+        self.send(key, Message::Insert);
+    }
+
+    /// Removes the key, without looking whether it is in the tree.
+    pub fn delete(&mut self, key: K) {
+        self.send(key, Message::Remove);
+    }
+
+    /// Returns an iterator over the keys of the tree, in ascending order.
+    ///
+    /// The keys are collected up front, since the latest change to a key may
+    /// be buffered anywhere between the root and its leaf.
+    pub fn iter(&self) -> std::vec::IntoIter<&K> {
+        let mut keys = Vec::new();
+        if let Some(root) = self.root {
+            self.collect(root, &mut keys);
+        }
+        keys.into_iter()
+    }
+
+    /// Appends the keys of the subtree to `out`, applying the messages
+    /// buffered within it.
+    fn collect<'a>(&'a self, id: NodeId, out: &mut Vec<&'a K>) {
+        let node = &self.nodes[id];
+        if node.is_leaf() {
+            out.extend(&node.keys);
+            return;
+        }
+
+        for (idx, &child) in node.children.iter().enumerate() {
+            let start = out.len();
+            self.collect(child, out);
+            let below = out.split_off(start).into_iter();
+            let messages = node.buffer[node.batch_range(idx)].iter();
+            let merged = merge(
+                below.map(|key| (key, Message::Insert)),
+                messages.map(|(key, message)| (key, *message)),
+            );
+            out.extend(merged.into_iter().filter_map(inserted));
+        }
+    }
+
+    fn alloc(&mut self, node: Node<K>) -> NodeId {
+        match self.free.pop() {
+            Some(id) => {
+                self.nodes[id] = node;
+                id
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    /// Takes the node out of the arena, and puts its slot on the free list.
+    fn release(&mut self, id: NodeId) -> Node<K> {
+        self.free.push(id);
+        mem::take(&mut self.nodes[id])
+    }
+
+    /// Hands the message to the root.
+    fn send(&mut self, key: K, message: Message) {
+        let Some(root) = self.root else {
+            if message == Message::Insert {
+                let mut node = Node::default();
+                node.keys.push(key);
+                self.root = Some(self.alloc(node));
+            }
+            return;
+        };
+
+        self.deliver(root, [(key, message)]);
+        self.fix_root();
+    }
+
+    /// Hands the sorted batch of messages to the node. A leaf applies them to
+    /// its keys, while an intermediate node records them in its buffer, in
+    /// place of the older messages for the same keys.
+    fn deliver(&mut self, id: NodeId, batch: impl IntoIterator<Item = (K, Message)>) {
+        let node = &mut self.nodes[id];
+        if !node.is_leaf() {
+            for (key, message) in batch {
+                match node.buffer.binary_search_by(|(k, _)| k.cmp(&key)) {
+                    Ok(idx) => node.buffer[idx] = (key, message),
+                    Err(idx) => node.buffer.insert(idx, (key, message)),
+                }
+            }
+            self.flush(id);
+            return;
+        }
+
+        for (key, message) in batch {
+            match (node.keys.binary_search(&key), message) {
+                (Err(idx), Message::Insert) => node.keys.insert(idx, key),
+                (Ok(idx), Message::Remove) => {
+                    node.keys.remove(idx);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Moves the largest batch of messages for a single child down into it,
+    /// until the node buffers at most `BUFFER` messages, rebalancing the
+    /// children which received a batch.
+    fn flush(&mut self, id: NodeId) {
+        while self.nodes[id].buffer.len() > Self::BUFFER {
+            let node = &mut self.nodes[id];
+            let (idx, range) = (0..node.children.len())
+                .map(|idx| (idx, node.batch_range(idx)))
+                .max_by_key(|(_, range)| range.len())
+                .unwrap();
+            let child = node.children[idx];
+            let batch: Vec<_> = node.buffer.drain(range).collect();
+            self.deliver(child, batch);
+            self.rebalance(id, idx);
+        }
+    }
+
+    /// Splits the child at the given index while it holds too many keys, or
+    /// merges it with a sibling if it holds too few. A child which is the
+    /// only one of the node stays deficient, until the node itself is merged
+    /// with a sibling.
+    fn rebalance(&mut self, id: NodeId, idx: usize) {
+        let child = self.nodes[id].children[idx];
+        let len = self.nodes[child].keys.len();
+
+        if len > Self::MAX_KEYS {
+            while self.nodes[child].keys.len() > Self::MAX_KEYS {
+                let (separator, sibling) = self.split(child);
+                let node = &mut self.nodes[id];
+                node.keys.insert(idx, separator);
+                node.children.insert(idx + 1, sibling);
+            }
+        } else if len < Self::MIN_KEYS && self.nodes[id].children.len() > 1 {
+            // Merge with the left sibling, or the right one for the first
+            // child. The merged node may hold too many keys, or too few if
+            // its buffer had to be flushed, so it is rebalanced in turn.
+            let idx = idx.saturating_sub(1);
+            self.merge(id, idx);
+            self.rebalance(id, idx);
+        }
+    }
+
+    /// Splits the last `B` keys off the node into a new sibling, returning
+    /// the separator and the sibling. A leaf keeps a copy of the separator
+    /// as the first key of the sibling, while an intermediate node hoists it,
+    /// and hands the sibling the messages for the children it takes.
+    fn split(&mut self, id: NodeId) -> (K, NodeId) {
+        let node = &mut self.nodes[id];
+        let at = node.keys.len() - B;
+        let keys = node.keys.split_off(at);
+
+        if node.is_leaf() {
+            let separator = keys[0].clone();
+            let sibling = self.alloc(Node {
+                keys,
+                ..Node::default()
+            });
+            return (separator, sibling);
+        }
+
+        let separator = node.keys.pop().unwrap();
+        let children = node.children.split_off(at);
+        let buffer = node
+            .buffer
+            .split_off(node.buffer.partition_point(|(key, _)| *key < separator));
+        let sibling = self.alloc(Node {
+            keys,
+            children,
+            buffer,
+        });
+        (separator, sibling)
+    }
+
+    /// Merges the child after the given index into the child at it. Leaves
+    /// drop the separator, while intermediate nodes pull it down between
+    /// their pivots, and flush the buffer they end up with if it is too
+    /// large.
+    fn merge(&mut self, id: NodeId, idx: usize) {
+        let node = &mut self.nodes[id];
+        let separator = node.keys.remove(idx);
+        let right = node.children.remove(idx + 1);
+        let left = node.children[idx];
+        let right = self.release(right);
+
+        let node = &mut self.nodes[left];
+        if node.is_leaf() {
+            node.keys.extend(right.keys);
+            return;
+        }
+
+        node.keys.push(separator);
+        node.keys.extend(right.keys);
+        node.children.extend(right.children);
+        node.buffer.extend(right.buffer);
+        self.flush(left);
+
+        // Either node may have brought along a deficient only child.
+        let mut idx = 0;
+        while idx < self.nodes[left].children.len() {
+            let child = self.nodes[left].children[idx];
+            let len = self.nodes[child].keys.len();
+            if (Self::MIN_KEYS..=Self::MAX_KEYS).contains(&len) {
+                idx += 1;
+            } else {
+                self.rebalance(left, idx);
+                idx = 0;
+            }
+        }
+    }
+
+    /// Grows the tree while the root holds too many keys, and shrinks it
+    /// while the root is an empty leaf, or an intermediate node with a
+    /// single child, whose messages move down into the child.
+    fn fix_root(&mut self) {
+        while let Some(root) = self.root {
+            let node = &self.nodes[root];
+            if node.keys.len() > Self::MAX_KEYS {
+                let id = self.alloc(Node {
+                    children: vec![root],
+                    ..Node::default()
+                });
+                self.root = Some(id);
+                self.rebalance(id, 0);
+            } else if node.keys.is_empty() {
+                let node = self.release(root);
+                self.root = node.children.first().copied();
+                if let Some(child) = self.root {
+                    self.deliver(child, node.buffer);
+                }
+            } else {
+                return;
+            }
+        }
+    }
+}
+
+/// Merges the sorted newer messages into the sorted older ones, keeping only
+/// the newer message where both have one for the same key.
+fn merge<T: Ord>(
+    older: impl IntoIterator<Item = (T, Message)>,
+    newer: impl IntoIterator<Item = (T, Message)>,
+) -> Vec<(T, Message)> {
+    let mut older = older.into_iter().peekable();
+    let mut merged = Vec::new();
+    for (key, message) in newer {
+        while let Some(entry) = older.next_if(|(k, _)| *k < key) {
+            merged.push(entry);
+        }
+        older.next_if(|(k, _)| *k == key);
+        merged.push((key, message));
+    }
+    merged.extend(older);
+    merged
+}
+
+/// Returns the key of an insertion.
+fn inserted<T>((key, message): (T, Message)) -> Option<T> {
+    (message == Message::Insert).then_some(key)
+}
+
+impl<K: Ord + Clone, const B: usize> Default for BEpsilonTreeSet<K, B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord + Clone, const B: usize> BTreeSet for BEpsilonTreeSet<K, B> {
+    type Key = K;
+    const B: usize = B;
+
+    fn search(&self, key: &Self::Key) -> Result<&Self::Key> {
+        let mut id = self.root.ok_or(Error::KeyNotFound)?;
+        while !self.nodes[id].is_leaf() {
+            let node = &self.nodes[id];
+            match node.buffer.binary_search_by(|(k, _)| k.cmp(key)) {
+                Ok(idx) if node.buffer[idx].1 == Message::Insert => return Ok(&node.buffer[idx].0),
+                Ok(_) => return Err(Error::KeyNotFound),
+                Err(_) => id = node.children[node.child_index(key)],
+            }
+        }
+
+        let keys = &self.nodes[id].keys;
+        match keys.binary_search(key) {
+            Ok(idx) => Ok(&keys[idx]),
+            Err(_) => Err(Error::KeyNotFound),
+        }
+    }
+
+    fn insert(&mut self, key: Self::Key) -> Result<()> {
+        if self.contains(&key) {
+            return Err(Error::KeyAlreadyExists);
+        }
+        self.put(key);
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &Self::Key) -> Result<Self::Key> {
+        let key = self.search(key)?.clone();
+        self.delete(key.clone());
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::{DifferentialTester, ReferenceBTreeSet};
+    use crate::test_btree_impl;
+    use crate::workload::{Kind, Workload};
+
+    test_btree_impl!(BEpsilonTreeSet);
+
+    /// Checks that the nodes hold as many keys and messages as they may, that
+    /// keys, pivots and messages are sorted and within the range of their
+    /// node, and that every leaf is at the same depth.
+    fn check<K: Ord + Clone, const B: usize>(tree: &BEpsilonTreeSet<K, B>) {
+        fn walk<K: Ord + Clone, const B: usize>(
+            tree: &BEpsilonTreeSet<K, B>,
+            id: NodeId,
+            bounds: (Option<&K>, Option<&K>),
+            is_root: bool,
+        ) -> usize {
+            let node = &tree.nodes[id];
+            let len = node.keys.len();
+            assert!(len <= BEpsilonTreeSet::<K, B>::MAX_KEYS);
+            assert!(is_root || len >= BEpsilonTreeSet::<K, B>::MIN_KEYS);
+            assert!(node.buffer.len() <= BEpsilonTreeSet::<K, B>::BUFFER);
+            assert!(node.keys.is_sorted_by(|a, b| a < b));
+            assert!(node.buffer.is_sorted_by(|a, b| a.0 < b.0));
+
+            let within = |key: &K| {
+                bounds.0.is_none_or(|low| low <= key) && bounds.1.is_none_or(|high| key < high)
+            };
+            assert!(node.keys.iter().all(within));
+            assert!(node.buffer.iter().all(|(key, _)| within(key)));
+
+            if node.is_leaf() {
+                return 0;
+            }
+            assert_eq!(node.children.len(), len + 1);
+            let depths: Vec<_> = (0..=len)
+                .map(|idx| {
+                    let low = idx.checked_sub(1).map(|idx| &node.keys[idx]).or(bounds.0);
+                    let high = node.keys.get(idx).or(bounds.1);
+                    walk(tree, node.children[idx], (low, high), false)
+                })
+                .collect();
+            assert!(depths.iter().all(|&depth| depth == depths[0]));
+            depths[0] + 1
+        }
+
+        if let Some(root) = tree.root {
+            walk(tree, root, (None, None), true);
+        }
+    }
+
+    #[test]
+    fn test_agrees_with_reference_on_mixed_operations() {
+        let mut tester =
+            DifferentialTester::new(BEpsilonTreeSet::<usize, 2>::new(), ReferenceBTreeSet::new());
+
+        for i in 0..20_000usize {
+            let key = (i * 7919) % 503;
+            let _ = match i % 3 {
+                0 => tester.remove(&key).map(|_| ()),
+                _ => tester.insert(key),
+            };
+            if i % 100 == 0 {
+                check(tester.first());
+            }
+        }
+
+        let (tree, reference) = tester.into_inner();
+        check(&tree);
+        assert!(tree.iter().eq(reference.iter()));
+    }
+
+    #[test]
+    fn test_workloads() {
+        for kind in Kind::ALL {
+            let mut tree = BEpsilonTreeSet::<u64, 3>::new();
+            Workload::new(kind, 0x5eed)
+                .with_key_space(4096)
+                .check(&mut tree, 20_000);
+            check(&tree);
+        }
+    }
+
+    #[test]
+    fn test_blind_writes_agree_with_std() {
+        let mut tree = BEpsilonTreeSet::<usize, 2>::new();
+        let mut reference = std::collections::BTreeSet::new();
+        for i in 0..30_000usize {
+            let key = (i * 7919) % 1009;
+            if i % 5 < 2 {
+                tree.delete(key);
+                reference.remove(&key);
+            } else {
+                tree.put(key);
+                reference.insert(key);
+            }
+            if i % 500 == 0 {
+                check(&tree);
+                assert!(tree.iter().eq(&reference));
+            }
+        }
+        check(&tree);
+        assert!(tree.iter().eq(&reference));
+    }
+
+    #[test]
+    fn test_blind_writes_are_buffered() {
+        let mut tree = BEpsilonTreeSet::<usize, 4>::new();
+        for key in 0..10_000 {
+            tree.put(key * 7919 % 10_000);
+        }
+        check(&tree);
+
+        // Most of the latest writes have not reached their leaves yet.
+        let root = &tree.nodes[tree.root.unwrap()];
+        assert!(!root.buffer.is_empty());
+        assert!(tree.iter().copied().eq(0..10_000));
+
+        // Writing a key again, or deleting a missing one, changes nothing.
+        tree.put(42);
+        tree.delete(10_000);
+        for key in (0..10_000).filter(|key| key % 4 != 0) {
+            tree.delete(key);
+        }
+        check(&tree);
+        assert!(tree.iter().copied().eq((0..10_000).step_by(4)));
+        assert_eq!(tree.search(&4).unwrap(), &4);
+        assert!(matches!(tree.search(&5), Err(Error::KeyNotFound)));
+
+        for key in (0..10_000).step_by(4) {
+            tree.delete(key);
+        }
+        check(&tree);
+        assert_eq!(tree.iter().next(), None);
+    }
+}
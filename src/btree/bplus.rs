@@ -0,0 +1,683 @@
+use super::array::Array;
+use super::prefix::Prefixed;
+use crate::{BTreeSet, Error, Result};
+use std::iter::FusedIterator;
+use std::mem;
+use std::ops::{Bound, RangeBounds};
+
+/// A B+ tree, which keeps every key in its leaves. Intermediate nodes only
+/// hold separators, copies of keys which route searches to the right child,
+/// and each leaf links to the next one, so scans move from leaf to leaf
+/// without climbing back up the tree.
+///
+/// Like `ArenaBTreeSet`, the nodes live in a single arena, and children and
+/// leaf links are indices into it.
+///
+/// The K type parameter represents the key type, and B is the branching factor.
+pub struct BPlusTreeSet<K, const B: usize = 6> {
+    nodes: Vec<Node<K, B>>,
+    free: Vec<NodeId>,
+    root: Option<NodeId>,
+}
+
+/// The index of a node in the arena.
+type NodeId = usize;
+
+/// A node in the arena. Leaf nodes are the ones with no children, and only
+/// they use the link to their successor.
+///
+/// Every key in the subtree of `children[i]` is less than `keys[i]`, and
+/// every key in the subtree of `children[i + 1]` is greater than or equal to
+/// it.
+struct Node<K, const B: usize> {
+    keys: Array<K, B>,
+    children: Array<NodeId, B>,
+    next: Option<NodeId>,
+}
+
+impl<K, const B: usize> Default for Node<K, B> {
+    fn default() -> Self {
+        Node {
+            keys: Array::new(),
+            children: Array::new(),
+            next: None,
+        }
+    }
+}
+
+impl<K: Ord, const B: usize> Node<K, B> {
+    const MIN_KEYS: usize = B - 1;
+    const MAX_KEYS: usize = 2 * B - 1;
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// Returns the index of the child whose subtree may contain the key.
+    fn child_index(&self, key: &K) -> usize {
+        match self.keys.binary_search(key) {
+            Ok(idx) => idx + 1,
+            Err(idx) => idx,
+        }
+    }
+}
+
+impl<K: Ord + Clone, const B: usize> BPlusTreeSet<K, B> {
+    pub fn new() -> Self {
+        BPlusTreeSet {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            root: None,
+        }
+    }
+
+    /// Returns the number of bytes the tree has allocated, counted like
+    /// `ArenaBTreeSet::heap_size`.
+    pub fn heap_size(&self) -> usize {
+        self.nodes.capacity() * mem::size_of::<Node<K, B>>()
+            + self.free.capacity() * mem::size_of::<NodeId>()
+    }
+
+    /// Returns an iterator over the keys of the tree, in ascending order.
+    pub fn iter(&self) -> Iter<'_, K, B> {
+        self.range(..)
+    }
+
+    /// Returns an iterator over the leaves of the tree, in ascending order,
+    /// which yields the keys of every leaf as one slice. Scanning slices
+    /// saves the per-key bookkeeping of `iter`, and lets the caller work on
+    /// many keys at once.
+    pub fn iter_chunks(&self) -> Chunks<'_, K, B> {
+        let mut leaf = self.root;
+        while let Some(id) = leaf
+            && !self.nodes[id].is_leaf()
+        {
+            leaf = Some(self.nodes[id].children[0]);
+        }
+        Chunks {
+            nodes: &self.nodes,
+            leaf,
+        }
+    }
+
+    /// Returns an iterator over the keys of the tree within the range, in
+    /// ascending order.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Iter<'_, K, B> {
+        let mut iter = Iter {
+            nodes: &self.nodes,
+            leaf: None,
+            idx: 0,
+            end: range.end_bound().cloned(),
+        };
+
+        let Some(mut id) = self.root else {
+            return iter;
+        };
+
+        let start = range.start_bound();
+        while !self.nodes[id].is_leaf() {
+            let node = &self.nodes[id];
+            id = match start {
+                Bound::Included(key) | Bound::Excluded(key) => node.children[node.child_index(key)],
+                Bound::Unbounded => node.children[0],
+            };
+        }
+
+        let keys = &self.nodes[id].keys;
+        iter.leaf = Some(id);
+        iter.idx = match start {
+            Bound::Included(key) => keys.partition_point(|k| k < key),
+            Bound::Excluded(key) => keys.partition_point(|k| k <= key),
+            Bound::Unbounded => 0,
+        };
+        iter
+    }
+
+    /// Returns an iterator over the keys of the tree within the range, in
+    /// descending order, which starts at the end of the range without
+    /// visiting the keys before it.
+    pub fn range_rev<R: RangeBounds<K>>(&self, range: R) -> RevIter<'_, K, B> {
+        let mut iter = RevIter {
+            nodes: &self.nodes,
+            path: Vec::new(),
+            leaf: None,
+            idx: 0,
+            start: range.start_bound().cloned(),
+        };
+
+        let Some(mut id) = self.root else {
+            return iter;
+        };
+
+        let end = range.end_bound();
+        while !self.nodes[id].is_leaf() {
+            let node = &self.nodes[id];
+            let idx = match end {
+                Bound::Included(key) | Bound::Excluded(key) => node.child_index(key),
+                Bound::Unbounded => node.children.len() - 1,
+            };
+            iter.path.push((id, idx));
+            id = node.children[idx];
+        }
+
+        let keys = &self.nodes[id].keys;
+        iter.leaf = Some(id);
+        iter.idx = match end {
+            Bound::Included(key) => keys.partition_point(|k| k <= key),
+            Bound::Excluded(key) => keys.partition_point(|k| k < key),
+            Bound::Unbounded => keys.len(),
+        };
+        iter
+    }
+
+    /// Returns an iterator over the keys of the tree which start with the
+    /// prefix, in ascending order.
+    pub fn prefix_iter(&self, prefix: &K::Prefix) -> Iter<'_, K, B>
+    where
+        K: Prefixed,
+    {
+        self.range(K::prefix_range(prefix))
+    }
+
+    fn alloc(&mut self, node: Node<K, B>) -> NodeId {
+        match self.free.pop() {
+            Some(id) => {
+                self.nodes[id] = node;
+                id
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    /// Takes the node out of the arena, and puts its slot on the free list.
+    fn release(&mut self, id: NodeId) -> Node<K, B> {
+        self.free.push(id);
+        mem::take(&mut self.nodes[id])
+    }
+
+    /// Inserts the key into the subtree, returning the separator and the new
+    /// sibling when the node had to be split.
+    fn insert_into(&mut self, id: NodeId, key: K) -> Result<Option<(K, NodeId)>> {
+        let node = &self.nodes[id];
+
+        if node.is_leaf() {
+            let Err(idx) = node.keys.binary_search(&key) else {
+                return Err(Error::KeyAlreadyExists);
+            };
+            self.nodes[id].keys.insert(idx, key);
+        } else {
+            let idx = node.child_index(&key);
+            if let Some((separator, sibling)) = self.insert_into(node.children[idx], key)? {
+                let node = &mut self.nodes[id];
+                node.keys.insert(idx, separator);
+                node.children.insert(idx + 1, sibling);
+            }
+        }
+
+        if self.nodes[id].keys.len() > Node::<K, B>::MAX_KEYS {
+            Ok(Some(self.split(id)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Splits the overflowed node, returning the separator and the new
+    /// sibling. A leaf keeps a copy of the separator as the first key of the
+    /// sibling, while an intermediate node hoists it.
+    fn split(&mut self, id: NodeId) -> (K, NodeId) {
+        let node = &mut self.nodes[id];
+        let keys = node.keys.split_off(B);
+
+        if node.is_leaf() {
+            let separator = keys[0].clone();
+            let next = node.next;
+            let sibling = self.alloc(Node {
+                keys,
+                children: Array::new(),
+                next,
+            });
+            self.nodes[id].next = Some(sibling);
+            return (separator, sibling);
+        }
+
+        let separator = node.keys.pop_back().unwrap();
+        let children = node.children.split_off(B);
+        let sibling = self.alloc(Node {
+            keys,
+            children,
+            next: None,
+        });
+        (separator, sibling)
+    }
+
+    /// Removes the key from the subtree, leaving the node at `id` possibly
+    /// deficient, but all of its descendants valid.
+    fn remove_from(&mut self, id: NodeId, key: &K) -> Option<K> {
+        let node = &self.nodes[id];
+
+        if node.is_leaf() {
+            let idx = node.keys.binary_search(key).ok()?;
+            return self.nodes[id].keys.remove(idx);
+        }
+
+        let idx = node.child_index(key);
+        let removed = self.remove_from(node.children[idx], key)?;
+        self.fix_deficient_child(id, idx);
+        Some(removed)
+    }
+
+    /// Refills the child at the given index if it became deficient, either by
+    /// rotating a key from one of its siblings, or by merging it with one.
+    fn fix_deficient_child(&mut self, id: NodeId, idx: usize) {
+        let children = &self.nodes[id].children;
+        if self.nodes[children[idx]].keys.len() >= Node::<K, B>::MIN_KEYS {
+            return;
+        }
+
+        let left = idx.checked_sub(1).map(|i| children[i]);
+        let right = children.get(idx + 1).copied();
+        let can_spare = |sibling: NodeId| self.nodes[sibling].keys.len() > Node::<K, B>::MIN_KEYS;
+
+        match (left, right) {
+            (Some(left), _) if can_spare(left) => self.rotate_right(id, idx),
+            (_, Some(right)) if can_spare(right) => self.rotate_left(id, idx),
+            (Some(_), _) => self.merge(id, idx - 1),
+            (None, Some(_)) => self.merge(id, idx),
+            (None, None) => unreachable!("intermediate nodes have at least two children"),
+        }
+    }
+
+    /// Moves the last key of the left sibling into the child. Between leaves
+    /// the key moves directly, and the separator becomes a copy of it, while
+    /// between intermediate nodes it rotates through the separator.
+    fn rotate_right(&mut self, id: NodeId, idx: usize) {
+        let (left, child) = (
+            self.nodes[id].children[idx - 1],
+            self.nodes[id].children[idx],
+        );
+        let key = self.nodes[left].keys.pop_back().unwrap();
+        let grandchild = self.nodes[left].children.pop_back();
+
+        let key = match grandchild {
+            Some(_) => mem::replace(&mut self.nodes[id].keys[idx - 1], key),
+            None => {
+                self.nodes[id].keys[idx - 1] = key.clone();
+                key
+            }
+        };
+
+        let child = &mut self.nodes[child];
+        child.keys.push_front(key);
+        if let Some(grandchild) = grandchild {
+            child.children.push_front(grandchild);
+        }
+    }
+
+    /// Moves the first key of the right sibling into the child, the same way
+    /// `rotate_right` does in the other direction.
+    fn rotate_left(&mut self, id: NodeId, idx: usize) {
+        let (child, right) = (
+            self.nodes[id].children[idx],
+            self.nodes[id].children[idx + 1],
+        );
+        let key = self.nodes[right].keys.pop_front().unwrap();
+        let grandchild = self.nodes[right].children.pop_front();
+
+        let key = match grandchild {
+            Some(_) => mem::replace(&mut self.nodes[id].keys[idx], key),
+            None => {
+                self.nodes[id].keys[idx] = self.nodes[right].keys[0].clone();
+                key
+            }
+        };
+
+        let child = &mut self.nodes[child];
+        child.keys.push_back(key);
+        if let Some(grandchild) = grandchild {
+            child.children.push_back(grandchild);
+        }
+    }
+
+    /// Merges the child after the given index into the child at it. Leaves
+    /// drop the separator and take over the link of the merged sibling,
+    /// while intermediate nodes pull the separator down between their keys.
+    fn merge(&mut self, id: NodeId, idx: usize) {
+        let separator = self.nodes[id].keys.remove(idx).unwrap();
+        let right = self.nodes[id].children.remove(idx + 1).unwrap();
+        let left = self.nodes[id].children[idx];
+        let right = self.release(right);
+
+        let left = &mut self.nodes[left];
+        if left.is_leaf() {
+            left.next = right.next;
+        } else {
+            left.keys.push_back(separator);
+        }
+        left.keys.extend(right.keys);
+        left.children.extend(right.children);
+    }
+}
+
+impl<K: Ord + Clone, const B: usize> Default for BPlusTreeSet<K, B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord + Clone, const B: usize> BTreeSet for BPlusTreeSet<K, B> {
+    type Key = K;
+    const B: usize = B;
+
+    fn search(&self, key: &Self::Key) -> Result<&Self::Key> {
+        let mut id = self.root.ok_or(Error::KeyNotFound)?;
+        while !self.nodes[id].is_leaf() {
+            let node = &self.nodes[id];
+            id = node.children[node.child_index(key)];
+        }
+
+        let keys = &self.nodes[id].keys;
+        match keys.binary_search(key) {
+            Ok(idx) => Ok(&keys[idx]),
+            Err(_) => Err(Error::KeyNotFound),
+        }
+    }
+
+    fn insert(&mut self, key: Self::Key) -> Result<()> {
+        let Some(root) = self.root else {
+            let mut node = Node::default();
+            node.keys.push_back(key);
+            self.root = Some(self.alloc(node));
+            return Ok(());
+        };
+
+        if let Some((separator, sibling)) = self.insert_into(root, key)? {
+            let mut node = Node::default();
+            node.keys.push_back(separator);
+            node.children.extend([root, sibling]);
+            self.root = Some(self.alloc(node));
+        }
+
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &Self::Key) -> Result<Self::Key> {
+        let root = self.root.ok_or(Error::KeyNotFound)?;
+        let removed = self.remove_from(root, key).ok_or(Error::KeyNotFound)?;
+
+        let node = &self.nodes[root];
+        if node.keys.is_empty() {
+            self.root = node.children.first().copied();
+            self.release(root);
+        }
+
+        Ok(removed)
+    }
+}
+
+impl<'a, K: Ord + Clone, const B: usize> IntoIterator for &'a BPlusTreeSet<K, B> {
+    type Item = &'a K;
+    type IntoIter = Iter<'a, K, B>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the keys of a `BPlusTreeSet`, in ascending order. It
+/// walks the linked leaves, so every step takes constant time.
+pub struct Iter<'a, K, const B: usize> {
+    nodes: &'a [Node<K, B>],
+    leaf: Option<NodeId>,
+    idx: usize,
+    end: Bound<K>,
+}
+
+impl<'a, K: Ord, const B: usize> Iterator for Iter<'a, K, B> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = &self.nodes[self.leaf?];
+            let Some(key) = node.keys.get(self.idx) else {
+                self.leaf = node.next;
+                self.idx = 0;
+                continue;
+            };
+
+            let within = match &self.end {
+                Bound::Included(end) => key <= end,
+                Bound::Excluded(end) => key < end,
+                Bound::Unbounded => true,
+            };
+            if !within {
+                self.leaf = None;
+                return None;
+            }
+
+            self.idx += 1;
+            return Some(key);
+        }
+    }
+}
+
+impl<K: Ord, const B: usize> FusedIterator for Iter<'_, K, B> {}
+
+/// An iterator over the keys of a `BPlusTreeSet` within a range, in
+/// descending order, as returned by `BPlusTreeSet::range_rev`.
+///
+/// The leaves only link to their successors, so the iterator keeps the path
+/// from the root to its leaf, and moves to the previous leaf through the
+/// closest ancestor with a child to the left of the path.
+pub struct RevIter<'a, K, const B: usize> {
+    nodes: &'a [Node<K, B>],
+    /// The intermediate nodes from the root down to the leaf, each with the
+    /// index of the child the path descends into.
+    path: Vec<(NodeId, usize)>,
+    leaf: Option<NodeId>,
+    /// The number of keys of the leaf which are left to yield.
+    idx: usize,
+    start: Bound<K>,
+}
+
+impl<K, const B: usize> RevIter<'_, K, B> {
+    /// Moves to the last key of the leaf before the current one, or returns
+    /// `None` if the current leaf is the first one.
+    fn prev_leaf(&mut self) -> Option<NodeId> {
+        let mut id = loop {
+            let (id, idx) = self.path.last_mut()?;
+            if *idx > 0 {
+                *idx -= 1;
+                break self.nodes[*id].children[*idx];
+            }
+            self.path.pop();
+        };
+
+        while !self.nodes[id].children.is_empty() {
+            let idx = self.nodes[id].children.len() - 1;
+            self.path.push((id, idx));
+            id = self.nodes[id].children[idx];
+        }
+        self.idx = self.nodes[id].keys.len();
+        Some(id)
+    }
+}
+
+impl<'a, K: Ord, const B: usize> Iterator for RevIter<'a, K, B> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let id = self.leaf?;
+            if self.idx == 0 {
+                self.leaf = self.prev_leaf();
+                continue;
+            }
+
+            let key = &self.nodes[id].keys[self.idx - 1];
+            let within = match &self.start {
+                Bound::Included(start) => key >= start,
+                Bound::Excluded(start) => key > start,
+                Bound::Unbounded => true,
+            };
+            if !within {
+                self.leaf = None;
+                return None;
+            }
+
+            self.idx -= 1;
+            return Some(key);
+        }
+    }
+}
+
+impl<K: Ord, const B: usize> FusedIterator for RevIter<'_, K, B> {}
+
+/// An iterator over the keys of a `BPlusTreeSet`, one leaf at a time, as
+/// returned by `BPlusTreeSet::iter_chunks`. Every slice holds at least one
+/// key.
+pub struct Chunks<'a, K, const B: usize> {
+    nodes: &'a [Node<K, B>],
+    leaf: Option<NodeId>,
+}
+
+impl<'a, K, const B: usize> Iterator for Chunks<'a, K, B> {
+    type Item = &'a [K];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = &self.nodes[self.leaf?];
+            self.leaf = node.next;
+            // Only an empty root leaf has no keys.
+            if !node.keys.is_empty() {
+                return Some(&node.keys);
+            }
+        }
+    }
+}
+
+impl<K, const B: usize> FusedIterator for Chunks<'_, K, B> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::{DifferentialTester, ReferenceBTreeSet};
+    use crate::test_btree_impl;
+
+    test_btree_impl!(BPlusTreeSet);
+
+    #[test]
+    fn test_agrees_with_reference_on_mixed_operations() {
+        let mut tester =
+            DifferentialTester::new(BPlusTreeSet::<usize, 2>::new(), ReferenceBTreeSet::new());
+
+        for i in 0..20_000usize {
+            let key = (i * 7919) % 503;
+            let _ = match i % 3 {
+                0 => tester.remove(&key).map(|_| ()),
+                _ => tester.insert(key),
+            };
+        }
+
+        let (tree, reference) = tester.into_inner();
+        assert!(tree.iter().eq(reference.iter()));
+    }
+
+    #[test]
+    fn test_chunks_are_the_leaves_in_order() {
+        let mut tree = BPlusTreeSet::<usize, 2>::new();
+        assert_eq!(tree.iter_chunks().next(), None);
+        for key in (0..1000).rev() {
+            tree.insert(key).unwrap();
+        }
+        for key in 0..1000 {
+            tree.remove(&key).unwrap();
+        }
+        assert_eq!(tree.iter_chunks().next(), None);
+
+        for key in 0..1000 {
+            tree.insert(key * 3).unwrap();
+        }
+        let chunks: Vec<&[usize]> = tree.iter_chunks().collect();
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| (1..=3).contains(&chunk.len())));
+        assert!(chunks.concat().into_iter().eq(tree.iter().copied()));
+    }
+
+    #[test]
+    fn test_range_rev_matches_std() {
+        let mut tree = BPlusTreeSet::<usize, 2>::new();
+        let mut std = std::collections::BTreeSet::new();
+        assert_eq!(tree.range_rev(..).next(), None);
+        for key in (0..600).map(|i| i * 7919 % 1000) {
+            tree.insert(key).unwrap();
+            std.insert(key);
+        }
+        for key in (0..1000).step_by(3) {
+            tree.remove(&key).ok();
+            std.remove(&key);
+        }
+
+        assert!(tree.range_rev(..).eq(std.iter().rev()));
+        for (start, end) in [(0, 1000), (5, 5), (10, 11), (123, 456), (998, 2000)] {
+            assert!(tree.range_rev(start..end).eq(std.range(start..end).rev()));
+            assert!(tree.range_rev(start..=end).eq(std.range(start..=end).rev()));
+            assert!(tree.range_rev(..end).eq(std.range(..end).rev()));
+            assert!(tree.range_rev(start..).eq(std.range(start..).rev()));
+        }
+        assert!(
+            tree.range_rev(..500)
+                .take(3)
+                .eq(std.range(..500).rev().take(3))
+        );
+    }
+
+    #[test]
+    fn test_prefix_iter_yields_keys_with_the_prefix() {
+        let mut tree = BPlusTreeSet::<String, 2>::new();
+        for i in 0..300 {
+            tree.insert(format!("{}/{i}", ["a", "ab", "b"][i % 3]))
+                .unwrap();
+        }
+
+        assert_eq!(tree.prefix_iter("a/").count(), 100);
+        assert!(tree.prefix_iter("ab").all(|key| key.starts_with("ab/")));
+        assert_eq!(tree.prefix_iter("a").count(), 200);
+        assert_eq!(tree.prefix_iter("c").next(), None);
+        assert_eq!(tree.prefix_iter("").count(), 300);
+    }
+
+    #[test]
+    fn test_range_walks_the_leaves() {
+        let mut tree = BPlusTreeSet::<usize, 2>::new();
+        for key in 0..1000 {
+            tree.insert(key * 2).unwrap();
+        }
+
+        assert!(tree.iter().copied().eq((0..1000).map(|i| i * 2)));
+        assert!(tree.range(100..110).copied().eq([100, 102, 104, 106, 108]));
+        assert!(tree.range(101..=110).copied().eq([102, 104, 106, 108, 110]));
+        assert!(
+            tree.range((Bound::Excluded(1994), Bound::Unbounded))
+                .copied()
+                .eq([1996, 1998])
+        );
+        assert_eq!(tree.range(2000..).next(), None);
+        assert_eq!(BPlusTreeSet::<usize>::new().iter().next(), None);
+    }
+
+    #[test]
+    fn test_leaf_links_survive_removals() {
+        let mut tree = BPlusTreeSet::<usize, 2>::new();
+        for key in 0..1000 {
+            tree.insert(key).unwrap();
+        }
+        for key in (0..1000).filter(|key| key % 3 != 0) {
+            tree.remove(&key).unwrap();
+        }
+
+        assert!(tree.iter().copied().eq((0..1000).step_by(3)));
+    }
+}
@@ -0,0 +1,742 @@
+use super::sync::{AtomicPtr, AtomicUsize, Ordering};
+use crate::{BTreeSet, Error, Result};
+use std::iter::FusedIterator;
+use std::ptr;
+
+/// The number of pages the mapping table holds, unless told otherwise.
+pub const DEFAULT_MAPPING_TABLE_SIZE: usize = 1 << 16;
+
+/// The number of delta records a page collects before it is consolidated.
+const MAX_CHAIN_LEN: usize = 8;
+
+type PageId = usize;
+
+/// An experimental, lock-free Bw-tree.
+///
+/// Pages are never updated in place. A mapping table translates the id of a
+/// page to the newest record of its chain, and every change is a delta
+/// record prepended to the chain with a single compare-and-swap on the
+/// mapping table. Once a chain grows long, it is consolidated into a new
+/// base page, which is split in two if it holds too many keys.
+///
+/// Splits follow the B-link design: the split is first installed as a delta
+/// on the page, which sends the keys past the separator to the new right
+/// sibling, and only then posted to the parent. Any thread may finish
+/// growing the tree when the root splits, so no thread ever waits for
+/// another. Pages are never merged.
+///
+/// There is no epoch-based reclamation: the records replaced by a
+/// consolidation are only freed when the tree is dropped, which makes the
+/// tree suitable for research and testing rather than for long-running use.
+/// Once the mapping table is full, pages stop splitting and grow instead.
+///
+/// The K type parameter represents the key type, and B is the branching
+/// factor, which bounds consolidated pages to `2 * B - 1` keys.
+pub struct BwTreeSet<K, const B: usize = 6> {
+    table: Box<[AtomicPtr<Page<K>>]>,
+    next_id: AtomicUsize,
+    root: AtomicUsize,
+    retired: AtomicPtr<Page<K>>,
+}
+
+// SAFETY: The records of a tree are only shared through the tree itself, and
+// are immutable once installed, apart from the atomic link of the retired
+// list.
+unsafe impl<K: Send + Sync, const B: usize> Send for BwTreeSet<K, B> {}
+unsafe impl<K: Send + Sync, const B: usize> Sync for BwTreeSet<K, B> {}
+
+/// A record of the chain of a page: its base page, or a delta on top of it.
+struct Page<K> {
+    /// The level of the page, with leaves at level 0.
+    level: usize,
+    /// The number of deltas on top of the base page.
+    chain_len: usize,
+    kind: Kind<K>,
+    /// The next record in the list of retired records.
+    retired: AtomicPtr<Page<K>>,
+}
+
+enum Kind<K> {
+    /// A consolidated page. Keys at or above `high` belong to the `right`
+    /// sibling. Inner pages have one more child than they have keys.
+    Base {
+        keys: Vec<K>,
+        children: Vec<PageId>,
+        high: Option<K>,
+        right: Option<PageId>,
+    },
+    Insert {
+        key: K,
+        next: *mut Page<K>,
+    },
+    Delete {
+        key: K,
+        next: *mut Page<K>,
+    },
+    /// The keys at or above the separator were moved to the right sibling.
+    Split {
+        separator: K,
+        right: PageId,
+        next: *mut Page<K>,
+    },
+    /// The keys from `low` up to `high` now belong to the child.
+    IndexEntry {
+        low: K,
+        high: Option<K>,
+        child: PageId,
+        next: *mut Page<K>,
+    },
+}
+
+/// Where a key leads within a page.
+enum Route<'a, K> {
+    Found(&'a K),
+    Absent,
+    Child(PageId),
+    Right(PageId),
+}
+
+/// The consolidated contents of a chain.
+struct View<'a, K> {
+    keys: Vec<&'a K>,
+    children: Vec<PageId>,
+    high: Option<&'a K>,
+    right: Option<PageId>,
+}
+
+impl<K> Page<K> {
+    fn base(
+        level: usize,
+        keys: Vec<K>,
+        children: Vec<PageId>,
+        high: Option<K>,
+        right: Option<PageId>,
+    ) -> Box<Self> {
+        Box::new(Page {
+            level,
+            chain_len: 0,
+            kind: Kind::Base {
+                keys,
+                children,
+                high,
+                right,
+            },
+            retired: AtomicPtr::new(ptr::null_mut()),
+        })
+    }
+
+    fn delta(kind: Kind<K>) -> Box<Self> {
+        Box::new(Page {
+            level: 0,
+            chain_len: 0,
+            kind,
+            retired: AtomicPtr::new(ptr::null_mut()),
+        })
+    }
+
+    fn next(&self) -> Option<&Page<K>> {
+        match self.kind {
+            Kind::Base { .. } => None,
+            Kind::Insert { next, .. }
+            | Kind::Delete { next, .. }
+            | Kind::Split { next, .. }
+            | Kind::IndexEntry { next, .. } => {
+                // SAFETY: The records of a chain outlive every reference to
+                // the tree, see `BwTreeSet::load`.
+                Some(unsafe { &*next })
+            }
+        }
+    }
+
+    /// Puts the delta on top of the given head of a chain.
+    fn link(&mut self, head: *mut Page<K>) {
+        // SAFETY: The head was loaded from the mapping table.
+        let head_ref = unsafe { &*head };
+        self.level = head_ref.level;
+        self.chain_len = head_ref.chain_len + 1;
+        match &mut self.kind {
+            Kind::Base { .. } => unreachable!("a base page is not a delta"),
+            Kind::Insert { next, .. }
+            | Kind::Delete { next, .. }
+            | Kind::Split { next, .. }
+            | Kind::IndexEntry { next, .. } => *next = head,
+        }
+    }
+}
+
+impl<K: Ord> Page<K> {
+    /// Returns where the key leads from the chain starting at this record.
+    fn route(&self, key: &K) -> Route<'_, K> {
+        let mut page = self;
+        loop {
+            match &page.kind {
+                Kind::Insert { key: found, .. } if found == key => return Route::Found(found),
+                Kind::Delete { key: found, .. } if found == key => return Route::Absent,
+                Kind::Split {
+                    separator, right, ..
+                } if key >= separator => return Route::Right(*right),
+                Kind::IndexEntry {
+                    low, high, child, ..
+                } if low <= key && high.as_ref().is_none_or(|high| key < high) => {
+                    return Route::Child(*child);
+                }
+                Kind::Base {
+                    keys,
+                    children,
+                    high,
+                    right,
+                } => {
+                    if high.as_ref().is_some_and(|high| key >= high) {
+                        return Route::Right(right.expect("a bounded page has a sibling"));
+                    }
+                    if page.level > 0 {
+                        return Route::Child(children[keys.partition_point(|k| k <= key)]);
+                    }
+                    return match keys.binary_search(key) {
+                        Ok(i) => Route::Found(&keys[i]),
+                        Err(_) => Route::Absent,
+                    };
+                }
+                _ => page = page.next().expect("a delta has a next record"),
+            }
+        }
+    }
+
+    /// Applies the deltas of the chain starting at this record to its base
+    /// page, oldest first.
+    fn view(&self) -> View<'_, K> {
+        let mut deltas = Vec::with_capacity(self.chain_len);
+        let mut page = self;
+        while let Some(next) = page.next() {
+            deltas.push(page);
+            page = next;
+        }
+
+        let Kind::Base {
+            keys,
+            children,
+            high,
+            right,
+        } = &page.kind
+        else {
+            unreachable!("a chain ends in a base page");
+        };
+        let mut view = View {
+            keys: keys.iter().collect(),
+            children: children.clone(),
+            high: high.as_ref(),
+            right: *right,
+        };
+
+        for delta in deltas.into_iter().rev() {
+            match &delta.kind {
+                Kind::Insert { key, .. } => {
+                    if let Err(i) = view.keys.binary_search(&key) {
+                        view.keys.insert(i, key);
+                    }
+                }
+                Kind::Delete { key, .. } => {
+                    if let Ok(i) = view.keys.binary_search(&key) {
+                        view.keys.remove(i);
+                    }
+                }
+                Kind::Split {
+                    separator, right, ..
+                } => {
+                    let at = view.keys.partition_point(|&k| k < separator);
+                    view.keys.truncate(at);
+                    if self.level > 0 {
+                        view.children.truncate(at + 1);
+                    }
+                    view.high = Some(separator);
+                    view.right = Some(*right);
+                }
+                Kind::IndexEntry { low, child, .. } => {
+                    let at = view.keys.partition_point(|&k| k < low);
+                    view.keys.insert(at, low);
+                    view.children.insert(at + 1, *child);
+                }
+                Kind::Base { .. } => unreachable!("a chain has a single base page"),
+            }
+        }
+        view
+    }
+
+    /// Returns the separator and the right sibling of the newest split of
+    /// the page.
+    fn newest_split(&self) -> Option<(&K, PageId)> {
+        let mut page = self;
+        loop {
+            match &page.kind {
+                Kind::Split {
+                    separator, right, ..
+                } => return Some((separator, *right)),
+                Kind::Base { high, right, .. } => return high.as_ref().zip(*right),
+                _ => page = page.next().expect("a delta has a next record"),
+            }
+        }
+    }
+}
+
+impl<K: Ord + Clone, const B: usize> BwTreeSet<K, B> {
+    const MAX_KEYS: usize = 2 * B - 1;
+
+    pub fn new() -> Self {
+        Self::with_mapping_table_size(DEFAULT_MAPPING_TABLE_SIZE)
+    }
+
+    /// Creates a tree whose mapping table holds the given number of pages.
+    pub fn with_mapping_table_size(pages: usize) -> Self {
+        assert!(pages > 0, "the mapping table must hold the root");
+        let table: Box<[_]> = (0..pages)
+            .map(|_| AtomicPtr::new(ptr::null_mut()))
+            .collect();
+        table[0].store(
+            Box::into_raw(Page::base(0, Vec::new(), Vec::new(), None, None)),
+            Ordering::Release,
+        );
+
+        BwTreeSet {
+            table,
+            next_id: AtomicUsize::new(1),
+            root: AtomicUsize::new(0),
+            retired: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Returns the newest record of the page.
+    fn load(&self, id: PageId) -> (*mut Page<K>, &Page<K>) {
+        let head = self.table[id].load(Ordering::Acquire);
+        // SAFETY: Installed records are only freed when the tree is dropped,
+        // so they outlive every reference to the tree.
+        (head, unsafe { &*head })
+    }
+
+    /// Replaces the head of the chain of the page, if it is still the newest
+    /// record.
+    fn install(&self, id: PageId, head: *mut Page<K>, new: *mut Page<K>) -> bool {
+        self.table[id]
+            .compare_exchange(head, new, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    /// Puts the delta on top of the page, handing it back if the page
+    /// changed since its head was loaded.
+    fn prepend(
+        &self,
+        id: PageId,
+        head: *mut Page<K>,
+        mut delta: Box<Page<K>>,
+    ) -> std::result::Result<(), Box<Page<K>>> {
+        delta.link(head);
+        let delta = Box::into_raw(delta);
+        if self.install(id, head, delta) {
+            Ok(())
+        } else {
+            // SAFETY: The delta was never installed, so no one else saw it.
+            Err(unsafe { Box::from_raw(delta) })
+        }
+    }
+
+    /// Places a new page in the mapping table, and returns its id.
+    fn allocate(&self, page: Box<Page<K>>) -> Option<PageId> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let slot = self.table.get(id)?;
+        slot.store(Box::into_raw(page), Ordering::Release);
+        Some(id)
+    }
+
+    /// Frees a page which was allocated, but never linked into the tree.
+    fn abandon(&self, id: PageId) {
+        let page = self.table[id].swap(ptr::null_mut(), Ordering::AcqRel);
+        // SAFETY: The page was never reachable from the root.
+        drop(unsafe { Box::from_raw(page) });
+    }
+
+    /// Hands the records of a replaced chain over to the tree, to be freed
+    /// when it is dropped.
+    fn retire(&self, head: *mut Page<K>) {
+        let mut page = head;
+        while !page.is_null() {
+            // SAFETY: The chain was loaded from the mapping table.
+            let record = unsafe { &*page };
+            let next = record
+                .next()
+                .map_or(ptr::null_mut(), |next| ptr::from_ref(next).cast_mut());
+
+            let mut top = self.retired.load(Ordering::Relaxed);
+            loop {
+                record.retired.store(top, Ordering::Relaxed);
+                match self.retired.compare_exchange_weak(
+                    top,
+                    page,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => break,
+                    Err(current) => top = current,
+                }
+            }
+            page = next;
+        }
+    }
+
+    /// Returns the page at the given level whose keys include the given key,
+    /// along with the newest record of its chain.
+    fn find(&self, key: &K, level: usize) -> (PageId, *mut Page<K>, &Page<K>) {
+        let mut id = self.root.load(Ordering::Acquire);
+        loop {
+            let (head, page) = self.load(id);
+            match page.route(key) {
+                Route::Right(right) => id = right,
+                Route::Child(child) if page.level > level => id = child,
+                _ => return (id, head, page),
+            }
+        }
+    }
+
+    pub fn search(&self, key: &K) -> Result<&K> {
+        let (_, _, leaf) = self.find(key, 0);
+        match leaf.route(key) {
+            Route::Found(found) => Ok(found),
+            _ => Err(Error::KeyNotFound),
+        }
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.search(key).is_ok()
+    }
+
+    pub fn insert(&self, key: K) -> Result<()> {
+        let mut delta = Page::delta(Kind::Insert {
+            key,
+            next: ptr::null_mut(),
+        });
+        loop {
+            let Kind::Insert { key, .. } = &delta.kind else {
+                unreachable!();
+            };
+            let (id, head, leaf) = self.find(key, 0);
+            if let Route::Found(_) = leaf.route(key) {
+                return Err(Error::KeyAlreadyExists);
+            }
+            match self.prepend(id, head, delta) {
+                Ok(()) => {
+                    self.consolidate(id);
+                    return Ok(());
+                }
+                Err(rejected) => delta = rejected,
+            }
+        }
+    }
+
+    pub fn remove(&self, key: &K) -> Result<K> {
+        loop {
+            let (id, head, leaf) = self.find(key, 0);
+            let Route::Found(found) = leaf.route(key) else {
+                return Err(Error::KeyNotFound);
+            };
+            let delta = Page::delta(Kind::Delete {
+                key: found.clone(),
+                next: ptr::null_mut(),
+            });
+            if self.prepend(id, head, delta).is_ok() {
+                self.consolidate(id);
+                return Ok(found.clone());
+            }
+        }
+    }
+
+    /// Replaces a long chain of the page with a new base page, and splits
+    /// the page if it holds too many keys. Gives up if another thread
+    /// changes the page first.
+    fn consolidate(&self, id: PageId) {
+        let (head, page) = self.load(id);
+        if page.chain_len < MAX_CHAIN_LEN {
+            return;
+        }
+
+        let view = page.view();
+        let base = Page::base(
+            page.level,
+            view.keys.into_iter().cloned().collect(),
+            view.children,
+            view.high.cloned(),
+            view.right,
+        );
+        let base = Box::into_raw(base);
+        if !self.install(id, head, base) {
+            // SAFETY: The page was never installed, so no one else saw it.
+            drop(unsafe { Box::from_raw(base) });
+            return;
+        }
+        self.retire(head);
+        self.split(id, base);
+    }
+
+    /// Splits the freshly consolidated page in two, if it holds too many
+    /// keys, and posts the new page to the parent.
+    fn split(&self, id: PageId, head: *mut Page<K>) {
+        // SAFETY: The page was just installed by this thread.
+        let page = unsafe { &*head };
+        let Kind::Base {
+            keys,
+            children,
+            high,
+            right,
+        } = &page.kind
+        else {
+            unreachable!("the page was just consolidated");
+        };
+        if keys.len() <= Self::MAX_KEYS {
+            return;
+        }
+
+        let mid = keys.len() / 2;
+        let separator = keys[mid].clone();
+        let sibling = if page.level == 0 {
+            Page::base(0, keys[mid..].to_vec(), Vec::new(), high.clone(), *right)
+        } else {
+            Page::base(
+                page.level,
+                keys[mid + 1..].to_vec(),
+                children[mid + 1..].to_vec(),
+                high.clone(),
+                *right,
+            )
+        };
+        let Some(sibling) = self.allocate(sibling) else {
+            return;
+        };
+
+        let delta = Page::delta(Kind::Split {
+            separator: separator.clone(),
+            right: sibling,
+            next: ptr::null_mut(),
+        });
+        if self.prepend(id, head, delta).is_err() {
+            self.abandon(sibling);
+            return;
+        }
+        self.post(page.level, separator, sibling, high.clone());
+    }
+
+    /// Adds the new right sibling of a split page at the given level to the
+    /// parent of the page, growing the tree first if the page has none.
+    fn post(&self, level: usize, separator: K, sibling: PageId, high: Option<K>) {
+        let mut delta = Page::delta(Kind::IndexEntry {
+            low: separator,
+            high,
+            child: sibling,
+            next: ptr::null_mut(),
+        });
+        loop {
+            let (_, root) = self.load(self.root.load(Ordering::Acquire));
+            if root.level == level {
+                if !self.grow(level) {
+                    return;
+                }
+                continue;
+            }
+
+            let Kind::IndexEntry { low, .. } = &delta.kind else {
+                unreachable!();
+            };
+            let (id, head, parent) = self.find(low, level + 1);
+            if let Route::Child(child) = parent.route(low)
+                && child == sibling
+            {
+                return;
+            }
+            match self.prepend(id, head, delta) {
+                Ok(()) => {
+                    self.consolidate(id);
+                    return;
+                }
+                Err(rejected) => delta = rejected,
+            }
+        }
+    }
+
+    /// Puts a new root above the root at the given level and its newest
+    /// right sibling. Returns whether the tree grew, by this thread or
+    /// another one.
+    fn grow(&self, level: usize) -> bool {
+        let id = self.root.load(Ordering::Acquire);
+        let (_, root) = self.load(id);
+        if root.level > level {
+            return true;
+        }
+        let Some((separator, right)) = root.newest_split() else {
+            return false;
+        };
+
+        let page = Page::base(
+            root.level + 1,
+            vec![separator.clone()],
+            vec![id, right],
+            None,
+            None,
+        );
+        let Some(new_root) = self.allocate(page) else {
+            return false;
+        };
+        if self
+            .root
+            .compare_exchange(id, new_root, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            self.abandon(new_root);
+        }
+        true
+    }
+
+    /// Returns an iterator over the keys of the tree, in ascending order.
+    ///
+    /// The iterator walks the leaves from left to right, and sees every leaf
+    /// as of the moment it reaches it, so it is not a snapshot of the tree.
+    pub fn iter(&self) -> Iter<'_, K, B> {
+        let mut id = self.root.load(Ordering::Acquire);
+        loop {
+            let (_, page) = self.load(id);
+            if page.level == 0 {
+                break;
+            }
+            id = page.view().children[0];
+        }
+        Iter {
+            tree: self,
+            next: Some(id),
+            keys: Vec::new().into_iter(),
+        }
+    }
+}
+
+impl<K: Ord + Clone, const B: usize> Default for BwTreeSet<K, B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, const B: usize> Drop for BwTreeSet<K, B> {
+    fn drop(&mut self) {
+        for slot in self.table.iter() {
+            let mut page = slot.load(Ordering::Acquire);
+            while !page.is_null() {
+                // SAFETY: The tree is dropped, so no one else holds a record,
+                // and every installed record is freed exactly once.
+                let record = unsafe { Box::from_raw(page) };
+                page = record
+                    .next()
+                    .map_or(ptr::null_mut(), |next| ptr::from_ref(next).cast_mut());
+            }
+        }
+
+        let mut page = self.retired.load(Ordering::Acquire);
+        while !page.is_null() {
+            // SAFETY: Retired records are no longer reachable from the
+            // mapping table, and each was retired once.
+            let record = unsafe { Box::from_raw(page) };
+            page = record.retired.load(Ordering::Relaxed);
+        }
+    }
+}
+
+impl<K: Ord + Clone, const B: usize> BTreeSet for BwTreeSet<K, B> {
+    type Key = K;
+    const B: usize = B;
+
+    fn search(&self, key: &Self::Key) -> Result<&Self::Key> {
+        BwTreeSet::search(self, key)
+    }
+
+    fn insert(&mut self, key: Self::Key) -> Result<()> {
+        BwTreeSet::insert(self, key)
+    }
+
+    fn remove(&mut self, key: &Self::Key) -> Result<Self::Key> {
+        BwTreeSet::remove(self, key)
+    }
+}
+
+/// An iterator over the keys of a `BwTreeSet`, as returned by
+/// `BwTreeSet::iter`.
+pub struct Iter<'a, K, const B: usize> {
+    tree: &'a BwTreeSet<K, B>,
+    next: Option<PageId>,
+    keys: std::vec::IntoIter<&'a K>,
+}
+
+impl<'a, K: Ord + Clone, const B: usize> Iterator for Iter<'a, K, B> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        loop {
+            if let Some(key) = self.keys.next() {
+                return Some(key);
+            }
+            let (_, leaf) = self.tree.load(self.next?);
+            let view = leaf.view();
+            self.keys = view.keys.into_iter();
+            self.next = view.right;
+        }
+    }
+}
+
+impl<K: Ord + Clone, const B: usize> FusedIterator for Iter<'_, K, B> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_btree_impl;
+    use crate::workload::{Kind as WorkloadKind, Workload};
+    use std::thread;
+
+    test_btree_impl!(BwTreeSet);
+
+    #[test]
+    fn test_matches_reference() {
+        for kind in WorkloadKind::ALL {
+            let mut tree = BwTreeSet::<u64, 2>::new();
+            Workload::new(kind, 11)
+                .with_key_space(2000)
+                .check(&mut tree, 5000);
+
+            let keys: Vec<_> = tree.iter().collect();
+            assert!(keys.is_sorted_by(|a, b| a < b), "{kind:?}");
+            assert!(keys.iter().all(|key| tree.contains(key)), "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn test_full_mapping_table_stops_splits() {
+        let mut tree = BwTreeSet::<u32, 2>::with_mapping_table_size(3);
+        for key in (0..500).rev() {
+            BTreeSet::insert(&mut tree, key).unwrap();
+        }
+        assert!(tree.iter().copied().eq(0..500));
+    }
+
+    #[test]
+    fn test_concurrent_writers() {
+        let tree = BwTreeSet::<u64, 3>::new();
+
+        thread::scope(|scope| {
+            for thread in 0..8u64 {
+                let tree = &tree;
+                scope.spawn(move || {
+                    for key in (0..20_000).filter(|key| key % 8 == thread) {
+                        tree.insert(key).unwrap();
+                    }
+                    for key in (0..20_000).filter(|key| key % 8 == thread && key % 3 == 0) {
+                        assert_eq!(tree.remove(&key).unwrap(), key);
+                    }
+                });
+            }
+        });
+
+        let expected: Vec<u64> = (0..20_000).filter(|key| key % 3 != 0).collect();
+        assert!(tree.iter().copied().eq(expected));
+        assert!(!tree.contains(&3) && tree.contains(&4));
+    }
+}
@@ -0,0 +1,30 @@
+use core::cmp::Ordering;
+
+/// Decides the order of the keys in a tree, in place of their `Ord`
+/// implementation. This lets a tree order its keys case-insensitively, in
+/// reverse, or by any other total order, without wrapping every key in a
+/// newtype.
+///
+/// The comparator must be a total order, and must not change while the tree
+/// holds any keys.
+pub trait Comparator<K: ?Sized> {
+    fn compare(&self, a: &K, b: &K) -> Ordering;
+}
+
+/// Orders the keys by their `Ord` implementation. This is the default
+/// comparator of the trees.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OrdComparator;
+
+impl<K: Ord + ?Sized> Comparator<K> for OrdComparator {
+    fn compare(&self, a: &K, b: &K) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+/// Any function comparing two keys can be used as a comparator.
+impl<K: ?Sized, F: Fn(&K, &K) -> Ordering> Comparator<K> for F {
+    fn compare(&self, a: &K, b: &K) -> Ordering {
+        self(a, b)
+    }
+}
@@ -0,0 +1,471 @@
+use super::array::Array;
+use super::augment::{AugmentedBTreeSet, Count, Node, Tree};
+use std::iter::FusedIterator;
+use std::ops::RangeBounds;
+
+/// An order-statistic B-tree, where every node knows how many keys its
+/// subtree holds. This lets `select` find the key at a given rank, and `rank`
+/// count the keys below a given one, both in logarithmic time.
+///
+/// The K type parameter represents the key type, and B is the branching factor.
+pub type CountedBTreeSet<K, const B: usize = 6> = AugmentedBTreeSet<K, Count, B>;
+
+impl<K, const B: usize> AugmentedBTreeSet<K, Count, B> {
+    /// Returns the number of keys in the tree.
+    pub fn len(&self) -> usize {
+        self.summary()
+    }
+
+    /// Returns an iterator over the keys of the tree, in ascending order,
+    /// which knows how many keys are left and skips keys along the counts.
+    pub fn iter(&self) -> Iter<'_, K, B> {
+        let mut iter = Iter {
+            tree: self,
+            stack: Vec::new(),
+            remaining: self.len(),
+        };
+        if let Some(root) = &self.root {
+            iter.descend(root);
+        }
+        iter
+    }
+
+    /// Returns the key with the given rank, which is the number of keys less
+    /// than it, or `None` if the tree has no more keys than the rank.
+    pub fn select(&self, mut rank: usize) -> Option<&K> {
+        let mut node = self.root.as_deref()?;
+        if rank >= node.summary {
+            return None;
+        }
+
+        loop {
+            match node.find_rank(rank) {
+                Ok(idx) => return Some(&node.keys[idx]),
+                Err((child, within)) => (node, rank) = (&node.children[child], within),
+            }
+        }
+    }
+
+    /// Splits the tree in two at the given rank. The tree keeps its first
+    /// `rank` keys, and the rest are returned as a new tree. A rank past the
+    /// last key moves no keys.
+    ///
+    /// The counts lead the cut along a single path, and the fragments on
+    /// each side of the path are joined back together. The subtrees hanging
+    /// off the path are handed over whole, so the split takes logarithmic
+    /// time.
+    pub fn split_at_rank(&mut self, rank: usize) -> Self {
+        let Some(root) = self.root.take() else {
+            return Self::new();
+        };
+
+        let rank = rank.min(root.summary);
+        let height = root.height();
+        let ((left, _), (right, _)) = split_node(*root, height, rank);
+        self.root = left;
+        Self::with_root(right)
+    }
+}
+
+impl<K, const B: usize> Node<K, Count, B> {
+    /// Finds the key with the given rank in the subtree of the node. Returns
+    /// the index of the key if the node holds it, or else the index of the
+    /// child which does, along with the rank of the key within the child.
+    pub(super) fn find_rank(&self, mut rank: usize) -> Result<usize, (usize, usize)> {
+        if self.is_leaf() {
+            return Ok(rank);
+        }
+
+        for (idx, child) in self.children.iter().enumerate() {
+            if rank < child.summary {
+                return Err((idx, rank));
+            }
+            if rank == child.summary {
+                return Ok(idx);
+            }
+            rank -= child.summary + 1;
+        }
+        unreachable!("the rank is below the count of the subtree")
+    }
+}
+
+/// Splits the subtree rooted at the node, which is at the given height, into
+/// its first `rank` keys and the rest.
+fn split_node<K, const B: usize>(
+    node: Node<K, Count, B>,
+    height: usize,
+    mut rank: usize,
+) -> (Tree<K, Count, B>, Tree<K, Count, B>) {
+    let Node {
+        mut keys,
+        mut children,
+        ..
+    } = node;
+    if children.is_empty() {
+        let right = keys.split_off(rank);
+        return (
+            Node::fragment(keys, children, 0),
+            Node::fragment(right, Array::new(), 0),
+        );
+    }
+
+    // The rank falls into the child at `idx`, or right after its keys.
+    let mut idx = 0;
+    while rank > children[idx].summary {
+        rank -= children[idx].summary + 1;
+        idx += 1;
+    }
+
+    let mut right_keys = keys.split_off(idx);
+    let right_children = children.split_off(idx + 1);
+    let child = children.pop_back().unwrap();
+    let (child_left, child_right) = split_node(*child, height - 1, rank);
+
+    let left = match keys.pop_back() {
+        Some(separator) => {
+            let fragment = Node::fragment(keys, children, height);
+            Node::join(fragment, separator, child_left)
+        }
+        None => child_left,
+    };
+    let right = match right_keys.pop_front() {
+        Some(separator) => {
+            let fragment = Node::fragment(right_keys, right_children, height);
+            Node::join(child_right, separator, fragment)
+        }
+        None => child_right,
+    };
+    (left, right)
+}
+
+impl<K: Ord, const B: usize> AugmentedBTreeSet<K, Count, B> {
+    /// Returns the number of keys in the tree less than the given one, which
+    /// does not need to be in the tree itself.
+    pub fn rank(&self, key: &K) -> usize {
+        let mut rank = 0;
+        let mut current = self.root.as_deref();
+
+        while let Some(node) = current {
+            let result = node.keys.binary_search(key);
+            let (Ok(idx) | Err(idx)) = result;
+
+            let counts = node.children.iter().take(idx).map(|child| child.summary);
+            rank += idx + counts.sum::<usize>();
+            current = node.children.get(idx).map(|child| &**child);
+
+            if result.is_ok() {
+                return rank + current.map_or(0, |child| child.summary);
+            }
+        }
+
+        rank
+    }
+
+    /// Returns the number of keys within the range, without visiting them.
+    /// Like `range_aggregate`, it only descends along the edges of the range,
+    /// and takes logarithmic time.
+    pub fn range_count<R: RangeBounds<K>>(&self, range: R) -> usize {
+        self.range_aggregate(range)
+    }
+}
+
+impl<'a, K, const B: usize> IntoIterator for &'a AugmentedBTreeSet<K, Count, B> {
+    type Item = &'a K;
+    type IntoIter = Iter<'a, K, B>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the keys of a `CountedBTreeSet`, or the elements of a
+/// `BTreeList`, in order. The stack holds the nodes from the root down to the
+/// current one, each with the index of its next key.
+pub struct Iter<'a, K, const B: usize> {
+    tree: &'a AugmentedBTreeSet<K, Count, B>,
+    stack: Vec<(&'a Node<K, Count, B>, usize)>,
+    remaining: usize,
+}
+
+impl<'a, K, const B: usize> Iter<'a, K, B> {
+    /// Pushes the leftmost path of the subtree rooted at the node.
+    fn descend(&mut self, mut node: &'a Node<K, Count, B>) {
+        loop {
+            self.stack.push((node, 0));
+            match node.children.first() {
+                Some(child) => node = child,
+                None => return,
+            }
+        }
+    }
+}
+
+impl<'a, K, const B: usize> Iterator for Iter<'a, K, B> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        loop {
+            let (node, idx) = *self.stack.last()?;
+            if idx == node.keys.len() {
+                self.stack.pop();
+                continue;
+            }
+
+            self.stack.last_mut().unwrap().1 += 1;
+            if let Some(child) = node.children.get(idx + 1) {
+                self.descend(child);
+            }
+            self.remaining -= 1;
+            return Some(&node.keys[idx]);
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+
+    /// Skips the next `n` keys by descending from the root along the
+    /// counts, in logarithmic time, instead of stepping over them.
+    fn nth(&mut self, n: usize) -> Option<&'a K> {
+        if n >= self.remaining {
+            self.stack.clear();
+            self.remaining = 0;
+            return None;
+        }
+
+        let mut rank = self.tree.len() - self.remaining + n;
+        let mut node = self.tree.root.as_deref()?;
+        self.stack.clear();
+        loop {
+            match node.find_rank(rank) {
+                Ok(idx) => {
+                    self.stack.push((node, idx));
+                    break;
+                }
+                Err((child, within)) => {
+                    self.stack.push((node, child));
+                    (node, rank) = (&node.children[child], within);
+                }
+            }
+        }
+
+        self.remaining -= n;
+        self.next()
+    }
+}
+
+impl<K, const B: usize> ExactSizeIterator for Iter<'_, K, B> {}
+
+impl<K, const B: usize> FusedIterator for Iter<'_, K, B> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BTreeSet;
+    use crate::btree::{DifferentialTester, ReferenceBTreeSet};
+    use crate::test_btree_impl;
+
+    test_btree_impl!(CountedBTreeSet);
+
+    /// Checks that the count of every node matches its subtree, returning the
+    /// count of the node.
+    fn check_counts<K: Ord, const B: usize>(node: &Node<K, Count, B>) -> usize {
+        let count = node.keys.len()
+            + node
+                .children
+                .iter()
+                .map(|child| check_counts(child))
+                .sum::<usize>();
+        assert_eq!(node.summary, count);
+        count
+    }
+
+    #[test]
+    fn test_select_and_rank_agree_with_sorted_keys() {
+        let mut tester =
+            DifferentialTester::new(CountedBTreeSet::<usize, 2>::new(), ReferenceBTreeSet::new());
+
+        for i in 0..5000usize {
+            let key = (i * 7919) % 503;
+            let _ = match i % 3 {
+                0 => tester.remove(&key).map(|_| ()),
+                _ => tester.insert(key),
+            };
+
+            if i % 97 == 0 {
+                let (tree, reference) = (tester.first(), tester.second());
+                let keys: Vec<usize> = reference.iter().copied().collect();
+                if let Some(root) = &tree.root {
+                    check_counts(root);
+                }
+
+                assert_eq!(tree.len(), keys.len());
+                for (rank, key) in keys.iter().enumerate() {
+                    assert_eq!(tree.select(rank), Some(key));
+                    assert_eq!(tree.rank(key), rank);
+                    assert_eq!(tree.rank(&(key + 1)), keys.partition_point(|k| *k <= *key));
+                }
+                assert_eq!(tree.select(keys.len()), None);
+            }
+        }
+    }
+
+    #[test]
+    fn test_range_count_matches_std() {
+        let keys: std::collections::BTreeSet<usize> = (0..400).map(|i| i * 5 % 1009).collect();
+        let mut tree = CountedBTreeSet::<usize, 2>::new();
+        for &key in &keys {
+            tree.insert(key).unwrap();
+        }
+
+        for start in (0..1020).step_by(7) {
+            for end in (start..1020).step_by(13) {
+                assert_eq!(tree.range_count(start..end), keys.range(start..end).count());
+                assert_eq!(
+                    tree.range_count(start..=end),
+                    keys.range(start..=end).count()
+                );
+            }
+            assert_eq!(tree.range_count(start..), keys.range(start..).count());
+            assert_eq!(tree.range_count(..start), keys.range(..start).count());
+        }
+        assert_eq!(tree.range_count(..), keys.len());
+    }
+
+    /// Checks that the tree is balanced, and that no node but the root is
+    /// deficient, returning the height of the subtree.
+    fn check_shape<K: Ord, const B: usize>(node: &Node<K, Count, B>, is_root: bool) -> usize {
+        if !is_root {
+            assert!(node.keys.len() >= B - 1, "node is deficient");
+        }
+        assert!(node.keys.len() < 2 * B, "node is overflowed");
+
+        let heights: Vec<usize> = node
+            .children
+            .iter()
+            .map(|child| check_shape(child, false))
+            .collect();
+        assert!(
+            heights.windows(2).all(|pair| pair[0] == pair[1]),
+            "node is unbalanced"
+        );
+        heights.first().map_or(0, |height| height + 1)
+    }
+
+    fn check(tree: &CountedBTreeSet<usize, 2>, expected: impl Iterator<Item = usize>) {
+        let keys: Vec<usize> = (0..tree.len())
+            .map(|rank| *tree.select(rank).unwrap())
+            .collect();
+        assert!(keys.into_iter().eq(expected));
+        if let Some(root) = &tree.root {
+            check_counts(root);
+            check_shape(root, true);
+        }
+    }
+
+    #[test]
+    fn test_split_at_rank_keeps_both_halves_valid() {
+        for len in [0, 1, 2, 5, 17, 100, 1000] {
+            for rank in [0, 1, len / 3, len / 2, len - len.min(1), len, len + 1] {
+                let mut tree = CountedBTreeSet::<usize, 2>::new();
+                for i in 0..len {
+                    tree.insert(i * 7919 % len).unwrap();
+                }
+
+                let right = tree.split_at_rank(rank);
+                let rank = rank.min(len);
+                check(&tree, 0..rank);
+                check(&right, rank..len);
+
+                // Both halves stay fully usable.
+                tree.insert(len + 10).unwrap();
+                assert_eq!(tree.rank(&(len + 10)), rank);
+                if rank < len {
+                    right.search(&rank).unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_at_rank_of_a_grown_and_shrunk_tree() {
+        let mut tree = CountedBTreeSet::<usize, 2>::new();
+        for key in 0..2000 {
+            tree.insert(key).unwrap();
+        }
+        for key in (0..2000).filter(|key| key % 3 != 0) {
+            tree.remove(&key).unwrap();
+        }
+
+        let mut right = tree.split_at_rank(400);
+        let last = right.split_at_rank(100);
+        check(&tree, (0..400).map(|i| i * 3));
+        check(&right, (400..500).map(|i| i * 3));
+        check(&last, (500..667).map(|i| i * 3));
+    }
+
+    fn collect_nodes<K, const B: usize>(node: &Node<K, Count, B>, nodes: &mut Vec<usize>) {
+        nodes.push(node as *const _ as usize);
+        for child in node.children.iter() {
+            collect_nodes(child, nodes);
+        }
+    }
+
+    #[test]
+    fn test_split_at_rank_hands_subtrees_over_without_copying() {
+        let mut tree = CountedBTreeSet::<usize, 2>::new();
+        for key in 0..10_000 {
+            tree.insert(key).unwrap();
+        }
+        let height = tree.root.as_ref().unwrap().height();
+        let mut before = Vec::new();
+        collect_nodes(tree.root.as_ref().unwrap(), &mut before);
+        before.sort_unstable();
+
+        let right = tree.split_at_rank(5_000);
+        let mut after = Vec::new();
+        collect_nodes(tree.root.as_ref().unwrap(), &mut after);
+        collect_nodes(right.root.as_ref().unwrap(), &mut after);
+        let fresh = after
+            .iter()
+            .filter(|node| before.binary_search(node).is_err())
+            .count();
+
+        // Only the nodes along the cut are rebuilt.
+        assert!(fresh <= 4 * (height + 1), "{fresh} fresh nodes");
+        check(&tree, 0..5_000);
+        check(&right, 5_000..10_000);
+    }
+
+    #[test]
+    fn test_empty_tree_has_no_ranks() {
+        let tree = CountedBTreeSet::<usize>::new();
+        assert_eq!(tree.len(), 0);
+        assert_eq!(tree.select(0), None);
+        assert_eq!(tree.rank(&42), 0);
+        assert_eq!(tree.range_count(..), 0);
+    }
+
+    #[test]
+    fn test_iter_knows_its_length_and_skips_along_the_counts() {
+        let mut tree = CountedBTreeSet::<usize, 2>::new();
+        for key in (0..1000).rev() {
+            tree.insert(key * 2).unwrap();
+        }
+
+        let mut iter = tree.iter();
+        assert_eq!(iter.len(), 1000);
+        assert!(iter.by_ref().take(10).copied().eq((0..10).map(|i| i * 2)));
+        assert_eq!(iter.len(), 990);
+        assert_eq!(iter.nth(489), Some(&998));
+        assert_eq!(iter.len(), 500);
+        assert!(iter.copied().eq((500..1000).map(|i| i * 2)));
+
+        for skip in [0, 1, 499, 999, 1000, 5000] {
+            let mut iter = tree.iter();
+            assert_eq!(iter.nth(skip).copied(), (skip < 1000).then_some(skip * 2));
+            assert_eq!(iter.len(), 999usize.saturating_sub(skip));
+        }
+        assert_eq!(CountedBTreeSet::<usize>::new().iter().next(), None);
+    }
+}
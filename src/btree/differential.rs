@@ -0,0 +1,264 @@
+use super::ReferenceBTreeSet;
+use crate::{BTreeSet, Result};
+use std::fmt::Debug;
+use std::mem::discriminant;
+
+/// A `BTreeSet` which forwards every operation to two wrapped trees, and
+/// panics as soon as their results diverge. Errors are considered equal when
+/// they are of the same variant.
+///
+/// Wrapping a new implementation together with a trusted one turns any test
+/// or workload into a differential test. The results of the first tree are
+/// returned to the caller.
+pub struct DifferentialTester<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> DifferentialTester<A, B> {
+    pub fn new(first: A, second: B) -> Self {
+        DifferentialTester { first, second }
+    }
+
+    pub fn first(&self) -> &A {
+        &self.first
+    }
+
+    pub fn second(&self) -> &B {
+        &self.second
+    }
+
+    pub fn into_inner(self) -> (A, B) {
+        (self.first, self.second)
+    }
+}
+
+impl<K, A, B> BTreeSet for DifferentialTester<A, B>
+where
+    K: Ord + Clone + Debug,
+    A: BTreeSet<Key = K>,
+    B: BTreeSet<Key = K>,
+{
+    type Key = K;
+    const B: usize = A::B;
+
+    fn search(&self, key: &Self::Key) -> Result<&Self::Key> {
+        let first = self.first.search(key);
+        let second = self.second.search(key);
+        assert_agree("search", key, &first, &second);
+        first
+    }
+
+    fn insert(&mut self, key: Self::Key) -> Result<()> {
+        let first = self.first.insert(key.clone());
+        let second = self.second.insert(key.clone());
+        assert_agree("insert", &key, &first, &second);
+        first
+    }
+
+    fn remove(&mut self, key: &Self::Key) -> Result<Self::Key> {
+        let first = self.first.remove(key);
+        let second = self.second.remove(key);
+        assert_agree("remove", key, &first, &second);
+        first
+    }
+}
+
+/// A `BTreeSet` which mirrors every operation on the wrapped tree into a
+/// `ReferenceBTreeSet`, and panics as soon as their results diverge, like a
+/// `DifferentialTester` against the reference.
+///
+/// This lets an application run with a built-in oracle while tracking down a
+/// suspected bug of a tree. The shadow is only kept in debug builds, or with
+/// the `shadow` feature enabled. Otherwise, operations go straight to the
+/// tree, so the wrapper can stay in place in release builds.
+///
+/// The shadow starts out with a copy of the keys of the wrapped tree, so the
+/// tree does not need to be empty.
+pub struct ShadowChecked<T: BTreeSet> {
+    inner: T,
+    shadow: Option<ReferenceBTreeSet<T::Key>>,
+}
+
+impl<T> ShadowChecked<T>
+where
+    T: BTreeSet,
+    T::Key: Ord + Clone,
+    for<'a> &'a T: IntoIterator<Item = &'a T::Key>,
+{
+    pub fn new(inner: T) -> Self {
+        let checked = cfg!(any(debug_assertions, feature = "shadow"));
+        let shadow = checked.then(|| {
+            let mut shadow = ReferenceBTreeSet::new();
+            for key in &inner {
+                shadow
+                    .insert(key.clone())
+                    .expect("the keys of a tree are distinct");
+            }
+            shadow
+        });
+        ShadowChecked { inner, shadow }
+    }
+}
+
+impl<T: BTreeSet> ShadowChecked<T> {
+    /// Returns whether the operations are checked against the shadow.
+    pub fn is_checked(&self) -> bool {
+        self.shadow.is_some()
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T> BTreeSet for ShadowChecked<T>
+where
+    T: BTreeSet,
+    T::Key: Ord + Clone + Debug,
+{
+    type Key = T::Key;
+    const B: usize = T::B;
+
+    fn search(&self, key: &Self::Key) -> Result<&Self::Key> {
+        let result = self.inner.search(key);
+        if let Some(shadow) = &self.shadow {
+            assert_agree("search", key, &result, &shadow.search(key));
+        }
+        result
+    }
+
+    fn insert(&mut self, key: Self::Key) -> Result<()> {
+        let Some(shadow) = &mut self.shadow else {
+            return self.inner.insert(key);
+        };
+        let result = self.inner.insert(key.clone());
+        assert_agree("insert", &key, &result, &shadow.insert(key.clone()));
+        result
+    }
+
+    fn remove(&mut self, key: &Self::Key) -> Result<Self::Key> {
+        let result = self.inner.remove(key);
+        if let Some(shadow) = &mut self.shadow {
+            assert_agree("remove", key, &result, &shadow.remove(key));
+        }
+        result
+    }
+}
+
+fn assert_agree<K: Debug, T: PartialEq + Debug>(
+    operation: &str,
+    key: &K,
+    first: &Result<T>,
+    second: &Result<T>,
+) {
+    let agree = match (first, second) {
+        (Ok(first), Ok(second)) => first == second,
+        (Err(first), Err(second)) => discriminant(first) == discriminant(second),
+        _ => false,
+    };
+
+    assert!(
+        agree,
+        "trees diverged on {operation}({key:?}): first returned {first:?}, second returned {second:?}"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use crate::btree::{ReferenceBTreeSet, SimpleBTreeSet};
+
+    /// A broken tree, which forgets every key it is given.
+    struct ForgetfulBTreeSet;
+
+    impl BTreeSet for ForgetfulBTreeSet {
+        type Key = i32;
+        const B: usize = 6;
+
+        fn search(&self, _key: &Self::Key) -> Result<&Self::Key> {
+            Err(Error::KeyNotFound)
+        }
+
+        fn insert(&mut self, _key: Self::Key) -> Result<()> {
+            Ok(())
+        }
+
+        fn remove(&mut self, _key: &Self::Key) -> Result<Self::Key> {
+            Err(Error::KeyNotFound)
+        }
+    }
+
+    impl<'a> IntoIterator for &'a ForgetfulBTreeSet {
+        type Item = &'a i32;
+        type IntoIter = std::iter::Empty<&'a i32>;
+
+        fn into_iter(self) -> Self::IntoIter {
+            std::iter::empty()
+        }
+    }
+
+    #[test]
+    fn test_agreeing_trees_forward_results() {
+        let mut tester =
+            DifferentialTester::new(SimpleBTreeSet::<i32>::new(), ReferenceBTreeSet::new());
+
+        for key in 0..100 {
+            tester.insert(key).unwrap();
+        }
+        assert!(matches!(
+            tester.insert(5).unwrap_err(),
+            Error::KeyAlreadyExists
+        ));
+        assert_eq!(tester.search(&5).unwrap(), &5);
+        assert_eq!(tester.remove(&5).unwrap(), 5);
+        assert!(!tester.contains(&5));
+
+        let (first, second) = tester.into_inner();
+        assert!(first.iter().eq(second.iter()));
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, feature = "shadow"))]
+    #[should_panic(expected = "trees diverged on remove(1)")]
+    fn test_shadow_catches_a_diverging_tree() {
+        let mut tree = ShadowChecked::new(ForgetfulBTreeSet);
+        assert!(tree.is_checked());
+        tree.insert(1).unwrap();
+        assert!(tree.remove(&2).is_err());
+        let _ = tree.remove(&1);
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, feature = "shadow"))]
+    fn test_shadow_starts_with_the_keys_of_the_tree() {
+        let mut inner = SimpleBTreeSet::<i32>::new();
+        for key in 0..100 {
+            inner.insert(key).unwrap();
+        }
+
+        let mut tree = ShadowChecked::new(inner);
+        assert_eq!(tree.search(&42).unwrap(), &42);
+        assert_eq!(tree.remove(&42).unwrap(), 42);
+        assert!(tree.insert(7).is_err());
+        tree.insert(100).unwrap();
+        assert!(
+            tree.into_inner()
+                .iter()
+                .eq((0..42).chain(43..101).collect::<Vec<_>>().iter())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "trees diverged on search(1)")]
+    fn test_diverging_trees_panic() {
+        let mut tester = DifferentialTester::new(ReferenceBTreeSet::new(), ForgetfulBTreeSet);
+        tester.insert(1).unwrap();
+        tester.search(&1).unwrap();
+    }
+}
@@ -0,0 +1,1531 @@
+use crate::storage::{
+    Compression, FileHeader, FilePager, MemoryPager, NodePage, PageId, PageKey, Pager,
+    decode_free_list, decode_overflow, decode_page_spilled, encode_free_list, encode_overflow,
+    encode_page_spilling, free_list_capacity, max_cell_size, overflow_capacity,
+};
+use crate::{BTreeSet, Context, Error, Result};
+use std::borrow::Cow;
+use std::cell::{OnceCell, RefCell};
+use std::collections::{BTreeSet as SortedSet, HashMap};
+use std::mem;
+use std::ops::{Bound, RangeBounds};
+
+mod snapshot;
+mod transaction;
+
+use snapshot::Versions;
+pub use snapshot::{Reader, Snapshot};
+pub use transaction::{Savepoint, Transaction};
+
+/// A B-tree whose nodes are stored as pages through a `Pager`, usually in a
+/// file. The first page holds the file header, and every other page holds a
+/// single node.
+///
+/// Every mutation writes the pages it changed before returning, and commits
+/// them as a group, so that a `WalPager` can apply them atomically. Nodes are
+/// decoded at most once, and kept in memory afterwards, which lets searches
+/// hand out references to the keys.
+///
+/// The pages freed by deletions are listed in the file, and handed to the
+/// pager when the tree is opened, so their space is reused rather than the
+/// file growing.
+///
+/// Keys are either `FixedSizeKey`s, or byte strings of any length, which are
+/// stored with their lengths. The prefix the byte strings of a node share is
+/// stored only once, and the part of a byte string beyond what a node has
+/// room for per key is stored in overflow pages, which belong to the node,
+/// and are written again whenever it is.
+///
+/// The K type parameter represents the key type, B is the branching factor,
+/// and P is the pager the pages are stored through.
+pub struct DiskBTreeSet<K, const B: usize = 32, P = FilePager> {
+    pager: P,
+    root: Option<PageId>,
+    nodes: Vec<OnceCell<NodePage<K>>>,
+    compression: Compression,
+    overlay: Option<Overlay<K>>,
+    versions: Versions,
+    free: FreeList,
+    /// The overflow pages of the keys of the node pages which were read or
+    /// written, if they have any.
+    overflow: RefCell<HashMap<PageId, Vec<PageId>>>,
+}
+
+/// The pages of the file which are free, and where they are listed.
+#[derive(Default)]
+struct FreeList {
+    pages: SortedSet<PageId>,
+    /// The first page of the list stored in the file.
+    head: Option<PageId>,
+    /// Whether the pages changed since the list was last stored.
+    dirty: bool,
+}
+
+/// Writes the list of the given free pages, in ascending order, into the
+/// free pages with the highest ids, which are the last ones a pager reuses.
+/// Returns the first page of the list.
+pub(crate) fn write_free_list(pager: &mut impl Pager, ids: &[PageId]) -> Result<Option<PageId>> {
+    let capacity = free_list_capacity(pager.page_size());
+    let pages = ids.len().div_ceil(capacity);
+    let list = &ids[ids.len() - pages..];
+
+    let mut page = vec![0; pager.page_size()];
+    let mut next = None;
+    for (chunk, &id) in ids.chunks(capacity).zip(list).rev() {
+        encode_free_list(chunk, next, &mut page)?;
+        pager.write_page(id, &page)?;
+        next = Some(id);
+    }
+    Ok(next)
+}
+
+/// The number of pages of a file, and how many of them are free.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileStats {
+    /// The number of pages of the file, including its header.
+    pub total_pages: u64,
+    /// The number of pages which are free, and reused by later allocations.
+    pub free_pages: u64,
+}
+
+impl FileStats {
+    /// Returns the number of pages which are not free.
+    pub fn used_pages(&self) -> u64 {
+        self.total_pages - self.free_pages
+    }
+}
+
+/// The number of bytes of the keys of a tree, and how many of them its pages
+/// store, once the prefix shared by the keys of every node is stored only
+/// once.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PrefixStats {
+    pub nodes: u64,
+    pub key_bytes: u64,
+    pub stored_bytes: u64,
+}
+
+impl PrefixStats {
+    /// Returns the number of bytes the shared prefixes save.
+    pub fn saved_bytes(&self) -> u64 {
+        self.key_bytes - self.stored_bytes
+    }
+
+    /// Returns the fraction of the bytes of the keys which are stored, which
+    /// is 1 if the keys of the nodes share no prefixes.
+    pub fn ratio(&self) -> f64 {
+        if self.key_bytes == 0 {
+            return 1.0;
+        }
+        self.stored_bytes as f64 / self.key_bytes as f64
+    }
+}
+
+/// The pages changed by an open transaction, which are kept apart from the
+/// committed ones until the transaction commits.
+struct Overlay<K> {
+    /// The nodes written by the transaction. A node taken with `load_owned`
+    /// leaves `None` behind, until it is stored or restored.
+    dirty: HashMap<PageId, Option<NodePage<K>>>,
+    /// The pages allocated by the transaction, which are given back to the
+    /// pager if it rolls back.
+    allocated: Vec<PageId>,
+    /// The pages released by the transaction, which are only freed when it
+    /// commits.
+    released: Vec<PageId>,
+}
+
+const HEADER_PAGE: PageId = 0;
+
+impl<K: PageKey, const B: usize> DiskBTreeSet<K, B, MemoryPager> {
+    /// Creates an empty tree, whose pages are kept in memory.
+    pub fn new() -> Self {
+        Self::open(MemoryPager::default()).unwrap()
+    }
+}
+
+impl<K: PageKey, const B: usize> Default for DiskBTreeSet<K, B, MemoryPager> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: PageKey, const B: usize, P: Pager> DiskBTreeSet<K, B, P> {
+    const MIN_KEYS: usize = B - 1;
+    const MAX_KEYS: usize = 2 * B - 1;
+
+    /// The size of the keys stored in the header, which is 0 for byte strings.
+    const KEY_SIZE: u32 = match K::SIZE {
+        Some(size) => size as u32,
+        None => 0,
+    };
+
+    /// Opens the tree stored through the given pager, or creates an empty
+    /// one if the pager holds no pages yet. Opening an existing tree does not
+    /// write anything, so read-only pagers can be used for searching.
+    pub fn open(pager: P) -> Result<Self> {
+        Self::open_pager(pager).context("DiskBTreeSet::open")
+    }
+
+    fn open_pager(mut pager: P) -> Result<Self> {
+        let page_size = pager.page_size();
+        let needed = NodePage::<K>::intermediate_size(Self::MAX_KEYS);
+        if needed > page_size {
+            return Err(Error::PageOverflow { needed, page_size });
+        }
+
+        if pager.page_count() == 0 {
+            let id = pager.allocate()?;
+            debug_assert_eq!(id, HEADER_PAGE);
+
+            let mut tree = DiskBTreeSet {
+                pager,
+                root: None,
+                nodes: Vec::new(),
+                compression: Compression::None,
+                overlay: None,
+                versions: Versions::default(),
+                free: FreeList::default(),
+                overflow: RefCell::default(),
+            };
+            tree.write_header()?;
+            tree.pager.commit()?;
+            return Ok(tree);
+        }
+
+        let header = FileHeader::decode(&pager.view_page(HEADER_PAGE)?)?;
+
+        if header.page_size as usize != page_size || header.key_size != Self::KEY_SIZE {
+            return Err(Error::CorruptPage {
+                reason: format!(
+                    "file has {} byte pages and {} byte keys, expected {} and {}",
+                    header.page_size,
+                    header.key_size,
+                    page_size,
+                    Self::KEY_SIZE
+                ),
+            });
+        }
+
+        // The pages of the list are free too, and list themselves.
+        let mut free = FreeList {
+            head: header.free_list,
+            ..FreeList::default()
+        };
+        let mut next = header.free_list;
+        while let Some(id) = next {
+            let (ids, following) = decode_free_list(&pager.view_page(id)?)?;
+            if let Some(&id) = ids.iter().find(|&&id| id >= pager.page_count()) {
+                return Err(Error::CorruptPage {
+                    reason: format!("free page {id} is out of bounds"),
+                });
+            }
+            free.pages.extend(ids);
+            next = following;
+        }
+
+        // The pages are handed over in descending order, so that the pager
+        // reuses the ones nearest the start of the file first.
+        for &id in free.pages.iter().rev() {
+            pager.free(id)?;
+        }
+
+        let nodes = (0..pager.page_count()).map(|_| OnceCell::new()).collect();
+        Ok(DiskBTreeSet {
+            pager,
+            root: header.root,
+            nodes,
+            compression: Compression::None,
+            overlay: None,
+            versions: Versions::default(),
+            free,
+            overflow: RefCell::default(),
+        })
+    }
+
+    /// Writes the header, and makes sure every page reached the backing store.
+    pub fn sync(&mut self) -> Result<()> {
+        self.write_free_list()?;
+        self.write_header()?;
+        self.pager.commit()?;
+        self.pager.sync()
+    }
+
+    /// Returns the number of pages of the file, and how many of them are
+    /// free.
+    pub fn file_stats(&self) -> FileStats {
+        FileStats {
+            total_pages: self.pager.page_count(),
+            free_pages: self.free.pages.len() as u64,
+        }
+    }
+
+    /// Returns how much of the keys the prefixes shared by the keys of every
+    /// node save, reading every node of the tree.
+    pub fn prefix_stats(&self) -> Result<PrefixStats> {
+        self.prefix_stats_at(self.root)
+    }
+
+    /// Returns the prefix stats of the tree with the given root.
+    pub(crate) fn prefix_stats_at(&self, root: Option<PageId>) -> Result<PrefixStats> {
+        let cell_size = max_cell_size(self.pager.page_size(), Self::MAX_KEYS);
+        let mut stats = PrefixStats::default();
+        let mut pending: Vec<_> = root.into_iter().collect();
+        while let Some(id) = pending.pop() {
+            let node = self.load(id)?;
+            let key_bytes: usize = node.keys.iter().map(PageKey::encoded_len).sum();
+            let shared = node.keys.len().saturating_sub(1) * node.shared_prefix_len(cell_size);
+
+            stats.nodes += 1;
+            stats.key_bytes += key_bytes as u64;
+            stats.stored_bytes += (key_bytes - shared) as u64;
+            pending.extend(&node.children);
+        }
+        Ok(stats)
+    }
+
+    /// Sets how the nodes are compressed when they are written. The pages
+    /// already written are left as they are, and stay readable.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// Returns the pager the pages are stored through.
+    pub fn pager(&self) -> &P {
+        &self.pager
+    }
+
+    pub(crate) fn pager_mut(&mut self) -> &mut P {
+        &mut self.pager
+    }
+
+    /// Consumes the tree, returning its pager.
+    pub fn into_pager(self) -> P {
+        self.pager
+    }
+
+    /// Returns the root of the tree stored in the header.
+    pub(crate) fn root(&self) -> Option<PageId> {
+        self.root
+    }
+
+    /// Stores the root of the tree in the header, and commits every write
+    /// made since the last commit.
+    pub(crate) fn commit_with_root(&mut self, root: Option<PageId>) -> Result<()> {
+        for id in self.versions.commit() {
+            self.free(id)?;
+        }
+
+        let head = self.free.head;
+        self.write_free_list()?;
+        if root != self.root || head != self.free.head {
+            self.root = root;
+            self.write_header()?;
+        }
+        self.pager.commit()
+    }
+
+    /// Stores the list of free pages, if it changed since it was last stored.
+    fn write_free_list(&mut self) -> Result<()> {
+        if !mem::take(&mut self.free.dirty) {
+            return Ok(());
+        }
+
+        let ids: Vec<_> = self.free.pages.iter().copied().collect();
+        self.free.head = write_free_list(&mut self.pager, &ids)?;
+        Ok(())
+    }
+
+    fn write_header(&mut self) -> Result<()> {
+        let header = FileHeader {
+            page_size: self.pager.page_size() as u32,
+            key_size: Self::KEY_SIZE,
+            root: self.root,
+            page_count: self.pager.page_count(),
+            free_list: self.free.head,
+        };
+        let mut page = vec![0; self.pager.page_size()];
+        header.encode(&mut page);
+        self.pager.write_page(HEADER_PAGE, &page)
+    }
+
+    /// Returns the node stored in the given page, reading it if needed.
+    pub(crate) fn load(&self, id: PageId) -> Result<&NodePage<K>> {
+        if let Some(overlay) = &self.overlay
+            && let Some(node) = overlay.dirty.get(&id)
+        {
+            return Ok(node.as_ref().expect("a taken node is loaded again"));
+        }
+
+        let cell = self.nodes.get(id as usize).ok_or(Error::CorruptPage {
+            reason: format!("page {id} is out of bounds"),
+        })?;
+
+        if let Some(node) = cell.get() {
+            return Ok(node);
+        }
+
+        let node = self.read(id)?;
+        Ok(cell.get_or_init(|| node))
+    }
+
+    /// Decodes the node stored in the given page, along with the keys which
+    /// overflow from it.
+    fn read(&self, id: PageId) -> Result<NodePage<K>> {
+        let (node, chain) = read_node(id, self.pager.page_count(), |id| self.pager.view_page(id))?;
+        if !chain.is_empty() {
+            self.overflow.borrow_mut().insert(id, chain);
+        }
+        Ok(node)
+    }
+
+    /// Decodes the node stored in the given page, without keeping it, and
+    /// returns it along with the overflow pages of its keys.
+    pub(crate) fn read_with_overflow(&self, id: PageId) -> Result<(NodePage<K>, Vec<PageId>)> {
+        read_node(id, self.pager.page_count(), |id| self.pager.view_page(id))
+    }
+
+    /// Returns an owned copy of the node in the given page, to be modified
+    /// and stored again.
+    fn load_owned(&mut self, id: PageId) -> Result<NodePage<K>> {
+        if let Some(overlay) = &mut self.overlay {
+            if let Some(node) = overlay.dirty.get_mut(&id) {
+                return Ok(node.take().expect("a taken node is loaded again"));
+            }
+            // The committed node stays in the cache, in case the transaction
+            // rolls back, so the transaction works on a copy of its page.
+            return self.read(id);
+        }
+
+        self.load(id)?;
+        Ok(self.nodes[id as usize].take().unwrap())
+    }
+
+    /// Lets the pager read ahead the pages of the nodes which are not in
+    /// memory yet.
+    fn prefetch(&self, ids: &[PageId]) {
+        let missing: Vec<_> = ids
+            .iter()
+            .copied()
+            .filter(|&id| {
+                let cached = self.nodes.get(id as usize).and_then(OnceCell::get);
+                let dirty = self
+                    .overlay
+                    .as_ref()
+                    .is_some_and(|overlay| overlay.dirty.contains_key(&id));
+                cached.is_none() && !dirty
+            })
+            .collect();
+        if !missing.is_empty() {
+            self.pager.prefetch(&missing);
+        }
+    }
+
+    /// Makes sure every page of the pager has a slot in the node cache.
+    fn make_room(&mut self) {
+        let count = self.pager.page_count() as usize;
+        if self.nodes.len() < count {
+            self.nodes.resize_with(count, OnceCell::new);
+        }
+    }
+
+    /// Writes the node into the given page, and keeps it in memory. Within a
+    /// transaction, the node is only kept in the overlay.
+    ///
+    /// A page which a snapshot may still read is left as it is, and the node
+    /// is written into a new page instead. Returns the page the node is in.
+    fn store(&mut self, id: PageId, node: NodePage<K>) -> Result<PageId> {
+        if self.versions.is_visible(id) {
+            self.release(id)?;
+            return self.allocate(node);
+        }
+
+        match &mut self.overlay {
+            Some(overlay) => {
+                overlay.dirty.insert(id, Some(node));
+            }
+            None => self.write(id, node)?,
+        }
+        Ok(id)
+    }
+
+    fn write(&mut self, id: PageId, node: NodePage<K>) -> Result<()> {
+        // The keys which overflowed from the page before are stored anew.
+        for page in self.overflow.get_mut().remove(&id).unwrap_or_default() {
+            self.release(page)?;
+        }
+
+        let page_size = self.pager.page_size();
+        let (compression, cell_size) = (self.compression, max_cell_size(page_size, Self::MAX_KEYS));
+        let mut chain = Vec::new();
+        let mut page = vec![0; page_size];
+        encode_page_spilling(&node, &mut page, compression, cell_size, |bytes| {
+            self.write_overflow(bytes, &mut chain)
+        })?;
+        self.pager.write_page(id, &page)?;
+        if !chain.is_empty() {
+            self.overflow.get_mut().insert(id, chain);
+        }
+
+        self.make_room();
+        self.nodes[id as usize] = OnceCell::from(node);
+        Ok(())
+    }
+
+    /// Puts back a node taken with `load_owned` which was not modified.
+    fn restore(&mut self, id: PageId, node: NodePage<K>) {
+        match &mut self.overlay {
+            // A committed node was copied, so only a dirty one is put back.
+            Some(overlay) => {
+                if let Some(slot) = overlay.dirty.get_mut(&id) {
+                    *slot = Some(node);
+                }
+            }
+            None => self.nodes[id as usize] = OnceCell::from(node),
+        }
+    }
+
+    fn allocate(&mut self, node: NodePage<K>) -> Result<PageId> {
+        let id = self.allocate_page()?;
+        self.store(id, node)
+    }
+
+    fn allocate_page(&mut self) -> Result<PageId> {
+        let id = self.pager.allocate()?;
+        if self.free.pages.remove(&id) {
+            self.free.dirty = true;
+        }
+        self.versions.allocated(id);
+        if let Some(overlay) = &mut self.overlay {
+            overlay.allocated.push(id);
+        }
+        Ok(id)
+    }
+
+    /// Stores the bytes in a chain of new overflow pages, which are appended
+    /// to `chain`, and returns the first one.
+    fn write_overflow(&mut self, bytes: &[u8], chain: &mut Vec<PageId>) -> Result<PageId> {
+        let page_size = self.pager.page_size();
+        let parts: Vec<_> = bytes.chunks(overflow_capacity(page_size)).collect();
+        let ids = (0..parts.len())
+            .map(|_| self.allocate_page())
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut page = vec![0; page_size];
+        let mut next = None;
+        for (part, &id) in parts.iter().zip(&ids).rev() {
+            encode_overflow(part, next, &mut page)?;
+            self.pager.write_page(id, &page)?;
+            next = Some(id);
+        }
+        chain.extend(&ids);
+        Ok(ids[0])
+    }
+
+    /// Gives the page back once the tree no longer refers to it. A page which
+    /// a snapshot may still read is only freed once the snapshot is dropped.
+    fn release(&mut self, id: PageId) -> Result<()> {
+        match &mut self.overlay {
+            Some(overlay) => {
+                overlay.dirty.remove(&id);
+                overlay.released.push(id);
+                Ok(())
+            }
+            None => {
+                let chain = self.overflow.get_mut().remove(&id).unwrap_or_default();
+                if self.versions.is_visible(id) {
+                    self.versions.retire(id);
+                } else {
+                    self.free(id)?;
+                }
+                for page in chain {
+                    self.release(page)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Frees the page. The overflow pages of a node page are not freed along
+    /// with it.
+    fn free(&mut self, id: PageId) -> Result<()> {
+        // A page allocated by a transaction may have no slot yet.
+        if let Some(cell) = self.nodes.get_mut(id as usize) {
+            *cell = OnceCell::new();
+        }
+        self.overflow.get_mut().remove(&id);
+        self.pager.free(id)?;
+        self.free.pages.insert(id);
+        self.free.dirty = true;
+        Ok(())
+    }
+}
+
+impl<K: PageKey + Ord, const B: usize, P: Pager> DiskBTreeSet<K, B, P> {
+    /// Inserts the given key, replacing the key equal to it if there is one.
+    /// Returns the replaced key, or `None` if the key was not in the tree.
+    ///
+    /// A replaced key is overwritten in its page, so the replacement is
+    /// committed as a single write, like any other mutation.
+    pub fn replace(&mut self, key: K) -> Result<Option<K>> {
+        let mut root = self.root;
+        let replaced = self.replace_at(&mut root, key)?;
+        self.commit_with_root(root)?;
+        Ok(replaced)
+    }
+
+    /// Returns the keys within the range, in ascending order.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Result<Vec<&K>> {
+        self.range_at(self.root, range)
+    }
+
+    /// Fills the empty tree with the keys, which must be in strictly
+    /// ascending order, writing every node once.
+    ///
+    /// Fails with `Error::KeyAlreadyExists` if the tree is not empty, and with
+    /// `Error::KeyOutOfOrder` if the keys are not ascending, before anything
+    /// is written.
+    pub fn bulk_load(&mut self, keys: impl IntoIterator<Item = K>) -> Result<()> {
+        let mut root = self.root;
+        self.bulk_load_at(&mut root, keys.into_iter().collect())?;
+        self.commit_with_root(root)
+    }
+
+    // The methods below work on the tree rooted at the given page instead of
+    // the root stored in the header, so that a file can hold several trees
+    // sharing the pager and the node cache. They update the root in place,
+    // and leave writing it down, and committing, to the caller.
+
+    pub(crate) fn search_at(&self, root: Option<PageId>, key: &K) -> Result<&K> {
+        let mut id = root.ok_or(Error::KeyNotFound)?;
+        loop {
+            let node = self.load(id)?;
+            match node.keys.binary_search(key) {
+                Ok(idx) => return Ok(&node.keys[idx]),
+                Err(_) if node.is_leaf() => return Err(Error::KeyNotFound),
+                Err(idx) => id = node.children[idx],
+            }
+        }
+    }
+
+    pub(crate) fn insert_at(&mut self, root: &mut Option<PageId>, key: K) -> Result<()> {
+        let Some(id) = *root else {
+            *root = Some(self.allocate(NodePage {
+                keys: vec![key],
+                children: Vec::new(),
+            })?);
+            return Ok(());
+        };
+
+        let (id, split) = self.insert_into(id, key)?;
+        *root = Some(id);
+        if let Some((hoist, sibling)) = split {
+            debug_event!(page = id, sibling, "root grew");
+            *root = Some(self.allocate(NodePage {
+                keys: vec![hoist],
+                children: vec![id, sibling],
+            })?);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn remove_at(&mut self, root: &mut Option<PageId>, key: &K) -> Result<K> {
+        let id = root.ok_or(Error::KeyNotFound)?;
+        let (id, removed) = self.remove_from(id, key)?;
+        let removed = removed.ok_or(Error::KeyNotFound)?;
+        *root = Some(id);
+
+        let node = self.load(id)?;
+        if node.keys.is_empty() {
+            *root = node.children.first().copied();
+            debug_event!(page = id, root = ?*root, "root shrank");
+            self.release(id)?;
+        }
+        Ok(removed)
+    }
+
+    pub(crate) fn replace_at(&mut self, root: &mut Option<PageId>, key: K) -> Result<Option<K>> {
+        let mut path = Vec::new();
+        let mut next = *root;
+        while let Some(id) = next {
+            let node = self.load(id)?;
+            match node.keys.binary_search(&key) {
+                Ok(idx) => {
+                    let mut node = self.load_owned(id)?;
+                    let replaced = mem::replace(&mut node.keys[idx], key);
+                    let id = self.store(id, node)?;
+                    *root = Some(self.update_path(path, id)?);
+                    return Ok(Some(replaced));
+                }
+                Err(_) if node.is_leaf() => break,
+                Err(idx) => {
+                    path.push((id, idx));
+                    next = Some(node.children[idx]);
+                }
+            }
+        }
+
+        self.insert_at(root, key)?;
+        Ok(None)
+    }
+
+    /// Builds the tree one level at a time, from the leaves up. The keys are
+    /// spread evenly over as few nodes as hold them, which leaves every node
+    /// but the root at least half full.
+    pub(crate) fn bulk_load_at(
+        &mut self,
+        root: &mut Option<PageId>,
+        mut keys: Vec<K>,
+    ) -> Result<()> {
+        if root.is_some() {
+            return Err(Error::KeyAlreadyExists);
+        }
+        if keys.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(Error::KeyOutOfOrder);
+        }
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        let mut children: Vec<PageId> = Vec::new();
+        loop {
+            // Every node but the last is followed by a separator, which moves
+            // up to the next level.
+            let nodes = (keys.len() + 1).div_ceil(Self::MAX_KEYS + 1);
+            let kept = keys.len() - (nodes - 1);
+            let leaf = children.is_empty();
+
+            let mut keys_left = keys.into_iter();
+            let mut children_left = children.into_iter();
+            let mut separators = Vec::with_capacity(nodes - 1);
+            let mut ids = Vec::with_capacity(nodes);
+            for i in 0..nodes {
+                let count = kept / nodes + usize::from(i < kept % nodes);
+                let node = NodePage {
+                    keys: keys_left.by_ref().take(count).collect(),
+                    children: match leaf {
+                        true => Vec::new(),
+                        false => children_left.by_ref().take(count + 1).collect(),
+                    },
+                };
+                ids.push(self.allocate(node)?);
+                separators.extend(keys_left.next());
+            }
+
+            if nodes == 1 {
+                *root = Some(ids[0]);
+                return Ok(());
+            }
+            keys = separators;
+            children = ids;
+        }
+    }
+
+    /// Points the nodes along the path, given as the pages and the indexes of
+    /// the children taken, at the child which was stored into the given
+    /// page. Returns the page of the first node of the path.
+    fn update_path(&mut self, path: Vec<(PageId, usize)>, mut child: PageId) -> Result<PageId> {
+        for (id, idx) in path.into_iter().rev() {
+            let mut node = self.load_owned(id)?;
+            if node.children[idx] == child {
+                self.restore(id, node);
+                child = id;
+            } else {
+                node.children[idx] = child;
+                child = self.store(id, node)?;
+            }
+        }
+        Ok(child)
+    }
+
+    pub(crate) fn range_at<R: RangeBounds<K>>(
+        &self,
+        root: Option<PageId>,
+        range: R,
+    ) -> Result<Vec<&K>> {
+        let mut keys = Vec::new();
+        if let Some(root) = root {
+            self.collect_range(root, &range, &mut keys)?;
+        }
+        Ok(keys)
+    }
+
+    /// Frees every page of the tree, leaving it empty.
+    pub(crate) fn clear_at(&mut self, root: &mut Option<PageId>) -> Result<()> {
+        if let Some(id) = root.take() {
+            self.release_subtree(id)?;
+        }
+        Ok(())
+    }
+
+    fn release_subtree(&mut self, id: PageId) -> Result<()> {
+        let children = self.load(id)?.children.clone();
+        for child in children {
+            self.release_subtree(child)?;
+        }
+        self.release(id)
+    }
+
+    /// Appends the keys of the subtree within the range to `keys`, skipping
+    /// the children which lie entirely outside of it.
+    fn collect_range<'a>(
+        &'a self,
+        id: PageId,
+        range: &impl RangeBounds<K>,
+        keys: &mut Vec<&'a K>,
+    ) -> Result<()> {
+        let node = self.load(id)?;
+        let (start, end) = range_bounds(&node.keys, range);
+
+        if !node.is_leaf() {
+            self.prefetch(&node.children[start..=end.max(start)]);
+        }
+        for idx in start..=end.max(start) {
+            if !node.is_leaf() {
+                self.collect_range(node.children[idx], range, keys)?;
+            }
+            if idx < end {
+                keys.push(&node.keys[idx]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Inserts the key into the subtree, returning the page the root of the
+    /// subtree is in afterwards, and the hoisted key and the new sibling when
+    /// the node had to be split.
+    fn insert_into(&mut self, id: PageId, key: K) -> Result<(PageId, Option<(K, PageId)>)> {
+        let mut node = self.load_owned(id)?;
+        let idx = match node.keys.binary_search(&key) {
+            Ok(_) => {
+                self.restore(id, node);
+                return Err(Error::KeyAlreadyExists);
+            }
+            Err(idx) => idx,
+        };
+
+        if node.is_leaf() {
+            node.keys.insert(idx, key);
+        } else {
+            let child = node.children[idx];
+            self.restore(id, node);
+            let (moved, split) = self.insert_into(child, key)?;
+            node = self.load_owned(id)?;
+
+            match split {
+                Some((hoist, sibling)) => {
+                    node.keys.insert(idx, hoist);
+                    node.children.insert(idx + 1, sibling);
+                }
+                None if moved == child => {
+                    self.restore(id, node);
+                    return Ok((id, None));
+                }
+                None => {}
+            }
+            node.children[idx] = moved;
+        }
+
+        if node.keys.len() <= Self::MAX_KEYS {
+            return Ok((self.store(id, node)?, None));
+        }
+
+        let keys = node.keys.split_off(B);
+        let hoist = node.keys.pop().unwrap();
+        let children = match node.is_leaf() {
+            true => Vec::new(),
+            false => node.children.split_off(B),
+        };
+
+        let id = self.store(id, node)?;
+        let sibling = self.allocate(NodePage { keys, children })?;
+        debug_event!(page = id, sibling, "node split");
+        Ok((id, Some((hoist, sibling))))
+    }
+
+    /// Removes the key from the subtree, leaving the root of the subtree
+    /// possibly deficient, but all of its descendants valid. Returns the page
+    /// the root of the subtree is in afterwards.
+    fn remove_from(&mut self, id: PageId, key: &K) -> Result<(PageId, Option<K>)> {
+        let mut node = self.load_owned(id)?;
+        let result = node.keys.binary_search(key);
+
+        let (removed, idx) = match result {
+            Ok(idx) if node.is_leaf() => {
+                let removed = node.keys.remove(idx);
+                return Ok((self.store(id, node)?, Some(removed)));
+            }
+            Err(_) if node.is_leaf() => {
+                self.restore(id, node);
+                return Ok((id, None));
+            }
+            Ok(idx) => {
+                let child = node.children[idx];
+                self.restore(id, node);
+                let (moved, predecessor) = self.remove_last(child)?;
+                node = self.load_owned(id)?;
+                node.children[idx] = moved;
+                (mem::replace(&mut node.keys[idx], predecessor), idx)
+            }
+            Err(idx) => {
+                let child = node.children[idx];
+                self.restore(id, node);
+                let (moved, Some(removed)) = self.remove_from(child, key)? else {
+                    return Ok((id, None));
+                };
+                node = self.load_owned(id)?;
+                node.children[idx] = moved;
+                (removed, idx)
+            }
+        };
+
+        self.fix_deficient_child(&mut node, idx)?;
+        Ok((self.store(id, node)?, Some(removed)))
+    }
+
+    /// Removes the greatest key of the subtree, returning the page the root
+    /// of the subtree is in afterwards, and the key.
+    fn remove_last(&mut self, id: PageId) -> Result<(PageId, K)> {
+        let mut node = self.load_owned(id)?;
+        if node.is_leaf() {
+            let key = node.keys.pop().unwrap();
+            return Ok((self.store(id, node)?, key));
+        }
+
+        let idx = node.children.len() - 1;
+        let child = node.children[idx];
+        self.restore(id, node);
+        let (moved, key) = self.remove_last(child)?;
+
+        let mut node = self.load_owned(id)?;
+        node.children[idx] = moved;
+        self.fix_deficient_child(&mut node, idx)?;
+        Ok((self.store(id, node)?, key))
+    }
+
+    /// Refills the child at the given index of the parent if it became
+    /// deficient, either by rotating a key from one of its siblings, or by
+    /// merging it with one. The parent is stored by the caller.
+    fn fix_deficient_child(&mut self, parent: &mut NodePage<K>, idx: usize) -> Result<()> {
+        let child_id = parent.children[idx];
+        if self.load(child_id)?.keys.len() >= Self::MIN_KEYS {
+            return Ok(());
+        }
+
+        if idx > 0 {
+            let left_id = parent.children[idx - 1];
+            if self.load(left_id)?.keys.len() > Self::MIN_KEYS {
+                let mut left = self.load_owned(left_id)?;
+                let mut child = self.load_owned(child_id)?;
+
+                let key = left.keys.pop().unwrap();
+                child
+                    .keys
+                    .insert(0, mem::replace(&mut parent.keys[idx - 1], key));
+                if let Some(grandchild) = left.children.pop() {
+                    child.children.insert(0, grandchild);
+                }
+
+                parent.children[idx - 1] = self.store(left_id, left)?;
+                parent.children[idx] = self.store(child_id, child)?;
+                return Ok(());
+            }
+        }
+
+        if let Some(&right_id) = parent.children.get(idx + 1)
+            && self.load(right_id)?.keys.len() > Self::MIN_KEYS
+        {
+            let mut right = self.load_owned(right_id)?;
+            let mut child = self.load_owned(child_id)?;
+
+            let key = right.keys.remove(0);
+            child.keys.push(mem::replace(&mut parent.keys[idx], key));
+            if !right.is_leaf() {
+                child.children.push(right.children.remove(0));
+            }
+
+            parent.children[idx + 1] = self.store(right_id, right)?;
+            parent.children[idx] = self.store(child_id, child)?;
+            return Ok(());
+        }
+
+        let idx = if idx > 0 { idx - 1 } else { idx };
+        let separator = parent.keys.remove(idx);
+        let right_id = parent.children.remove(idx + 1);
+        let left_id = parent.children[idx];
+
+        let mut left = self.load_owned(left_id)?;
+        let right = self.load_owned(right_id)?;
+        left.keys.push(separator);
+        left.keys.extend(right.keys);
+        left.children.extend(right.children);
+
+        debug_event!(page = left_id, released = right_id, "nodes merged");
+        self.release(right_id)?;
+        parent.children[idx] = self.store(left_id, left)?;
+        Ok(())
+    }
+}
+
+/// Decodes the node stored in the given page of a store of `page_count`
+/// pages, read through `view`, along with the keys which overflow from it.
+/// Returns the node, and the overflow pages it was read from.
+fn read_node<'a, K: PageKey>(
+    id: PageId,
+    page_count: u64,
+    view: impl Fn(PageId) -> Result<Cow<'a, [u8]>>,
+) -> Result<(NodePage<K>, Vec<PageId>)> {
+    let mut chain = Vec::new();
+    let node = decode_page_spilled(&view(id)?, |first, bytes| {
+        let mut next = Some(first);
+        while let Some(id) = next {
+            if chain.len() as u64 >= page_count {
+                return Err(Error::CorruptPage {
+                    reason: format!("overflow page {id} is part of a cycle"),
+                });
+            }
+            let page = view(id)?;
+            let (part, following) = decode_overflow(&page)?;
+            bytes.extend_from_slice(part);
+            chain.push(id);
+            next = following;
+        }
+        Ok(())
+    })?;
+    Ok((node, chain))
+}
+
+/// Returns the positions in the sorted keys of a node between which the
+/// keys are within the range. The keys before `start` are below the range,
+/// and the keys from `end` onwards are above it.
+fn range_bounds<K: Ord>(keys: &[K], range: &impl RangeBounds<K>) -> (usize, usize) {
+    let start = match range.start_bound() {
+        Bound::Included(bound) => keys.partition_point(|k| k < bound),
+        Bound::Excluded(bound) => keys.partition_point(|k| k <= bound),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(bound) => keys.partition_point(|k| k <= bound),
+        Bound::Excluded(bound) => keys.partition_point(|k| k < bound),
+        Bound::Unbounded => keys.len(),
+    };
+    (start, end)
+}
+
+impl<K: PageKey + Ord, const B: usize, P: Pager> BTreeSet for DiskBTreeSet<K, B, P> {
+    type Key = K;
+    const B: usize = B;
+
+    fn search(&self, key: &Self::Key) -> Result<&Self::Key> {
+        self.search_at(self.root, key)
+    }
+
+    fn insert(&mut self, key: Self::Key) -> Result<()> {
+        let mut root = self.root;
+        self.insert_at(&mut root, key)?;
+        self.commit_with_root(root)
+    }
+
+    fn remove(&mut self, key: &Self::Key) -> Result<Self::Key> {
+        let mut root = self.root;
+        let removed = self.remove_at(&mut root, key)?;
+        self.commit_with_root(root)?;
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::{DifferentialTester, ReferenceBTreeSet};
+    use crate::storage::{DEFAULT_PAGE_SIZE, FixedSizeKey};
+    use crate::test_btree_impl;
+    use crate::tests::temp_path;
+
+    type MemoryBTreeSet<K> = DiskBTreeSet<K, 3, MemoryPager>;
+
+    test_btree_impl!(MemoryBTreeSet);
+
+    mod narrow {
+        use super::*;
+
+        type NarrowBTreeSet<K> = DiskBTreeSet<K, 2, MemoryPager>;
+
+        test_btree_impl!(NarrowBTreeSet);
+    }
+
+    #[test]
+    fn test_agrees_with_reference_on_mixed_operations() {
+        let mut tester = DifferentialTester::new(
+            DiskBTreeSet::<u32, 3, MemoryPager>::new(),
+            ReferenceBTreeSet::new(),
+        );
+
+        for i in 0..5000u32 {
+            let key = (i * 7919) % 257;
+            let _ = match i % 3 {
+                0 => tester.remove(&key).map(|_| ()),
+                _ => tester.insert(key),
+            };
+        }
+    }
+
+    #[test]
+    fn test_reopened_file_keeps_keys() {
+        let path = temp_path("reopen");
+
+        let pager = FilePager::open(&path, DEFAULT_PAGE_SIZE).unwrap();
+        let mut tree = DiskBTreeSet::<u64, 4>::open(pager).unwrap();
+        for key in 0..2000 {
+            tree.insert(key * 3).unwrap();
+        }
+        for key in 0..500 {
+            tree.remove(&(key * 6)).unwrap();
+        }
+        tree.sync().unwrap();
+        drop(tree);
+
+        let pager = FilePager::open(&path, DEFAULT_PAGE_SIZE).unwrap();
+        let tree = DiskBTreeSet::<u64, 4>::open(pager).unwrap();
+        for key in 0..2000 {
+            let removed = key < 1000 && key % 2 == 0;
+            assert_eq!(tree.contains(&(key * 3)), !removed, "key {}", key * 3);
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mapped_file_can_be_searched_but_not_modified() {
+        use crate::storage::MmapPager;
+
+        let path = temp_path("mmap");
+
+        let pager = FilePager::open(&path, DEFAULT_PAGE_SIZE).unwrap();
+        let mut tree = DiskBTreeSet::<u64, 4>::open(pager).unwrap();
+        for key in 0..2000 {
+            tree.insert(key * 2).unwrap();
+        }
+        tree.sync().unwrap();
+        drop(tree);
+
+        let pager = MmapPager::open(&path, DEFAULT_PAGE_SIZE).unwrap();
+        let mut tree = DiskBTreeSet::<u64, 4, _>::open(pager).unwrap();
+        for key in 0..4000 {
+            assert_eq!(tree.contains(&key), key % 2 == 0, "key {key}");
+        }
+
+        assert!(matches!(tree.insert(1), Err(Error::ReadOnly)));
+        assert!(matches!(tree.remove(&2), Err(Error::ReadOnly)));
+        assert!(tree.contains(&2));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_torn_log_recovers_a_prefix_of_the_operations() {
+        use crate::storage::WalPager;
+        use std::collections::BTreeSet as StdBTreeSet;
+
+        const PAGE_SIZE: usize = 256;
+        let data = temp_path("wal-data");
+        let log = temp_path("wal-log");
+
+        let ops: Vec<(bool, u64)> = (0..200)
+            .map(|i| (true, (i * 7919) % 200))
+            .chain((0..100).map(|i| (false, (i * 31) % 200)))
+            .collect();
+
+        let mut states = vec![StdBTreeSet::new()];
+        for &(insert, key) in &ops {
+            let mut state = states.last().unwrap().clone();
+            match insert {
+                true => state.insert(key),
+                false => state.remove(&key),
+            };
+            states.push(state);
+        }
+
+        let mut pager = WalPager::open(FilePager::open(&data, PAGE_SIZE).unwrap(), &log).unwrap();
+        pager.set_checkpoint_threshold(64);
+        let mut tree = DiskBTreeSet::<u64, 2, _>::open(pager).unwrap();
+        for &(insert, key) in &ops {
+            match insert {
+                true => tree.insert(key).unwrap(),
+                false => tree.remove(&key).map(|_| ()).unwrap(),
+            }
+        }
+        // Crash without a checkpoint.
+        drop(tree);
+
+        let log_len = std::fs::metadata(&log).unwrap().len();
+        let mut offsets = vec![0, log_len];
+        let mut state = 0x2545f4914f6cdd1du64;
+        for _ in 0..40 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            offsets.push(state % log_len);
+        }
+        offsets.sort_unstable();
+
+        let mut last = 0;
+        for offset in offsets {
+            let (trial_data, trial_log) = (temp_path("wal-trial-data"), temp_path("wal-trial-log"));
+            std::fs::copy(&data, &trial_data).unwrap();
+            std::fs::copy(&log, &trial_log).unwrap();
+            let file = std::fs::OpenOptions::new()
+                .write(true)
+                .open(&trial_log)
+                .unwrap();
+            file.set_len(offset).unwrap();
+            drop(file);
+
+            let pager = FilePager::open(&trial_data, PAGE_SIZE).unwrap();
+            let pager = WalPager::open(pager, &trial_log).unwrap();
+            let mut tree = DiskBTreeSet::<u64, 2, _>::open(pager).unwrap();
+            let recovered: StdBTreeSet<u64> = (0..200).filter(|key| tree.contains(key)).collect();
+
+            let prefix = states[last..]
+                .iter()
+                .position(|state| *state == recovered)
+                .unwrap_or_else(|| panic!("log truncated to {offset} recovered {recovered:?}"));
+            last += prefix;
+
+            tree.insert(1000).unwrap();
+            assert!(tree.contains(&1000));
+
+            std::fs::remove_file(&trial_data).unwrap();
+            std::fs::remove_file(&trial_log).unwrap();
+        }
+        assert_eq!(last, ops.len());
+
+        std::fs::remove_file(&data).unwrap();
+        std::fs::remove_file(&log).unwrap();
+    }
+
+    #[test]
+    fn test_freed_pages_are_reused() {
+        let mut tree = DiskBTreeSet::<u64, 2, MemoryPager>::new();
+
+        for key in 0..300 {
+            tree.insert(key).unwrap();
+        }
+        let page_count = tree.pager.page_count();
+
+        for _ in 0..3 {
+            for key in 0..300 {
+                tree.remove(&key).unwrap();
+            }
+            for key in 0..300 {
+                tree.insert(key).unwrap();
+            }
+        }
+        assert_eq!(tree.pager.page_count(), page_count);
+    }
+
+    #[test]
+    fn test_free_pages_are_reused_after_reopening() {
+        let path = temp_path("free-list");
+        let mut tree =
+            DiskBTreeSet::<u64, 2, _>::open(FilePager::open(&path, 256).unwrap()).unwrap();
+        for key in 0..2000 {
+            tree.insert(key).unwrap();
+        }
+        let total_pages = tree.file_stats().total_pages;
+        for key in 0..2000 {
+            tree.remove(&key).unwrap();
+        }
+        let stats = tree.file_stats();
+        assert_eq!(stats.total_pages, total_pages);
+        assert_eq!(stats.used_pages(), 1);
+        tree.sync().unwrap();
+        drop(tree);
+
+        let pager = FilePager::open(&path, 256).unwrap();
+        let mut tree = DiskBTreeSet::<u64, 2, _>::open(pager).unwrap();
+        assert_eq!(tree.file_stats(), stats);
+        for key in 0..2000 {
+            tree.insert(key * 7919 % 2000).unwrap();
+        }
+        assert!(tree.range(..).unwrap().into_iter().copied().eq(0..2000));
+        assert_eq!(tree.file_stats().total_pages, total_pages);
+        assert!(tree.file_stats().free_pages < stats.free_pages);
+        drop(tree);
+
+        // The list is stored as it is after every commit, not only on sync.
+        let pager = FilePager::open(&path, 256).unwrap();
+        let tree = DiskBTreeSet::<u64, 2, _>::open(pager).unwrap();
+        assert_eq!(tree.file_stats().total_pages, total_pages);
+        assert!(tree.range(..).unwrap().into_iter().copied().eq(0..2000));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_range_matches_reference() {
+        let mut tree = DiskBTreeSet::<u32, 2, MemoryPager>::new();
+        let mut reference = std::collections::BTreeSet::new();
+        for i in 0..500u32 {
+            let key = (i * 7919) % 1000;
+            tree.insert(key).unwrap();
+            reference.insert(key);
+        }
+
+        let bounds: [Bound<u32>; 7] = [
+            Bound::Unbounded,
+            Bound::Included(0),
+            Bound::Excluded(0),
+            Bound::Included(321),
+            Bound::Excluded(321),
+            Bound::Included(999),
+            Bound::Excluded(1000),
+        ];
+        for start in bounds {
+            for end in bounds {
+                let range = (start, end);
+                let expected: Vec<&u32> =
+                    reference.iter().filter(|&&k| range.contains(&k)).collect();
+                assert_eq!(tree.range(range).unwrap(), expected, "{range:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_replace_overwrites_in_place() {
+        /// A key ordered by its first half only.
+        #[derive(Debug, PartialEq, Eq)]
+        struct Pair(u32, u32);
+
+        impl Ord for Pair {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                self.0.cmp(&other.0)
+            }
+        }
+
+        impl PartialOrd for Pair {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl FixedSizeKey for Pair {
+            const SIZE: usize = 8;
+
+            fn encode(&self, buf: &mut [u8]) {
+                self.0.encode(&mut buf[..4]);
+                self.1.encode(&mut buf[4..]);
+            }
+
+            fn decode(buf: &[u8]) -> Self {
+                Pair(u32::decode(&buf[..4]), u32::decode(&buf[4..]))
+            }
+        }
+
+        let mut tree = DiskBTreeSet::<Pair, 2, MemoryPager>::new();
+        for key in 0..100 {
+            assert_eq!(tree.replace(Pair(key, 0)).unwrap(), None);
+        }
+        let page_count = tree.pager.page_count();
+        for key in 0..100 {
+            assert_eq!(tree.replace(Pair(key, 1)).unwrap(), Some(Pair(key, 0)));
+        }
+        assert_eq!(tree.pager.page_count(), page_count);
+
+        // Reopening decodes every node from its page again.
+        let tree = DiskBTreeSet::<Pair, 2, _>::open(tree.into_pager()).unwrap();
+        for key in 0..100 {
+            assert_eq!(tree.search(&Pair(key, 9)).unwrap(), &Pair(key, 1));
+        }
+    }
+
+    #[cfg(any(feature = "lz4", feature = "zstd"))]
+    #[test]
+    fn test_compressed_and_uncompressed_pages_coexist() {
+        #[cfg(feature = "lz4")]
+        let compression = Compression::Lz4;
+        #[cfg(not(feature = "lz4"))]
+        let compression = Compression::Zstd { level: 3 };
+
+        let mut tree = DiskBTreeSet::<u64, 8, _>::open(MemoryPager::default()).unwrap();
+        for key in 0..1000 {
+            tree.insert(key).unwrap();
+        }
+        tree.set_compression(compression);
+        for key in 1000..2000 {
+            tree.insert(key).unwrap();
+        }
+
+        let pager = tree.into_pager();
+        let flags: Vec<u8> = (1..pager.page_count())
+            .map(|id| pager.view_page(id).unwrap()[1])
+            .collect();
+        assert!(flags.contains(&0) && flags.iter().any(|&flags| flags != 0));
+
+        let tree = DiskBTreeSet::<u64, 8, _>::open(pager).unwrap();
+        assert!(tree.range(..).unwrap().into_iter().copied().eq(0..2000));
+    }
+
+    #[test]
+    fn test_branching_factor_must_fit_in_a_page() {
+        let err = DiskBTreeSet::<u64, 32, _>::open(MemoryPager::new(512))
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "DiskBTreeSet::open failed");
+        assert!(matches!(
+            err.root_cause(),
+            Error::PageOverflow {
+                needed: 1024,
+                page_size: 512
+            }
+        ));
+    }
+
+    #[test]
+    fn test_key_size_must_match_the_file() {
+        let mut tree = DiskBTreeSet::<u64, 4, MemoryPager>::new();
+        tree.insert(1).unwrap();
+        let pager = tree.into_pager();
+
+        let err = DiskBTreeSet::<u32, 4, _>::open(pager).err().unwrap();
+        assert!(matches!(err.root_cause(), Error::CorruptPage { .. }));
+
+        let mut tree = DiskBTreeSet::<Vec<u8>, 4, _>::open(MemoryPager::new(512)).unwrap();
+        tree.insert(b"key".to_vec()).unwrap();
+        let err = DiskBTreeSet::<u64, 4, _>::open(tree.into_pager())
+            .err()
+            .unwrap();
+        assert!(matches!(err.root_cause(), Error::CorruptPage { .. }));
+    }
+
+    /// Returns a key of a length between 5 and about 2000 bytes, which makes
+    /// some keys overflow into several 512 byte pages.
+    fn byte_key(i: usize) -> Vec<u8> {
+        let mut key = format!("{i:05}").into_bytes();
+        key.resize(5 + i % 7 * i, b'x');
+        key
+    }
+
+    #[test]
+    fn test_byte_string_keys_of_any_length() {
+        let path = temp_path("bytes");
+        let pager = FilePager::open(&path, 512).unwrap();
+        let mut tree = DiskBTreeSet::<Vec<u8>, 4, _>::open(pager).unwrap();
+        let mut reference = std::collections::BTreeSet::new();
+        for i in 0..300 {
+            tree.insert(byte_key(i * 7919 % 300)).unwrap();
+            reference.insert(byte_key(i * 7919 % 300));
+        }
+        assert!(tree.range(..).unwrap().into_iter().eq(&reference));
+        assert_eq!(tree.search(&byte_key(299)).unwrap(), &byte_key(299));
+
+        for i in (0..300).step_by(2) {
+            assert_eq!(tree.remove(&byte_key(i)).unwrap(), byte_key(i));
+            reference.remove(&byte_key(i));
+        }
+        tree.sync().unwrap();
+        drop(tree);
+
+        let pager = FilePager::open(&path, 512).unwrap();
+        let mut tree = DiskBTreeSet::<Vec<u8>, 4, _>::open(pager).unwrap();
+        assert!(tree.range(..).unwrap().into_iter().eq(&reference));
+
+        // Removing every key frees the overflow pages along with the nodes.
+        for key in &reference {
+            tree.remove(key).unwrap();
+        }
+        assert_eq!(tree.file_stats().used_pages(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_bulk_load_packs_the_nodes() {
+        for len in [0, 1, 5, 6, 100, 1000] {
+            let mut tree = DiskBTreeSet::<u64, 3, _>::open(MemoryPager::new(512)).unwrap();
+            tree.bulk_load(0..len).unwrap();
+            assert!(tree.range(..).unwrap().into_iter().copied().eq(0..len));
+
+            // Every node but the root is at least half full.
+            let mut pending: Vec<_> = tree.root.into_iter().collect();
+            while let Some(id) = pending.pop() {
+                let node = tree.load(id).unwrap();
+                assert!(Some(id) == tree.root || node.keys.len() >= 2);
+                assert!(node.keys.len() <= 5);
+                pending.extend(&node.children);
+            }
+
+            // The tree is written to like any other.
+            tree.insert(len).unwrap();
+            tree.remove(&0).ok();
+            assert!(tree.range(..).unwrap().into_iter().copied().eq(1..=len));
+        }
+
+        let mut tree = DiskBTreeSet::<u64, 3, _>::open(MemoryPager::new(512)).unwrap();
+        assert!(matches!(
+            tree.bulk_load([1, 3, 2]),
+            Err(Error::KeyOutOfOrder)
+        ));
+        assert!(matches!(tree.bulk_load([1, 1]), Err(Error::KeyOutOfOrder)));
+        assert_eq!(tree.file_stats().used_pages(), 1);
+        tree.insert(7).unwrap();
+        assert!(matches!(tree.bulk_load([1]), Err(Error::KeyAlreadyExists)));
+    }
+
+    #[test]
+    fn test_shared_prefixes_are_stored_once() {
+        let path = temp_path("prefix");
+        let url =
+            |i: usize| format!("https://example.com/users/{}/posts/{i:05}", i % 3).into_bytes();
+        let pager = FilePager::open(&path, 512).unwrap();
+        let mut tree = DiskBTreeSet::<Vec<u8>, 4, _>::open(pager).unwrap();
+        for i in 0..500 {
+            tree.insert(url(i * 7919 % 500)).unwrap();
+        }
+
+        let stats = tree.prefix_stats().unwrap();
+        assert!(stats.nodes > 1);
+        assert_eq!(
+            stats.key_bytes,
+            (0..500).map(|i| url(i).len() as u64).sum::<u64>()
+        );
+        assert!(stats.ratio() < 0.5, "{stats:?}");
+        tree.sync().unwrap();
+        drop(tree);
+
+        let pager = FilePager::open(&path, 512).unwrap();
+        let tree = DiskBTreeSet::<Vec<u8>, 4, _>::open(pager).unwrap();
+        let mut urls: Vec<_> = (0..500).map(url).collect();
+        urls.sort();
+        assert!(tree.range(..).unwrap().into_iter().eq(&urls));
+        assert_eq!(tree.prefix_stats().unwrap(), stats);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_overflow_pages_outlive_snapshots_and_rollbacks() {
+        let mut tree = DiskBTreeSet::<Vec<u8>, 4, _>::open(MemoryPager::new(512)).unwrap();
+        for i in 0..100 {
+            tree.insert(byte_key(i)).unwrap();
+        }
+
+        let snapshot = tree.snapshot();
+        let mut transaction = tree.begin();
+        for i in 0..50 {
+            transaction.remove(&byte_key(i)).unwrap();
+        }
+        transaction.rollback().unwrap();
+        for i in 0..100 {
+            tree.remove(&byte_key(i)).unwrap();
+            tree.insert(byte_key(i + 100)).unwrap();
+        }
+
+        let keys = snapshot.range(&tree, ..).unwrap();
+        assert!(keys.into_iter().cloned().eq((0..100).map(byte_key)));
+        drop(snapshot);
+
+        for i in 100..200 {
+            tree.remove(&byte_key(i)).unwrap();
+        }
+        assert_eq!(tree.file_stats().used_pages(), 1);
+    }
+}
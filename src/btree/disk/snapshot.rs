@@ -0,0 +1,439 @@
+use super::{DiskBTreeSet, range_bounds, read_node};
+use crate::storage::{NodePage, PageId, PageKey, PageReader, Pager, SharedPager};
+use crate::{Error, Result};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::marker::PhantomData;
+use std::ops::RangeBounds;
+use std::sync::{Arc, Mutex};
+
+/// The number of live snapshots of every version of a tree.
+type Readers = Arc<Mutex<BTreeMap<u64, usize>>>;
+
+/// A read-only view of a `DiskBTreeSet` as it was when the snapshot was
+/// taken.
+///
+/// While a snapshot is alive, the writer never changes the pages it may read.
+/// A node which it would overwrite is copied into a new page instead, and the
+/// old page is freed at the first commit after the last snapshot which may
+/// read it is dropped. A snapshot borrows nothing, so it can be kept across
+/// writes, and read with the tree it was taken of.
+///
+/// A snapshot is read through the tree, whose pager and decoded nodes are
+/// not shared between threads, so its reads take turns with the writes. To
+/// read from other threads while the writer goes on, take a `Reader`, which
+/// holds a snapshot and reads its pages itself.
+pub struct Snapshot {
+    root: Option<PageId>,
+    version: u64,
+    readers: Readers,
+}
+
+impl<K: PageKey, const B: usize, P: Pager> DiskBTreeSet<K, B, P> {
+    /// Takes a snapshot of the last committed state of the tree.
+    pub fn snapshot(&self) -> Snapshot {
+        self.snapshot_at(self.root)
+    }
+
+    /// Takes a snapshot of the tree with the given root, which must be a root
+    /// of the last committed state.
+    pub(crate) fn snapshot_at(&self, root: Option<PageId>) -> Snapshot {
+        let version = self.versions.current;
+        let readers = Arc::clone(&self.versions.readers);
+        *readers.lock().unwrap().entry(version).or_default() += 1;
+        Snapshot {
+            root,
+            version,
+            readers,
+        }
+    }
+}
+
+impl Snapshot {
+    /// Returns the number of commits made to the tree since it was opened,
+    /// at the time the snapshot was taken.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub(crate) fn root(&self) -> Option<PageId> {
+        self.root
+    }
+
+    /// Returns the key equal to the given key, as of the snapshot.
+    pub fn search<'a, K, const B: usize, P>(
+        &self,
+        tree: &'a DiskBTreeSet<K, B, P>,
+        key: &K,
+    ) -> Result<&'a K>
+    where
+        K: PageKey + Ord,
+        P: Pager,
+    {
+        self.check(tree);
+        tree.search_at(self.root, key)
+    }
+
+    /// Returns the keys within the range, in ascending order, as of the
+    /// snapshot.
+    pub fn range<'a, K, const B: usize, P, R>(
+        &self,
+        tree: &'a DiskBTreeSet<K, B, P>,
+        range: R,
+    ) -> Result<Vec<&'a K>>
+    where
+        K: PageKey + Ord,
+        P: Pager,
+        R: RangeBounds<K>,
+    {
+        self.check(tree);
+        tree.range_at(self.root, range)
+    }
+
+    pub(crate) fn check<K: PageKey, const B: usize, P: Pager>(&self, tree: &DiskBTreeSet<K, B, P>) {
+        assert!(
+            Arc::ptr_eq(&self.readers, &tree.versions.readers),
+            "the snapshot was taken of another tree"
+        );
+    }
+}
+
+/// A handle which reads a snapshot of a `DiskBTreeSet` on its own, so that
+/// any number of threads can read it while the writer goes on committing.
+///
+/// The reader holds a snapshot, which keeps the writer off the pages it
+/// reads, and reads them through a handle of its own on the pager. Reads
+/// take no lock, and the writer never waits for them. The reader keeps no
+/// decoded nodes, so every read decodes the nodes on its path, and returns
+/// its keys by value.
+pub struct Reader<K, R> {
+    snapshot: Snapshot,
+    pages: R,
+    page_count: u64,
+    keys: PhantomData<fn() -> K>,
+}
+
+impl<K: PageKey, const B: usize, P: SharedPager> DiskBTreeSet<K, B, P> {
+    /// Returns a reader of the last committed state of the tree, which can
+    /// be sent to other threads.
+    pub fn reader(&self) -> Result<Reader<K, P::Reader>> {
+        Ok(Reader {
+            snapshot: self.snapshot(),
+            pages: self.pager.reader()?,
+            page_count: self.pager.page_count(),
+            keys: PhantomData,
+        })
+    }
+}
+
+impl<K: PageKey + Ord, R: PageReader> Reader<K, R> {
+    /// Returns the snapshot the reader reads.
+    pub fn snapshot(&self) -> &Snapshot {
+        &self.snapshot
+    }
+
+    /// Returns the key equal to the given key, as of the snapshot.
+    pub fn search(&self, key: &K) -> Result<K> {
+        let mut id = self.snapshot.root.ok_or(Error::KeyNotFound)?;
+        loop {
+            let mut node = self.read(id)?;
+            match node.keys.binary_search(key) {
+                Ok(idx) => return Ok(node.keys.swap_remove(idx)),
+                Err(_) if node.is_leaf() => return Err(Error::KeyNotFound),
+                Err(idx) => id = node.children[idx],
+            }
+        }
+    }
+
+    /// Returns the keys within the range, in ascending order, as of the
+    /// snapshot.
+    pub fn range(&self, range: impl RangeBounds<K>) -> Result<Vec<K>> {
+        let mut keys = Vec::new();
+        if let Some(root) = self.snapshot.root {
+            self.collect_range(root, &range, &mut keys)?;
+        }
+        Ok(keys)
+    }
+
+    fn collect_range(
+        &self,
+        id: PageId,
+        range: &impl RangeBounds<K>,
+        keys: &mut Vec<K>,
+    ) -> Result<()> {
+        let node = self.read(id)?;
+        let (start, end) = range_bounds(&node.keys, range);
+
+        let mut within = node.keys.into_iter().skip(start);
+        for idx in start..=end.max(start) {
+            if let Some(&child) = node.children.get(idx) {
+                self.collect_range(child, range, keys)?;
+            }
+            if idx < end {
+                keys.extend(within.next());
+            }
+        }
+        Ok(())
+    }
+
+    fn read(&self, id: PageId) -> Result<NodePage<K>> {
+        let (node, _) = read_node(id, self.page_count, |id| {
+            let mut page = vec![0; self.pages.page_size()];
+            self.pages.read_page(id, &mut page)?;
+            Ok(Cow::Owned(page))
+        })?;
+        Ok(node)
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        let mut readers = self.readers.lock().unwrap();
+        if let Some(count) = readers.get_mut(&self.version) {
+            *count -= 1;
+            if *count == 0 {
+                readers.remove(&self.version);
+            }
+        }
+    }
+}
+
+/// The versions of a tree, and the pages kept for the snapshots of the older
+/// ones.
+#[derive(Default)]
+pub(super) struct Versions {
+    /// The number of commits made since the tree was opened.
+    current: u64,
+    readers: Readers,
+    /// The first version a page allocated while there were snapshots is part
+    /// of. The pages which are not in the map are part of every version which
+    /// has a snapshot.
+    born: HashMap<PageId, u64>,
+    /// The pages which are no longer part of the tree, along with the first
+    /// version they are not part of.
+    retired: Vec<(u64, PageId)>,
+}
+
+impl Versions {
+    pub(super) fn allocated(&mut self, id: PageId) {
+        self.born.insert(id, self.current + 1);
+    }
+
+    /// Returns whether a live snapshot may read the page.
+    pub(super) fn is_visible(&self, id: PageId) -> bool {
+        let readers = self.readers.lock().unwrap();
+        let Some((&newest, _)) = readers.last_key_value() else {
+            return false;
+        };
+        self.born.get(&id).copied().unwrap_or(0) <= newest
+    }
+
+    /// Keeps the page, which the next version is no longer made of, until
+    /// the snapshots which may read it are dropped.
+    pub(super) fn retire(&mut self, id: PageId) {
+        self.retired.push((self.current + 1, id));
+    }
+
+    /// Moves on to the next version, and returns the retired pages which no
+    /// live snapshot may read anymore.
+    pub(super) fn commit(&mut self) -> Vec<PageId> {
+        self.current += 1;
+
+        let readers = self.readers.lock().unwrap();
+        let oldest = readers.first_key_value().map(|(&version, _)| version);
+        if oldest.is_none() {
+            self.born.clear();
+        }
+
+        let (reclaimed, retired) = self
+            .retired
+            .drain(..)
+            .partition(|&(version, _)| oldest.is_none_or(|oldest| version <= oldest));
+        self.retired = retired;
+        reclaimed.into_iter().map(|(_, id)| id).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Reader;
+    use crate::BTreeSet;
+    use crate::btree::DiskBTreeSet;
+    use crate::storage::{FilePager, MemoryPager, Pager};
+    use crate::tests::temp_path;
+
+    type MemoryBTreeSet = DiskBTreeSet<u32, 3, MemoryPager>;
+
+    fn keys(keys: Vec<&u32>) -> Vec<u32> {
+        keys.into_iter().copied().collect()
+    }
+
+    #[test]
+    fn test_snapshot_is_isolated_from_writes() {
+        let mut tree = MemoryBTreeSet::new();
+        for key in 0..200 {
+            tree.insert(key).unwrap();
+        }
+
+        let snapshot = tree.snapshot();
+        for key in (0..200).step_by(2) {
+            tree.remove(&key).unwrap();
+        }
+        for key in 200..400 {
+            tree.insert(key).unwrap();
+        }
+        tree.replace(1).unwrap();
+
+        let newer = tree.snapshot();
+        tree.insert(1000).unwrap();
+
+        assert_eq!(
+            keys(snapshot.range(&tree, ..).unwrap()),
+            (0..200).collect::<Vec<_>>()
+        );
+        assert_eq!(snapshot.search(&tree, &10).unwrap(), &10);
+        assert!(snapshot.search(&tree, &300).is_err());
+        assert_eq!(newer.range(&tree, ..).unwrap().len(), 300);
+        assert!(newer.search(&tree, &1000).is_err());
+        assert_eq!(tree.range(..).unwrap().len(), 301);
+        assert!(snapshot.version() < newer.version());
+    }
+
+    #[test]
+    fn test_old_pages_are_reclaimed_once_snapshots_are_dropped() {
+        let mut tree = MemoryBTreeSet::new();
+        for key in 0..500 {
+            tree.insert(key).unwrap();
+        }
+        let pages = tree.pager().page_count();
+
+        let snapshot = tree.snapshot();
+        for key in 0..500 {
+            tree.remove(&key).unwrap();
+            tree.insert(key).unwrap();
+        }
+        assert_eq!(snapshot.range(&tree, ..).unwrap().len(), 500);
+        let grown = tree.pager().page_count();
+        assert!(grown > pages);
+
+        // The retired pages are freed by the first commit after the drop,
+        // and reused afterwards.
+        drop(snapshot);
+        for _ in 0..3 {
+            for key in 0..500 {
+                tree.remove(&key).unwrap();
+                tree.insert(key).unwrap();
+            }
+        }
+        assert_eq!(tree.pager().page_count(), grown);
+        assert_eq!(tree.range(..).unwrap().len(), 500);
+    }
+
+    #[test]
+    fn test_snapshot_is_isolated_from_transactions() {
+        let mut tree = MemoryBTreeSet::new();
+        for key in 0..100 {
+            tree.insert(key).unwrap();
+        }
+
+        let snapshot = tree.snapshot();
+        let mut txn = tree.begin();
+        for key in 0..100 {
+            txn.remove(&key).unwrap();
+        }
+        txn.rollback().unwrap();
+
+        let mut txn = tree.begin();
+        for key in 100..200 {
+            txn.insert(key).unwrap();
+        }
+        txn.remove(&0).unwrap();
+        txn.commit().unwrap();
+
+        assert_eq!(
+            keys(snapshot.range(&tree, ..).unwrap()),
+            (0..100).collect::<Vec<_>>()
+        );
+        assert_eq!(keys(tree.range(..).unwrap()), (1..200).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_readers_read_on_other_threads_while_the_writer_commits() {
+        fn check<K, R: Send + Sync>(_: &Reader<K, R>) {}
+
+        let path = temp_path("disk-readers");
+        let mut tree =
+            DiskBTreeSet::<u32, 3, FilePager>::open(FilePager::open(&path, 512).unwrap()).unwrap();
+        for key in 0..500 {
+            tree.insert(key).unwrap();
+        }
+
+        let reader = tree.reader().unwrap();
+        check(&reader);
+        let memory = MemoryBTreeSet::new();
+        check(&memory.reader().unwrap());
+
+        std::thread::scope(|scope| {
+            for _ in 0..2 {
+                scope.spawn(|| {
+                    for round in 0..20 {
+                        assert_eq!(reader.range(..).unwrap(), (0..500).collect::<Vec<_>>());
+                        assert_eq!(reader.search(&round).unwrap(), round);
+                        assert!(reader.search(&1000).is_err());
+                    }
+                });
+            }
+
+            for round in 0..20 {
+                for key in (round % 2..500).step_by(2) {
+                    tree.remove(&key).unwrap();
+                }
+                for key in (round % 2..500).step_by(2) {
+                    tree.insert(key + 1000).unwrap();
+                    tree.remove(&(key + 1000)).unwrap();
+                    tree.insert(key).unwrap();
+                }
+            }
+        });
+        tree.insert(1000).unwrap();
+
+        assert_eq!(
+            reader.range(100..110).unwrap(),
+            (100..110).collect::<Vec<_>>()
+        );
+        assert_eq!(tree.range(..).unwrap().len(), 501);
+        drop(reader);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_memory_reader_does_not_observe_later_changes() {
+        let mut tree = MemoryBTreeSet::new();
+        for key in 0..200 {
+            tree.insert(key).unwrap();
+        }
+
+        let reader = tree.reader().unwrap();
+        for key in 0..200 {
+            tree.remove(&key).unwrap();
+        }
+
+        let reader = std::thread::spawn(move || {
+            assert_eq!(reader.range(50..).unwrap(), (50..200).collect::<Vec<_>>());
+            reader
+        })
+        .join()
+        .unwrap();
+        assert_eq!(reader.search(&7).unwrap(), 7);
+        assert!(tree.range(..).unwrap().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "another tree")]
+    fn test_snapshot_of_another_tree_panics() {
+        let tree = MemoryBTreeSet::new();
+        let other = MemoryBTreeSet::new();
+        let snapshot = other.snapshot();
+        let _ = snapshot.search(&tree, &0);
+    }
+}
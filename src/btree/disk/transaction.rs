@@ -0,0 +1,377 @@
+use super::{DiskBTreeSet, Overlay};
+use crate::storage::{PageId, PageKey, Pager, decode_page, encode_page};
+use crate::{BTreeSet, Result};
+use std::collections::HashMap;
+use std::ops::RangeBounds;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A group of mutations of a `DiskBTreeSet`, which are applied together by
+/// `commit`, or not at all.
+///
+/// The pages changed by the transaction are kept in memory, apart from the
+/// committed ones, and nothing reaches the pager before the transaction
+/// commits. The transaction sees its own changes. A transaction which is
+/// dropped without committing rolls back.
+///
+/// Part of the work of a transaction can be undone by rolling back to a
+/// savepoint, which leaves the transaction open.
+pub struct Transaction<'a, K: PageKey, const B: usize, P: Pager> {
+    tree: &'a mut DiskBTreeSet<K, B, P>,
+    root: Option<PageId>,
+    checkpoints: Vec<Checkpoint>,
+}
+
+/// The source of the identifiers of savepoints, which tell apart the ones of
+/// different transactions.
+static SAVEPOINTS: AtomicU64 = AtomicU64::new(0);
+
+/// A point within a transaction, which it can be rolled back to.
+///
+/// Savepoints nest: rolling back to one also rolls back past the savepoints
+/// taken after it, which cannot be used afterwards. The savepoint itself
+/// stays, and can be rolled back to again.
+#[derive(Debug)]
+pub struct Savepoint {
+    depth: usize,
+    id: u64,
+}
+
+/// The state of a transaction at a savepoint.
+struct Checkpoint {
+    id: u64,
+    root: Option<PageId>,
+    /// The nodes written by the transaction so far, encoded into pages, as
+    /// nodes cannot be cloned.
+    dirty: Vec<(PageId, Vec<u8>)>,
+    /// The number of pages allocated by the transaction so far.
+    allocated: usize,
+    /// The number of pages released by the transaction so far.
+    released: usize,
+}
+
+impl<K: PageKey, const B: usize, P: Pager> DiskBTreeSet<K, B, P> {
+    /// Starts a transaction on the tree.
+    pub fn begin(&mut self) -> Transaction<'_, K, B, P> {
+        self.overlay = Some(Overlay {
+            dirty: HashMap::new(),
+            allocated: Vec::new(),
+            released: Vec::new(),
+        });
+        Transaction {
+            root: self.root,
+            tree: self,
+            checkpoints: Vec::new(),
+        }
+    }
+}
+
+impl<K: PageKey, const B: usize, P: Pager> Transaction<'_, K, B, P> {
+    /// Writes the pages changed by the transaction, and commits them together
+    /// with the new root of the tree.
+    pub fn commit(self) -> Result<()> {
+        let overlay = self.tree.overlay.take().unwrap();
+
+        let mut dirty: Vec<_> = overlay.dirty.into_iter().collect();
+        dirty.sort_unstable_by_key(|&(id, _)| id);
+        for (id, node) in dirty {
+            self.tree.write(id, node.unwrap())?;
+        }
+        for id in overlay.released {
+            self.tree.release(id)?;
+        }
+        self.tree.commit_with_root(self.root)
+    }
+
+    /// Discards the changes made by the transaction, and gives the pages it
+    /// allocated back to the pager.
+    pub fn rollback(mut self) -> Result<()> {
+        self.discard()
+    }
+
+    /// Records the state of the transaction, to roll back to it later.
+    pub fn savepoint(&mut self) -> Result<Savepoint> {
+        let overlay = self.tree.overlay.as_ref().unwrap();
+        let mut dirty = Vec::with_capacity(overlay.dirty.len());
+        for (&id, node) in &overlay.dirty {
+            let node = node.as_ref().unwrap();
+            let mut page = vec![0; node.encoded_size()];
+            encode_page(node, &mut page)?;
+            dirty.push((id, page));
+        }
+
+        let id = SAVEPOINTS.fetch_add(1, Ordering::Relaxed);
+        self.checkpoints.push(Checkpoint {
+            id,
+            root: self.root,
+            dirty,
+            allocated: overlay.allocated.len(),
+            released: overlay.released.len(),
+        });
+        Ok(Savepoint {
+            depth: self.checkpoints.len() - 1,
+            id,
+        })
+    }
+
+    /// Undoes the changes made since the savepoint was taken, and gives the
+    /// pages allocated since then back to the pager.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the savepoint was taken by another transaction, or was
+    /// rolled back past.
+    pub fn rollback_to(&mut self, savepoint: &Savepoint) -> Result<()> {
+        assert!(
+            self.checkpoints
+                .get(savepoint.depth)
+                .is_some_and(|checkpoint| checkpoint.id == savepoint.id),
+            "the savepoint is not part of the transaction"
+        );
+        self.checkpoints.truncate(savepoint.depth + 1);
+        let checkpoint = &self.checkpoints[savepoint.depth];
+
+        let overlay = self.tree.overlay.as_mut().unwrap();
+        let allocated: Vec<_> = overlay.allocated.drain(checkpoint.allocated..).collect();
+        for id in allocated {
+            self.tree.free(id)?;
+        }
+        let overlay = self.tree.overlay.as_mut().unwrap();
+        overlay.released.truncate(checkpoint.released);
+        overlay.dirty.clear();
+        for (id, page) in &checkpoint.dirty {
+            overlay.dirty.insert(*id, Some(decode_page(page)?));
+        }
+        self.root = checkpoint.root;
+        Ok(())
+    }
+
+    fn discard(&mut self) -> Result<()> {
+        let Some(overlay) = self.tree.overlay.take() else {
+            return Ok(());
+        };
+        for id in overlay.allocated {
+            self.tree.free(id)?;
+        }
+        Ok(())
+    }
+}
+
+impl<K: PageKey + Ord, const B: usize, P: Pager> Transaction<'_, K, B, P> {
+    /// Inserts the given key, replacing the key equal to it if there is one.
+    /// Returns the replaced key, or `None` if the key was not in the tree.
+    pub fn replace(&mut self, key: K) -> Result<Option<K>> {
+        self.tree.replace_at(&mut self.root, key)
+    }
+
+    /// Returns the keys within the range, in ascending order.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Result<Vec<&K>> {
+        self.tree.range_at(self.root, range)
+    }
+}
+
+impl<K: PageKey + Ord, const B: usize, P: Pager> BTreeSet for Transaction<'_, K, B, P> {
+    type Key = K;
+    const B: usize = B;
+
+    fn search(&self, key: &Self::Key) -> Result<&Self::Key> {
+        self.tree.search_at(self.root, key)
+    }
+
+    fn insert(&mut self, key: Self::Key) -> Result<()> {
+        self.tree.insert_at(&mut self.root, key)
+    }
+
+    fn remove(&mut self, key: &Self::Key) -> Result<Self::Key> {
+        self.tree.remove_at(&mut self.root, key)
+    }
+}
+
+impl<K: PageKey, const B: usize, P: Pager> Drop for Transaction<'_, K, B, P> {
+    fn drop(&mut self) {
+        let _ = self.discard();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use crate::storage::MemoryPager;
+
+    type MemoryBTreeSet = DiskBTreeSet<u32, 3, MemoryPager>;
+
+    fn keys(tree: &MemoryBTreeSet) -> Vec<u32> {
+        tree.range(..).unwrap().into_iter().copied().collect()
+    }
+
+    #[test]
+    fn test_committed_transaction_is_applied() {
+        let mut tree = MemoryBTreeSet::new();
+        for key in 0..100 {
+            tree.insert(key).unwrap();
+        }
+
+        let mut txn = tree.begin();
+        for key in 100..300 {
+            txn.insert(key).unwrap();
+        }
+        for key in 0..50 {
+            assert_eq!(txn.remove(&key).unwrap(), key);
+        }
+        assert!(txn.search(&10).is_err());
+        assert_eq!(txn.search(&250).unwrap(), &250);
+        assert_eq!(txn.range(..).unwrap().len(), 250);
+        txn.commit().unwrap();
+
+        assert_eq!(keys(&tree), (50..300).collect::<Vec<_>>());
+
+        // The committed pages reached the pager, along with the new root.
+        let tree = MemoryBTreeSet::open(tree.into_pager()).unwrap();
+        assert_eq!(keys(&tree), (50..300).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_rolled_back_transaction_leaves_no_trace() {
+        let mut tree = MemoryBTreeSet::new();
+        for key in (0..200).step_by(2) {
+            tree.insert(key).unwrap();
+        }
+        let before = keys(&tree);
+
+        let mut txn = tree.begin();
+        for key in (1..400).step_by(2) {
+            txn.insert(key).unwrap();
+        }
+        for key in (0..200).step_by(4) {
+            txn.remove(&key).unwrap();
+        }
+        txn.rollback().unwrap();
+        let pages = tree.pager().page_count();
+
+        assert_eq!(keys(&tree), before);
+        for key in (0..200).step_by(2) {
+            assert_eq!(tree.search(&key).unwrap(), &key);
+        }
+
+        // The pages allocated by the transaction are reused afterwards.
+        let mut txn = tree.begin();
+        for key in (1..400).step_by(2) {
+            txn.insert(key).unwrap();
+        }
+        txn.commit().unwrap();
+        assert_eq!(tree.pager().page_count(), pages);
+        assert_eq!(keys(&tree).len(), 300);
+    }
+
+    #[test]
+    fn test_failed_transaction_is_rolled_back_on_drop() {
+        let mut tree = MemoryBTreeSet::new();
+        for key in 0..100 {
+            tree.insert(key).unwrap();
+        }
+
+        let result: Result<()> = (|| {
+            let mut txn = tree.begin();
+            for key in 100..200 {
+                txn.insert(key)?;
+            }
+            txn.remove(&0)?;
+            // The key was removed above, so the transaction fails here.
+            txn.remove(&0)?;
+            txn.commit()
+        })();
+        assert!(matches!(result, Err(Error::KeyNotFound)));
+
+        assert_eq!(keys(&tree), (0..100).collect::<Vec<_>>());
+        assert_eq!(tree.search(&0).unwrap(), &0);
+        assert!(tree.search(&150).is_err());
+
+        // The tree is usable afterwards, inside and outside of transactions.
+        tree.insert(100).unwrap();
+        let mut txn = tree.begin();
+        txn.replace(0).unwrap();
+        txn.remove(&100).unwrap();
+        txn.commit().unwrap();
+        assert_eq!(keys(&tree), (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_rollback_to_savepoint_undoes_later_changes() {
+        let mut tree = MemoryBTreeSet::new();
+        for key in 0..100 {
+            tree.insert(key).unwrap();
+        }
+
+        let mut txn = tree.begin();
+        for key in 100..200 {
+            txn.insert(key).unwrap();
+        }
+        let outer = txn.savepoint().unwrap();
+        for key in 0..150 {
+            txn.remove(&key).unwrap();
+        }
+        let inner = txn.savepoint().unwrap();
+        for key in 200..400 {
+            txn.insert(key).unwrap();
+        }
+
+        txn.rollback_to(&inner).unwrap();
+        assert_eq!(txn.range(..).unwrap().len(), 50);
+        assert!(txn.search(&300).is_err());
+
+        txn.rollback_to(&outer).unwrap();
+        assert_eq!(txn.range(..).unwrap().len(), 200);
+        assert_eq!(txn.search(&10).unwrap(), &10);
+
+        // The outer savepoint stays, and can be rolled back to again.
+        txn.remove(&10).unwrap();
+        txn.rollback_to(&outer).unwrap();
+        assert_eq!(txn.search(&10).unwrap(), &10);
+        txn.insert(1000).unwrap();
+        txn.commit().unwrap();
+
+        let mut expected: Vec<_> = (0..200).collect();
+        expected.push(1000);
+        assert_eq!(keys(&tree), expected);
+        let tree = MemoryBTreeSet::open(tree.into_pager()).unwrap();
+        assert_eq!(keys(&tree), expected);
+    }
+
+    #[test]
+    fn test_pages_allocated_after_savepoint_are_reused() {
+        let mut tree = MemoryBTreeSet::new();
+        let mut txn = tree.begin();
+        for key in 0..50 {
+            txn.insert(key).unwrap();
+        }
+        let savepoint = txn.savepoint().unwrap();
+        for key in 50..500 {
+            txn.insert(key).unwrap();
+        }
+        txn.rollback_to(&savepoint).unwrap();
+        for key in 50..500 {
+            txn.insert(key).unwrap();
+        }
+        txn.commit().unwrap();
+        let pages = tree.pager().page_count();
+
+        let mut other = MemoryBTreeSet::new();
+        for key in 0..500 {
+            other.insert(key).unwrap();
+        }
+        assert_eq!(pages, other.pager().page_count());
+        assert_eq!(keys(&tree), (0..500).collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[should_panic(expected = "not part of the transaction")]
+    fn test_savepoint_rolled_back_past_cannot_be_used() {
+        let mut tree = MemoryBTreeSet::new();
+        let mut txn = tree.begin();
+        let outer = txn.savepoint().unwrap();
+        txn.insert(1).unwrap();
+        let inner = txn.savepoint().unwrap();
+        txn.rollback_to(&outer).unwrap();
+        let _ = txn.rollback_to(&inner);
+    }
+}
@@ -0,0 +1,183 @@
+use std::cmp::Ordering;
+use std::mem;
+use std::ops::{Index, IndexMut};
+
+/// Sorted keys stored in Eytzinger order: the keys are laid out like a
+/// complete binary search tree in breadth-first order, so the root is stored
+/// first, followed by its two children, then its four grandchildren, and so
+/// on. The layout is meant to be compared against plain sorted arrays as the
+/// key storage of a node.
+///
+/// A search descends the implicit tree without any branches that depend on
+/// the keys, and the first levels it visits share a few cache lines, instead
+/// of being spread over the whole array like the probes of a binary search.
+///
+/// Keys are addressed by their rank, their position in sorted order, so the
+/// layout can stand in for a sorted array.
+pub struct Eytzinger<K> {
+    /// The keys in Eytzinger order. The implicit tree is one-based, so the
+    /// key at tree position `p` is stored at `keys[p - 1]`.
+    keys: Vec<K>,
+    /// The rank of the key at each tree position, offset like `keys`.
+    ranks: Vec<usize>,
+    /// The tree position of the key at each rank.
+    positions: Vec<usize>,
+}
+
+impl<K> Eytzinger<K> {
+    /// Lays out keys which are already sorted. The order is not checked, and
+    /// searching keys which were not sorted returns meaningless ranks.
+    pub fn from_sorted(sorted: impl IntoIterator<Item = K>) -> Self {
+        let sorted: Vec<K> = sorted.into_iter().collect();
+        let len = sorted.len();
+
+        // An in-order walk of the implicit tree visits the positions in the
+        // order of the ranks.
+        let mut positions = Vec::with_capacity(len);
+        let mut stack = Vec::new();
+        let mut position = 1;
+        while position <= len || !stack.is_empty() {
+            if position <= len {
+                stack.push(position);
+                position *= 2;
+            } else {
+                let parent = stack.pop().unwrap();
+                positions.push(parent);
+                position = parent * 2 + 1;
+            }
+        }
+
+        let mut ranks = vec![0; len];
+        let mut slots: Vec<Option<K>> = (0..len).map(|_| None).collect();
+        for (rank, key) in sorted.into_iter().enumerate() {
+            ranks[positions[rank] - 1] = rank;
+            slots[positions[rank] - 1] = Some(key);
+        }
+
+        Eytzinger {
+            keys: slots.into_iter().map(Option::unwrap).collect(),
+            ranks,
+            positions,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Returns the number of bytes allocated for the keys and their
+    /// positions, including unused capacity.
+    pub fn heap_size(&self) -> usize {
+        self.keys.capacity() * mem::size_of::<K>()
+            + (self.ranks.capacity() + self.positions.capacity()) * mem::size_of::<usize>()
+    }
+
+    /// Returns the key at the given rank.
+    pub fn get(&self, rank: usize) -> Option<&K> {
+        let position = *self.positions.get(rank)?;
+        Some(&self.keys[position - 1])
+    }
+
+    /// Searches the keys like `slice::binary_search_by`, with `f` comparing a
+    /// key against the target. Returns the rank of the matching key, or the
+    /// rank at which a matching key would be inserted.
+    pub fn search_by(&self, f: impl Fn(&K) -> Ordering) -> Result<usize, usize> {
+        let len = self.keys.len();
+
+        // Descend to the right child past keys less than the target, and to
+        // the left child otherwise. The turn is computed, not branched on.
+        let mut position = 1;
+        while position <= len {
+            position = 2 * position + usize::from(f(&self.keys[position - 1]) == Ordering::Less);
+        }
+
+        // The right turns taken since the last left turn led past the end.
+        // Undoing them, and the left turn, yields the first key which is not
+        // less than the target. A position of zero means there is none.
+        position >>= position.trailing_ones() + 1;
+        if position == 0 {
+            return Err(len);
+        }
+
+        let rank = self.ranks[position - 1];
+        match f(&self.keys[position - 1]) {
+            Ordering::Equal => Ok(rank),
+            _ => Err(rank),
+        }
+    }
+
+    /// Returns an iterator over the keys in sorted order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &K> + ExactSizeIterator {
+        self.positions
+            .iter()
+            .map(|&position| &self.keys[position - 1])
+    }
+
+    /// Returns the keys in sorted order.
+    pub fn into_sorted(self) -> Vec<K> {
+        let mut slots: Vec<Option<K>> = self.keys.into_iter().map(Some).collect();
+        self.positions
+            .iter()
+            .map(|&position| slots[position - 1].take().unwrap())
+            .collect()
+    }
+}
+
+impl<K> Index<usize> for Eytzinger<K> {
+    type Output = K;
+
+    /// Returns the key at the given rank.
+    fn index(&self, rank: usize) -> &K {
+        &self.keys[self.positions[rank] - 1]
+    }
+}
+
+impl<K> IndexMut<usize> for Eytzinger<K> {
+    fn index_mut(&mut self, rank: usize) -> &mut K {
+        &mut self.keys[self.positions[rank] - 1]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_is_breadth_first() {
+        let layout = Eytzinger::from_sorted(0..7);
+        assert_eq!(layout.keys, [3, 1, 5, 0, 2, 4, 6]);
+    }
+
+    #[test]
+    fn test_search_matches_binary_search() {
+        for len in 0..70 {
+            let sorted: Vec<usize> = (0..len).map(|k| k * 2).collect();
+            let layout = Eytzinger::from_sorted(sorted.clone());
+
+            for probe in 0..=len * 2 {
+                assert_eq!(
+                    layout.search_by(|k| k.cmp(&probe)),
+                    sorted.binary_search(&probe),
+                    "{probe} in {len} keys"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_ranks_round_trip() {
+        let sorted: Vec<String> = (0..100).map(|k| format!("{k:03}")).collect();
+        let layout = Eytzinger::from_sorted(sorted.clone());
+
+        for (rank, key) in sorted.iter().enumerate() {
+            assert_eq!(layout.get(rank), Some(key));
+        }
+        assert_eq!(layout.get(100), None);
+        assert!(layout.iter().eq(sorted.iter()));
+        assert_eq!(layout.into_sorted(), sorted);
+    }
+}
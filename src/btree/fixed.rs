@@ -0,0 +1,391 @@
+use super::array::Array;
+use crate::{BTreeSet, Error, Result};
+use core::mem;
+
+/// An in-memory B-tree which never allocates. All nodes live in a pool of
+/// `CAP` nodes stored inline in the tree, and children are referenced by
+/// their index in the pool, so the tree can be placed in a `static`, or on
+/// the stack of a target without a heap.
+///
+/// When the pool cannot hold the nodes an insertion would need, the
+/// insertion fails with `Error::CapacityExceeded`, and the tree is left
+/// unchanged. Removing keys returns their nodes to the pool.
+///
+/// The tree only depends on `core`, and is available without the `std`
+/// feature.
+///
+/// The K type parameter represents the key type, B is the branching factor,
+/// and CAP is the number of nodes in the pool.
+pub struct StaticBTreeSet<K, const B: usize, const CAP: usize> {
+    nodes: [Node<K, B>; CAP],
+    /// A stack of the unused slots of the pool.
+    free: [NodeId; CAP],
+    free_len: usize,
+    root: Option<NodeId>,
+}
+
+/// The index of a node in the pool.
+type NodeId = usize;
+
+/// A node in the pool. Leaf nodes are the ones with no children.
+struct Node<K, const B: usize> {
+    keys: Array<K, B>,
+    children: Array<NodeId, B>,
+}
+
+impl<K, const B: usize> Default for Node<K, B> {
+    fn default() -> Self {
+        Node {
+            keys: Array::new(),
+            children: Array::new(),
+        }
+    }
+}
+
+impl<K, const B: usize> Node<K, B> {
+    const MIN_KEYS: usize = B - 1;
+    const MAX_KEYS: usize = 2 * B - 1;
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.keys.len() == Self::MAX_KEYS
+    }
+}
+
+impl<K: Ord, const B: usize, const CAP: usize> StaticBTreeSet<K, B, CAP> {
+    pub fn new() -> Self {
+        const { assert!(B >= 2, "the branching factor B must be at least 2") };
+        StaticBTreeSet {
+            nodes: core::array::from_fn(|_| Node::default()),
+            // Slots are handed out from the front of the pool first.
+            free: core::array::from_fn(|i| CAP - 1 - i),
+            free_len: CAP,
+            root: None,
+        }
+    }
+
+    /// Returns the number of nodes left in the pool.
+    pub fn free_nodes(&self) -> usize {
+        self.free_len
+    }
+
+    /// Takes a slot off the free stack and places the node in it.
+    ///
+    /// This method assumes that the pool has a free slot, which insertions
+    /// check up front.
+    fn alloc(&mut self, node: Node<K, B>) -> NodeId {
+        self.free_len -= 1;
+        let id = self.free[self.free_len];
+        self.nodes[id] = node;
+        id
+    }
+
+    /// Takes the node out of the pool, and puts its slot on the free stack.
+    fn release(&mut self, id: NodeId) -> Node<K, B> {
+        self.free[self.free_len] = id;
+        self.free_len += 1;
+        mem::take(&mut self.nodes[id])
+    }
+
+    /// Counts the nodes an insertion of the key would allocate: one for every
+    /// full node which splits, starting from the leaf and stopping at the
+    /// first node with room for the hoisted key, and one more for a new root
+    /// if the root splits as well.
+    fn nodes_needed_for(&self, key: &K) -> Result<usize> {
+        let Some(mut id) = self.root else {
+            return Ok(1);
+        };
+
+        let mut needed = if self.nodes[id].is_full() { 2 } else { 0 };
+        loop {
+            let node = &self.nodes[id];
+            match node.keys.binary_search(key) {
+                Ok(_) => return Err(Error::KeyAlreadyExists),
+                Err(_) if node.is_leaf() => return Ok(needed),
+                Err(idx) => {
+                    id = node.children[idx];
+                    needed = if self.nodes[id].is_full() {
+                        needed + 1
+                    } else {
+                        0
+                    };
+                }
+            }
+        }
+    }
+
+    /// Inserts the key into the subtree, returning the hoisted key and the new
+    /// sibling when the node had to be split.
+    fn insert_into(&mut self, id: NodeId, key: K) -> Option<(K, NodeId)> {
+        let node = &self.nodes[id];
+        let idx = node.keys.binary_search(&key).unwrap_err();
+
+        if node.is_leaf() {
+            self.nodes[id].keys.insert(idx, key);
+        } else if let Some((hoist, sibling)) = self.insert_into(node.children[idx], key) {
+            let node = &mut self.nodes[id];
+            node.keys.insert(idx, hoist);
+            node.children.insert(idx + 1, sibling);
+        }
+
+        if self.nodes[id].keys.len() > Node::<K, B>::MAX_KEYS {
+            Some(self.split(id))
+        } else {
+            None
+        }
+    }
+
+    /// Splits the overflowed node, returning the hoisted key and the new sibling.
+    fn split(&mut self, id: NodeId) -> (K, NodeId) {
+        let node = &mut self.nodes[id];
+        let keys = node.keys.split_off(B);
+        let hoist = node.keys.pop_back().unwrap();
+        let children = if node.is_leaf() {
+            Array::new()
+        } else {
+            node.children.split_off(B)
+        };
+
+        (hoist, self.alloc(Node { keys, children }))
+    }
+
+    /// Removes the key from the subtree, leaving the node at `id` possibly
+    /// deficient, but all of its descendants valid.
+    fn remove_from(&mut self, id: NodeId, key: &K) -> Option<K> {
+        let node = &self.nodes[id];
+        let result = node.keys.binary_search(key);
+
+        if node.is_leaf() {
+            return result
+                .ok()
+                .map(|idx| self.nodes[id].keys.remove(idx).unwrap());
+        }
+
+        let (removed, idx) = match result {
+            Ok(idx) => {
+                let predecessor = self.remove_last(node.children[idx]);
+                (
+                    mem::replace(&mut self.nodes[id].keys[idx], predecessor),
+                    idx,
+                )
+            }
+            Err(idx) => (self.remove_from(node.children[idx], key)?, idx),
+        };
+
+        self.fix_deficient_child(id, idx);
+        Some(removed)
+    }
+
+    /// Removes the greatest key of the subtree.
+    fn remove_last(&mut self, id: NodeId) -> K {
+        let node = &self.nodes[id];
+        if node.is_leaf() {
+            return self.nodes[id].keys.pop_back().unwrap();
+        }
+
+        let idx = node.children.len() - 1;
+        let key = self.remove_last(node.children[idx]);
+        self.fix_deficient_child(id, idx);
+        key
+    }
+
+    /// Refills the child at the given index if it became deficient, either by
+    /// rotating a key from one of its siblings, or by merging it with one.
+    fn fix_deficient_child(&mut self, id: NodeId, idx: usize) {
+        let children = &self.nodes[id].children;
+        let child = children[idx];
+        if self.nodes[child].keys.len() >= Node::<K, B>::MIN_KEYS {
+            return;
+        }
+
+        let left = idx.checked_sub(1).map(|i| children[i]);
+        let right = children.get(idx + 1).copied();
+        let can_spare = |sibling: NodeId| self.nodes[sibling].keys.len() > Node::<K, B>::MIN_KEYS;
+
+        match (left, right) {
+            (Some(left), _) if can_spare(left) => self.rotate_right(id, idx, left, child),
+            (_, Some(right)) if can_spare(right) => self.rotate_left(id, idx, child, right),
+            (Some(_), _) => self.merge(id, idx - 1),
+            (None, Some(_)) => self.merge(id, idx),
+            (None, None) => unreachable!("intermediate nodes have at least two children"),
+        }
+    }
+
+    /// Moves the last key of the left sibling up into the parent, and the
+    /// separating parent key down into the child.
+    fn rotate_right(&mut self, id: NodeId, idx: usize, left: NodeId, child: NodeId) {
+        let key = self.nodes[left].keys.pop_back().unwrap();
+        let grandchild = self.nodes[left].children.pop_back();
+        let separator = mem::replace(&mut self.nodes[id].keys[idx - 1], key);
+
+        let child = &mut self.nodes[child];
+        child.keys.push_front(separator);
+        if let Some(grandchild) = grandchild {
+            child.children.push_front(grandchild);
+        }
+    }
+
+    /// Moves the first key of the right sibling up into the parent, and the
+    /// separating parent key down into the child.
+    fn rotate_left(&mut self, id: NodeId, idx: usize, child: NodeId, right: NodeId) {
+        let key = self.nodes[right].keys.pop_front().unwrap();
+        let grandchild = self.nodes[right].children.pop_front();
+        let separator = mem::replace(&mut self.nodes[id].keys[idx], key);
+
+        let child = &mut self.nodes[child];
+        child.keys.push_back(separator);
+        if let Some(grandchild) = grandchild {
+            child.children.push_back(grandchild);
+        }
+    }
+
+    /// Merges the child after the given index into the child at it, together
+    /// with the separating parent key.
+    fn merge(&mut self, id: NodeId, idx: usize) {
+        let separator = self.nodes[id].keys.remove(idx).unwrap();
+        let right = self.nodes[id].children.remove(idx + 1).unwrap();
+        let left = self.nodes[id].children[idx];
+        let right = self.release(right);
+
+        let left = &mut self.nodes[left];
+        left.keys.push_back(separator);
+        left.keys.extend(right.keys);
+        left.children.extend(right.children);
+    }
+}
+
+impl<K: Ord, const B: usize, const CAP: usize> Default for StaticBTreeSet<K, B, CAP> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord, const B: usize, const CAP: usize> BTreeSet for StaticBTreeSet<K, B, CAP> {
+    type Key = K;
+    const B: usize = B;
+
+    fn search(&self, key: &Self::Key) -> Result<&Self::Key> {
+        let mut id = self.root.ok_or(Error::KeyNotFound)?;
+        loop {
+            let node = &self.nodes[id];
+            match node.keys.binary_search(key) {
+                Ok(idx) => return Ok(&node.keys[idx]),
+                Err(_) if node.is_leaf() => return Err(Error::KeyNotFound),
+                Err(idx) => id = node.children[idx],
+            }
+        }
+    }
+
+    fn insert(&mut self, key: Self::Key) -> Result<()> {
+        if self.nodes_needed_for(&key)? > self.free_len {
+            return Err(Error::CapacityExceeded);
+        }
+
+        let Some(root) = self.root else {
+            let mut keys = Array::new();
+            keys.push_back(key);
+            self.root = Some(self.alloc(Node {
+                keys,
+                children: Array::new(),
+            }));
+            return Ok(());
+        };
+
+        if let Some((hoist, sibling)) = self.insert_into(root, key) {
+            let mut node = Node::default();
+            node.keys.push_back(hoist);
+            node.children.extend([root, sibling]);
+            self.root = Some(self.alloc(node));
+        }
+
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &Self::Key) -> Result<Self::Key> {
+        let root = self.root.ok_or(Error::KeyNotFound)?;
+        let removed = self.remove_from(root, key).ok_or(Error::KeyNotFound)?;
+
+        let node = &self.nodes[root];
+        if node.keys.is_empty() {
+            self.root = node.children.first().copied();
+            self.release(root);
+        }
+
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::{DifferentialTester, ReferenceBTreeSet};
+    use crate::test_btree_impl;
+
+    /// A pool large enough for every test of the shared suite.
+    type LargeStaticBTreeSet<K> = StaticBTreeSet<K, 3, 1024>;
+
+    test_btree_impl!(LargeStaticBTreeSet);
+
+    #[test]
+    fn test_full_pool_rejects_insertions_without_changing_the_tree() {
+        let mut tree = StaticBTreeSet::<usize, 2, 4>::new();
+
+        let mut inserted = 0;
+        while tree.insert(inserted).is_ok() {
+            inserted += 1;
+        }
+
+        // Four nodes of at most three keys each: a root with three children,
+        // whose last leaf had to split.
+        assert!(matches!(
+            tree.insert(inserted),
+            Err(Error::CapacityExceeded)
+        ));
+        assert_eq!(tree.free_nodes(), 0);
+        for key in 0..inserted {
+            assert!(tree.contains(&key), "tree lost {key}");
+        }
+        assert!(!tree.contains(&inserted));
+
+        // Duplicates are still reported as such.
+        assert!(matches!(tree.insert(0), Err(Error::KeyAlreadyExists)));
+    }
+
+    #[test]
+    fn test_removals_return_nodes_to_the_pool() {
+        let mut tree = StaticBTreeSet::<usize, 2, 64>::new();
+
+        for _ in 0..5 {
+            let mut inserted = 0;
+            while tree.insert(inserted).is_ok() {
+                inserted += 1;
+            }
+            assert!(inserted > 64);
+
+            for key in 0..inserted {
+                tree.remove(&key).unwrap();
+            }
+            assert!(tree.root.is_none());
+            assert_eq!(tree.free_nodes(), 64);
+        }
+    }
+
+    #[test]
+    fn test_agrees_with_reference_on_mixed_operations() {
+        let mut tester = DifferentialTester::new(
+            StaticBTreeSet::<usize, 2, 512>::new(),
+            ReferenceBTreeSet::new(),
+        );
+
+        for i in 0..20_000usize {
+            let key = (i * 7919) % 503;
+            let _ = match i % 3 {
+                0 => tester.remove(&key).map(|_| ()),
+                _ => tester.insert(key),
+            };
+        }
+    }
+}
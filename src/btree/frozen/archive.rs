@@ -0,0 +1,154 @@
+use super::{ArchivedFrozenBTreeSet, FrozenBTreeSet, Iter, Walk, contains_by, range_by};
+use crate::{Error, Result};
+use rkyv::api::high::{HighSerializer, HighValidator};
+use rkyv::bytecheck::CheckBytes;
+use rkyv::rancor;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::{Archive, Serialize};
+use std::ops::RangeBounds;
+
+fn invalid(err: rancor::Error) -> Error {
+    Error::InvalidArchive {
+        reason: err.to_string(),
+    }
+}
+
+impl<K: Ord, const B: usize> FrozenBTreeSet<K, B> {
+    /// Archives the tree. The bytes can be written out, and searched later by
+    /// `ArchivedFrozenBTreeSet::from_bytes`, from memory or a mapped file.
+    pub fn to_bytes(&self) -> Result<AlignedVec>
+    where
+        K: for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rancor::Error>>,
+    {
+        rkyv::to_bytes::<rancor::Error>(self).map_err(invalid)
+    }
+}
+
+/// The archived keys keep the implicit layout of the tree, so they are
+/// searched just like the keys of a `FrozenBTreeSet`. Archived keys are
+/// compared against keys of any type they can be ordered with, such as
+/// `u64` for an archived `u64`, or `str` for an archived `String`.
+impl<K: Archive, const B: usize> ArchivedFrozenBTreeSet<K, B> {
+    /// Checks that the bytes hold an archived tree, and returns it, borrowing
+    /// the bytes. The bytes must be aligned like the ones of `to_bytes`,
+    /// which a memory-mapped file is.
+    pub fn from_bytes(bytes: &[u8]) -> Result<&Self>
+    where
+        K::Archived: for<'a> CheckBytes<HighValidator<'a, rancor::Error>>,
+    {
+        rkyv::access::<Self, rancor::Error>(bytes).map_err(invalid)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Returns whether the tree contains the key.
+    pub fn contains<Q: ?Sized>(&self, key: &Q) -> bool
+    where
+        K::Archived: PartialOrd<Q>,
+    {
+        contains_by::<K::Archived, B>(&self.keys, |k| k.lt(key), |k| k.eq(key))
+    }
+
+    /// Returns an iterator over the archived keys, in ascending order.
+    pub fn iter(&self) -> Iter<'_, K::Archived, B> {
+        Iter::new(&self.keys, Walk::first(self.keys.len()), None)
+    }
+
+    /// Returns an iterator over the archived keys within the range, in
+    /// ascending order.
+    pub fn range<Q: ?Sized, R: RangeBounds<Q>>(&self, range: R) -> Iter<'_, K::Archived, B>
+    where
+        K::Archived: PartialOrd<Q>,
+    {
+        range_by(&self.keys, range.start_bound(), range.end_bound())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::SimpleBTreeSet;
+    use std::ops::Bound;
+
+    fn frozen<K: Ord>(keys: impl IntoIterator<Item = K>) -> FrozenBTreeSet<K> {
+        let mut tree = SimpleBTreeSet::<K>::new();
+        for key in keys {
+            tree.replace(key);
+        }
+        tree.freeze()
+    }
+
+    #[test]
+    fn test_archived_tree_matches_the_tree() {
+        let tree = frozen((0..1000u64).map(|k| k * 3));
+        let bytes = tree.to_bytes().unwrap();
+        let archived = ArchivedFrozenBTreeSet::<u64>::from_bytes(&bytes).unwrap();
+
+        assert_eq!(archived.len(), 1000);
+        for probe in 0..3000 {
+            assert_eq!(archived.contains(&probe), tree.contains(&probe), "{probe}");
+        }
+        assert!(
+            archived
+                .iter()
+                .map(|k| k.to_native())
+                .eq(tree.iter().copied())
+        );
+        assert!(
+            archived
+                .range(100..=200u64)
+                .map(|k| k.to_native())
+                .eq(tree.range(100..=200).copied())
+        );
+        let reversed = (Bound::Included(200u64), Bound::Excluded(100u64));
+        assert_eq!(archived.range(reversed).count(), 0);
+
+        let strings = frozen((0..100).map(|k| format!("{k:02}")));
+        let bytes = strings.to_bytes().unwrap();
+        let archived = ArchivedFrozenBTreeSet::<String>::from_bytes(&bytes).unwrap();
+        assert!(archived.contains("42"));
+        assert!(!archived.contains("420"));
+        assert!(
+            archived
+                .range("10".."13")
+                .map(|k| k.as_str())
+                .eq(["10", "11", "12"])
+        );
+    }
+
+    #[test]
+    fn test_invalid_bytes_are_rejected() {
+        let bytes = frozen(0..100u64).to_bytes().unwrap();
+        let mut truncated = AlignedVec::<16>::new();
+        truncated.extend_from_slice(&bytes[..bytes.len() - 8]);
+        assert!(matches!(
+            ArchivedFrozenBTreeSet::<u64>::from_bytes(&truncated),
+            Err(Error::InvalidArchive { .. })
+        ));
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_archive_is_searched_from_a_mapped_file() {
+        let path = crate::tests::temp_path("frozen");
+        let tree = frozen((0..10_000u64).map(|k| k * 2));
+        std::fs::write(&path, tree.to_bytes().unwrap()).unwrap();
+
+        let file = std::fs::File::open(&path).unwrap();
+        let map = unsafe { memmap2::Mmap::map(&file) }.unwrap();
+        let archived = ArchivedFrozenBTreeSet::<u64>::from_bytes(&map).unwrap();
+        assert!(archived.contains(&19_998));
+        assert!(!archived.contains(&19_999));
+        assert_eq!(archived.range(..100u64).count(), 50);
+
+        drop(map);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
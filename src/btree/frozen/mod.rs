@@ -0,0 +1,402 @@
+use super::alloc::Allocator;
+use super::compare::OrdComparator;
+use super::layout::Layout;
+use super::prefix::Prefixed;
+use super::simple::SimpleBTreeSet;
+use std::borrow::Borrow;
+use std::iter::FusedIterator;
+use std::ops::{Bound, RangeBounds};
+
+#[cfg(feature = "rkyv")]
+mod archive;
+
+/// An immutable B-tree, which keeps its keys in a single array and has no
+/// child pointers at all. It is made by freezing a `SimpleBTreeSet` once the
+/// keys stop changing, and thawed back into one to change them again.
+///
+/// The array is cut into blocks of `B` keys, and the blocks form an implicit
+/// tree: the children of block `k` are the blocks `k * (B + 1) + 1` through
+/// `k * (B + 1) + B + 1`, so the root is stored first, followed by its
+/// children, then its grandchildren, and so on, like in `Eytzinger` but with
+/// `B + 1` children per node. Only the last block may be partially filled.
+///
+/// Without pointers, lengths and the slack of half-full nodes, the keys take
+/// no more memory than a sorted array, and a search visits one block per
+/// level, each of which spans a couple of cache lines.
+///
+/// With the `rkyv` feature, the tree can be archived into bytes, which an
+/// `ArchivedFrozenBTreeSet` searches in place, without deserializing them.
+///
+/// The K type parameter represents the key type, and B is the number of keys
+/// in a block.
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
+pub struct FrozenBTreeSet<K, const B: usize = 16> {
+    keys: Box<[K]>,
+}
+
+/// Returns the block which holds the keys between the `i`th key of the block
+/// and the one before it.
+fn child<const B: usize>(block: usize, i: usize) -> usize {
+    block * (B + 1) + 1 + i
+}
+
+impl<K: Ord, const B: usize> FrozenBTreeSet<K, B> {
+    /// Lays out keys which are already sorted and distinct. The order is not
+    /// checked, and searching keys which were not sorted gives meaningless
+    /// results.
+    fn from_sorted(sorted: Vec<K>) -> Self {
+        const { assert!(B >= 1, "a block must hold at least one key") };
+        let len = sorted.len();
+
+        // An in-order walk of the implicit tree visits the slots in the order
+        // of the keys.
+        let mut slots: Vec<Option<K>> = (0..len).map(|_| None).collect();
+        for (slot, key) in Walk::<B>::first(len).zip(sorted) {
+            slots[slot] = Some(key);
+        }
+
+        FrozenBTreeSet {
+            keys: slots.into_iter().map(Option::unwrap).collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Returns whether the tree contains the key. Like `search` of
+    /// `SimpleBTreeSet`, the key may be a borrowed form of the key type.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        contains_by::<K, B>(&self.keys, |k| k.borrow() < key, |k| k.borrow() == key)
+    }
+
+    /// Returns an iterator over the keys of the tree, in ascending order.
+    pub fn iter(&self) -> Iter<'_, K, B> {
+        Iter::new(&self.keys, Walk::first(self.keys.len()), None)
+    }
+
+    /// Returns an iterator over the keys of the tree within the range, in
+    /// ascending order.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Iter<'_, K, B> {
+        range_by(&self.keys, range.start_bound(), range.end_bound())
+    }
+
+    /// Returns an iterator over the keys of the tree which start with the
+    /// prefix, in ascending order.
+    pub fn prefix_iter(&self, prefix: &K::Prefix) -> Iter<'_, K, B>
+    where
+        K: Prefixed,
+    {
+        self.range(K::prefix_range(prefix))
+    }
+
+    /// Converts the tree back into a `SimpleBTreeSet`, whose keys can be
+    /// changed again.
+    pub fn thaw<const T: usize>(self) -> SimpleBTreeSet<K, T> {
+        let len = self.keys.len();
+        let mut slots: Vec<Option<K>> = self.keys.into_vec().into_iter().map(Some).collect();
+        let mut tree = SimpleBTreeSet::new();
+        for slot in Walk::<B>::first(len) {
+            tree.replace(slots[slot].take().unwrap());
+        }
+        tree
+    }
+}
+
+/// Returns the keys of the block, which are fewer than `B` in the last one.
+fn block<T, const B: usize>(keys: &[T], block: usize) -> &[T] {
+    let start = block * B;
+    &keys[start..keys.len().min(start + B)]
+}
+
+/// Searches keys in the implicit layout, with `before` telling whether a key
+/// is less than the target, and `is` whether it matches it. The layout is
+/// searched the same way whether the keys belong to a `FrozenBTreeSet` or to
+/// its archived form.
+fn contains_by<T, const B: usize>(
+    keys: &[T],
+    before: impl Fn(&T) -> bool,
+    is: impl Fn(&T) -> bool,
+) -> bool {
+    let mut id = 0;
+    while id * B < keys.len() {
+        let block = block::<T, B>(keys, id);
+        // Counting the smaller keys does not branch on them, unlike a binary
+        // search, and the block is short enough to scan.
+        let i = block.iter().filter(|k| before(k)).count();
+        if block.get(i).is_some_and(&is) {
+            return true;
+        }
+        id = child::<B>(id, i);
+    }
+    false
+}
+
+/// Starts a walk at the first key for which `before` returns false.
+fn seek<T, const B: usize>(keys: &[T], before: impl Fn(&T) -> bool) -> Walk<B> {
+    let mut walk = Walk::empty(keys.len());
+    let mut id = 0;
+    while id * B < keys.len() {
+        let i = block::<T, B>(keys, id).iter().filter(|k| before(k)).count();
+        walk.stack.push((id, i));
+        id = child::<B>(id, i);
+    }
+    walk
+}
+
+/// Returns an iterator over the keys in the implicit layout within the
+/// bounds, which may be given in any type the keys can be compared with.
+fn range_by<'a, T, Q, const B: usize>(
+    keys: &'a [T],
+    start: Bound<&Q>,
+    end: Bound<&Q>,
+) -> Iter<'a, T, B>
+where
+    T: PartialOrd<Q>,
+    Q: ?Sized,
+{
+    // An unbounded start is before every key, and an unbounded end is after
+    // every key, so both seek to the ends of the layout.
+    let before_start = |k: &T| match start {
+        Bound::Included(q) => k.lt(q),
+        Bound::Excluded(q) => k.le(q),
+        Bound::Unbounded => false,
+    };
+    let before_end = |k: &T| match end {
+        Bound::Included(q) => k.le(q),
+        Bound::Excluded(q) => k.lt(q),
+        Bound::Unbounded => true,
+    };
+
+    // The walk only stops early at the first key past the range, which it
+    // never reaches when the range starts after it.
+    let walk = seek(keys, before_start);
+    match walk.peek() {
+        Some(first) if before_end(&keys[first]) => {
+            Iter::new(keys, walk, seek::<T, B>(keys, before_end).peek())
+        }
+        _ => Iter::new(keys, Walk::empty(keys.len()), None),
+    }
+}
+
+impl<K: Ord, const B: usize, A: Allocator + Clone, L: Layout>
+    SimpleBTreeSet<K, B, A, OrdComparator, L>
+{
+    /// Converts the tree into a `FrozenBTreeSet`, which cannot be changed, but
+    /// takes less memory and is faster to search.
+    pub fn freeze(self) -> FrozenBTreeSet<K> {
+        FrozenBTreeSet::from_sorted(self.into_iter().collect())
+    }
+}
+
+/// An in-order walk over the slots of the implicit tree. The stack holds a
+/// block of every level above the walk, each with the index of the next key
+/// of the block to visit, once the subtree left of it is done.
+struct Walk<const B: usize> {
+    len: usize,
+    stack: Vec<(usize, usize)>,
+}
+
+impl<const B: usize> Walk<B> {
+    fn empty(len: usize) -> Self {
+        Walk {
+            len,
+            stack: Vec::new(),
+        }
+    }
+
+    /// Starts a walk at the smallest key.
+    fn first(len: usize) -> Self {
+        let mut walk = Walk::empty(len);
+        walk.descend(0);
+        walk
+    }
+
+    /// Pushes the leftmost path of the subtree rooted at the block.
+    fn descend(&mut self, mut block: usize) {
+        while block * B < self.len {
+            self.stack.push((block, 0));
+            block = child::<B>(block, 0);
+        }
+    }
+
+    /// Returns the slot of the `i`th key of the block, if the block has one.
+    fn slot(&self, block: usize, i: usize) -> Option<usize> {
+        let slot = block * B + i;
+        (i < B && slot < self.len).then_some(slot)
+    }
+
+    /// Returns the slot the walk visits next, without moving past it. The
+    /// blocks at the top of the stack which have no keys left are skipped.
+    fn peek(&self) -> Option<usize> {
+        self.stack
+            .iter()
+            .rev()
+            .find_map(|&(block, i)| self.slot(block, i))
+    }
+}
+
+impl<const B: usize> Iterator for Walk<B> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        loop {
+            let (block, i) = self.stack.last_mut()?;
+            let (block, key) = (*block, *i);
+            match self.slot(block, key) {
+                Some(slot) => {
+                    self.stack.last_mut().unwrap().1 += 1;
+                    self.descend(child::<B>(block, key + 1));
+                    return Some(slot);
+                }
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+/// An iterator over the keys of a `FrozenBTreeSet`, in ascending order.
+pub struct Iter<'a, K, const B: usize> {
+    keys: &'a [K],
+    walk: Walk<B>,
+    /// The slot of the first key past the range, where the walk stops.
+    end: Option<usize>,
+}
+
+impl<'a, K, const B: usize> Iter<'a, K, B> {
+    fn new(keys: &'a [K], walk: Walk<B>, end: Option<usize>) -> Self {
+        Iter { keys, walk, end }
+    }
+}
+
+impl<'a, K, const B: usize> Iterator for Iter<'a, K, B> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        let slot = self.walk.next()?;
+        if Some(slot) == self.end {
+            self.walk.stack.clear();
+            return None;
+        }
+        Some(&self.keys[slot])
+    }
+}
+
+impl<K, const B: usize> FusedIterator for Iter<'_, K, B> {}
+
+impl<'a, K: Ord, const B: usize> IntoIterator for &'a FrozenBTreeSet<K, B> {
+    type Item = &'a K;
+    type IntoIter = Iter<'a, K, B>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet as StdBTreeSet;
+
+    fn frozen<const B: usize>(keys: impl IntoIterator<Item = usize>) -> FrozenBTreeSet<usize, B> {
+        let mut tree = SimpleBTreeSet::<usize>::new();
+        for key in keys {
+            tree.replace(key);
+        }
+        FrozenBTreeSet::from_sorted(tree.into_iter().collect())
+    }
+
+    #[test]
+    fn test_layout_is_breadth_first() {
+        let tree = frozen::<2>(0..8);
+        // The root holds the keys splitting the others into three subtrees,
+        // which are stored right after it.
+        assert_eq!(&*tree.keys, [2, 5, 0, 1, 3, 4, 6, 7]);
+    }
+
+    #[test]
+    fn test_search_matches_std() {
+        for len in 0..70 {
+            let keys: StdBTreeSet<usize> = (0..len).map(|k| k * 2).collect();
+            let tree = frozen::<3>(keys.iter().copied());
+
+            assert_eq!(tree.len(), len);
+            assert!(tree.iter().eq(keys.iter()), "{len} keys");
+            for probe in 0..=len * 2 {
+                assert_eq!(
+                    tree.contains(&probe),
+                    keys.contains(&probe),
+                    "{probe} in {len} keys"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_range_matches_std() {
+        let keys: StdBTreeSet<usize> = (0..50).map(|k| k * 2).collect();
+        let tree = frozen::<4>(keys.iter().copied());
+
+        for start in 0..=100 {
+            for end in start..=101 {
+                assert!(
+                    tree.range(start..end).eq(keys.range(start..end)),
+                    "{start}..{end}"
+                );
+                assert!(
+                    tree.range(start..=end).eq(keys.range(start..=end)),
+                    "{start}..={end}"
+                );
+                let excluded = (Bound::Excluded(start), Bound::Included(end));
+                assert!(
+                    tree.range(excluded).eq(keys.range(excluded)),
+                    "{excluded:?}"
+                );
+            }
+            assert!(tree.range(start..).eq(keys.range(start..)), "{start}..");
+            assert!(tree.range(..start).eq(keys.range(..start)), "..{start}");
+        }
+        assert_eq!(
+            tree.range((Bound::Included(60), Bound::Excluded(20)))
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_freeze_and_thaw_round_trip() {
+        let mut tree = SimpleBTreeSet::<String>::new();
+        for key in (0..500).rev() {
+            tree.replace(format!("{key:03}"));
+        }
+
+        let frozen = tree.freeze();
+        assert_eq!(frozen.len(), 500);
+        assert!(frozen.contains("042"));
+        assert!(!frozen.contains("42"));
+        assert!(
+            frozen
+                .iter()
+                .cloned()
+                .eq((0..500).map(|k| format!("{k:03}")))
+        );
+
+        let mut thawed: SimpleBTreeSet<String> = frozen.thaw();
+        assert!(thawed.remove("042").is_ok());
+        assert_eq!(thawed.iter().count(), 499);
+        assert!(thawed.freeze().iter().all(|key| key != "042"));
+    }
+}
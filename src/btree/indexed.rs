@@ -0,0 +1,197 @@
+use super::map::SimpleBTreeMap;
+use super::simple::SimpleBTreeSet;
+use crate::{BTreeMap, Error, Result};
+use std::collections::HashMap;
+
+/// A secondary index, which maps the index key of every value to the keys of
+/// the entries holding such a value.
+struct Index<K, V, I, const B: usize> {
+    key_of: Box<dyn Fn(&V) -> I>,
+    entries: SimpleBTreeMap<I, SimpleBTreeSet<K, B>, B>,
+}
+
+impl<K: Ord, V, I: Ord, const B: usize> Index<K, V, I, B> {
+    fn insert(&mut self, key: K, value: &V) {
+        let keys = self.entries.entry((self.key_of)(value)).or_default();
+        keys.replace(key);
+    }
+
+    fn remove(&mut self, key: &K, value: &V) {
+        let index_key = (self.key_of)(value);
+        let Ok(keys) = self.entries.get_mut(&index_key) else {
+            return;
+        };
+        let _ = keys.remove(key);
+        if keys.iter().next().is_none() {
+            let _ = self.entries.remove(&index_key);
+        }
+    }
+}
+
+/// A `SimpleBTreeMap` with named secondary indexes, which are kept in sync
+/// with the map on every insert and remove.
+///
+/// An index is a function from a value to an index key, and many entries may
+/// share an index key. All indexes of a map use the same index key type `I`.
+/// Values cannot be modified in place, since that would bypass the indexes;
+/// remove and insert the entry again instead.
+pub struct IndexedBTreeMap<K, V, I, const B: usize = 6> {
+    map: SimpleBTreeMap<K, V, B>,
+    indexes: HashMap<String, Index<K, V, I, B>>,
+}
+
+impl<K: Ord + Clone, V, I: Ord, const B: usize> IndexedBTreeMap<K, V, I, B> {
+    pub fn new() -> Self {
+        IndexedBTreeMap {
+            map: SimpleBTreeMap::new(),
+            indexes: HashMap::new(),
+        }
+    }
+
+    /// Registers an index under the given name, and indexes every entry
+    /// already in the map.
+    pub fn add_index(
+        &mut self,
+        name: impl Into<String>,
+        key_of: impl Fn(&V) -> I + 'static,
+    ) -> Result<()> {
+        let name = name.into();
+        if self.indexes.contains_key(&name) {
+            return Err(Error::IndexAlreadyExists);
+        }
+
+        let mut index = Index {
+            key_of: Box::new(key_of),
+            entries: SimpleBTreeMap::new(),
+        };
+        for (key, value) in self.map.pairs() {
+            index.insert(key.clone(), value);
+        }
+        self.indexes.insert(name, index);
+        Ok(())
+    }
+
+    /// Drops the index registered under the given name.
+    pub fn remove_index(&mut self, name: &str) -> Result<()> {
+        self.indexes
+            .remove(name)
+            .map(drop)
+            .ok_or(Error::IndexNotFound)
+    }
+
+    pub fn get(&self, key: &K) -> Result<&V> {
+        self.map.get(key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.map.contains_key(key)
+    }
+
+    /// Inserts the entry into the map and into every index. Nothing changes
+    /// if the key already exists.
+    pub fn insert(&mut self, key: K, value: V) -> Result<()> {
+        if self.map.contains_key(&key) {
+            return Err(Error::KeyAlreadyExists);
+        }
+        for index in self.indexes.values_mut() {
+            index.insert(key.clone(), &value);
+        }
+        self.map.insert(key, value)
+    }
+
+    /// Removes the entry from the map and from every index.
+    pub fn remove(&mut self, key: &K) -> Result<V> {
+        let value = self.map.remove(key)?;
+        for index in self.indexes.values_mut() {
+            index.remove(key, &value);
+        }
+        Ok(value)
+    }
+
+    /// Returns the entries whose value has the given key in the named index,
+    /// in ascending order of their keys.
+    pub fn get_by_index<'a>(
+        &'a self,
+        name: &str,
+        index_key: &I,
+    ) -> Result<impl Iterator<Item = (&'a K, &'a V)>> {
+        let index = self.indexes.get(name).ok_or(Error::IndexNotFound)?;
+        let keys = index.entries.get(index_key).ok();
+        Ok(keys
+            .into_iter()
+            .flat_map(|keys| keys.iter())
+            .map(|key| (key, self.map.get(key).expect("indexed key is in the map"))))
+    }
+}
+
+impl<K: Ord + Clone, V, I: Ord, const B: usize> Default for IndexedBTreeMap<K, V, I, B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct User {
+        city: &'static str,
+        age: u32,
+    }
+
+    fn user(city: &'static str, age: u32) -> User {
+        User { city, age }
+    }
+
+    fn ids<'a>(entries: impl Iterator<Item = (&'a u32, &'a User)>) -> Vec<u32> {
+        entries.map(|(id, _)| *id).collect()
+    }
+
+    #[test]
+    fn test_indexes_follow_inserts_and_removes() {
+        let mut users = IndexedBTreeMap::<u32, User, String, 2>::new();
+        users
+            .add_index("city", |u: &User| u.city.to_string())
+            .unwrap();
+
+        for (id, city) in (0..50).map(|id| (id, ["Ankara", "Izmir"][id as usize % 2])) {
+            users.insert(id, user(city, 30)).unwrap();
+        }
+        assert!(matches!(
+            users.insert(4, user("Bursa", 30)),
+            Err(Error::KeyAlreadyExists)
+        ));
+        for id in (0..50).filter(|id| id % 4 == 0) {
+            users.remove(&id).unwrap();
+        }
+
+        let izmir = ids(users.get_by_index("city", &"Izmir".to_string()).unwrap());
+        assert_eq!(izmir, (1..50).step_by(2).collect::<Vec<_>>());
+        let ankara = ids(users.get_by_index("city", &"Ankara".to_string()).unwrap());
+        assert_eq!(ankara, (2..50).step_by(4).collect::<Vec<_>>());
+        let bursa = ids(users.get_by_index("city", &"Bursa".to_string()).unwrap());
+        assert!(bursa.is_empty());
+    }
+
+    #[test]
+    fn test_added_index_covers_existing_entries() {
+        let mut users = IndexedBTreeMap::<u32, User, u32>::new();
+        users.insert(1, user("Ankara", 30)).unwrap();
+        users.insert(2, user("Izmir", 40)).unwrap();
+        users.insert(3, user("Ankara", 30)).unwrap();
+
+        users.add_index("age", |u: &User| u.age).unwrap();
+        assert!(matches!(
+            users.add_index("age", |u: &User| u.age),
+            Err(Error::IndexAlreadyExists)
+        ));
+        assert_eq!(ids(users.get_by_index("age", &30).unwrap()), [1, 3]);
+
+        users.remove_index("age").unwrap();
+        assert!(matches!(
+            users.get_by_index("age", &30),
+            Err(Error::IndexNotFound)
+        ));
+        assert_eq!(users.get(&2).unwrap().city, "Izmir");
+    }
+}
@@ -0,0 +1,195 @@
+use super::augment::{Augment, AugmentedBTreeSet, Node};
+use std::iter::FusedIterator;
+use std::ops::{Bound, Range};
+
+/// Keeps the greatest end point of the intervals, or `None` for no intervals.
+pub struct MaxEnd;
+
+impl<T: Ord + Clone> Augment<(T, T)> for MaxEnd {
+    type Value = Option<T>;
+
+    fn identity() -> Option<T> {
+        None
+    }
+
+    fn summarize((_, end): &(T, T)) -> Option<T> {
+        Some(end.clone())
+    }
+
+    fn combine(left: &Option<T>, right: &Option<T>) -> Option<T> {
+        left.as_ref().max(right.as_ref()).cloned()
+    }
+}
+
+/// A set of half-open intervals, stored as `(start, end)` pairs ordered by
+/// their start. Every node knows the greatest end point in its subtree, so
+/// queries skip the subtrees whose intervals all end too early.
+///
+/// Intervals whose end is not after their start are empty, and are never
+/// reported by the queries.
+///
+/// The T type parameter represents the end point type, and B is the
+/// branching factor.
+pub type IntervalTreeSet<T, const B: usize = 6> = AugmentedBTreeSet<(T, T), MaxEnd, B>;
+
+impl<T: Ord + Clone, const B: usize> AugmentedBTreeSet<(T, T), MaxEnd, B> {
+    /// Returns an iterator over the intervals containing the point, ordered
+    /// by their start.
+    pub fn stabbing(&self, point: T) -> Overlapping<'_, T, B> {
+        Overlapping::new(self, point.clone(), Bound::Included(point))
+    }
+
+    /// Returns an iterator over the intervals sharing at least one point with
+    /// the range, ordered by their start.
+    pub fn overlapping(&self, range: Range<T>) -> Overlapping<'_, T, B> {
+        let empty = range.is_empty();
+        let mut iter = Overlapping::new(self, range.start, Bound::Excluded(range.end));
+        if empty {
+            iter.stack.clear();
+        }
+        iter
+    }
+}
+
+type IntervalNode<T, const B: usize> = Node<(T, T), MaxEnd, B>;
+
+/// An iterator over the intervals of an `IntervalTreeSet` which end after a
+/// given point, and start before a given bound.
+pub struct Overlapping<'a, T: Ord + Clone, const B: usize> {
+    /// The nodes from the root down to the current one, each with the next
+    /// step to take in it. Even steps visit the children, and odd steps the
+    /// keys between them.
+    stack: Vec<(&'a IntervalNode<T, B>, usize)>,
+    after: T,
+    before: Bound<T>,
+}
+
+impl<'a, T: Ord + Clone, const B: usize> Overlapping<'a, T, B> {
+    fn new(tree: &'a IntervalTreeSet<T, B>, after: T, before: Bound<T>) -> Self {
+        let mut iter = Overlapping {
+            stack: Vec::new(),
+            after,
+            before,
+        };
+        if let Some(root) = &tree.root {
+            iter.enter(root);
+        }
+        iter
+    }
+
+    /// Pushes the node, unless every interval in its subtree ends too early.
+    fn enter(&mut self, node: &'a Node<(T, T), MaxEnd, B>) {
+        if node.summary.as_ref().is_some_and(|end| *end > self.after) {
+            self.stack.push((node, 0));
+        }
+    }
+}
+
+impl<'a, T: Ord + Clone, const B: usize> Iterator for Overlapping<'a, T, B> {
+    type Item = &'a (T, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, step) = self.stack.last_mut()?;
+            let node: &'a Node<_, _, B> = node;
+            let idx = *step / 2;
+
+            if *step % 2 == 0 {
+                *step += 1;
+                if idx == node.keys.len() {
+                    self.stack.pop();
+                }
+                if let Some(child) = node.children.get(idx) {
+                    self.enter(child);
+                }
+                continue;
+            }
+
+            *step += 1;
+            let interval = &node.keys[idx];
+            let started = match &self.before {
+                Bound::Included(bound) => interval.0 <= *bound,
+                Bound::Excluded(bound) => interval.0 < *bound,
+                Bound::Unbounded => true,
+            };
+
+            // Every later interval starts at least as late as this one.
+            if !started {
+                self.stack.clear();
+                return None;
+            }
+            if interval.1 > self.after && interval.0 < interval.1 {
+                return Some(interval);
+            }
+        }
+    }
+}
+
+impl<T: Ord + Clone, const B: usize> FusedIterator for Overlapping<'_, T, B> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BTreeSet;
+
+    fn intervals() -> Vec<(u32, u32)> {
+        (0..500u32)
+            .map(|i| {
+                let start = i * 7919 % 1000;
+                (start, start + i * 31 % 50)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_stabbing_finds_the_intervals_containing_the_point() {
+        let mut tree = IntervalTreeSet::<u32, 2>::new();
+        for interval in intervals() {
+            tree.insert(interval).unwrap();
+        }
+
+        let mut sorted = intervals();
+        sorted.sort();
+        for point in (0..1100).step_by(7) {
+            let expected = sorted
+                .iter()
+                .filter(|(start, end)| (*start..*end).contains(&point));
+            assert!(tree.stabbing(point).eq(expected), "point {point}");
+        }
+    }
+
+    #[test]
+    fn test_overlapping_finds_the_intervals_sharing_a_point() {
+        let mut tree = IntervalTreeSet::<u32, 2>::new();
+        for interval in intervals() {
+            tree.insert(interval).unwrap();
+        }
+        for interval in intervals().iter().step_by(3) {
+            tree.remove(interval).unwrap();
+        }
+
+        let mut remaining: Vec<_> = intervals().into_iter().skip(1).step_by(3).collect();
+        remaining.extend(intervals().into_iter().skip(2).step_by(3));
+        remaining.sort();
+
+        for start in (0..1100).step_by(13) {
+            for len in [0, 1, 10, 100] {
+                let end = start + len;
+                let expected = remaining
+                    .iter()
+                    .filter(|(s, e)| start < end && *s < end && *e > start && s < e);
+                assert!(tree.overlapping(start..end).eq(expected), "{start}..{end}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty_intervals_are_never_reported() {
+        let mut tree = IntervalTreeSet::<u32>::new();
+        tree.insert((5, 5)).unwrap();
+        tree.insert((7, 3)).unwrap();
+
+        assert_eq!(tree.stabbing(5).next(), None);
+        assert_eq!(tree.overlapping(0..10).next(), None);
+    }
+}
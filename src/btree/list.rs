@@ -0,0 +1,313 @@
+use super::augment::{AugmentedBTreeSet, Count, Node};
+pub use super::counted::Iter;
+use std::fmt;
+use std::mem;
+
+/// A sequence ordered by position rather than by key. Unlike a `Vec`,
+/// inserting or removing an element in the middle of the list does not move
+/// the elements after it, and takes logarithmic time, as does looking up the
+/// element at a position.
+///
+/// The list is a `CountedBTreeSet` whose elements are never compared. Every
+/// node knows how many elements its subtree holds, so a position is found by
+/// descending along the counts, the way `select` finds a rank, and the tree
+/// is kept balanced by the same splits, rotations and merges.
+///
+/// The T type parameter represents the element type, and B is the branching
+/// factor.
+pub struct BTreeList<T, const B: usize = 6> {
+    tree: AugmentedBTreeSet<T, Count, B>,
+}
+
+impl<T, const B: usize> BTreeList<T, B> {
+    pub fn new() -> Self {
+        BTreeList {
+            tree: AugmentedBTreeSet::new(),
+        }
+    }
+
+    /// Returns the number of elements in the list.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Returns the element at the given position, or `None` if the position
+    /// is out of bounds.
+    pub fn get(&self, idx: usize) -> Option<&T> {
+        self.tree.select(idx)
+    }
+
+    /// Returns a mutable reference to the element at the given position, or
+    /// `None` if the position is out of bounds.
+    pub fn get_mut(&mut self, mut idx: usize) -> Option<&mut T> {
+        let mut node = self.tree.root.as_deref_mut()?;
+        if idx >= node.summary {
+            return None;
+        }
+
+        loop {
+            match node.find_rank(idx) {
+                Ok(idx) => return Some(&mut node.keys[idx]),
+                Err((child, within)) => (node, idx) = (&mut node.children[child], within),
+            }
+        }
+    }
+
+    /// Appends an element to the back of the list.
+    pub fn push(&mut self, value: T) {
+        self.insert_at(self.len(), value);
+    }
+
+    /// Inserts an element at the given position, shifting the elements after
+    /// it one position back.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is greater than the length of the list.
+    pub fn insert_at(&mut self, idx: usize, value: T) {
+        let len = self.len();
+        assert!(
+            idx <= len,
+            "insertion index (is {idx}) should be <= len (is {len})"
+        );
+
+        let Some(root) = &mut self.tree.root else {
+            self.tree.root = Some(Node::leaf(value));
+            return;
+        };
+
+        if let Some((hoist, sibling)) = Self::insert_into(root, idx, value) {
+            let root = self.tree.root.take().unwrap();
+            self.tree.root = Some(Node::grow(root, hoist, sibling));
+        }
+    }
+
+    /// Removes the element at the given position, shifting the elements after
+    /// it one position forward. Returns `None` if the position is out of
+    /// bounds.
+    pub fn remove_at(&mut self, idx: usize) -> Option<T> {
+        if idx >= self.len() {
+            return None;
+        }
+
+        let root = self.tree.root.as_mut()?;
+        let removed = Self::remove_from(root, idx);
+
+        if root.keys.is_empty() {
+            self.tree.root = root.children.pop_front();
+        }
+        Some(removed)
+    }
+
+    /// Splits the list in two at the given position. The list keeps the
+    /// elements before the position, and the rest are returned as a new
+    /// list, like `split_at_rank` of `CountedBTreeSet`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is greater than the length of the list.
+    pub fn split_off(&mut self, at: usize) -> Self {
+        let len = self.len();
+        assert!(
+            at <= len,
+            "`at` split index (is {at}) should be <= len (is {len})"
+        );
+        BTreeList {
+            tree: self.tree.split_at_rank(at),
+        }
+    }
+
+    /// Returns an iterator over the elements of the list, in order.
+    pub fn iter(&self) -> Iter<'_, T, B> {
+        self.tree.iter()
+    }
+
+    /// Inserts the element at the given position of the subtree, returning
+    /// the hoisted element and the new sibling when the node had to be split.
+    fn insert_into(
+        node: &mut Node<T, Count, B>,
+        mut idx: usize,
+        value: T,
+    ) -> Option<(T, Box<Node<T, Count, B>>)> {
+        if node.is_leaf() {
+            node.keys.insert(idx, value);
+            return node.split_if_overflowed();
+        }
+
+        // The position may lie right after the last element of a child, which
+        // keeps the element next to its predecessor.
+        let mut child = 0;
+        while idx > node.children[child].summary {
+            idx -= node.children[child].summary + 1;
+            child += 1;
+        }
+
+        if let Some((hoist, sibling)) = Self::insert_into(&mut node.children[child], idx, value) {
+            node.keys.insert(child, hoist);
+            node.children.insert(child + 1, sibling);
+        }
+        node.split_if_overflowed()
+    }
+
+    /// Removes the element at the given position of the subtree, leaving the
+    /// node possibly deficient, but all of its descendants valid.
+    fn remove_from(node: &mut Node<T, Count, B>, mut idx: usize) -> T {
+        if node.is_leaf() {
+            let removed = node.keys.remove(idx).unwrap();
+            node.update();
+            return removed;
+        }
+
+        let mut child = 0;
+        let removed = loop {
+            let count = node.children[child].summary;
+            if idx < count {
+                break Self::remove_from(&mut node.children[child], idx);
+            }
+            if idx == count {
+                let predecessor = node.children[child].remove_last();
+                break mem::replace(&mut node.keys[child], predecessor);
+            }
+            idx -= count + 1;
+            child += 1;
+        };
+
+        node.fix_deficient_child(child);
+        node.update();
+        removed
+    }
+}
+
+impl<T, const B: usize> Default for BTreeList<T, B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug, const B: usize> fmt::Debug for BTreeList<T, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T, const B: usize> FromIterator<T> for BTreeList<T, B> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = BTreeList::new();
+        for value in iter {
+            list.push(value);
+        }
+        list
+    }
+}
+
+impl<'a, T, const B: usize> IntoIterator for &'a BTreeList<T, B> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, B>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Steps a xorshift generator, to pick positions deterministically.
+    fn next(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    #[test]
+    fn test_list_matches_vec() {
+        let mut list = BTreeList::<u64, 2>::new();
+        let mut vec = Vec::new();
+        let mut state = 0x9e37_79b9_7f4a_7c15;
+
+        for i in 0..5000 {
+            let roll = next(&mut state);
+            let len = vec.len() as u64;
+            if roll.is_multiple_of(3) && !vec.is_empty() {
+                let idx = (roll / 3 % len) as usize;
+                assert_eq!(list.remove_at(idx), Some(vec.remove(idx)));
+            } else {
+                let idx = (roll / 3 % (len + 1)) as usize;
+                list.insert_at(idx, i);
+                vec.insert(idx, i);
+            }
+
+            if i % 250 == 0 {
+                assert_eq!(list.len(), vec.len());
+                assert!(list.iter().eq(vec.iter()));
+                for (idx, value) in vec.iter().enumerate() {
+                    assert_eq!(list.get(idx), Some(value));
+                }
+            }
+        }
+
+        assert_eq!(list.get(vec.len()), None);
+        assert_eq!(list.remove_at(vec.len()), None);
+        assert_eq!(list.iter().len(), vec.len());
+    }
+
+    #[test]
+    fn test_nth_skips_along_the_counts() {
+        let list: BTreeList<usize, 2> = (0..1000).collect();
+        let mut iter = list.iter();
+        let mut expected = 0..1000;
+
+        for n in [0, 1, 2, 7, 40, 3, 199, 0, 500] {
+            assert_eq!(iter.nth(n), expected.nth(n).as_ref());
+            assert_eq!(iter.len(), expected.len());
+            assert_eq!(iter.next(), expected.next().as_ref());
+        }
+        assert_eq!(iter.nth(1000), None);
+        assert_eq!(iter.len(), 0);
+        assert_eq!(iter.next(), None);
+        assert_eq!(BTreeList::<usize>::new().iter().nth(3), None);
+    }
+
+    #[test]
+    fn test_elements_are_never_compared() {
+        // Closures are not even comparable for equality.
+        let mut list: BTreeList<Box<dyn Fn() -> usize>, 2> = BTreeList::new();
+        for i in 0..100 {
+            list.insert_at(i / 2, Box::new(move || i));
+        }
+        *list.get_mut(0).unwrap() = Box::new(|| 1000);
+
+        let values: Vec<usize> = list.iter().map(|f| f()).collect();
+        assert_eq!(values[0], 1000);
+        assert_eq!(values.len(), 100);
+        assert_eq!(list.remove_at(99).unwrap()(), 0);
+    }
+
+    #[test]
+    fn test_split_off_keeps_both_lists_usable() {
+        let mut list: BTreeList<usize, 2> = (0..500).collect();
+        let mut back = list.split_off(123);
+        assert!(list.iter().copied().eq(0..123));
+        assert!(back.iter().copied().eq(123..500));
+
+        back.insert_at(0, 1000);
+        list.push(2000);
+        assert_eq!(back.get(0), Some(&1000));
+        assert_eq!(list.get(123), Some(&2000));
+        assert_eq!(format!("{:?}", list.split_off(122)), "[122, 2000]");
+    }
+
+    #[test]
+    #[should_panic(expected = "insertion index (is 2) should be <= len (is 1)")]
+    fn test_insert_past_the_end_panics() {
+        let mut list = BTreeList::<u8>::new();
+        list.push(0);
+        list.insert_at(2, 1);
+    }
+}
@@ -0,0 +1,497 @@
+use super::{DiskBTreeSet, SimpleBTreeSet};
+use crate::storage::{MemoryPager, PageId, PageKey, Pager};
+use crate::{BTreeSet, Error, Result};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::iter::{FusedIterator, Peekable};
+use std::ops::{Bound, RangeBounds};
+use std::vec;
+
+/// The number of keys the memtable holds before it is written into a run,
+/// unless told otherwise.
+pub const DEFAULT_MEMTABLE_KEYS: usize = 4096;
+
+/// A log-structured merge tree: writes go into a `SimpleBTreeSet`, the
+/// memtable, and once it holds enough keys, it is written into an immutable
+/// sorted run with `DiskBTreeSet::bulk_load_at`, every node once and packed.
+///
+/// Removals are written as tombstones, which hide the key in the older runs.
+/// Searches look into the memtable and then the runs, newest first, and
+/// iterators merge all of them, so the newest version of every key wins.
+/// After every flush, the newest runs are merged while a run is at least as
+/// large as the one before it, which keeps the number of runs logarithmic in
+/// the number of keys. Tombstones are dropped once they are merged into the
+/// oldest run, since there is nothing left for them to hide.
+///
+/// The runs are trees of a single `DiskBTreeSet`, stored through the given
+/// pager, which frees the pages of the runs merged away. Only the memtable
+/// and the list of runs are kept in memory, and they are not stored, so the
+/// set cannot be reopened from the pager.
+///
+/// The K type parameter represents the key type, and P is the pager the runs
+/// are stored through.
+pub struct LsmTreeSet<K, P = MemoryPager> {
+    memtable: SimpleBTreeSet<Record<K>>,
+    memtable_len: usize,
+    threshold: usize,
+    store: DiskBTreeSet<Record<K>, RUN_B, P>,
+    /// The runs, newest first.
+    runs: Vec<Run>,
+}
+
+/// The branching factor of the runs.
+const RUN_B: usize = 32;
+
+/// An immutable run, stored as a tree of `LsmTreeSet::store`.
+struct Run {
+    root: PageId,
+    len: usize,
+}
+
+/// A key, or a tombstone which hides the key in older runs. Records are
+/// ordered by their keys alone.
+#[derive(Clone, Debug)]
+struct Record<K> {
+    key: K,
+    live: bool,
+}
+
+impl<K> Record<K> {
+    fn live(key: K) -> Self {
+        Record { key, live: true }
+    }
+
+    /// Returns the key, unless the record is a tombstone.
+    fn get(&self) -> Result<&K> {
+        match self.live {
+            true => Ok(&self.key),
+            false => Err(Error::KeyNotFound),
+        }
+    }
+}
+
+impl<K: Ord> PartialEq for Record<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<K: Ord> Eq for Record<K> {}
+
+impl<K: Ord> PartialOrd for Record<K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord> Ord for Record<K> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+impl<K> Borrow<K> for Record<K> {
+    fn borrow(&self) -> &K {
+        &self.key
+    }
+}
+
+/// A record is stored as its key, followed by a byte telling whether it is
+/// a tombstone, so byte string keys still share their prefixes.
+impl<K: PageKey> PageKey for Record<K> {
+    const SIZE: Option<usize> = match K::SIZE {
+        Some(size) => Some(size + 1),
+        None => None,
+    };
+
+    fn encoded_len(&self) -> usize {
+        self.key.encoded_len() + 1
+    }
+
+    fn encode_into(&self, buf: &mut [u8]) {
+        let (key, live) = buf.split_at_mut(buf.len() - 1);
+        self.key.encode_into(key);
+        live[0] = u8::from(self.live);
+    }
+
+    fn decode_from(buf: &[u8]) -> Self {
+        let (live, key) = buf.split_last().expect("a record is at least a byte long");
+        Record {
+            key: K::decode_from(key),
+            live: *live != 0,
+        }
+    }
+}
+
+impl<K: PageKey + Ord + Clone> LsmTreeSet<K> {
+    /// Creates an empty set, whose runs are kept in memory.
+    pub fn new() -> Self {
+        Self::with_pager(MemoryPager::default(), DEFAULT_MEMTABLE_KEYS)
+            .expect("an empty memory pager holds a tree")
+    }
+}
+
+impl<K: PageKey + Ord + Clone> Default for LsmTreeSet<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: PageKey + Ord + Clone, P: Pager> LsmTreeSet<K, P> {
+    /// Creates an empty set, whose runs are stored through the given pager,
+    /// and whose memtable is written into a run once it holds `threshold`
+    /// keys. The pager should hold no pages yet.
+    pub fn with_pager(pager: P, threshold: usize) -> Result<Self> {
+        Ok(LsmTreeSet {
+            memtable: SimpleBTreeSet::new(),
+            memtable_len: 0,
+            threshold: threshold.max(1),
+            store: DiskBTreeSet::open(pager)?,
+            runs: Vec::new(),
+        })
+    }
+
+    /// Returns the number of keys and tombstones in the memtable.
+    pub fn memtable_len(&self) -> usize {
+        self.memtable_len
+    }
+
+    /// Returns the number of keys and tombstones in every run, newest first.
+    pub fn run_lens(&self) -> Vec<usize> {
+        self.runs.iter().map(|run| run.len).collect()
+    }
+
+    /// Writes the memtable into a new run, and merges the newest runs while
+    /// a run is at least as large as the one before it.
+    pub fn flush(&mut self) -> Result<()> {
+        if self.memtable_len == 0 {
+            return Ok(());
+        }
+
+        // The memtable is only cleared once its run is written, so that a
+        // failed write loses nothing.
+        let records = self.memtable.iter().cloned().collect();
+        self.push_run(records)?;
+        self.memtable = SimpleBTreeSet::new();
+        self.memtable_len = 0;
+
+        while let [newer, older, ..] = &self.runs[..]
+            && newer.len >= older.len
+        {
+            self.merge_runs(2)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the memtable, and merges every run into one, dropping the
+    /// tombstones.
+    pub fn compact(&mut self) -> Result<()> {
+        self.flush()?;
+        if self.runs.len() > 1 {
+            self.merge_runs(self.runs.len())?;
+        }
+        Ok(())
+    }
+
+    /// Returns an iterator over the keys of the set, in ascending order.
+    pub fn iter(&self) -> Result<Iter<'_, K>> {
+        self.range(..)
+    }
+
+    /// Returns an iterator over the keys of the set within the range, in
+    /// ascending order. The keys of the runs within the range are read up
+    /// front, and merged with the memtable as the iterator advances.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Result<Iter<'_, K>> {
+        let mut sources = vec![self.memtable_range(&range)];
+        let bounds = (
+            range.start_bound().map(|key| Record::live(key.clone())),
+            range.end_bound().map(|key| Record::live(key.clone())),
+        );
+        for run in &self.runs {
+            sources.push(self.store.range_at(Some(run.root), bounds.clone())?);
+        }
+        Ok(Iter(Merge::new(sources)))
+    }
+
+    /// Returns the records of the memtable within the range.
+    fn memtable_range(&self, range: &impl RangeBounds<K>) -> Vec<&Record<K>> {
+        let mut cursor = self.memtable.cursor();
+        if let Bound::Included(key) | Bound::Excluded(key) = range.start_bound() {
+            cursor.seek(&Record::live(key.clone()));
+        }
+
+        let mut records = Vec::new();
+        while let Some(record) = cursor.key() {
+            let beyond = match range.end_bound() {
+                Bound::Included(end) => record.key > *end,
+                Bound::Excluded(end) => record.key >= *end,
+                Bound::Unbounded => false,
+            };
+            if beyond {
+                break;
+            }
+            if range.contains(&record.key) {
+                records.push(record);
+            }
+            cursor.move_next();
+        }
+        records
+    }
+
+    /// Records the key, or the tombstone, in the memtable, and flushes it
+    /// once it is full.
+    fn write(&mut self, record: Record<K>) -> Result<()> {
+        // Without runs, there is nothing for a tombstone to hide.
+        if !record.live && self.runs.is_empty() {
+            if self.memtable.take(&record.key).is_some() {
+                self.memtable_len -= 1;
+            }
+            return Ok(());
+        }
+
+        if self.memtable.replace(record).is_none() {
+            self.memtable_len += 1;
+        }
+        if self.memtable_len >= self.threshold {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the records, which are in ascending order, into a new run in
+    /// front of the others. No run is added for no records.
+    fn push_run(&mut self, records: Vec<Record<K>>) -> Result<()> {
+        let len = records.len();
+        let mut root = None;
+        self.store.bulk_load_at(&mut root, records)?;
+        self.store.commit_with_root(None)?;
+        if let Some(root) = root {
+            self.runs.insert(0, Run { root, len });
+        }
+        Ok(())
+    }
+
+    /// Merges the given number of newest runs into a single run.
+    fn merge_runs(&mut self, count: usize) -> Result<()> {
+        let oldest = count == self.runs.len();
+        let mut sources = Vec::with_capacity(count);
+        for run in &self.runs[..count] {
+            sources.push(self.store.range_at(Some(run.root), ..)?);
+        }
+        let records = Merge::new(sources)
+            .filter(|record| record.live || !oldest)
+            .cloned()
+            .collect();
+
+        let merged: Vec<_> = self.runs.drain(..count).collect();
+        self.push_run(records)?;
+        for run in merged {
+            self.store.clear_at(&mut Some(run.root))?;
+        }
+        self.store.commit_with_root(None)
+    }
+}
+
+impl<K: PageKey + Ord + Clone, P: Pager> BTreeSet for LsmTreeSet<K, P> {
+    type Key = K;
+    const B: usize = RUN_B;
+
+    fn search(&self, key: &Self::Key) -> Result<&Self::Key> {
+        if let Ok(record) = self.memtable.search(key) {
+            return record.get();
+        }
+        if self.runs.is_empty() {
+            return Err(Error::KeyNotFound);
+        }
+
+        let probe = Record::live(key.clone());
+        for run in &self.runs {
+            match self.store.search_at(Some(run.root), &probe) {
+                Ok(record) => return record.get(),
+                Err(Error::KeyNotFound) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Err(Error::KeyNotFound)
+    }
+
+    fn insert(&mut self, key: Self::Key) -> Result<()> {
+        match self.search(&key) {
+            Ok(_) => Err(Error::KeyAlreadyExists),
+            Err(Error::KeyNotFound) => self.write(Record::live(key)),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn remove(&mut self, key: &Self::Key) -> Result<Self::Key> {
+        let key = self.search(key)?.clone();
+        self.write(Record {
+            key: key.clone(),
+            live: false,
+        })?;
+        Ok(key)
+    }
+}
+
+/// Merges sorted sources of records, newest first, into the newest record
+/// of every key.
+struct Merge<'a, K> {
+    sources: Vec<Peekable<vec::IntoIter<&'a Record<K>>>>,
+}
+
+impl<'a, K: Ord> Merge<'a, K> {
+    fn new(sources: Vec<Vec<&'a Record<K>>>) -> Self {
+        Merge {
+            sources: sources
+                .into_iter()
+                .map(|source| source.into_iter().peekable())
+                .collect(),
+        }
+    }
+}
+
+impl<'a, K: Ord> Iterator for Merge<'a, K> {
+    type Item = &'a Record<K>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The first of several equal minimums is the newest one.
+        let (idx, record) = self
+            .sources
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(idx, source)| Some((idx, *source.peek()?)))
+            .min_by(|(_, a), (_, b)| a.cmp(b))?;
+
+        for source in &mut self.sources[idx..] {
+            source.next_if(|other| *other == record);
+        }
+        Some(record)
+    }
+}
+
+/// An iterator over the keys of an `LsmTreeSet`, in ascending order, which
+/// merges the memtable with the runs.
+pub struct Iter<'a, K>(Merge<'a, K>);
+
+impl<'a, K: Ord> Iterator for Iter<'a, K> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.find_map(|record| record.get().ok())
+    }
+}
+
+impl<K: Ord> FusedIterator for Iter<'_, K> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::{DifferentialTester, ReferenceBTreeSet};
+    use crate::storage::{DEFAULT_PAGE_SIZE, FilePager};
+    use crate::workload::{Kind, Workload};
+
+    fn small() -> LsmTreeSet<u64> {
+        LsmTreeSet::with_pager(MemoryPager::default(), 16).unwrap()
+    }
+
+    #[test]
+    fn test_agrees_with_reference_on_mixed_operations() {
+        let mut tester = DifferentialTester::new(small(), ReferenceBTreeSet::new());
+
+        for i in 0..20_000u64 {
+            let key = (i * 7919) % 503;
+            let _ = match i % 3 {
+                0 => tester.remove(&key).map(|_| ()),
+                _ => tester.insert(key),
+            };
+        }
+
+        let (tree, reference) = tester.into_inner();
+        assert!(tree.iter().unwrap().eq(reference.iter()));
+    }
+
+    #[test]
+    fn test_workloads() {
+        for kind in Kind::ALL {
+            let mut tree = small();
+            Workload::new(kind, 0x5eed)
+                .with_key_space(4096)
+                .check(&mut tree, 20_000);
+        }
+    }
+
+    #[test]
+    fn test_runs_are_merged_as_they_grow() {
+        let mut tree = small();
+        for key in 0..16 * 11 {
+            tree.insert(key).unwrap();
+        }
+        // Eleven flushes leave runs of eight, two and one memtables.
+        assert_eq!(tree.run_lens(), [16, 32, 128]);
+        assert_eq!(tree.memtable_len(), 0);
+
+        // Tombstones hide the keys of older runs, until they are compacted.
+        for key in (0..16 * 11).filter(|key| key % 2 == 1) {
+            tree.remove(&key).unwrap();
+        }
+        assert!(tree.search(&3).is_err());
+        assert!(tree.iter().unwrap().copied().eq((0..16 * 11).step_by(2)));
+        let stored: usize = tree.run_lens().iter().sum::<usize>() + tree.memtable_len();
+        assert!(stored > 16 * 11);
+
+        tree.compact().unwrap();
+        assert_eq!(tree.run_lens(), [88]);
+        assert!(tree.iter().unwrap().copied().eq((0..16 * 11).step_by(2)));
+    }
+
+    #[test]
+    fn test_range_merges_the_memtable_with_the_runs() {
+        let mut tree = small();
+        for key in 0..100 {
+            tree.insert(key * 2).unwrap();
+        }
+        tree.remove(&40).unwrap();
+        tree.insert(41).unwrap();
+        assert!(tree.memtable_len() > 0 && !tree.run_lens().is_empty());
+
+        let range = |range: (Bound<u64>, Bound<u64>)| -> Vec<u64> {
+            tree.range(range).unwrap().copied().collect()
+        };
+        use Bound::*;
+        assert_eq!(range((Included(36), Excluded(44))), [36, 38, 41, 42]);
+        assert_eq!(range((Excluded(36), Included(44))), [38, 41, 42, 44]);
+        assert_eq!(range((Excluded(194), Unbounded)), [196, 198]);
+        assert_eq!(range((Unbounded, Excluded(4))), [0, 2]);
+        assert!(range((Included(300), Unbounded)).is_empty());
+    }
+
+    #[test]
+    fn test_runs_are_stored_through_the_pager() {
+        let path = crate::tests::temp_path("lsm");
+
+        let pager = FilePager::open(&path, DEFAULT_PAGE_SIZE).unwrap();
+        let mut tree = LsmTreeSet::<Vec<u8>, _>::with_pager(pager, 64).unwrap();
+        let key = |i: u32| format!("https://example.com/{i:05}").into_bytes();
+        let mut total_pages = 0;
+        for round in 0..2 {
+            for i in 0..2000 {
+                let _ = tree.insert(key(i * 7919 % 2000));
+            }
+            for i in 0..1000 {
+                tree.remove(&key(i)).unwrap();
+            }
+            tree.compact().unwrap();
+            assert!(tree.iter().unwrap().cloned().eq((1000..2000).map(key)));
+
+            // The pages of the runs merged away are reused by the next round,
+            // which would otherwise about double the file.
+            let stats = tree.store.file_stats();
+            assert!(stats.used_pages() < 24, "{stats:?}");
+            if round == 1 {
+                assert!(stats.total_pages < total_pages + 8, "{stats:?}");
+            }
+            total_pages = stats.total_pages;
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,456 @@
+use super::simple::{Path, SimpleBTreeSet, Write};
+use crate::{BTreeMap, Error, Result};
+use std::cmp::Ordering;
+
+/// An in-memory B-tree map built on top of `SimpleBTreeSet`. Every key is
+/// stored together with its value, and the pairs are ordered by key only.
+///
+/// The K and V type parameters represent the key and value types, and B is
+/// the branching factor.
+pub struct SimpleBTreeMap<K, V, const B: usize = 6> {
+    set: SimpleBTreeSet<KeyValue<K, V>, B>,
+}
+
+/// A key-value pair, which is ordered solely by its key.
+struct KeyValue<K, V> {
+    key: K,
+    value: V,
+}
+
+impl<K: Ord, V> PartialEq for KeyValue<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<K: Ord, V> Eq for KeyValue<K, V> {}
+
+impl<K: Ord, V> PartialOrd for KeyValue<K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, V> Ord for KeyValue<K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+impl<K: Ord, V, const B: usize> SimpleBTreeMap<K, V, B> {
+    pub fn new() -> Self {
+        SimpleBTreeMap {
+            set: SimpleBTreeSet::new(),
+        }
+    }
+
+    /// Replaces the value of the key with `new` if the current value equals
+    /// `expected`, within a single descent of the tree, and returns the
+    /// replaced value. Otherwise nothing changes, and the current value is
+    /// returned as the inner error.
+    pub fn compare_and_swap(
+        &mut self,
+        key: &K,
+        expected: &V,
+        new: V,
+    ) -> Result<std::result::Result<V, &V>>
+    where
+        V: PartialEq,
+    {
+        let current = self.get_mut(key)?;
+        if current != expected {
+            return Ok(Err(current));
+        }
+        Ok(Ok(std::mem::replace(current, new)))
+    }
+
+    /// Applies every write of the batch, in ascending order of keys. The
+    /// writes which land in the same leaf share a single descent of the tree,
+    /// unless one of them splits or merges the leaf. Writes to the same key
+    /// are applied in the order they appear in the batch, and removing a
+    /// missing key does nothing.
+    pub fn apply_batch(&mut self, mut batch: Vec<BatchOp<K, V>>) {
+        batch.sort_by(|a, b| a.key().cmp(b.key()));
+        let writes = batch
+            .into_iter()
+            .map(|op| match op {
+                BatchOp::Insert(key, value) => Write::Upsert(KeyValue { key, value }),
+                BatchOp::Remove(key) => Write::Remove(key),
+            })
+            .collect();
+        self.set.apply_sorted_by(writes, |kv, key| kv.key.cmp(key));
+    }
+
+    /// Returns the key-value pairs of the map, in ascending order of keys.
+    pub(super) fn pairs(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.set.iter().map(|kv| (&kv.key, &kv.value))
+    }
+
+    /// Gets the entry of the given key for in-place manipulation. The tree is
+    /// descended only once, whether the key exists or not.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, B> {
+        match self.set.search_path_by(|kv| kv.key.cmp(&key)) {
+            Ok(path) => Entry::Occupied(OccupiedEntry { map: self, path }),
+            Err(path) => Entry::Vacant(VacantEntry {
+                map: self,
+                path,
+                key,
+            }),
+        }
+    }
+}
+
+impl<K: Ord, V, const B: usize> Default for SimpleBTreeMap<K, V, B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord, V, const B: usize> BTreeMap for SimpleBTreeMap<K, V, B> {
+    type Key = K;
+    type Value = V;
+    const B: usize = B;
+
+    fn get(&self, key: &Self::Key) -> Result<&Self::Value> {
+        let kv = self.set.search_by(|kv| kv.key.cmp(key))?;
+        Ok(&kv.value)
+    }
+
+    fn get_mut(&mut self, key: &Self::Key) -> Result<&mut Self::Value> {
+        let path = self
+            .set
+            .search_path_by(|kv| kv.key.cmp(key))
+            .map_err(|_| Error::KeyNotFound)?;
+        Ok(&mut self.set.get_mut(&path).value)
+    }
+
+    fn insert(&mut self, key: Self::Key, value: Self::Value) -> Result<()> {
+        match self.entry(key) {
+            Entry::Occupied(_) => Err(Error::KeyAlreadyExists),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                Ok(())
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &Self::Key) -> Result<Self::Value> {
+        let kv = self.set.remove_by(|kv| kv.key.cmp(key))?;
+        Ok(kv.value)
+    }
+}
+
+/// A single write of a batch applied with `apply_batch`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BatchOp<K, V> {
+    /// Stores the value under the key, replacing the value stored under it.
+    Insert(K, V),
+    /// Removes the key, if it exists.
+    Remove(K),
+}
+
+impl<K, V> BatchOp<K, V> {
+    pub fn key(&self) -> &K {
+        match self {
+            BatchOp::Insert(key, _) | BatchOp::Remove(key) => key,
+        }
+    }
+}
+
+/// A view into a single entry of a map, which is either occupied or vacant.
+pub enum Entry<'a, K, V, const B: usize> {
+    Occupied(OccupiedEntry<'a, K, V, B>),
+    Vacant(VacantEntry<'a, K, V, B>),
+}
+
+/// A view into an existing entry of a map.
+pub struct OccupiedEntry<'a, K, V, const B: usize> {
+    map: &'a mut SimpleBTreeMap<K, V, B>,
+    path: Path,
+}
+
+/// A view into a missing entry of a map. It remembers where the key belongs,
+/// so inserting it does not search the tree again.
+pub struct VacantEntry<'a, K, V, const B: usize> {
+    map: &'a mut SimpleBTreeMap<K, V, B>,
+    path: Path,
+    key: K,
+}
+
+impl<'a, K: Ord, V, const B: usize> Entry<'a, K, V, B> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Inserts the default value if the entry is vacant, and returns a mutable
+    /// reference to the value in the entry.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Inserts the result of the given function if the entry is vacant, and
+    /// returns a mutable reference to the value in the entry.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Inserts the default value of `V` if the entry is vacant, and returns a
+    /// mutable reference to the value in the entry.
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Modifies the value in place if the entry is occupied.
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+impl<'a, K: Ord, V, const B: usize> OccupiedEntry<'a, K, V, B> {
+    pub fn key(&self) -> &K {
+        &self.map.set.get(&self.path).key
+    }
+
+    pub fn get(&self) -> &V {
+        &self.map.set.get(&self.path).value
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.map.set.get_mut(&self.path).value
+    }
+
+    /// Converts the entry into a mutable reference bound to the map itself.
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.map.set.get_mut(&self.path).value
+    }
+
+    /// Replaces the value in the entry, returning the old value.
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+}
+
+impl<'a, K: Ord, V, const B: usize> VacantEntry<'a, K, V, B> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Inserts the value into the entry, and returns a mutable reference to it.
+    pub fn insert(self, value: V) -> &'a mut V {
+        let kv = KeyValue {
+            key: self.key,
+            value,
+        };
+        let path = self.map.set.insert_along(&self.path, kv);
+        &mut self.map.set.get_mut(&path).value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_btree_map_impl;
+
+    test_btree_map_impl!(SimpleBTreeMap);
+
+    #[test]
+    fn test_entry_or_insert_inserts_into_vacant_entry() {
+        let mut map = SimpleBTreeMap::<i32, i32>::new();
+        assert_eq!(*map.entry(1).or_insert(10), 10);
+        assert_eq!(map.get(&1).unwrap(), &10);
+    }
+
+    #[test]
+    fn test_entry_or_insert_keeps_occupied_entry() {
+        let mut map = SimpleBTreeMap::<i32, i32>::new();
+        map.insert(1, 10).unwrap();
+        assert_eq!(*map.entry(1).or_insert(20), 10);
+        assert_eq!(map.get(&1).unwrap(), &10);
+    }
+
+    #[test]
+    fn test_entry_or_insert_with_is_lazy() {
+        let mut map = SimpleBTreeMap::<i32, i32>::new();
+        map.insert(1, 10).unwrap();
+        map.entry(1).or_insert_with(|| panic!("entry is occupied"));
+        assert_eq!(*map.entry(2).or_insert_with(|| 20), 20);
+    }
+
+    #[test]
+    fn test_entry_and_modify_only_modifies_occupied_entry() {
+        let mut map = SimpleBTreeMap::<&str, i32>::new();
+        map.entry("a").and_modify(|v| *v += 1).or_insert(0);
+        assert_eq!(map.get(&"a").unwrap(), &0);
+        map.entry("a").and_modify(|v| *v += 1).or_insert(0);
+        assert_eq!(map.get(&"a").unwrap(), &1);
+    }
+
+    #[test]
+    fn test_entry_counts_with_many_splits() {
+        let mut map = SimpleBTreeMap::<usize, usize, 2>::new();
+        let items = (0..1000).map(|i| (i * 7) % 100);
+
+        for i in items {
+            *map.entry(i).or_default() += 1;
+        }
+
+        for i in 0..100 {
+            assert_eq!(map.get(&i).unwrap(), &10);
+        }
+    }
+
+    #[test]
+    fn test_vacant_entry_insert_returns_inserted_value_after_splits() {
+        let mut map = SimpleBTreeMap::<usize, usize, 2>::new();
+
+        for i in (0..500).rev() {
+            let value = map.entry(i).or_insert(i + 1);
+            assert_eq!(*value, i + 1);
+            *value = i * 3;
+        }
+
+        for i in 0..500 {
+            assert_eq!(map.get(&i).unwrap(), &(i * 3));
+        }
+    }
+
+    #[test]
+    fn test_occupied_entry_insert_replaces_value() {
+        let mut map = SimpleBTreeMap::<i32, &str>::new();
+        map.insert(1, "one").unwrap();
+
+        let Entry::Occupied(mut entry) = map.entry(1) else {
+            panic!("entry should be occupied");
+        };
+
+        assert_eq!(entry.key(), &1);
+        assert_eq!(entry.insert("uno"), "one");
+        assert_eq!(entry.get(), &"uno");
+    }
+
+    #[test]
+    fn test_compare_and_swap_only_swaps_expected_value() {
+        let mut map = SimpleBTreeMap::<i32, &str>::new();
+        map.insert(1, "one").unwrap();
+
+        assert_eq!(
+            map.compare_and_swap(&1, &"uno", "bir").unwrap(),
+            Err(&"one")
+        );
+        assert_eq!(map.compare_and_swap(&1, &"one", "bir").unwrap(), Ok("one"));
+        assert_eq!(map.get(&1).unwrap(), &"bir");
+        assert!(matches!(
+            map.compare_and_swap(&2, &"two", "iki"),
+            Err(Error::KeyNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_apply_batch_applies_writes_in_order() {
+        let mut map = SimpleBTreeMap::<usize, usize, 2>::new();
+        for i in 0..100 {
+            map.insert(i, 0).unwrap();
+        }
+
+        let mut batch = Vec::new();
+        for i in (0..200).rev() {
+            batch.push(BatchOp::Insert(i, i));
+            if i % 3 == 0 {
+                batch.push(BatchOp::Remove(i));
+            }
+        }
+        batch.push(BatchOp::Insert(0, 42));
+        map.apply_batch(batch);
+
+        assert_eq!(map.get(&0).unwrap(), &42);
+        for i in 1..200 {
+            assert_eq!(map.get(&i).ok(), (i % 3 != 0).then_some(&i));
+        }
+    }
+
+    #[test]
+    fn test_apply_batch_matches_std_and_keeps_the_tree_valid() {
+        use crate::workload::Rng;
+
+        for seed in 0..32 {
+            let mut rng = Rng::new(seed);
+            let mut map = SimpleBTreeMap::<u64, u64, 2>::new();
+            let mut expected = std::collections::BTreeMap::new();
+            for _ in 0..8 {
+                let span = 1 + rng.next() % 500;
+                let mut batch = Vec::new();
+                for _ in 0..rng.next() % 300 {
+                    let key = rng.next() % span;
+                    if rng.next().is_multiple_of(3) {
+                        batch.push(BatchOp::Remove(key));
+                        expected.remove(&key);
+                    } else {
+                        let value = rng.next();
+                        batch.push(BatchOp::Insert(key, value));
+                        expected.insert(key, value);
+                    }
+                }
+                // The writes are applied in order of keys, so a stable sort
+                // of the batch must agree with applying it as given.
+                map.apply_batch(batch);
+
+                map.set.check_invariants().unwrap();
+                let pairs: Vec<_> = map.pairs().map(|(k, v)| (*k, *v)).collect();
+                let wanted: Vec<_> = expected.iter().map(|(k, v)| (*k, *v)).collect();
+                assert_eq!(pairs, wanted, "seed {seed}");
+            }
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_apply_batch_shares_descents_within_a_leaf() {
+        let mut map = SimpleBTreeMap::<usize, usize, 8>::new();
+        for i in 0..1000 {
+            map.insert(i * 2, 0).unwrap();
+        }
+        map.set.reset_metrics();
+
+        let batch = (0..1000).map(|i| BatchOp::Insert(i * 2, 1)).collect();
+        map.apply_batch(batch);
+
+        assert!(map.pairs().all(|(_, value)| *value == 1));
+        let descents = map.set.metrics().descents;
+        assert!(descents < 250, "{descents} descents for 1000 writes");
+    }
+
+    #[test]
+    fn test_vacant_entry_exposes_key() {
+        let mut map = SimpleBTreeMap::<i32, i32>::new();
+
+        let Entry::Vacant(entry) = map.entry(1) else {
+            panic!("entry should be vacant");
+        };
+
+        assert_eq!(entry.key(), &1);
+        assert_eq!(entry.into_key(), 1);
+        assert!(!map.contains_key(&1));
+    }
+}
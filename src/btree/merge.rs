@@ -0,0 +1,170 @@
+use super::map::{Entry, SimpleBTreeMap};
+use super::simple::SimpleBTreeSet;
+use crate::{BTreeMap, Result};
+
+/// The value of a key, together with the operands merged into it since it
+/// was last collapsed.
+struct Slot<V> {
+    value: Option<V>,
+    operands: Vec<V>,
+}
+
+impl<V> Slot<V> {
+    fn collapse(&mut self, merge: &dyn Fn(V, V) -> V) -> &V {
+        for operand in self.operands.drain(..) {
+            self.value = Some(match self.value.take() {
+                Some(value) => merge(value, operand),
+                None => operand,
+            });
+        }
+        self.value
+            .as_ref()
+            .expect("slot holds a value or an operand")
+    }
+
+    fn into_value(mut self, merge: &dyn Fn(V, V) -> V) -> V {
+        self.collapse(merge);
+        self.value.expect("slot holds a value or an operand")
+    }
+}
+
+/// A `SimpleBTreeMap` which supports merging an operand into a value without
+/// reading it first, in the style of RocksDB's merge operator.
+///
+/// The map is created with an associative merge function, which combines a
+/// value with an operand. Merged operands are only buffered in the entry of
+/// their key, and are collapsed into its value when the key is read or
+/// removed, or when the map is flushed. Merging into a missing key creates
+/// it, with the operand as its value.
+pub struct MergeBTreeMap<K, V, const B: usize = 6> {
+    map: SimpleBTreeMap<K, Slot<V>, B>,
+    merge: Box<dyn Fn(V, V) -> V>,
+    pending: SimpleBTreeSet<K, B>,
+}
+
+impl<K: Ord + Clone, V, const B: usize> MergeBTreeMap<K, V, B> {
+    pub fn new(merge: impl Fn(V, V) -> V + 'static) -> Self {
+        MergeBTreeMap {
+            map: SimpleBTreeMap::new(),
+            merge: Box::new(merge),
+            pending: SimpleBTreeSet::new(),
+        }
+    }
+
+    /// Returns the value of the key, collapsing the operands merged into it
+    /// first.
+    pub fn get(&mut self, key: &K) -> Result<&V> {
+        let slot = self.map.get_mut(key)?;
+        if !slot.operands.is_empty() {
+            let _ = self.pending.remove(key);
+        }
+        Ok(slot.collapse(&self.merge))
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.map.contains_key(key)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Result<()> {
+        let slot = Slot {
+            value: Some(value),
+            operands: Vec::new(),
+        };
+        self.map.insert(key, slot)
+    }
+
+    /// Removes the key, and returns its value with every operand merged into
+    /// it.
+    pub fn remove(&mut self, key: &K) -> Result<V> {
+        let slot = self.map.remove(key)?;
+        if !slot.operands.is_empty() {
+            let _ = self.pending.remove(key);
+        }
+        Ok(slot.into_value(&self.merge))
+    }
+
+    /// Merges the operand into the value of the key, within a single descent
+    /// of the tree.
+    pub fn merge(&mut self, key: K, operand: V) {
+        let slot = match self.map.entry(key) {
+            Entry::Occupied(entry) => {
+                if entry.get().operands.is_empty() {
+                    self.pending.replace(entry.key().clone());
+                }
+                entry.into_mut()
+            }
+            Entry::Vacant(entry) => {
+                self.pending.replace(entry.key().clone());
+                entry.insert(Slot {
+                    value: None,
+                    operands: Vec::new(),
+                })
+            }
+        };
+        slot.operands.push(operand);
+    }
+
+    /// Collapses the operands of every key.
+    pub fn flush(&mut self) {
+        for key in self.pending.drain() {
+            if let Ok(slot) = self.map.get_mut(&key) {
+                slot.collapse(&self.merge);
+            }
+        }
+    }
+
+    /// Returns the number of operands which have not been collapsed yet.
+    pub fn pending_operands(&self) -> usize {
+        self.pending
+            .iter()
+            .filter_map(|key| self.map.get(key).ok())
+            .map(|slot| slot.operands.len())
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn test_operands_collapse_lazily() {
+        let mut counters = MergeBTreeMap::<&str, u64, 2>::new(|a, b| a + b);
+        counters.insert("hits", 10).unwrap();
+        for _ in 0..5 {
+            counters.merge("hits", 1);
+            counters.merge("misses", 2);
+        }
+
+        assert_eq!(counters.pending_operands(), 10);
+        assert_eq!(counters.get(&"hits").unwrap(), &15);
+        assert_eq!(counters.pending_operands(), 5);
+
+        counters.merge("hits", 1);
+        counters.flush();
+        assert_eq!(counters.pending_operands(), 0);
+        assert_eq!(counters.get(&"hits").unwrap(), &16);
+        assert_eq!(counters.get(&"misses").unwrap(), &10);
+    }
+
+    #[test]
+    fn test_remove_returns_merged_value() {
+        let mut logs = MergeBTreeMap::<u32, Vec<&str>>::new(|mut a, b| {
+            a.extend(b);
+            a
+        });
+        for key in 0..100 {
+            logs.merge(key % 10, vec!["a"]);
+            logs.merge(key % 10, vec!["b"]);
+        }
+
+        assert_eq!(logs.remove(&3).unwrap(), ["a", "b"].repeat(10));
+        assert!(matches!(logs.remove(&3), Err(Error::KeyNotFound)));
+        assert!(matches!(
+            logs.insert(4, vec![]),
+            Err(Error::KeyAlreadyExists)
+        ));
+        assert_eq!(logs.pending_operands(), 9 * 20);
+    }
+}
@@ -0,0 +1,276 @@
+use super::augment::{Augment, AugmentedBTreeSet, Node};
+use crate::storage::PageKey;
+use crate::{BTreeSet, Result};
+use sha2::{Digest, Sha256};
+use std::marker::PhantomData;
+
+/// A SHA-256 digest.
+pub type Hash = [u8; 32];
+
+/// Hashes the encoding of a key, prefixed so that it never collides with the
+/// hash of a node.
+fn hash_key<K: PageKey>(key: &K) -> Hash {
+    let mut buf = vec![0; key.encoded_len()];
+    key.encode_into(&mut buf);
+    Sha256::new()
+        .chain_update([0])
+        .chain_update(&buf)
+        .finalize()
+        .into()
+}
+
+/// Chains one more key or child hash into the hash of a node.
+fn chain(node: &Hash, item: &Hash) -> Hash {
+    Sha256::new()
+        .chain_update([1])
+        .chain_update(node)
+        .chain_update(item)
+        .finalize()
+        .into()
+}
+
+/// Hashes the keys and children of a node, in order.
+///
+/// An `AugmentedBTreeSet` summarizes a node by folding `combine` over its
+/// children and keys, so chaining the hashes in `combine` yields a hash of
+/// exactly those, instead of a summary of the keys alone. The combination is
+/// not associative, which only `range_aggregate` relies on, so the
+/// augmentation stays hidden behind `MerkleBTreeSet`.
+struct Merkle;
+
+impl<K: PageKey> Augment<K> for Merkle {
+    type Value = Hash;
+
+    fn identity() -> Hash {
+        [0; 32]
+    }
+
+    fn summarize(key: &K) -> Hash {
+        hash_key(key)
+    }
+
+    fn combine(left: &Hash, right: &Hash) -> Hash {
+        chain(left, right)
+    }
+}
+
+/// A B-tree which keeps a hash of every node, computed from the hashes of
+/// its keys and children, like a Merkle tree. The hash of the root commits to
+/// every key of the tree, and a `MerkleProof` shows that a key is in the tree
+/// to anyone who knows the hash of the root, without the rest of the tree.
+///
+/// The hashes follow the shape of the tree, so two trees with the same keys
+/// only have the same root hash if they were built by the same operations.
+/// Keys are hashed in their `PageKey` encoding.
+///
+/// The K type parameter represents the key type, and B is the branching factor.
+pub struct MerkleBTreeSet<K: PageKey, const B: usize = 6> {
+    tree: AugmentedBTreeSet<K, Merkle, B>,
+}
+
+/// The hashes a verifier needs to recompute the root hash of a tree from a
+/// single key.
+///
+/// Every node on the path from the node holding the key up to the root is
+/// given by the hashes of its children and keys in order, with the hash the
+/// verifier computes itself left out. The proof is typed by the key, since
+/// keys are hashed in their encoding, which differs between key types.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleProof<K> {
+    /// The nodes from the one holding the key up to the root, each with the
+    /// position of the missing hash.
+    nodes: Vec<(Vec<Hash>, usize)>,
+    key: PhantomData<fn(&K)>,
+}
+
+impl<K: PageKey + Ord, const B: usize> MerkleBTreeSet<K, B> {
+    pub fn new() -> Self {
+        MerkleBTreeSet {
+            tree: AugmentedBTreeSet::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Returns the hash of the root node, or zeros if the tree is empty.
+    pub fn root_hash(&self) -> Hash {
+        self.tree.summary()
+    }
+
+    /// Returns a proof that the key is in the tree, or `None` if it is not.
+    pub fn prove(&self, key: &K) -> Option<MerkleProof<K>> {
+        let mut nodes = Vec::new();
+        let mut node = self.tree.root.as_deref()?;
+        loop {
+            let result = node.keys.binary_search(key);
+            let (Ok(idx) | Err(idx)) = result;
+
+            // The children come before the key of the same index.
+            let (at, next) = match (result, node.is_leaf()) {
+                (Ok(_), true) => (idx, None),
+                (Ok(_), false) => (2 * idx + 1, None),
+                (Err(_), true) => return None,
+                (Err(_), false) => (2 * idx, Some(&node.children[idx])),
+            };
+            nodes.push((items(node), at));
+
+            match next {
+                Some(child) => node = child,
+                None => break,
+            }
+        }
+
+        nodes.reverse();
+        for (items, at) in &mut nodes {
+            items.remove(*at);
+        }
+        Some(MerkleProof {
+            nodes,
+            key: PhantomData,
+        })
+    }
+}
+
+/// Returns the hashes of the children and keys of the node, in the order
+/// they are chained.
+fn items<K: PageKey, const B: usize>(node: &Node<K, Merkle, B>) -> Vec<Hash> {
+    let child = |idx: usize| node.children.get(idx).map(|child| child.summary);
+
+    let mut items = Vec::new();
+    for (idx, key) in node.keys.iter().enumerate() {
+        items.extend(child(idx));
+        items.push(hash_key(key));
+    }
+    items.extend(child(node.keys.len()));
+    items
+}
+
+impl<K: PageKey> MerkleProof<K> {
+    /// Returns whether the proof shows that the key is in a tree with the given
+    /// root hash.
+    pub fn verify(&self, root_hash: &Hash, key: &K) -> bool {
+        let mut hash = hash_key(key);
+        for (items, at) in &self.nodes {
+            if *at > items.len() {
+                return false;
+            }
+            let (before, after) = items.split_at(*at);
+            hash = before
+                .iter()
+                .chain([&hash])
+                .chain(after)
+                .fold(<Merkle as Augment<K>>::identity(), |node, item| {
+                    chain(&node, item)
+                });
+        }
+        !self.nodes.is_empty() && hash == *root_hash
+    }
+}
+
+impl<K: PageKey + Ord, const B: usize> Default for MerkleBTreeSet<K, B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: PageKey + Ord, const B: usize> BTreeSet for MerkleBTreeSet<K, B> {
+    type Key = K;
+    const B: usize = B;
+
+    fn search(&self, key: &K) -> Result<&K> {
+        self.tree.search(key)
+    }
+
+    fn insert(&mut self, key: K) -> Result<()> {
+        self.tree.insert(key)
+    }
+
+    fn remove(&mut self, key: &K) -> Result<K> {
+        self.tree.remove(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::{DifferentialTester, ReferenceBTreeSet};
+    use crate::workload::{Kind, Workload};
+
+    /// Recomputes the hash of the subtree from its keys, ignoring the stored
+    /// hashes of the nodes below.
+    fn rehash<K: PageKey, const B: usize>(node: &Node<K, Merkle, B>) -> Hash {
+        let mut hash = [0; 32];
+        for (idx, key) in node.keys.iter().enumerate() {
+            if let Some(child) = node.children.get(idx) {
+                hash = chain(&hash, &rehash(child));
+            }
+            hash = chain(&hash, &hash_key(key));
+        }
+        if let Some(child) = node.children.get(node.keys.len()) {
+            hash = chain(&hash, &rehash(child));
+        }
+        hash
+    }
+
+    #[test]
+    fn test_matches_reference() {
+        for kind in Kind::ALL {
+            let mut tester =
+                DifferentialTester::new(MerkleBTreeSet::<u64, 2>::new(), ReferenceBTreeSet::new());
+            Workload::new(kind, 7)
+                .with_key_space(500)
+                .check(&mut tester, 3000);
+
+            let tree = &tester.first().tree;
+            let expected = tree.root.as_deref().map_or([0; 32], rehash);
+            assert_eq!(tester.first().root_hash(), expected, "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn test_proofs_verify_only_their_key() {
+        let mut tree = MerkleBTreeSet::<u64, 2>::new();
+        assert_eq!(tree.root_hash(), [0; 32]);
+        for key in (0..300).map(|i| i * 7 % 1000) {
+            tree.insert(key).unwrap();
+        }
+        let root = tree.root_hash();
+
+        for key in (0..300).map(|i| i * 7 % 1000) {
+            let proof = tree.prove(&key).unwrap();
+            assert!(proof.verify(&root, &key), "{key}");
+            assert!(!proof.verify(&root, &(key + 1)), "{key}");
+            assert!(!proof.verify(&[0; 32], &key), "{key}");
+        }
+        assert_eq!(tree.prove(&1001), None);
+
+        // Any change to the tree changes the root hash, and so invalidates
+        // the proofs made before.
+        let proof = tree.prove(&7).unwrap();
+        tree.insert(1001).unwrap();
+        assert_ne!(tree.root_hash(), root);
+        assert!(!proof.verify(&tree.root_hash(), &7));
+        assert!(tree.prove(&7).unwrap().verify(&tree.root_hash(), &7));
+    }
+
+    #[test]
+    fn test_same_operations_give_same_root_hash() {
+        let build = |keys: &[&[u8]]| {
+            let mut tree = MerkleBTreeSet::<Vec<u8>>::new();
+            for key in keys {
+                tree.insert(key.to_vec()).unwrap();
+            }
+            tree.root_hash()
+        };
+
+        let keys: [&[u8]; 4] = [b"apple", b"banana", b"cherry", b"date"];
+        assert_eq!(build(&keys), build(&keys));
+        assert_ne!(build(&keys), build(&keys[..3]));
+        assert_ne!(
+            build(&keys),
+            build(&[b"apple", b"banana", b"cherry", b"dates"])
+        );
+    }
+}
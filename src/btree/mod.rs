@@ -1,5 +1,144 @@
-mod simple;
+#[cfg(feature = "std")]
+mod alloc;
+#[cfg(feature = "std")]
+mod arena;
+mod array;
+#[cfg(feature = "async")]
+mod async_disk;
+#[cfg(feature = "std")]
+mod augment;
+#[cfg(feature = "std")]
+mod bepsilon;
+#[cfg(feature = "std")]
+mod bplus;
+#[cfg(feature = "std")]
+mod bwtree;
+mod compare;
+#[cfg(feature = "std")]
+mod counted;
+#[cfg(feature = "std")]
+mod differential;
+#[cfg(feature = "std")]
+mod disk;
+#[cfg(feature = "std")]
+mod eytzinger;
+mod fixed;
+#[cfg(feature = "std")]
+mod frozen;
+#[cfg(feature = "std")]
+mod indexed;
+#[cfg(feature = "std")]
+mod interval;
+#[cfg(feature = "std")]
+mod layout;
+#[cfg(feature = "std")]
+mod list;
+#[cfg(feature = "std")]
+mod lsm;
+#[cfg(feature = "std")]
+mod map;
+#[cfg(feature = "std")]
+mod merge;
+#[cfg(feature = "merkle")]
+mod merkle;
+#[cfg(feature = "std")]
+mod observed;
+#[cfg(feature = "std")]
+mod olc;
+#[cfg(feature = "std")]
+mod oplog;
+#[cfg(feature = "std")]
+mod persistent;
+#[cfg(feature = "std")]
+mod prefix;
+#[cfg(test)]
+mod proptests;
+#[cfg(feature = "std")]
 mod reference;
+#[cfg(feature = "std")]
+mod sharded;
+#[cfg(feature = "shared")]
+mod shared;
+#[cfg(feature = "std")]
+mod simple;
+#[cfg(feature = "std")]
+mod snapshot;
+#[cfg(feature = "std")]
+mod sync;
 
-pub use simple::SimpleBTreeSet;
-pub(crate) use reference::ReferenceBTreeSet;
+#[cfg(feature = "std")]
+pub use alloc::{Allocator, Global};
+#[cfg(feature = "std")]
+pub use arena::ArenaBTreeSet;
+#[cfg(feature = "async")]
+pub use async_disk::AsyncDiskBTreeSet;
+#[cfg(feature = "std")]
+pub use augment::{Augment, AugmentedBTreeSet, Count, Max, Min, Sum};
+#[cfg(feature = "std")]
+pub use bepsilon::BEpsilonTreeSet;
+#[cfg(feature = "std")]
+pub use bplus::{BPlusTreeSet, Chunks as BPlusChunks, Iter as BPlusIter, RevIter as BPlusRevIter};
+#[cfg(feature = "std")]
+pub use bwtree::{BwTreeSet, DEFAULT_MAPPING_TABLE_SIZE, Iter as BwIter};
+pub use compare::{Comparator, OrdComparator};
+#[cfg(feature = "std")]
+pub use counted::{CountedBTreeSet, Iter as CountedIter};
+#[cfg(feature = "std")]
+pub use differential::{DifferentialTester, ShadowChecked};
+#[cfg(feature = "std")]
+pub(crate) use disk::write_free_list;
+#[cfg(feature = "std")]
+pub use disk::{
+    DiskBTreeSet, FileStats, PrefixStats, Reader as DiskReader, Savepoint,
+    Snapshot as DiskSnapshot, Transaction,
+};
+#[cfg(feature = "std")]
+pub use eytzinger::Eytzinger;
+pub use fixed::StaticBTreeSet;
+#[cfg(feature = "rkyv")]
+pub use frozen::ArchivedFrozenBTreeSet;
+#[cfg(feature = "std")]
+pub use frozen::{FrozenBTreeSet, Iter as FrozenIter};
+#[cfg(feature = "std")]
+pub use indexed::IndexedBTreeMap;
+#[cfg(feature = "std")]
+pub use interval::{IntervalTreeSet, MaxEnd, Overlapping};
+#[cfg(feature = "std")]
+pub use layout::{EytzingerLayout, Layout, NodeStorage, SortedLayout};
+#[cfg(feature = "std")]
+pub use list::{BTreeList, Iter as ListIter};
+#[cfg(feature = "std")]
+pub use lsm::{DEFAULT_MEMTABLE_KEYS, Iter as LsmIter, LsmTreeSet};
+#[cfg(feature = "std")]
+pub use map::{BatchOp, Entry, OccupiedEntry, SimpleBTreeMap, VacantEntry};
+#[cfg(feature = "std")]
+pub use merge::MergeBTreeMap;
+#[cfg(feature = "merkle")]
+pub use merkle::{Hash, MerkleBTreeSet, MerkleProof};
+#[cfg(feature = "std")]
+pub use observed::{Change, ObservedBTreeSet};
+#[cfg(feature = "std")]
+pub use olc::{Iter as OlcIter, OlcBTreeSet, Word};
+#[cfg(feature = "std")]
+pub use oplog::{LoggedBTreeSet, replay};
+#[cfg(feature = "std")]
+pub use persistent::{Iter as PersistentIter, PersistentBTreeSet};
+#[cfg(feature = "std")]
+pub use prefix::Prefixed;
+#[cfg(feature = "std")]
+pub use reference::{ReferenceBTreeMap, ReferenceBTreeSet};
+#[cfg(feature = "std")]
+pub use sharded::{Iter as ShardedIter, ShardedBTreeSet};
+#[cfg(feature = "shared")]
+pub use shared::SharedBTreeSet;
+#[cfg(feature = "metrics")]
+pub use simple::Metrics;
+#[cfg(feature = "rayon")]
+pub use simple::ParIter;
+#[cfg(feature = "std")]
+pub use simple::{
+    Cursor, CursorMut, Difference, ExtractIf, Intersection, IntoIter, Iter, KWayMerge, Position,
+    SimpleBTreeSet, SymmetricDifference, TreeStats, Union,
+};
+#[cfg(feature = "std")]
+pub use snapshot::Snapshot;
@@ -0,0 +1,189 @@
+use crate::{BTreeSet, Result};
+use std::ops::{Bound, RangeBounds};
+use std::sync::mpsc::{Receiver, Sender, channel};
+
+/// A mutation made to an [`ObservedBTreeSet`], carrying the affected key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change<K> {
+    Inserted(K),
+    Removed(K),
+}
+
+impl<K> Change<K> {
+    pub fn key(&self) -> &K {
+        match self {
+            Change::Inserted(key) | Change::Removed(key) => key,
+        }
+    }
+}
+
+type Callback<K> = Box<dyn FnMut(&K)>;
+
+struct Watcher<K> {
+    range: (Bound<K>, Bound<K>),
+    sender: Sender<Change<K>>,
+}
+
+/// A `BTreeSet` which notifies observers of every successful mutation of the
+/// wrapped tree, e.g. to invalidate caches or replicate changes.
+///
+/// Observers either register callbacks with [`on_insert`] and [`on_remove`],
+/// which run synchronously after the mutation, or [`watch`] a range of keys
+/// and receive the changes to it over a channel. A watcher is dropped as soon
+/// as its receiver is. Failed operations notify nobody.
+///
+/// [`on_insert`]: ObservedBTreeSet::on_insert
+/// [`on_remove`]: ObservedBTreeSet::on_remove
+/// [`watch`]: ObservedBTreeSet::watch
+pub struct ObservedBTreeSet<T: BTreeSet> {
+    inner: T,
+    on_insert: Vec<Callback<T::Key>>,
+    on_remove: Vec<Callback<T::Key>>,
+    watchers: Vec<Watcher<T::Key>>,
+}
+
+impl<T: BTreeSet> ObservedBTreeSet<T> {
+    pub fn new(inner: T) -> Self {
+        ObservedBTreeSet {
+            inner,
+            on_insert: Vec::new(),
+            on_remove: Vec::new(),
+            watchers: Vec::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the wrapped tree, dropping every observer.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Calls `callback` with every key inserted from now on.
+    pub fn on_insert(&mut self, callback: impl FnMut(&T::Key) + 'static) {
+        self.on_insert.push(Box::new(callback));
+    }
+
+    /// Calls `callback` with every key removed from now on.
+    pub fn on_remove(&mut self, callback: impl FnMut(&T::Key) + 'static) {
+        self.on_remove.push(Box::new(callback));
+    }
+}
+
+impl<T> ObservedBTreeSet<T>
+where
+    T: BTreeSet,
+    T::Key: Ord + Clone,
+{
+    /// Returns a receiver of every change made to a key within `range` from
+    /// now on.
+    pub fn watch<R: RangeBounds<T::Key>>(&mut self, range: R) -> Receiver<Change<T::Key>> {
+        let (sender, receiver) = channel();
+        let range = (range.start_bound().cloned(), range.end_bound().cloned());
+        self.watchers.push(Watcher { range, sender });
+        receiver
+    }
+
+    fn notify(&mut self, change: Change<T::Key>) {
+        let callbacks = match change {
+            Change::Inserted(_) => &mut self.on_insert,
+            Change::Removed(_) => &mut self.on_remove,
+        };
+        for callback in callbacks {
+            callback(change.key());
+        }
+
+        self.watchers.retain(|watcher| {
+            !watcher.range.contains(change.key()) || watcher.sender.send(change.clone()).is_ok()
+        });
+    }
+}
+
+impl<T> BTreeSet for ObservedBTreeSet<T>
+where
+    T: BTreeSet,
+    T::Key: Ord + Clone,
+{
+    type Key = T::Key;
+    const B: usize = T::B;
+
+    fn search(&self, key: &Self::Key) -> Result<&Self::Key> {
+        self.inner.search(key)
+    }
+
+    fn insert(&mut self, key: Self::Key) -> Result<()> {
+        self.inner.insert(key.clone())?;
+        self.notify(Change::Inserted(key));
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &Self::Key) -> Result<Self::Key> {
+        let removed = self.inner.remove(key)?;
+        self.notify(Change::Removed(removed.clone()));
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::SimpleBTreeSet;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_callbacks_see_successful_mutations() {
+        let mut tree = ObservedBTreeSet::new(SimpleBTreeSet::<i32>::new());
+        let seen = Rc::new(RefCell::new(Vec::new()));
+
+        let log = Rc::clone(&seen);
+        tree.on_insert(move |key| log.borrow_mut().push(Change::Inserted(*key)));
+        let log = Rc::clone(&seen);
+        tree.on_remove(move |key| log.borrow_mut().push(Change::Removed(*key)));
+
+        tree.insert(1).unwrap();
+        tree.insert(2).unwrap();
+        assert!(tree.insert(1).is_err());
+        tree.remove(&1).unwrap();
+        assert!(tree.remove(&1).is_err());
+
+        assert_eq!(
+            *seen.borrow(),
+            [Change::Inserted(1), Change::Inserted(2), Change::Removed(1)]
+        );
+        assert!(tree.get_ref().contains(&2));
+    }
+
+    #[test]
+    fn test_watchers_receive_changes_in_their_range() {
+        let mut tree = ObservedBTreeSet::new(SimpleBTreeSet::<i32>::new());
+        let low = tree.watch(..10);
+        let high = tree.watch(10..);
+
+        for key in [3, 15, 9, 10] {
+            tree.insert(key).unwrap();
+        }
+        tree.remove(&3).unwrap();
+
+        let low: Vec<_> = low.try_iter().collect();
+        assert_eq!(
+            low,
+            [Change::Inserted(3), Change::Inserted(9), Change::Removed(3)]
+        );
+        let high: Vec<_> = high.try_iter().collect();
+        assert_eq!(high, [Change::Inserted(15), Change::Inserted(10)]);
+    }
+
+    #[test]
+    fn test_dropped_watchers_are_forgotten() {
+        let mut tree = ObservedBTreeSet::new(SimpleBTreeSet::<i32>::new());
+        drop(tree.watch(..));
+        let kept = tree.watch(..);
+
+        tree.insert(1).unwrap();
+        assert_eq!(tree.watchers.len(), 1);
+        assert_eq!(kept.try_recv().unwrap(), Change::Inserted(1));
+    }
+}
@@ -0,0 +1,536 @@
+use super::sync::{AtomicPtr, AtomicU64, AtomicUsize, Ordering, fence, spin_loop};
+use crate::{Error, Result};
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::ptr;
+
+/// A key which fits in a machine word. Readers of an `OlcBTreeSet` copy keys
+/// out of nodes which a writer may be changing at the same time, which is
+/// only sound for keys that can be loaded atomically.
+pub trait Word: Copy + Ord {
+    fn to_word(self) -> u64;
+    fn from_word(word: u64) -> Self;
+}
+
+macro_rules! impl_word {
+    ($($ty:ty),*) => {$(
+        impl Word for $ty {
+            fn to_word(self) -> u64 {
+                self as u64
+            }
+
+            fn from_word(word: u64) -> Self {
+                word as $ty
+            }
+        }
+    )*};
+}
+
+impl_word!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+/// The low bit of a version is set while a writer holds the node. Unlocking
+/// sets it back, carrying into the bits above, so every unlock moves the
+/// version forward.
+const LOCKED: u64 = 1;
+
+/// The outcome of an optimistic read which raced with a writer, and has to
+/// be started over from the root.
+struct Restart;
+
+type Optimistic<T> = std::result::Result<T, Restart>;
+
+/// A concurrent B+ tree whose readers take no locks, through optimistic lock
+/// coupling.
+///
+/// Every node carries a version, which a writer bumps whenever it changes
+/// the node. Readers descend without writing to shared memory: they note the
+/// version of a node, read what they need from it, and check that the
+/// version has not moved on before trusting what they read, restarting from
+/// the root if it has. Writers lock only the leaf they change, and the
+/// parent of a node they split, by setting a bit of the version. Full nodes
+/// are split on the way down, so a split never climbs back up the tree.
+///
+/// Keys live in the leaves, which are linked from left to right for
+/// iteration. Nodes are never merged, and are only freed when the tree is
+/// dropped, so a reader never follows a pointer to freed memory. As a
+/// consequence, removals leave leaves underfull, or even empty.
+///
+/// The K type parameter represents the key type, and B is the branching
+/// factor, which bounds every node to `2 * B - 1` keys.
+pub struct OlcBTreeSet<K, const B: usize = 6> {
+    root: AtomicPtr<Node>,
+    _keys: PhantomData<K>,
+}
+
+/// A node of the tree, whose keys are stored as words so that readers can
+/// load them while a writer stores them.
+struct Node {
+    version: AtomicU64,
+    is_leaf: bool,
+    len: AtomicUsize,
+    keys: Box<[AtomicU64]>,
+    /// The children of an intermediate node, one more than its keys.
+    children: Box<[AtomicPtr<Node>]>,
+    /// The leaf to the right of a leaf.
+    next: AtomicPtr<Node>,
+}
+
+impl Node {
+    fn new(is_leaf: bool, max_keys: usize) -> Box<Node> {
+        let children = if is_leaf { 0 } else { max_keys + 1 };
+        Box::new(Node {
+            version: AtomicU64::new(0),
+            is_leaf,
+            len: AtomicUsize::new(0),
+            keys: (0..max_keys).map(|_| AtomicU64::new(0)).collect(),
+            children: (0..children)
+                .map(|_| AtomicPtr::new(ptr::null_mut()))
+                .collect(),
+            next: AtomicPtr::new(ptr::null_mut()),
+        })
+    }
+
+    /// Returns the version of the node, unless a writer holds it.
+    fn read_lock(&self) -> Optimistic<u64> {
+        let version = self.version.load(Ordering::Acquire);
+        if version & LOCKED == 0 {
+            Ok(version)
+        } else {
+            spin_loop();
+            Err(Restart)
+        }
+    }
+
+    /// Checks that the node is still at the given version, and so that
+    /// everything read from it since is consistent.
+    fn validate(&self, version: u64) -> Optimistic<()> {
+        fence(Ordering::Acquire);
+        if self.version.load(Ordering::Relaxed) == version {
+            Ok(())
+        } else {
+            Err(Restart)
+        }
+    }
+
+    /// Locks the node for writing, if it is still at the given version.
+    fn upgrade(&self, version: u64) -> Optimistic<()> {
+        self.version
+            .compare_exchange(
+                version,
+                version + LOCKED,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .map_err(|_| Restart)?;
+        fence(Ordering::Release);
+        Ok(())
+    }
+
+    fn unlock(&self) {
+        self.version.fetch_add(LOCKED, Ordering::Release);
+    }
+
+    /// Returns the number of keys, which a racing writer cannot push past the
+    /// capacity of the node.
+    fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed).min(self.keys.len())
+    }
+
+    fn is_full(&self) -> bool {
+        self.len() == self.keys.len()
+    }
+
+    fn key(&self, idx: usize) -> u64 {
+        self.keys[idx].load(Ordering::Relaxed)
+    }
+
+    fn set_key(&self, idx: usize, key: u64) {
+        self.keys[idx].store(key, Ordering::Relaxed);
+    }
+
+    fn child(&self, idx: usize) -> *mut Node {
+        self.children[idx].load(Ordering::Acquire)
+    }
+
+    fn set_child(&self, idx: usize, child: *mut Node) {
+        self.children[idx].store(child, Ordering::Release);
+    }
+
+    /// Searches the keys of the node, as read so far.
+    fn search<K: Word>(&self, key: K) -> std::result::Result<usize, usize> {
+        let (mut lo, mut hi) = (0, self.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match K::from_word(self.key(mid)).cmp(&key) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Ok(mid),
+            }
+        }
+        Err(lo)
+    }
+
+    /// Returns the child of an intermediate node which leads to the key. Keys
+    /// equal to a separator are in the subtree to its right.
+    fn child_for<K: Word>(&self, key: K) -> Optimistic<&Node> {
+        let idx = match self.search(key) {
+            Ok(idx) => idx + 1,
+            Err(idx) => idx,
+        };
+        let child = self.child(idx);
+        // SAFETY: Nodes are only freed when the tree is dropped, so a child
+        // pointer which was ever stored is still valid, stale or not.
+        unsafe { child.as_ref() }.ok_or(Restart)
+    }
+
+    /// Moves the upper half of the locked, full node into a new node, and
+    /// returns the separator of the two halves along with the new node.
+    fn split(&self) -> (u64, *mut Node) {
+        let len = self.len();
+        let mid = len / 2;
+        let right = Node::new(self.is_leaf, self.keys.len());
+        let separator = self.key(mid);
+        if self.is_leaf {
+            for (to, from) in (mid..len).enumerate() {
+                right.set_key(to, self.key(from));
+            }
+            right.len.store(len - mid, Ordering::Relaxed);
+            right
+                .next
+                .store(self.next.load(Ordering::Relaxed), Ordering::Relaxed);
+        } else {
+            for (to, from) in (mid + 1..len).enumerate() {
+                right.set_key(to, self.key(from));
+            }
+            for (to, from) in (mid + 1..=len).enumerate() {
+                right.set_child(to, self.child(from));
+            }
+            right.len.store(len - mid - 1, Ordering::Relaxed);
+        }
+
+        let right = Box::into_raw(right);
+        if self.is_leaf {
+            self.next.store(right, Ordering::Release);
+        }
+        self.len.store(mid, Ordering::Relaxed);
+        (separator, right)
+    }
+
+    /// Adds a separator and the child to its right to the locked, intermediate
+    /// node, which is not full.
+    fn insert_child<K: Word>(&self, separator: u64, right: *mut Node) {
+        let len = self.len();
+        let mut idx = len;
+        while idx > 0 && K::from_word(self.key(idx - 1)) > K::from_word(separator) {
+            self.set_key(idx, self.key(idx - 1));
+            self.set_child(idx + 1, self.child(idx));
+            idx -= 1;
+        }
+        self.set_key(idx, separator);
+        self.set_child(idx + 1, right);
+        self.len.store(len + 1, Ordering::Relaxed);
+    }
+}
+
+impl<K: Word, const B: usize> OlcBTreeSet<K, B> {
+    const MAX_KEYS: usize = 2 * B - 1;
+
+    pub fn new() -> Self {
+        OlcBTreeSet {
+            root: AtomicPtr::new(Box::into_raw(Node::new(true, Self::MAX_KEYS))),
+            _keys: PhantomData,
+        }
+    }
+
+    /// Returns the root along with its version, making sure that the node
+    /// was still the root when the version was read.
+    fn root(&self) -> Optimistic<(&Node, u64)> {
+        let root = self.root.load(Ordering::Acquire);
+        // SAFETY: Nodes are only freed when the tree is dropped.
+        let node = unsafe { &*root };
+        let version = node.read_lock()?;
+        if self.root.load(Ordering::Acquire) == root {
+            Ok((node, version))
+        } else {
+            Err(Restart)
+        }
+    }
+
+    /// Runs an optimistic operation until it gets through without racing a
+    /// writer.
+    fn retry<T>(&self, mut op: impl FnMut() -> Optimistic<T>) -> T {
+        loop {
+            if let Ok(result) = op() {
+                return result;
+            }
+        }
+    }
+
+    /// Descends to the leaf which holds the key, and returns it along with
+    /// the version it was found at.
+    fn find_leaf(&self, key: K) -> Optimistic<(&Node, u64)> {
+        let (mut node, mut version) = self.root()?;
+        while !node.is_leaf {
+            let child = node.child_for(key)?;
+            let child_version = child.read_lock()?;
+            // The child was the one leading to the key when its version was
+            // read, so any later change to it shows in that version.
+            node.validate(version)?;
+            (node, version) = (child, child_version);
+        }
+        Ok((node, version))
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.retry(|| {
+            let (leaf, version) = self.find_leaf(*key)?;
+            let found = leaf.search(*key).is_ok();
+            leaf.validate(version)?;
+            Ok(found)
+        })
+    }
+
+    pub fn insert(&self, key: K) -> Result<()> {
+        self.retry(|| self.try_insert(key))
+    }
+
+    fn try_insert(&self, key: K) -> Optimistic<Result<()>> {
+        let (mut node, mut version) = self.root()?;
+        let mut parent: Option<(&Node, u64)> = None;
+        loop {
+            if node.is_full() {
+                self.split(node, version, parent)?;
+                // Start over, now that the node has room.
+                return Err(Restart);
+            }
+            if node.is_leaf {
+                break;
+            }
+            let child = node.child_for(key)?;
+            let child_version = child.read_lock()?;
+            node.validate(version)?;
+            parent = Some((node, version));
+            (node, version) = (child, child_version);
+        }
+
+        node.upgrade(version)?;
+        let result = match node.search(key) {
+            Ok(_) => Err(Error::KeyAlreadyExists),
+            Err(idx) => {
+                let len = node.len();
+                for from in (idx..len).rev() {
+                    node.set_key(from + 1, node.key(from));
+                }
+                node.set_key(idx, key.to_word());
+                node.len.store(len + 1, Ordering::Relaxed);
+                Ok(())
+            }
+        };
+        node.unlock();
+        Ok(result)
+    }
+
+    /// Splits the full node, which was read at the given version, locking it
+    /// and its parent. The parent was not full when it was read, and locking
+    /// it checks that it has not changed since.
+    fn split(&self, node: &Node, version: u64, parent: Option<(&Node, u64)>) -> Optimistic<()> {
+        if let Some((parent, parent_version)) = parent {
+            parent.upgrade(parent_version)?;
+            if node.upgrade(version).is_err() {
+                parent.unlock();
+                return Err(Restart);
+            }
+            let (separator, right) = node.split();
+            parent.insert_child::<K>(separator, right);
+            node.unlock();
+            parent.unlock();
+        } else {
+            node.upgrade(version)?;
+            // The root is only replaced by a writer holding it.
+            if !ptr::eq(self.root.load(Ordering::Acquire), node) {
+                node.unlock();
+                return Err(Restart);
+            }
+            let (separator, right) = node.split();
+            let root = Node::new(false, Self::MAX_KEYS);
+            root.set_key(0, separator);
+            root.set_child(0, ptr::from_ref(node).cast_mut());
+            root.set_child(1, right);
+            root.len.store(1, Ordering::Relaxed);
+            self.root.store(Box::into_raw(root), Ordering::Release);
+            node.unlock();
+        }
+        Ok(())
+    }
+
+    pub fn remove(&self, key: &K) -> Result<K> {
+        self.retry(|| {
+            let (leaf, version) = self.find_leaf(*key)?;
+            leaf.upgrade(version)?;
+            let result = match leaf.search(*key) {
+                Ok(idx) => {
+                    let len = leaf.len();
+                    for from in idx + 1..len {
+                        leaf.set_key(from - 1, leaf.key(from));
+                    }
+                    leaf.len.store(len - 1, Ordering::Relaxed);
+                    Ok(*key)
+                }
+                Err(_) => Err(Error::KeyNotFound),
+            };
+            leaf.unlock();
+            Ok(result)
+        })
+    }
+
+    /// Returns an iterator over the keys of the tree, in ascending order.
+    ///
+    /// The iterator walks the leaves from left to right, copying the keys of
+    /// each leaf as of the moment it reaches it. Keys present for the whole
+    /// iteration are yielded exactly once; keys inserted or removed during it
+    /// may or may not be.
+    pub fn iter(&self) -> Iter<'_, K, B> {
+        let first = self.retry(|| {
+            let (mut node, mut version) = self.root()?;
+            while !node.is_leaf {
+                let child = node.child(0);
+                // SAFETY: Nodes are only freed when the tree is dropped.
+                let child = unsafe { child.as_ref() }.ok_or(Restart)?;
+                let child_version = child.read_lock()?;
+                node.validate(version)?;
+                (node, version) = (child, child_version);
+            }
+            Ok(ptr::from_ref(node))
+        });
+        Iter {
+            tree: self,
+            next: first,
+            last: None,
+            keys: Vec::new().into_iter(),
+        }
+    }
+}
+
+impl<K: Word, const B: usize> Default for OlcBTreeSet<K, B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, const B: usize> Drop for OlcBTreeSet<K, B> {
+    fn drop(&mut self) {
+        let mut nodes = vec![self.root.load(Ordering::Acquire)];
+        while let Some(node) = nodes.pop() {
+            // SAFETY: The tree is dropped, so no one else holds a node, and
+            // every node is the child of exactly one other node, or the root.
+            let node = unsafe { Box::from_raw(node) };
+            if !node.is_leaf {
+                nodes.extend((0..=node.len()).map(|idx| node.child(idx)));
+            }
+        }
+    }
+}
+
+/// An iterator over the keys of an `OlcBTreeSet`, as returned by
+/// `OlcBTreeSet::iter`.
+pub struct Iter<'a, K, const B: usize> {
+    tree: &'a OlcBTreeSet<K, B>,
+    next: *const Node,
+    /// The largest key yielded so far. A leaf which is read again after a
+    /// split only yields the keys past it.
+    last: Option<K>,
+    keys: std::vec::IntoIter<K>,
+}
+
+impl<K: Word, const B: usize> Iterator for Iter<'_, K, B> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<K> {
+        loop {
+            if let Some(key) = self.keys.next() {
+                if self.last.is_some_and(|last| key <= last) {
+                    continue;
+                }
+                self.last = Some(key);
+                return Some(key);
+            }
+
+            // SAFETY: Nodes are only freed when the tree is dropped, and the
+            // iterator borrows the tree.
+            let leaf = unsafe { self.next.as_ref()? };
+            let (keys, next) = self.tree.retry(|| {
+                let version = leaf.read_lock()?;
+                let keys: Vec<K> = (0..leaf.len())
+                    .map(|idx| K::from_word(leaf.key(idx)))
+                    .collect();
+                let next = leaf.next.load(Ordering::Acquire);
+                leaf.validate(version)?;
+                Ok((keys, next))
+            });
+            self.keys = keys.into_iter();
+            self.next = next;
+        }
+    }
+}
+
+impl<K: Word, const B: usize> FusedIterator for Iter<'_, K, B> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_insert_contains_remove() {
+        let tree = OlcBTreeSet::<i32, 2>::new();
+        for key in (0..1000).map(|i| i * 7919 % 1000 - 500) {
+            tree.insert(key).unwrap();
+        }
+        assert!(matches!(tree.insert(7), Err(Error::KeyAlreadyExists)));
+        assert!(tree.iter().eq(-500..500));
+
+        for key in (-500..500).filter(|key| key % 3 == 0) {
+            assert_eq!(tree.remove(&key).unwrap(), key);
+        }
+        assert!(matches!(tree.remove(&0), Err(Error::KeyNotFound)));
+        assert!(!tree.contains(&3) && tree.contains(&4));
+        assert!(tree.iter().eq((-500..500).filter(|key| key % 3 != 0)));
+    }
+
+    #[test]
+    fn test_concurrent_readers_and_writers() {
+        let tree = OlcBTreeSet::<u64, 3>::new();
+        for key in (0..20_000).step_by(2) {
+            tree.insert(key).unwrap();
+        }
+
+        // The writers insert the odd keys, and remove the even keys which
+        // are not multiples of four, so the readers see the multiples of
+        // four throughout.
+        thread::scope(|scope| {
+            for thread in 0..4u64 {
+                let tree = &tree;
+                scope.spawn(move || {
+                    for key in (0..20_000).filter(|key| key % 8 == 2 * thread + 1) {
+                        tree.insert(key).unwrap();
+                    }
+                    for key in (0..20_000).filter(|key| key % 8 == (4 * thread + 2) % 8) {
+                        let _ = tree.remove(&key);
+                    }
+                });
+                scope.spawn(move || {
+                    for _ in 0..10 {
+                        let keys: Vec<u64> = tree.iter().collect();
+                        assert!(keys.is_sorted_by(|a, b| a < b));
+                        let kept = keys.iter().filter(|&&key| key % 4 == 0).count();
+                        assert_eq!(kept, 5000);
+                    }
+                    assert!((0..20_000).step_by(4).all(|key| tree.contains(&key)));
+                });
+            }
+        });
+
+        let expected = (0..20_000).filter(|key| key % 2 == 1 || key % 4 == 0);
+        assert!(tree.iter().eq(expected));
+    }
+}
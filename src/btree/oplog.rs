@@ -0,0 +1,196 @@
+//! Recording the mutations of a tree into an append-only log, and replaying
+//! such a log to rebuild the tree.
+//!
+//! The log is a sequence of records, one per successful mutation:
+//!
+//! ```text
+//! record  operation (1) | key length (4) | key (key length) | checksum (4)
+//! ```
+//!
+//! The operation is 0 for an insert and 1 for a remove, and the checksum
+//! covers everything before it. A record cut short at the end of the log,
+//! such as a torn write, is discarded when the log is replayed.
+
+use crate::storage::{PageKey, crc32};
+use crate::{BTreeSet, Error, Result};
+use std::io::{Read, Write};
+
+const INSERT: u8 = 0;
+const REMOVE: u8 = 1;
+const RECORD_HEADER_SIZE: usize = 5;
+const CHECKSUM_SIZE: usize = 4;
+
+/// A `BTreeSet` which appends every successful insert and remove on the
+/// wrapped tree to a log, before applying it.
+///
+/// Replaying the log with [`replay`] into a tree equal to the one originally
+/// wrapped, usually an empty one, rebuilds an identical tree. Failed
+/// operations are not logged. The log is not flushed by the tree.
+pub struct LoggedBTreeSet<T, W = Vec<u8>> {
+    inner: T,
+    log: W,
+}
+
+impl<T, W> LoggedBTreeSet<T, W> {
+    pub fn new(inner: T, log: W) -> Self {
+        LoggedBTreeSet { inner, log }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn log(&self) -> &W {
+        &self.log
+    }
+
+    pub fn log_mut(&mut self) -> &mut W {
+        &mut self.log
+    }
+
+    pub fn into_parts(self) -> (T, W) {
+        (self.inner, self.log)
+    }
+}
+
+impl<T, W> LoggedBTreeSet<T, W>
+where
+    T: BTreeSet,
+    T::Key: PageKey,
+    W: Write,
+{
+    fn append(&mut self, operation: u8, key: &T::Key) -> Result<()> {
+        let len = key.encoded_len();
+        let mut record = vec![0; RECORD_HEADER_SIZE + len + CHECKSUM_SIZE];
+        record[0] = operation;
+        record[1..RECORD_HEADER_SIZE].copy_from_slice(&(len as u32).to_le_bytes());
+        key.encode_into(&mut record[RECORD_HEADER_SIZE..RECORD_HEADER_SIZE + len]);
+
+        let (body, checksum) = record.split_at_mut(RECORD_HEADER_SIZE + len);
+        checksum.copy_from_slice(&crc32(&[body]).to_le_bytes());
+        self.log.write_all(&record)?;
+        Ok(())
+    }
+}
+
+impl<T, W> BTreeSet for LoggedBTreeSet<T, W>
+where
+    T: BTreeSet,
+    T::Key: PageKey,
+    W: Write,
+{
+    type Key = T::Key;
+    const B: usize = T::B;
+
+    fn search(&self, key: &Self::Key) -> Result<&Self::Key> {
+        self.inner.search(key)
+    }
+
+    fn insert(&mut self, key: Self::Key) -> Result<()> {
+        if self.inner.contains(&key) {
+            return Err(Error::KeyAlreadyExists);
+        }
+        self.append(INSERT, &key)?;
+        self.inner.insert(key)
+    }
+
+    fn remove(&mut self, key: &Self::Key) -> Result<Self::Key> {
+        if !self.inner.contains(key) {
+            return Err(Error::KeyNotFound);
+        }
+        self.append(REMOVE, key)?;
+        self.inner.remove(key)
+    }
+}
+
+/// Applies every operation recorded in the log to the tree, and returns it.
+///
+/// Fails if a record is corrupted, or if an operation fails on the tree,
+/// which happens when the tree does not start out as the one the log was
+/// recorded against.
+pub fn replay<T>(mut tree: T, mut log: impl Read) -> Result<T>
+where
+    T: BTreeSet,
+    T::Key: PageKey,
+{
+    let mut contents = Vec::new();
+    log.read_to_end(&mut contents)?;
+
+    let mut rest = contents.as_slice();
+    while rest.len() >= RECORD_HEADER_SIZE {
+        let len = u32::from_le_bytes(rest[1..RECORD_HEADER_SIZE].try_into().unwrap()) as usize;
+        let Some(record) = rest.get(..RECORD_HEADER_SIZE + len + CHECKSUM_SIZE) else {
+            break;
+        };
+        rest = &rest[record.len()..];
+
+        let (body, checksum) = record.split_at(RECORD_HEADER_SIZE + len);
+        if crc32(&[body]).to_le_bytes() != checksum {
+            return Err(Error::CorruptPage {
+                reason: "log record has an invalid checksum".to_string(),
+            });
+        }
+
+        let key = T::Key::decode_from(&body[RECORD_HEADER_SIZE..]);
+        match body[0] {
+            INSERT => tree.insert(key)?,
+            REMOVE => drop(tree.remove(&key)?),
+            operation => {
+                return Err(Error::CorruptPage {
+                    reason: format!("log record has an unknown operation {operation}"),
+                });
+            }
+        }
+    }
+
+    Ok(tree)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::SimpleBTreeSet;
+    use crate::workload::{Kind, Workload};
+
+    #[test]
+    fn test_replay_rebuilds_identical_tree() {
+        for kind in Kind::ALL {
+            let mut tree = LoggedBTreeSet::new(SimpleBTreeSet::<u64>::new(), Vec::new());
+            Workload::new(kind, 7)
+                .with_key_space(500)
+                .check(&mut tree, 2000);
+
+            let (tree, log) = tree.into_parts();
+            let replayed = replay(SimpleBTreeSet::<u64>::new(), log.as_slice()).unwrap();
+            assert!(replayed.iter().eq(tree.iter()), "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn test_replay_discards_torn_record() {
+        let mut tree = LoggedBTreeSet::new(SimpleBTreeSet::<Vec<u8>>::new(), Vec::new());
+        for key in [b"apple".to_vec(), b"banana".to_vec()] {
+            tree.insert(key).unwrap();
+        }
+
+        let (_, log) = tree.into_parts();
+        let replayed: SimpleBTreeSet<Vec<u8>> =
+            replay(SimpleBTreeSet::new(), &log[..log.len() - 1]).unwrap();
+        assert!(replayed.iter().eq([b"apple".to_vec()].iter()));
+    }
+
+    #[test]
+    fn test_replay_rejects_corrupted_record() {
+        let mut tree = LoggedBTreeSet::new(SimpleBTreeSet::<u64>::new(), Vec::new());
+        tree.insert(1).unwrap();
+        tree.insert(2).unwrap();
+        tree.remove(&1).unwrap();
+
+        let (_, mut log) = tree.into_parts();
+        log[RECORD_HEADER_SIZE] ^= 1;
+        assert!(matches!(
+            replay(SimpleBTreeSet::<u64>::new(), log.as_slice()),
+            Err(Error::CorruptPage { .. })
+        ));
+    }
+}
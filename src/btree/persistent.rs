@@ -0,0 +1,502 @@
+use super::array::Array;
+use crate::{BTreeSet, Error, Result};
+use std::alloc;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::iter::FusedIterator;
+use std::mem;
+use std::sync::Arc;
+
+/// A copy-on-write B-tree, whose nodes are shared between the versions of
+/// the tree through `Arc`s.
+///
+/// Cloning the tree is cheap, and `with` and `without` return a new version
+/// while leaving the original untouched. A new version copies only the nodes
+/// on the path to the changed key, and shares every other subtree with the
+/// version it was made from. The mutating methods of the `BTreeSet` trait
+/// copy nodes the same way, but only the ones which are shared.
+///
+/// The K type parameter represents the key type, and B is the branching factor.
+pub struct PersistentBTreeSet<K, const B: usize = 6> {
+    root: Option<Arc<Node<K, B>>>,
+}
+
+/// A node shared between versions. Leaf nodes are the ones with no children.
+#[derive(Clone)]
+struct Node<K, const B: usize> {
+    keys: Array<K, B>,
+    children: Array<Arc<Node<K, B>>, B>,
+}
+
+impl<K, const B: usize> Node<K, B> {
+    const MIN_KEYS: usize = B - 1;
+    const MAX_KEYS: usize = 2 * B - 1;
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+}
+
+impl<K, const B: usize> Clone for PersistentBTreeSet<K, B> {
+    fn clone(&self) -> Self {
+        PersistentBTreeSet {
+            root: self.root.clone(),
+        }
+    }
+}
+
+impl<K: Ord + Clone, const B: usize> PersistentBTreeSet<K, B> {
+    pub fn new() -> Self {
+        PersistentBTreeSet { root: None }
+    }
+
+    /// Returns a new version of the tree which also contains the key.
+    pub fn with(&self, key: K) -> Result<Self> {
+        let mut tree = self.clone();
+        tree.insert(key)?;
+        Ok(tree)
+    }
+
+    /// Returns a new version of the tree which no longer contains the key.
+    pub fn without(&self, key: &K) -> Result<Self> {
+        let mut tree = self.clone();
+        tree.remove(key)?;
+        Ok(tree)
+    }
+
+    /// Inserts the key, which is known to be missing, into the subtree,
+    /// returning the hoisted key and the new sibling when the node had to be
+    /// split.
+    fn insert_into(node: &mut Arc<Node<K, B>>, key: K) -> Option<(K, Arc<Node<K, B>>)> {
+        let node = Arc::make_mut(node);
+        let idx = node.keys.binary_search(&key).unwrap_err();
+
+        if node.is_leaf() {
+            node.keys.insert(idx, key);
+        } else if let Some((hoist, sibling)) = Self::insert_into(&mut node.children[idx], key) {
+            node.keys.insert(idx, hoist);
+            node.children.insert(idx + 1, sibling);
+        }
+
+        if node.keys.len() <= Node::<K, B>::MAX_KEYS {
+            return None;
+        }
+
+        let keys = node.keys.split_off(B);
+        let hoist = node.keys.pop_back().unwrap();
+        let children = if node.is_leaf() {
+            Array::new()
+        } else {
+            node.children.split_off(B)
+        };
+        Some((hoist, Arc::new(Node { keys, children })))
+    }
+
+    /// Removes the key, which is known to be present, from the subtree,
+    /// leaving the root of the subtree possibly deficient, but all of its
+    /// descendants valid.
+    fn remove_from(node: &mut Arc<Node<K, B>>, key: &K) -> K {
+        let node = Arc::make_mut(node);
+        let result = node.keys.binary_search(key);
+
+        if node.is_leaf() {
+            return node.keys.remove(result.unwrap()).unwrap();
+        }
+
+        let (removed, idx) = match result {
+            Ok(idx) => {
+                let predecessor = Self::remove_last(&mut node.children[idx]);
+                (mem::replace(&mut node.keys[idx], predecessor), idx)
+            }
+            Err(idx) => (Self::remove_from(&mut node.children[idx], key), idx),
+        };
+
+        Self::fix_deficient_child(node, idx);
+        removed
+    }
+
+    /// Removes the greatest key of the subtree.
+    fn remove_last(node: &mut Arc<Node<K, B>>) -> K {
+        let node = Arc::make_mut(node);
+        if node.is_leaf() {
+            return node.keys.pop_back().unwrap();
+        }
+
+        let idx = node.children.len() - 1;
+        let key = Self::remove_last(&mut node.children[idx]);
+        Self::fix_deficient_child(node, idx);
+        key
+    }
+
+    /// Refills the child at the given index if it became deficient, either by
+    /// rotating a key from one of its siblings, or by merging it with one.
+    fn fix_deficient_child(node: &mut Node<K, B>, idx: usize) {
+        if node.children[idx].keys.len() >= Node::<K, B>::MIN_KEYS {
+            return;
+        }
+
+        let can_spare = |sibling: &Arc<Node<K, B>>| sibling.keys.len() > Node::<K, B>::MIN_KEYS;
+        let left = idx.checked_sub(1).map(|i| &node.children[i]);
+        let right = node.children.get(idx + 1);
+
+        match (left, right) {
+            (Some(left), _) if can_spare(left) => Self::rotate_right(node, idx),
+            (_, Some(right)) if can_spare(right) => Self::rotate_left(node, idx),
+            (Some(_), _) => Self::merge(node, idx - 1),
+            (None, Some(_)) => Self::merge(node, idx),
+            (None, None) => unreachable!("intermediate nodes have at least two children"),
+        }
+    }
+
+    /// Moves the last key of the left sibling up into the parent, and the
+    /// separating parent key down into the child.
+    fn rotate_right(node: &mut Node<K, B>, idx: usize) {
+        let left = Arc::make_mut(&mut node.children[idx - 1]);
+        let key = left.keys.pop_back().unwrap();
+        let grandchild = left.children.pop_back();
+        let separator = mem::replace(&mut node.keys[idx - 1], key);
+
+        let child = Arc::make_mut(&mut node.children[idx]);
+        child.keys.push_front(separator);
+        if let Some(grandchild) = grandchild {
+            child.children.push_front(grandchild);
+        }
+    }
+
+    /// Moves the first key of the right sibling up into the parent, and the
+    /// separating parent key down into the child.
+    fn rotate_left(node: &mut Node<K, B>, idx: usize) {
+        let right = Arc::make_mut(&mut node.children[idx + 1]);
+        let key = right.keys.pop_front().unwrap();
+        let grandchild = right.children.pop_front();
+        let separator = mem::replace(&mut node.keys[idx], key);
+
+        let child = Arc::make_mut(&mut node.children[idx]);
+        child.keys.push_back(separator);
+        if let Some(grandchild) = grandchild {
+            child.children.push_back(grandchild);
+        }
+    }
+
+    /// Merges the child after the given index into the child at it, together
+    /// with the separating parent key.
+    fn merge(node: &mut Node<K, B>, idx: usize) {
+        let separator = node.keys.remove(idx).unwrap();
+        let right = Arc::unwrap_or_clone(node.children.remove(idx + 1).unwrap());
+
+        let left = Arc::make_mut(&mut node.children[idx]);
+        left.keys.push_back(separator);
+        left.keys.extend(right.keys);
+        left.children.extend(right.children);
+    }
+}
+
+impl<K, const B: usize> PersistentBTreeSet<K, B> {
+    /// Returns an iterator over the keys of the tree, in ascending order.
+    pub fn iter(&self) -> Iter<'_, K, B> {
+        let mut iter = Iter { stack: Vec::new() };
+        if let Some(root) = &self.root {
+            iter.descend(root);
+        }
+        iter
+    }
+
+    /// Returns the number of bytes allocated for the nodes of this version,
+    /// each of which sits next to the reference counts of its `Arc`. Nodes
+    /// shared with other versions are counted in full by each of them.
+    /// Memory owned by the keys themselves is not included.
+    pub fn heap_size(&self) -> usize {
+        let counts = alloc::Layout::new::<[usize; 2]>();
+        let (layout, _) = counts.extend(alloc::Layout::new::<Node<K, B>>()).unwrap();
+        let node_size = layout.pad_to_align().size();
+
+        let mut nodes = 0;
+        let mut stack: Vec<&Arc<Node<K, B>>> = self.root.iter().collect();
+        while let Some(node) = stack.pop() {
+            nodes += 1;
+            stack.extend(node.children.iter());
+        }
+        nodes * node_size
+    }
+}
+
+/// Versions are equal when they hold equal keys. Versions which still share
+/// their root are equal without comparing any keys.
+impl<K: PartialEq, const B: usize> PartialEq for PersistentBTreeSet<K, B> {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.root, &other.root) {
+            (Some(a), Some(b)) if Arc::ptr_eq(a, b) => true,
+            _ => self.iter().eq(other.iter()),
+        }
+    }
+}
+
+impl<K: Eq, const B: usize> Eq for PersistentBTreeSet<K, B> {}
+
+impl<K: PartialOrd, const B: usize> PartialOrd for PersistentBTreeSet<K, B> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<K: Ord, const B: usize> Ord for PersistentBTreeSet<K, B> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+/// Hashes the keys in order, followed by their count, like `SimpleBTreeSet`.
+impl<K: Hash, const B: usize> Hash for PersistentBTreeSet<K, B> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut len = 0;
+        for key in self.iter() {
+            key.hash(state);
+            len += 1;
+        }
+        state.write_usize(len);
+    }
+}
+
+impl<K: Ord + Clone, const B: usize> Default for PersistentBTreeSet<K, B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord + Clone, const B: usize> BTreeSet for PersistentBTreeSet<K, B> {
+    type Key = K;
+    const B: usize = B;
+
+    fn search(&self, key: &Self::Key) -> Result<&Self::Key> {
+        let mut node = self.root.as_ref().ok_or(Error::KeyNotFound)?;
+        loop {
+            match node.keys.binary_search(key) {
+                Ok(idx) => return Ok(&node.keys[idx]),
+                Err(_) if node.is_leaf() => return Err(Error::KeyNotFound),
+                Err(idx) => node = &node.children[idx],
+            }
+        }
+    }
+
+    fn insert(&mut self, key: Self::Key) -> Result<()> {
+        // Checking first keeps a failed insertion from copying shared nodes.
+        if self.contains(&key) {
+            return Err(Error::KeyAlreadyExists);
+        }
+
+        let Some(root) = &mut self.root else {
+            let mut keys = Array::new();
+            keys.push_back(key);
+            self.root = Some(Arc::new(Node {
+                keys,
+                children: Array::new(),
+            }));
+            return Ok(());
+        };
+
+        if let Some((hoist, sibling)) = Self::insert_into(root, key) {
+            let mut keys = Array::new();
+            keys.push_back(hoist);
+            let children = [root.clone(), sibling].into_iter().collect();
+            *root = Arc::new(Node { keys, children });
+        }
+
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &Self::Key) -> Result<Self::Key> {
+        if !self.contains(key) {
+            return Err(Error::KeyNotFound);
+        }
+
+        let root = self.root.as_mut().unwrap();
+        let removed = Self::remove_from(root, key);
+
+        if root.keys.is_empty() {
+            self.root = root.children.first().cloned();
+        }
+
+        Ok(removed)
+    }
+}
+
+impl<K: Ord + Clone, const B: usize> FromIterator<K> for PersistentBTreeSet<K, B> {
+    /// Collects the keys into a tree, skipping the duplicates.
+    fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Self {
+        let mut tree = Self::new();
+        for key in iter {
+            let _ = tree.insert(key);
+        }
+        tree
+    }
+}
+
+impl<'a, K, const B: usize> IntoIterator for &'a PersistentBTreeSet<K, B> {
+    type Item = &'a K;
+    type IntoIter = Iter<'a, K, B>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the keys of a `PersistentBTreeSet`, in ascending order.
+pub struct Iter<'a, K, const B: usize> {
+    /// The nodes from the root down to the current one, each with the index
+    /// of the next key to yield from it.
+    stack: Vec<(&'a Node<K, B>, usize)>,
+}
+
+impl<'a, K, const B: usize> Iter<'a, K, B> {
+    /// Pushes the node and its leftmost descendants onto the stack.
+    fn descend(&mut self, mut node: &'a Node<K, B>) {
+        loop {
+            self.stack.push((node, 0));
+            match node.children.first() {
+                Some(child) => node = child,
+                None => return,
+            }
+        }
+    }
+}
+
+impl<'a, K, const B: usize> Iterator for Iter<'a, K, B> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, idx) = self.stack.last_mut()?;
+            let node: &'a Node<K, B> = node;
+            if *idx == node.keys.len() {
+                self.stack.pop();
+                continue;
+            }
+
+            *idx += 1;
+            let key = &node.keys[*idx - 1];
+            if let Some(child) = node.children.get(*idx) {
+                self.descend(child);
+            }
+            return Some(key);
+        }
+    }
+}
+
+impl<K, const B: usize> FusedIterator for Iter<'_, K, B> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::{DifferentialTester, ReferenceBTreeSet};
+    use crate::test_btree_impl;
+
+    test_btree_impl!(PersistentBTreeSet);
+
+    #[test]
+    fn test_agrees_with_reference_on_mixed_operations() {
+        let mut tester = DifferentialTester::new(
+            PersistentBTreeSet::<usize, 2>::new(),
+            ReferenceBTreeSet::new(),
+        );
+
+        for i in 0..20_000usize {
+            let key = (i * 7919) % 503;
+            let _ = match i % 3 {
+                0 => tester.remove(&key).map(|_| ()),
+                _ => tester.insert(key),
+            };
+        }
+    }
+
+    #[test]
+    fn test_new_versions_leave_the_original_untouched() {
+        let mut versions = vec![PersistentBTreeSet::<usize, 2>::new()];
+        for key in 0..200 {
+            let next = versions.last().unwrap().with(key).unwrap();
+            versions.push(next);
+        }
+        for key in 0..200 {
+            let next = versions.last().unwrap().without(&(key * 7 % 200)).unwrap();
+            versions.push(next);
+        }
+
+        for (version, tree) in versions.iter().enumerate() {
+            for key in 0..200 {
+                let removed = (0..version.saturating_sub(200)).any(|i| i * 7 % 200 == key);
+                let present = key < version && !removed;
+                assert_eq!(tree.contains(&key), present, "version {version}, key {key}");
+            }
+        }
+    }
+
+    fn nodes<K, const B: usize>(node: &Arc<Node<K, B>>, found: &mut Vec<*const Node<K, B>>) {
+        found.push(Arc::as_ptr(node));
+        for child in &node.children {
+            nodes(child, found);
+        }
+    }
+
+    #[test]
+    fn test_unchanged_subtrees_are_shared() {
+        let mut tree = PersistentBTreeSet::<usize, 2>::new();
+        for key in 0..1000 {
+            tree.insert(key * 2).unwrap();
+        }
+
+        let mut original = Vec::new();
+        nodes(tree.root.as_ref().unwrap(), &mut original);
+        let height =
+            std::iter::successors(tree.root.as_ref(), |node| node.children.first()).count();
+
+        for updated in [tree.with(501).unwrap(), tree.without(&500).unwrap()] {
+            let mut found = Vec::new();
+            nodes(updated.root.as_ref().unwrap(), &mut found);
+
+            // Only the path to the key, and the siblings it borrowed from or
+            // merged with, are copied.
+            let copied = found.iter().filter(|node| !original.contains(node)).count();
+            assert!(
+                copied <= 2 * height,
+                "{copied} of {} nodes copied",
+                found.len()
+            );
+        }
+    }
+
+    #[test]
+    fn test_iter_yields_keys_in_order() {
+        let tree: PersistentBTreeSet<usize, 2> = (0..500).map(|i| i * 7919 % 500).collect();
+        assert!(tree.iter().copied().eq(0..500));
+        assert_eq!(PersistentBTreeSet::<usize>::new().iter().next(), None);
+    }
+
+    #[test]
+    fn test_versions_compare_by_their_keys() {
+        let tree: PersistentBTreeSet<usize, 2> = (0..100).collect();
+        let round_trip = tree.with(500).unwrap().without(&500).unwrap();
+        assert!(tree == round_trip);
+        assert!(tree == tree.clone());
+        assert!(tree < tree.without(&0).unwrap());
+        assert!(tree > tree.without(&99).unwrap());
+
+        use std::hash::BuildHasher;
+        let state = std::hash::RandomState::new();
+        assert_eq!(state.hash_one(&tree), state.hash_one(&round_trip));
+    }
+
+    #[test]
+    fn test_failed_updates_do_not_copy_nodes() {
+        let mut tree = PersistentBTreeSet::<usize, 2>::new();
+        for key in 0..100 {
+            tree.insert(key).unwrap();
+        }
+
+        let version = tree.clone();
+        assert!(matches!(tree.insert(50), Err(Error::KeyAlreadyExists)));
+        assert!(matches!(tree.remove(&100), Err(Error::KeyNotFound)));
+        assert!(Arc::ptr_eq(
+            tree.root.as_ref().unwrap(),
+            version.root.as_ref().unwrap()
+        ));
+    }
+}
@@ -0,0 +1,70 @@
+use std::ops::Bound;
+
+/// Keys made of a sequence of units, ordered lexicographically, which can be
+/// scanned by prefix: byte strings, and strings.
+pub trait Prefixed: Ord + Sized {
+    /// The borrowed form of a prefix of the key.
+    type Prefix: ?Sized;
+
+    /// Returns the bounds of the range which holds exactly the keys starting
+    /// with the prefix.
+    ///
+    /// The range starts at the prefix itself, and ends before the smallest
+    /// key greater than every key with the prefix, which is the prefix with
+    /// its last unit incremented. Units which cannot be incremented are
+    /// dropped first, and if there are none left, the range is unbounded.
+    fn prefix_range(prefix: &Self::Prefix) -> (Bound<Self>, Bound<Self>);
+}
+
+impl Prefixed for Vec<u8> {
+    type Prefix = [u8];
+
+    fn prefix_range(prefix: &[u8]) -> (Bound<Self>, Bound<Self>) {
+        let mut end = prefix.to_vec();
+        while let Some(last) = end.pop() {
+            if last < u8::MAX {
+                end.push(last + 1);
+                return (Bound::Included(prefix.to_vec()), Bound::Excluded(end));
+            }
+        }
+        (Bound::Included(prefix.to_vec()), Bound::Unbounded)
+    }
+}
+
+/// Strings are ordered by their UTF-8 encoding, which orders them by their
+/// characters, so the last character is incremented instead of the last byte.
+impl Prefixed for String {
+    type Prefix = str;
+
+    fn prefix_range(prefix: &str) -> (Bound<Self>, Bound<Self>) {
+        let mut end = prefix.to_string();
+        while let Some(last) = end.pop() {
+            // Skips over the surrogates, which are not characters.
+            let next = (last as u32 + 1..=char::MAX as u32).find_map(char::from_u32);
+            if let Some(next) = next {
+                end.push(next);
+                return (Bound::Included(prefix.to_string()), Bound::Excluded(end));
+            }
+        }
+        (Bound::Included(prefix.to_string()), Bound::Unbounded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_range_ends_after_the_last_key_with_the_prefix() {
+        let range = Vec::<u8>::prefix_range(b"ab\xff\xff");
+        assert_eq!(range.1, Bound::Excluded(b"ac".to_vec()));
+        assert_eq!(Vec::<u8>::prefix_range(b"\xff").1, Bound::Unbounded);
+        assert_eq!(Vec::<u8>::prefix_range(b"").1, Bound::Unbounded);
+
+        assert_eq!(String::prefix_range("ab").1, Bound::Excluded("ac".into()));
+        let range = String::prefix_range("a\u{d7ff}");
+        assert_eq!(range.1, Bound::Excluded("a\u{e000}".into()));
+        let range = String::prefix_range("a\u{10ffff}");
+        assert_eq!(range.1, Bound::Excluded("b".into()));
+    }
+}
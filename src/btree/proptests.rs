@@ -0,0 +1,93 @@
+//! Differential property tests, which apply random sequences of operations to
+//! `SimpleBTreeSet` and to `ReferenceBTreeSet`, and expect identical results.
+//! On failure, proptest shrinks the sequence to a minimal failing one.
+
+use super::{ReferenceBTreeSet, SimpleBTreeSet};
+use crate::workload::{Kind, Workload};
+use crate::{BTreeSet, Result};
+use proptest::prelude::*;
+use std::mem::discriminant;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Insert(u16),
+    Remove(u16),
+    Search(u16),
+}
+
+/// Generates operations over a small key domain, so that the sequences
+/// revisit keys often, and both trees grow and shrink repeatedly.
+fn op(domain: u16) -> impl Strategy<Value = Op> {
+    prop_oneof![
+        3 => (0..domain).prop_map(Op::Insert),
+        2 => (0..domain).prop_map(Op::Remove),
+        1 => (0..domain).prop_map(Op::Search),
+    ]
+}
+
+fn assert_same<T: PartialEq + std::fmt::Debug>(
+    actual: Result<T>,
+    expected: Result<T>,
+    op: &Op,
+) -> std::result::Result<(), TestCaseError> {
+    match (actual, expected) {
+        (Ok(actual), Ok(expected)) => prop_assert_eq!(actual, expected, "{:?}", op),
+        (Err(actual), Err(expected)) => {
+            prop_assert_eq!(discriminant(&actual), discriminant(&expected), "{:?}", op)
+        }
+        (actual, expected) => {
+            prop_assert!(false, "{:?}: got {:?}, expected {:?}", op, actual, expected)
+        }
+    }
+    Ok(())
+}
+
+fn run<const B: usize>(ops: &[Op]) -> std::result::Result<(), TestCaseError> {
+    let mut tree = SimpleBTreeSet::<u16, B>::new();
+    let mut reference = ReferenceBTreeSet::<u16>::new();
+
+    for op in ops {
+        match *op {
+            Op::Insert(key) => assert_same(tree.insert(key), reference.insert(key), op)?,
+            Op::Remove(key) => assert_same(tree.remove(&key), reference.remove(&key), op)?,
+            Op::Search(key) => assert_same(tree.search(&key), reference.search(&key), op)?,
+        }
+
+        if let Err(error) = tree.check_invariants() {
+            prop_assert!(false, "{:?} broke the tree: {}\n{:#?}", op, error, tree);
+        }
+    }
+
+    prop_assert!(tree.iter().eq(reference.iter()));
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn test_matches_reference_with_minimal_nodes(ops in prop::collection::vec(op(64), 0..256)) {
+        run::<2>(&ops)?;
+    }
+
+    #[test]
+    fn test_matches_reference_with_small_nodes(ops in prop::collection::vec(op(256), 0..512)) {
+        run::<3>(&ops)?;
+    }
+
+    #[test]
+    fn test_matches_reference_with_default_nodes(ops in prop::collection::vec(op(1024), 0..1024)) {
+        run::<6>(&ops)?;
+    }
+
+    /// Replays seeded workloads, whose seed is printed on failure. Unlike the
+    /// sequences above these are not shrunk, but they reach shapes like long
+    /// runs of removals which random sequences rarely produce.
+    #[test]
+    fn test_matches_reference_on_seeded_workloads(
+        kind in prop::sample::select(Kind::ALL.to_vec()),
+        seed in any::<u64>(),
+    ) {
+        let mut tree = SimpleBTreeSet::<u64, 2>::new();
+        Workload::new(kind, seed).with_key_space(1024).check(&mut tree, 2048);
+        prop_assert!(tree.check_invariants().is_ok());
+    }
+}
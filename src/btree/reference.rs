@@ -1,8 +1,10 @@
+use std::collections::BTreeMap as StdBTreeMap;
 use std::collections::BTreeSet as StdBTreeSet;
+use std::collections::btree_map::Entry as StdEntry;
 
-use crate::{BTreeSet, Error, Result};
+use crate::{BTreeMap, BTreeSet, Error, Result};
 
-/// A BTreeSet test oracle.
+/// A BTreeSet test oracle, backed by the `BTreeSet` of the standard library.
 pub struct ReferenceBTreeSet<K>(StdBTreeSet<K>);
 
 impl<K> ReferenceBTreeSet<K> {
@@ -11,6 +13,18 @@ impl<K> ReferenceBTreeSet<K> {
     }
 }
 
+impl<K> Default for ReferenceBTreeSet<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord> ReferenceBTreeSet<K> {
+    pub fn iter(&self) -> impl Iterator<Item = &K> {
+        self.0.iter()
+    }
+}
+
 impl<K: Ord> BTreeSet for ReferenceBTreeSet<K> {
     type Key = K;
     const B: usize = 6;
@@ -32,10 +46,60 @@ impl<K: Ord> BTreeSet for ReferenceBTreeSet<K> {
     }
 }
 
+/// A BTreeMap test oracle, backed by the `BTreeMap` of the standard library.
+pub struct ReferenceBTreeMap<K, V>(StdBTreeMap<K, V>);
+
+impl<K, V> ReferenceBTreeMap<K, V> {
+    pub fn new() -> Self {
+        Self(StdBTreeMap::new())
+    }
+}
+
+impl<K, V> Default for ReferenceBTreeMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord, V> BTreeMap for ReferenceBTreeMap<K, V> {
+    type Key = K;
+    type Value = V;
+    const B: usize = 6;
+
+    fn get(&self, key: &Self::Key) -> Result<&Self::Value> {
+        self.0.get(key).ok_or(Error::KeyNotFound)
+    }
+
+    fn get_mut(&mut self, key: &Self::Key) -> Result<&mut Self::Value> {
+        self.0.get_mut(key).ok_or(Error::KeyNotFound)
+    }
+
+    fn insert(&mut self, key: Self::Key, value: Self::Value) -> Result<()> {
+        match self.0.entry(key) {
+            StdEntry::Occupied(_) => Err(Error::KeyAlreadyExists),
+            StdEntry::Vacant(entry) => {
+                entry.insert(value);
+                Ok(())
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &Self::Key) -> Result<Self::Value> {
+        self.0.remove(key).ok_or(Error::KeyNotFound)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::test_btree_impl;
 
     test_btree_impl!(ReferenceBTreeSet);
-}
\ No newline at end of file
+
+    mod map {
+        use super::*;
+        use crate::test_btree_map_impl;
+
+        test_btree_map_impl!(ReferenceBTreeMap);
+    }
+}
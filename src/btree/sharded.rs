@@ -0,0 +1,154 @@
+use super::simple::SimpleBTreeSet;
+use super::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use crate::{BTreeSet, Result};
+use std::iter::FusedIterator;
+
+/// A concurrent B-tree, which partitions the key space by range across a
+/// number of independently locked `SimpleBTreeSet`s.
+///
+/// The shards are separated by boundary keys given up front: shard `i` holds
+/// the keys at or above boundary `i - 1`, and below boundary `i`. Every
+/// operation locks only the shard its key is routed to, so threads writing to
+/// different shards never wait for each other. Picking boundaries which split
+/// the expected keys evenly is up to the caller.
+///
+/// The K type parameter represents the key type, and B is the branching factor
+/// of every shard.
+pub struct ShardedBTreeSet<K, const B: usize = 6> {
+    boundaries: Vec<K>,
+    shards: Vec<RwLock<SimpleBTreeSet<K, B>>>,
+}
+
+impl<K: Ord, const B: usize> ShardedBTreeSet<K, B> {
+    /// Creates a tree with one more shard than there are distinct boundaries.
+    pub fn with_boundaries(mut boundaries: Vec<K>) -> Self {
+        boundaries.sort();
+        boundaries.dedup();
+        let shards = (0..=boundaries.len())
+            .map(|_| RwLock::new(SimpleBTreeSet::new()))
+            .collect();
+        ShardedBTreeSet { boundaries, shards }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Returns the index of the shard the key is routed to.
+    pub fn shard_of(&self, key: &K) -> usize {
+        self.boundaries.partition_point(|boundary| boundary <= key)
+    }
+
+    fn read(&self, shard: usize) -> RwLockReadGuard<'_, SimpleBTreeSet<K, B>> {
+        self.shards[shard].read().unwrap()
+    }
+
+    fn write(&self, shard: usize) -> RwLockWriteGuard<'_, SimpleBTreeSet<K, B>> {
+        self.shards[shard].write().unwrap()
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.read(self.shard_of(key)).contains(key)
+    }
+
+    pub fn insert(&self, key: K) -> Result<()> {
+        self.write(self.shard_of(&key)).insert(key)
+    }
+
+    pub fn remove(&self, key: &K) -> Result<K> {
+        self.write(self.shard_of(key)).remove(key)
+    }
+
+    /// Returns an iterator over the keys of every shard, in ascending order.
+    ///
+    /// The keys of a shard are copied out while it is locked, one shard at a
+    /// time, so the iterator sees every shard as of some point during the
+    /// iteration, but not the whole tree as of a single point.
+    pub fn iter(&self) -> Iter<'_, K, B>
+    where
+        K: Clone,
+    {
+        Iter {
+            tree: self,
+            shard: 0,
+            keys: Vec::new().into_iter(),
+        }
+    }
+}
+
+/// An iterator over the keys of a `ShardedBTreeSet`, as returned by
+/// `ShardedBTreeSet::iter`.
+pub struct Iter<'a, K, const B: usize> {
+    tree: &'a ShardedBTreeSet<K, B>,
+    shard: usize,
+    keys: std::vec::IntoIter<K>,
+}
+
+impl<K: Ord + Clone, const B: usize> Iterator for Iter<'_, K, B> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<K> {
+        loop {
+            if let Some(key) = self.keys.next() {
+                return Some(key);
+            }
+            if self.shard == self.tree.shard_count() {
+                return None;
+            }
+            let keys: Vec<K> = self.tree.read(self.shard).iter().cloned().collect();
+            self.keys = keys.into_iter();
+            self.shard += 1;
+        }
+    }
+}
+
+impl<K: Ord + Clone, const B: usize> FusedIterator for Iter<'_, K, B> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use std::thread;
+
+    #[test]
+    fn test_routes_keys_by_range() {
+        let tree = ShardedBTreeSet::<u32>::with_boundaries(vec![200, 100, 100]);
+        assert_eq!(tree.shard_count(), 3);
+        assert_eq!(tree.shard_of(&99), 0);
+        assert_eq!(tree.shard_of(&100), 1);
+        assert_eq!(tree.shard_of(&250), 2);
+
+        for key in (0..300).rev() {
+            tree.insert(key).unwrap();
+        }
+        assert!(matches!(tree.insert(150), Err(Error::KeyAlreadyExists)));
+        assert_eq!(tree.remove(&150).unwrap(), 150);
+        assert!(!tree.contains(&150));
+        assert!(matches!(tree.remove(&150), Err(Error::KeyNotFound)));
+
+        let expected: Vec<u32> = (0..300).filter(|&key| key != 150).collect();
+        assert_eq!(tree.iter().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn test_concurrent_writers() {
+        let tree = ShardedBTreeSet::<u64, 4>::with_boundaries((1..8).map(|i| i * 1000).collect());
+
+        thread::scope(|scope| {
+            for thread in 0..8u64 {
+                let tree = &tree;
+                scope.spawn(move || {
+                    for key in (0..8000).filter(|key| key % 8 == thread) {
+                        tree.insert(key).unwrap();
+                    }
+                    for key in (0..8000).filter(|key| key % 8 == thread && key % 3 == 0) {
+                        tree.remove(&key).unwrap();
+                    }
+                });
+            }
+        });
+
+        let expected: Vec<u64> = (0..8000).filter(|key| key % 3 != 0).collect();
+        assert_eq!(tree.iter().collect::<Vec<_>>(), expected);
+    }
+}
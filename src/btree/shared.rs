@@ -0,0 +1,123 @@
+use super::persistent::PersistentBTreeSet;
+use super::sync::ArcSwap;
+use crate::{BTreeSet, Result};
+use std::sync::Arc;
+
+/// A concurrent B-tree for read-mostly workloads, in the style of RCU.
+///
+/// The tree holds the current version of a `PersistentBTreeSet` in an
+/// `ArcSwap`. Readers load the current version without taking any lock, and
+/// keep reading it for as long as they hold it, no matter what is written in
+/// the meantime. Writers copy the path to the changed key into a new version,
+/// and publish it with a compare-and-swap, retrying if another writer
+/// published first. Every write is therefore visible to every read which
+/// starts after it.
+///
+/// The K type parameter represents the key type, and B is the branching factor.
+pub struct SharedBTreeSet<K, const B: usize = 6> {
+    current: ArcSwap<PersistentBTreeSet<K, B>>,
+}
+
+impl<K: Ord + Clone, const B: usize> SharedBTreeSet<K, B> {
+    pub fn new() -> Self {
+        SharedBTreeSet {
+            current: ArcSwap::from_pointee(PersistentBTreeSet::new()),
+        }
+    }
+
+    /// Returns the current version of the tree, which later writes leave
+    /// untouched.
+    pub fn snapshot(&self) -> Arc<PersistentBTreeSet<K, B>> {
+        self.current.load_full()
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.current.load().contains(key)
+    }
+
+    pub fn insert(&self, key: K) -> Result<()> {
+        self.update(|tree| tree.insert(key.clone()))
+    }
+
+    pub fn remove(&self, key: &K) -> Result<K> {
+        self.update(|tree| tree.remove(key))
+    }
+
+    /// Applies the write to a copy of the current version, and publishes the
+    /// copy unless the write fails. The write is repeated against the newer
+    /// version if another writer published first.
+    fn update<T>(
+        &self,
+        mut write: impl FnMut(&mut PersistentBTreeSet<K, B>) -> Result<T>,
+    ) -> Result<T> {
+        let mut result = None;
+        self.current.rcu(|current| {
+            let mut tree = PersistentBTreeSet::clone(current);
+            match write(&mut tree) {
+                Ok(value) => {
+                    result = Some(Ok(value));
+                    Arc::new(tree)
+                }
+                Err(err) => {
+                    result = Some(Err(err));
+                    Arc::clone(current)
+                }
+            }
+        });
+        result.expect("the write ran at least once")
+    }
+}
+
+impl<K: Ord + Clone, const B: usize> Default for SharedBTreeSet<K, B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use std::thread;
+
+    #[test]
+    fn test_snapshots_are_isolated_from_writes() {
+        let tree = SharedBTreeSet::<u32>::new();
+        for key in 0..100 {
+            tree.insert(key).unwrap();
+        }
+
+        let snapshot = tree.snapshot();
+        assert_eq!(tree.remove(&5).unwrap(), 5);
+        tree.insert(100).unwrap();
+        assert!(matches!(tree.insert(100), Err(Error::KeyAlreadyExists)));
+        assert!(matches!(tree.remove(&5), Err(Error::KeyNotFound)));
+
+        assert!(snapshot.iter().copied().eq(0..100));
+        assert!(!tree.contains(&5) && tree.contains(&100));
+    }
+
+    #[test]
+    fn test_concurrent_readers_and_writers() {
+        let tree = SharedBTreeSet::<u64, 3>::new();
+
+        thread::scope(|scope| {
+            for thread in 0..4u64 {
+                let tree = &tree;
+                scope.spawn(move || {
+                    for key in (0..2000).filter(|key| key % 4 == thread) {
+                        tree.insert(key).unwrap();
+                    }
+                });
+                scope.spawn(move || {
+                    for _ in 0..50 {
+                        let snapshot = tree.snapshot();
+                        assert!(snapshot.iter().is_sorted_by(|a, b| a < b));
+                    }
+                });
+            }
+        });
+
+        assert!(tree.snapshot().iter().copied().eq(0..2000));
+    }
+}
@@ -0,0 +1,147 @@
+use super::{Allocator, Comparator, Layout, Node, Recorder, Root, SimpleBTreeSet};
+
+impl<K, const B: usize, A: Allocator + Clone, C: Comparator<K> + Clone, L: Layout>
+    SimpleBTreeSet<K, B, A, C, L>
+{
+    /// Rebuilds the tree bottom-up into its canonical shape, which depends
+    /// only on the number of keys, and not on the order they were inserted
+    /// and removed in.
+    ///
+    /// The nodes of every level are filled to capacity from left to right,
+    /// except that the last two nodes of a level share what is left when the
+    /// last one would be deficient. The rebuilt tree has as few nodes as any
+    /// tree holding the same keys, and its shape can be compared against a
+    /// fixed one in tests. The keys are moved, never compared or cloned.
+    pub fn canonicalize(&mut self) {
+        let len = self.iter().count();
+        let old = SimpleBTreeSet {
+            root: self.root.take(),
+            alloc: self.alloc.clone(),
+            cmp: self.cmp.clone(),
+            spare: Vec::new(),
+            id: self.id,
+            generation: 0,
+            metrics: Recorder::default(),
+        };
+
+        self.root = (len > 0).then(|| Root {
+            node: Self::canonical_root(old.into_iter(), len, &self.alloc),
+        });
+        self.bump_generation();
+        self.validate_after("canonicalize");
+    }
+
+    /// Builds the canonical tree of the given number of keys, given in
+    /// ascending order, one level at a time.
+    fn canonical_root(
+        mut keys: impl Iterator<Item = K>,
+        len: usize,
+        alloc: &A,
+    ) -> Node<K, B, A, L> {
+        // Every leaf but the last is followed by a separator, so a leaf of
+        // `k` keys uses up `k + 1` keys, counting one past the end for the
+        // last leaf.
+        let max = Node::<K, B, A, L>::MAX_CHILDREN;
+        let min = Node::<K, B, A, L>::MIN_CHILDREN;
+        let sizes = group_sizes(len + 1, max, min);
+        let mut nodes = Vec::with_capacity(sizes.len());
+        let mut separators = Vec::with_capacity(sizes.len() - 1);
+        for (i, size) in sizes.iter().enumerate() {
+            nodes.push(Node::leaf(keys.by_ref().take(size - 1), alloc.clone()));
+            if i + 1 < sizes.len() {
+                separators.push(keys.next().unwrap());
+            }
+        }
+
+        // The children of a node are separated by its keys, and the nodes of
+        // the level by the separators left over, which move up a level.
+        while nodes.len() > 1 {
+            let sizes = group_sizes(nodes.len(), max, min);
+            let mut children = nodes.into_iter().map(Node::link);
+            let mut keys = separators.into_iter();
+            nodes = Vec::with_capacity(sizes.len());
+            separators = Vec::with_capacity(sizes.len() - 1);
+            for (i, &size) in sizes.iter().enumerate() {
+                let node_keys = keys.by_ref().take(size - 1);
+                let node_children = children.by_ref().take(size);
+                nodes.push(Node::intermediate(node_keys, node_children, alloc.clone()));
+                if i + 1 < sizes.len() {
+                    separators.push(keys.next().unwrap());
+                }
+            }
+        }
+
+        nodes.pop().unwrap()
+    }
+}
+
+/// Splits a number of items into as few groups of at most `max` items as
+/// possible, filled from the left. When the last group would hold fewer than
+/// `min` items, the second to last one hands it the difference.
+fn group_sizes(total: usize, max: usize, min: usize) -> Vec<usize> {
+    let mut sizes = vec![max; total / max];
+    if !total.is_multiple_of(max) {
+        sizes.push(total % max);
+    }
+    if let [.., left, right] = sizes.as_mut_slice()
+        && *right < min
+    {
+        *left -= min - *right;
+        *right = min;
+    }
+    sizes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BTreeSet;
+    use crate::btree::{EytzingerLayout, Global, OrdComparator};
+
+    #[test]
+    fn test_canonical_shape_depends_only_on_the_keys() {
+        let mut ascending = SimpleBTreeSet::<usize, 2>::new();
+        let mut descending = SimpleBTreeSet::<usize, 2>::new();
+        for key in 0..10 {
+            ascending.insert(key).unwrap();
+            descending.insert(9 - key).unwrap();
+        }
+        assert_ne!(format!("{ascending:?}"), format!("{descending:?}"));
+
+        ascending.canonicalize();
+        descending.canonicalize();
+        let expected = "SimpleBTreeSet { B: 2, levels: [[(2) [3, 7]], \
+                        [(3) [0, 1, 2], (3) [4, 5, 6], (2) [8, 9]]] }";
+        assert_eq!(format!("{ascending:?}"), expected);
+        assert_eq!(format!("{descending:?}"), expected);
+    }
+
+    #[test]
+    fn test_canonicalize_packs_every_size() {
+        for len in 0..300u32 {
+            let mut tree =
+                SimpleBTreeSet::<u32, 3, Global, OrdComparator, EytzingerLayout>::default();
+            for key in (0..len).map(|key| key * 7919 % len.max(1)) {
+                tree.insert(key).unwrap();
+            }
+            tree.canonicalize();
+            assert!(tree.check_invariants().is_ok(), "{len} keys");
+            assert!(tree.iter().copied().eq(0..len));
+
+            // No fewer leaves could hold the keys and their separators.
+            let stats = tree.stats();
+            assert_eq!(
+                stats.leaf_nodes,
+                (len as usize + 1).div_ceil(6) * usize::from(len > 0)
+            );
+        }
+    }
+
+    #[test]
+    fn test_group_sizes() {
+        assert_eq!(group_sizes(8, 4, 2), [4, 4]);
+        assert_eq!(group_sizes(9, 4, 2), [4, 3, 2]);
+        assert_eq!(group_sizes(11, 4, 2), [4, 4, 3]);
+        assert_eq!(group_sizes(1, 4, 2), [1]);
+    }
+}
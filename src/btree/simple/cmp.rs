@@ -0,0 +1,81 @@
+use super::{Allocator, Comparator, Layout, SimpleBTreeSet};
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+
+/// Trees are equal when they hold equal keys in the same order, however their
+/// nodes are shaped.
+impl<K: PartialEq, const B: usize, A: Allocator + Clone, C: Comparator<K>, L: Layout> PartialEq
+    for SimpleBTreeSet<K, B, A, C, L>
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<K: Eq, const B: usize, A: Allocator + Clone, C: Comparator<K>, L: Layout> Eq
+    for SimpleBTreeSet<K, B, A, C, L>
+{
+}
+
+/// Trees are ordered lexicographically by their keys, in the order the tree
+/// keeps them.
+impl<K: PartialOrd, const B: usize, A: Allocator + Clone, C: Comparator<K>, L: Layout> PartialOrd
+    for SimpleBTreeSet<K, B, A, C, L>
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<K: Ord, const B: usize, A: Allocator + Clone, C: Comparator<K>, L: Layout> Ord
+    for SimpleBTreeSet<K, B, A, C, L>
+{
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+/// Hashes the keys in order, followed by their count, so equal trees hash
+/// equally whatever their shape.
+impl<K: Hash, const B: usize, A: Allocator + Clone, C: Comparator<K>, L: Layout> Hash
+    for SimpleBTreeSet<K, B, A, C, L>
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut len = 0;
+        for key in self.iter() {
+            key.hash(state);
+            len += 1;
+        }
+        state.write_usize(len);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::tree_with;
+    use super::*;
+    use std::collections::HashSet;
+    use std::hash::{BuildHasher, RandomState};
+
+    #[test]
+    fn test_comparisons_ignore_the_shape_of_the_tree() {
+        let ascending = tree_with(0..100);
+        let descending = tree_with((0..100).rev());
+        assert_ne!(format!("{ascending:?}"), format!("{descending:?}"));
+        assert_eq!(ascending, descending);
+
+        let state = RandomState::new();
+        assert_eq!(state.hash_one(&ascending), state.hash_one(&descending));
+
+        assert!(tree_with([1, 2]) < tree_with([1, 3]));
+        assert!(tree_with([1, 2]) < tree_with([1, 2, 3]));
+        assert!(tree_with([]) < tree_with([0]));
+        assert_eq!(tree_with([5]).cmp(&tree_with([5])), Ordering::Equal);
+
+        // The metrics of a tree change behind a shared reference, but they are
+        // not hashed.
+        #[allow(clippy::mutable_key_type)]
+        let trees: HashSet<_> = [tree_with(0..3), tree_with([2, 1, 0]), tree_with(0..4)].into();
+        assert_eq!(trees.len(), 2);
+    }
+}
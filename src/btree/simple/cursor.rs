@@ -0,0 +1,666 @@
+use super::{
+    Allocator, Comparator, Global, Layout, Node, NodeStorage, OrdComparator, Path, SimpleBTreeSet,
+    SortedLayout,
+};
+use crate::{Error, Result};
+use std::cmp::Ordering;
+
+/// A cursor over a `SimpleBTreeSet`, which points at a key of the tree.
+///
+/// Besides the keys, the cursor can point at a "ghost" position, which sits
+/// between the largest and the smallest key. Moving forward from the largest
+/// key, or backward from the smallest key, lands on the ghost position, and
+/// moving away from the ghost position wraps around to the other end.
+pub struct Cursor<
+    'a,
+    K,
+    const B: usize,
+    A: Allocator = Global,
+    C = OrdComparator,
+    L: Layout = SortedLayout,
+> {
+    root: Option<&'a Node<K, B, A, L>>,
+    cmp: &'a C,
+    /// The nodes visited from the root. The index of the last node points to
+    /// the current key, while the others point to the child descended into.
+    /// The stack is empty at the ghost position.
+    stack: Vec<(&'a Node<K, B, A, L>, usize)>,
+    /// The identity and generation of the tree the cursor was created from.
+    tree: u64,
+    generation: u64,
+}
+
+/// A cursor position saved with `Cursor::position`, which does not borrow the
+/// tree. It can be turned back into a cursor over the same tree with
+/// `SimpleBTreeSet::cursor_at`, as long as the tree was not modified in
+/// between.
+#[derive(Clone, Debug)]
+pub struct Position {
+    pub(super) path: Option<Path>,
+    pub(super) tree: u64,
+    pub(super) generation: u64,
+}
+
+impl<'a, K, const B: usize, A: Allocator, C, L: Layout> Cursor<'a, K, B, A, C, L> {
+    /// Creates a cursor pointing at the ghost position.
+    pub(super) fn new(set: &'a SimpleBTreeSet<K, B, A, C, L>) -> Self {
+        Cursor {
+            root: set.root.as_ref().map(|root| &root.node),
+            cmp: &set.cmp,
+            stack: Vec::new(),
+            tree: set.id,
+            generation: set.generation,
+        }
+    }
+
+    /// Creates a cursor pointing at the key the given path leads to, or at the
+    /// ghost position if there is no path.
+    pub(super) fn from_path(set: &'a SimpleBTreeSet<K, B, A, C, L>, path: Option<&Path>) -> Self {
+        let mut cursor = Cursor::new(set);
+
+        if let (Some(mut node), Some(path)) = (cursor.root, path) {
+            for &idx in path.range(..path.len() - 1) {
+                cursor.stack.push((node, idx));
+                node = &node.children[idx];
+            }
+            cursor.stack.push((node, path[path.len() - 1]));
+        }
+
+        cursor
+    }
+
+    /// Returns the path to the current key, or `None` at the ghost position.
+    fn path(&self) -> Option<Path> {
+        if self.stack.is_empty() {
+            None
+        } else {
+            Some(self.stack.iter().map(|&(_, idx)| idx).collect())
+        }
+    }
+
+    /// Saves the position of the cursor, to be restored once the tree is no
+    /// longer borrowed.
+    pub fn position(&self) -> Position {
+        Position {
+            path: self.path(),
+            tree: self.tree,
+            generation: self.generation,
+        }
+    }
+
+    /// Returns the current key, or `None` at the ghost position.
+    pub fn key(&self) -> Option<&'a K> {
+        self.stack.last().map(|&(node, idx)| &node.keys[idx])
+    }
+
+    /// Moves the cursor to the next key.
+    pub fn move_next(&mut self) {
+        let Some(&mut (node, ref mut idx)) = self.stack.last_mut() else {
+            if let Some(root) = self.root {
+                self.descend_first(root);
+            }
+            return;
+        };
+
+        *idx += 1;
+
+        if !node.is_leaf {
+            let child = &node.children[*idx];
+            self.descend_first(child);
+        } else if *idx == node.keys.len() {
+            self.ascend_next();
+        }
+    }
+
+    /// Moves the cursor to the previous key.
+    pub fn move_prev(&mut self) {
+        let Some(&mut (node, ref mut idx)) = self.stack.last_mut() else {
+            if let Some(root) = self.root {
+                self.descend_last(root);
+            }
+            return;
+        };
+
+        if !node.is_leaf {
+            let child = &node.children[*idx];
+            self.descend_last(child);
+        } else if *idx == 0 {
+            self.ascend_prev();
+        } else {
+            *idx -= 1;
+        }
+    }
+
+    /// Descends to the smallest key of the given subtree.
+    fn descend_first(&mut self, mut node: &'a Node<K, B, A, L>) {
+        loop {
+            self.stack.push((node, 0));
+            if node.is_leaf {
+                break;
+            }
+            node = &node.children[0];
+        }
+
+        // Only an empty root can be a leaf without keys.
+        if node.keys.is_empty() {
+            self.stack.clear();
+        }
+    }
+
+    /// Descends to the largest key of the given subtree.
+    fn descend_last(&mut self, mut node: &'a Node<K, B, A, L>) {
+        while !node.is_leaf {
+            self.stack.push((node, node.keys.len()));
+            node = &node.children[node.keys.len()];
+        }
+
+        // Only an empty root can be a leaf without keys.
+        match node.keys.len().checked_sub(1) {
+            Some(idx) => self.stack.push((node, idx)),
+            None => self.stack.clear(),
+        }
+    }
+
+    /// Leaves the current leaf, and moves up to the first ancestor key that
+    /// comes after it.
+    fn ascend_next(&mut self) {
+        self.stack.pop();
+        while let Some(&(node, idx)) = self.stack.last() {
+            if idx < node.keys.len() {
+                return;
+            }
+            self.stack.pop();
+        }
+    }
+
+    /// Leaves the current leaf, and moves up to the first ancestor key that
+    /// comes before it.
+    fn ascend_prev(&mut self) {
+        self.stack.pop();
+        while let Some((_, idx)) = self.stack.last_mut() {
+            if *idx > 0 {
+                *idx -= 1;
+                return;
+            }
+            self.stack.pop();
+        }
+    }
+}
+
+impl<'a, K, const B: usize, A: Allocator + Clone, C: Comparator<K>, L: Layout>
+    Cursor<'a, K, B, A, C, L>
+{
+    /// Moves the cursor to the given key or, if the key does not exist, to the
+    /// smallest key greater than it. If there is no such key, the cursor moves
+    /// to the ghost position.
+    pub fn seek(&mut self, key: &K) {
+        self.stack.clear();
+
+        let Some(mut node) = self.root else {
+            return;
+        };
+
+        loop {
+            match node.keys.search_by(|k| self.cmp.compare(k, key)) {
+                Ok(idx) => {
+                    self.stack.push((node, idx));
+                    return;
+                }
+                Err(idx) => {
+                    self.stack.push((node, idx));
+                    if node.is_leaf {
+                        if idx == node.keys.len() {
+                            self.ascend_next();
+                        }
+                        return;
+                    }
+                    node = &node.children[idx];
+                }
+            }
+        }
+    }
+}
+
+impl<K, const B: usize, A: Allocator, C, L: Layout> Clone for Cursor<'_, K, B, A, C, L> {
+    fn clone(&self) -> Self {
+        Cursor {
+            root: self.root,
+            cmp: self.cmp,
+            stack: self.stack.clone(),
+            tree: self.tree,
+            generation: self.generation,
+        }
+    }
+}
+
+/// A cursor over a `SimpleBTreeSet`, which can also remove and insert keys
+/// at its position. See `Cursor` for the semantics of the ghost position.
+///
+/// Since the tree might be rebalanced after each modification, the cursor
+/// keeps a path to its key instead of references to the nodes, so every
+/// operation descends the tree from the root again.
+pub struct CursorMut<
+    'a,
+    K,
+    const B: usize,
+    A: Allocator = Global,
+    C = OrdComparator,
+    L: Layout = SortedLayout,
+> {
+    set: &'a mut SimpleBTreeSet<K, B, A, C, L>,
+    path: Option<Path>,
+}
+
+impl<'a, K, const B: usize, A: Allocator + Clone, C: Comparator<K>, L: Layout>
+    CursorMut<'a, K, B, A, C, L>
+{
+    /// Creates a cursor pointing at the ghost position.
+    pub(super) fn new(set: &'a mut SimpleBTreeSet<K, B, A, C, L>) -> Self {
+        CursorMut { set, path: None }
+    }
+
+    pub(super) fn from_path(
+        set: &'a mut SimpleBTreeSet<K, B, A, C, L>,
+        path: Option<Path>,
+    ) -> Self {
+        CursorMut { set, path }
+    }
+
+    /// Saves the position of the cursor. See `Cursor::position`.
+    pub fn position(&self) -> Position {
+        Position {
+            path: self.path.clone(),
+            tree: self.set.id,
+            generation: self.set.generation,
+        }
+    }
+
+    /// Returns a read-only cursor pointing at the same key.
+    pub fn as_cursor(&self) -> Cursor<'_, K, B, A, C, L> {
+        Cursor::from_path(self.set, self.path.as_ref())
+    }
+
+    fn navigate(&mut self, f: impl FnOnce(&mut Cursor<'_, K, B, A, C, L>)) {
+        let mut cursor = self.as_cursor();
+        f(&mut cursor);
+        self.path = cursor.path();
+    }
+
+    /// Returns the current key, or `None` at the ghost position.
+    pub fn key(&self) -> Option<&K> {
+        self.path.as_ref().map(|path| self.set.get(path))
+    }
+
+    /// Moves the cursor to the next key.
+    pub fn move_next(&mut self) {
+        self.navigate(|cursor| cursor.move_next());
+    }
+
+    /// Moves the cursor to the previous key.
+    pub fn move_prev(&mut self) {
+        self.navigate(|cursor| cursor.move_prev());
+    }
+
+    /// Moves the cursor to the given key or, if the key does not exist, to the
+    /// smallest key greater than it. If there is no such key, the cursor moves
+    /// to the ghost position.
+    pub fn seek(&mut self, key: &K) {
+        self.navigate(|cursor| cursor.seek(key));
+    }
+
+    /// Removes the current key, and moves the cursor to the next key. Returns
+    /// `None` at the ghost position.
+    pub fn remove_current(&mut self) -> Option<K> {
+        let path = self.path.take()?;
+        let key = self.set.remove_along(&path);
+        self.seek(&key);
+        Some(key)
+    }
+
+    /// Inserts a key right after the current key, without moving the cursor.
+    /// At the ghost position, the key is inserted before the smallest key.
+    ///
+    /// The key must be greater than the current key and smaller than the
+    /// next one, otherwise an error is returned.
+    pub fn insert_after(&mut self, key: K) -> Result<()> {
+        let mut cursor = self.as_cursor();
+        let lower = cursor.key();
+        cursor.move_next();
+        let upper = cursor.key();
+        check_between(&self.set.cmp, lower, &key, upper)?;
+
+        let was_ghost = self.path.is_none();
+        self.insert_between(key);
+        if was_ghost {
+            self.path = None;
+        } else {
+            self.move_prev();
+        }
+        Ok(())
+    }
+
+    /// Inserts a key right before the current key, without moving the cursor.
+    /// At the ghost position, the key is inserted after the largest key.
+    ///
+    /// The key must be smaller than the current key and greater than the
+    /// previous one, otherwise an error is returned.
+    pub fn insert_before(&mut self, key: K) -> Result<()> {
+        let mut cursor = self.as_cursor();
+        let upper = cursor.key();
+        cursor.move_prev();
+        let lower = cursor.key();
+        check_between(&self.set.cmp, lower, &key, upper)?;
+
+        let was_ghost = self.path.is_none();
+        self.insert_between(key);
+        if was_ghost {
+            self.path = None;
+        } else {
+            self.move_next();
+        }
+        Ok(())
+    }
+
+    /// Inserts a key which is known to be absent from the tree, and moves the
+    /// cursor to it.
+    fn insert_between(&mut self, key: K) {
+        let cmp = &self.set.cmp;
+        let path = self
+            .set
+            .search_path_by(|k| cmp.compare(k, &key))
+            .unwrap_err();
+        self.path = Some(self.set.insert_along(&path, key));
+    }
+}
+
+/// Checks that the key fits strictly between the given bounds.
+fn check_between<K>(
+    cmp: &impl Comparator<K>,
+    lower: Option<&K>,
+    key: &K,
+    upper: Option<&K>,
+) -> Result<()> {
+    let check = |ordering, expected| match ordering {
+        Ordering::Equal => Err(Error::KeyAlreadyExists),
+        ordering if ordering == expected => Ok(()),
+        _ => Err(Error::KeyOutOfOrder),
+    };
+
+    if let Some(lower) = lower {
+        check(cmp.compare(key, lower), Ordering::Greater)?;
+    }
+    if let Some(upper) = upper {
+        check(cmp.compare(key, upper), Ordering::Less)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::tree_with;
+    use super::*;
+    use crate::BTreeSet;
+
+    #[test]
+    fn test_cursor_on_empty_tree_points_at_ghost() {
+        let tree = SimpleBTreeSet::<usize, 2>::new();
+        let mut cursor = tree.cursor();
+        assert_eq!(cursor.key(), None);
+        cursor.move_next();
+        assert_eq!(cursor.key(), None);
+        cursor.move_prev();
+        assert_eq!(cursor.key(), None);
+    }
+
+    #[test]
+    fn test_cursor_move_next_visits_keys_in_order() {
+        let tree = tree_with((0..500).rev());
+        let mut cursor = tree.cursor();
+
+        for i in 0..500 {
+            assert_eq!(cursor.key(), Some(&i));
+            cursor.move_next();
+        }
+
+        assert_eq!(cursor.key(), None);
+        cursor.move_next();
+        assert_eq!(cursor.key(), Some(&0));
+    }
+
+    #[test]
+    fn test_cursor_move_prev_visits_keys_in_reverse_order() {
+        let tree = tree_with(0..500);
+        let mut cursor = tree.cursor();
+        cursor.move_prev();
+        assert_eq!(cursor.key(), None);
+
+        for i in (0..500).rev() {
+            cursor.move_prev();
+            assert_eq!(cursor.key(), Some(&i));
+        }
+
+        cursor.move_prev();
+        assert_eq!(cursor.key(), None);
+    }
+
+    #[test]
+    fn test_cursor_seek_finds_key_or_next_greater_key() {
+        let tree = tree_with((0..500).map(|i| i * 2));
+        let mut cursor = tree.cursor();
+
+        for i in 0..999 {
+            cursor.seek(&i);
+            assert_eq!(cursor.key(), Some(&(i + i % 2)));
+        }
+
+        cursor.seek(&999);
+        assert_eq!(cursor.key(), None);
+    }
+
+    #[test]
+    fn test_cursor_seek_then_step_in_both_directions() {
+        let tree = tree_with((0..500).map(|i| i * 2));
+        let mut cursor = tree.cursor();
+
+        cursor.seek(&501);
+        assert_eq!(cursor.key(), Some(&502));
+        cursor.move_prev();
+        cursor.move_prev();
+        assert_eq!(cursor.key(), Some(&498));
+        cursor.move_next();
+        assert_eq!(cursor.key(), Some(&500));
+    }
+
+    #[test]
+    fn test_bounds_match_std() {
+        let keys: std::collections::BTreeSet<usize> = (0..300).map(|i| i * 3).collect();
+        let tree = tree_with(keys.iter().copied());
+
+        for i in 0..=900 {
+            assert_eq!(tree.lower_bound(&i).key(), keys.range(i..).next());
+            assert_eq!(tree.upper_bound(&i).key(), keys.range(i + 1..).next());
+            assert_eq!(tree.successor(&i), keys.range(i + 1..).next());
+            assert_eq!(tree.predecessor(&i), keys.range(..i).next_back());
+        }
+    }
+
+    #[test]
+    fn test_bounds_on_empty_tree_and_at_the_ends() {
+        let empty = SimpleBTreeSet::<usize, 2>::new();
+        assert_eq!(empty.lower_bound(&0).key(), None);
+        assert_eq!(empty.predecessor(&0), None);
+        assert_eq!(empty.successor(&0), None);
+
+        let tree = tree_with(10..20);
+        assert_eq!(tree.predecessor(&10), None);
+        assert_eq!(tree.predecessor(&100), Some(&19));
+        assert_eq!(tree.successor(&19), None);
+        assert_eq!(tree.successor(&0), Some(&10));
+
+        // The cursors keep walking from the bound.
+        let mut cursor = tree.upper_bound(&14);
+        cursor.move_next();
+        assert_eq!(cursor.key(), Some(&16));
+        cursor.move_prev();
+        cursor.move_prev();
+        assert_eq!(cursor.key(), Some(&14));
+    }
+
+    #[test]
+    fn test_cursor_merge_join_of_two_trees() {
+        let left = tree_with((0..300).map(|i| i * 2));
+        let right = tree_with((0..200).map(|i| i * 3));
+        let (mut a, mut b) = (left.cursor(), right.cursor());
+        let mut joined = Vec::new();
+
+        while let (Some(x), Some(y)) = (a.key(), b.key()) {
+            match x.cmp(y) {
+                Ordering::Less => a.seek(y),
+                Ordering::Greater => b.seek(x),
+                Ordering::Equal => {
+                    joined.push(*x);
+                    a.move_next();
+                    b.move_next();
+                }
+            }
+        }
+
+        let expected: Vec<_> = (0..100).map(|i| i * 6).collect();
+        assert_eq!(joined, expected);
+    }
+
+    #[test]
+    fn test_cursor_mut_remove_current_moves_to_next_key() {
+        let mut tree = tree_with(0..10);
+        let mut cursor = tree.cursor_mut();
+        cursor.seek(&4);
+
+        assert_eq!(cursor.remove_current(), Some(4));
+        assert_eq!(cursor.key(), Some(&5));
+        cursor.seek(&9);
+        assert_eq!(cursor.remove_current(), Some(9));
+        assert_eq!(cursor.key(), None);
+        assert_eq!(cursor.remove_current(), None);
+
+        assert!(!tree.contains(&4));
+        assert!(!tree.contains(&9));
+        assert!(tree.contains(&5));
+    }
+
+    #[test]
+    fn test_cursor_mut_insert_after_and_before_keep_position() {
+        let mut tree = tree_with((0..100).map(|i| i * 10));
+        let mut cursor = tree.cursor_mut();
+        cursor.seek(&500);
+
+        for i in (1..10).rev() {
+            cursor.insert_after(500 + i).unwrap();
+            cursor.insert_before(500 - i).unwrap();
+            assert_eq!(cursor.key(), Some(&500));
+        }
+
+        cursor.move_next();
+        assert_eq!(cursor.key(), Some(&501));
+
+        for i in 491..=509 {
+            assert!(tree.contains(&i));
+        }
+    }
+
+    #[test]
+    fn test_cursor_mut_insert_at_ghost_position_extends_both_ends() {
+        let mut tree = tree_with(10..20);
+        let mut cursor = tree.cursor_mut();
+        cursor.move_prev();
+        assert_eq!(cursor.key(), None);
+
+        cursor.insert_after(5).unwrap();
+        cursor.insert_before(25).unwrap();
+        assert_eq!(cursor.key(), None);
+
+        cursor.move_next();
+        assert_eq!(cursor.key(), Some(&5));
+        cursor.move_prev();
+        cursor.move_prev();
+        assert_eq!(cursor.key(), Some(&25));
+    }
+
+    #[test]
+    fn test_cursor_mut_insert_out_of_order_returns_error() {
+        let mut tree = tree_with([10, 20, 30]);
+        let mut cursor = tree.cursor_mut();
+        cursor.seek(&20);
+
+        let result = cursor.insert_after(35);
+        assert!(matches!(result.unwrap_err(), Error::KeyOutOfOrder));
+        let result = cursor.insert_before(5);
+        assert!(matches!(result.unwrap_err(), Error::KeyOutOfOrder));
+        let result = cursor.insert_after(30);
+        assert!(matches!(result.unwrap_err(), Error::KeyAlreadyExists));
+        assert!(!tree.contains(&35));
+        assert!(!tree.contains(&5));
+    }
+
+    #[test]
+    fn test_saved_position_is_rejected_after_modification() {
+        let tree = std::cell::RefCell::new(tree_with(0..100));
+        let position = tree.borrow().lower_bound(&41).position();
+
+        let restored = tree
+            .borrow_mut()
+            .cursor_mut_at(&position)
+            .map(|mut cursor| {
+                cursor.move_next();
+                cursor.position()
+            });
+        assert_eq!(
+            tree.borrow().cursor_at(&restored.unwrap()).unwrap().key(),
+            Some(&42)
+        );
+
+        let generation = tree.borrow().generation();
+        tree.borrow_mut().remove(&50).unwrap();
+        assert_eq!(tree.borrow().generation(), generation + 1);
+        let result = tree
+            .borrow()
+            .cursor_at(&position)
+            .map(|cursor| cursor.key().copied());
+        assert!(matches!(result, Err(Error::StalePosition)));
+
+        // Emptying a tree and refilling it does not bring old positions back.
+        let mut other = tree_with(0..100);
+        let position = other.cursor().position();
+        other.append(tree_with(100..200));
+        let drained = std::mem::take(&mut other);
+        other.append(drained);
+        assert!(matches!(
+            other.cursor_at(&position),
+            Err(Error::StalePosition)
+        ));
+    }
+
+    #[test]
+    fn test_position_of_another_tree_is_rejected() {
+        // Both trees are at the same generation, and the position leads past
+        // the keys of the smaller one.
+        let large = tree_with(0..100);
+        let mut small = tree_with(0..3);
+        small.remove(&2).unwrap();
+        for _ in 0..48 {
+            small.insert(1000).unwrap();
+            small.remove(&1000).unwrap();
+        }
+        assert_eq!(small.generation(), large.generation());
+
+        let position = large.lower_bound(&99).position();
+        assert!(matches!(
+            small.cursor_at(&position),
+            Err(Error::StalePosition)
+        ));
+        assert!(matches!(
+            large.clone().cursor_at(&position),
+            Err(Error::StalePosition)
+        ));
+        assert_eq!(large.cursor_at(&position).unwrap().key(), Some(&99));
+    }
+}
@@ -0,0 +1,93 @@
+use super::{Allocator, Layout, Node, NodeStorage, SimpleBTreeSet};
+use std::fmt::{self, Debug, Formatter};
+
+/// Prints the structure of the tree level by level, starting from the root.
+/// Every node is printed on its own as its key count, followed by its keys.
+impl<K: Debug, const B: usize, A: Allocator, C, L: Layout> Debug for SimpleBTreeSet<K, B, A, C, L> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let levels = Levels(self.root.as_ref().map(|root| &root.node));
+        f.debug_struct("SimpleBTreeSet")
+            .field("B", &B)
+            .field("levels", &levels)
+            .finish()
+    }
+}
+
+/// Prints the node as its key count, followed by its keys, on a single line.
+impl<K: Debug, const B: usize, A: Allocator, L: Layout> Debug for Node<K, B, A, L> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "({}) ", self.keys.len())?;
+        f.debug_list().entries(self.keys.iter()).finish()
+    }
+}
+
+struct Levels<'a, K, const B: usize, A: Allocator, L: Layout>(Option<&'a Node<K, B, A, L>>);
+
+impl<K: Debug, const B: usize, A: Allocator, L: Layout> Debug for Levels<'_, K, B, A, L> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+        let mut level: Vec<_> = self.0.into_iter().collect();
+
+        while !level.is_empty() {
+            list.entry(&Level(&level));
+            level = level
+                .iter()
+                .flat_map(|node| node.children.iter().map(|child| &**child))
+                .collect();
+        }
+
+        list.finish()
+    }
+}
+
+struct Level<'a, K, const B: usize, A: Allocator, L: Layout>(&'a [&'a Node<K, B, A, L>]);
+
+impl<K: Debug, const B: usize, A: Allocator, L: Layout> Debug for Level<'_, K, B, A, L> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // The nodes of a level are always printed on a single line, even in
+        // the alternate mode, so that each line of the output is a level.
+        write!(f, "[")?;
+        for (i, node) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{node:?}")?;
+        }
+        write!(f, "]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::tree_with;
+    use super::*;
+
+    #[test]
+    fn test_debug_empty_tree() {
+        let tree = SimpleBTreeSet::<usize, 2>::new();
+        assert_eq!(format!("{tree:?}"), "SimpleBTreeSet { B: 2, levels: [] }");
+    }
+
+    #[test]
+    fn test_debug_prints_levels_with_key_counts() {
+        let tree = tree_with(1..=5);
+        assert_eq!(
+            format!("{tree:?}"),
+            "SimpleBTreeSet { B: 2, levels: [[(1) [2]], [(1) [1], (3) [3, 4, 5]]] }"
+        );
+    }
+
+    #[test]
+    fn test_pretty_debug_prints_one_level_per_line() {
+        let tree = tree_with(1..=5);
+        let expected = "\
+SimpleBTreeSet {
+    B: 2,
+    levels: [
+        [(1) [2]],
+        [(1) [1], (3) [3, 4, 5]],
+    ],
+}";
+        assert_eq!(format!("{tree:#?}"), expected);
+    }
+}
@@ -0,0 +1,99 @@
+use super::{Allocator, Layout, Node, NodeStorage, SimpleBTreeSet};
+use std::fmt::{Debug, Write};
+
+impl<K: Debug, const B: usize, A: Allocator, C, L: Layout> SimpleBTreeSet<K, B, A, C, L> {
+    /// Renders the structure of the tree as a Graphviz DOT graph. Every node
+    /// is drawn as a record of its keys, with an edge from the slot between
+    /// two keys to the child holding the keys in between.
+    ///
+    /// The output can be rendered with `dot -Tsvg tree.dot > tree.svg`.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph SimpleBTreeSet {\n    node [shape=record];\n");
+
+        if let Some(root) = self.root.as_ref() {
+            let mut next_id = 0;
+            write_node(&mut dot, &root.node, &mut next_id);
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Writes the node and its subtree, returning the identifier of the node.
+fn write_node<K: Debug, const B: usize, A: Allocator, L: Layout>(
+    dot: &mut String,
+    node: &Node<K, B, A, L>,
+    next_id: &mut usize,
+) -> usize {
+    let id = *next_id;
+    *next_id += 1;
+
+    let mut label = String::new();
+    for (i, key) in node.keys.iter().enumerate() {
+        write!(label, "<c{i}> |{}| ", escape(&format!("{key:?}"))).unwrap();
+    }
+    write!(label, "<c{}>", node.keys.len()).unwrap();
+    writeln!(dot, "    n{id} [label=\"{label}\"];").unwrap();
+
+    for (i, child) in node.children.iter().enumerate() {
+        let child_id = write_node(dot, child, next_id);
+        writeln!(dot, "    n{id}:c{i} -> n{child_id};").unwrap();
+    }
+
+    id
+}
+
+/// Escapes the characters which have a special meaning in record labels.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '{' | '}' | '|' | '<' | '>' | '"' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BTreeSet;
+
+    #[test]
+    fn test_to_dot_empty_tree() {
+        let tree = SimpleBTreeSet::<usize, 2>::new();
+        assert_eq!(
+            tree.to_dot(),
+            "digraph SimpleBTreeSet {\n    node [shape=record];\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_to_dot_links_children_to_key_slots() {
+        let mut tree = SimpleBTreeSet::<usize, 2>::new();
+        for key in 1..=5 {
+            tree.insert(key).unwrap();
+        }
+
+        let expected = "\
+digraph SimpleBTreeSet {
+    node [shape=record];
+    n0 [label=\"<c0> |2| <c1>\"];
+    n1 [label=\"<c0> |1| <c1>\"];
+    n0:c0 -> n1;
+    n2 [label=\"<c0> |3| <c1> |4| <c2> |5| <c3>\"];
+    n0:c1 -> n2;
+}
+";
+        assert_eq!(tree.to_dot(), expected);
+    }
+
+    #[test]
+    fn test_to_dot_escapes_record_characters() {
+        let mut tree = SimpleBTreeSet::<&str, 2>::new();
+        tree.insert("a|b").unwrap();
+        assert!(tree.to_dot().contains(r#"|\"a\|b\"|"#));
+    }
+}
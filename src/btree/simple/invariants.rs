@@ -0,0 +1,267 @@
+use super::{Allocator, Comparator, Layout, Node, NodeStorage, SimpleBTreeSet};
+use crate::{Error, Result};
+
+impl<K, const B: usize, A: Allocator + Clone, C: Comparator<K>, L: Layout>
+    SimpleBTreeSet<K, B, A, C, L>
+{
+    /// Verifies the structural invariants of the tree:
+    ///
+    ///    1. The keys are in strictly ascending order, both within each node
+    ///       and across the subtrees separated by the keys of a parent.
+    ///    2. Every node, except the root, holds between `B - 1` and `2B - 1`
+    ///       keys. The root holds at most `2B - 1` keys, and at least one key
+    ///       if it is an intermediate node.
+    ///    3. Intermediate nodes have exactly one more child than keys, while
+    ///       leaves have no children.
+    ///    4. All leaves are at the same depth.
+    ///
+    /// Returns an `Error::InvariantViolation` naming the path of child indices
+    /// from the root to the first violating node.
+    pub fn check_invariants(&self) -> Result<()> {
+        let Some(root) = self.root.as_ref() else {
+            return Ok(());
+        };
+
+        let mut checker = Checker {
+            path: Vec::new(),
+            leaf_depth: None,
+            cmp: &self.cmp,
+        };
+        checker.check(&root.node, None, None)
+    }
+
+    /// Checks the invariants after the given operation when the `paranoid`
+    /// feature is enabled, panicking with the operation and the violation, so
+    /// a corrupted tree fails right where it was corrupted.
+    pub(super) fn validate_after(&self, operation: &str) {
+        if cfg!(feature = "paranoid")
+            && let Err(err) = self.check_invariants()
+        {
+            panic!("{operation} left the tree invalid: {err}");
+        }
+    }
+}
+
+struct Checker<'a, C> {
+    /// The child indices leading from the root to the node being checked.
+    path: Vec<usize>,
+    /// The depth of the first leaf encountered.
+    leaf_depth: Option<usize>,
+    /// The comparator which orders the keys of the tree.
+    cmp: &'a C,
+}
+
+impl<C> Checker<'_, C> {
+    fn violation(&self, reason: String) -> Error {
+        Error::InvariantViolation {
+            path: self.path.clone(),
+            reason,
+        }
+    }
+
+    /// Checks the subtree rooted at the node, whose keys must lie strictly
+    /// between the given bounds.
+    fn check<K, const B: usize, A: Allocator, L: Layout>(
+        &mut self,
+        node: &Node<K, B, A, L>,
+        lower: Option<&K>,
+        upper: Option<&K>,
+    ) -> Result<()>
+    where
+        C: Comparator<K>,
+    {
+        let keys = node.keys.len();
+        let is_root = self.path.is_empty();
+
+        if keys > Node::<K, B, A>::MAX_KEYS {
+            let max = Node::<K, B, A>::MAX_KEYS;
+            return Err(self.violation(format!("node holds {keys} keys, more than {max}")));
+        }
+        if !is_root && node.is_deficient() {
+            let min = Node::<K, B, A>::MIN_KEYS;
+            return Err(self.violation(format!("node holds {keys} keys, less than {min}")));
+        }
+        if is_root && !node.is_leaf && keys == 0 {
+            return Err(self.violation("intermediate root holds no keys".to_string()));
+        }
+
+        if node
+            .keys
+            .iter()
+            .zip(node.keys.iter().skip(1))
+            .any(|(a, b)| self.cmp.compare(a, b).is_ge())
+        {
+            return Err(self.violation("keys are not in ascending order".to_string()));
+        }
+        if let (Some(lower), Some(first)) = (lower, node.keys.first())
+            && self.cmp.compare(first, lower).is_le()
+        {
+            return Err(self.violation("key is not greater than its parent key".to_string()));
+        }
+        if let (Some(upper), Some(last)) = (upper, node.keys.last())
+            && self.cmp.compare(last, upper).is_ge()
+        {
+            return Err(self.violation("key is not smaller than its parent key".to_string()));
+        }
+
+        if node.is_leaf {
+            return self.check_leaf(node);
+        }
+
+        let children = node.children.len();
+        if children != keys + 1 {
+            return Err(self.violation(format!("node holds {keys} keys but {children} children")));
+        }
+
+        for (idx, child) in node.children.iter().enumerate() {
+            let lower = if idx == 0 {
+                lower
+            } else {
+                Some(&node.keys[idx - 1])
+            };
+            let upper = node.keys.get(idx).or(upper);
+
+            self.path.push(idx);
+            self.check(child, lower, upper)?;
+            self.path.pop();
+        }
+
+        Ok(())
+    }
+
+    fn check_leaf<K, const B: usize, A: Allocator, L: Layout>(
+        &mut self,
+        node: &Node<K, B, A, L>,
+    ) -> Result<()> {
+        if !node.children.is_empty() {
+            let children = node.children.len();
+            return Err(self.violation(format!("leaf has {children} children")));
+        }
+
+        let depth = self.path.len();
+        match self.leaf_depth {
+            None => self.leaf_depth = Some(depth),
+            Some(expected) if expected != depth => {
+                return Err(self.violation(format!(
+                    "leaf is at depth {depth}, while other leaves are at depth {expected}"
+                )));
+            }
+            Some(_) => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Global, Link, OrdComparator, Recorder, Root, next_tree_id};
+    use super::*;
+    use crate::BTreeSet;
+
+    type TestNode = Node<usize, 2, Global>;
+
+    fn leaf<const N: usize>(keys: [usize; N]) -> TestNode {
+        Node::leaf(keys, Global)
+    }
+
+    fn intermediate<const N: usize, const M: usize>(
+        keys: [usize; N],
+        children: [Link<usize, 2, Global>; M],
+    ) -> TestNode {
+        Node::intermediate(keys, children, Global)
+    }
+
+    fn tree_from_root(node: TestNode) -> SimpleBTreeSet<usize, 2> {
+        SimpleBTreeSet {
+            root: Some(Root { node }),
+            alloc: Global,
+            cmp: OrdComparator,
+            spare: Vec::new(),
+            id: next_tree_id(),
+            generation: 0,
+            metrics: Recorder::default(),
+        }
+    }
+
+    fn assert_violation(tree: &SimpleBTreeSet<usize, 2>, expected_path: &[usize], expected: &str) {
+        match tree.check_invariants() {
+            Err(Error::InvariantViolation { path, reason }) => {
+                assert_eq!(path, expected_path);
+                assert!(reason.contains(expected), "unexpected reason: {reason}");
+            }
+            result => panic!("expected a violation, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn test_valid_trees_pass() {
+        let mut tree = SimpleBTreeSet::<usize, 2>::new();
+        tree.check_invariants().unwrap();
+
+        for key in 0..1000 {
+            tree.insert((key * 7) % 1000).unwrap();
+            tree.check_invariants().unwrap();
+        }
+        for key in 0..1000 {
+            tree.remove(&((key * 13) % 1000)).unwrap();
+            tree.check_invariants().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_unordered_keys_are_reported() {
+        let tree = tree_from_root(leaf([2, 1]));
+        assert_violation(&tree, &[], "ascending order");
+    }
+
+    #[test]
+    fn test_keys_outside_parent_bounds_are_reported() {
+        let left = leaf([1, 5]);
+        let right = leaf([7, 8]);
+        let tree = tree_from_root(intermediate([4], [left.link(), right.link()]));
+        assert_violation(&tree, &[0], "not smaller than its parent key");
+
+        let left = leaf([1, 2]);
+        let right = leaf([3, 8]);
+        let tree = tree_from_root(intermediate([4], [left.link(), right.link()]));
+        assert_violation(&tree, &[1], "not greater than its parent key");
+    }
+
+    #[test]
+    fn test_deficient_non_root_node_is_reported() {
+        let left = leaf([1]);
+        let right = leaf([]);
+        let tree = tree_from_root(intermediate([4], [left.link(), right.link()]));
+        assert_violation(&tree, &[1], "less than 1");
+    }
+
+    #[test]
+    fn test_children_count_mismatch_is_reported() {
+        let left = leaf([1]);
+        let tree = tree_from_root(intermediate([4], [left.link()]));
+        assert_violation(&tree, &[], "1 keys but 1 children");
+    }
+
+    #[test]
+    fn test_uneven_leaf_depth_is_reported() {
+        let left = intermediate([2], [leaf([1]).link(), leaf([3]).link()]);
+        let right = leaf([5]);
+        let tree = tree_from_root(intermediate([4], [left.link(), right.link()]));
+        assert_violation(&tree, &[1], "depth 1, while other leaves are at depth 2");
+    }
+
+    #[cfg(feature = "paranoid")]
+    #[test]
+    #[should_panic(expected = "insert left the tree invalid")]
+    fn test_paranoid_mode_panics_on_the_corrupting_operation() {
+        let left = leaf([1]);
+        let right = leaf([3]);
+        let mut tree = tree_from_root(intermediate([2], [left.link(), right.link()]));
+
+        // Break the order of the keys behind the back of the tree, so the
+        // next mutation notices it.
+        tree.root.as_mut().unwrap().node.keys[0] = 5;
+        tree.insert(4).unwrap();
+    }
+}
@@ -0,0 +1,692 @@
+use super::{
+    Allocator, Comparator, Cursor, CursorMut, Global, Layout, Node, NodeStorage, OrdComparator,
+    SimpleBTreeSet, SortedLayout,
+};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque, vec_deque};
+use std::iter::{FusedIterator, Peekable};
+use std::ops;
+
+/// An iterator over the keys of a `SimpleBTreeSet`, in ascending order.
+pub struct Iter<
+    'a,
+    K,
+    const B: usize,
+    A: Allocator = Global,
+    C = OrdComparator,
+    L: Layout = SortedLayout,
+> {
+    cursor: Cursor<'a, K, B, A, C, L>,
+}
+
+impl<'a, K, const B: usize, A: Allocator, C, L: Layout> Iter<'a, K, B, A, C, L> {
+    pub(super) fn new(set: &'a SimpleBTreeSet<K, B, A, C, L>) -> Self {
+        let mut cursor = Cursor::new(set);
+        cursor.move_next();
+        Iter { cursor }
+    }
+}
+
+impl<'a, K, const B: usize, A: Allocator, C, L: Layout> Iterator for Iter<'a, K, B, A, C, L> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Once the cursor reaches the ghost position, it is never moved again,
+        // so the iterator does not wrap around.
+        let key = self.cursor.key()?;
+        self.cursor.move_next();
+        Some(key)
+    }
+}
+
+impl<K, const B: usize, A: Allocator, C, L: Layout> FusedIterator for Iter<'_, K, B, A, C, L> {}
+
+impl<'a, K, const B: usize, A: Allocator + Clone, C: Comparator<K>, L: Layout> IntoIterator
+    for &'a SimpleBTreeSet<K, B, A, C, L>
+{
+    type Item = &'a K;
+    type IntoIter = Iter<'a, K, B, A, C, L>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An owning iterator over the keys of a `SimpleBTreeSet`, in ascending
+/// order.
+pub struct IntoIter<K>(vec_deque::IntoIter<K>);
+
+impl<K> Iterator for IntoIter<K> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<K> ExactSizeIterator for IntoIter<K> {}
+
+impl<K> FusedIterator for IntoIter<K> {}
+
+impl<K, const B: usize, A: Allocator, C, L: Layout> IntoIterator for SimpleBTreeSet<K, B, A, C, L> {
+    type Item = K;
+    type IntoIter = IntoIter<K>;
+
+    fn into_iter(mut self) -> Self::IntoIter {
+        let mut keys = VecDeque::new();
+        if let Some(root) = self.root.take() {
+            root.node.collect_keys(&mut keys);
+        }
+        IntoIter(keys.into_iter())
+    }
+}
+
+impl<K, const B: usize, A: Allocator, L: Layout> Node<K, B, A, L> {
+    /// Moves the keys of the subtree rooted at the node into the given queue,
+    /// in ascending order.
+    fn collect_keys(self, keys: &mut VecDeque<K>) {
+        if self.is_leaf {
+            keys.extend(self.keys.into_keys());
+            return;
+        }
+
+        let mut children = self.children.into_iter();
+        for (key, child) in self.keys.into_keys().zip(children.by_ref()) {
+            child.collect_keys(keys);
+            keys.push_back(key);
+        }
+        if let Some(last) = children.next() {
+            last.collect_keys(keys);
+        }
+    }
+}
+
+/// An iterator which removes the keys matching a predicate from a
+/// `SimpleBTreeSet`, and yields them in ascending order. The keys are only
+/// removed as the iterator advances, and the tree stays valid in between.
+pub struct ExtractIf<
+    'a,
+    K,
+    F,
+    const B: usize,
+    A: Allocator = Global,
+    C = OrdComparator,
+    L: Layout = SortedLayout,
+> {
+    cursor: CursorMut<'a, K, B, A, C, L>,
+    pred: F,
+}
+
+impl<K, F, const B: usize, A, C, L: Layout> Iterator for ExtractIf<'_, K, F, B, A, C, L>
+where
+    F: FnMut(&K) -> bool,
+    A: Allocator + Clone,
+    C: Comparator<K>,
+{
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let key = self.cursor.key()?;
+            if (self.pred)(key) {
+                return self.cursor.remove_current();
+            }
+            self.cursor.move_next();
+        }
+    }
+}
+
+impl<K, F, const B: usize, A, C, L: Layout> FusedIterator for ExtractIf<'_, K, F, B, A, C, L>
+where
+    F: FnMut(&K) -> bool,
+    A: Allocator + Clone,
+    C: Comparator<K>,
+{
+}
+
+/// Walks two sorted iterators side by side, yielding the smaller key of each
+/// side at every step, or both keys when they are equal.
+///
+/// Both sets must order their keys the same way, so the comparator of the
+/// first set is used for both.
+struct MergeIter<'a, K, const B: usize, A: Allocator, C, L: Layout> {
+    a: Peekable<Iter<'a, K, B, A, C, L>>,
+    b: Peekable<Iter<'a, K, B, A, C, L>>,
+    cmp: &'a C,
+}
+
+impl<'a, K, const B: usize, A: Allocator + Clone, C: Comparator<K>, L: Layout>
+    MergeIter<'a, K, B, A, C, L>
+{
+    fn new(a: &'a SimpleBTreeSet<K, B, A, C, L>, b: &'a SimpleBTreeSet<K, B, A, C, L>) -> Self {
+        MergeIter {
+            a: a.iter().peekable(),
+            b: b.iter().peekable(),
+            cmp: &a.cmp,
+        }
+    }
+
+    fn nexts(&mut self) -> (Option<&'a K>, Option<&'a K>) {
+        let ordering = match (self.a.peek(), self.b.peek()) {
+            (Some(a), Some(b)) => self.cmp.compare(a, b),
+            _ => Ordering::Equal,
+        };
+
+        match ordering {
+            Ordering::Less => (self.a.next(), None),
+            Ordering::Greater => (None, self.b.next()),
+            Ordering::Equal => (self.a.next(), self.b.next()),
+        }
+    }
+}
+
+/// A lazy iterator over the keys in either of two sets, in ascending order.
+pub struct Union<
+    'a,
+    K,
+    const B: usize,
+    A: Allocator = Global,
+    C = OrdComparator,
+    L: Layout = SortedLayout,
+>(MergeIter<'a, K, B, A, C, L>);
+
+/// A lazy iterator over the keys in both of two sets, in ascending order.
+pub struct Intersection<
+    'a,
+    K,
+    const B: usize,
+    A: Allocator = Global,
+    C = OrdComparator,
+    L: Layout = SortedLayout,
+>(MergeIter<'a, K, B, A, C, L>);
+
+/// A lazy iterator over the keys in the first set but not in the second, in
+/// ascending order.
+pub struct Difference<
+    'a,
+    K,
+    const B: usize,
+    A: Allocator = Global,
+    C = OrdComparator,
+    L: Layout = SortedLayout,
+>(MergeIter<'a, K, B, A, C, L>);
+
+/// A lazy iterator over the keys in exactly one of two sets, in ascending
+/// order.
+pub struct SymmetricDifference<
+    'a,
+    K,
+    const B: usize,
+    A: Allocator = Global,
+    C = OrdComparator,
+    L: Layout = SortedLayout,
+>(MergeIter<'a, K, B, A, C, L>);
+
+impl<'a, K, const B: usize, A: Allocator + Clone, C: Comparator<K>, L: Layout> Iterator
+    for Union<'a, K, B, A, C, L>
+{
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (a, b) = self.0.nexts();
+        a.or(b)
+    }
+}
+
+impl<K, const B: usize, A: Allocator + Clone, C: Comparator<K>, L: Layout> FusedIterator
+    for Union<'_, K, B, A, C, L>
+{
+}
+
+impl<'a, K, const B: usize, A: Allocator + Clone, C: Comparator<K>, L: Layout> Iterator
+    for Intersection<'a, K, B, A, C, L>
+{
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.0.nexts() {
+                (Some(a), Some(_)) => return Some(a),
+                (None, None) => return None,
+                // Once either side runs out, there are no common keys left.
+                (Some(_), None) if self.0.b.peek().is_none() => return None,
+                (None, Some(_)) if self.0.a.peek().is_none() => return None,
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<K, const B: usize, A: Allocator + Clone, C: Comparator<K>, L: Layout> FusedIterator
+    for Intersection<'_, K, B, A, C, L>
+{
+}
+
+impl<'a, K, const B: usize, A: Allocator + Clone, C: Comparator<K>, L: Layout> Iterator
+    for Difference<'a, K, B, A, C, L>
+{
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.0.nexts() {
+                (Some(a), None) => return Some(a),
+                (None, None) => return None,
+                (None, Some(_)) if self.0.a.peek().is_none() => return None,
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<K, const B: usize, A: Allocator + Clone, C: Comparator<K>, L: Layout> FusedIterator
+    for Difference<'_, K, B, A, C, L>
+{
+}
+
+impl<'a, K, const B: usize, A: Allocator + Clone, C: Comparator<K>, L: Layout> Iterator
+    for SymmetricDifference<'a, K, B, A, C, L>
+{
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.0.nexts() {
+                (Some(key), None) | (None, Some(key)) => return Some(key),
+                (None, None) => return None,
+                (Some(_), Some(_)) => {}
+            }
+        }
+    }
+}
+
+impl<K, const B: usize, A: Allocator + Clone, C: Comparator<K>, L: Layout> FusedIterator
+    for SymmetricDifference<'_, K, B, A, C, L>
+{
+}
+
+/// A lazy iterator over the keys in any of many sets, in ascending order.
+/// Keys found in several sets are yielded once, from the first of the sets.
+///
+/// The iterators of the sets are kept in a heap ordered by their next key, so
+/// every key costs a logarithmic number of comparisons in the number of sets.
+/// All sets must order their keys the same way.
+pub struct KWayMerge<
+    'a,
+    K,
+    const B: usize,
+    A: Allocator = Global,
+    C = OrdComparator,
+    L: Layout = SortedLayout,
+> {
+    heap: BinaryHeap<Head<'a, K, B, A, C, L>>,
+}
+
+/// The next key of one of the merged sets, together with the iterator over
+/// the keys after it.
+struct Head<'a, K, const B: usize, A: Allocator, C, L: Layout> {
+    key: &'a K,
+    /// The position of the set among the merged ones, which breaks ties.
+    source: usize,
+    rest: Iter<'a, K, B, A, C, L>,
+    cmp: &'a C,
+}
+
+impl<'a, K, const B: usize, A: Allocator, C, L: Layout> Head<'a, K, B, A, C, L> {
+    /// Moves to the next key of the set, or returns `None` if there is none.
+    fn advance(mut self) -> Option<Self> {
+        self.key = self.rest.next()?;
+        Some(self)
+    }
+}
+
+impl<K, const B: usize, A: Allocator, C: Comparator<K>, L: Layout> Ord for Head<'_, K, B, A, C, L> {
+    /// Orders the heads in reverse, so the max-heap yields the smallest key,
+    /// and of equal keys, the one of the first set.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.cmp
+            .compare(other.key, self.key)
+            .then(other.source.cmp(&self.source))
+    }
+}
+
+impl<K, const B: usize, A: Allocator, C: Comparator<K>, L: Layout> PartialOrd
+    for Head<'_, K, B, A, C, L>
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K, const B: usize, A: Allocator, C: Comparator<K>, L: Layout> PartialEq
+    for Head<'_, K, B, A, C, L>
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<K, const B: usize, A: Allocator, C: Comparator<K>, L: Layout> Eq for Head<'_, K, B, A, C, L> {}
+
+impl<'a, K, const B: usize, A: Allocator, C: Comparator<K>, L: Layout> Iterator
+    for KWayMerge<'a, K, B, A, C, L>
+{
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let head = self.heap.pop()?;
+        let (key, cmp) = (head.key, head.cmp);
+        self.heap.extend(head.advance());
+
+        // The same key may still be at the front of other sets.
+        while let Some(head) = self.heap.peek()
+            && cmp.compare(head.key, key) == Ordering::Equal
+        {
+            let head = self.heap.pop().unwrap();
+            self.heap.extend(head.advance());
+        }
+        Some(key)
+    }
+}
+
+impl<K, const B: usize, A: Allocator, C: Comparator<K>, L: Layout> FusedIterator
+    for KWayMerge<'_, K, B, A, C, L>
+{
+}
+
+impl<K, const B: usize, A: Allocator + Clone, C: Comparator<K>, L: Layout>
+    SimpleBTreeSet<K, B, A, C, L>
+{
+    /// Returns an iterator over the keys of the tree, in ascending order.
+    pub fn iter(&self) -> Iter<'_, K, B, A, C, L> {
+        Iter::new(self)
+    }
+
+    /// Removes all keys from the tree, and returns them in an iterator, in
+    /// ascending order. The tree is empty as soon as this returns.
+    pub fn drain(&mut self) -> IntoIter<K> {
+        let mut keys = VecDeque::new();
+        if let Some(root) = self.root.take() {
+            root.node.collect_keys(&mut keys);
+        }
+        self.bump_generation();
+        IntoIter(keys.into_iter())
+    }
+
+    /// Returns an iterator which removes the keys for which the predicate
+    /// returns `true`, and yields them in ascending order. Keys which are not
+    /// reached before the iterator is dropped are kept in the tree.
+    pub fn extract_if<F>(&mut self, pred: F) -> ExtractIf<'_, K, F, B, A, C, L>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        ExtractIf {
+            cursor: self.cursor_mut(),
+            pred,
+        }
+    }
+
+    /// Returns a lazy iterator over the keys in `self` or `other`.
+    pub fn union<'a>(&'a self, other: &'a Self) -> Union<'a, K, B, A, C, L> {
+        Union(MergeIter::new(self, other))
+    }
+
+    /// Returns a lazy iterator over the keys in both `self` and `other`.
+    pub fn intersection<'a>(&'a self, other: &'a Self) -> Intersection<'a, K, B, A, C, L> {
+        Intersection(MergeIter::new(self, other))
+    }
+
+    /// Returns a lazy iterator over the keys in `self` but not in `other`.
+    pub fn difference<'a>(&'a self, other: &'a Self) -> Difference<'a, K, B, A, C, L> {
+        Difference(MergeIter::new(self, other))
+    }
+
+    /// Returns a lazy iterator over the keys in `self` or `other`, but not in
+    /// both.
+    pub fn symmetric_difference<'a>(
+        &'a self,
+        other: &'a Self,
+    ) -> SymmetricDifference<'a, K, B, A, C, L> {
+        SymmetricDifference(MergeIter::new(self, other))
+    }
+
+    /// Returns a lazy iterator over the keys in any of the given sets, in
+    /// ascending order and without duplicates. This is the many-way version
+    /// of `union`.
+    pub fn merge_iter<'a>(sets: impl IntoIterator<Item = &'a Self>) -> KWayMerge<'a, K, B, A, C, L>
+    where
+        Self: 'a,
+    {
+        let heads = sets.into_iter().enumerate().filter_map(|(source, set)| {
+            let mut rest = set.iter();
+            Some(Head {
+                key: rest.next()?,
+                source,
+                rest,
+                cmp: &set.cmp,
+            })
+        });
+        KWayMerge {
+            heap: heads.collect(),
+        }
+    }
+}
+
+/// Implements a set operator on references to trees, which builds a new tree
+/// out of the keys of a merge iterator. The keys come out in ascending order,
+/// so they are appended to the new tree without being compared again.
+macro_rules! impl_set_operator {
+    ($($op:ident, $method:ident, $merge:ident, $doc:literal;)*) => {
+        $(
+            impl<K, const B: usize, A, C, L> ops::$op<&SimpleBTreeSet<K, B, A, C, L>>
+                for &SimpleBTreeSet<K, B, A, C, L>
+            where
+                K: Clone,
+                A: Allocator + Clone,
+                C: Comparator<K> + Clone,
+                L: Layout,
+            {
+                type Output = SimpleBTreeSet<K, B, A, C, L>;
+
+                #[doc = $doc]
+                fn $method(self, rhs: &SimpleBTreeSet<K, B, A, C, L>) -> Self::Output {
+                    let keys = self.$merge(rhs).cloned();
+                    SimpleBTreeSet::from_sorted_in(keys, self.cmp.clone(), self.alloc.clone())
+                }
+            }
+        )*
+    };
+}
+
+impl_set_operator!(
+    BitOr, bitor, union,
+    "Returns the keys in `self` or `rhs` as a new tree.";
+    BitAnd, bitand, intersection,
+    "Returns the keys in both `self` and `rhs` as a new tree.";
+    Sub, sub, difference,
+    "Returns the keys in `self` but not in `rhs` as a new tree.";
+    BitXor, bitxor, symmetric_difference,
+    "Returns the keys in `self` or `rhs`, but not in both, as a new tree.";
+);
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::tree_with;
+    use super::*;
+    use crate::BTreeSet;
+    use std::collections::BTreeSet as StdBTreeSet;
+
+    fn trees_with(
+        keys: impl IntoIterator<Item = usize> + Clone,
+    ) -> (SimpleBTreeSet<usize, 2>, StdBTreeSet<usize>) {
+        (tree_with(keys.clone()), keys.into_iter().collect())
+    }
+
+    #[test]
+    fn test_iter_on_empty_tree_yields_nothing() {
+        let tree = SimpleBTreeSet::<usize, 2>::new();
+        assert_eq!(tree.iter().next(), None);
+    }
+
+    #[test]
+    fn test_iter_yields_keys_in_order() {
+        let (tree, reference) = trees_with((0..1000).map(|i| (i * 7) % 1000));
+        assert!(tree.iter().eq(reference.iter()));
+        assert!((&tree).into_iter().eq(reference.iter()));
+    }
+
+    #[test]
+    fn test_into_iter_yields_owned_keys_in_order() {
+        let (tree, reference) = trees_with((0..1000).map(|i| (i * 7) % 1000));
+        let mut keys = tree.into_iter();
+        assert_eq!(keys.len(), 1000);
+        keys.nth(9);
+        assert_eq!(keys.len(), 990);
+        assert!(keys.eq(reference.into_iter().skip(10)));
+    }
+
+    #[test]
+    fn test_set_operators_build_new_trees() {
+        let (a, std_a) = trees_with((0..300).map(|i| i * 2));
+        let (b, std_b) = trees_with((0..200).map(|i| i * 3));
+
+        let union = &a | &b;
+        assert!(union.iter().copied().eq(&std_a | &std_b));
+        assert!((&a & &b).into_iter().eq(&std_a & &std_b));
+        assert!((&a - &b).into_iter().eq(&std_a - &std_b));
+        assert!((&a ^ &b).into_iter().eq(&std_a ^ &std_b));
+        assert!(union.check_invariants().is_ok());
+
+        let empty = SimpleBTreeSet::new();
+        assert_eq!((&a & &empty).iter().next(), None);
+        assert!((&empty | &a).iter().eq(a.iter()));
+    }
+
+    #[test]
+    fn test_iter_is_fused() {
+        let (tree, _) = trees_with(0..3);
+        let mut iter = tree.iter();
+        assert_eq!(iter.by_ref().count(), 3);
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn test_set_operations_match_std() {
+        let (a, std_a) = trees_with((0..300).map(|i| i * 2));
+        let (b, std_b) = trees_with((0..200).map(|i| i * 3));
+
+        assert!(a.union(&b).eq(std_a.union(&std_b)));
+        assert!(a.intersection(&b).eq(std_a.intersection(&std_b)));
+        assert!(a.difference(&b).eq(std_a.difference(&std_b)));
+        assert!(b.difference(&a).eq(std_b.difference(&std_a)));
+        assert!(
+            a.symmetric_difference(&b)
+                .eq(std_a.symmetric_difference(&std_b))
+        );
+    }
+
+    #[test]
+    fn test_set_operations_with_empty_tree() {
+        let (a, _) = trees_with(0..10);
+        let empty = SimpleBTreeSet::new();
+
+        assert!(a.union(&empty).eq(a.iter()));
+        assert_eq!(a.intersection(&empty).next(), None);
+        assert!(a.difference(&empty).eq(a.iter()));
+        assert_eq!(empty.difference(&a).next(), None);
+        assert!(empty.symmetric_difference(&a).eq(a.iter()));
+    }
+
+    #[test]
+    fn test_set_operations_with_disjoint_ranges() {
+        let (a, _) = trees_with(0..100);
+        let (b, _) = trees_with(100..200);
+
+        assert!(a.union(&b).copied().eq(0..200));
+        assert_eq!(a.intersection(&b).next(), None);
+        assert!(a.difference(&b).eq(a.iter()));
+        assert!(a.symmetric_difference(&b).copied().eq(0..200));
+    }
+
+    #[test]
+    fn test_merge_iter_matches_std() {
+        let parts: Vec<_> = (1..8)
+            .map(|step| trees_with((0..300).map(|i| i * step % 701)))
+            .collect();
+        let trees: Vec<&SimpleBTreeSet<usize, 2>> = parts.iter().map(|(tree, _)| tree).collect();
+        let expected: StdBTreeSet<usize> = parts
+            .iter()
+            .flat_map(|(_, keys)| keys.iter().copied())
+            .collect();
+
+        assert!(SimpleBTreeSet::merge_iter(trees.iter().copied()).eq(expected.iter()));
+        assert!(SimpleBTreeSet::merge_iter([trees[0], trees[0]]).eq(parts[0].1.iter()));
+        assert_eq!(SimpleBTreeSet::<usize, 2>::merge_iter([]).next(), None);
+    }
+
+    #[test]
+    fn test_merge_iter_yields_keys_of_the_first_set() {
+        // Keys which compare equal but are not the same value.
+        let comparator = |a: &(u8, char), b: &(u8, char)| a.0.cmp(&b.0);
+        let mut first = SimpleBTreeSet::<(u8, char), 2, _, _>::with_comparator(comparator);
+        let mut second = SimpleBTreeSet::<(u8, char), 2, _, _>::with_comparator(comparator);
+        for key in [1, 3, 5] {
+            first.insert((key, 'a')).unwrap();
+        }
+        for key in [3, 4, 5, 6] {
+            second.insert((key, 'b')).unwrap();
+        }
+
+        let merged: Vec<_> = SimpleBTreeSet::merge_iter([&first, &second])
+            .copied()
+            .collect();
+        assert_eq!(merged, [(1, 'a'), (3, 'a'), (4, 'b'), (5, 'a'), (6, 'b')]);
+    }
+
+    #[test]
+    fn test_drain_empties_tree_and_yields_keys_in_order() {
+        let (mut tree, reference) = trees_with((0..1000).map(|i| (i * 7) % 1000));
+        assert!(tree.drain().eq(reference));
+        assert_eq!(tree.iter().next(), None);
+
+        tree.insert(42).unwrap();
+        assert!(tree.iter().eq([42].iter()));
+    }
+
+    #[test]
+    fn test_extract_if_removes_only_matching_keys() {
+        let (mut tree, _) = trees_with((0..1000).map(|i| (i * 7) % 1000));
+        let extracted: Vec<_> = tree.extract_if(|key| key % 3 == 0).collect();
+
+        assert!(
+            extracted
+                .into_iter()
+                .eq((0..1000).filter(|key| key % 3 == 0))
+        );
+        assert!(
+            tree.iter()
+                .copied()
+                .eq((0..1000).filter(|key| key % 3 != 0))
+        );
+        tree.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn test_extract_if_keeps_tree_valid_between_steps() {
+        let (mut tree, _) = trees_with(0..500);
+        let mut extract = tree.extract_if(|key| key % 2 == 1);
+        for expected in (1..200).step_by(2) {
+            assert_eq!(extract.next(), Some(expected));
+        }
+        drop(extract);
+
+        tree.check_invariants().unwrap();
+        let expected = (0..500).filter(|key| key % 2 == 0 || *key >= 200);
+        assert!(tree.iter().copied().eq(expected));
+    }
+}
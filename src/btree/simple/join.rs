@@ -0,0 +1,656 @@
+use super::{
+    Allocator, Array, Comparator, Layout, Link, Node, NodeStorage, Recorder, Root, SimpleBTreeSet,
+    next_tree_id,
+};
+use crate::BTreeSet;
+use std::cmp::Ordering;
+use std::ops::{Bound, RangeBounds};
+
+impl<K, const B: usize, A: Allocator + Clone, C: Comparator<K> + Clone, L: Layout>
+    SimpleBTreeSet<K, B, A, C, L>
+{
+    /// Moves all keys of `other` into the tree.
+    ///
+    /// When all keys of `other` are greater than the keys of the tree (or the
+    /// other way around), the trees are joined in logarithmic time, by
+    /// splicing the shorter tree into the spine of the taller one. Otherwise,
+    /// the keys of `other` are inserted one by one, and the keys which already
+    /// exist in the tree are dropped.
+    pub fn append(&mut self, mut other: Self) {
+        let (Some(self_last), Some(other_first)) = (self.last(), other.first()) else {
+            if self.first().is_none() {
+                other.id = self.id;
+                other.generation = self.generation.wrapping_add(1);
+                *self = other;
+            }
+            return;
+        };
+
+        if self.cmp.compare(self_last, other_first).is_lt() {
+            let separator = other.pop_first().unwrap();
+            let left = self.take_root_node();
+            let right = other.take_root_node();
+            self.root = Some(Root {
+                node: Node::join_trees(left, separator, right),
+            });
+        } else if let (Some(other_last), Some(self_first)) = (other.last(), self.first())
+            && self.cmp.compare(other_last, self_first).is_lt()
+        {
+            let separator = other.pop_last().unwrap();
+            let left = other.take_root_node();
+            let right = self.take_root_node();
+            self.root = Some(Root {
+                node: Node::join_trees(left, separator, right),
+            });
+        } else {
+            for key in other {
+                let _ = self.insert(key);
+            }
+        }
+        self.bump_generation();
+        self.validate_after("append");
+    }
+
+    /// Splits the tree in two at the given key. The tree keeps the keys which
+    /// are smaller than the given key, and the rest are returned as a new
+    /// tree.
+    ///
+    /// The tree is cut along the search path of the key, and the fragments
+    /// on each side of the path are joined back together, which takes
+    /// logarithmic time.
+    pub fn split_off(&mut self, key: &K) -> Self {
+        let cmp = self.cmp.clone();
+        self.split_off_by(|k| cmp.compare(k, key))
+    }
+
+    /// Removes every key within the range from the tree.
+    ///
+    /// The tree is split at both bounds of the range, and the outer trees
+    /// are joined back together, which takes logarithmic time, apart from
+    /// dropping the removed keys.
+    pub fn remove_range<R: RangeBounds<K>>(&mut self, range: R) {
+        let cmp = self.cmp.clone();
+
+        // The keys equal to an excluded start, or an included end, are moved
+        // to the left side of the split.
+        let mut middle = match range.start_bound() {
+            Bound::Included(start) => self.split_off_by(|k| cmp.compare(k, start)),
+            Bound::Excluded(start) => {
+                self.split_off_by(|k| cmp.compare(k, start).then(Ordering::Less))
+            }
+            Bound::Unbounded => self.split_off_by(|_| Ordering::Greater),
+        };
+        let right = match range.end_bound() {
+            Bound::Included(end) => {
+                middle.split_off_by(|k| cmp.compare(k, end).then(Ordering::Less))
+            }
+            Bound::Excluded(end) => middle.split_off_by(|k| cmp.compare(k, end)),
+            Bound::Unbounded => return,
+        };
+
+        self.append(right);
+    }
+
+    /// Splits the tree in two, keeping the keys for which the given function
+    /// returns `Ordering::Less`, and returning the rest as a new tree. The
+    /// function must be monotonic over the keys.
+    fn split_off_by(&mut self, f: impl Fn(&K) -> Ordering) -> Self {
+        let node = self.take_root_node();
+        let height = node.height();
+        let ((left, _), (right, _)) = node.split_at_key(height, &f);
+
+        self.root = Some(Root { node: left });
+        let right = SimpleBTreeSet {
+            root: Some(Root { node: right }),
+            alloc: self.alloc.clone(),
+            cmp: self.cmp.clone(),
+            spare: Vec::new(),
+            id: next_tree_id(),
+            generation: 0,
+            metrics: Recorder::default(),
+        };
+        self.bump_generation();
+        self.validate_after("split_off");
+        right.validate_after("split_off");
+        right
+    }
+
+    /// Keeps only the keys for which the predicate returns `true`.
+    ///
+    /// Instead of removing the other keys one by one, the kept keys are moved
+    /// out of the tree in order, and appended to a new tree along its right
+    /// edge, which visits every key once and never compares keys.
+    pub fn retain(&mut self, mut f: impl FnMut(&K) -> bool) {
+        let old = SimpleBTreeSet {
+            root: self.root.take(),
+            alloc: self.alloc.clone(),
+            cmp: self.cmp.clone(),
+            spare: Vec::new(),
+            id: self.id,
+            generation: 0,
+            metrics: Recorder::default(),
+        };
+
+        self.root = Self::root_from_sorted(old.into_iter().filter(|key| f(key)), &self.alloc);
+        self.bump_generation();
+        self.validate_after("retain");
+    }
+
+    /// Builds a tree out of keys given in ascending order, without duplicates,
+    /// by appending them along its right edge.
+    pub(super) fn from_sorted_in(keys: impl IntoIterator<Item = K>, cmp: C, alloc: A) -> Self {
+        let mut tree = SimpleBTreeSet::with_comparator_in(cmp, alloc);
+        tree.root = Self::root_from_sorted(keys, &tree.alloc);
+        tree.validate_after("from_sorted");
+        tree
+    }
+
+    fn root_from_sorted(keys: impl IntoIterator<Item = K>, alloc: &A) -> Option<Root<K, B, A, L>> {
+        let mut node = Node::leaf([], alloc.clone());
+        for key in keys {
+            node.push_last_key(key);
+        }
+        (!node.has_no_remaining_keys()).then_some(Root { node })
+    }
+
+    fn first(&self) -> Option<&K> {
+        self.cursor().key()
+    }
+
+    fn last(&self) -> Option<&K> {
+        let mut cursor = self.cursor();
+        cursor.move_prev();
+        cursor.move_prev();
+        cursor.key()
+    }
+
+    fn pop_first(&mut self) -> Option<K> {
+        self.cursor_mut().remove_current()
+    }
+
+    fn pop_last(&mut self) -> Option<K> {
+        let mut cursor = self.cursor_mut();
+        cursor.move_prev();
+        cursor.move_prev();
+        cursor.remove_current()
+    }
+
+    /// Takes the root node out of the tree, leaving it empty.
+    fn take_root_node(&mut self) -> Node<K, B, A, L> {
+        self.root
+            .take()
+            .map(|root| root.node)
+            .unwrap_or_else(|| Node::leaf([], self.alloc.clone()))
+    }
+}
+
+/// A tree given by its root node and its height.
+type Tree<K, const B: usize, A, L> = (Node<K, B, A, L>, usize);
+
+impl<K, const B: usize, A: Allocator + Clone, L: Layout> Node<K, B, A, L> {
+    /// Returns the number of levels below the node.
+    fn height(&self) -> usize {
+        let mut height = 0;
+        let mut node = self;
+        while !node.is_leaf {
+            node = &node.children[0];
+            height += 1;
+        }
+        height
+    }
+
+    /// Joins two trees into one, computing their heights first.
+    fn join_trees(
+        left: Node<K, B, A, L>,
+        separator: K,
+        right: Node<K, B, A, L>,
+    ) -> Node<K, B, A, L> {
+        let (left_height, right_height) = (left.height(), right.height());
+        let (node, _) = Node::join((left, left_height), separator, (right, right_height));
+        node
+    }
+
+    /// Joins two trees of the given heights into one, given a separator key
+    /// which is greater than all keys of the left tree, and smaller than all
+    /// keys of the right tree. The roots of both trees are allowed to be
+    /// deficient, or even empty. Returns the joined tree and its height.
+    ///
+    /// This takes time proportional to the difference of the heights.
+    fn join(
+        (mut left, left_height): Tree<K, B, A, L>,
+        separator: K,
+        (mut right, right_height): Tree<K, B, A, L>,
+    ) -> Tree<K, B, A, L> {
+        if right.has_no_remaining_keys() && right.is_leaf {
+            let grown = left.push_last_key(separator);
+            return (left, left_height + usize::from(grown));
+        }
+        if left.has_no_remaining_keys() && left.is_leaf {
+            let grown = right.push_first_key(separator);
+            return (right, right_height + usize::from(grown));
+        }
+
+        if left_height == right_height {
+            let alloc = left.alloc.clone();
+            let mut root = Node::intermediate([separator], [left.link(), right.link()], alloc);
+            root.rebalance_children_at(0);
+            if root.has_no_remaining_keys() {
+                return (*root.children.pop_front().unwrap(), left_height);
+            }
+            (root, left_height + 1)
+        } else if left_height > right_height {
+            match left.join_right(left_height, separator, right, right_height) {
+                Some((hoist, sibling)) => {
+                    let alloc = left.alloc.clone();
+                    let root = Node::intermediate([hoist], [left.link(), sibling.link()], alloc);
+                    (root, left_height + 1)
+                }
+                None => (left, left_height),
+            }
+        } else {
+            match right.join_left(right_height, left, separator, left_height) {
+                Some((hoist, sibling)) => {
+                    let alloc = right.alloc.clone();
+                    let root = Node::intermediate([hoist], [right.link(), sibling.link()], alloc);
+                    (root, right_height + 1)
+                }
+                None => (right, right_height),
+            }
+        }
+    }
+
+    /// Splits the subtree rooted at the node, which is at the given height,
+    /// into the keys for which the given function returns `Ordering::Less`
+    /// and the rest. Returns both trees together with their heights.
+    fn split_at_key(
+        self,
+        height: usize,
+        f: &impl Fn(&K) -> Ordering,
+    ) -> (Tree<K, B, A, L>, Tree<K, B, A, L>) {
+        let mut keys = self.keys;
+        let alloc = self.alloc;
+
+        if self.is_leaf {
+            let idx = keys.search_by(f).unwrap_or_else(|idx| idx);
+            let right = keys.split_off(idx);
+            return (
+                (Node::leaf(keys.into_keys(), alloc.clone()), 0),
+                (Node::leaf(right.into_keys(), alloc), 0),
+            );
+        }
+
+        let mut children = self.children;
+        match keys.search_by(f) {
+            Ok(idx) => {
+                // The key itself goes to the right tree, as its smallest key.
+                let mut right_keys = keys.split_off(idx);
+                let right_children = children.split_off(idx + 1);
+                let separator = right_keys.pop_front().unwrap();
+                let left = Node::fragment(keys, children, height, alloc.clone());
+                let (mut right, right_height) =
+                    Node::fragment(right_keys, right_children, height, alloc);
+                let grown = right.push_first_key(separator);
+                (left, (right, right_height + usize::from(grown)))
+            }
+            Err(idx) => {
+                let mut right_keys = keys.split_off(idx);
+                let right_children = children.split_off(idx + 1);
+                let child = *children.pop_back().unwrap();
+                let (child_left, child_right) = child.split_at_key(height - 1, f);
+
+                let left = match keys.pop_back() {
+                    Some(separator) => {
+                        let fragment = Node::fragment(keys, children, height, alloc.clone());
+                        Node::join(fragment, separator, child_left)
+                    }
+                    None => child_left,
+                };
+                let right = match right_keys.pop_front() {
+                    Some(separator) => {
+                        let fragment = Node::fragment(right_keys, right_children, height, alloc);
+                        Node::join(child_right, separator, fragment)
+                    }
+                    None => child_right,
+                };
+                (left, right)
+            }
+        }
+    }
+
+    /// Builds a tree out of the given keys and children cut from a node at the
+    /// given height. A fragment without keys collapses into its only child.
+    fn fragment(
+        keys: L::Keys<K, B>,
+        mut children: Array<Link<K, B, A, L>, B>,
+        height: usize,
+        alloc: A,
+    ) -> Tree<K, B, A, L> {
+        if keys.is_empty() {
+            (*children.pop_front().unwrap(), height - 1)
+        } else {
+            (
+                Node::intermediate(keys.into_keys(), children, alloc),
+                height,
+            )
+        }
+    }
+
+    /// Attaches a shorter tree to the right spine of the subtree rooted at
+    /// the node, which is at the given height. Returns the hoisted key and
+    /// the new sibling if the node had to be split.
+    fn join_right(
+        &mut self,
+        height: usize,
+        separator: K,
+        right: Node<K, B, A, L>,
+        right_height: usize,
+    ) -> Option<(K, Node<K, B, A, L>)> {
+        if height == right_height + 1 {
+            self.keys.push_back(separator);
+            self.children.push_back(right.link());
+            self.rebalance_children_at(self.keys.len() - 1);
+        } else {
+            let last = self.children.len() - 1;
+            let result = self.children[last].join_right(height - 1, separator, right, right_height);
+            if let Some((hoist, sibling)) = result {
+                self.keys.push_back(hoist);
+                self.children.push_back(sibling.link());
+            }
+        }
+
+        self.is_overflowed().then(|| self.split())
+    }
+
+    /// Attaches a shorter tree to the left spine of the subtree rooted at the
+    /// node, which is at the given height. Returns the hoisted key and the
+    /// new sibling if the node had to be split.
+    fn join_left(
+        &mut self,
+        height: usize,
+        left: Node<K, B, A, L>,
+        separator: K,
+        left_height: usize,
+    ) -> Option<(K, Node<K, B, A, L>)> {
+        if height == left_height + 1 {
+            self.keys.push_front(separator);
+            self.children.push_front(left.link());
+            self.rebalance_children_at(0);
+        } else {
+            let result = self.children[0].join_left(height - 1, left, separator, left_height);
+            if let Some((hoist, sibling)) = result {
+                self.keys.push_front(hoist);
+                self.children.insert(1, sibling.link());
+            }
+        }
+
+        self.is_overflowed().then(|| self.split())
+    }
+
+    /// Makes sure that both children around the key at the given index hold
+    /// the minimum number of keys. The children are merged if they fit into
+    /// a single node, otherwise keys are rotated from one to the other.
+    ///
+    /// This method assumes that the children hold at least `2B - 2` keys in
+    /// total, or that they fit into a single node.
+    fn rebalance_children_at(&mut self, idx: usize) {
+        let total = self.children[idx].keys.len() + self.children[idx + 1].keys.len();
+
+        if total < Self::MAX_KEYS {
+            self.merge_and_lower_intermediate_parent_key(idx);
+            return;
+        }
+
+        while self.children[idx].is_deficient() {
+            self.rotate_left(idx);
+        }
+        while self.children[idx + 1].is_deficient() {
+            self.rotate_right(idx);
+        }
+    }
+
+    /// Appends a key to the largest leaf of the subtree rooted at the node.
+    /// Returns whether the subtree grew by one level.
+    fn push_last_key(&mut self, key: K) -> bool {
+        let result = self.insert_along_edge(key, Edge::Last);
+        self.finish_edge_insert(result)
+    }
+
+    /// Prepends a key to the smallest leaf of the subtree rooted at the node.
+    /// Returns whether the subtree grew by one level.
+    fn push_first_key(&mut self, key: K) -> bool {
+        let result = self.insert_along_edge(key, Edge::First);
+        self.finish_edge_insert(result)
+    }
+
+    fn insert_along_edge(&mut self, key: K, edge: Edge) -> Option<(K, Node<K, B, A, L>)> {
+        let idx = match edge {
+            Edge::First => 0,
+            Edge::Last => self.keys.len(),
+        };
+
+        if self.is_leaf {
+            self.keys.insert(idx, key);
+        } else if let Some((hoist, sibling)) = self.children[idx].insert_along_edge(key, edge) {
+            self.keys.insert(idx, hoist);
+            self.children.insert(idx + 1, sibling.link());
+        }
+
+        self.is_overflowed().then(|| self.split())
+    }
+
+    /// Grows the subtree by one level if its root node was split.
+    fn finish_edge_insert(&mut self, result: Option<(K, Node<K, B, A, L>)>) -> bool {
+        let Some((hoist, sibling)) = result else {
+            return false;
+        };
+
+        let alloc = self.alloc.clone();
+        let old_node = std::mem::replace(self, Node::leaf([], alloc.clone()));
+        *self = Node::intermediate([hoist], [old_node.link(), sibling.link()], alloc);
+        true
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Edge {
+    First,
+    Last,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::tree_with;
+    use super::*;
+    use std::collections::BTreeSet as StdBTreeSet;
+
+    /// Removes every key one by one, checking the invariants along the way.
+    fn assert_drains_cleanly(
+        mut tree: SimpleBTreeSet<usize, 2>,
+        keys: impl Iterator<Item = usize>,
+    ) {
+        tree.check_invariants().unwrap();
+        for (i, key) in keys.enumerate() {
+            assert_eq!(tree.remove(&key).unwrap(), key);
+            assert!(!tree.contains(&key));
+            if i % 64 == 0 {
+                tree.check_invariants().unwrap();
+            }
+        }
+        assert_eq!(tree.iter().next(), None);
+    }
+
+    #[test]
+    fn test_append_greater_keys_of_various_sizes() {
+        for n in [0, 1, 2, 3, 10, 100, 1000] {
+            for m in [0, 1, 2, 3, 10, 100, 1000] {
+                let mut left = tree_with(0..n);
+                left.append(tree_with(n..n + m));
+
+                assert!(left.iter().copied().eq(0..n + m));
+                assert_drains_cleanly(left, (0..n + m).rev());
+            }
+        }
+    }
+
+    #[test]
+    fn test_append_smaller_keys_of_various_sizes() {
+        for n in [0, 1, 2, 3, 10, 100, 1000] {
+            for m in [0, 1, 2, 3, 10, 100, 1000] {
+                let mut right = tree_with(m..m + n);
+                right.append(tree_with(0..m));
+
+                assert!(right.iter().copied().eq(0..n + m));
+                assert_drains_cleanly(right, 0..n + m);
+            }
+        }
+    }
+
+    #[test]
+    fn test_append_after_removals_keeps_tree_usable() {
+        let mut left = tree_with(0..500);
+        let mut right = tree_with(500..600);
+        for key in (0..500).step_by(3) {
+            left.remove(&key).unwrap();
+        }
+        for key in 500..590 {
+            right.remove(&key).unwrap();
+        }
+
+        left.append(right);
+        left.insert(0).unwrap();
+        left.insert(595).unwrap_err();
+
+        let expected: Vec<_> = (0..500)
+            .filter(|k| k % 3 != 0 || *k == 0)
+            .chain(590..600)
+            .collect();
+        assert!(left.iter().eq(expected.iter()));
+        assert_drains_cleanly(left, expected.into_iter());
+    }
+
+    #[test]
+    fn test_split_off_at_every_position() {
+        for n in [0, 1, 2, 3, 10, 100, 500] {
+            for at in 0..=n + 1 {
+                let mut left = tree_with((0..n).map(|i| i * 2));
+                let right = left.split_off(&at);
+
+                assert!(
+                    left.iter()
+                        .copied()
+                        .eq((0..n).map(|i| i * 2).filter(|k| *k < at))
+                );
+                assert!(
+                    right
+                        .iter()
+                        .copied()
+                        .eq((0..n).map(|i| i * 2).filter(|k| *k >= at))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_off_leaves_both_trees_usable() {
+        for at in [0, 1, 250, 499, 500, 777, 999, 1000] {
+            let mut left = tree_with(0..1000);
+            let mut right = left.split_off(&at);
+
+            left.insert(at + 5000).unwrap();
+            right.insert(at + 5000).unwrap();
+            assert_drains_cleanly(left, (0..at).chain([at + 5000]));
+            assert_drains_cleanly(right, (at..1000).rev().chain([at + 5000]));
+        }
+    }
+
+    #[test]
+    fn test_split_off_then_append_restores_keys() {
+        let mut tree = tree_with((0..2000).map(|i| (i * 7) % 2000));
+        let upper = tree.split_off(&1234);
+        tree.append(upper);
+
+        assert!(tree.iter().copied().eq(0..2000));
+        assert_drains_cleanly(tree, 0..2000);
+    }
+
+    #[test]
+    fn test_append_overlapping_keys_inserts_missing_keys() {
+        let mut left = tree_with((0..100).map(|i| i * 2));
+        left.append(tree_with(50..150));
+
+        let expected: StdBTreeSet<_> = (0..100).map(|i| i * 2).chain(50..150).collect();
+        assert!(left.iter().eq(expected.iter()));
+    }
+
+    #[test]
+    fn test_retain_keeps_matching_keys() {
+        for n in [0, 1, 2, 3, 10, 100, 1000] {
+            for modulus in [1, 2, 3, 7, n + 1] {
+                let mut tree = tree_with((0..n).map(|i| (i * 7919) % n.max(1)));
+                let mut visited = 0;
+                tree.retain(|key| {
+                    visited += 1;
+                    key % modulus == 0
+                });
+
+                assert_eq!(visited, n);
+                let expected: Vec<_> = (0..n).filter(|key| key % modulus == 0).collect();
+                assert!(tree.iter().eq(expected.iter()));
+                assert_drains_cleanly(tree, expected.into_iter().rev());
+            }
+        }
+    }
+
+    #[test]
+    fn test_retain_nothing_leaves_tree_usable() {
+        let mut tree = tree_with(0..100);
+        tree.retain(|_| false);
+        assert_eq!(tree.iter().next(), None);
+
+        tree.insert(42).unwrap();
+        assert!(tree.iter().eq([42].iter()));
+    }
+
+    #[test]
+    fn test_remove_range_with_every_kind_of_bound() {
+        let n = 300;
+        let expect = |range: (Bound<usize>, Bound<usize>)| -> Vec<usize> {
+            (0..n)
+                .map(|i| i * 2)
+                .filter(|k| !range.contains(k))
+                .collect()
+        };
+
+        for start in [0, 1, 2, 101, 300, 599, 600, 700] {
+            for end in [0, 1, 2, 150, 301, 598, 599, 700] {
+                let bounds = [
+                    (Bound::Included(start), Bound::Included(end)),
+                    (Bound::Included(start), Bound::Excluded(end)),
+                    (Bound::Excluded(start), Bound::Included(end)),
+                    (Bound::Excluded(start), Bound::Excluded(end)),
+                    (Bound::Unbounded, Bound::Excluded(end)),
+                    (Bound::Included(start), Bound::Unbounded),
+                ];
+                for range in bounds {
+                    let mut tree = tree_with((0..n).map(|i| i * 2));
+                    tree.remove_range(range);
+
+                    let expected = expect(range);
+                    assert!(tree.iter().eq(expected.iter()), "{range:?}");
+                    tree.check_invariants().unwrap();
+                    assert_drains_cleanly(tree, expected.into_iter());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_remove_full_range_empties_tree() {
+        let mut tree = tree_with(0..1000);
+        tree.remove_range(..);
+        assert_eq!(tree.iter().next(), None);
+
+        tree.insert(42).unwrap();
+        assert!(tree.iter().eq([42].iter()));
+    }
+}
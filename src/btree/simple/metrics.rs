@@ -0,0 +1,183 @@
+//! Counters of the work done by the searches, insertions and removals of a
+//! `SimpleBTreeSet`, kept when the `metrics` feature is enabled.
+//!
+//! The nodes do not know which tree they belong to, so they count what they
+//! do on the current thread, and the tree collects the counts into its own
+//! metrics once the operation is over. Without the feature, counting compiles
+//! to nothing.
+
+#[cfg(feature = "metrics")]
+use std::cell::Cell;
+#[cfg(feature = "metrics")]
+use std::sync::Mutex;
+
+/// Counters of the work done by the operations on a `SimpleBTreeSet`, as
+/// returned by `SimpleBTreeSet::metrics`.
+#[cfg(feature = "metrics")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// The number of descents from the root which compared keys. Every
+    /// search makes one, and so does every insertion or removal by key.
+    pub descents: u64,
+    /// The number of nodes visited by the descents.
+    pub nodes_visited: u64,
+    /// The largest number of nodes visited by a single descent.
+    pub max_depth: u64,
+    /// The number of keys compared by the descents.
+    pub comparisons: u64,
+    /// The number of nodes split by insertions.
+    pub splits: u64,
+    /// The number of nodes merged into a sibling by removals.
+    pub merges: u64,
+    /// The number of keys moved between siblings by removals.
+    pub rotations: u64,
+}
+
+#[cfg(feature = "metrics")]
+impl Metrics {
+    /// Returns the average number of nodes visited by a descent, or zero if
+    /// there were none.
+    pub fn average_depth(&self) -> f64 {
+        match self.descents {
+            0 => 0.0,
+            descents => self.nodes_visited as f64 / descents as f64,
+        }
+    }
+
+    /// Adds the counts of a single operation.
+    fn add(&mut self, op: Metrics) {
+        if op.nodes_visited > 0 {
+            self.descents += 1;
+            self.max_depth = self.max_depth.max(op.nodes_visited);
+        }
+        self.nodes_visited += op.nodes_visited;
+        self.comparisons += op.comparisons;
+        self.splits += op.splits;
+        self.merges += op.merges;
+        self.rotations += op.rotations;
+    }
+}
+
+pub(super) enum Event {
+    Visit,
+    Comparison,
+    Split,
+    Merge,
+    Rotation,
+}
+
+#[cfg(feature = "metrics")]
+thread_local! {
+    /// The counts of the operation in progress on this thread.
+    static PENDING: Cell<Metrics> = const {
+        Cell::new(Metrics {
+            descents: 0,
+            nodes_visited: 0,
+            max_depth: 0,
+            comparisons: 0,
+            splits: 0,
+            merges: 0,
+            rotations: 0,
+        })
+    };
+}
+
+/// Counts an event of the operation in progress on this thread.
+#[inline(always)]
+pub(super) fn count(event: Event) {
+    #[cfg(feature = "metrics")]
+    PENDING.with(|pending| {
+        let mut counts = pending.get();
+        match event {
+            Event::Visit => counts.nodes_visited += 1,
+            Event::Comparison => counts.comparisons += 1,
+            Event::Split => counts.splits += 1,
+            Event::Merge => counts.merges += 1,
+            Event::Rotation => counts.rotations += 1,
+        }
+        pending.set(counts);
+    });
+    #[cfg(not(feature = "metrics"))]
+    let _ = event;
+}
+
+/// The metrics of a tree. The counters sit behind a lock, so searches, which
+/// only borrow the tree, can update them without making it `!Sync`.
+#[derive(Default)]
+pub(super) struct Recorder {
+    #[cfg(feature = "metrics")]
+    totals: Mutex<Metrics>,
+}
+
+impl Recorder {
+    /// Runs an operation on the tree, adding the events counted by its nodes
+    /// to the metrics of the tree.
+    #[inline(always)]
+    pub(super) fn record<T>(&self, op: impl FnOnce() -> T) -> T {
+        #[cfg(feature = "metrics")]
+        {
+            let outer = PENDING.take();
+            let result = op();
+            let counts = PENDING.replace(outer);
+            self.totals.lock().unwrap().add(counts);
+            result
+        }
+        #[cfg(not(feature = "metrics"))]
+        op()
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(super) fn get(&self) -> Metrics {
+        *self.totals.lock().unwrap()
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(super) fn reset(&self) {
+        *self.totals.lock().unwrap() = Metrics::default();
+    }
+}
+
+impl Clone for Recorder {
+    fn clone(&self) -> Self {
+        Recorder {
+            #[cfg(feature = "metrics")]
+            totals: Mutex::new(self.get()),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::super::SimpleBTreeSet;
+    use crate::BTreeSet;
+
+    #[test]
+    fn test_metrics_count_the_work_of_each_operation() {
+        let mut tree = SimpleBTreeSet::<u32, 2>::new();
+        for key in 0..4 {
+            tree.insert(key).unwrap();
+        }
+        // The fourth key overflowed the root leaf, which was split in two.
+        let metrics = tree.metrics();
+        assert_eq!(metrics.splits, 1);
+        assert_eq!(metrics.descents, 3);
+        assert_eq!(metrics.max_depth, 1);
+
+        tree.reset_metrics();
+        assert_eq!(tree.search(&3).unwrap(), &3);
+        let metrics = tree.metrics();
+        assert_eq!(metrics.descents, 1);
+        assert_eq!(metrics.nodes_visited, 2);
+        assert!(metrics.comparisons >= 2);
+
+        // Removing from the leaf [0] takes the key 1 out of the parent, and
+        // moves the key 2 up from the right leaf [2, 3].
+        tree.reset_metrics();
+        tree.remove(&0).unwrap();
+        let metrics = tree.metrics();
+        assert_eq!((metrics.rotations, metrics.merges), (1, 0));
+        tree.remove(&1).unwrap();
+        assert_eq!(tree.metrics().merges, 1);
+        assert_eq!(tree.metrics().average_depth(), 2.0);
+    }
+}
@@ -0,0 +1,1728 @@
+use super::alloc::{Allocator, Global, boxed_in, try_boxed_in};
+use super::array::Array;
+use super::compare::{Comparator, OrdComparator};
+use super::layout::{Layout, NodeStorage, SortedLayout};
+use crate::{BTreeSet, Error, Result};
+use metrics::{Event, Recorder};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+
+mod canonical;
+mod cmp;
+mod cursor;
+mod debug;
+#[cfg(feature = "visualize")]
+mod dot;
+mod invariants;
+mod iter;
+mod join;
+mod metrics;
+#[cfg(feature = "rayon")]
+mod par;
+mod stats;
+
+pub use cursor::{Cursor, CursorMut, Position};
+pub use iter::{
+    Difference, ExtractIf, Intersection, IntoIter, Iter, KWayMerge, SymmetricDifference, Union,
+};
+#[cfg(feature = "metrics")]
+pub use metrics::Metrics;
+#[cfg(feature = "rayon")]
+pub use par::ParIter;
+pub use stats::TreeStats;
+
+/// A simple in-memory B-tree implementation. The tree does not consider any
+/// "clever" optimizations. The implementation is intended for learning
+/// purposes.
+///
+/// The K type parameter represents the key type, B is the branching factor,
+/// A is the allocator the nodes are placed in, C is the comparator which
+/// orders the keys, and L is the layout of the keys within a node.
+///
+/// The root is wrapped in an `Option`, which allows the tree to avoid any
+/// allocations.
+pub struct SimpleBTreeSet<
+    K,
+    const B: usize = 6,
+    A: Allocator = Global,
+    C = OrdComparator,
+    L: Layout = SortedLayout,
+> {
+    root: Option<Root<K, B, A, L>>,
+    alloc: A,
+    cmp: C,
+    spare: Spare<K, B, A, L>,
+    /// Identifies the tree, so a saved cursor position is only accepted by
+    /// the tree it was saved from.
+    id: u64,
+    /// Counts the structural modifications of the tree, so a saved cursor
+    /// position can tell whether it still points where it did.
+    generation: u64,
+    metrics: Recorder,
+}
+
+/// Returns an identity no other tree has been given.
+fn next_tree_id() -> u64 {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    NEXT_ID.fetch_add(1, AtomicOrdering::Relaxed)
+}
+
+/// Represents the root of the B-tree. It contains a single node, which is
+/// either a leaf or an intermediate node.
+///
+/// The root node has no restrictions on the number of keys it can hold, in
+/// fact, it could hold no keys at all!
+struct Root<K, const B: usize, A: Allocator, L: Layout> {
+    node: Node<K, B, A, L>,
+}
+
+impl<K, const B: usize, A: Allocator + Clone, L: Layout> Root<K, B, A, L> {
+    fn insert(
+        &mut self,
+        key: K,
+        cmp: &impl Comparator<K>,
+        spare: &mut Spare<K, B, A, L>,
+    ) -> Result<()> {
+        let result = self.node.insert(key, cmp, spare);
+        self.grow(result, spare).map(|_| ())
+    }
+
+    fn search_by(&self, f: impl Fn(&K) -> Ordering) -> Result<&K> {
+        let f = counting_comparisons(f);
+        let mut node = &self.node;
+        loop {
+            metrics::count(Event::Visit);
+            match node.search_by(&f) {
+                SearchResult::None => return Err(Error::KeyNotFound),
+                SearchResult::Key(key) => return Ok(key),
+                SearchResult::Child(child) => {
+                    node = child;
+                }
+            }
+        }
+    }
+
+    /// Descends the tree, recording the path taken. On success, the path
+    /// leads to the matching key. On failure, it leads to the position in a
+    /// leaf where a matching key would be inserted.
+    fn search_path_by(&self, f: impl Fn(&K) -> Ordering) -> std::result::Result<Path, Path> {
+        let f = counting_comparisons(f);
+        let mut path = Path::new();
+        let mut node = &self.node;
+        loop {
+            metrics::count(Event::Visit);
+            match node.keys.search_by(&f) {
+                Ok(idx) => {
+                    path.push_back(idx);
+                    return Ok(path);
+                }
+                Err(idx) => {
+                    path.push_back(idx);
+                    if node.is_leaf {
+                        return Err(path);
+                    }
+                    node = &node.children[idx];
+                }
+            }
+        }
+    }
+
+    /// Inserts a key at the leaf position the given path leads to, without
+    /// comparing any keys. Returns the path to the inserted key.
+    ///
+    /// This method assumes that the path was obtained from a failed
+    /// `search_path_by`, and the tree was not modified since.
+    fn insert_along(&mut self, path: &Path, key: K, spare: &mut Spare<K, B, A, L>) -> Path {
+        let result = self.node.insert_along(path, 0, key, spare);
+        self.grow(result, spare).unwrap()
+    }
+
+    /// Returns the number of node allocations an insertion at the leaf
+    /// position the given path leads to would make: one for every full node
+    /// on the path which splits, and one more for the old root if the root
+    /// splits as well.
+    ///
+    /// This method assumes that the path was obtained from a failed
+    /// `search_path_by`, and the tree was not modified since.
+    fn links_needed_along(&self, path: &Path) -> usize {
+        let mut node = &self.node;
+        let mut needed = usize::from(node.keys.len() == Node::<K, B, A, L>::MAX_KEYS) * 2;
+        for &idx in path.range(..path.len() - 1) {
+            node = &node.children[idx];
+            needed = if node.keys.len() == Node::<K, B, A, L>::MAX_KEYS {
+                needed + 1
+            } else {
+                0
+            };
+        }
+        needed
+    }
+
+    /// Descends to the leaf the first of the writes targets, and returns the
+    /// indices of the children leading to it, along with the number of
+    /// leading writes which fall strictly between the separators around the
+    /// leaf.
+    ///
+    /// If the first write targets a separator instead, the indices lead to
+    /// the node holding it, and the run holds only the write, or nothing if
+    /// the write is a removal.
+    fn leaf_run<Q>(
+        &self,
+        writes: &[Write<K, Q>],
+        cmp: &impl Comparator<K>,
+        f: &impl Fn(&K, &Q) -> Ordering,
+    ) -> (Vec<usize>, usize) {
+        let first = &writes[0];
+        let mut path = Vec::new();
+        let (mut lower, mut upper) = (None, None);
+        let mut node = &self.node;
+        loop {
+            metrics::count(Event::Visit);
+            let found = node
+                .keys
+                .search_by(counting_comparisons(|k| first.compare(k, cmp, f)));
+            if node.is_leaf {
+                break;
+            }
+            let Err(idx) = found else {
+                return (path, usize::from(matches!(first, Write::Upsert(_))));
+            };
+            lower = idx.checked_sub(1).and_then(|i| node.keys.get(i)).or(lower);
+            upper = node.keys.get(idx).or(upper);
+            path.push(idx);
+            node = &node.children[idx];
+        }
+
+        let run = writes.iter().take_while(|write| {
+            let compare = counting_comparisons(|k| write.compare(k, cmp, f));
+            lower.is_none_or(|k| compare(k).is_lt()) && upper.is_none_or(|k| compare(k).is_gt())
+        });
+        (path, run.count())
+    }
+
+    /// Applies the leading writes of a run to the node the given indices lead
+    /// to, for as long as it neither overflows nor underflows, and returns
+    /// the number of writes applied. Only a leaf takes insertions and
+    /// removals, an intermediate node only has its separator replaced.
+    fn apply_to_leaf<Q>(
+        &mut self,
+        path: &[usize],
+        writes: &mut std::vec::IntoIter<Write<K, Q>>,
+        run: usize,
+        cmp: &impl Comparator<K>,
+        f: &impl Fn(&K, &Q) -> Ordering,
+    ) -> usize {
+        let mut leaf = &mut self.node;
+        for &idx in path {
+            leaf = &mut leaf.children[idx];
+        }
+
+        let is_root = path.is_empty();
+        for applied in 0..run {
+            let write = &writes.as_slice()[0];
+            let found = leaf
+                .keys
+                .search_by(counting_comparisons(|k| write.compare(k, cmp, f)));
+            let fits = match (write, found) {
+                (Write::Upsert(_), Err(_)) => leaf.keys.len() < Node::<K, B, A, L>::MAX_KEYS,
+                (Write::Remove(_), Ok(_)) => leaf.is_leaf && (is_root || leaf.can_spare_key()),
+                _ => true,
+            };
+            if !fits {
+                return applied;
+            }
+
+            match (writes.next().unwrap(), found) {
+                (Write::Upsert(key), Ok(idx)) => leaf.keys[idx] = key,
+                (Write::Upsert(key), Err(idx)) => leaf.keys.insert(idx, key),
+                (Write::Remove(_), Ok(idx)) => drop(leaf.keys.remove(idx)),
+                (Write::Remove(_), Err(_)) => {}
+            }
+        }
+        run
+    }
+
+    /// Finishes an insertion at the root, growing the tree by one level if the
+    /// root node was split.
+    fn grow(
+        &mut self,
+        result: InsertResult<K, B, A, L>,
+        spare: &mut Spare<K, B, A, L>,
+    ) -> Result<Path> {
+        match result {
+            InsertResult::AlreadyExists => Err(Error::KeyAlreadyExists),
+            InsertResult::Inserted(path) => Ok(path),
+            InsertResult::Split(hoist, sibling, placement) => {
+                // If the root node is split, we create a new root node.
+                debug_event!("root grew");
+                let alloc = self.node.alloc.clone();
+                let old_node = std::mem::replace(&mut self.node, Node::leaf([], alloc.clone()));
+                let children = [old_node.link_from(spare), sibling.link_from(spare)];
+                self.node = Node::intermediate([hoist], children, alloc);
+                Ok(placement.into_path(0))
+            }
+        }
+    }
+
+    fn get(&self, path: &Path) -> &K {
+        let mut node = &self.node;
+        for &idx in path.range(..path.len() - 1) {
+            node = &node.children[idx];
+        }
+        &node.keys[path[path.len() - 1]]
+    }
+
+    fn get_mut(&mut self, path: &Path) -> &mut K {
+        let mut node = &mut self.node;
+        for &idx in path.range(..path.len() - 1) {
+            node = &mut node.children[idx];
+        }
+        &mut node.keys[path[path.len() - 1]]
+    }
+
+    fn remove_by(&mut self, f: impl Fn(&K) -> Ordering) -> Result<K> {
+        self.remove_located(&f)
+    }
+
+    /// Removes the key the given path leads to, without comparing any keys.
+    ///
+    /// This method assumes that the path points to an existing key.
+    fn remove_along(&mut self, path: &Path) -> K {
+        self.remove_located(path).unwrap()
+    }
+
+    fn remove_located(&mut self, target: &impl Locate<K>) -> Result<K> {
+        match self.node.remove(target, 0) {
+            RemoveResult::None => Err(Error::KeyNotFound),
+            RemoveResult::Key(key) => Ok(key),
+            RemoveResult::Deficiency(key) => {
+                // If the root node has no remaining keys left, and it's an
+                // intermediate node, this means that the node was merged, and
+                // the parent key was lowered. We can safely presume that there
+                // *is* a single child left, which is the new root.
+                if self.node.has_no_remaining_keys() && !self.node.is_leaf {
+                    debug_event!("root shrank");
+                    self.node = *self.node.children.pop_front().unwrap();
+                }
+
+                Ok(key)
+            }
+        }
+    }
+}
+
+/// A link to a node in the B-tree. This is used to avoid recursive types.
+#[cfg(feature = "allocator_api")]
+type Link<K, const B: usize, A, L = SortedLayout> = Box<Node<K, B, A, L>, A>;
+
+/// A link to a node in the B-tree. This is used to avoid recursive types.
+#[cfg(not(feature = "allocator_api"))]
+type Link<K, const B: usize, A, L = SortedLayout> = Box<Node<K, B, A, L>>;
+
+/// Node allocations set aside by `SimpleBTreeSet::try_reserve`, which splits
+/// place their new nodes in before allocating any.
+type Spare<K, const B: usize, A, L> = Vec<Link<K, B, A, L>>;
+
+/// Represents a node in the B-tree. It can be either a leaf or an intermediate.
+///
+/// Intermediate nodes contain keys and links to child nodes while leaf nodes
+/// contain only keys, and absolutely no children. Both are stored inline, so
+/// the only allocation of a node is its own, placed in the allocator the node
+/// carries around for its future siblings.
+struct Node<K, const B: usize, A: Allocator, L: Layout = SortedLayout> {
+    is_leaf: bool,
+    keys: L::Keys<K, B>,
+    children: Array<Link<K, B, A, L>, B>,
+    alloc: A,
+}
+
+/// The capacity rules of the nodes, all derived from the branching factor.
+/// Every node except the root holds between `MIN_KEYS` and `MAX_KEYS` keys,
+/// and an intermediate node has one more child than keys.
+impl<K, const B: usize, A: Allocator, L: Layout> Node<K, B, A, L> {
+    const MIN_KEYS: usize = B - 1;
+    const MAX_KEYS: usize = 2 * B - 1;
+    const MIN_CHILDREN: usize = Self::MIN_KEYS + 1;
+    const MAX_CHILDREN: usize = Self::MAX_KEYS + 1;
+
+    fn has_no_remaining_keys(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    fn is_deficient(&self) -> bool {
+        self.keys.len() < Self::MIN_KEYS
+    }
+
+    fn is_overflowed(&self) -> bool {
+        self.keys.len() > Self::MAX_KEYS
+    }
+
+    fn can_spare_key(&self) -> bool {
+        self.keys.len() > Self::MIN_KEYS
+    }
+}
+
+impl<K, const B: usize, A: Allocator + Clone, L: Layout> Node<K, B, A, L> {
+    fn intermediate(
+        keys_iter: impl IntoIterator<Item = K>,
+        children_iter: impl IntoIterator<Item = Link<K, B, A, L>>,
+        alloc: A,
+    ) -> Node<K, B, A, L> {
+        let keys = NodeStorage::from_keys(keys_iter.into_iter().take(Self::MAX_KEYS));
+
+        let mut children = Array::new();
+        let limited_children = children_iter.into_iter().take(Self::MAX_CHILDREN);
+
+        children.extend(limited_children);
+
+        Self {
+            keys,
+            children,
+            is_leaf: false,
+            alloc,
+        }
+    }
+
+    fn leaf(keys_iter: impl IntoIterator<Item = K>, alloc: A) -> Node<K, B, A, L> {
+        let keys = NodeStorage::from_keys(keys_iter.into_iter().take(Self::MAX_KEYS));
+
+        Self {
+            keys,
+            children: Array::new(),
+            is_leaf: true,
+            alloc,
+        }
+    }
+
+    fn link(self) -> Link<K, B, A, L> {
+        let alloc = self.alloc.clone();
+        boxed_in(self, alloc)
+    }
+
+    /// Links the node, placing it in a spare allocation if there is one.
+    fn link_from(self, spare: &mut Spare<K, B, A, L>) -> Link<K, B, A, L> {
+        match spare.pop() {
+            Some(mut link) => {
+                *link = self;
+                link
+            }
+            None => self.link(),
+        }
+    }
+}
+
+impl<K, const B: usize, A: Allocator + Clone, L: Layout> Node<K, B, A, L> {
+    fn search_by(&self, f: &impl Fn(&K) -> Ordering) -> SearchResult<'_, K, B, A, L> {
+        match self.keys.search_by(f) {
+            Ok(idx) => SearchResult::Key(&self.keys[idx]),
+            Err(idx) => {
+                if self.is_leaf {
+                    SearchResult::None
+                } else {
+                    SearchResult::Child(&self.children[idx])
+                }
+            }
+        }
+    }
+
+    fn insert(
+        &mut self,
+        key: K,
+        cmp: &impl Comparator<K>,
+        spare: &mut Spare<K, B, A, L>,
+    ) -> InsertResult<K, B, A, L> {
+        metrics::count(Event::Visit);
+        let Err(idx) = self
+            .keys
+            .search_by(counting_comparisons(|k| cmp.compare(k, &key)))
+        else {
+            return InsertResult::AlreadyExists;
+        };
+
+        if self.is_leaf {
+            self.insert_into_leaf_at(idx, key)
+        } else {
+            let result = self.children[idx].insert(key, cmp, spare);
+            self.absorb_child_insert(idx, result, spare)
+        }
+    }
+
+    /// Inserts a key by following the given path from the given depth,
+    /// instead of searching for the position.
+    fn insert_along(
+        &mut self,
+        path: &Path,
+        depth: usize,
+        key: K,
+        spare: &mut Spare<K, B, A, L>,
+    ) -> InsertResult<K, B, A, L> {
+        let idx = path[depth];
+
+        if self.is_leaf {
+            self.insert_into_leaf_at(idx, key)
+        } else {
+            let result = self.children[idx].insert_along(path, depth + 1, key, spare);
+            self.absorb_child_insert(idx, result, spare)
+        }
+    }
+
+    fn insert_into_leaf_at(&mut self, idx: usize, key: K) -> InsertResult<K, B, A, L> {
+        self.keys.insert(idx, key);
+        let path = Path::from([idx]);
+
+        // If the leaf node has overflowed, we split it.
+        if self.is_overflowed() {
+            let (hoist, sibling) = self.split();
+            InsertResult::Split(hoist, sibling, Placement::after_split(path, Self::MIN_KEYS))
+        } else {
+            InsertResult::Inserted(path)
+        }
+    }
+
+    /// Handles the result of an insertion into the child at the given index.
+    fn absorb_child_insert(
+        &mut self,
+        idx: usize,
+        result: InsertResult<K, B, A, L>,
+        spare: &mut Spare<K, B, A, L>,
+    ) -> InsertResult<K, B, A, L> {
+        match result {
+            InsertResult::Split(hoist, sibling, placement) => {
+                // We insert the hoisted key and the new sibling into the current node.
+                self.keys.insert(idx, hoist);
+                self.children.insert(idx + 1, sibling.link_from(spare));
+                let path = placement.into_path(idx);
+
+                // If the current node has overflowed, we split it too.
+                if self.is_overflowed() {
+                    let (hoist, sibling) = self.split();
+                    InsertResult::Split(
+                        hoist,
+                        sibling,
+                        Placement::after_split(path, Self::MIN_KEYS),
+                    )
+                } else {
+                    InsertResult::Inserted(path)
+                }
+            }
+            InsertResult::Inserted(mut path) => {
+                path.push_front(idx);
+                InsertResult::Inserted(path)
+            }
+            x => x,
+        }
+    }
+
+    fn remove(&mut self, target: &impl Locate<K>, depth: usize) -> RemoveResult<K> {
+        let result = target.locate(&self.keys, depth);
+
+        let key = if self.is_leaf {
+            match result {
+                Ok(idx) => self.remove_from_leaf_at(idx),
+                Err(_) => return RemoveResult::None,
+            }
+        } else {
+            match result {
+                Ok(idx) => self.remove_from_intermediate_at(idx),
+                Err(idx) => match self.children[idx].remove(target, depth + 1) {
+                    RemoveResult::Deficiency(key) => {
+                        self.fix_deficient_child(idx);
+                        key
+                    }
+                    result => return result,
+                },
+            }
+        };
+
+        if self.is_deficient() {
+            RemoveResult::Deficiency(key)
+        } else {
+            RemoveResult::Key(key)
+        }
+    }
+}
+
+impl<K, const B: usize, A: Allocator + Clone, L: Layout> Node<K, B, A, L> {
+    /// Splits the node into two nodes, returning the hoisted key and the new sibling node.
+    /// The node keeps the first `MIN_KEYS` keys, and the key right after them
+    /// is hoisted.
+    ///
+    /// This method assumes that the node contains more than `MAX_KEYS` keys.
+    fn split(&mut self) -> (K, Node<K, B, A, L>) {
+        metrics::count(Event::Split);
+        debug_event!(leaf = self.is_leaf, "node split");
+        let keys = self.keys.split_off(Self::MIN_KEYS + 1);
+        let hoist = self.keys.pop_back().unwrap();
+        let children = if self.is_leaf {
+            Array::new()
+        } else {
+            self.children.split_off(Self::MIN_CHILDREN)
+        };
+        let sibling = Node {
+            keys,
+            children,
+            is_leaf: self.is_leaf,
+            alloc: self.alloc.clone(),
+        };
+        (hoist, sibling)
+    }
+
+    /// Merges the right child into the left child and lowers the parent key.
+    ///
+    /// This method assumes that:
+    ///    1. The given index points to a valid key.
+    ///    2. The left and right children contains at most `2B - 2` keys in total.
+    fn merge_and_lower_intermediate_parent_key(&mut self, idx: usize) {
+        metrics::count(Event::Merge);
+        debug_event!(leaf = self.children[idx].is_leaf, "nodes merged");
+        let right_child = self.children.remove(idx + 1).unwrap();
+        let parent_key = self.keys.remove(idx).unwrap();
+        let left = &mut self.children[idx];
+        left.keys.push_back(parent_key);
+        left.keys.append(right_child.keys);
+        left.children.extend(right_child.children);
+    }
+
+    /// Performs a left rotation on the key at the given index.
+    ///
+    /// This method assumes that:
+    ///     1. The index points to a valid key.
+    ///     2. The right child can spare a key.
+    ///     3. The left child contains less keys than the maximum number allowed.
+    fn rotate_left(&mut self, idx: usize) {
+        metrics::count(Event::Rotation);
+        if self.children[idx].is_leaf {
+            let right = &mut self.children[idx + 1];
+            let right_key = right.keys.pop_front().unwrap();
+            let parent_key = std::mem::replace(&mut self.keys[idx], right_key);
+            let left = &mut self.children[idx];
+            left.keys.push_back(parent_key);
+        } else {
+            let right = &mut self.children[idx + 1];
+            let right_key = right.keys.pop_front().unwrap();
+            let right_child = right.children.pop_front().unwrap();
+            let parent_key = std::mem::replace(&mut self.keys[idx], right_key);
+            let left = &mut self.children[idx];
+            left.keys.push_back(parent_key);
+            left.children.push_back(right_child);
+        }
+    }
+
+    /// Performs a right rotation on the key at the given index.
+    ///
+    /// This method assumes that:
+    ///     1. The index points to a valid key.
+    ///     2. The left child can spare a key.
+    ///     3. The right child contains less keys than the maximum number allowed.
+    fn rotate_right(&mut self, idx: usize) {
+        metrics::count(Event::Rotation);
+        if self.children[idx + 1].is_leaf {
+            let left = &mut self.children[idx];
+            let left_key = left.keys.pop_back().unwrap();
+            let parent_key = std::mem::replace(&mut self.keys[idx], left_key);
+            let right = &mut self.children[idx + 1];
+            right.keys.push_front(parent_key);
+        } else {
+            let left = &mut self.children[idx];
+            let left_key = left.keys.pop_back().unwrap();
+            let left_child = left.children.pop_back().unwrap();
+            let parent_key = std::mem::replace(&mut self.keys[idx], left_key);
+            let right = &mut self.children[idx + 1];
+            right.keys.push_front(parent_key);
+            right.children.push_front(left_child);
+        }
+    }
+
+    /// Removes the largest key of the subtree rooted at the node.
+    fn remove_last(&mut self) -> RemoveResult<K> {
+        let key = if self.is_leaf {
+            self.keys.pop_back().unwrap()
+        } else {
+            let idx = self.keys.len();
+            match self.children[idx].remove_last() {
+                RemoveResult::Deficiency(key) => {
+                    self.fix_deficient_child(idx);
+                    key
+                }
+                result => return result,
+            }
+        };
+
+        if self.is_deficient() {
+            RemoveResult::Deficiency(key)
+        } else {
+            RemoveResult::Key(key)
+        }
+    }
+
+    /// Removes a key from a leaf node at the given index.
+    ///
+    /// This method assumes that:
+    ///      1 - The current node is a leaf node.
+    ///      2 - The given index points to an existing key.
+    fn remove_from_leaf_at(&mut self, idx: usize) -> K {
+        self.keys.remove(idx).unwrap()
+    }
+
+    /// Removes a key from an intermediate node at the given index, replacing it
+    /// with its predecessor, which is the largest key of the left child.
+    ///
+    /// This method assumes that:
+    ///      1 - The current node is an intermediate node.
+    ///      2 - The given index points to an existing key.
+    fn remove_from_intermediate_at(&mut self, idx: usize) -> K {
+        let predecessor = match self.children[idx].remove_last() {
+            RemoveResult::Deficiency(predecessor) => {
+                let key = std::mem::replace(&mut self.keys[idx], predecessor);
+                self.fix_deficient_child(idx);
+                return key;
+            }
+            RemoveResult::Key(predecessor) => predecessor,
+            RemoveResult::None => unreachable!("children are never empty"),
+        };
+
+        std::mem::replace(&mut self.keys[idx], predecessor)
+    }
+
+    /// Restores the minimum number of keys of the child at the given index,
+    /// which is short of exactly one key, by borrowing a key from a sibling
+    /// through a rotation. If neither sibling can spare a key, the child is
+    /// merged with one of them.
+    ///
+    /// This method assumes that:
+    ///      1 - The current node is an intermediate node.
+    ///      2 - The given index points to an existing child.
+    fn fix_deficient_child(&mut self, idx: usize) {
+        if idx > 0 && self.children[idx - 1].can_spare_key() {
+            self.rotate_right(idx - 1);
+        } else if idx < self.keys.len() && self.children[idx + 1].can_spare_key() {
+            self.rotate_left(idx);
+        } else if idx > 0 {
+            self.merge_and_lower_intermediate_parent_key(idx - 1);
+        } else {
+            self.merge_and_lower_intermediate_parent_key(idx);
+        }
+    }
+}
+
+enum RemoveResult<K> {
+    None,
+    Key(K),
+    Deficiency(K),
+}
+
+enum SearchResult<'a, K, const B: usize, A: Allocator, L: Layout> {
+    None,
+    Key(&'a K),
+    Child(&'a Node<K, B, A, L>),
+}
+
+enum InsertResult<K, const B: usize, A: Allocator, L: Layout> {
+    AlreadyExists,
+    Inserted(Path),
+    Split(K, Node<K, B, A, L>, Placement),
+}
+
+/// Locates the key an operation targets within the node at the given depth,
+/// returning either the index of the key, or the index of the child to
+/// descend into.
+trait Locate<K> {
+    fn locate(&self, keys: &impl NodeStorage<K>, depth: usize)
+    -> std::result::Result<usize, usize>;
+}
+
+impl<K, F: Fn(&K) -> Ordering> Locate<K> for F {
+    fn locate(
+        &self,
+        keys: &impl NodeStorage<K>,
+        _depth: usize,
+    ) -> std::result::Result<usize, usize> {
+        metrics::count(Event::Visit);
+        keys.search_by(counting_comparisons(self))
+    }
+}
+
+impl<K> Locate<K> for Path {
+    fn locate(
+        &self,
+        _keys: &impl NodeStorage<K>,
+        depth: usize,
+    ) -> std::result::Result<usize, usize> {
+        if depth + 1 == self.len() {
+            Ok(self[depth])
+        } else {
+            Err(self[depth])
+        }
+    }
+}
+
+/// Wraps a function comparing keys against a target, so that every call is
+/// counted as a comparison.
+fn counting_comparisons<K>(f: impl Fn(&K) -> Ordering) -> impl Fn(&K) -> Ordering {
+    move |k| {
+        metrics::count(Event::Comparison);
+        f(k)
+    }
+}
+
+/// A path to a key in the tree: the indices of the children to descend into,
+/// followed by the index of the key in the last node.
+pub(super) type Path = VecDeque<usize>;
+
+/// A write of a batch, which either inserts a key, replacing the key equal to
+/// it, or removes the key matching a probe.
+pub(super) enum Write<K, Q> {
+    Upsert(K),
+    Remove(Q),
+}
+
+impl<K, Q> Write<K, Q> {
+    /// Compares a key of the tree to the key the write targets.
+    fn compare(
+        &self,
+        key: &K,
+        cmp: &impl Comparator<K>,
+        f: &impl Fn(&K, &Q) -> Ordering,
+    ) -> Ordering {
+        match self {
+            Write::Upsert(new) => cmp.compare(key, new),
+            Write::Remove(probe) => f(key, probe),
+        }
+    }
+}
+
+/// Describes where an inserted key ended up after its node was split.
+enum Placement {
+    /// The key stayed in the split node, at the given path.
+    Node(Path),
+    /// The key was hoisted into the parent.
+    Hoist,
+    /// The key was moved into the new sibling, at the given path.
+    Sibling(Path),
+}
+
+impl Placement {
+    /// Locates a key after the node containing it was split, given the path
+    /// to the key from that node before the split, and the number of keys
+    /// the split node kept.
+    fn after_split(mut path: Path, min_keys: usize) -> Placement {
+        let idx = path[0];
+
+        // If the path ends in this node, it points to a key, otherwise to a child.
+        let (kept, hoisted) = if path.len() == 1 {
+            (idx < min_keys, idx == min_keys)
+        } else {
+            (idx <= min_keys, false)
+        };
+
+        if kept {
+            Placement::Node(path)
+        } else if hoisted {
+            Placement::Hoist
+        } else {
+            path[0] -= min_keys + 1;
+            Placement::Sibling(path)
+        }
+    }
+
+    /// Converts the placement into a path from the parent of the split node,
+    /// given the index of the split node in its parent.
+    fn into_path(self, idx: usize) -> Path {
+        match self {
+            Placement::Node(mut path) => {
+                path.push_front(idx);
+                path
+            }
+            Placement::Hoist => Path::from([idx]),
+            Placement::Sibling(mut path) => {
+                path.push_front(idx + 1);
+                path
+            }
+        }
+    }
+}
+
+impl<K: Ord, const B: usize> SimpleBTreeSet<K, B> {
+    pub fn new() -> Self {
+        Self::new_in(Global)
+    }
+}
+
+impl<K: Ord, const B: usize, A: Allocator + Clone> SimpleBTreeSet<K, B, A> {
+    /// Creates an empty tree, whose nodes are placed in the given allocator.
+    pub fn new_in(alloc: A) -> Self {
+        Self::with_comparator_in(OrdComparator, alloc)
+    }
+}
+
+impl<K: Ord, const B: usize, A: Allocator + Clone, L: Layout>
+    SimpleBTreeSet<K, B, A, OrdComparator, L>
+{
+    /// Returns the key matching the given borrowed form of a key, so a tree
+    /// of `String`s can be searched with a `&str`.
+    ///
+    /// The ordering of the borrowed form must match the ordering of the keys.
+    pub fn search<Q>(&self, key: &Q) -> Result<&K>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.search_by(|k| k.borrow().cmp(key))
+    }
+
+    /// Returns `true` if the tree contains a key matching the given borrowed
+    /// form of a key.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.search(key).is_ok()
+    }
+
+    /// Removes the key matching the given borrowed form of a key, and
+    /// returns it.
+    pub fn remove<Q>(&mut self, key: &Q) -> Result<K>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.remove_by(|k| k.borrow().cmp(key))
+    }
+
+    /// Removes the key matching the given borrowed form of a key, and returns
+    /// it, or `None` if there is no such key.
+    pub fn take<Q>(&mut self, key: &Q) -> Option<K>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.remove(key).ok()
+    }
+
+    /// Returns the key matching the given borrowed form of a key, or inserts
+    /// the key computed from it if there is none, in a single descent.
+    ///
+    /// The computed key must match the borrowed form it was computed from.
+    pub fn get_or_insert_with<Q>(&mut self, value: &Q, f: impl FnOnce(&Q) -> K) -> &K
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let path = match self.search_path_by(|k| k.borrow().cmp(value)) {
+            Ok(path) => path,
+            Err(path) => self.insert_along(&path, f(value)),
+        };
+        self.get(&path)
+    }
+}
+
+impl<K, const B: usize, C: Comparator<K>> SimpleBTreeSet<K, B, Global, C> {
+    /// Creates an empty tree, whose keys are ordered by the given comparator.
+    pub fn with_comparator(cmp: C) -> Self {
+        Self::with_comparator_in(cmp, Global)
+    }
+}
+
+impl<K, const B: usize, A: Allocator + Clone, C: Comparator<K>, L: Layout>
+    SimpleBTreeSet<K, B, A, C, L>
+{
+    /// Creates an empty tree, whose keys are ordered by the given comparator,
+    /// and whose nodes are placed in the given allocator.
+    pub fn with_comparator_in(cmp: C, alloc: A) -> Self {
+        const { assert!(B >= 2, "the branching factor B must be at least 2") };
+        SimpleBTreeSet {
+            root: None,
+            alloc,
+            cmp,
+            spare: Vec::new(),
+            id: next_tree_id(),
+            generation: 0,
+            metrics: Recorder::default(),
+        }
+    }
+
+    /// Returns the comparator which orders the keys of the tree.
+    pub fn comparator(&self) -> &C {
+        &self.cmp
+    }
+
+    /// Inserts the given key, replacing the key equal to it if there is one.
+    /// Returns the replaced key, or `None` if the key was not in the tree.
+    pub fn replace(&mut self, key: K) -> Option<K> {
+        match self.search_path_by(|k| self.cmp.compare(k, &key)) {
+            Ok(path) => {
+                let replaced = std::mem::replace(self.get_mut(&path), key);
+                self.validate_after("replace");
+                Some(replaced)
+            }
+            Err(path) => {
+                self.insert_along(&path, key);
+                None
+            }
+        }
+    }
+
+    /// Returns the key equal to the given one, or inserts the given key if
+    /// there is none, in a single descent.
+    pub fn get_or_insert(&mut self, key: K) -> &K {
+        let path = match self.search_path_by(|k| self.cmp.compare(k, &key)) {
+            Ok(path) => path,
+            Err(path) => self.insert_along(&path, key),
+        };
+        self.get(&path)
+    }
+
+    /// Inserts the given key like `BTreeSet::insert`, but fails with
+    /// `Error::AllocFailed` instead of aborting if the allocator cannot hold
+    /// the nodes the insertion creates. On failure, the tree is unchanged.
+    ///
+    /// The nodes are allocated before the tree is touched, so only the nodes
+    /// of the tree are covered. A key layout which allocates on its own, like
+    /// `EytzingerLayout`, still aborts when out of memory.
+    pub fn try_insert(&mut self, key: K) -> Result<()> {
+        let path = match self.search_path_by(|k| self.cmp.compare(k, &key)) {
+            Ok(_) => return Err(Error::KeyAlreadyExists),
+            Err(path) => path,
+        };
+
+        if let Some(root) = self.root.as_ref() {
+            let needed = root.links_needed_along(&path);
+            self.try_reserve(needed.saturating_sub(self.spare.len()))?;
+        }
+        self.insert_along(&path, key);
+        Ok(())
+    }
+
+    /// Allocates `additional` nodes up front, failing with
+    /// `Error::AllocFailed` instead of aborting if the allocator is out of
+    /// memory. Later insertions place the nodes their splits create in the
+    /// reserved allocations before allocating any.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<()> {
+        self.spare
+            .try_reserve(additional)
+            .map_err(|_| Error::AllocFailed)?;
+        for _ in 0..additional {
+            let node = Node::leaf([], self.alloc.clone());
+            self.spare.push(try_boxed_in(node, self.alloc.clone())?);
+        }
+        Ok(())
+    }
+
+    pub(super) fn search_by(&self, f: impl Fn(&K) -> Ordering) -> Result<&K> {
+        let root = self.root.as_ref().ok_or(Error::KeyNotFound)?;
+        self.metrics.record(|| root.search_by(f))
+    }
+
+    pub(super) fn search_path_by(
+        &self,
+        f: impl Fn(&K) -> Ordering,
+    ) -> std::result::Result<Path, Path> {
+        match self.root.as_ref() {
+            Some(root) => self.metrics.record(|| root.search_path_by(f)),
+            None => Err(Path::from([0])),
+        }
+    }
+
+    pub(super) fn insert_along(&mut self, path: &Path, key: K) -> Path {
+        let path = if let Some(root) = self.root.as_mut() {
+            self.metrics
+                .record(|| root.insert_along(path, key, &mut self.spare))
+        } else {
+            let node = Node::leaf([key], self.alloc.clone());
+            self.root = Some(Root { node });
+            Path::from([0])
+        };
+        self.bump_generation();
+        self.validate_after("insert");
+        path
+    }
+
+    /// Returns the key the given path leads to.
+    ///
+    /// This method assumes that the path points to an existing key.
+    pub(super) fn get(&self, path: &Path) -> &K {
+        self.root.as_ref().unwrap().get(path)
+    }
+
+    /// Returns a mutable reference to the key the given path leads to. The
+    /// caller must not change the ordering of the key.
+    ///
+    /// This method assumes that the path points to an existing key.
+    pub(super) fn get_mut(&mut self, path: &Path) -> &mut K {
+        self.root.as_mut().unwrap().get_mut(path)
+    }
+
+    pub(super) fn remove_by(&mut self, f: impl Fn(&K) -> Ordering) -> Result<K> {
+        let root = self.root.as_mut().ok_or(Error::KeyNotFound)?;
+        let key = self.metrics.record(|| root.remove_by(f))?;
+        self.bump_generation();
+        self.validate_after("remove");
+        Ok(key)
+    }
+
+    /// Removes the key the given path leads to.
+    ///
+    /// This method assumes that the path points to an existing key.
+    fn remove_along(&mut self, path: &Path) -> K {
+        let root = self.root.as_mut().unwrap();
+        let key = self.metrics.record(|| root.remove_along(path));
+        self.bump_generation();
+        self.validate_after("remove");
+        key
+    }
+
+    /// Applies the writes of the batch in order, where `f` compares a key of
+    /// the tree to the probe of a removal. The writes must be sorted by the
+    /// keys they target.
+    ///
+    /// The tree is descended once for every run of writes which falls
+    /// between the same two separators, and the run is applied to the leaf
+    /// in place, for as long as the leaf neither overflows nor underflows.
+    /// A write which would rebalance the leaf, or which removes a separator,
+    /// goes through `replace` or `remove_by`, and the next write starts a new
+    /// run.
+    pub(super) fn apply_sorted_by<Q>(
+        &mut self,
+        batch: Vec<Write<K, Q>>,
+        f: impl Fn(&K, &Q) -> Ordering,
+    ) {
+        let mut writes = batch.into_iter();
+        while !writes.as_slice().is_empty() {
+            let mut applied = 0;
+            if let Some(root) = self.root.as_mut() {
+                let cmp = &self.cmp;
+                applied = self.metrics.record(|| {
+                    let (path, run) = root.leaf_run(writes.as_slice(), cmp, &f);
+                    root.apply_to_leaf(&path, &mut writes, run, cmp, &f)
+                });
+            }
+
+            if applied > 0 {
+                self.bump_generation();
+                self.validate_after("apply_sorted_by");
+                continue;
+            }
+            match writes.next().unwrap() {
+                Write::Upsert(key) => drop(self.replace(key)),
+                Write::Remove(probe) => drop(self.remove_by(|k| f(k, &probe))),
+            }
+        }
+    }
+
+    /// Returns the number of times the tree was structurally modified, by
+    /// inserting or removing keys, since it was created.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub(super) fn bump_generation(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    /// Returns the work done by the searches, insertions and removals on the
+    /// tree since it was created, or since the metrics were last reset.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.get()
+    }
+
+    /// Sets every counter of the metrics back to zero.
+    #[cfg(feature = "metrics")]
+    pub fn reset_metrics(&self) {
+        self.metrics.reset()
+    }
+
+    /// Returns a cursor pointing at the smallest key of the tree.
+    pub fn cursor(&self) -> Cursor<'_, K, B, A, C, L> {
+        let mut cursor = Cursor::new(self);
+        cursor.move_next();
+        cursor
+    }
+
+    /// Returns a mutable cursor pointing at the smallest key of the tree.
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, K, B, A, C, L> {
+        let mut cursor = CursorMut::new(self);
+        cursor.move_next();
+        cursor
+    }
+
+    /// Returns a cursor pointing at the saved position, which fails with
+    /// `Error::StalePosition` if the tree was modified since the position was
+    /// saved, or if it was saved from a cursor over another tree.
+    pub fn cursor_at(&self, position: &Position) -> Result<Cursor<'_, K, B, A, C, L>> {
+        self.check_position(position)?;
+        Ok(Cursor::from_path(self, position.path.as_ref()))
+    }
+
+    /// Returns a mutable cursor pointing at the saved position. See
+    /// `cursor_at`.
+    pub fn cursor_mut_at(&mut self, position: &Position) -> Result<CursorMut<'_, K, B, A, C, L>> {
+        self.check_position(position)?;
+        Ok(CursorMut::from_path(self, position.path.clone()))
+    }
+
+    fn check_position(&self, position: &Position) -> Result<()> {
+        if position.tree == self.id && position.generation == self.generation {
+            Ok(())
+        } else {
+            Err(Error::StalePosition)
+        }
+    }
+
+    /// Returns a cursor pointing at the smallest key greater than or equal to
+    /// the given key, or at the ghost position if there is none.
+    pub fn lower_bound(&self, key: &K) -> Cursor<'_, K, B, A, C, L> {
+        let mut cursor = Cursor::new(self);
+        cursor.seek(key);
+        cursor
+    }
+
+    /// Returns a cursor pointing at the smallest key greater than the given
+    /// key, or at the ghost position if there is none.
+    pub fn upper_bound(&self, key: &K) -> Cursor<'_, K, B, A, C, L> {
+        let mut cursor = self.lower_bound(key);
+        if cursor
+            .key()
+            .is_some_and(|k| self.cmp.compare(k, key) == Ordering::Equal)
+        {
+            cursor.move_next();
+        }
+        cursor
+    }
+
+    /// Returns the smallest key greater than the given key.
+    pub fn successor(&self, key: &K) -> Option<&K> {
+        self.upper_bound(key).key()
+    }
+
+    /// Returns the largest key less than the given key.
+    pub fn predecessor(&self, key: &K) -> Option<&K> {
+        // Moving back from the ghost position wraps around to the largest key,
+        // which is the predecessor when every key is less than the given one.
+        let mut cursor = self.lower_bound(key);
+        cursor.move_prev();
+        cursor.key()
+    }
+}
+
+impl<K, const B: usize, C: Comparator<K> + Default, L: Layout> Default
+    for SimpleBTreeSet<K, B, Global, C, L>
+{
+    fn default() -> Self {
+        Self::with_comparator_in(C::default(), Global)
+    }
+}
+
+/// Copies the tree node by node, so the copy has the same shape as the tree
+/// instead of the shape inserting its keys would give.
+impl<K: Clone, const B: usize, A: Allocator + Clone, C: Clone, L: Layout> Clone
+    for SimpleBTreeSet<K, B, A, C, L>
+{
+    fn clone(&self) -> Self {
+        SimpleBTreeSet {
+            root: self.root.as_ref().map(|root| Root {
+                node: root.node.clone(),
+            }),
+            alloc: self.alloc.clone(),
+            cmp: self.cmp.clone(),
+            spare: Vec::new(),
+            id: next_tree_id(),
+            generation: self.generation,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+impl<K: Clone, const B: usize, A: Allocator + Clone, L: Layout> Clone for Node<K, B, A, L> {
+    fn clone(&self) -> Self {
+        Node {
+            is_leaf: self.is_leaf,
+            keys: NodeStorage::from_keys(self.keys.iter().cloned()),
+            children: self
+                .children
+                .iter()
+                .map(|child| (**child).clone().link())
+                .collect(),
+            alloc: self.alloc.clone(),
+        }
+    }
+}
+
+/// Tears the nodes down one by one with an explicit worklist, instead of
+/// letting every node drop its children recursively.
+impl<K, const B: usize, A: Allocator, C, L: Layout> Drop for SimpleBTreeSet<K, B, A, C, L> {
+    fn drop(&mut self) {
+        let Some(mut root) = self.root.take() else {
+            return;
+        };
+
+        let mut worklist = Vec::from_iter(std::mem::take(&mut root.node.children));
+        while let Some(mut node) = worklist.pop() {
+            worklist.extend(std::mem::take(&mut node.children));
+        }
+    }
+}
+
+impl<K, const B: usize, A: Allocator + Clone, C: Comparator<K>, L: Layout> BTreeSet
+    for SimpleBTreeSet<K, B, A, C, L>
+{
+    type Key = K;
+    const B: usize = B;
+
+    fn search(&self, key: &Self::Key) -> Result<&Self::Key> {
+        self.search_by(|k| self.cmp.compare(k, key))
+    }
+
+    fn insert(&mut self, key: Self::Key) -> Result<()> {
+        if let Some(root) = self.root.as_mut() {
+            let (cmp, spare) = (&self.cmp, &mut self.spare);
+            self.metrics.record(|| root.insert(key, cmp, spare))?;
+        } else {
+            let node = Node::leaf([key], self.alloc.clone());
+            self.root = Some(Root { node });
+        }
+        self.bump_generation();
+        self.validate_after("insert");
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &Self::Key) -> Result<Self::Key> {
+        let cmp = &self.cmp;
+        let root = self.root.as_mut().ok_or(Error::KeyNotFound)?;
+        let key = self
+            .metrics
+            .record(|| root.remove_by(|k| cmp.compare(k, key)))?;
+        self.bump_generation();
+        self.validate_after("remove");
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::EytzingerLayout;
+    use crate::test_btree_impl;
+
+    test_btree_impl!(SimpleBTreeSet);
+
+    /// Returns a tree with B = 2 holding the given keys, inserted in order.
+    pub(super) fn tree_with<K: Ord>(keys: impl IntoIterator<Item = K>) -> SimpleBTreeSet<K, 2> {
+        let mut tree = SimpleBTreeSet::new();
+        for key in keys {
+            tree.insert(key).unwrap();
+        }
+        tree
+    }
+
+    #[test]
+    fn test_removal_rebalances_without_losing_keys() {
+        // Removing 0, 2, 4 and 1 from 0..5 used to borrow from a child which
+        // could not spare a key, and then index past the end of the node.
+        let mut tree = SimpleBTreeSet::<usize, 2>::new();
+        for key in 0..5 {
+            tree.insert(key).unwrap();
+        }
+        for key in [0, 2, 4, 1] {
+            assert_eq!(tree.remove(&key).unwrap(), key);
+        }
+        assert_eq!(tree.search(&3).unwrap(), &3);
+
+        for len in 1..40 {
+            for step in (1..len).filter(|step| gcd(*step, len) == 1) {
+                let mut tree = SimpleBTreeSet::<usize, 2>::new();
+                for key in 0..len {
+                    tree.insert(key).unwrap();
+                }
+                for (i, key) in (0..len).map(|i| i * step % len).enumerate() {
+                    assert_eq!(tree.remove(&key).unwrap(), key);
+                    let left = (0..len).filter(|key| tree.search(key).is_ok()).count();
+                    assert_eq!(left, len - i - 1, "{len} keys, step {step}");
+                }
+            }
+        }
+    }
+
+    fn gcd(a: usize, b: usize) -> usize {
+        if b == 0 { a } else { gcd(b, a % b) }
+    }
+
+    #[test]
+    fn test_clone_copies_the_shape_and_is_independent() {
+        let mut tree = SimpleBTreeSet::<u32, 2>::new();
+        for key in (0..500).map(|i| i * 7919 % 500) {
+            tree.insert(key).unwrap();
+        }
+        let rebuilt: SimpleBTreeSet<u32, 2> = {
+            let mut rebuilt = SimpleBTreeSet::new();
+            for key in tree.iter() {
+                rebuilt.insert(*key).unwrap();
+            }
+            rebuilt
+        };
+
+        let mut copy = tree.clone();
+        assert_eq!(format!("{copy:?}"), format!("{tree:?}"));
+        assert_ne!(format!("{rebuilt:?}"), format!("{tree:?}"));
+
+        for key in 0..250 {
+            copy.remove(&key).unwrap();
+        }
+        copy.insert(1000).unwrap();
+        assert!(tree.iter().copied().eq(0..500));
+        assert!(copy.iter().copied().eq((250..500).chain([1000])));
+        assert!(copy.check_invariants().is_ok());
+
+        let mut eytzinger =
+            SimpleBTreeSet::<u32, 3, Global, OrdComparator, EytzingerLayout>::default();
+        for key in (0..100).rev() {
+            eytzinger.insert(key).unwrap();
+        }
+        let copy = eytzinger.clone();
+        assert!(copy.iter().copied().eq(0..100));
+        assert!(copy.check_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_dropping_a_deep_tree_does_not_overflow_the_stack() {
+        // A chain of nodes with a single child each is far deeper than a
+        // valid tree could get, and too deep to drop recursively on the
+        // stack of a test thread.
+        let mut node = Node::leaf([0], Global);
+        for key in 1..200_000 {
+            node = Node::intermediate([key], [node.link()], Global);
+        }
+        let mut tree = SimpleBTreeSet::<u32, 2>::new();
+        tree.root = Some(Root { node });
+        drop(tree);
+    }
+
+    #[test]
+    #[ignore = "builds a tree of four million keys"]
+    fn test_dropping_a_huge_tree_does_not_overflow_the_stack() {
+        let mut tree = SimpleBTreeSet::<u32, 2>::new();
+        for key in 0..4_000_000 {
+            tree.insert(key).unwrap();
+        }
+        assert!(tree.contains(&3_999_999));
+        drop(tree);
+    }
+
+    #[test]
+    fn test_string_keys_can_be_looked_up_by_str() {
+        let mut tree = SimpleBTreeSet::<String, 2>::new();
+        for i in 0..100 {
+            tree.insert(format!("key{i:03}")).unwrap();
+        }
+
+        assert_eq!(tree.search("key042").unwrap(), "key042");
+        assert!(tree.contains("key099"));
+        assert!(!tree.contains("key100"));
+        assert_eq!(tree.remove("key007").unwrap(), "key007");
+        assert!(matches!(tree.remove("key007"), Err(Error::KeyNotFound)));
+        assert!(matches!(tree.search("key"), Err(Error::KeyNotFound)));
+        tree.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn test_eytzinger_layout_conforms() {
+        type EytzingerTree = SimpleBTreeSet<usize, 3, Global, OrdComparator, EytzingerLayout>;
+        crate::testsuite::run_all(EytzingerTree::default);
+
+        let mut tree = EytzingerTree::default();
+        for key in (0..2003).map(|i| (i * 7919) % 2003) {
+            tree.insert(key).unwrap();
+        }
+        for key in (0..2003).step_by(3) {
+            let _ = tree.remove(&key);
+        }
+        tree.check_invariants().unwrap();
+        assert!(
+            tree.iter()
+                .copied()
+                .eq((0..2003).filter(|key| key % 3 != 0))
+        );
+    }
+
+    #[test]
+    fn test_scanned_layout_conforms() {
+        type ScannedTree = SimpleBTreeSet<usize, 3, Global, OrdComparator, SortedLayout<4>>;
+        crate::testsuite::run_all(ScannedTree::default);
+    }
+
+    #[test]
+    fn test_capacity_rules_follow_the_branching_factor() {
+        type Node3 = Node<u32, 3, Global>;
+        assert_eq!(Node3::MIN_KEYS, 2);
+        assert_eq!(Node3::MAX_KEYS, 5);
+        assert_eq!(Node3::MIN_CHILDREN, 3);
+        assert_eq!(Node3::MAX_CHILDREN, 6);
+
+        // Splits are tracked by the placements, so every inserted key must be
+        // found where the returned reference points.
+        let mut tree = SimpleBTreeSet::<u32, 3>::new();
+        for i in 0..1000 {
+            let key = (i * 7919) % 1009;
+            assert_eq!(*tree.get_or_insert(key), key);
+        }
+        tree.check_invariants().unwrap();
+
+        let occupancy = tree.stats().occupancy;
+        assert_eq!(occupancy.len(), Node3::MAX_KEYS + 1);
+        assert!(occupancy[..Node3::MIN_KEYS].iter().sum::<usize>() <= 1);
+    }
+
+    #[test]
+    fn test_get_or_insert_returns_the_existing_key() {
+        let mut tree = SimpleBTreeSet::<usize, 2>::new();
+        for i in 0..503 {
+            let key = (i * 7919) % 503;
+            assert_eq!(*tree.get_or_insert(key), key);
+        }
+        for key in 0..503 {
+            assert_eq!(*tree.get_or_insert(key), key);
+        }
+        assert_eq!(tree.iter().count(), 503);
+        tree.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn test_get_or_insert_with_computes_only_missing_keys() {
+        let mut tree = SimpleBTreeSet::<String, 2>::new();
+        let mut computed = 0;
+        for word in ["b", "a", "c", "a", "b", "d"] {
+            let key = tree.get_or_insert_with(word, |word| {
+                computed += 1;
+                word.to_string()
+            });
+            assert_eq!(key, word);
+        }
+
+        assert_eq!(computed, 4);
+        assert!(tree.iter().eq(["a", "b", "c", "d"]));
+    }
+
+    #[test]
+    fn test_replace_swaps_in_the_equal_key() {
+        /// A key which carries a payload ignored by the ordering.
+        #[derive(Debug)]
+        struct Tagged(usize, &'static str);
+
+        let mut tree =
+            SimpleBTreeSet::<Tagged, 2, Global, _>::with_comparator(|a: &Tagged, b: &Tagged| {
+                a.0.cmp(&b.0)
+            });
+        for key in 0..100 {
+            assert!(tree.replace(Tagged(key, "old")).is_none());
+        }
+
+        let replaced = tree.replace(Tagged(42, "new")).unwrap();
+        assert_eq!(replaced.1, "old");
+        assert_eq!(tree.search(&Tagged(42, "")).unwrap().1, "new");
+        assert_eq!(tree.iter().count(), 100);
+        tree.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn test_take_returns_none_for_missing_keys() {
+        let mut tree = SimpleBTreeSet::<String, 2>::new();
+        tree.insert("a".to_string()).unwrap();
+
+        assert_eq!(tree.take("a").as_deref(), Some("a"));
+        assert_eq!(tree.take("a"), None);
+        assert_eq!(tree.take("b"), None);
+    }
+
+    #[test]
+    fn test_reverse_comparator_orders_keys_descending() {
+        let mut tree =
+            SimpleBTreeSet::<usize, 2, Global, _>::with_comparator(|a: &usize, b: &usize| b.cmp(a));
+        for i in 0..503 {
+            tree.insert((i * 7919) % 503).unwrap();
+        }
+        for key in (0..503).step_by(3) {
+            assert_eq!(tree.remove(&key).unwrap(), key);
+        }
+        tree.check_invariants().unwrap();
+
+        let expected: Vec<_> = (0..503).rev().filter(|key| key % 3 != 0).collect();
+        assert!(tree.iter().copied().eq(expected.iter().copied()));
+
+        // Seeking lands on the next key in the order of the comparator.
+        let mut cursor = tree.cursor();
+        cursor.seek(&300);
+        assert_eq!(cursor.key(), Some(&299));
+
+        // The keys after 250 in the order of the comparator are the smaller ones.
+        let low = tree.split_off(&250);
+        assert!(low.iter().all(|&key| key <= 250));
+        assert!(tree.iter().all(|&key| key > 250));
+        tree.append(low);
+        tree.check_invariants().unwrap();
+        assert!(tree.iter().copied().eq(expected.iter().copied()));
+    }
+
+    #[test]
+    fn test_case_insensitive_comparator_treats_cases_as_equal() {
+        struct CaseInsensitive;
+
+        impl Comparator<String> for CaseInsensitive {
+            fn compare(&self, a: &String, b: &String) -> Ordering {
+                a.to_lowercase().cmp(&b.to_lowercase())
+            }
+        }
+
+        let mut tree = SimpleBTreeSet::<String, 2, Global, _>::with_comparator(CaseInsensitive);
+        for word in ["banana", "Cherry", "apple", "Date"] {
+            tree.insert(word.to_string()).unwrap();
+        }
+
+        assert!(matches!(
+            tree.insert("APPLE".to_string()),
+            Err(Error::KeyAlreadyExists)
+        ));
+        assert_eq!(tree.search(&"CHERRY".to_string()).unwrap(), "Cherry");
+        assert_eq!(tree.remove(&"date".to_string()).unwrap(), "Date");
+        assert!(tree.iter().eq(["apple", "banana", "Cherry"]));
+    }
+
+    #[test]
+    fn test_comparator_allows_keys_without_ord() {
+        let mut tree = SimpleBTreeSet::<f64, 2, Global, _>::with_comparator(f64::total_cmp);
+        for i in 0..100 {
+            tree.insert(((i * 37) % 100) as f64 / 4.0 - 10.0).unwrap();
+        }
+        tree.insert(-0.0).unwrap();
+        tree.check_invariants().unwrap();
+
+        // The total order tells the zeros apart.
+        assert!(tree.contains(&-0.0));
+        assert!(tree.contains(&0.0));
+        assert_eq!(tree.iter().count(), 101);
+        assert!(tree.iter().is_sorted_by(|a, b| a.total_cmp(b).is_lt()));
+    }
+
+    #[cfg(feature = "allocator_api")]
+    #[test]
+    fn test_nodes_are_placed_in_the_given_allocator() {
+        use std::alloc::{AllocError, Layout};
+        use std::cell::Cell;
+        use std::ptr::NonNull;
+
+        /// Forwards to the global allocator, counting the live allocations.
+        #[derive(Clone, Copy)]
+        struct Counting<'a>(&'a Cell<usize>);
+
+        unsafe impl Allocator for Counting<'_> {
+            fn allocate(&self, layout: Layout) -> std::result::Result<NonNull<[u8]>, AllocError> {
+                self.0.set(self.0.get() + 1);
+                Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                self.0.set(self.0.get() - 1);
+                unsafe { Global.deallocate(ptr, layout) }
+            }
+        }
+
+        let live = Cell::new(0);
+        let mut tree = SimpleBTreeSet::<usize, 2, _>::new_in(Counting(&live));
+
+        for key in 0..1000 {
+            tree.insert(key).unwrap();
+        }
+        assert!(live.get() > 0);
+
+        let mut other = tree.split_off(&500);
+        other.append(tree);
+        for key in 0..1000 {
+            assert_eq!(other.remove(&key).unwrap(), key);
+        }
+        assert_eq!(live.get(), 0);
+
+        for key in 0..1000 {
+            other.insert(key).unwrap();
+        }
+        drop(other);
+        assert_eq!(live.get(), 0);
+    }
+
+    #[test]
+    fn test_try_insert_matches_insert() {
+        let mut tree = SimpleBTreeSet::<usize, 2>::new();
+        for key in (0..1000).map(|i| i * 7919 % 1000) {
+            tree.try_insert(key).unwrap();
+            assert!(tree.spare.is_empty());
+        }
+        assert!(matches!(tree.try_insert(7), Err(Error::KeyAlreadyExists)));
+        assert!(tree.iter().copied().eq(0..1000));
+        tree.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn test_reserved_nodes_are_used_by_splits() {
+        let mut tree = SimpleBTreeSet::<usize, 2>::new();
+        tree.try_reserve(10).unwrap();
+        assert_eq!(tree.spare.len(), 10);
+
+        for key in 0..100 {
+            tree.insert(key).unwrap();
+        }
+        assert!(tree.spare.is_empty());
+        assert!(tree.iter().copied().eq(0..100));
+        tree.check_invariants().unwrap();
+    }
+
+    #[cfg(feature = "allocator_api")]
+    #[test]
+    fn test_try_insert_fails_without_changing_the_tree() {
+        use std::alloc::{AllocError, Layout};
+        use std::cell::Cell;
+        use std::ptr::NonNull;
+
+        /// Forwards to the global allocator until the budget runs out.
+        #[derive(Clone, Copy)]
+        struct Limited<'a>(&'a Cell<usize>);
+
+        unsafe impl Allocator for Limited<'_> {
+            fn allocate(&self, layout: Layout) -> std::result::Result<NonNull<[u8]>, AllocError> {
+                let budget = self.0.get().checked_sub(1).ok_or(AllocError)?;
+                self.0.set(budget);
+                Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                unsafe { Global.deallocate(ptr, layout) }
+            }
+        }
+
+        let budget = Cell::new(0);
+        let mut tree = SimpleBTreeSet::<usize, 2, _>::new_in(Limited(&budget));
+
+        // Without any budget, every insertion which splits a node fails, and
+        // with a single allocation left, so does every one splitting the root.
+        let mut failures = 0;
+        for key in 0..1000 {
+            for limit in [0, 1] {
+                budget.set(limit);
+                match tree.try_insert(key) {
+                    Ok(()) => break,
+                    Err(Error::AllocFailed) => failures += 1,
+                    Err(err) => panic!("unexpected error {err}"),
+                }
+                assert!(tree.iter().copied().eq(0..key));
+                tree.check_invariants().unwrap();
+            }
+            budget.set(usize::MAX);
+            if !tree.contains(&key) {
+                tree.try_insert(key).unwrap();
+            }
+        }
+
+        assert!(failures > 0);
+        assert!(tree.iter().copied().eq(0..1000));
+    }
+}
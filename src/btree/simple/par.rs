@@ -0,0 +1,291 @@
+use super::{Allocator, Global, Layout, Node, NodeStorage, Root, SimpleBTreeSet, SortedLayout};
+use rayon::iter::plumbing::{Folder, UnindexedConsumer, UnindexedProducer, bridge_unindexed};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rayon::slice::ParallelSliceMut;
+
+/// A parallel iterator over the keys of a `SimpleBTreeSet`.
+///
+/// The work is split at subtree boundaries: a node is split into its
+/// children and the keys between them, and each half of those pieces is
+/// handed to a different thread, which walks its subtrees sequentially.
+/// Order-aware adaptors like `collect` into a `Vec` still see the keys in
+/// ascending order.
+pub struct ParIter<'a, K, const B: usize, A: Allocator = Global, L: Layout = SortedLayout> {
+    root: Option<&'a Node<K, B, A, L>>,
+}
+
+impl<'a, K, const B: usize, A, L> ParallelIterator for ParIter<'a, K, B, A, L>
+where
+    K: Sync,
+    A: Allocator + Sync,
+    L: Layout,
+    L::Keys<K, B>: Sync,
+{
+    type Item = &'a K;
+
+    fn drive_unindexed<Co: UnindexedConsumer<Self::Item>>(self, consumer: Co) -> Co::Result {
+        let pieces = self.root.into_iter().map(Piece::Subtree).collect();
+        bridge_unindexed(Pieces(pieces), consumer)
+    }
+}
+
+/// A part of the tree handed to a thread: either a whole subtree, or a single
+/// key separating two subtrees.
+enum Piece<'a, K, const B: usize, A: Allocator, L: Layout> {
+    Key(&'a K),
+    Subtree(&'a Node<K, B, A, L>),
+}
+
+/// A run of adjacent pieces, in ascending order of their keys.
+struct Pieces<'a, K, const B: usize, A: Allocator, L: Layout>(Vec<Piece<'a, K, B, A, L>>);
+
+impl<'a, K, const B: usize, A, L> UnindexedProducer for Pieces<'a, K, B, A, L>
+where
+    K: Sync,
+    A: Allocator + Sync,
+    L: Layout,
+    L::Keys<K, B>: Sync,
+{
+    type Item = &'a K;
+
+    fn split(mut self) -> (Self, Option<Self>) {
+        // A lone intermediate node is opened up into its children and keys,
+        // which can then be divided between threads.
+        if let [Piece::Subtree(node)] = self.0[..]
+            && !node.is_leaf
+        {
+            self.0 = node.pieces().collect();
+        }
+
+        if self.0.len() < 2 {
+            return (self, None);
+        }
+        let right = self.0.split_off(self.0.len() / 2);
+        (self, Some(Pieces(right)))
+    }
+
+    fn fold_with<F: Folder<Self::Item>>(self, mut folder: F) -> F {
+        for piece in self.0 {
+            folder = match piece {
+                Piece::Key(key) => folder.consume(key),
+                Piece::Subtree(node) => node.fold_keys(folder),
+            };
+            if folder.full() {
+                break;
+            }
+        }
+        folder
+    }
+}
+
+impl<K, const B: usize, A: Allocator, L: Layout> Node<K, B, A, L> {
+    /// Returns the children of the node interleaved with its keys.
+    fn pieces(&self) -> impl Iterator<Item = Piece<'_, K, B, A, L>> {
+        let children = self.children.iter().map(|child| Piece::Subtree(&**child));
+        let keys = self.keys.iter().map(Piece::Key);
+        let mut keys = keys.fuse();
+        children.flat_map(move |child| std::iter::once(child).chain(keys.next()))
+    }
+
+    /// Feeds the keys of the subtree rooted at the node to the folder, in
+    /// ascending order, until the folder is full.
+    fn fold_keys<'a, F: Folder<&'a K>>(&'a self, mut folder: F) -> F {
+        if self.is_leaf {
+            return folder.consume_iter(self.keys.iter());
+        }
+
+        for piece in self.pieces() {
+            folder = match piece {
+                Piece::Key(key) => folder.consume(key),
+                Piece::Subtree(child) => child.fold_keys(folder),
+            };
+            if folder.full() {
+                break;
+            }
+        }
+        folder
+    }
+}
+
+impl<K, const B: usize, A: Allocator, C, L: Layout> SimpleBTreeSet<K, B, A, C, L> {
+    /// Returns a parallel iterator over the keys of the tree.
+    pub fn par_iter(&self) -> ParIter<'_, K, B, A, L> {
+        ParIter {
+            root: self.root.as_ref().map(|root| &root.node),
+        }
+    }
+}
+
+impl<'a, K, const B: usize, A, C, L> IntoParallelIterator for &'a SimpleBTreeSet<K, B, A, C, L>
+where
+    K: Sync,
+    A: Allocator + Sync,
+    L: Layout,
+    L::Keys<K, B>: Sync,
+{
+    type Item = &'a K;
+    type Iter = ParIter<'a, K, B, A, L>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        self.par_iter()
+    }
+}
+
+impl<K: Ord + Send, const B: usize> SimpleBTreeSet<K, B> {
+    /// Builds a tree out of the given keys, which may be in any order and
+    /// contain duplicates, using all threads of the rayon pool.
+    ///
+    /// The keys are sorted in parallel and deduplicated, and the shape of
+    /// the tree is then decided up front from the number of keys, so every
+    /// subtree is built from its own slice of the keys. Sibling subtrees,
+    /// and so the leaves, are built in parallel, and each node is assembled
+    /// once its children are done. No key is compared after sorting.
+    pub fn from_unsorted_parallel(mut keys: Vec<K>) -> Self {
+        keys.par_sort_unstable();
+        keys.dedup();
+
+        let mut tree = Self::new();
+        if !keys.is_empty() {
+            let mut height = 0;
+            while subtree_capacity::<B>(height) < keys.len() {
+                height += 1;
+            }
+            tree.root = Some(Root {
+                node: Node::build(keys, height, 2),
+            });
+        }
+        tree.validate_after("from_unsorted_parallel");
+        tree
+    }
+}
+
+/// Returns the number of keys a subtree of the given height holds when all
+/// of its nodes are full.
+fn subtree_capacity<const B: usize>(height: u32) -> usize {
+    (2 * B).saturating_pow(height + 1) - 1
+}
+
+impl<K: Send, const B: usize> Node<K, B, Global, SortedLayout> {
+    /// Builds the subtree of the given height holding the sorted keys, whose
+    /// root has at least `min_children` children if it is not a leaf.
+    ///
+    /// This method assumes that the keys fit into a subtree of the given
+    /// height, and, unless the subtree is the whole tree, that there are
+    /// enough of them for its nodes not to be deficient.
+    fn build(mut keys: Vec<K>, height: u32, min_children: usize) -> Self {
+        if height == 0 {
+            return Node::leaf(keys, Global);
+        }
+
+        // The fewest children which can hold the keys, but never fewer than
+        // the node may have. Spreading the keys evenly between them keeps
+        // every child within the bounds of its height.
+        let child_capacity = subtree_capacity::<B>(height - 1);
+        let count = (keys.len() + 1)
+            .div_ceil(child_capacity + 1)
+            .max(min_children);
+        let total = keys.len() - (count - 1);
+
+        let mut parts = Vec::with_capacity(count);
+        let mut separators = Vec::with_capacity(count - 1);
+        for idx in (0..count).rev() {
+            let size = total / count + usize::from(idx < total % count);
+            parts.push(keys.split_off(keys.len() - size));
+            if idx > 0 {
+                separators.push(keys.pop().unwrap());
+            }
+        }
+        parts.reverse();
+        separators.reverse();
+
+        let children: Vec<Self> = parts
+            .into_par_iter()
+            .map(|part| Node::build(part, height - 1, Self::MIN_CHILDREN))
+            .collect();
+        let children = children.into_iter().map(Node::link);
+        Node::intermediate(separators, children, Global)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BTreeSet;
+    use crate::btree::EytzingerLayout;
+    use crate::btree::OrdComparator;
+
+    #[test]
+    fn test_par_iter_yields_keys_in_order() {
+        for n in [0, 1, 3, 100, 10_000] {
+            let mut tree = SimpleBTreeSet::<usize, 2>::new();
+            for key in (0..n).map(|i| i * 7919 % n.max(1)) {
+                tree.insert(key).unwrap();
+            }
+
+            let keys: Vec<usize> = tree.par_iter().copied().collect();
+            assert_eq!(keys, (0..n).collect::<Vec<_>>(), "{n} keys");
+            assert_eq!(
+                (&tree).into_par_iter().map(|k| k * 2).sum::<usize>(),
+                n * n.saturating_sub(1)
+            );
+        }
+    }
+
+    #[test]
+    fn test_par_iter_splits_at_subtrees() {
+        let mut tree = SimpleBTreeSet::<usize, 2>::new();
+        for key in 0..1000 {
+            tree.insert(key).unwrap();
+        }
+
+        let root = Pieces(vec![Piece::Subtree(&tree.root.as_ref().unwrap().node)]);
+        let (left, right) = root.split();
+        let right = right.expect("an intermediate root should be split");
+        assert!(!left.0.is_empty() && !right.0.is_empty());
+        assert_eq!(
+            left.0.len() + right.0.len(),
+            2 * tree.root.as_ref().unwrap().node.keys.len() + 1
+        );
+    }
+
+    #[test]
+    fn test_from_unsorted_parallel_matches_insertion() {
+        for n in [0, 1, 3, 4, 15, 16, 17, 63, 64, 65, 1000, 100_000] {
+            let keys: Vec<usize> = (0..n * 2).map(|i| i * 7919 % n.max(1)).collect();
+
+            let tree = SimpleBTreeSet::<usize, 2>::from_unsorted_parallel(keys.clone());
+            tree.check_invariants().unwrap();
+            assert!(tree.iter().copied().eq(0..n), "{n} keys");
+
+            let tree = SimpleBTreeSet::<usize>::from_unsorted_parallel(keys);
+            tree.check_invariants().unwrap();
+            assert!(tree.iter().copied().eq(0..n), "{n} keys");
+        }
+    }
+
+    #[test]
+    fn test_tree_built_in_parallel_stays_usable() {
+        let keys = (0..10_000).rev().map(|k| k * 2).collect();
+        let mut tree = SimpleBTreeSet::<usize, 3>::from_unsorted_parallel(keys);
+
+        for key in 0..10_000 {
+            tree.insert(key * 2 + 1).unwrap();
+            assert_eq!(tree.remove(&(key * 2)).unwrap(), key * 2);
+        }
+        tree.check_invariants().unwrap();
+        assert!(tree.iter().copied().eq((0..10_000).map(|k| k * 2 + 1)));
+    }
+
+    #[test]
+    fn test_par_iter_short_circuits() {
+        let mut tree =
+            SimpleBTreeSet::<usize, 3, Global, OrdComparator, EytzingerLayout>::default();
+        for key in 0..50_000 {
+            tree.insert(key).unwrap();
+        }
+
+        assert_eq!(tree.par_iter().find_first(|&&k| k > 40_000), Some(&40_001));
+        assert!(tree.par_iter().any(|&k| k == 12_345));
+        assert_eq!(tree.par_iter().count(), 50_000);
+    }
+}
@@ -0,0 +1,169 @@
+use super::{Allocator, Layout, Link, Node, NodeStorage, SimpleBTreeSet};
+use std::mem;
+
+/// Structural statistics of a `SimpleBTreeSet`, useful for tuning the
+/// branching factor and judging how well the nodes are filled.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TreeStats {
+    /// The number of levels in the tree, which is zero for an empty tree.
+    pub height: usize,
+    /// The number of intermediate nodes, including an intermediate root.
+    pub internal_nodes: usize,
+    /// The number of leaf nodes.
+    pub leaf_nodes: usize,
+    /// The number of keys in the tree.
+    pub keys: usize,
+    /// The number of nodes holding each possible number of keys, indexed by
+    /// the number of keys, from zero up to the capacity of a node.
+    pub occupancy: Vec<usize>,
+    /// The estimated number of bytes the nodes take up on the heap. Memory
+    /// owned by the keys themselves is not included, see
+    /// `SimpleBTreeSet::heap_size` for an exact count.
+    pub heap_bytes: usize,
+}
+
+impl TreeStats {
+    /// Returns the total number of nodes.
+    pub fn nodes(&self) -> usize {
+        self.internal_nodes + self.leaf_nodes
+    }
+
+    /// Returns the average number of keys held by a node, or zero for an
+    /// empty tree.
+    pub fn average_occupancy(&self) -> f64 {
+        match self.nodes() {
+            0 => 0.0,
+            nodes => self.keys as f64 / nodes as f64,
+        }
+    }
+
+    /// Returns the average fraction of a node's capacity that is used, from
+    /// zero to one.
+    pub fn fill_factor(&self) -> f64 {
+        self.average_occupancy() / (self.occupancy.len() - 1) as f64
+    }
+}
+
+impl<K, const B: usize, A: Allocator, C, L: Layout> SimpleBTreeSet<K, B, A, C, L> {
+    /// Collects structural statistics of the tree, visiting every node once.
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = TreeStats {
+            height: 0,
+            internal_nodes: 0,
+            leaf_nodes: 0,
+            keys: 0,
+            occupancy: vec![0; Node::<K, B, A>::MAX_KEYS + 1],
+            heap_bytes: 0,
+        };
+
+        let Some(root) = self.root.as_ref() else {
+            return stats;
+        };
+
+        let mut stack = vec![(&root.node, 1)];
+        while let Some((node, depth)) = stack.pop() {
+            stats.height = stats.height.max(depth);
+            stats.keys += node.keys.len();
+            stats.occupancy[node.keys.len()] += 1;
+
+            if node.is_leaf {
+                stats.leaf_nodes += 1;
+            } else {
+                stats.internal_nodes += 1;
+                stack.extend(node.children.iter().map(|child| (&**child, depth + 1)));
+            }
+        }
+
+        // The root node is stored inline in the tree, every other node is
+        // allocated on its own.
+        stats.heap_bytes = (stats.nodes() - 1) * mem::size_of::<Node<K, B, A, L>>();
+        stats
+    }
+
+    /// Returns the number of bytes the tree has allocated: every node but the
+    /// root, which is stored inline, the key storage of layouts which keep
+    /// the keys outside of their node, and the nodes reserved for upcoming
+    /// insertions. Memory owned by the keys themselves is not included.
+    pub fn heap_size(&self) -> usize {
+        let node_size = mem::size_of::<Node<K, B, A, L>>();
+        let mut bytes = self.spare.capacity() * mem::size_of::<Link<K, B, A, L>>()
+            + self
+                .spare
+                .iter()
+                .map(|node| node_size + node.keys.heap_size())
+                .sum::<usize>();
+
+        let Some(root) = self.root.as_ref() else {
+            return bytes;
+        };
+        let mut stack = vec![&root.node];
+        while let Some(node) = stack.pop() {
+            bytes += node.keys.heap_size();
+            for child in node.children.iter() {
+                bytes += node_size;
+                stack.push(child);
+            }
+        }
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Global;
+    use super::*;
+    use crate::BTreeSet;
+
+    #[test]
+    fn test_stats_of_empty_tree() {
+        let tree = SimpleBTreeSet::<usize, 2>::new();
+        let stats = tree.stats();
+
+        assert_eq!(stats.height, 0);
+        assert_eq!(stats.nodes(), 0);
+        assert_eq!(stats.keys, 0);
+        assert_eq!(stats.occupancy, vec![0; 4]);
+        assert_eq!(stats.heap_bytes, 0);
+        assert_eq!(stats.average_occupancy(), 0.0);
+    }
+
+    #[test]
+    fn test_stats_add_up() {
+        let mut tree = SimpleBTreeSet::<usize, 2>::new();
+        for i in 0..5000 {
+            tree.insert((i * 7919) % 5003).unwrap();
+        }
+        for key in (0..5003).step_by(3) {
+            let _ = tree.remove(&key);
+        }
+
+        let stats = tree.stats();
+        assert_eq!(stats.keys, tree.iter().count());
+        assert_eq!(stats.nodes(), stats.occupancy.iter().sum::<usize>());
+        assert_eq!(
+            stats.keys,
+            stats
+                .occupancy
+                .iter()
+                .enumerate()
+                .map(|(keys, nodes)| keys * nodes)
+                .sum::<usize>()
+        );
+
+        let mut height = 1;
+        let mut node = &tree.root.as_ref().unwrap().node;
+        while !node.is_leaf {
+            node = &node.children[0];
+            height += 1;
+        }
+        assert_eq!(stats.height, height);
+        assert_eq!(
+            stats.heap_bytes,
+            (stats.nodes() - 1) * mem::size_of::<Node<usize, 2, Global>>()
+        );
+
+        // Every node but the root is at least a third full with B = 2.
+        let fill = stats.fill_factor();
+        assert!((1.0 / 3.0..=1.0).contains(&fill), "fill factor {fill}");
+    }
+}
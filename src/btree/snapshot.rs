@@ -0,0 +1,109 @@
+use super::persistent::{self, PersistentBTreeSet};
+use super::{Allocator, SimpleBTreeSet};
+use crate::{BTreeSet, Result};
+
+/// An immutable view of a tree at the moment it was taken.
+///
+/// A snapshot shares its nodes copy-on-write, so cloning it is cheap, and
+/// later changes to the tree it was taken from are never observed.
+pub struct Snapshot<K, const B: usize = 6> {
+    tree: PersistentBTreeSet<K, B>,
+}
+
+impl<K, const B: usize> Clone for Snapshot<K, B> {
+    fn clone(&self) -> Self {
+        Snapshot {
+            tree: self.tree.clone(),
+        }
+    }
+}
+
+impl<K: Ord + Clone, const B: usize> Snapshot<K, B> {
+    pub fn search(&self, key: &K) -> Result<&K> {
+        self.tree.search(key)
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.tree.contains(key)
+    }
+}
+
+impl<K, const B: usize> Snapshot<K, B> {
+    /// Returns an iterator over the keys of the snapshot, in ascending order.
+    pub fn iter(&self) -> persistent::Iter<'_, K, B> {
+        self.tree.iter()
+    }
+}
+
+impl<'a, K, const B: usize> IntoIterator for &'a Snapshot<K, B> {
+    type Item = &'a K;
+    type IntoIter = persistent::Iter<'a, K, B>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K, const B: usize> PersistentBTreeSet<K, B> {
+    /// Takes a snapshot of the tree, which shares every node with it.
+    pub fn snapshot(&self) -> Snapshot<K, B> {
+        Snapshot { tree: self.clone() }
+    }
+}
+
+impl<K: Ord + Clone, const B: usize, A: Allocator + Clone> SimpleBTreeSet<K, B, A> {
+    /// Copies the keys of the tree into a new `PersistentBTreeSet`.
+    ///
+    /// The nodes of this tree are owned rather than shared, so this takes
+    /// time linear in the size of the tree. Snapshots of the copy are cheap.
+    pub fn to_persistent(&self) -> PersistentBTreeSet<K, B> {
+        self.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_does_not_observe_later_changes() {
+        let mut tree = PersistentBTreeSet::<usize, 2>::new();
+        for key in 0..300 {
+            tree.insert(key).unwrap();
+        }
+
+        let snapshot = tree.snapshot();
+        for key in 0..150 {
+            tree.remove(&(key * 2)).unwrap();
+        }
+        for key in 300..400 {
+            tree.insert(key).unwrap();
+        }
+
+        assert!(snapshot.iter().copied().eq(0..300));
+        assert!(snapshot.clone().iter().copied().eq(0..300));
+        assert!(
+            tree.iter()
+                .copied()
+                .eq((0..150).map(|i| i * 2 + 1).chain(300..400))
+        );
+    }
+
+    #[test]
+    fn test_snapshot_of_copied_simple_tree_does_not_observe_later_changes() {
+        let mut tree = SimpleBTreeSet::<usize, 2>::new();
+        for key in 0..300 {
+            tree.insert(key).unwrap();
+        }
+
+        let snapshot = tree.to_persistent().snapshot();
+        let copy = snapshot.clone();
+        for key in 0..300 {
+            tree.remove(&key).unwrap();
+        }
+
+        assert!(snapshot.iter().copied().eq(0..300));
+        assert!(copy.contains(&299));
+        assert!(!tree.contains(&299));
+    }
+}
@@ -0,0 +1,56 @@
+//! The synchronization primitives of the concurrent trees. When built with
+//! `--cfg loom`, they are swapped for the ones of loom, which model checks
+//! every interleaving of the threads using them.
+
+#[cfg(loom)]
+pub(super) use loom::hint::spin_loop;
+#[cfg(loom)]
+pub(super) use loom::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering, fence};
+#[cfg(loom)]
+pub(super) use loom::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(not(loom))]
+pub(super) use std::hint::spin_loop;
+#[cfg(not(loom))]
+pub(super) use std::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering, fence};
+#[cfg(not(loom))]
+pub(super) use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+#[cfg(all(feature = "shared", not(loom)))]
+pub(super) use arc_swap::ArcSwap;
+
+/// A stand-in for `ArcSwap`, whose atomics loom cannot see. The current
+/// value is swapped under a lock, so loom interleaves the compare-and-swap
+/// of `rcu` with the loads of other threads.
+#[cfg(all(feature = "shared", loom))]
+pub(super) struct ArcSwap<T>(RwLock<std::sync::Arc<T>>);
+
+#[cfg(all(feature = "shared", loom))]
+impl<T> ArcSwap<T> {
+    pub(super) fn from_pointee(value: T) -> Self {
+        ArcSwap(RwLock::new(std::sync::Arc::new(value)))
+    }
+
+    pub(super) fn load(&self) -> std::sync::Arc<T> {
+        self.load_full()
+    }
+
+    pub(super) fn load_full(&self) -> std::sync::Arc<T> {
+        std::sync::Arc::clone(&self.0.read().unwrap())
+    }
+
+    /// Replaces the current value with the one computed from it, computing
+    /// it again if another thread replaced the value in the meantime.
+    pub(super) fn rcu(
+        &self,
+        mut f: impl FnMut(&std::sync::Arc<T>) -> std::sync::Arc<T>,
+    ) -> std::sync::Arc<T> {
+        loop {
+            let current = self.load_full();
+            let new = f(&current);
+            let mut guard = self.0.write().unwrap();
+            if std::sync::Arc::ptr_eq(&guard, &current) {
+                return std::mem::replace(&mut *guard, new);
+            }
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use super::{Database, Entry, log_path};
+use crate::btree::{DiskSnapshot, write_free_list};
+use crate::storage::{FileHeader, FilePager, PageId, PageKey, Pager};
+use crate::{Context, Result};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::ErrorKind;
+use std::iter;
+use std::path::Path;
+
+/// A copy of a database into another file, made a few pages at a time.
+///
+/// The backup copies the database as it was when the backup started, so the
+/// database can be written to between the steps: the pages of the copied
+/// state are kept until the backup is finished or dropped. Only the pages in
+/// use are copied, each into the page of the same number, and the pages in
+/// between are listed as free in the copy, to be reused by its writes.
+pub struct Backup {
+    snapshot: DiskSnapshot,
+    pager: FilePager,
+    /// The pages left to copy, and whether they belong to the catalog.
+    pending: Vec<(PageId, bool)>,
+    copied: HashSet<PageId>,
+}
+
+impl Database {
+    /// Copies the database into a new file at the given path, replacing
+    /// whatever is there. The copy can be opened like any other database.
+    pub fn backup_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut backup = self.backup(path)?;
+        while !backup.step(self, usize::MAX)? {}
+        Ok(())
+    }
+
+    /// Starts copying the database into a new file at the given path, which
+    /// is done by `Backup::step`.
+    pub fn backup(&self, path: impl AsRef<Path>) -> Result<Backup> {
+        let path = path.as_ref();
+        self.start_backup(path).with_key("Database::backup", path)
+    }
+
+    fn start_backup(&self, path: &Path) -> Result<Backup> {
+        File::create(path)?;
+        match fs::remove_file(log_path(path)) {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+
+        let mut pager = FilePager::open(path, super::PAGE_SIZE)?;
+        pager.allocate()?;
+
+        let snapshot = self.snapshot();
+        let pending = snapshot
+            .root()
+            .map(|root| (root, true))
+            .into_iter()
+            .collect();
+        Ok(Backup {
+            snapshot,
+            pager,
+            pending,
+            copied: HashSet::new(),
+        })
+    }
+}
+
+impl Backup {
+    /// Copies up to the given number of pages from the database, which must
+    /// be the one the backup was started from. Returns whether the backup is
+    /// finished, in which case the copy is complete and durable.
+    pub fn step(&mut self, db: &Database, pages: usize) -> Result<bool> {
+        self.copy_pages(db, pages).context("Backup::step")
+    }
+
+    fn copy_pages(&mut self, db: &Database, pages: usize) -> Result<bool> {
+        self.snapshot.check(&db.tree);
+
+        for _ in 0..pages {
+            let Some((id, catalog)) = self.pending.pop() else {
+                break;
+            };
+
+            // The overflow pages of the entries are copied along with their
+            // node.
+            let (node, overflow) = db.tree.read_with_overflow(id)?;
+            for id in iter::once(id).chain(overflow) {
+                while self.pager.page_count() <= id {
+                    self.pager.allocate()?;
+                }
+                self.pager.write_page(id, &db.tree.pager().view_page(id)?)?;
+                self.copied.insert(id);
+            }
+
+            self.pending
+                .extend(node.children.iter().map(|&child| (child, catalog)));
+            if catalog {
+                let roots = node.keys.iter().filter_map(Entry::root);
+                self.pending.extend(roots.map(|root| (root, false)));
+            }
+        }
+
+        if !self.pending.is_empty() {
+            return Ok(false);
+        }
+        self.finish()?;
+        Ok(true)
+    }
+
+    /// Returns the number of pages known to be left to copy. More are found
+    /// as the copy goes on.
+    pub fn remaining(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let free: Vec<_> = (1..self.pager.page_count())
+            .filter(|id| !self.copied.contains(id))
+            .collect();
+        let free_list = write_free_list(&mut self.pager, &free)?;
+
+        let header = FileHeader {
+            page_size: self.pager.page_size() as u32,
+            key_size: Entry::SIZE.map_or(0, |size| size as u32),
+            root: self.snapshot.root(),
+            page_count: self.pager.page_count(),
+            free_list,
+        };
+        let mut page = vec![0; self.pager.page_size()];
+        header.encode(&mut page);
+        self.pager.write_page(0, &page)?;
+        self.pager.sync()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{remove, temp_path};
+    use super::*;
+
+    #[test]
+    fn test_backup_while_writing_copies_the_starting_state() {
+        let path = temp_path("backup-source");
+        let copy = temp_path("backup-copy");
+        let mut db = Database::open(&path).unwrap();
+        // Leave free pages among the copied ones, which the copy skips.
+        let mut scratch = db.create_bucket(b"scratch").unwrap();
+        for i in 0..500u32 {
+            scratch.put(&i.to_be_bytes(), b"scratch").unwrap();
+        }
+        for i in 0..2000u32 {
+            db.put(&i.to_be_bytes(), b"old").unwrap();
+        }
+        let mut bucket = db.create_bucket(b"bucket").unwrap();
+        for i in 0..500u32 {
+            bucket.put(&i.to_be_bytes(), b"old").unwrap();
+        }
+        // Values larger than a page are copied along with their overflow
+        // pages.
+        for i in 0..5u8 {
+            bucket.put(&[b'x', i], &vec![i; 50_000]).unwrap();
+        }
+
+        db.delete_bucket(b"scratch").unwrap();
+        assert!(db.file_stats().free_pages > 0);
+
+        let mut backup = db.backup(&copy).unwrap();
+        let mut round = 0u32;
+        while !backup.step(&db, 3).unwrap() {
+            // Rewrite, delete, and add entries between the steps.
+            for i in (round * 40..(round + 1) * 40).map(|i| i % 2000) {
+                db.put(&i.to_be_bytes(), b"new").unwrap();
+            }
+            let _ = db.delete(&(round * 7 % 2000).to_be_bytes());
+            db.put(&(10_000 + round).to_be_bytes(), b"new").unwrap();
+            db.delete_bucket(b"bucket").unwrap_or(());
+            round += 1;
+        }
+        assert!(round > 10);
+        let backup_pages = backup.copied.len();
+        drop(backup);
+        assert_eq!(db.get(&10_000u32.to_be_bytes()).unwrap(), b"new");
+
+        let mut restored = Database::open(&copy).unwrap();
+        // The pages skipped by the copy are free, and nothing else is.
+        let stats = restored.file_stats();
+        assert!(stats.free_pages > 0);
+        assert_eq!(stats.used_pages() as usize, backup_pages + 1);
+        let entries = restored.range(..).unwrap();
+        assert_eq!(entries.len(), 2000);
+        assert!(entries.iter().all(|&(_, value)| value == b"old"));
+        let bucket = restored.bucket(b"bucket").unwrap();
+        assert_eq!(bucket.range(..).unwrap().len(), 505);
+        for i in 0..5u8 {
+            assert_eq!(bucket.get(&[b'x', i]).unwrap(), vec![i; 50_000]);
+        }
+
+        // The copy is a database of its own, which reuses the skipped pages.
+        restored.put(b"key", b"value").unwrap();
+        assert_eq!(restored.get(b"key").unwrap(), b"value");
+        assert_eq!(restored.file_stats().total_pages, stats.total_pages);
+
+        drop(db);
+        drop(restored);
+        remove(&path);
+        remove(&copy);
+    }
+}
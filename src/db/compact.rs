@@ -0,0 +1,171 @@
+use super::{B, Database, Entry, PAGE_SIZE};
+use crate::btree::DiskBTreeSet;
+use crate::storage::FilePager;
+use crate::{Context, Result};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+impl Database {
+    /// Rewrites the database into a fresh file without free pages, and
+    /// replaces the file with it.
+    ///
+    /// Every node of the new file is as full as the tree allows, and the
+    /// leaves of every bucket follow each other in key order, so scans read
+    /// the file sequentially. The new file is written next to the old one,
+    /// and renamed over it once it is complete, so a crash leaves either
+    /// file in place. Snapshots and backups taken before cannot be used with
+    /// the compacted database.
+    pub fn compact(&mut self) -> Result<()> {
+        self.compact_file().context("Database::compact")
+    }
+
+    fn compact_file(&mut self) -> Result<()> {
+        self.tree.sync()?;
+        let path = compact_path(&self.path);
+        self.write_compacted(&path)?;
+
+        // The log only holds pages of the old file, so it is emptied before
+        // the new file takes the place of the old one.
+        self.tree.pager_mut().inner_mut()?.checkpoint()?;
+        fs::rename(&path, &self.path)?;
+        sync_dir(&self.path)?;
+
+        *self = Database::open_with_cache(&self.path, self.budget, self.eviction)?;
+        Ok(())
+    }
+
+    /// Writes the buckets, and then the catalog, into a new file at the given
+    /// path, packing each of them with `bulk_load_at`.
+    fn write_compacted(&self, path: &Path) -> Result<()> {
+        File::create(path)?;
+        let mut tree = Tree::open(FilePager::open(path, PAGE_SIZE)?)?;
+
+        let mut catalog = Vec::new();
+        for bucket in self.tree.range_at(self.tree.root(), ..)? {
+            let entries = self.tree.range_at(bucket.root(), ..)?;
+            let mut root = None;
+            tree.bulk_load_at(&mut root, entries.into_iter().cloned().collect())?;
+            catalog.push(Entry::bucket(&bucket.key, root)?);
+        }
+        let mut root = None;
+        tree.bulk_load_at(&mut root, catalog)?;
+        tree.commit_with_root(root)?;
+        tree.sync()
+    }
+}
+
+/// The tree the compacted file is written through, without the log and the
+/// cache of the database, since the file only replaces the old one once it
+/// is complete.
+type Tree = DiskBTreeSet<Entry, B, FilePager>;
+
+/// Returns the path the compacted file is written to, before it replaces the
+/// file at the given path.
+fn compact_path(path: &Path) -> PathBuf {
+    let mut compact = path.as_os_str().to_owned();
+    compact.push("-compact");
+    PathBuf::from(compact)
+}
+
+/// Makes the renaming of a file in the directory at the given path durable.
+fn sync_dir(path: &Path) -> Result<()> {
+    if cfg!(unix)
+        && let Some(dir) = path.parent()
+    {
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{remove, temp_path};
+    use super::*;
+
+    #[test]
+    fn test_compact_reclaims_free_pages() {
+        let path = temp_path("compact");
+        let mut db = Database::open(&path).unwrap();
+        for i in 0..2000u32 {
+            db.put(&i.to_be_bytes(), &[i as u8; 100]).unwrap();
+        }
+        let mut bucket = db.create_bucket(b"bucket").unwrap();
+        for i in 0..300u32 {
+            bucket.put(&i.to_be_bytes(), b"value").unwrap();
+        }
+        for i in (0..2000u32).filter(|i| i % 10 != 0) {
+            db.delete(&i.to_be_bytes()).unwrap();
+        }
+        let before = db.file_stats();
+        assert!(before.free_pages > 0);
+
+        db.compact().unwrap();
+        let after = db.file_stats();
+        assert_eq!(after.free_pages, 0);
+        assert!(after.total_pages < before.used_pages());
+        assert_eq!(
+            fs::metadata(&path).unwrap().len(),
+            after.total_pages * PAGE_SIZE as u64
+        );
+        assert!(!compact_path(&path).exists());
+
+        let check =
+            |db: &mut Database| {
+                let entries = db.range(..).unwrap();
+                assert_eq!(entries.len(), 200);
+                assert!(entries.iter().zip((0..2000u32).step_by(10)).all(
+                |(&(key, value), i)| key == i.to_be_bytes() && value == [i as u8; 100]
+            ));
+                let bucket = db.bucket(b"bucket").unwrap();
+                assert_eq!(bucket.range(..).unwrap().len(), 300);
+            };
+        check(&mut db);
+
+        // The compacted database is written to, and reopened, like any other.
+        db.put(b"key", b"value").unwrap();
+        drop(db);
+        let mut db = Database::open(&path).unwrap();
+        assert_eq!(db.get(b"key").unwrap(), b"value");
+        db.delete(b"key").unwrap();
+        check(&mut db);
+
+        drop(db);
+        remove(&path);
+    }
+
+    #[test]
+    fn test_packed_nodes_are_at_least_half_full() {
+        let path = temp_path("compact-packed");
+        let entries: Vec<_> = (0..1000u32)
+            .map(|i| Entry::new(&i.to_be_bytes(), b"").unwrap())
+            .collect();
+        for len in [1, 15, 16, 31, 32, 241, 1000] {
+            File::create(&path).unwrap();
+            let mut tree = Tree::open(FilePager::open(&path, PAGE_SIZE).unwrap()).unwrap();
+            let mut root = None;
+            tree.bulk_load_at(&mut root, entries[..len].to_vec())
+                .unwrap();
+
+            let mut keys = Vec::new();
+            let mut pending = vec![(root.unwrap(), true)];
+            while let Some((id, is_root)) = pending.pop() {
+                let node = tree.load(id).unwrap();
+                assert!(node.keys.len() < 2 * B);
+                assert!(is_root || node.keys.len() >= B - 1);
+                keys.extend(node.keys.iter().map(|entry| entry.key.clone()));
+                pending.extend(node.children.iter().map(|&child| (child, false)));
+            }
+            keys.sort();
+            assert!(
+                keys.iter()
+                    .eq(entries[..len].iter().map(|entry| &entry.key))
+            );
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,977 @@
+//! A key-value store over a single file, built from the disk tree.
+//!
+//! `Database` hides the pieces of the storage stack behind a small map-like
+//! interface of byte keys and values: the entries live in a `DiskBTreeSet`,
+//! whose pages go through a `CachedPager` and a `WalPager` in front of a
+//! `FilePager`, and whose nodes are kept in memory once they are decoded.
+//!
+//! A database holds several independent key-value trees, called buckets,
+//! which are addressed by name. Every bucket has its own root page, and the
+//! roots are kept in one more tree, the catalog, whose root is stored in the
+//! file header. The buckets share the file, the pager, and the cache.
+//!
+//! Reads can be isolated from writes with a snapshot of the catalog, which
+//! keeps every bucket as it was when the snapshot was taken, and is read
+//! through a `View`. A snapshot is also what `backup_to` copies, so a backup
+//! can be taken in steps, with writes in between. Snapshots are read through
+//! the database, so reads and writes still take turns on one thread: the
+//! log keeps the committed pages in memory until it is checkpointed, so the
+//! pages cannot be read past it the way a `DiskBTreeSet::reader` reads the
+//! pages of a `FilePager` from other threads.
+//!
+//! The pages freed by deletions are reused by later writes, but the file
+//! never shrinks by itself: `compact` rewrites it without the free pages.
+
+use crate::btree::{BatchOp, DiskBTreeSet, DiskSnapshot, FileStats, Prefixed};
+use crate::storage::{
+    CacheStats, CachedPager, DEFAULT_CACHE_BUDGET, Eviction, FilePager, PageId, PageKey, WalPager,
+};
+use crate::{Context, Error, Result};
+use std::cmp::Ordering;
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+
+mod backup;
+mod compact;
+
+pub use backup::Backup;
+
+/// The size of the pages of a database file.
+pub const PAGE_SIZE: usize = 16384;
+
+/// The maximum length of a key and its value together, in bytes. The pages
+/// store the length of an entry in four bytes, and an entry holds four more
+/// bytes of its own, the length of its key.
+pub const MAX_ENTRY_SIZE: usize = u32::MAX as usize - 4;
+
+/// The branching factor of the tree. Its nodes hold up to 15 entries, so an
+/// entry of up to a kilobyte is stored in its node, and only the rest of a
+/// larger one in overflow pages.
+const B: usize = 8;
+
+/// A key-value store of byte strings, kept in a file, with keys ordered like
+/// `memcmp`.
+///
+/// The methods of the database itself work on the default bucket, whose
+/// name is empty, and the other buckets are reached through `bucket`.
+///
+/// Every `put` and `delete` is committed to the write-ahead log as a whole,
+/// together with the new root of its bucket, so a crash never leaves one half
+/// applied, and `sync` makes the committed writes durable. The log is kept
+/// next to the file, in a file of the same name ending in `-wal`.
+///
+/// Entries take up as much of their page as they need, and the entries of a
+/// node store the prefix their keys share once. Keys and values may be
+/// larger than a page, up to `MAX_ENTRY_SIZE` bytes together.
+pub struct Database {
+    tree: Tree,
+    path: PathBuf,
+    budget: usize,
+    eviction: Eviction,
+}
+
+/// The tree of the catalog, whose root is the one in the header. The trees
+/// of the buckets live in the same pages.
+type Tree = DiskBTreeSet<Entry, B, CachedPager<WalPager<FilePager>>>;
+
+/// The name of the default bucket.
+const DEFAULT_BUCKET: &[u8] = b"";
+
+impl Database {
+    /// Opens the database stored in the file at the given path, creating it
+    /// if it does not exist. Writes committed to the log before a crash are
+    /// recovered.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open_with_cache(path, DEFAULT_CACHE_BUDGET, Eviction::Lru)
+    }
+
+    /// Opens the database like `open`, with a page cache of the given memory
+    /// budget, in bytes, and eviction policy.
+    pub fn open_with_cache(
+        path: impl AsRef<Path>,
+        budget: usize,
+        eviction: Eviction,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        Self::open_file(path, budget, eviction).with_key("Database::open", path)
+    }
+
+    fn open_file(path: &Path, budget: usize, eviction: Eviction) -> Result<Self> {
+        let pager = FilePager::open(path, PAGE_SIZE)?;
+        let pager = WalPager::open(pager, log_path(path))?;
+        let pager = CachedPager::new(pager, budget, eviction);
+
+        let mut db = Database {
+            tree: DiskBTreeSet::open(pager)?,
+            path: path.to_owned(),
+            budget,
+            eviction,
+        };
+        if let Err(Error::BucketNotFound) = db.root(db.tree.root(), DEFAULT_BUCKET) {
+            db.create_bucket(DEFAULT_BUCKET)?;
+        }
+        Ok(db)
+    }
+
+    /// Returns the value stored under the key.
+    pub fn get(&self, key: &[u8]) -> Result<&[u8]> {
+        self.get_in(self.tree.root(), DEFAULT_BUCKET, key)
+    }
+
+    /// Stores the value under the key, and returns the value it replaced, if
+    /// there was one.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.put_in(DEFAULT_BUCKET, key, value)
+    }
+
+    /// Removes the key, and returns the value stored under it.
+    pub fn delete(&mut self, key: &[u8]) -> Result<Vec<u8>> {
+        self.delete_in(DEFAULT_BUCKET, key)
+    }
+
+    /// Applies every write of the batch, sorted by key, and commits them to
+    /// the write-ahead log together, so either all of them survive a crash or
+    /// none do. Each write descends the tree on its own; what the batch saves
+    /// is the commit of every write but the last. Writes to the same key are
+    /// applied in the order they appear in the batch. Nothing is written if
+    /// any entry is too large.
+    pub fn apply_batch(&mut self, batch: Vec<BatchOp<Vec<u8>, Vec<u8>>>) -> Result<()> {
+        self.apply_batch_in(DEFAULT_BUCKET, batch)
+    }
+
+    /// Stores `new` under the key if the value stored under it equals
+    /// `expected`, and returns the value it replaced. Otherwise nothing
+    /// changes, and the current value is returned as the inner error. The
+    /// comparison and the write are committed together.
+    pub fn compare_and_swap(
+        &mut self,
+        key: &[u8],
+        expected: &[u8],
+        new: &[u8],
+    ) -> Result<std::result::Result<Vec<u8>, Vec<u8>>> {
+        self.compare_and_swap_in(DEFAULT_BUCKET, key, expected, new)
+    }
+
+    /// Returns the keys within the range, and their values, in ascending
+    /// order of the keys.
+    pub fn range<'k>(&self, range: impl RangeBounds<&'k [u8]>) -> Result<Vec<(&[u8], &[u8])>> {
+        self.range_in(self.tree.root(), DEFAULT_BUCKET, range)
+    }
+
+    /// Returns the keys which start with the prefix, and their values, in
+    /// ascending order of the keys.
+    pub fn prefix(&self, prefix: &[u8]) -> Result<Vec<(&[u8], &[u8])>> {
+        self.prefix_in(self.tree.root(), DEFAULT_BUCKET, prefix)
+    }
+
+    /// Takes a snapshot of every bucket of the database, as of the last
+    /// write. The pages the snapshot reads are kept until it is dropped, and
+    /// are reused by the writes after that.
+    pub fn snapshot(&self) -> DiskSnapshot {
+        self.tree.snapshot()
+    }
+
+    /// Returns the default bucket as of the snapshot, which must have been
+    /// taken of this database.
+    pub fn view<'a>(&'a self, snapshot: &DiskSnapshot) -> View<'a> {
+        snapshot.check(&self.tree);
+        View {
+            db: self,
+            catalog: snapshot.root(),
+            name: DEFAULT_BUCKET.to_vec(),
+        }
+    }
+
+    /// Makes sure every committed write is durable.
+    pub fn sync(&mut self) -> Result<()> {
+        self.tree.sync()
+    }
+
+    /// Returns the counters of the page cache.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.tree.pager().stats()
+    }
+
+    /// Returns the number of pages of the file, and how many of them are
+    /// free to be reused.
+    pub fn file_stats(&self) -> FileStats {
+        self.tree.file_stats()
+    }
+
+    /// Creates an empty bucket with the given name, and returns it. Bucket
+    /// names are stored like keys.
+    pub fn create_bucket(&mut self, name: &[u8]) -> Result<Bucket<'_>> {
+        let mut catalog = self.tree.root();
+        match self
+            .tree
+            .insert_at(&mut catalog, Entry::bucket(name, None)?)
+        {
+            Err(Error::KeyAlreadyExists) => return Err(Error::BucketAlreadyExists),
+            result => result?,
+        }
+        self.tree.commit_with_root(catalog)?;
+        Ok(Bucket {
+            db: self,
+            name: name.to_vec(),
+        })
+    }
+
+    /// Returns the bucket with the given name.
+    pub fn bucket(&mut self, name: &[u8]) -> Result<Bucket<'_>> {
+        self.root(self.tree.root(), name)?;
+        Ok(Bucket {
+            db: self,
+            name: name.to_vec(),
+        })
+    }
+
+    /// Removes the bucket with the given name, and frees the pages of its
+    /// keys. The default bucket is only emptied, and cannot be removed.
+    pub fn delete_bucket(&mut self, name: &[u8]) -> Result<()> {
+        let mut root = self.root(self.tree.root(), name)?;
+        self.tree.clear_at(&mut root)?;
+
+        let mut catalog = self.tree.root();
+        if name == DEFAULT_BUCKET {
+            self.tree
+                .replace_at(&mut catalog, Entry::bucket(name, None)?)?;
+        } else {
+            self.tree.remove_at(&mut catalog, &Entry::probe(name)?)?;
+        }
+        self.tree.commit_with_root(catalog)
+    }
+
+    /// Returns the names of the buckets, other than the default one, in
+    /// ascending order.
+    pub fn buckets(&self) -> Result<Vec<&[u8]>> {
+        let range = (
+            Bound::Excluded(Entry::bound(DEFAULT_BUCKET)),
+            Bound::Unbounded,
+        );
+        let entries = self.tree.range_at(self.tree.root(), range)?;
+        Ok(entries.into_iter().map(|entry| &entry.key[..]).collect())
+    }
+
+    /// Returns the root of the bucket with the given name, in the catalog
+    /// with the given root.
+    fn root(&self, catalog: Option<PageId>, name: &[u8]) -> Result<Option<PageId>> {
+        let entry = match self.tree.search_at(catalog, &Entry::probe(name)?) {
+            Err(Error::KeyNotFound) => return Err(Error::BucketNotFound),
+            entry => entry?,
+        };
+        Ok(entry.root())
+    }
+
+    /// Runs the mutation against the tree of the bucket, and commits it,
+    /// together with the new root of the bucket if it changed.
+    fn update<T>(
+        &mut self,
+        name: &[u8],
+        mutation: impl FnOnce(&mut Tree, &mut Option<PageId>) -> Result<T>,
+    ) -> Result<T> {
+        let mut root = self.root(self.tree.root(), name)?;
+        let old = root;
+        let result = mutation(&mut self.tree, &mut root)?;
+
+        let mut catalog = self.tree.root();
+        if root != old {
+            self.tree
+                .replace_at(&mut catalog, Entry::bucket(name, root)?)?;
+        }
+        self.tree.commit_with_root(catalog)?;
+        Ok(result)
+    }
+
+    fn get_in(&self, catalog: Option<PageId>, name: &[u8], key: &[u8]) -> Result<&[u8]> {
+        let entry = self
+            .tree
+            .search_at(self.root(catalog, name)?, &Entry::probe(key)?)?;
+        Ok(&entry.value)
+    }
+
+    fn put_in(&mut self, name: &[u8], key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+        let entry = Entry::new(key, value)?;
+        let replaced = self.update(name, |tree, root| tree.replace_at(root, entry))?;
+        Ok(replaced.map(|replaced| replaced.value))
+    }
+
+    fn delete_in(&mut self, name: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+        let probe = Entry::probe(key)?;
+        let entry = self.update(name, |tree, root| tree.remove_at(root, &probe))?;
+        Ok(entry.value)
+    }
+
+    fn apply_batch_in(
+        &mut self,
+        name: &[u8],
+        mut batch: Vec<BatchOp<Vec<u8>, Vec<u8>>>,
+    ) -> Result<()> {
+        batch.sort_by(|a, b| a.key().cmp(b.key()));
+        let writes = batch
+            .into_iter()
+            .map(|op| match op {
+                BatchOp::Insert(key, value) => Ok((Entry::new(&key, &value)?, true)),
+                BatchOp::Remove(key) => Ok((Entry::probe(&key)?, false)),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        self.update(name, |tree, root| {
+            for (entry, insert) in writes {
+                if insert {
+                    tree.replace_at(root, entry)?;
+                    continue;
+                }
+                match tree.remove_at(root, &entry) {
+                    Ok(_) | Err(Error::KeyNotFound) => {}
+                    Err(err) => return Err(err),
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn compare_and_swap_in(
+        &mut self,
+        name: &[u8],
+        key: &[u8],
+        expected: &[u8],
+        new: &[u8],
+    ) -> Result<std::result::Result<Vec<u8>, Vec<u8>>> {
+        let entry = Entry::new(key, new)?;
+        self.update(name, |tree, root| {
+            let current = tree.search_at(*root, &entry)?;
+            if current.value != expected {
+                return Ok(Err(current.value.clone()));
+            }
+            let replaced = tree.replace_at(root, entry)?;
+            Ok(Ok(replaced.expect("compared entry is in the tree").value))
+        })
+    }
+
+    fn range_in<'k>(
+        &self,
+        catalog: Option<PageId>,
+        name: &[u8],
+        range: impl RangeBounds<&'k [u8]>,
+    ) -> Result<Vec<(&[u8], &[u8])>> {
+        let bound = |bound: Bound<&&[u8]>| bound.map(|key| Entry::bound(key));
+        let range = (bound(range.start_bound()), bound(range.end_bound()));
+
+        let entries = self.tree.range_at(self.root(catalog, name)?, range)?;
+        Ok(entries
+            .into_iter()
+            .map(|entry| (&entry.key[..], &entry.value[..]))
+            .collect())
+    }
+
+    fn prefix_in(
+        &self,
+        catalog: Option<PageId>,
+        name: &[u8],
+        prefix: &[u8],
+    ) -> Result<Vec<(&[u8], &[u8])>> {
+        let (start, end) = Vec::<u8>::prefix_range(prefix);
+        let range = (
+            start.as_ref().map(Vec::as_slice),
+            end.as_ref().map(Vec::as_slice),
+        );
+        self.range_in(catalog, name, range)
+    }
+}
+
+/// A bucket of a database: a key-value store of its own, kept in the same
+/// file as the other buckets.
+pub struct Bucket<'a> {
+    db: &'a mut Database,
+    name: Vec<u8>,
+}
+
+impl Bucket<'_> {
+    /// Returns the name of the bucket.
+    pub fn name(&self) -> &[u8] {
+        &self.name
+    }
+
+    /// Returns the value stored under the key.
+    pub fn get(&self, key: &[u8]) -> Result<&[u8]> {
+        self.db.get_in(self.db.tree.root(), &self.name, key)
+    }
+
+    /// Stores the value under the key, and returns the value it replaced, if
+    /// there was one.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.db.put_in(&self.name, key, value)
+    }
+
+    /// Removes the key, and returns the value stored under it.
+    pub fn delete(&mut self, key: &[u8]) -> Result<Vec<u8>> {
+        self.db.delete_in(&self.name, key)
+    }
+
+    /// Applies every write of the batch atomically, like
+    /// `Database::apply_batch`.
+    pub fn apply_batch(&mut self, batch: Vec<BatchOp<Vec<u8>, Vec<u8>>>) -> Result<()> {
+        self.db.apply_batch_in(&self.name, batch)
+    }
+
+    /// Stores `new` under the key if the value stored under it equals
+    /// `expected`, like `Database::compare_and_swap`.
+    pub fn compare_and_swap(
+        &mut self,
+        key: &[u8],
+        expected: &[u8],
+        new: &[u8],
+    ) -> Result<std::result::Result<Vec<u8>, Vec<u8>>> {
+        self.db.compare_and_swap_in(&self.name, key, expected, new)
+    }
+
+    /// Returns the keys within the range, and their values, in ascending
+    /// order of the keys.
+    pub fn range<'k>(&self, range: impl RangeBounds<&'k [u8]>) -> Result<Vec<(&[u8], &[u8])>> {
+        self.db.range_in(self.db.tree.root(), &self.name, range)
+    }
+
+    /// Returns the keys which start with the prefix, and their values, in
+    /// ascending order of the keys.
+    pub fn prefix(&self, prefix: &[u8]) -> Result<Vec<(&[u8], &[u8])>> {
+        self.db.prefix_in(self.db.tree.root(), &self.name, prefix)
+    }
+}
+
+/// A bucket of a database as of a snapshot. The writes made after the
+/// snapshot was taken are not seen through the view.
+pub struct View<'a> {
+    db: &'a Database,
+    catalog: Option<PageId>,
+    name: Vec<u8>,
+}
+
+impl<'a> View<'a> {
+    /// Returns the name of the bucket.
+    pub fn name(&self) -> &[u8] {
+        &self.name
+    }
+
+    /// Returns the bucket with the given name, as of the same snapshot.
+    pub fn bucket(&self, name: &[u8]) -> Result<View<'a>> {
+        self.db.root(self.catalog, name)?;
+        Ok(View {
+            db: self.db,
+            catalog: self.catalog,
+            name: name.to_vec(),
+        })
+    }
+
+    /// Returns the value stored under the key.
+    pub fn get(&self, key: &[u8]) -> Result<&'a [u8]> {
+        self.db.get_in(self.catalog, &self.name, key)
+    }
+
+    /// Returns the keys within the range, and their values, in ascending
+    /// order of the keys.
+    pub fn range<'k>(
+        &self,
+        range: impl RangeBounds<&'k [u8]>,
+    ) -> Result<Vec<(&'a [u8], &'a [u8])>> {
+        self.db.range_in(self.catalog, &self.name, range)
+    }
+
+    /// Returns the keys which start with the prefix, and their values, in
+    /// ascending order of the keys.
+    pub fn prefix(&self, prefix: &[u8]) -> Result<Vec<(&'a [u8], &'a [u8])>> {
+        self.db.prefix_in(self.catalog, &self.name, prefix)
+    }
+}
+
+/// Returns the path of the log of the database at the given path.
+fn log_path(path: &Path) -> PathBuf {
+    let mut log = path.as_os_str().to_owned();
+    log.push("-wal");
+    PathBuf::from(log)
+}
+
+/// A key and its value, stored as a single key of the tree. Entries are
+/// ordered, and compared, by their keys alone.
+#[derive(Clone, Debug)]
+struct Entry {
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl Entry {
+    fn new(key: &[u8], value: &[u8]) -> Result<Self> {
+        if key.len() > MAX_ENTRY_SIZE {
+            return Err(Error::KeyTooLarge {
+                len: key.len(),
+                max: MAX_ENTRY_SIZE,
+            });
+        }
+        if value.len() > MAX_ENTRY_SIZE - key.len() {
+            return Err(Error::ValueTooLarge {
+                len: value.len(),
+                max: MAX_ENTRY_SIZE - key.len(),
+            });
+        }
+        Ok(Entry {
+            key: key.to_vec(),
+            value: value.to_vec(),
+        })
+    }
+
+    /// Returns an entry without a value, to search for the given key.
+    fn probe(key: &[u8]) -> Result<Self> {
+        Entry::new(key, &[])
+    }
+
+    /// Returns the entry of the catalog holding the root of a bucket.
+    fn bucket(name: &[u8], root: Option<PageId>) -> Result<Self> {
+        let value = root.map(PageId::to_le_bytes);
+        Entry::new(name, value.as_ref().map_or(&[], |root| &root[..]))
+    }
+
+    /// Returns the root of the bucket of an entry of the catalog.
+    fn root(&self) -> Option<PageId> {
+        self.value
+            .as_slice()
+            .try_into()
+            .ok()
+            .map(PageId::from_le_bytes)
+    }
+
+    /// Returns an entry without a value, to bound a range. A bound may be
+    /// longer than any key.
+    fn bound(key: &[u8]) -> Self {
+        Entry {
+            key: key.to_vec(),
+            value: Vec::new(),
+        }
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+/// An entry is encoded as its key, followed by its value and the length of
+/// the key, in four bytes. Putting the key first lets the entries of a node
+/// share the prefix of their keys.
+impl PageKey for Entry {
+    const SIZE: Option<usize> = None;
+
+    fn encoded_len(&self) -> usize {
+        self.key.len() + self.value.len() + 4
+    }
+
+    fn encode_into(&self, buf: &mut [u8]) {
+        let (key, rest) = buf.split_at_mut(self.key.len());
+        let (value, len) = rest.split_at_mut(self.value.len());
+        key.copy_from_slice(&self.key);
+        value.copy_from_slice(&self.value);
+        len.copy_from_slice(&(self.key.len() as u32).to_le_bytes());
+    }
+
+    /// The page checksums catch corrupted lengths, so a length which does
+    /// not fit is merely clamped.
+    fn decode_from(buf: &[u8]) -> Self {
+        let (rest, len) = buf.split_at(buf.len().saturating_sub(4));
+        let len = len.try_into().map_or(0, u32::from_le_bytes) as usize;
+        let (key, value) = rest.split_at(len.min(rest.len()));
+        Entry {
+            key: key.to_vec(),
+            value: value.to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Pager;
+
+    /// Returns a path for a database, removing the file and the log left at
+    /// it.
+    pub(super) fn temp_path(name: &str) -> PathBuf {
+        let path = crate::tests::temp_path(&format!("db-{name}"));
+        let _ = std::fs::remove_file(log_path(&path));
+        path
+    }
+
+    pub(super) fn remove(path: &Path) {
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(log_path(path)).unwrap();
+    }
+
+    #[test]
+    fn test_put_get_delete() {
+        let path = temp_path("basic");
+        let mut db = Database::open(&path).unwrap();
+
+        assert_eq!(db.put(b"apple", b"red").unwrap(), None);
+        assert_eq!(db.put(b"banana", b"yellow").unwrap(), None);
+        assert_eq!(db.get(b"apple").unwrap(), b"red");
+
+        assert_eq!(db.put(b"apple", b"green").unwrap(), Some(b"red".to_vec()));
+        assert_eq!(db.get(b"apple").unwrap(), b"green");
+
+        assert_eq!(db.delete(b"banana").unwrap(), b"yellow");
+        assert!(matches!(db.get(b"banana"), Err(Error::KeyNotFound)));
+        assert!(matches!(db.delete(b"banana"), Err(Error::KeyNotFound)));
+
+        // Empty keys and values are allowed.
+        db.put(b"", b"").unwrap();
+        assert_eq!(db.get(b"").unwrap(), b"");
+
+        drop(db);
+        remove(&path);
+    }
+
+    #[test]
+    fn test_open_errors_name_the_failed_call() {
+        let path = temp_path("truncated");
+        std::fs::write(&path, [0; 100]).unwrap();
+
+        let err = Database::open(&path).err().unwrap();
+        assert_eq!(err.to_string(), format!("Database::open({path:?}) failed"));
+        let source = std::error::Error::source(&err).unwrap();
+        assert_eq!(
+            source.to_string(),
+            format!("FilePager::open({path:?}) failed")
+        );
+        assert!(matches!(err.root_cause(), Error::CorruptPage { .. }));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_range_is_ordered_by_key() {
+        let path = temp_path("range");
+        let mut db = Database::open(&path).unwrap();
+
+        for i in (0..1000u32).rev() {
+            let key = format!("key{i:04}");
+            db.put(key.as_bytes(), &i.to_le_bytes()).unwrap();
+        }
+
+        let entries = db.range(&b"key0100"[..]..&b"key0105"[..]).unwrap();
+        let keys: Vec<&[u8]> = entries.iter().map(|(key, _)| *key).collect();
+        assert_eq!(
+            keys,
+            [b"key0100", b"key0101", b"key0102", b"key0103", b"key0104"]
+        );
+        assert_eq!(entries[0].1, 100u32.to_le_bytes());
+
+        assert_eq!(db.range(..).unwrap().len(), 1000);
+        assert_eq!(db.range(&b"key0998"[..]..).unwrap().len(), 2);
+        assert_eq!(db.range(..=&b"key"[..]).unwrap().len(), 0);
+
+        drop(db);
+        remove(&path);
+    }
+
+    #[test]
+    fn test_prefix_scans_the_keys_starting_with_it() {
+        let path = temp_path("prefix");
+        let mut db = Database::open(&path).unwrap();
+        for key in [
+            &b"user:1"[..],
+            b"user:2",
+            b"users",
+            b"user\xff",
+            b"user;",
+            b"\xff\xff",
+        ] {
+            db.put(key, key).unwrap();
+        }
+
+        let keys = |entries: Vec<(&[u8], &[u8])>| -> Vec<Vec<u8>> {
+            entries.into_iter().map(|(key, _)| key.to_vec()).collect()
+        };
+        assert_eq!(keys(db.prefix(b"user:").unwrap()), [b"user:1", b"user:2"]);
+        assert_eq!(keys(db.prefix(b"user").unwrap()).len(), 5);
+        assert_eq!(keys(db.prefix(b"\xff").unwrap()), [b"\xff\xff"]);
+        assert_eq!(keys(db.prefix(b"").unwrap()).len(), 6);
+
+        let snapshot = db.snapshot();
+        db.delete(b"user:1").unwrap();
+        assert_eq!(keys(db.view(&snapshot).prefix(b"user:").unwrap()).len(), 2);
+        let bucket = db.bucket(DEFAULT_BUCKET).unwrap();
+        assert_eq!(keys(bucket.prefix(b"user:").unwrap()), [b"user:2"]);
+
+        drop(snapshot);
+        drop(db);
+        remove(&path);
+    }
+
+    #[test]
+    fn test_reopened_database_keeps_entries() {
+        let path = temp_path("reopen");
+
+        let mut db = Database::open(&path).unwrap();
+        for i in 0..2000u32 {
+            db.put(&i.to_be_bytes(), &[i as u8; 100]).unwrap();
+        }
+        for i in (0..2000u32).step_by(2) {
+            db.delete(&i.to_be_bytes()).unwrap();
+        }
+        // The last writes are only committed to the log, not synced.
+        db.put(&1u32.to_be_bytes(), b"updated").unwrap();
+        drop(db);
+
+        let db = Database::open(&path).unwrap();
+        assert_eq!(db.range(..).unwrap().len(), 1000);
+        assert_eq!(db.get(&1u32.to_be_bytes()).unwrap(), b"updated");
+        assert_eq!(db.get(&3u32.to_be_bytes()).unwrap(), [3; 100]);
+        assert!(db.get(&4u32.to_be_bytes()).is_err());
+
+        drop(db);
+        remove(&path);
+    }
+
+    #[test]
+    fn test_compare_and_swap() {
+        let path = temp_path("cas");
+        let mut db = Database::open(&path).unwrap();
+        db.put(b"counter", b"1").unwrap();
+
+        assert_eq!(
+            db.compare_and_swap(b"counter", b"0", b"2").unwrap(),
+            Err(b"1".to_vec())
+        );
+        assert_eq!(
+            db.compare_and_swap(b"counter", b"1", b"2").unwrap(),
+            Ok(b"1".to_vec())
+        );
+        assert!(matches!(
+            db.compare_and_swap(b"missing", b"", b"1"),
+            Err(Error::KeyNotFound)
+        ));
+        drop(db);
+
+        let db = Database::open(&path).unwrap();
+        assert_eq!(db.get(b"counter").unwrap(), b"2");
+
+        drop(db);
+        remove(&path);
+    }
+
+    #[test]
+    fn test_apply_batch() {
+        let path = temp_path("batch");
+        let mut db = Database::open(&path).unwrap();
+        db.put(b"b", b"old").unwrap();
+
+        let batch = vec![
+            BatchOp::Insert(b"c".to_vec(), b"3".to_vec()),
+            BatchOp::Insert(b"a".to_vec(), b"1".to_vec()),
+            BatchOp::Remove(b"b".to_vec()),
+            BatchOp::Remove(b"missing".to_vec()),
+            BatchOp::Insert(b"c".to_vec(), b"33".to_vec()),
+        ];
+        db.apply_batch(batch).unwrap();
+        drop(db);
+
+        let db = Database::open(&path).unwrap();
+        let entries = db.range(..).unwrap();
+        assert_eq!(entries, [(&b"a"[..], &b"1"[..]), (&b"c"[..], &b"33"[..])]);
+
+        drop(db);
+        remove(&path);
+    }
+
+    #[test]
+    fn test_cache_is_configurable() {
+        let path = temp_path("cache");
+
+        let mut db = Database::open_with_cache(&path, 4 * PAGE_SIZE, Eviction::Clock).unwrap();
+        for i in 0..2000u32 {
+            db.put(&i.to_be_bytes(), &[i as u8; 100]).unwrap();
+        }
+        let stats = db.cache_stats();
+        assert!(stats.evictions > 0 && stats.writebacks > 0);
+        drop(db);
+
+        let db = Database::open_with_cache(&path, 0, Eviction::Lru).unwrap();
+        assert_eq!(
+            db.get(&1999u32.to_be_bytes()).unwrap(),
+            [1999u32 as u8; 100]
+        );
+        assert_eq!(db.cache_stats().hits, 0);
+
+        drop(db);
+        remove(&path);
+    }
+
+    #[test]
+    fn test_entries_larger_than_a_page_are_stored() {
+        let path = temp_path("limits");
+        let mut db = Database::open(&path).unwrap();
+
+        let key = |i: u8| vec![i; 3 * PAGE_SIZE];
+        for i in 0..20 {
+            db.put(&key(i), &vec![i; 5 * PAGE_SIZE + i as usize])
+                .unwrap();
+            db.put(&[i], b"small").unwrap();
+        }
+        db.put(&key(3), b"replaced").unwrap();
+        db.delete(&key(4)).unwrap();
+        drop(db);
+
+        let db = Database::open(&path).unwrap();
+        assert_eq!(db.get(&key(3)).unwrap(), b"replaced");
+        assert!(matches!(db.get(&key(4)), Err(Error::KeyNotFound)));
+        assert_eq!(db.get(&key(19)).unwrap(), vec![19; 5 * PAGE_SIZE + 19]);
+        assert_eq!(db.get(&[7]).unwrap(), b"small");
+        assert_eq!(db.range(..).unwrap().len(), 39);
+
+        drop(db);
+        remove(&path);
+    }
+
+    #[test]
+    fn test_entries_share_the_prefix_of_their_keys() {
+        let path = temp_path("prefix");
+        let mut db = Database::open(&path).unwrap();
+
+        for i in 0..1000 {
+            let key = format!("https://example.com/articles/{i:04}");
+            db.put(key.as_bytes(), b"value").unwrap();
+        }
+        let root = db.root(db.tree.root(), DEFAULT_BUCKET).unwrap();
+        let stats = db.tree.prefix_stats_at(root).unwrap();
+        assert!(stats.ratio() < 0.5, "{stats:?}");
+
+        drop(db);
+        remove(&path);
+    }
+
+    #[test]
+    fn test_buckets_are_independent() {
+        let path = temp_path("buckets");
+        let mut db = Database::open(&path).unwrap();
+
+        db.put(b"key", b"default").unwrap();
+        db.create_bucket(b"users")
+            .unwrap()
+            .put(b"key", b"users")
+            .unwrap();
+        let mut orders = db.create_bucket(b"orders").unwrap();
+        for i in 0..500u32 {
+            orders.put(&i.to_be_bytes(), b"order").unwrap();
+        }
+        assert!(matches!(
+            db.create_bucket(b"users"),
+            Err(Error::BucketAlreadyExists)
+        ));
+        assert!(matches!(db.bucket(b"missing"), Err(Error::BucketNotFound)));
+
+        assert_eq!(db.get(b"key").unwrap(), b"default");
+        assert_eq!(db.bucket(b"users").unwrap().get(b"key").unwrap(), b"users");
+        assert!(db.bucket(b"orders").unwrap().get(b"key").is_err());
+        assert_eq!(db.bucket(b"orders").unwrap().range(..).unwrap().len(), 500);
+        assert_eq!(db.buckets().unwrap(), [&b"orders"[..], b"users"]);
+
+        drop(db);
+        let mut db = Database::open(&path).unwrap();
+        assert_eq!(db.get(b"key").unwrap(), b"default");
+        assert_eq!(db.bucket(b"users").unwrap().get(b"key").unwrap(), b"users");
+        let orders = db.bucket(b"orders").unwrap();
+        assert_eq!(orders.get(&7u32.to_be_bytes()).unwrap(), b"order");
+
+        drop(db);
+        remove(&path);
+    }
+
+    #[test]
+    fn test_deleted_bucket_frees_its_pages() {
+        let path = temp_path("delete-bucket");
+        let mut db = Database::open(&path).unwrap();
+
+        let fill = |db: &mut Database| {
+            let mut bucket = db.create_bucket(b"bucket").unwrap();
+            for i in 0..1000u32 {
+                bucket.put(&i.to_be_bytes(), &[0; 100]).unwrap();
+            }
+        };
+
+        fill(&mut db);
+        let pages = db.tree.pager().page_count();
+        db.delete_bucket(b"bucket").unwrap();
+        assert!(matches!(db.bucket(b"bucket"), Err(Error::BucketNotFound)));
+        assert!(db.buckets().unwrap().is_empty());
+
+        // The pages of the deleted bucket are reused by the new one.
+        fill(&mut db);
+        assert_eq!(db.tree.pager().page_count(), pages);
+
+        // The default bucket is emptied, but stays.
+        db.put(b"key", b"value").unwrap();
+        db.delete_bucket(b"").unwrap();
+        assert!(db.get(b"key").is_err());
+        db.put(b"key", b"value").unwrap();
+
+        drop(db);
+        remove(&path);
+    }
+
+    #[test]
+    fn test_view_is_isolated_from_later_writes() {
+        let path = temp_path("view");
+        let mut db = Database::open(&path).unwrap();
+        for i in 0..500u32 {
+            db.put(&i.to_be_bytes(), b"old").unwrap();
+        }
+        db.create_bucket(b"bucket")
+            .unwrap()
+            .put(b"key", b"old")
+            .unwrap();
+
+        let snapshot = db.snapshot();
+        for i in 0..500u32 {
+            db.put(&i.to_be_bytes(), b"new").unwrap();
+        }
+        db.bucket(b"bucket").unwrap().delete(b"key").unwrap();
+        db.create_bucket(b"later").unwrap();
+
+        let view = db.view(&snapshot);
+        assert_eq!(view.get(&7u32.to_be_bytes()).unwrap(), b"old");
+        assert!(
+            view.range(..)
+                .unwrap()
+                .iter()
+                .all(|&(_, value)| value == b"old")
+        );
+        assert_eq!(view.bucket(b"bucket").unwrap().get(b"key").unwrap(), b"old");
+        assert!(matches!(view.bucket(b"later"), Err(Error::BucketNotFound)));
+        assert_eq!(db.get(&7u32.to_be_bytes()).unwrap(), b"new");
+        assert!(db.bucket(b"bucket").unwrap().get(b"key").is_err());
+
+        // Once the snapshot is dropped, rewriting every entry needs no more
+        // pages than it did before.
+        drop(snapshot);
+        db.put(b"key", b"value").unwrap();
+        let pages = db.tree.pager().page_count();
+        for i in 0..500u32 {
+            db.put(&i.to_be_bytes(), b"newer").unwrap();
+        }
+        assert_eq!(db.tree.pager().page_count(), pages);
+
+        drop(db);
+        remove(&path);
+    }
+}
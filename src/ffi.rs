@@ -0,0 +1,495 @@
+//! A C interface to the tree, for embedding it in C and C++ projects.
+//!
+//! Two kinds of trees are exposed through opaque handles: `BTreeI64`, which
+//! holds 64-bit integers, and `BTreeBytes`, which holds byte strings ordered
+//! like `memcmp`. The declarations are in `include/btree.h`, and a static or
+//! dynamic library can be built with
+//! `cargo rustc --release --features ffi --crate-type staticlib` (or
+//! `cdylib`).
+//!
+//! Every handle returned by a `_new` function must be released with the
+//! matching `_free` function, and must not be used afterwards. The functions
+//! accept null handles, and report them with `BTreeStatus::InvalidArgument`,
+//! `false` or a null handle, instead of crashing.
+//!
+//! Iterators remember the last key they returned rather than a position in
+//! the tree, so the tree may be modified between calls to `_iter_next`: the
+//! iterator continues with the smallest key greater than the last one. An
+//! iterator must be freed before the tree it iterates over.
+
+use crate::btree::SimpleBTreeSet;
+use crate::{BTreeSet, Error};
+use std::{ptr, slice};
+
+/// The outcome of an operation which modifies a tree.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BTreeStatus {
+    Ok = 0,
+    KeyNotFound = 1,
+    KeyAlreadyExists = 2,
+    /// A null handle, or a null key pointer with a non-zero length.
+    InvalidArgument = 3,
+}
+
+impl From<crate::Result<()>> for BTreeStatus {
+    fn from(result: crate::Result<()>) -> Self {
+        match result {
+            Ok(()) => BTreeStatus::Ok,
+            Err(Error::KeyNotFound) => BTreeStatus::KeyNotFound,
+            Err(Error::KeyAlreadyExists) => BTreeStatus::KeyAlreadyExists,
+            Err(err) => unreachable!("in-memory trees do not fail with {err}"),
+        }
+    }
+}
+
+/// A tree of 64-bit integers.
+pub struct BTreeI64(SimpleBTreeSet<i64>);
+
+/// A tree of byte strings, ordered like `memcmp`, with shorter strings
+/// before the longer strings they prefix.
+pub struct BTreeBytes(SimpleBTreeSet<Vec<u8>>);
+
+/// An iterator over the keys of a `BTreeI64`, in ascending order.
+pub struct BTreeI64Iter {
+    tree: *const BTreeI64,
+    last: Option<i64>,
+    done: bool,
+}
+
+/// An iterator over the keys of a `BTreeBytes`, in ascending order.
+pub struct BTreeBytesIter {
+    tree: *const BTreeBytes,
+    /// A copy of the last key returned, which the pointer handed out by
+    /// `btree_bytes_iter_next` points into.
+    last: Option<Vec<u8>>,
+    done: bool,
+}
+
+/// Returns the smallest key of the tree greater than `last`, or the smallest
+/// key if there is no last key.
+fn next_after<'a, K: Ord>(tree: &'a SimpleBTreeSet<K>, last: Option<&K>) -> Option<&'a K> {
+    let mut cursor = tree.cursor();
+    if let Some(last) = last {
+        cursor.seek(last);
+        if cursor.key() == Some(last) {
+            cursor.move_next();
+        }
+    }
+    cursor.key()
+}
+
+/// Converts a key passed from C into a slice.
+///
+/// # Safety
+///
+/// Unless `len` is zero, `key` must point to `len` readable bytes.
+unsafe fn bytes<'a>(key: *const u8, len: usize) -> Option<&'a [u8]> {
+    match (key.is_null(), len) {
+        (_, 0) => Some(&[]),
+        (true, _) => None,
+        // SAFETY: The caller guarantees that the pointer covers `len` bytes.
+        (false, _) => Some(unsafe { slice::from_raw_parts(key, len) }),
+    }
+}
+
+/// Creates an empty tree of integers.
+#[unsafe(no_mangle)]
+pub extern "C" fn btree_i64_new() -> *mut BTreeI64 {
+    Box::into_raw(Box::new(BTreeI64(SimpleBTreeSet::new())))
+}
+
+/// Frees the tree and its keys.
+///
+/// # Safety
+///
+/// The tree must be null, or a handle returned by `btree_i64_new` which was
+/// not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn btree_i64_free(tree: *mut BTreeI64) {
+    if !tree.is_null() {
+        // SAFETY: The caller guarantees that the handle is live.
+        drop(unsafe { Box::from_raw(tree) });
+    }
+}
+
+/// Inserts the key, failing if it is already in the tree.
+///
+/// # Safety
+///
+/// The tree must be null or a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn btree_i64_insert(tree: *mut BTreeI64, key: i64) -> BTreeStatus {
+    // SAFETY: The caller guarantees that the handle is null or live.
+    match unsafe { tree.as_mut() } {
+        Some(tree) => tree.0.insert(key).into(),
+        None => BTreeStatus::InvalidArgument,
+    }
+}
+
+/// Returns whether the key is in the tree.
+///
+/// # Safety
+///
+/// The tree must be null or a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn btree_i64_contains(tree: *const BTreeI64, key: i64) -> bool {
+    // SAFETY: The caller guarantees that the handle is null or live.
+    unsafe { tree.as_ref() }.is_some_and(|tree| tree.0.contains(&key))
+}
+
+/// Removes the key, failing if it is not in the tree.
+///
+/// # Safety
+///
+/// The tree must be null or a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn btree_i64_remove(tree: *mut BTreeI64, key: i64) -> BTreeStatus {
+    // SAFETY: The caller guarantees that the handle is null or live.
+    match unsafe { tree.as_mut() } {
+        Some(tree) => tree.0.remove(&key).map(|_| ()).into(),
+        None => BTreeStatus::InvalidArgument,
+    }
+}
+
+/// Creates an iterator over the keys of the tree, or returns null if the
+/// tree is null.
+///
+/// # Safety
+///
+/// The tree must be null or a live handle, and must outlive the iterator.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn btree_i64_iter_new(tree: *const BTreeI64) -> *mut BTreeI64Iter {
+    if tree.is_null() {
+        return ptr::null_mut();
+    }
+    Box::into_raw(Box::new(BTreeI64Iter {
+        tree,
+        last: None,
+        done: false,
+    }))
+}
+
+/// Writes the next key to `key` and returns `true`, or returns `false` once
+/// there are no keys left.
+///
+/// # Safety
+///
+/// The iterator must be null or a live handle whose tree is still live, and
+/// `key` must be null or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn btree_i64_iter_next(iter: *mut BTreeI64Iter, key: *mut i64) -> bool {
+    // SAFETY: The caller guarantees that the iterator is null or live.
+    let Some(iter) = (unsafe { iter.as_mut() }) else {
+        return false;
+    };
+    if iter.done || key.is_null() {
+        return false;
+    }
+
+    // SAFETY: The caller guarantees that the tree outlives the iterator.
+    let tree = unsafe { &(*iter.tree).0 };
+    match next_after(tree, iter.last.as_ref()) {
+        Some(&next) => {
+            iter.last = Some(next);
+            // SAFETY: The caller guarantees that `key` is valid for writes.
+            unsafe { key.write(next) };
+            true
+        }
+        None => {
+            iter.done = true;
+            false
+        }
+    }
+}
+
+/// Frees the iterator.
+///
+/// # Safety
+///
+/// The iterator must be null, or a handle returned by `btree_i64_iter_new`
+/// which was not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn btree_i64_iter_free(iter: *mut BTreeI64Iter) {
+    if !iter.is_null() {
+        // SAFETY: The caller guarantees that the handle is live.
+        drop(unsafe { Box::from_raw(iter) });
+    }
+}
+
+/// Creates an empty tree of byte strings.
+#[unsafe(no_mangle)]
+pub extern "C" fn btree_bytes_new() -> *mut BTreeBytes {
+    Box::into_raw(Box::new(BTreeBytes(SimpleBTreeSet::new())))
+}
+
+/// Frees the tree and its keys.
+///
+/// # Safety
+///
+/// The tree must be null, or a handle returned by `btree_bytes_new` which
+/// was not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn btree_bytes_free(tree: *mut BTreeBytes) {
+    if !tree.is_null() {
+        // SAFETY: The caller guarantees that the handle is live.
+        drop(unsafe { Box::from_raw(tree) });
+    }
+}
+
+/// Inserts a copy of the `len` bytes at `key`, failing if they are already
+/// in the tree.
+///
+/// # Safety
+///
+/// The tree must be null or a live handle, and unless `len` is zero, `key`
+/// must be null or point to `len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn btree_bytes_insert(
+    tree: *mut BTreeBytes,
+    key: *const u8,
+    len: usize,
+) -> BTreeStatus {
+    // SAFETY: The caller guarantees that the pointers are null or valid.
+    match unsafe { (tree.as_mut(), bytes(key, len)) } {
+        (Some(tree), Some(key)) => tree.0.insert(key.to_vec()).into(),
+        _ => BTreeStatus::InvalidArgument,
+    }
+}
+
+/// Returns whether the `len` bytes at `key` are in the tree.
+///
+/// # Safety
+///
+/// The tree must be null or a live handle, and unless `len` is zero, `key`
+/// must be null or point to `len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn btree_bytes_contains(
+    tree: *const BTreeBytes,
+    key: *const u8,
+    len: usize,
+) -> bool {
+    // SAFETY: The caller guarantees that the pointers are null or valid.
+    match unsafe { (tree.as_ref(), bytes(key, len)) } {
+        (Some(tree), Some(key)) => tree.0.contains(key),
+        _ => false,
+    }
+}
+
+/// Removes the `len` bytes at `key`, failing if they are not in the tree.
+///
+/// # Safety
+///
+/// The tree must be null or a live handle, and unless `len` is zero, `key`
+/// must be null or point to `len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn btree_bytes_remove(
+    tree: *mut BTreeBytes,
+    key: *const u8,
+    len: usize,
+) -> BTreeStatus {
+    // SAFETY: The caller guarantees that the pointers are null or valid.
+    match unsafe { (tree.as_mut(), bytes(key, len)) } {
+        (Some(tree), Some(key)) => tree.0.remove(key).map(|_| ()).into(),
+        _ => BTreeStatus::InvalidArgument,
+    }
+}
+
+/// Creates an iterator over the keys of the tree, or returns null if the
+/// tree is null.
+///
+/// # Safety
+///
+/// The tree must be null or a live handle, and must outlive the iterator.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn btree_bytes_iter_new(tree: *const BTreeBytes) -> *mut BTreeBytesIter {
+    if tree.is_null() {
+        return ptr::null_mut();
+    }
+    Box::into_raw(Box::new(BTreeBytesIter {
+        tree,
+        last: None,
+        done: false,
+    }))
+}
+
+/// Writes a pointer to the next key to `key` and its length to `len`, and
+/// returns `true`, or returns `false` once there are no keys left. The key is
+/// owned by the iterator, and stays valid until the next call or until the
+/// iterator is freed.
+///
+/// # Safety
+///
+/// The iterator must be null or a live handle whose tree is still live, and
+/// `key` and `len` must be null or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn btree_bytes_iter_next(
+    iter: *mut BTreeBytesIter,
+    key: *mut *const u8,
+    len: *mut usize,
+) -> bool {
+    // SAFETY: The caller guarantees that the iterator is null or live.
+    let Some(iter) = (unsafe { iter.as_mut() }) else {
+        return false;
+    };
+    if iter.done || key.is_null() || len.is_null() {
+        return false;
+    }
+
+    // SAFETY: The caller guarantees that the tree outlives the iterator.
+    let tree = unsafe { &(*iter.tree).0 };
+    match next_after(tree, iter.last.as_ref()) {
+        Some(next) => {
+            let next = iter.last.insert(next.clone());
+            // SAFETY: The caller guarantees that both are valid for writes.
+            unsafe {
+                key.write(next.as_ptr());
+                len.write(next.len());
+            }
+            true
+        }
+        None => {
+            iter.done = true;
+            false
+        }
+    }
+}
+
+/// Frees the iterator.
+///
+/// # Safety
+///
+/// The iterator must be null, or a handle returned by `btree_bytes_iter_new`
+/// which was not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn btree_bytes_iter_free(iter: *mut BTreeBytesIter) {
+    if !iter.is_null() {
+        // SAFETY: The caller guarantees that the handle is live.
+        drop(unsafe { Box::from_raw(iter) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect_i64(tree: *const BTreeI64) -> Vec<i64> {
+        let mut keys = Vec::new();
+        unsafe {
+            let iter = btree_i64_iter_new(tree);
+            let mut key = 0;
+            while btree_i64_iter_next(iter, &mut key) {
+                keys.push(key);
+            }
+            btree_i64_iter_free(iter);
+        }
+        keys
+    }
+
+    #[test]
+    fn test_i64_tree_round_trip() {
+        unsafe {
+            let tree = btree_i64_new();
+            for key in (-500..500).rev() {
+                assert_eq!(btree_i64_insert(tree, key), BTreeStatus::Ok);
+            }
+            assert_eq!(btree_i64_insert(tree, 7), BTreeStatus::KeyAlreadyExists);
+            assert!(btree_i64_contains(tree, -500));
+            assert!(!btree_i64_contains(tree, 500));
+
+            assert_eq!(btree_i64_remove(tree, 0), BTreeStatus::Ok);
+            assert_eq!(btree_i64_remove(tree, 0), BTreeStatus::KeyNotFound);
+            assert_eq!(
+                collect_i64(tree),
+                (-500..500).filter(|&k| k != 0).collect::<Vec<_>>()
+            );
+            btree_i64_free(tree);
+        }
+    }
+
+    #[test]
+    fn test_iterator_survives_modifications() {
+        unsafe {
+            let tree = btree_i64_new();
+            for key in [10, 20, 30, 40] {
+                btree_i64_insert(tree, key);
+            }
+
+            let iter = btree_i64_iter_new(tree);
+            let mut key = 0;
+            assert!(btree_i64_iter_next(iter, &mut key));
+            assert_eq!(key, 10);
+
+            btree_i64_remove(tree, 20);
+            btree_i64_insert(tree, 25);
+            btree_i64_insert(tree, 5);
+            let mut rest = Vec::new();
+            while btree_i64_iter_next(iter, &mut key) {
+                rest.push(key);
+            }
+            assert_eq!(rest, [25, 30, 40]);
+
+            // A drained iterator stays drained.
+            btree_i64_insert(tree, 50);
+            assert!(!btree_i64_iter_next(iter, &mut key));
+
+            btree_i64_iter_free(iter);
+            btree_i64_free(tree);
+        }
+    }
+
+    #[test]
+    fn test_bytes_tree_orders_like_memcmp() {
+        unsafe {
+            let tree = btree_bytes_new();
+            for key in [&b"banana"[..], b"apple", b"", b"app", b"\xff"] {
+                assert_eq!(
+                    btree_bytes_insert(tree, key.as_ptr(), key.len()),
+                    BTreeStatus::Ok
+                );
+            }
+            assert_eq!(
+                btree_bytes_insert(tree, b"app".as_ptr(), 3),
+                BTreeStatus::KeyAlreadyExists
+            );
+            assert!(btree_bytes_contains(tree, ptr::null(), 0));
+            assert!(btree_bytes_contains(tree, b"apple".as_ptr(), 5));
+            assert_eq!(
+                btree_bytes_remove(tree, b"banana".as_ptr(), 6),
+                BTreeStatus::Ok
+            );
+
+            let iter = btree_bytes_iter_new(tree);
+            let (mut key, mut len) = (ptr::null(), 0);
+            let mut keys = Vec::new();
+            while btree_bytes_iter_next(iter, &mut key, &mut len) {
+                keys.push(slice::from_raw_parts(key, len).to_vec());
+            }
+            btree_bytes_iter_free(iter);
+            assert_eq!(keys, [&b""[..], b"app", b"apple", b"\xff"]);
+
+            btree_bytes_free(tree);
+        }
+    }
+
+    #[test]
+    fn test_null_handles_are_rejected() {
+        unsafe {
+            assert_eq!(
+                btree_i64_insert(ptr::null_mut(), 1),
+                BTreeStatus::InvalidArgument
+            );
+            assert!(!btree_i64_contains(ptr::null(), 1));
+            assert!(btree_i64_iter_new(ptr::null()).is_null());
+            assert!(!btree_i64_iter_next(ptr::null_mut(), &mut 0));
+            btree_i64_free(ptr::null_mut());
+
+            let tree = btree_bytes_new();
+            assert_eq!(
+                btree_bytes_insert(tree, ptr::null(), 3),
+                BTreeStatus::InvalidArgument
+            );
+            btree_bytes_free(tree);
+        }
+    }
+}
@@ -1,20 +1,193 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+
 use thiserror::Error;
 
-pub mod btree;
+/// Emits a `tracing` event at the debug level when the `tracing` feature is
+/// enabled, and compiles to nothing otherwise.
+#[cfg_attr(not(feature = "std"), allow(unused_macros))]
+macro_rules! debug_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+    };
+}
 
-pub type Result<T> = std::result::Result<T, Error>;
+/// Enters a `tracing` span at the debug level until the returned guard is
+/// dropped, when the `tracing` feature is enabled.
+#[cfg_attr(not(feature = "std"), allow(unused_macros))]
+macro_rules! debug_span {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        let guard = tracing::debug_span!($($arg)*).entered();
+        #[cfg(not(feature = "tracing"))]
+        let guard = core::marker::PhantomData::<()>;
+        guard
+    }};
+}
 
+pub mod btree;
+#[cfg(feature = "std")]
+pub mod db;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+#[cfg(feature = "std")]
+pub mod storage;
+#[cfg(any(test, feature = "testsuite"))]
+pub mod testsuite;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "std")]
+pub mod workload;
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// The errors of the trees and of the storage they live on.
+///
+/// New variants may be added as the trees grow new subsystems, so matches on
+/// an `Error` need a wildcard arm.
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum Error {
     #[error("key not found")]
     KeyNotFound,
 
     #[error("key already exists")]
     KeyAlreadyExists,
+
+    #[error("key is out of order at the cursor position")]
+    KeyOutOfOrder,
+
+    #[error("the tree was modified since the cursor position was saved")]
+    StalePosition,
+
+    #[cfg(feature = "std")]
+    #[error("invariant violated at node {path:?}: {reason}")]
+    InvariantViolation { path: Vec<usize>, reason: String },
+
+    #[cfg(feature = "std")]
+    #[error("page is corrupted: {reason}")]
+    CorruptPage { reason: String },
+
+    #[error("node needs {needed} bytes, more than the page size of {page_size}")]
+    PageOverflow { needed: usize, page_size: usize },
+
+    #[error("unsupported file format version {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("pages cannot be written through a read-only pager")]
+    ReadOnly,
+
+    #[cfg(feature = "std")]
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("the tree has no room left for another node")]
+    CapacityExceeded,
+
+    #[error("memory allocation failed")]
+    AllocFailed,
+
+    #[error("the key is {len} bytes long, but keys may be at most {max} bytes long")]
+    KeyTooLarge { len: usize, max: usize },
+
+    #[error("the value is {len} bytes long, but values may be at most {max} bytes long")]
+    ValueTooLarge { len: usize, max: usize },
+
+    #[error("bucket not found")]
+    BucketNotFound,
+
+    #[error("bucket already exists")]
+    BucketAlreadyExists,
+
+    #[error("index not found")]
+    IndexNotFound,
+
+    #[error("index already exists")]
+    IndexAlreadyExists,
+
+    #[error("page {page} could not be decrypted: the key is wrong, or the page was tampered with")]
+    DecryptionFailed { page: u64 },
+
+    #[cfg(feature = "rkyv")]
+    #[error("the archive is invalid: {reason}")]
+    InvalidArchive { reason: String },
+
+    /// An error annotated with the operation which failed, and the key it
+    /// failed on, as added by `Context`. The error it wraps is its source,
+    /// and is left out of its message, so reporters which walk the sources
+    /// print it once.
+    #[cfg(feature = "std")]
+    #[error("{operation}{} failed", .key.as_deref().map(|key| format!("({key})")).unwrap_or_default())]
+    Operation {
+        operation: &'static str,
+        key: Option<String>,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Returns the error underneath the operations it was annotated with.
+    pub fn root_cause(&self) -> &Error {
+        match self {
+            #[cfg(feature = "std")]
+            Error::Operation { source, .. } => source.root_cause(),
+            err => err,
+        }
+    }
+}
+
+/// Annotates the error of a failed operation with what was being done, so an
+/// error which surfaces far from where it happened says which call failed.
+///
+/// Opening a `Database`, a `DiskBTreeSet` or a file-backed pager, and
+/// compacting or backing up a database, annotate their errors this way, as
+/// those fail for reasons far below the call. The errors of reads and writes
+/// of keys are returned as they are, so they can be matched on directly.
+///
+/// ```
+/// # use btree::{BTreeSet, Context, Error};
+/// # use btree::btree::SimpleBTreeSet;
+/// # use std::error::Error as _;
+/// let mut tree = SimpleBTreeSet::<u32>::new();
+/// let err = tree.remove(&7).with_key("remove", &7).unwrap_err();
+/// assert_eq!(err.to_string(), "remove(7) failed");
+/// assert_eq!(err.source().unwrap().to_string(), "key not found");
+/// assert!(matches!(err.root_cause(), Error::KeyNotFound));
+/// ```
+#[cfg(feature = "std")]
+pub trait Context<T> {
+    /// Names the operation which failed.
+    fn context(self, operation: &'static str) -> Result<T>;
+
+    /// Names the operation which failed, and the key it failed on.
+    fn with_key<K: core::fmt::Debug + ?Sized>(self, operation: &'static str, key: &K) -> Result<T>;
+}
+
+#[cfg(feature = "std")]
+impl<T> Context<T> for Result<T> {
+    fn context(self, operation: &'static str) -> Result<T> {
+        self.map_err(|source| Error::Operation {
+            operation,
+            key: None,
+            source: Box::new(source),
+        })
+    }
+
+    fn with_key<K: core::fmt::Debug + ?Sized>(self, operation: &'static str, key: &K) -> Result<T> {
+        self.map_err(|source| Error::Operation {
+            operation,
+            key: Some(format!("{key:?}")),
+            source: Box::new(source),
+        })
+    }
 }
 
 pub trait BTreeSet {
-    type Key: Ord;
+    type Key;
     const B: usize;
 
     fn search(&self, key: &Self::Key) -> Result<&Self::Key>;
@@ -30,6 +203,26 @@ pub trait BTreeSet {
     }
 }
 
+pub trait BTreeMap {
+    type Key: Ord;
+    type Value;
+    const B: usize;
+
+    fn get(&self, key: &Self::Key) -> Result<&Self::Value>;
+    fn get_mut(&mut self, key: &Self::Key) -> Result<&mut Self::Value>;
+    fn insert(&mut self, key: Self::Key, value: Self::Value) -> Result<()>;
+    fn remove(&mut self, key: &Self::Key) -> Result<Self::Value>;
+
+    fn contains_key(&self, key: &Self::Key) -> bool {
+        self.get(key).is_ok()
+    }
+
+    fn max_keys(&self) -> usize {
+        2 * Self::B - 1
+    }
+}
+
+#[cfg(test)]
 macro_rules! test_btree_impl (
     ($impl:ident) => {
         #[test]
@@ -39,188 +232,207 @@ macro_rules! test_btree_impl (
 
         #[test]
         fn test_empty_tree_does_not_contain_keys() {
-            let tree = $impl::<i32>::new();
-            let items = vec![0, 420, i32::MAX, i32::MIN];
-
-            for i in items {
-                assert!(!tree.contains(&i));
-            }
+            $crate::testsuite::empty_tree_does_not_contain_keys($impl::<usize>::new);
         }
 
         #[test]
-        fn test_contains_returns_true_after_insertion_without_splits() {
-            let mut tree = $impl::<usize>::new();
-            let items = (0..tree.max_keys());
+        fn test_contains_after_insertion_without_splits() {
+            $crate::testsuite::contains_after_insertion_without_splits($impl::<usize>::new);
+        }
 
-            for i in items {
-                assert!(!tree.contains(&i));
-                assert_eq!(tree.insert(i).unwrap(), ());
-                assert!(tree.contains(&i));
-            }
+        #[test]
+        fn test_contains_after_insertion_with_splits() {
+            $crate::testsuite::contains_after_insertion_with_splits($impl::<usize>::new);
         }
 
         #[test]
-        fn test_contains_returns_true_after_insertion_with_splits() {
-            let mut tree = $impl::<usize>::new();
-            let items = (0..tree.max_keys() + 1);
+        fn test_contains_after_insertion_with_many_splits() {
+            $crate::testsuite::contains_after_insertion_with_many_splits($impl::<usize>::new);
+        }
 
-            for i in items {
-                assert!(!tree.contains(&i));
-                assert_eq!(tree.insert(i).unwrap(), ());
-                assert!(tree.contains(&i));
-            }
+        #[test]
+        fn test_duplicate_key_is_rejected_without_splits() {
+            $crate::testsuite::duplicate_key_is_rejected_without_splits($impl::<usize>::new);
         }
 
         #[test]
-        fn test_contains_returns_true_after_insertion_with_many_splits() {
-            let mut tree = $impl::<usize>::new();
-            let items = (0..tree.max_keys().pow(4));
+        fn test_duplicate_key_is_rejected_with_splits() {
+            $crate::testsuite::duplicate_key_is_rejected_with_splits($impl::<usize>::new);
+        }
 
-            for i in items {
-                assert!(!tree.contains(&i));
-                assert_eq!(tree.insert(i).unwrap(), ());
-                assert!(tree.contains(&i));
-            }
+        #[test]
+        fn test_duplicate_key_is_rejected_with_many_splits() {
+            $crate::testsuite::duplicate_key_is_rejected_with_many_splits($impl::<usize>::new);
         }
 
         #[test]
-        fn test_duplicate_key_returns_error_without_splits() {
-            let mut tree = $impl::<usize>::new();
-            let items = (0..tree.max_keys());
+        fn test_search_existing_key_returns_it() {
+            $crate::testsuite::search_existing_key_returns_it($impl::<usize>::new);
+        }
 
-            for i in items {
-                assert_eq!(tree.insert(i).unwrap(), ());
-                assert!(tree.contains(&i));
-                let result = tree.insert(i);
-                assert!(result.is_err());
-                assert!(matches!(result.unwrap_err(), Error::KeyAlreadyExists));
-            }
+        #[test]
+        fn test_search_missing_key_fails() {
+            $crate::testsuite::search_missing_key_fails($impl::<usize>::new);
         }
 
         #[test]
-        fn test_duplicate_key_returns_error_with_splits() {
-            let mut tree = $impl::<usize>::new();
-            let items = (0..tree.max_keys() + 1);
+        fn test_remove_existing_key_returns_it() {
+            $crate::testsuite::remove_existing_key_returns_it($impl::<usize>::new);
+        }
 
-            for i in items {
-                assert_eq!(tree.insert(i).unwrap(), ());
-                assert!(tree.contains(&i));
-                let result = tree.insert(i);
-                assert!(result.is_err());
-                assert!(matches!(result.unwrap_err(), Error::KeyAlreadyExists));
-            }
+        #[test]
+        fn test_remove_missing_key_fails() {
+            $crate::testsuite::remove_missing_key_fails($impl::<usize>::new);
         }
 
         #[test]
-        fn test_duplicate_key_returns_error_with_many_splits() {
-            let mut tree = $impl::<usize>::new();
-            let items = (0..tree.max_keys().pow(4));
+        fn test_multiple_insertions_and_removals() {
+            $crate::testsuite::multiple_insertions_and_removals($impl::<usize>::new);
+        }
 
-            for i in items {
-                assert_eq!(tree.insert(i).unwrap(), ());
-                assert!(tree.contains(&i));
-                let result = tree.insert(i);
-                assert!(result.is_err());
-                assert!(matches!(result.unwrap_err(), Error::KeyAlreadyExists));
-            }
+        #[test]
+        fn test_remove_all_keys_in_scrambled_order() {
+            $crate::testsuite::remove_all_keys_in_scrambled_order($impl::<usize>::new);
         }
 
         #[test]
-        fn test_search_existing_key_returns_ok() {
-            let mut tree = $impl::<i32>::new();
-            let key = 50;
-            assert_eq!(tree.insert(key).unwrap(), ());
-            assert_eq!(tree.search(&key).unwrap(), &key);
+        fn test_stable_after_many_operations() {
+            $crate::testsuite::stable_after_many_operations($impl::<usize>::new);
         }
 
         #[test]
-        fn test_search_non_existing_key_returns_error() {
-            let tree = $impl::<i32>::new();
-            let key = 75;
-            let result = tree.search(&key);
-            assert!(result.is_err());
-            assert!(matches!(result.unwrap_err(), Error::KeyNotFound));
+        fn test_adversarial_orders() {
+            $crate::testsuite::adversarial_orders($impl::<usize>::new);
         }
 
         #[test]
-        fn test_remove_existing_key_returns_ok_and_removes() {
-            let mut tree = $impl::<i32>::new();
-            let key = 20;
-            assert_eq!(tree.insert(key).unwrap(), ());
-            assert!(tree.contains(&key));
-            assert_eq!(tree.remove(&key).unwrap(), key);
-            assert!(!tree.contains(&key));
+        fn test_mixed_workload() {
+            $crate::testsuite::mixed_workload($impl::<usize>::new);
         }
+    }
+);
+
+#[cfg(test)]
+pub(crate) use test_btree_impl;
 
+#[cfg(test)]
+macro_rules! test_btree_map_impl (
+    ($impl:ident) => {
         #[test]
-        fn test_remove_non_existing_key_returns_error() {
-            let mut tree = $impl::<i32>::new();
-            let key = 99;
-            let result = tree.remove(&key);
-            assert!(result.is_err());
-            assert!(matches!(result.unwrap_err(), Error::KeyNotFound));
+        fn test_new_returns_instance() {
+            let _map = $impl::<i32, i32>::new();
         }
 
         #[test]
-        fn test_multiple_insertions_and_deletions() {
-            let mut tree = $impl::<i32>::new();
-            let items = vec![10, 5, 15, 2, 7, 12, 18];
+        fn test_empty_map_does_not_contain_keys() {
+            let map = $impl::<i32, i32>::new();
+            let items = vec![0, 420, i32::MAX, i32::MIN];
 
-            for &item in &items {
-                tree.insert(item).unwrap();
+            for i in items {
+                assert!(!map.contains_key(&i));
             }
+        }
 
-            for &item in &items {
-                assert!(tree.contains(&item));
+        #[test]
+        fn test_get_returns_value_after_insertion_with_many_splits() {
+            let mut map = $impl::<usize, usize>::new();
+            let items = (0..map.max_keys().pow(4));
+
+            for i in items {
+                assert!(!map.contains_key(&i));
+                assert_eq!(map.insert(i, i * 2).unwrap(), ());
+                assert_eq!(map.get(&i).unwrap(), &(i * 2));
             }
+        }
 
-            assert_eq!(tree.remove(&7).unwrap(), 7);
-            assert!(!tree.contains(&7));
-            assert_eq!(tree.remove(&18).unwrap(), 18);
-            assert!(!tree.contains(&18));
+        #[test]
+        fn test_duplicate_key_returns_error_and_keeps_value() {
+            let mut map = $impl::<usize, &str>::new();
+            let items = (0..map.max_keys() + 1);
 
-            assert!(tree.contains(&10));
-            assert!(tree.contains(&5));
-            assert!(tree.contains(&15));
-            assert!(tree.contains(&2));
-            assert!(tree.contains(&12));
+            for i in items {
+                assert_eq!(map.insert(i, "first").unwrap(), ());
+                let result = map.insert(i, "second");
+                assert!(result.is_err());
+                assert!(matches!(result.unwrap_err(), Error::KeyAlreadyExists));
+                assert_eq!(map.get(&i).unwrap(), &"first");
+            }
+        }
 
-            let result = tree.remove(&7);
+        #[test]
+        fn test_get_non_existing_key_returns_error() {
+            let map = $impl::<i32, i32>::new();
+            let result = map.get(&75);
             assert!(result.is_err());
+            assert!(matches!(result.unwrap_err(), Error::KeyNotFound));
         }
 
         #[test]
-        fn test_tree_stability_after_many_operations() {
-            let mut tree = $impl::<i32>::new();
-            let mut inserted_keys = Vec::new();
+        fn test_get_mut_modifies_value() {
+            let mut map = $impl::<i32, i32>::new();
+            map.insert(1, 10).unwrap();
+            *map.get_mut(&1).unwrap() += 5;
+            assert_eq!(map.get(&1).unwrap(), &15);
+        }
 
-            // Insert many elements
-            for i in 0..1000 {
-                tree.insert(i).unwrap();
-                inserted_keys.push(i);
-            }
+        #[test]
+        fn test_remove_existing_key_returns_value() {
+            let mut map = $impl::<i32, &str>::new();
+            map.insert(20, "twenty").unwrap();
+            assert_eq!(map.remove(&20).unwrap(), "twenty");
+            assert!(!map.contains_key(&20));
+        }
 
-            // Verify all inserted elements are present
-            for &key in &inserted_keys {
-                assert!(tree.contains(&key));
-            }
+        #[test]
+        fn test_remove_non_existing_key_returns_error() {
+            let mut map = $impl::<i32, i32>::new();
+            let result = map.remove(&99);
+            assert!(result.is_err());
+            assert!(matches!(result.unwrap_err(), Error::KeyNotFound));
+        }
+    }
+);
 
-            // Delete some elements
-            for i in (0..1000).step_by(2) {
-                tree.remove(&i).unwrap();
-            }
+#[cfg(test)]
+pub(crate) use test_btree_map_impl;
 
-            // Verify remaining elements
-            for i in 0..1000 {
-                if i % 2 == 0 {
-                    assert!(!tree.contains(&i));
-                } else {
-                    assert!(tree.contains(&i));
-                }
-            }
-        }
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
 
+    /// Returns a path in the temporary directory for the files of a test,
+    /// removing any file left at it by an earlier run.
+    pub(crate) fn temp_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("btree-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
     }
-);
 
-pub(crate) use test_btree_impl;
+    #[test]
+    fn test_context_nests_around_the_root_cause() {
+        let result: Result<()> = Err(Error::PageOverflow {
+            needed: 5000,
+            page_size: 4096,
+        });
+        let err = result
+            .with_key("insert", "abc")
+            .context("commit")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "commit failed");
+        assert!(matches!(err.root_cause(), Error::PageOverflow { .. }));
+
+        // Each message is printed once, walking the sources.
+        let messages: Vec<String> =
+            std::iter::successors(Some(&err as &dyn std::error::Error), |err| err.source())
+                .map(|err| err.to_string())
+                .collect();
+        assert_eq!(
+            messages,
+            [
+                "commit failed",
+                "insert(\"abc\") failed",
+                "node needs 5000 bytes, more than the page size of 4096",
+            ]
+        );
+    }
+}
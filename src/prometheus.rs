@@ -0,0 +1,238 @@
+//! Renders the statistics of trees in the Prometheus text exposition format,
+//! so a service embedding the trees can serve them to a scraper.
+//!
+//! Every sample is labelled with the name of the tree it was taken from, and
+//! the samples of several trees are grouped by metric, as the format
+//! requires:
+//!
+//! ```
+//! # use btree::BTreeSet;
+//! # use btree::btree::SimpleBTreeSet;
+//! # use btree::prometheus::Exporter;
+//! let mut users = SimpleBTreeSet::<u64>::new();
+//! users.insert(7).unwrap();
+//! let orders = SimpleBTreeSet::<u64>::new();
+//!
+//! let mut exporter = Exporter::new();
+//! exporter.add("users", &users.stats());
+//! exporter.add("orders", &orders.stats());
+//! assert!(exporter.to_string().contains("btree_keys{tree=\"users\"} 1\n"));
+//! ```
+
+use crate::btree::{FileStats, TreeStats};
+use crate::storage::CacheStats;
+use std::fmt::{self, Display, Formatter};
+
+/// Statistics which can be rendered as Prometheus metrics.
+pub trait Export {
+    /// Adds a sample of every metric to the exporter, labelled with the name
+    /// of the tree.
+    fn export(&self, exporter: &mut Exporter, tree: &str);
+}
+
+/// Collects samples of metrics, and renders them in the Prometheus text
+/// exposition format through its `Display` implementation.
+#[derive(Clone, Debug, Default)]
+pub struct Exporter {
+    /// The metrics in the order they were first sampled.
+    families: Vec<Family>,
+}
+
+#[derive(Clone, Debug)]
+struct Family {
+    name: &'static str,
+    help: &'static str,
+    kind: &'static str,
+    /// The name of the tree, and the rendered value, of every sample.
+    samples: Vec<(String, String)>,
+}
+
+impl Exporter {
+    pub fn new() -> Self {
+        Exporter::default()
+    }
+
+    /// Adds the samples of the statistics of the named tree.
+    pub fn add(&mut self, tree: &str, stats: &impl Export) -> &mut Self {
+        stats.export(self, tree);
+        self
+    }
+
+    /// Adds a sample of a metric which can go up and down.
+    pub fn gauge(&mut self, name: &'static str, help: &'static str, tree: &str, value: f64) {
+        let value = if value.is_infinite() {
+            if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+        } else {
+            value.to_string()
+        };
+        self.sample(name, help, "gauge", tree, value);
+    }
+
+    /// Adds a sample of a metric which only goes up. By convention, the name
+    /// of a counter ends with `_total`.
+    pub fn counter(&mut self, name: &'static str, help: &'static str, tree: &str, value: u64) {
+        self.sample(name, help, "counter", tree, value.to_string());
+    }
+
+    fn sample(
+        &mut self,
+        name: &'static str,
+        help: &'static str,
+        kind: &'static str,
+        tree: &str,
+        value: String,
+    ) {
+        let idx = match self.families.iter().position(|family| family.name == name) {
+            Some(idx) => idx,
+            None => {
+                self.families.push(Family {
+                    name,
+                    help,
+                    kind,
+                    samples: Vec::new(),
+                });
+                self.families.len() - 1
+            }
+        };
+        self.families[idx].samples.push((tree.to_string(), value));
+    }
+}
+
+impl Display for Exporter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for family in &self.families {
+            writeln!(f, "# HELP {} {}", family.name, family.help)?;
+            writeln!(f, "# TYPE {} {}", family.name, family.kind)?;
+            for (tree, value) in &family.samples {
+                writeln!(f, "{}{{tree=\"{}\"}} {value}", family.name, escape(tree))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Escapes a label value, whose backslashes, double quotes and line feeds
+/// must be escaped.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Export for TreeStats {
+    fn export(&self, exporter: &mut Exporter, tree: &str) {
+        let help = "The number of keys in the tree.";
+        exporter.gauge("btree_keys", help, tree, self.keys as f64);
+        let help = "The number of levels in the tree.";
+        exporter.gauge("btree_height", help, tree, self.height as f64);
+        let help = "The number of leaf nodes.";
+        exporter.gauge("btree_leaf_nodes", help, tree, self.leaf_nodes as f64);
+        let help = "The number of intermediate nodes.";
+        exporter.gauge(
+            "btree_internal_nodes",
+            help,
+            tree,
+            self.internal_nodes as f64,
+        );
+        let help = "The bytes the nodes take up on the heap.";
+        exporter.gauge("btree_heap_bytes", help, tree, self.heap_bytes as f64);
+        let help = "The average fraction of the capacity of a node in use.";
+        let fill = if self.nodes() == 0 {
+            0.0
+        } else {
+            self.fill_factor()
+        };
+        exporter.gauge("btree_fill_ratio", help, tree, fill);
+    }
+}
+
+impl Export for CacheStats {
+    fn export(&self, exporter: &mut Exporter, tree: &str) {
+        let help = "The reads served from the page cache.";
+        exporter.counter("btree_cache_hits_total", help, tree, self.hits);
+        let help = "The reads which missed the page cache.";
+        exporter.counter("btree_cache_misses_total", help, tree, self.misses);
+        let help = "The pages evicted from the page cache.";
+        exporter.counter("btree_cache_evictions_total", help, tree, self.evictions);
+        let help = "The dirty pages written back by the page cache.";
+        exporter.counter("btree_cache_writebacks_total", help, tree, self.writebacks);
+        let help = "The fraction of the reads served from the page cache.";
+        exporter.gauge("btree_cache_hit_ratio", help, tree, self.hit_ratio());
+    }
+}
+
+impl Export for FileStats {
+    fn export(&self, exporter: &mut Exporter, tree: &str) {
+        let help = "The number of pages of the file.";
+        exporter.gauge("btree_file_pages", help, tree, self.total_pages as f64);
+        let help = "The number of pages of the file which are free.";
+        exporter.gauge("btree_file_free_pages", help, tree, self.free_pages as f64);
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Export for crate::btree::Metrics {
+    fn export(&self, exporter: &mut Exporter, tree: &str) {
+        let help = "The descents from the root which compared keys.";
+        exporter.counter("btree_descents_total", help, tree, self.descents);
+        let help = "The nodes visited by the descents.";
+        exporter.counter("btree_nodes_visited_total", help, tree, self.nodes_visited);
+        let help = "The keys compared by the descents.";
+        exporter.counter("btree_comparisons_total", help, tree, self.comparisons);
+        let help = "The nodes split by insertions.";
+        exporter.counter("btree_splits_total", help, tree, self.splits);
+        let help = "The nodes merged by removals.";
+        exporter.counter("btree_merges_total", help, tree, self.merges);
+        let help = "The keys moved between siblings by removals.";
+        exporter.counter("btree_rotations_total", help, tree, self.rotations);
+        let help = "The most nodes visited by a single descent.";
+        exporter.gauge("btree_max_depth", help, tree, self.max_depth as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samples_are_grouped_by_metric() {
+        let mut exporter = Exporter::new();
+        let stats = CacheStats {
+            hits: 3,
+            misses: 1,
+            evictions: 0,
+            writebacks: 2,
+        };
+        exporter.add("a", &stats);
+        exporter.add("b\"\n\\", &CacheStats::default());
+        exporter.gauge("btree_odd", "An odd value.", "a", f64::INFINITY);
+
+        let expected = "\
+# HELP btree_cache_hits_total The reads served from the page cache.
+# TYPE btree_cache_hits_total counter
+btree_cache_hits_total{tree=\"a\"} 3
+btree_cache_hits_total{tree=\"b\\\"\\n\\\\\"} 0
+# HELP btree_cache_misses_total The reads which missed the page cache.
+# TYPE btree_cache_misses_total counter
+btree_cache_misses_total{tree=\"a\"} 1
+btree_cache_misses_total{tree=\"b\\\"\\n\\\\\"} 0
+# HELP btree_cache_evictions_total The pages evicted from the page cache.
+# TYPE btree_cache_evictions_total counter
+btree_cache_evictions_total{tree=\"a\"} 0
+btree_cache_evictions_total{tree=\"b\\\"\\n\\\\\"} 0
+# HELP btree_cache_writebacks_total The dirty pages written back by the page cache.
+# TYPE btree_cache_writebacks_total counter
+btree_cache_writebacks_total{tree=\"a\"} 2
+btree_cache_writebacks_total{tree=\"b\\\"\\n\\\\\"} 0
+# HELP btree_cache_hit_ratio The fraction of the reads served from the page cache.
+# TYPE btree_cache_hit_ratio gauge
+btree_cache_hit_ratio{tree=\"a\"} 0.75
+btree_cache_hit_ratio{tree=\"b\\\"\\n\\\\\"} 0
+# HELP btree_odd An odd value.
+# TYPE btree_odd gauge
+btree_odd{tree=\"a\"} +Inf
+";
+        assert_eq!(exporter.to_string(), expected);
+    }
+}
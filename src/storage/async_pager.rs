@@ -0,0 +1,146 @@
+use super::{PageId, Pager};
+use crate::Result;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+/// Reads and writes fixed-size pages of a backing store, without blocking
+/// the task which waits for them.
+///
+/// The pages are numbered like the ones of a `Pager`. Pages are passed by
+/// value, so they can be handed to another thread while they are read or
+/// written.
+pub trait AsyncPager {
+    fn page_size(&self) -> usize;
+
+    /// Returns the number of pages in the store, including the free ones.
+    fn page_count(&self) -> u64;
+
+    /// Reads the page.
+    fn read_page(&self, id: PageId) -> impl Future<Output = Result<Vec<u8>>> + Send;
+
+    /// Writes the buffer, which is exactly a page long, into the page.
+    fn write_page(&mut self, id: PageId, page: Vec<u8>) -> impl Future<Output = Result<()>> + Send;
+
+    /// Allocates a page, whose contents are unspecified until it is written.
+    fn allocate(&mut self) -> impl Future<Output = Result<PageId>> + Send;
+
+    /// Gives the page back, so it can be allocated again.
+    fn free(&mut self, id: PageId) -> impl Future<Output = Result<()>> + Send;
+
+    /// Marks the end of a group of writes which must reach the backing store
+    /// together, or not at all.
+    fn commit(&mut self) -> impl Future<Output = Result<()>> + Send;
+
+    /// Makes sure that every written page has reached the backing store.
+    fn sync(&mut self) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// An `AsyncPager` which runs a `Pager` on the blocking thread pool of the
+/// tokio runtime, so its I/O never blocks the worker threads.
+///
+/// Every operation is a task of its own, and the operations of a pager run
+/// one at a time.
+pub struct BlockingPager<P> {
+    inner: Arc<Mutex<P>>,
+    page_size: usize,
+    page_count: u64,
+}
+
+impl<P: Pager + Send + 'static> BlockingPager<P> {
+    pub fn new(inner: P) -> Self {
+        BlockingPager {
+            page_size: inner.page_size(),
+            page_count: inner.page_count(),
+            inner: Arc::new(Mutex::new(inner)),
+        }
+    }
+
+    /// Returns the wrapped pager, once no operation on it is running.
+    pub fn into_inner(self) -> P {
+        match Arc::try_unwrap(self.inner) {
+            Ok(inner) => inner.into_inner().unwrap(),
+            Err(_) => panic!("an operation on the pager is still running"),
+        }
+    }
+
+    /// Runs the operation on the pager on the blocking thread pool.
+    async fn run<T: Send + 'static>(
+        &self,
+        operation: impl FnOnce(&mut P) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let inner = Arc::clone(&self.inner);
+        tokio::task::spawn_blocking(move || operation(&mut inner.lock().unwrap()))
+            .await
+            .map_err(std::io::Error::from)?
+    }
+}
+
+impl<P: Pager + Send + 'static> AsyncPager for BlockingPager<P> {
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    fn page_count(&self) -> u64 {
+        self.page_count
+    }
+
+    async fn read_page(&self, id: PageId) -> Result<Vec<u8>> {
+        self.run(move |pager| {
+            let mut page = vec![0; pager.page_size()];
+            pager.read_page(id, &mut page)?;
+            Ok(page)
+        })
+        .await
+    }
+
+    async fn write_page(&mut self, id: PageId, page: Vec<u8>) -> Result<()> {
+        self.run(move |pager| pager.write_page(id, &page)).await
+    }
+
+    async fn allocate(&mut self) -> Result<PageId> {
+        let (id, count) = self
+            .run(|pager| Ok((pager.allocate()?, pager.page_count())))
+            .await?;
+        self.page_count = count;
+        Ok(id)
+    }
+
+    async fn free(&mut self, id: PageId) -> Result<()> {
+        self.run(move |pager| pager.free(id)).await
+    }
+
+    async fn commit(&mut self) -> Result<()> {
+        self.run(|pager| pager.commit()).await
+    }
+
+    async fn sync(&mut self) -> Result<()> {
+        self.run(|pager| pager.sync()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryPager;
+
+    #[test]
+    fn test_blocking_pager() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let mut pager = BlockingPager::new(MemoryPager::new(64));
+            let a = pager.allocate().await.unwrap();
+            let b = pager.allocate().await.unwrap();
+            assert_eq!(pager.page_count(), 2);
+
+            pager.write_page(b, vec![7; 64]).await.unwrap();
+            assert_eq!(pager.read_page(b).await.unwrap(), vec![7; 64]);
+
+            pager.free(a).await.unwrap();
+            assert_eq!(pager.allocate().await.unwrap(), a);
+            assert!(pager.read_page(5).await.is_err());
+            assert_eq!(pager.into_inner().page_count(), 2);
+        });
+    }
+}
@@ -0,0 +1,446 @@
+//! A page cache in front of another pager.
+//!
+//! The cache holds up to a memory budget worth of pages, in frames. Reads
+//! are served from the frames when they can be, and written pages stay in
+//! their frames, marked dirty, until they are evicted or committed, so that
+//! repeated writes to a page reach the underlying pager once.
+
+use super::pager::out_of_bounds;
+use super::{FilePager, PageId, Pager};
+use crate::Result;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+
+/// The memory budget of a cache when none is given, in bytes.
+pub const DEFAULT_CACHE_BUDGET: usize = 8 << 20;
+
+/// The policy deciding which page leaves a full cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Eviction {
+    /// Evicts the page which was used least recently.
+    #[default]
+    Lru,
+    /// Sweeps the frames like the hand of a clock, evicting the first page
+    /// not used since the hand last passed it. It approximates `Lru` without
+    /// ordering the frames on every access.
+    Clock,
+}
+
+/// The counters of a cache, from the moment it was created.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The reads served from the cache.
+    pub hits: u64,
+    /// The reads which went to the underlying pager.
+    pub misses: u64,
+    /// The pages which left the cache to make room for another one.
+    pub evictions: u64,
+    /// The dirty pages written to the underlying pager.
+    pub writebacks: u64,
+}
+
+impl CacheStats {
+    /// Returns the fraction of the reads served from the cache.
+    pub fn hit_ratio(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            reads => self.hits as f64 / reads as f64,
+        }
+    }
+}
+
+/// A pager which caches the pages of another pager in memory.
+///
+/// Dirty pages are written to the underlying pager when they are evicted,
+/// and when the writes are committed or synced, before the underlying pager
+/// commits. Reads never write anything, so a read only evicts a clean page,
+/// and bypasses the cache if every frame is dirty.
+pub struct CachedPager<P = FilePager> {
+    inner: P,
+    pool: RefCell<Pool>,
+}
+
+struct Frame {
+    id: PageId,
+    page: Box<[u8]>,
+    dirty: bool,
+    /// The tick of the last use of the page, for `Eviction::Lru`.
+    used: u64,
+    /// Whether the page was used since the clock hand passed it, for
+    /// `Eviction::Clock`.
+    referenced: bool,
+}
+
+struct Pool {
+    eviction: Eviction,
+    capacity: usize,
+    frames: Vec<Frame>,
+    index: HashMap<PageId, usize>,
+    /// The frames, ordered by their last use.
+    recency: BTreeMap<u64, usize>,
+    tick: u64,
+    hand: usize,
+    stats: CacheStats,
+}
+
+impl<P: Pager> CachedPager<P> {
+    /// Creates a cache in front of the pager, holding as many pages as fit
+    /// into the budget, in bytes.
+    pub fn new(inner: P, budget: usize, eviction: Eviction) -> Self {
+        let capacity = budget / inner.page_size();
+        CachedPager {
+            inner,
+            pool: RefCell::new(Pool {
+                eviction,
+                capacity,
+                frames: Vec::with_capacity(capacity),
+                index: HashMap::new(),
+                recency: BTreeMap::new(),
+                tick: 0,
+                hand: 0,
+                stats: CacheStats::default(),
+            }),
+        }
+    }
+
+    /// Returns the counters of the cache.
+    pub fn stats(&self) -> CacheStats {
+        self.pool.borrow().stats
+    }
+
+    /// Writes the dirty pages, and returns the underlying pager. The pages
+    /// written to it directly are not seen through the cache.
+    pub(crate) fn inner_mut(&mut self) -> Result<&mut P> {
+        self.flush()?;
+        Ok(&mut self.inner)
+    }
+
+    /// Writes the dirty pages, and returns the underlying pager.
+    pub fn into_inner(mut self) -> Result<P> {
+        self.flush()?;
+        Ok(self.inner)
+    }
+
+    /// Writes every dirty page to the underlying pager, in page order.
+    fn flush(&mut self) -> Result<()> {
+        let pool = self.pool.get_mut();
+        let mut dirty: Vec<_> = pool.frames.iter_mut().filter(|frame| frame.dirty).collect();
+        dirty.sort_unstable_by_key(|frame| frame.id);
+
+        for frame in dirty {
+            self.inner.write_page(frame.id, &frame.page)?;
+            frame.dirty = false;
+            pool.stats.writebacks += 1;
+        }
+        Ok(())
+    }
+}
+
+impl Pool {
+    fn touch(&mut self, idx: usize) {
+        let frame = &mut self.frames[idx];
+        match self.eviction {
+            Eviction::Lru => {
+                self.recency.remove(&frame.used);
+                self.tick += 1;
+                frame.used = self.tick;
+                self.recency.insert(frame.used, idx);
+            }
+            Eviction::Clock => frame.referenced = true,
+        }
+    }
+
+    /// Returns the frame whose page should be evicted, skipping the dirty
+    /// ones unless `dirty` is set.
+    fn victim(&mut self, dirty: bool) -> Option<usize> {
+        let evictable = |frame: &Frame| dirty || !frame.dirty;
+        match self.eviction {
+            Eviction::Lru => self
+                .recency
+                .values()
+                .copied()
+                .find(|&idx| evictable(&self.frames[idx])),
+            Eviction::Clock => {
+                // Two turns clear every reference bit on the way, so a page is
+                // found in them if there is one which can be evicted.
+                for _ in 0..2 * self.frames.len() {
+                    let idx = self.hand;
+                    self.hand = (self.hand + 1) % self.frames.len();
+
+                    let frame = &mut self.frames[idx];
+                    if frame.referenced {
+                        frame.referenced = false;
+                    } else if evictable(frame) {
+                        return Some(idx);
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    /// Caches the page, evicting another one if the cache is full, and
+    /// returns the evicted page if it has to be written back. The page is
+    /// not cached if no page can be evicted.
+    fn insert(&mut self, id: PageId, page: &[u8], dirty: bool) -> Option<(PageId, Box<[u8]>)> {
+        let frame = Frame {
+            id,
+            page: page.into(),
+            dirty,
+            used: 0,
+            referenced: false,
+        };
+
+        let mut evicted = None;
+        let idx = if self.frames.len() < self.capacity {
+            self.frames.push(frame);
+            self.frames.len() - 1
+        } else {
+            let idx = self.victim(dirty)?;
+            let old = std::mem::replace(&mut self.frames[idx], frame);
+            self.stats.evictions += 1;
+            debug_event!(page = old.id, dirty = old.dirty, "page evicted");
+            self.index.remove(&old.id);
+            self.recency.remove(&old.used);
+            if old.dirty {
+                evicted = Some((old.id, old.page));
+            }
+            idx
+        };
+
+        self.index.insert(id, idx);
+        self.touch(idx);
+        evicted
+    }
+
+    /// Drops the page from the cache, without writing it back.
+    fn remove(&mut self, id: PageId) {
+        let Some(idx) = self.index.remove(&id) else {
+            return;
+        };
+        let frame = self.frames.swap_remove(idx);
+        self.recency.remove(&frame.used);
+
+        // The last frame took the place of the removed one.
+        if let Some(moved) = self.frames.get(idx) {
+            self.index.insert(moved.id, idx);
+            if self.eviction == Eviction::Lru {
+                self.recency.insert(moved.used, idx);
+            }
+        }
+        if self.hand >= self.frames.len() {
+            self.hand = 0;
+        }
+    }
+}
+
+impl<P: Pager> Pager for CachedPager<P> {
+    fn page_size(&self) -> usize {
+        self.inner.page_size()
+    }
+
+    fn page_count(&self) -> u64 {
+        self.inner.page_count()
+    }
+
+    fn read_page(&self, id: PageId, buf: &mut [u8]) -> Result<()> {
+        let mut pool = self.pool.borrow_mut();
+        if let Some(&idx) = pool.index.get(&id) {
+            pool.stats.hits += 1;
+            pool.touch(idx);
+            buf.copy_from_slice(&pool.frames[idx].page);
+            return Ok(());
+        }
+
+        pool.stats.misses += 1;
+        self.inner.read_page(id, buf)?;
+        pool.insert(id, buf, false);
+        Ok(())
+    }
+
+    fn write_page(&mut self, id: PageId, buf: &[u8]) -> Result<()> {
+        if id >= self.inner.page_count() {
+            return Err(out_of_bounds(id));
+        }
+
+        let pool = self.pool.get_mut();
+        if let Some(&idx) = pool.index.get(&id) {
+            pool.touch(idx);
+            let frame = &mut pool.frames[idx];
+            frame.page.copy_from_slice(buf);
+            frame.dirty = true;
+            return Ok(());
+        }
+
+        if pool.capacity == 0 {
+            return self.inner.write_page(id, buf);
+        }
+        if let Some((evicted, page)) = pool.insert(id, buf, true) {
+            pool.stats.writebacks += 1;
+            self.inner.write_page(evicted, &page)?;
+        }
+        Ok(())
+    }
+
+    fn allocate(&mut self) -> Result<PageId> {
+        self.inner.allocate()
+    }
+
+    fn free(&mut self, id: PageId) -> Result<()> {
+        self.pool.get_mut().remove(id);
+        self.inner.free(id)
+    }
+
+    fn prefetch(&self, ids: &[PageId]) {
+        let pool = self.pool.borrow();
+        let ids: Vec<_> = ids
+            .iter()
+            .copied()
+            .filter(|id| !pool.index.contains_key(id))
+            .collect();
+        self.inner.prefetch(&ids);
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        self.flush()?;
+        self.inner.commit()
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.flush()?;
+        self.inner.sync()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BTreeSet;
+    use crate::btree::{DiskBTreeSet, ReferenceBTreeSet};
+    use crate::storage::MemoryPager;
+
+    /// Returns a cache of the given number of 64 byte pages, in front of a
+    /// pager holding 8 pages, each filled with its id.
+    fn cache(frames: usize, eviction: Eviction) -> CachedPager<MemoryPager> {
+        let mut inner = MemoryPager::new(64);
+        for byte in 0..8 {
+            let id = inner.allocate().unwrap();
+            inner.write_page(id, &[byte; 64]).unwrap();
+        }
+        CachedPager::new(inner, frames * 64, eviction)
+    }
+
+    fn read(pager: &impl Pager, id: PageId) -> u8 {
+        let mut buf = [0; 64];
+        pager.read_page(id, &mut buf).unwrap();
+        buf[0]
+    }
+
+    #[test]
+    fn test_reads_are_counted() {
+        let pager = cache(2, Eviction::Lru);
+        for id in [0, 1, 0, 1, 2, 0] {
+            assert_eq!(read(&pager, id), id as u8);
+        }
+
+        let stats = pager.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (2, 4, 2));
+        assert_eq!(stats.hit_ratio(), 2.0 / 6.0);
+    }
+
+    #[test]
+    fn test_lru_evicts_the_least_recently_used_page() {
+        let pager = cache(3, Eviction::Lru);
+        for id in [0, 1, 2, 0, 3] {
+            read(&pager, id);
+        }
+
+        // Page 1 was evicted for page 3, as page 0 was used after it.
+        let misses = pager.stats().misses;
+        read(&pager, 0);
+        read(&pager, 2);
+        assert_eq!(pager.stats().misses, misses);
+        read(&pager, 1);
+        assert_eq!(pager.stats().misses, misses + 1);
+    }
+
+    #[test]
+    fn test_clock_gives_used_pages_a_second_chance() {
+        let pager = cache(3, Eviction::Clock);
+        for id in [0, 1, 2] {
+            read(&pager, id);
+        }
+        // Every page is referenced, so the hand clears them all, and comes
+        // back to evict page 0. Page 1 then has no reference left.
+        read(&pager, 3);
+        read(&pager, 2);
+        read(&pager, 4);
+
+        let misses = pager.stats().misses;
+        read(&pager, 2);
+        read(&pager, 3);
+        assert_eq!(pager.stats().misses, misses);
+        read(&pager, 1);
+        assert_eq!(pager.stats().misses, misses + 1);
+    }
+
+    #[test]
+    fn test_dirty_pages_are_written_back() {
+        let mut pager = cache(2, Eviction::Lru);
+        pager.write_page(0, &[10; 64]).unwrap();
+        pager.write_page(0, &[20; 64]).unwrap();
+        pager.write_page(1, &[21; 64]).unwrap();
+        assert_eq!(read(&pager.inner, 0), 0);
+        assert_eq!(read(&pager, 0), 20);
+
+        // Reads only evict clean pages, so this one bypasses the cache.
+        assert_eq!(read(&pager, 2), 2);
+        assert_eq!(pager.stats().writebacks, 0);
+
+        // Writes evict dirty pages, and write them back.
+        pager.write_page(3, &[23; 64]).unwrap();
+        assert_eq!(pager.stats().writebacks, 1);
+        assert_eq!(read(&pager.inner, 1), 21);
+
+        pager.commit().unwrap();
+        assert_eq!(read(&pager.inner, 0), 20);
+        assert_eq!(read(&pager.inner, 3), 23);
+
+        // Freed pages are dropped from the cache.
+        pager.free(3).unwrap();
+        pager.write_page(0, &[30; 64]).unwrap();
+        let inner = pager.into_inner().unwrap();
+        assert_eq!(read(&inner, 0), 30);
+        assert_eq!(read(&inner, 3), 23);
+
+        let mut pager = cache(1, Eviction::Lru);
+        assert!(pager.write_page(8, &[0; 64]).is_err());
+    }
+
+    #[test]
+    fn test_tree_through_small_cache() {
+        for eviction in [Eviction::Lru, Eviction::Clock] {
+            let pager = CachedPager::new(MemoryPager::new(256), 4 * 256, eviction);
+            let mut tree = DiskBTreeSet::<u32, 3, _>::open(pager).unwrap();
+            let mut reference = ReferenceBTreeSet::new();
+
+            for i in 0..2000u32 {
+                let key = i * 7919 % 1000;
+                if i % 3 == 0 {
+                    assert_eq!(tree.remove(&key).ok(), reference.remove(&key).ok());
+                } else {
+                    assert_eq!(tree.insert(key).is_ok(), reference.insert(key).is_ok());
+                }
+            }
+
+            // Reopening the underlying pager decodes every page again.
+            let pager = tree.into_pager();
+            assert!(pager.stats().evictions > 0);
+            let tree = DiskBTreeSet::<u32, 3, _>::open(pager.into_inner().unwrap()).unwrap();
+            assert_eq!(
+                tree.range(..).unwrap(),
+                reference.iter().collect::<Vec<_>>()
+            );
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Encryption of pages at rest, in front of another pager.
+//!
+//! Every page is encrypted with AES-256-GCM under a fresh random nonce each
+//! time it is written, and the id of the page is authenticated along with
+//! it, so a page cannot be moved to another place unnoticed. A page of the
+//! underlying pager holds:
+//!
+//! ```text
+//! nonce (12) | encrypted page (page size - 28) | tag (16)
+//! ```
+//!
+//! so the pages seen through the encrypting pager are 28 bytes smaller.
+
+use super::{PageId, Pager};
+use crate::{Error, Result};
+use aes_gcm::aead::{AeadCore, AeadInPlace, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce, Tag};
+
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+
+/// The number of bytes of every page taken by the nonce and the tag.
+pub const ENCRYPTION_OVERHEAD: usize = NONCE_SIZE + TAG_SIZE;
+
+/// A pager which encrypts the pages before they reach the underlying pager,
+/// and authenticates them when they are read back.
+///
+/// A page read with another key, or changed by anyone else, fails to read
+/// with `Error::DecryptionFailed`. The key should be random, such as one
+/// derived from a password with a key derivation function.
+pub struct EncryptedPager<P> {
+    inner: P,
+    cipher: Aes256Gcm,
+}
+
+impl<P: Pager> EncryptedPager<P> {
+    /// Encrypts the pages of the pager with the given 256-bit key.
+    pub fn new(inner: P, key: &[u8; 32]) -> Self {
+        EncryptedPager {
+            inner,
+            cipher: Aes256Gcm::new(key.into()),
+        }
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> P {
+        self.inner
+    }
+}
+
+impl<P: Pager> Pager for EncryptedPager<P> {
+    fn page_size(&self) -> usize {
+        self.inner.page_size() - ENCRYPTION_OVERHEAD
+    }
+
+    fn page_count(&self) -> u64 {
+        self.inner.page_count()
+    }
+
+    fn read_page(&self, id: PageId, buf: &mut [u8]) -> Result<()> {
+        let page = self.inner.view_page(id)?;
+        let (nonce, rest) = page.split_at(NONCE_SIZE);
+        let (body, tag) = rest.split_at(rest.len() - TAG_SIZE);
+
+        buf.copy_from_slice(body);
+        self.cipher
+            .decrypt_in_place_detached(
+                Nonce::from_slice(nonce),
+                &id.to_le_bytes(),
+                buf,
+                Tag::from_slice(tag),
+            )
+            .map_err(|_| Error::DecryptionFailed { page: id })
+    }
+
+    fn write_page(&mut self, id: PageId, buf: &[u8]) -> Result<()> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut page = Vec::with_capacity(self.inner.page_size());
+        page.extend_from_slice(&nonce);
+        page.extend_from_slice(buf);
+
+        let tag = self
+            .cipher
+            .encrypt_in_place_detached(&nonce, &id.to_le_bytes(), &mut page[NONCE_SIZE..])
+            .expect("a page is far shorter than the limit of AES-GCM");
+        page.extend_from_slice(&tag);
+        self.inner.write_page(id, &page)
+    }
+
+    fn allocate(&mut self) -> Result<PageId> {
+        self.inner.allocate()
+    }
+
+    fn free(&mut self, id: PageId) -> Result<()> {
+        self.inner.free(id)
+    }
+
+    fn prefetch(&self, ids: &[PageId]) {
+        self.inner.prefetch(ids);
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        self.inner.commit()
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.inner.sync()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BTreeSet;
+    use crate::btree::DiskBTreeSet;
+    use crate::storage::MemoryPager;
+
+    const KEY: [u8; 32] = [7; 32];
+
+    #[test]
+    fn test_pages_are_encrypted() {
+        let mut pager = EncryptedPager::new(MemoryPager::new(128), &KEY);
+        assert_eq!(pager.page_size(), 100);
+        let id = pager.allocate().unwrap();
+        pager.write_page(id, &[42; 100]).unwrap();
+
+        let mut buf = [0; 100];
+        pager.read_page(id, &mut buf).unwrap();
+        assert_eq!(buf, [42; 100]);
+
+        // Neither the contents nor the page are recognizable underneath.
+        let raw = pager.inner().view_page(id).unwrap().into_owned();
+        assert!(!raw.windows(8).any(|window| window == [42; 8]));
+        pager.write_page(id, &[42; 100]).unwrap();
+        assert_ne!(pager.inner().view_page(id).unwrap().into_owned(), raw);
+    }
+
+    #[test]
+    fn test_wrong_key_and_tampering_are_detected() {
+        let pager = EncryptedPager::new(MemoryPager::new(512), &KEY);
+        let mut tree = DiskBTreeSet::<u64, 4, _>::open(pager).unwrap();
+        for key in 0..500 {
+            tree.insert(key).unwrap();
+        }
+
+        let wrong = EncryptedPager::new(tree.into_pager().into_inner(), &[8; 32]);
+        let mut buf = vec![0; wrong.page_size()];
+        assert!(matches!(
+            wrong.read_page(1, &mut buf),
+            Err(Error::DecryptionFailed { page: 1 })
+        ));
+
+        // Flip a bit of one page, and copy another page over a third one.
+        let mut inner = wrong.into_inner();
+        let mut page = inner.view_page(2).unwrap().into_owned();
+        page[100] ^= 1;
+        inner.write_page(2, &page).unwrap();
+        let page = inner.view_page(4).unwrap().into_owned();
+        inner.write_page(3, &page).unwrap();
+
+        let pager = EncryptedPager::new(inner, &KEY);
+        pager.read_page(1, &mut buf).unwrap();
+        pager.read_page(4, &mut buf).unwrap();
+        for id in [2, 3] {
+            assert!(matches!(
+                pager.read_page(id, &mut buf),
+                Err(Error::DecryptionFailed { page }) if page == id
+            ));
+        }
+
+        let tree = DiskBTreeSet::<u64, 4, _>::open(pager).unwrap();
+        assert!(matches!(
+            tree.range(..),
+            Err(Error::DecryptionFailed { .. })
+        ));
+        let pager = EncryptedPager::new(tree.into_pager().into_inner(), &[8; 32]);
+        let err = DiskBTreeSet::<u64, 4, _>::open(pager).err().unwrap();
+        assert!(matches!(
+            err.root_cause(),
+            Error::DecryptionFailed { page: 0 }
+        ));
+    }
+}
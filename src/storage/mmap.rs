@@ -0,0 +1,119 @@
+use super::PageId;
+use super::pager::{Pager, out_of_bounds};
+use crate::{Context, Error, Result};
+use memmap2::Mmap;
+use std::borrow::Cow;
+use std::fs::File;
+use std::path::Path;
+
+/// A read-only pager which maps its file into memory, so pages are accessed
+/// in place instead of being read with a system call each.
+///
+/// Every method which would change the file returns `Error::ReadOnly`. The
+/// file must not be modified by anyone else while it is mapped.
+pub struct MmapPager {
+    map: Mmap,
+    page_size: usize,
+}
+
+impl MmapPager {
+    /// Maps the file at the given path, which must already exist.
+    pub fn open(path: impl AsRef<Path>, page_size: usize) -> Result<Self> {
+        let path = path.as_ref();
+        Self::open_file(path, page_size).with_key("MmapPager::open", path)
+    }
+
+    fn open_file(path: &Path, page_size: usize) -> Result<Self> {
+        let file = File::open(path)?;
+
+        // SAFETY: The map is read-only, and the pager requires that the file
+        // is not modified while it is mapped.
+        let map = unsafe { Mmap::map(&file)? };
+
+        if map.len() % page_size != 0 {
+            return Err(Error::CorruptPage {
+                reason: format!(
+                    "file length {} is not a multiple of the page size",
+                    map.len()
+                ),
+            });
+        }
+
+        Ok(MmapPager { map, page_size })
+    }
+
+    fn page(&self, id: PageId) -> Result<&[u8]> {
+        let start = usize::try_from(id)
+            .ok()
+            .and_then(|id| id.checked_mul(self.page_size))
+            .filter(|&start| start < self.map.len())
+            .ok_or_else(|| out_of_bounds(id))?;
+        Ok(&self.map[start..start + self.page_size])
+    }
+}
+
+impl Pager for MmapPager {
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    fn page_count(&self) -> u64 {
+        (self.map.len() / self.page_size) as u64
+    }
+
+    fn read_page(&self, id: PageId, buf: &mut [u8]) -> Result<()> {
+        buf.copy_from_slice(self.page(id)?);
+        Ok(())
+    }
+
+    fn view_page(&self, id: PageId) -> Result<Cow<'_, [u8]>> {
+        self.page(id).map(Cow::Borrowed)
+    }
+
+    fn write_page(&mut self, _id: PageId, _buf: &[u8]) -> Result<()> {
+        Err(Error::ReadOnly)
+    }
+
+    fn allocate(&mut self) -> Result<PageId> {
+        Err(Error::ReadOnly)
+    }
+
+    fn free(&mut self, _id: PageId) -> Result<()> {
+        Err(Error::ReadOnly)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::FilePager;
+
+    #[test]
+    fn test_mapped_pages_match_the_file() {
+        let path = crate::tests::temp_path("mmap-pager");
+
+        let mut pager = FilePager::open(&path, 64).unwrap();
+        for byte in 0..3 {
+            let id = pager.allocate().unwrap();
+            pager.write_page(id, &[byte; 64]).unwrap();
+        }
+        pager.sync().unwrap();
+
+        let mut pager = MmapPager::open(&path, 64).unwrap();
+        assert_eq!(pager.page_count(), 3);
+        for id in 0..3 {
+            let page = pager.view_page(id).unwrap();
+            assert!(matches!(page, Cow::Borrowed(_)));
+            assert!(page.iter().all(|&byte| byte == id as u8));
+        }
+        assert!(pager.view_page(3).is_err());
+
+        assert!(matches!(pager.allocate(), Err(Error::ReadOnly)));
+        assert!(matches!(
+            pager.write_page(0, &[0; 64]),
+            Err(Error::ReadOnly)
+        ));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
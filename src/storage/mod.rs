@@ -0,0 +1,43 @@
+#[cfg(feature = "async")]
+mod async_pager;
+mod cache;
+#[cfg(feature = "encryption")]
+mod encrypted;
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "s3")]
+mod object;
+mod page;
+mod pager;
+#[cfg(feature = "s3")]
+mod s3;
+#[cfg(all(feature = "io_uring", target_os = "linux"))]
+mod uring;
+mod wal;
+
+#[cfg(feature = "async")]
+pub use async_pager::{AsyncPager, BlockingPager};
+pub use cache::{CacheStats, CachedPager, DEFAULT_CACHE_BUDGET, Eviction};
+#[cfg(feature = "encryption")]
+pub use encrypted::{ENCRYPTION_OVERHEAD, EncryptedPager};
+#[cfg(feature = "mmap")]
+pub use mmap::MmapPager;
+#[cfg(feature = "s3")]
+pub use object::{MemoryStore, ObjectPager, ObjectStore};
+pub(crate) use page::crc32;
+pub use page::{
+    Compression, FileHeader, FixedSizeKey, NodePage, PAGE_HEADER_SIZE, PageId, PageKey,
+    decode_free_list, decode_overflow, decode_page, decode_page_spilled, encode_free_list,
+    encode_overflow, encode_page, encode_page_spilling, encode_page_with, free_list_capacity,
+    max_cell_size, overflow_capacity,
+};
+#[cfg(any(unix, windows))]
+pub use pager::FilePageReader;
+pub use pager::{
+    DEFAULT_PAGE_SIZE, FilePager, MemoryPageReader, MemoryPager, PageReader, Pager, SharedPager,
+};
+#[cfg(feature = "s3")]
+pub use s3::S3Store;
+#[cfg(all(feature = "io_uring", target_os = "linux"))]
+pub use uring::UringPager;
+pub use wal::{DEFAULT_CHECKPOINT_THRESHOLD, WalPager};
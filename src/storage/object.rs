@@ -0,0 +1,368 @@
+use super::PageId;
+use super::pager::{Pager, out_of_bounds};
+use crate::{Error, Result};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// A store of named blobs, such as a bucket of an object store.
+pub trait ObjectStore {
+    /// Returns the contents of the object, or `None` if there is no object
+    /// with the given key.
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Stores the object, replacing the one with the same key.
+    fn put(&mut self, key: &str, bytes: &[u8]) -> Result<()>;
+
+    /// Removes the object, if there is one.
+    fn delete(&mut self, key: &str) -> Result<()>;
+}
+
+/// An object store which keeps its objects in memory. It is mostly useful
+/// for tests.
+#[derive(Default)]
+pub struct MemoryStore {
+    objects: HashMap<String, Vec<u8>>,
+    gets: std::cell::Cell<u64>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of objects in the store.
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Returns the number of times an object was fetched.
+    pub fn gets(&self) -> u64 {
+        self.gets.get()
+    }
+}
+
+impl ObjectStore for MemoryStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.gets.set(self.gets.get() + 1);
+        Ok(self.objects.get(key).cloned())
+    }
+
+    fn put(&mut self, key: &str, bytes: &[u8]) -> Result<()> {
+        self.objects.insert(key.to_owned(), bytes.to_vec());
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &str) -> Result<()> {
+        self.objects.remove(key);
+        Ok(())
+    }
+}
+
+/// A pager which stores every page as an object of an `ObjectStore`, and
+/// keeps the pages it fetched in a local file of bounded size.
+///
+/// The pages are stored under the given prefix, as `{prefix}/{id}` with the
+/// id in hexadecimal, next to a `{prefix}/meta` object holding the page size
+/// and the number of pages. Writes go straight to the store, and the number
+/// of pages is stored by `commit`. The local cache only lives as long as the
+/// pager, and evicts the pages not read recently, with the clock policy.
+///
+/// Every page which is not in the cache costs a request to the store, so the
+/// pager suits trees which are mostly read. Like with `FilePager`, the list
+/// of free pages is only kept in memory.
+pub struct ObjectPager<S> {
+    store: S,
+    prefix: String,
+    page_size: usize,
+    page_count: u64,
+    stored_count: u64,
+    free: Vec<PageId>,
+    cache: RefCell<LocalCache>,
+}
+
+impl<S: ObjectStore> ObjectPager<S> {
+    /// Opens the pages stored under the prefix, which are created if there
+    /// are none yet. Up to `cache_pages` pages are kept in a file at
+    /// `cache_path`, which is overwritten.
+    pub fn open(
+        store: S,
+        prefix: impl Into<String>,
+        page_size: usize,
+        cache_path: impl AsRef<Path>,
+        cache_pages: usize,
+    ) -> Result<Self> {
+        let prefix = prefix.into();
+        let page_count = match store.get(&format!("{prefix}/meta"))? {
+            None => 0,
+            Some(meta) => {
+                let field = |at: usize| {
+                    meta.get(at..at + 8)
+                        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+                        .ok_or_else(|| Error::CorruptPage {
+                            reason: "the meta object is truncated".to_owned(),
+                        })
+                };
+                if field(0)? != page_size as u64 {
+                    return Err(Error::CorruptPage {
+                        reason: format!(
+                            "the store has {} byte pages, expected {page_size}",
+                            field(0)?
+                        ),
+                    });
+                }
+                field(8)?
+            }
+        };
+
+        Ok(ObjectPager {
+            store,
+            prefix,
+            page_size,
+            page_count,
+            stored_count: page_count,
+            free: Vec::new(),
+            cache: RefCell::new(LocalCache::create(cache_path, page_size, cache_pages)?),
+        })
+    }
+
+    /// Returns the object store the pages are kept in.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Consumes the pager, returning its object store.
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    fn key(&self, id: PageId) -> String {
+        format!("{}/{id:016x}", self.prefix)
+    }
+
+    /// Stores the number of pages, if it changed since it was last stored.
+    fn store_meta(&mut self) -> Result<()> {
+        if self.page_count == self.stored_count {
+            return Ok(());
+        }
+        let mut meta = [0; 16];
+        meta[..8].copy_from_slice(&(self.page_size as u64).to_le_bytes());
+        meta[8..].copy_from_slice(&self.page_count.to_le_bytes());
+        self.store.put(&format!("{}/meta", self.prefix), &meta)?;
+        self.stored_count = self.page_count;
+        Ok(())
+    }
+}
+
+impl<S: ObjectStore> Pager for ObjectPager<S> {
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    fn page_count(&self) -> u64 {
+        self.page_count
+    }
+
+    fn read_page(&self, id: PageId, buf: &mut [u8]) -> Result<()> {
+        if id >= self.page_count {
+            return Err(out_of_bounds(id));
+        }
+        let mut cache = self.cache.borrow_mut();
+        if cache.read(id, buf)? {
+            return Ok(());
+        }
+
+        // A page which was allocated but never written reads as zeros.
+        match self.store.get(&self.key(id))? {
+            Some(page) if page.len() == self.page_size => buf.copy_from_slice(&page),
+            Some(page) => {
+                return Err(Error::CorruptPage {
+                    reason: format!("the object of page {id} is {} bytes long", page.len()),
+                });
+            }
+            None => buf.fill(0),
+        }
+        cache.insert(id, buf)
+    }
+
+    fn write_page(&mut self, id: PageId, buf: &[u8]) -> Result<()> {
+        if id >= self.page_count {
+            return Err(out_of_bounds(id));
+        }
+        self.store.put(&self.key(id), buf)?;
+        self.cache.get_mut().insert(id, buf)
+    }
+
+    fn allocate(&mut self) -> Result<PageId> {
+        if let Some(id) = self.free.pop() {
+            return Ok(id);
+        }
+        self.page_count += 1;
+        Ok(self.page_count - 1)
+    }
+
+    fn free(&mut self, id: PageId) -> Result<()> {
+        if id >= self.page_count {
+            return Err(out_of_bounds(id));
+        }
+        self.free.push(id);
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        self.store_meta()
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.store_meta()
+    }
+}
+
+/// Pages kept in the slots of a local file.
+struct LocalCache {
+    file: File,
+    page_size: usize,
+    /// The page in every slot, and whether it was read since the clock hand
+    /// last passed the slot.
+    slots: Vec<Option<(PageId, bool)>>,
+    index: HashMap<PageId, usize>,
+    hand: usize,
+}
+
+impl LocalCache {
+    fn create(path: impl AsRef<Path>, page_size: usize, pages: usize) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        Ok(LocalCache {
+            file,
+            page_size,
+            slots: vec![None; pages],
+            index: HashMap::new(),
+            hand: 0,
+        })
+    }
+
+    /// Reads the page into the buffer if it is cached, and returns whether it
+    /// was.
+    fn read(&mut self, id: PageId, buf: &mut [u8]) -> Result<bool> {
+        let Some(&slot) = self.index.get(&id) else {
+            return Ok(false);
+        };
+        self.slots[slot] = Some((id, true));
+        self.seek_to(slot)?.read_exact(buf)?;
+        Ok(true)
+    }
+
+    /// Caches the page, evicting another one if every slot is taken.
+    fn insert(&mut self, id: PageId, buf: &[u8]) -> Result<()> {
+        if self.slots.is_empty() {
+            return Ok(());
+        }
+        let slot = match self.index.get(&id) {
+            Some(&slot) => slot,
+            None => {
+                let slot = self.evict();
+                self.index.insert(id, slot);
+                slot
+            }
+        };
+        self.slots[slot] = Some((id, true));
+        self.seek_to(slot)?.write_all(buf)?;
+        Ok(())
+    }
+
+    /// Frees a slot, and returns it.
+    fn evict(&mut self) -> usize {
+        loop {
+            let slot = self.hand;
+            self.hand = (self.hand + 1) % self.slots.len();
+            match &mut self.slots[slot] {
+                None => return slot,
+                Some((_, referenced)) if *referenced => *referenced = false,
+                Some((id, _)) => {
+                    self.index.remove(id);
+                    self.slots[slot] = None;
+                    return slot;
+                }
+            }
+        }
+    }
+
+    fn seek_to(&self, slot: usize) -> Result<&File> {
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start((slot * self.page_size) as u64))?;
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BTreeSet;
+    use crate::btree::DiskBTreeSet;
+    use std::path::PathBuf;
+
+    fn cache_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("btree-object-{name}-{}", std::process::id()))
+    }
+
+    #[test]
+    fn test_object_pager() {
+        let path = cache_path("pager");
+        let mut pager = ObjectPager::open(MemoryStore::new(), "tree", 64, &path, 1).unwrap();
+        let a = pager.allocate().unwrap();
+        let b = pager.allocate().unwrap();
+        pager.write_page(a, &[1; 64]).unwrap();
+        pager.commit().unwrap();
+
+        let mut buf = [7; 64];
+        pager.read_page(b, &mut buf).unwrap();
+        assert_eq!(buf, [0; 64]);
+        pager.read_page(a, &mut buf).unwrap();
+        assert_eq!(buf, [1; 64]);
+        assert!(pager.read_page(2, &mut buf).is_err());
+
+        let store = pager.into_store();
+        assert_eq!(store.len(), 2);
+        assert!(matches!(
+            ObjectPager::open(store, "tree", 128, &path, 1),
+            Err(Error::CorruptPage { .. })
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_tree_over_object_pager() {
+        let path = cache_path("tree");
+        let pager = ObjectPager::open(MemoryStore::new(), "tree", 512, &path, 8).unwrap();
+        let mut tree = DiskBTreeSet::<u64, 4, _>::open(pager).unwrap();
+        for key in 0..2000 {
+            tree.insert(key * 7919 % 2000).unwrap();
+        }
+        let store = tree.into_pager().into_store();
+
+        // The tree is read back through a cache far smaller than it is.
+        let pager = ObjectPager::open(store, "tree", 512, &path, 8).unwrap();
+        let tree = DiskBTreeSet::<u64, 4, _>::open(pager).unwrap();
+        assert!(tree.range(..).unwrap().into_iter().copied().eq(0..2000));
+
+        // Pages in the cache are not fetched again.
+        let pager = tree.into_pager();
+        let mut buf = [0; 512];
+        pager.read_page(1, &mut buf).unwrap();
+        let gets = pager.store().gets();
+        pager.read_page(1, &mut buf).unwrap();
+        assert_eq!(pager.store().gets(), gets);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
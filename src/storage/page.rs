@@ -0,0 +1,1221 @@
+//! The on-disk format of a B-tree file.
+//!
+//! A file is a sequence of fixed-size pages. The first page holds the file
+//! header, and every other page holds a single node:
+//!
+//! ```text
+//! file header   magic (8) | version (4) | page size (4) | key size (4)
+//!               | root page id (8) | page count (8)
+//!               | free list page id (8) | checksum (4)
+//!
+//! node page     kind (1) | flags (1) | key count (2) | checksum (4)
+//!               | child page ids (8 each, intermediate nodes only)
+//!               | keys (key size each)
+//! ```
+//!
+//! All integers are little-endian, and the checksums are CRC-32 over the
+//! rest of the header and of the page respectively. The header of version 1
+//! files has no free list page id.
+//!
+//! The pages which are free are listed in a chain of free list pages, which
+//! starts at the page named in the header. The free list pages are free
+//! pages themselves, and list themselves too:
+//!
+//! ```text
+//! free list     kind (1) | unused (1) | id count (2) | checksum (4)
+//!               | next free list page id (8) | free page ids (8 each)
+//! ```
+//!
+//! The keys of a tree of byte strings are stored after the prefix they all
+//! share, which is stored once, as cells of varying size holding the rest of
+//! every key. A cell starts with the length of the rest of the key, and of
+//! the part of it which is stored in the node. A key which would make its
+//! cell larger than the node has room for per key continues in a chain of
+//! overflow pages, which the cell points to:
+//!
+//! ```text
+//! keys          prefix length (2) | prefix | key cells
+//!
+//! key cell      length (4) | stored length (2) | stored bytes
+//!               | first overflow page id (8, only if the key overflows)
+//!
+//! overflow      kind (1) | unused (1) | byte count (2) | checksum (4)
+//!               | next overflow page id (8) | bytes
+//! ```
+//!
+//! The flags of a node page tell how its children and keys are compressed,
+//! if they are. A compressed page stores the size of the compressed bytes
+//! after its header, followed by the compressed bytes themselves:
+//!
+//! ```text
+//! compressed    kind (1) | flags (1) | key count (2) | checksum (4)
+//!               | compressed size (4) | compressed children and keys
+//! ```
+//!
+//! Compressed pages of byte string keys also store the size of the children
+//! and keys before compression, after the compressed size.
+//!
+//! Every page is compressed on its own, so compressed and uncompressed
+//! pages can be mixed in a file, and a page is only stored compressed if
+//! that makes it smaller.
+
+use crate::{Error, Result};
+use std::borrow::Cow;
+
+/// The identifier of a page, which is its index in the file.
+pub type PageId = u64;
+
+/// The size of the header at the start of every node page.
+pub const PAGE_HEADER_SIZE: usize = 8;
+
+const MAGIC: &[u8; 8] = b"BTREEDB\0";
+const FILE_HEADER_SIZE: usize = 48;
+const FILE_HEADER_V1_SIZE: usize = 40;
+
+const LEAF: u8 = 1;
+const INTERMEDIATE: u8 = 2;
+const FREE_LIST: u8 = 3;
+const OVERFLOW: u8 = 4;
+
+/// The size of a key cell without its bytes.
+const CELL_HEADER_SIZE: usize = 6;
+
+const UNCOMPRESSED: u8 = 0;
+const LZ4: u8 = 1;
+const ZSTD: u8 = 2;
+
+/// How the contents of node pages are compressed when they are written.
+/// Pages are read back whatever compression they were written with, as long
+/// as the feature of that compression is enabled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    #[cfg(feature = "lz4")]
+    Lz4,
+    #[cfg(feature = "zstd")]
+    Zstd { level: i32 },
+}
+
+impl Compression {
+    /// Returns the flags marking the pages compressed this way, and the
+    /// compressed bytes.
+    #[cfg_attr(not(any(feature = "lz4", feature = "zstd")), allow(unused_variables))]
+    fn compress(self, bytes: &[u8]) -> Option<(u8, Vec<u8>)> {
+        match self {
+            Compression::None => None,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Some((LZ4, lz4_flex::block::compress(bytes))),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { level } => Some((ZSTD, zstd::bulk::compress(bytes, level).ok()?)),
+        }
+    }
+}
+
+/// Decompresses the bytes of a page with the given flags, which decompress
+/// into exactly `len` bytes.
+#[cfg_attr(not(any(feature = "lz4", feature = "zstd")), allow(unused_variables))]
+fn decompress(flags: u8, bytes: &[u8], len: usize) -> Result<Vec<u8>> {
+    let decompressed: core::result::Result<Vec<u8>, &str> = match flags {
+        #[cfg(feature = "lz4")]
+        LZ4 => lz4_flex::block::decompress(bytes, len).map_err(|_| "page does not decompress"),
+        #[cfg(feature = "zstd")]
+        ZSTD => zstd::bulk::decompress(bytes, len).map_err(|_| "page does not decompress"),
+        #[cfg(not(feature = "lz4"))]
+        LZ4 => Err("page is compressed with lz4, which is not enabled"),
+        #[cfg(not(feature = "zstd"))]
+        ZSTD => Err("page is compressed with zstd, which is not enabled"),
+        _ => Err("page has unknown flags"),
+    };
+
+    match decompressed {
+        Ok(decompressed) if decompressed.len() == len => Ok(decompressed),
+        Ok(_) => Err(corrupt("page decompresses to the wrong size")),
+        Err(reason) => Err(corrupt(reason)),
+    }
+}
+
+/// A key which is encoded into the same number of bytes, whatever its value.
+pub trait FixedSizeKey: Sized {
+    const SIZE: usize;
+
+    /// Encodes the key into the buffer, which is exactly `SIZE` bytes long.
+    fn encode(&self, buf: &mut [u8]);
+
+    /// Decodes a key from the buffer, which is exactly `SIZE` bytes long.
+    fn decode(buf: &[u8]) -> Self;
+}
+
+macro_rules! impl_fixed_size_key {
+    ($($ty:ty),*) => {
+        $(
+            impl FixedSizeKey for $ty {
+                const SIZE: usize = size_of::<$ty>();
+
+                fn encode(&self, buf: &mut [u8]) {
+                    buf.copy_from_slice(&self.to_le_bytes());
+                }
+
+                fn decode(buf: &[u8]) -> Self {
+                    <$ty>::from_le_bytes(buf.try_into().unwrap())
+                }
+            }
+        )*
+    };
+}
+
+impl_fixed_size_key!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+/// `usize` is encoded as a `u64`, so files are portable between platforms.
+impl FixedSizeKey for usize {
+    const SIZE: usize = size_of::<u64>();
+
+    fn encode(&self, buf: &mut [u8]) {
+        (*self as u64).encode(buf);
+    }
+
+    fn decode(buf: &[u8]) -> Self {
+        u64::decode(buf) as usize
+    }
+}
+
+impl<const N: usize> FixedSizeKey for [u8; N] {
+    const SIZE: usize = N;
+
+    fn encode(&self, buf: &mut [u8]) {
+        buf.copy_from_slice(self);
+    }
+
+    fn decode(buf: &[u8]) -> Self {
+        buf.try_into().unwrap()
+    }
+}
+
+/// A key which can be stored in a node page: either a `FixedSizeKey`, or a
+/// byte string of any length.
+pub trait PageKey: Sized {
+    /// The number of bytes every key is encoded into, or `None` if keys are
+    /// stored in cells of varying size.
+    const SIZE: Option<usize>;
+
+    /// Returns the number of bytes the key is encoded into.
+    fn encoded_len(&self) -> usize;
+
+    /// Encodes the key into the buffer, which is `encoded_len` bytes long.
+    fn encode_into(&self, buf: &mut [u8]);
+
+    /// Decodes a key from the buffer it was encoded into.
+    fn decode_from(buf: &[u8]) -> Self;
+}
+
+impl<K: FixedSizeKey> PageKey for K {
+    const SIZE: Option<usize> = Some(K::SIZE);
+
+    fn encoded_len(&self) -> usize {
+        K::SIZE
+    }
+
+    fn encode_into(&self, buf: &mut [u8]) {
+        self.encode(buf);
+    }
+
+    fn decode_from(buf: &[u8]) -> Self {
+        K::decode(buf)
+    }
+}
+
+/// Byte strings are ordered like `memcmp`, and may be longer than a page.
+impl PageKey for Vec<u8> {
+    const SIZE: Option<usize> = None;
+
+    fn encoded_len(&self) -> usize {
+        self.len()
+    }
+
+    fn encode_into(&self, buf: &mut [u8]) {
+        buf.copy_from_slice(self);
+    }
+
+    fn decode_from(buf: &[u8]) -> Self {
+        buf.to_vec()
+    }
+}
+
+/// The header stored in the first page of a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileHeader {
+    pub page_size: u32,
+    pub key_size: u32,
+    pub root: Option<PageId>,
+    pub page_count: u64,
+    /// The first page of the free list, if any page is free.
+    pub free_list: Option<PageId>,
+}
+
+impl FileHeader {
+    pub const VERSION: u32 = 2;
+
+    /// Encodes the header into the start of the given page.
+    pub fn encode(&self, page: &mut [u8]) {
+        let mut writer = Writer::new(page);
+        writer.bytes(MAGIC);
+        writer.u32(Self::VERSION);
+        writer.u32(self.page_size);
+        writer.u32(self.key_size);
+        // The header lives in page 0, so no node is ever stored there.
+        writer.u64(self.root.unwrap_or(0));
+        writer.u64(self.page_count);
+        writer.u64(self.free_list.unwrap_or(0));
+
+        let checksum = crc32(&[&page[..FILE_HEADER_SIZE - 4]]);
+        page[FILE_HEADER_SIZE - 4..FILE_HEADER_SIZE].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    pub fn decode(page: &[u8]) -> Result<Self> {
+        if page.len() < FILE_HEADER_V1_SIZE {
+            return Err(corrupt("file header is truncated"));
+        }
+
+        let mut reader = Reader::new(page);
+        if reader.bytes(MAGIC.len()) != MAGIC {
+            return Err(corrupt("file does not start with the magic bytes"));
+        }
+
+        let version = reader.u32();
+        let size = match version {
+            1 => FILE_HEADER_V1_SIZE,
+            Self::VERSION => FILE_HEADER_SIZE,
+            _ => return Err(Error::UnsupportedVersion(version)),
+        };
+        if page.len() < size {
+            return Err(corrupt("file header is truncated"));
+        }
+
+        let header = FileHeader {
+            page_size: reader.u32(),
+            key_size: reader.u32(),
+            root: Some(reader.u64()).filter(|&root| root != 0),
+            page_count: reader.u64(),
+            free_list: match version {
+                1 => None,
+                _ => Some(reader.u64()).filter(|&page| page != 0),
+            },
+        };
+
+        if reader.u32() != crc32(&[&page[..size - 4]]) {
+            return Err(corrupt("file header checksum does not match"));
+        }
+
+        Ok(header)
+    }
+}
+
+/// A node as it is stored in a page. Leaf nodes are the ones with no
+/// children.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodePage<K> {
+    pub keys: Vec<K>,
+    pub children: Vec<PageId>,
+}
+
+impl<K: PageKey> NodePage<K> {
+    pub fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// Returns the number of bytes the node takes up in a page, when none of
+    /// its keys overflow.
+    pub fn encoded_size(&self) -> usize {
+        self.encoded_size_with(usize::MAX)
+    }
+
+    /// Returns the number of bytes the node takes up in a page, when the
+    /// cells of its keys are at most `cell_size` bytes long.
+    fn encoded_size_with(&self, cell_size: usize) -> usize {
+        let keys = match K::SIZE {
+            Some(size) => self.keys.len() * size,
+            None => {
+                let (keys, prefix) = shared_prefix(&self.keys, cell_size);
+                cells_size(&keys, prefix, cell_size)
+            }
+        };
+        PAGE_HEADER_SIZE + self.children.len() * size_of::<PageId>() + keys
+    }
+
+    /// Returns the length of the prefix shared by the byte string keys of
+    /// the node, which is stored once rather than in every key, when the
+    /// cells of its keys are at most `cell_size` bytes long. Fixed size keys
+    /// share no prefix.
+    pub fn shared_prefix_len(&self, cell_size: usize) -> usize {
+        match K::SIZE {
+            Some(_) => 0,
+            None => shared_prefix(&self.keys, cell_size).1,
+        }
+    }
+
+    /// Returns the number of keys an intermediate node can hold in a page of
+    /// the given size, together with its children. Byte string keys count
+    /// as cells of the smallest size which still holds a part of a key
+    /// beside its overflow page, and so does their shared prefix.
+    pub fn capacity(page_size: usize) -> usize {
+        match K::SIZE {
+            Some(size) => {
+                let per_key = size + size_of::<PageId>();
+                (page_size - PAGE_HEADER_SIZE - size_of::<PageId>()) / per_key
+            }
+            None => {
+                let per_key = MIN_CELL_SIZE + size_of::<PageId>();
+                ((page_size - PAGE_HEADER_SIZE - 2) / per_key).saturating_sub(1)
+            }
+        }
+    }
+
+    /// Returns the size of the smallest page which holds an intermediate
+    /// node of the given number of keys, counted like in `capacity`.
+    pub fn intermediate_size(keys: usize) -> usize {
+        let children = (keys + 1) * size_of::<PageId>();
+        match K::SIZE {
+            Some(size) => PAGE_HEADER_SIZE + keys * size + children,
+            None => PAGE_HEADER_SIZE + 2 + (keys + 1) * MIN_CELL_SIZE + children,
+        }
+    }
+}
+
+/// The smallest size of the cells of a node, which leaves room for 8 bytes
+/// of a key beside its first overflow page.
+const MIN_CELL_SIZE: usize = CELL_HEADER_SIZE + 8 + size_of::<PageId>();
+
+/// Returns the size of the largest cell of a byte string key in a page of
+/// the given size, such that an intermediate node of `max_keys` keys fits
+/// in the page whatever the lengths of its keys. The shared prefix of the
+/// keys is limited to the same size.
+pub fn max_cell_size(page_size: usize, max_keys: usize) -> usize {
+    let children = (max_keys + 1) * size_of::<PageId>();
+    page_size.saturating_sub(PAGE_HEADER_SIZE + children + 2) / (max_keys + 1)
+}
+
+/// Encodes the byte string keys, and returns them along with the length of
+/// the prefix they share, up to `limit` bytes.
+fn shared_prefix<K: PageKey>(keys: &[K], limit: usize) -> (Vec<Vec<u8>>, usize) {
+    let keys: Vec<_> = keys
+        .iter()
+        .map(|key| {
+            let mut bytes = vec![0; key.encoded_len()];
+            key.encode_into(&mut bytes);
+            bytes
+        })
+        .collect();
+
+    let prefix = match keys.split_first() {
+        Some((first, rest)) => rest.iter().fold(first.len(), |len, key| {
+            first[..len]
+                .iter()
+                .zip(key)
+                .take_while(|(a, b)| a == b)
+                .count()
+        }),
+        None => 0,
+    };
+    (keys, prefix.min(limit).min(u16::MAX as usize))
+}
+
+/// Returns the number of bytes the shared prefix and the cells of the keys
+/// take up.
+fn cells_size(keys: &[Vec<u8>], prefix: usize, cell_size: usize) -> usize {
+    let cells: usize = keys
+        .iter()
+        .map(|key| (CELL_HEADER_SIZE + key.len() - prefix).min(cell_size))
+        .sum();
+    2 + prefix + cells
+}
+
+/// Encodes the node into the given page, filling the rest of it with zeros.
+pub fn encode_page<K: PageKey>(node: &NodePage<K>, page: &mut [u8]) -> Result<()> {
+    encode_page_with(node, page, Compression::None)
+}
+
+/// Encodes the node into the given page like `encode_page`, compressing its
+/// children and keys if that makes them smaller.
+///
+/// The node has to fit into the page uncompressed, so that every page can
+/// be written uncompressed again.
+pub fn encode_page_with<K: PageKey>(
+    node: &NodePage<K>,
+    page: &mut [u8],
+    compression: Compression,
+) -> Result<()> {
+    encode_page_spilling(node, page, compression, usize::MAX, |_| {
+        unreachable!("keys only overflow cells of a limited size")
+    })
+}
+
+/// Encodes the node into the given page like `encode_page_with`, limiting
+/// the cells of byte string keys to `cell_size` bytes. The rest of a longer
+/// key is handed to `spill`, which stores it in overflow pages, and returns
+/// the first one.
+pub fn encode_page_spilling<K: PageKey>(
+    node: &NodePage<K>,
+    page: &mut [u8],
+    compression: Compression,
+    cell_size: usize,
+    mut spill: impl FnMut(&[u8]) -> Result<PageId>,
+) -> Result<()> {
+    let needed = node.encoded_size_with(cell_size);
+    if needed > page.len() {
+        return Err(Error::PageOverflow {
+            needed,
+            page_size: page.len(),
+        });
+    }
+    debug_assert!(node.is_leaf() || node.children.len() == node.keys.len() + 1);
+
+    page.fill(0);
+    let mut writer = Writer::new(page);
+    writer.u8(if node.is_leaf() { LEAF } else { INTERMEDIATE });
+    writer.u8(UNCOMPRESSED);
+    writer.u16(node.keys.len() as u16);
+    writer.u32(0);
+
+    for &child in &node.children {
+        writer.u64(child);
+    }
+    match K::SIZE {
+        Some(size) => {
+            for key in &node.keys {
+                key.encode_into(writer.next(size));
+            }
+        }
+        None => {
+            let (keys, prefix) = shared_prefix(&node.keys, cell_size);
+            writer.u16(prefix as u16);
+            if let Some(first) = keys.first() {
+                writer.bytes(&first[..prefix]);
+            }
+            for key in &keys {
+                let bytes = &key[prefix..];
+                writer.u32(bytes.len() as u32);
+                if CELL_HEADER_SIZE + bytes.len() <= cell_size {
+                    writer.u16(bytes.len() as u16);
+                    writer.bytes(bytes);
+                } else {
+                    let stored = cell_size - CELL_HEADER_SIZE - size_of::<PageId>();
+                    writer.u16(stored as u16);
+                    writer.bytes(&bytes[..stored]);
+                    writer.u64(spill(&bytes[stored..])?);
+                }
+            }
+        }
+    }
+
+    let body = &page[PAGE_HEADER_SIZE..needed];
+    if let Some((flags, compressed)) = compression.compress(body)
+        && 8 + compressed.len() < body.len()
+    {
+        let body_size = body.len() as u32;
+        page[1] = flags;
+        page[PAGE_HEADER_SIZE..].fill(0);
+        let mut writer = Writer::new(&mut page[PAGE_HEADER_SIZE..]);
+        writer.u32(compressed.len() as u32);
+        if K::SIZE.is_none() {
+            writer.u32(body_size);
+        }
+        writer.bytes(&compressed);
+    }
+
+    let checksum = page_checksum(page);
+    page[4..PAGE_HEADER_SIZE].copy_from_slice(&checksum.to_le_bytes());
+    Ok(())
+}
+
+/// Decodes the node stored in the given page, decompressing it if needed.
+///
+/// The node must not have keys which overflow: those are decoded with
+/// `decode_page_spilled`.
+pub fn decode_page<K: PageKey>(page: &[u8]) -> Result<NodePage<K>> {
+    decode_page_spilled(page, |_, _| {
+        Err(corrupt("page has a key which overflows into other pages"))
+    })
+}
+
+/// Decodes the node stored in the given page like `decode_page`, reading the
+/// rest of the keys which overflow with `fetch`, which appends the bytes
+/// stored in the chain of overflow pages starting at the given page.
+pub fn decode_page_spilled<K: PageKey>(
+    page: &[u8],
+    mut fetch: impl FnMut(PageId, &mut Vec<u8>) -> Result<()>,
+) -> Result<NodePage<K>> {
+    if page.len() < PAGE_HEADER_SIZE {
+        return Err(corrupt("page is shorter than its header"));
+    }
+
+    let mut reader = Reader::new(page);
+    let kind = reader.u8();
+    let flags = reader.u8();
+    let key_count = reader.u16() as usize;
+    let checksum = reader.u32();
+
+    if checksum != page_checksum(page) {
+        return Err(corrupt("page checksum does not match"));
+    }
+
+    let child_count = match kind {
+        LEAF => 0,
+        INTERMEDIATE => key_count + 1,
+        _ => return Err(corrupt(&format!("unknown page kind {kind}"))),
+    };
+
+    let rest = &page[PAGE_HEADER_SIZE..];
+    let body = if flags == UNCOMPRESSED {
+        Cow::Borrowed(rest)
+    } else {
+        let mut reader = Reader::new(rest);
+        let size = reader.try_u32()? as usize;
+        let body_size = match K::SIZE {
+            Some(size) => child_count * size_of::<PageId>() + key_count * size,
+            None => reader.try_u32()? as usize,
+        };
+        let compressed = reader
+            .take(size)
+            .map_err(|_| corrupt("compressed size does not fit in the page"))?;
+        Cow::Owned(decompress(flags, compressed, body_size)?)
+    };
+
+    let mut reader = Reader::new(&body);
+    let too_many = || corrupt(&format!("{key_count} keys do not fit in the page"));
+    let children = (0..child_count)
+        .map(|_| reader.try_u64().map_err(|_| too_many()))
+        .collect::<Result<_>>()?;
+
+    let keys = match K::SIZE {
+        Some(size) => (0..key_count)
+            .map(|_| {
+                reader
+                    .take(size)
+                    .map(K::decode_from)
+                    .map_err(|_| too_many())
+            })
+            .collect::<Result<_>>()?,
+        None => {
+            let prefix_len = reader.try_u16()? as usize;
+            let prefix = reader.take(prefix_len)?;
+            (0..key_count)
+                .map(|_| {
+                    let len = reader.try_u32()? as usize;
+                    let stored = reader.try_u16()? as usize;
+                    let mut bytes = prefix.to_vec();
+                    bytes.extend_from_slice(reader.take(stored)?);
+                    if stored < len {
+                        fetch(reader.try_u64()?, &mut bytes)?;
+                    }
+                    if bytes.len() != prefix_len + len {
+                        return Err(corrupt("key length does not match its bytes"));
+                    }
+                    Ok(K::decode_from(&bytes))
+                })
+                .collect::<Result<_>>()?
+        }
+    };
+
+    Ok(NodePage { keys, children })
+}
+
+/// Returns the number of bytes of a key an overflow page holds.
+pub fn overflow_capacity(page_size: usize) -> usize {
+    page_size
+        .saturating_sub(PAGE_HEADER_SIZE + size_of::<PageId>())
+        .min(u16::MAX as usize)
+}
+
+/// Encodes an overflow page, holding the given bytes of a key, and followed
+/// by the given overflow page.
+pub fn encode_overflow(bytes: &[u8], next: Option<PageId>, page: &mut [u8]) -> Result<()> {
+    if bytes.len() > overflow_capacity(page.len()) {
+        return Err(Error::PageOverflow {
+            needed: PAGE_HEADER_SIZE + size_of::<PageId>() + bytes.len(),
+            page_size: page.len(),
+        });
+    }
+
+    page.fill(0);
+    let mut writer = Writer::new(page);
+    writer.u8(OVERFLOW);
+    writer.u8(0);
+    writer.u16(bytes.len() as u16);
+    writer.u32(0);
+    writer.u64(next.unwrap_or(0));
+    writer.bytes(bytes);
+
+    let checksum = page_checksum(page);
+    page[4..PAGE_HEADER_SIZE].copy_from_slice(&checksum.to_le_bytes());
+    Ok(())
+}
+
+/// Decodes an overflow page, returning the bytes it holds and the next
+/// overflow page.
+pub fn decode_overflow(page: &[u8]) -> Result<(&[u8], Option<PageId>)> {
+    if page.len() < PAGE_HEADER_SIZE + size_of::<PageId>() {
+        return Err(corrupt("page is shorter than its header"));
+    }
+
+    let mut reader = Reader::new(page);
+    let kind = reader.u8();
+    reader.u8();
+    let count = reader.u16() as usize;
+    let checksum = reader.u32();
+
+    if checksum != page_checksum(page) {
+        return Err(corrupt("page checksum does not match"));
+    }
+    if kind != OVERFLOW {
+        return Err(corrupt(&format!(
+            "page of kind {kind} is not an overflow page"
+        )));
+    }
+
+    let next = Some(reader.u64()).filter(|&next| next != 0);
+    let bytes = reader
+        .take(count)
+        .map_err(|_| corrupt(&format!("{count} bytes do not fit in the page")))?;
+    Ok((bytes, next))
+}
+
+fn corrupt(reason: &str) -> Error {
+    Error::CorruptPage {
+        reason: reason.to_string(),
+    }
+}
+
+/// Returns the number of page ids a free list page holds.
+pub fn free_list_capacity(page_size: usize) -> usize {
+    page_size.saturating_sub(PAGE_HEADER_SIZE + 8) / size_of::<PageId>()
+}
+
+/// Encodes a free list page, listing the given pages, and followed by the
+/// given free list page.
+pub fn encode_free_list(ids: &[PageId], next: Option<PageId>, page: &mut [u8]) -> Result<()> {
+    if ids.len() > free_list_capacity(page.len()) || ids.len() > u16::MAX as usize {
+        return Err(Error::PageOverflow {
+            needed: PAGE_HEADER_SIZE + 8 + size_of_val(ids),
+            page_size: page.len(),
+        });
+    }
+
+    page.fill(0);
+    let mut writer = Writer::new(page);
+    writer.u8(FREE_LIST);
+    writer.u8(0);
+    writer.u16(ids.len() as u16);
+    writer.u32(0);
+    writer.u64(next.unwrap_or(0));
+    for &id in ids {
+        writer.u64(id);
+    }
+
+    let checksum = page_checksum(page);
+    page[4..PAGE_HEADER_SIZE].copy_from_slice(&checksum.to_le_bytes());
+    Ok(())
+}
+
+/// Decodes a free list page, returning the pages it lists and the next free
+/// list page.
+pub fn decode_free_list(page: &[u8]) -> Result<(Vec<PageId>, Option<PageId>)> {
+    if page.len() < PAGE_HEADER_SIZE + 8 {
+        return Err(corrupt("page is shorter than its header"));
+    }
+
+    let mut reader = Reader::new(page);
+    let kind = reader.u8();
+    reader.u8();
+    let count = reader.u16() as usize;
+    let checksum = reader.u32();
+
+    if checksum != page_checksum(page) {
+        return Err(corrupt("page checksum does not match"));
+    }
+    if kind != FREE_LIST {
+        return Err(corrupt(&format!("page of kind {kind} is not a free list")));
+    }
+    if count > free_list_capacity(page.len()) {
+        return Err(corrupt(&format!("{count} page ids do not fit in the page")));
+    }
+
+    let next = Some(reader.u64()).filter(|&next| next != 0);
+    let ids = (0..count).map(|_| reader.u64()).collect();
+    Ok((ids, next))
+}
+
+/// Computes the checksum of a node page, which covers everything but the
+/// checksum field itself.
+fn page_checksum(page: &[u8]) -> u32 {
+    crc32(&[&page[..4], &page[PAGE_HEADER_SIZE..]])
+}
+
+/// Computes the CRC-32 (IEEE) checksum of the concatenation of the given
+/// byte slices.
+pub(crate) fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in parts.iter().copied().flatten() {
+        crc = (crc >> 8) ^ CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize];
+    }
+    !crc
+}
+
+/// The CRC-32 of every byte value, computed bit by bit at compile time.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut crc = byte as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+            bit += 1;
+        }
+        table[byte] = crc;
+        byte += 1;
+    }
+    table
+};
+
+/// Writes little-endian values to consecutive positions of a buffer.
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> Writer<'a> {
+    fn new(buf: &'a mut [u8]) -> Self {
+        Writer { buf, pos: 0 }
+    }
+
+    fn next(&mut self, len: usize) -> &mut [u8] {
+        let slice = &mut self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        slice
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.next(bytes.len()).copy_from_slice(bytes);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.bytes(&[value]);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+}
+
+/// Reads little-endian values from consecutive positions of a buffer.
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Reader { buf, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> &'a [u8] {
+        let slice = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        slice
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.bytes(1)[0]
+    }
+
+    fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.bytes(2).try_into().unwrap())
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.bytes(4).try_into().unwrap())
+    }
+
+    fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.bytes(8).try_into().unwrap())
+    }
+
+    /// Returns the next bytes, or an error if the buffer ends before them.
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.buf.len() - self.pos < len {
+            return Err(corrupt("page ends in the middle of a value"));
+        }
+        Ok(self.bytes(len))
+    }
+
+    fn try_u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn try_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn try_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE_SIZE: usize = 4096;
+
+    fn round_trip<K: FixedSizeKey + std::fmt::Debug + Eq>(node: NodePage<K>) {
+        let mut page = vec![0xff; PAGE_SIZE];
+        encode_page(&node, &mut page).unwrap();
+        assert_eq!(decode_page::<K>(&page).unwrap(), node);
+    }
+
+    #[test]
+    fn test_leaf_round_trips() {
+        round_trip(NodePage::<u64> {
+            keys: vec![],
+            children: vec![],
+        });
+        round_trip(NodePage::<i32> {
+            keys: vec![-5, 0, 7, i32::MAX],
+            children: vec![],
+        });
+        round_trip(NodePage::<[u8; 3]> {
+            keys: vec![*b"abc", *b"xyz"],
+            children: vec![],
+        });
+    }
+
+    #[test]
+    fn test_intermediate_round_trips() {
+        round_trip(NodePage::<u128> {
+            keys: vec![10, 20, u128::MAX],
+            children: vec![1, 2, 3, u64::MAX],
+        });
+    }
+
+    #[test]
+    fn test_full_page_round_trips() {
+        let capacity = NodePage::<u64>::capacity(PAGE_SIZE);
+        let node = NodePage::<u64> {
+            keys: (0..capacity as u64).collect(),
+            children: (0..=capacity as u64).collect(),
+        };
+        assert!(node.encoded_size() <= PAGE_SIZE);
+        round_trip(node);
+    }
+
+    #[test]
+    fn test_oversized_node_is_rejected() {
+        let node = NodePage::<u64> {
+            keys: (0..600).collect(),
+            children: vec![],
+        };
+        let mut page = vec![0; PAGE_SIZE];
+        let result = encode_page(&node, &mut page);
+        assert!(matches!(
+            result,
+            Err(Error::PageOverflow {
+                needed: 4808,
+                page_size: PAGE_SIZE
+            })
+        ));
+    }
+
+    #[test]
+    fn test_byte_string_keys_round_trip() {
+        round_trip_bytes(NodePage {
+            keys: vec![vec![], b"a".to_vec(), vec![7; 300]],
+            children: vec![],
+        });
+        round_trip_bytes(NodePage {
+            keys: vec![b"path/to/a".to_vec(), b"path/to/b".to_vec()],
+            children: vec![1, 2, 3],
+        });
+    }
+
+    #[test]
+    fn test_shared_prefix_is_stored_once() {
+        let keys: Vec<_> = (0..20)
+            .map(|i| format!("https://example.com/articles/{i:02}").into_bytes())
+            .collect();
+        let node = NodePage {
+            keys: keys.clone(),
+            children: vec![],
+        };
+        let prefix = b"https://example.com/articles/".len();
+        assert_eq!(node.shared_prefix_len(usize::MAX), prefix);
+        assert_eq!(node.shared_prefix_len(10), 10);
+        assert_eq!(
+            node.encoded_size(),
+            PAGE_HEADER_SIZE + 2 + prefix + keys.len() * (CELL_HEADER_SIZE + 2)
+        );
+        round_trip_bytes(node);
+
+        // A single key, or keys without a common start, share nothing.
+        let node = NodePage {
+            keys: vec![b"a".to_vec(), b"b".to_vec()],
+            children: vec![],
+        };
+        assert_eq!(node.shared_prefix_len(usize::MAX), 0);
+        round_trip_bytes(node);
+    }
+
+    fn round_trip_bytes(node: NodePage<Vec<u8>>) {
+        let mut page = vec![0xff; PAGE_SIZE];
+        encode_page(&node, &mut page).unwrap();
+        assert_eq!(decode_page::<Vec<u8>>(&page).unwrap(), node);
+    }
+
+    #[test]
+    fn test_long_keys_spill_into_overflow_pages() {
+        let node = NodePage {
+            keys: vec![b"short".to_vec(), (0..=255).cycle().take(10_000).collect()],
+            children: vec![],
+        };
+        let mut pages = vec![vec![0; PAGE_SIZE]];
+        let mut spilled = |bytes: &[u8]| {
+            let mut next = None;
+            for part in bytes.chunks(overflow_capacity(PAGE_SIZE)).rev() {
+                let mut page = vec![0; PAGE_SIZE];
+                encode_overflow(part, next, &mut page).unwrap();
+                pages.push(page);
+                next = Some(pages.len() as PageId - 1);
+            }
+            Ok(next.unwrap())
+        };
+        let mut page = vec![0; PAGE_SIZE];
+        encode_page_spilling(&node, &mut page, Compression::None, 100, &mut spilled).unwrap();
+        assert_eq!(pages.len(), 4);
+        assert!(
+            page[PAGE_HEADER_SIZE + 2 + 11 + 100..]
+                .iter()
+                .all(|&byte| byte == 0)
+        );
+
+        let fetch = |first, bytes: &mut Vec<u8>| {
+            let mut next = Some(first);
+            while let Some(id) = next {
+                let (part, following) = decode_overflow(&pages[id as usize])?;
+                bytes.extend_from_slice(part);
+                next = following;
+            }
+            Ok(())
+        };
+        assert_eq!(decode_page_spilled(&page, fetch).unwrap(), node);
+        assert!(matches!(
+            decode_page::<Vec<u8>>(&page),
+            Err(Error::CorruptPage { .. })
+        ));
+        assert!(matches!(
+            decode_overflow(&page),
+            Err(Error::CorruptPage { .. })
+        ));
+    }
+
+    #[test]
+    fn test_flipped_bit_is_detected() {
+        let node = NodePage::<u32> {
+            keys: vec![1, 2, 3],
+            children: vec![4, 5, 6, 7],
+        };
+        let mut page = vec![0; PAGE_SIZE];
+        encode_page(&node, &mut page).unwrap();
+
+        for offset in [0, 2, 5, PAGE_HEADER_SIZE, 40, PAGE_SIZE - 1] {
+            let mut corrupted = page.clone();
+            corrupted[offset] ^= 0x10;
+            assert!(
+                matches!(
+                    decode_page::<u32>(&corrupted),
+                    Err(Error::CorruptPage { .. })
+                ),
+                "corruption at offset {offset} went unnoticed"
+            );
+        }
+    }
+
+    /// Returns the compressions enabled in the build.
+    fn compressions() -> Vec<Compression> {
+        vec![
+            Compression::None,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { level: 3 },
+        ]
+    }
+
+    #[test]
+    fn test_compressed_pages_round_trip() {
+        let sparse = NodePage::<u64> {
+            keys: (0..200).map(|k| k * 3).collect(),
+            children: (1000..1201).collect(),
+        };
+        let random = NodePage::<u64> {
+            keys: (0..200u64)
+                .map(|k| k.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+                .collect(),
+            children: vec![],
+        };
+
+        for compression in compressions() {
+            for node in [&sparse, &random] {
+                let mut page = vec![0xff; PAGE_SIZE];
+                encode_page_with(node, &mut page, compression).unwrap();
+                assert_eq!(&decode_page::<u64>(&page).unwrap(), node);
+            }
+
+            // Only the pages which shrink are stored compressed.
+            let mut page = vec![0; PAGE_SIZE];
+            encode_page_with(&sparse, &mut page, compression).unwrap();
+            assert_eq!(page[1] != UNCOMPRESSED, compression != Compression::None);
+            encode_page_with(&random, &mut page, compression).unwrap();
+            assert_eq!(page[1], UNCOMPRESSED);
+
+            let paths = NodePage {
+                keys: (0..50)
+                    .map(|k| format!("/usr/share/doc/{k}").into_bytes())
+                    .collect(),
+                children: vec![],
+            };
+            encode_page_with(&paths, &mut page, compression).unwrap();
+            assert_eq!(decode_page::<Vec<u8>>(&page).unwrap(), paths);
+        }
+    }
+
+    #[test]
+    fn test_unknown_compression_is_rejected() {
+        let node = NodePage::<u32> {
+            keys: vec![1, 2, 3],
+            children: vec![],
+        };
+        let mut page = vec![0; PAGE_SIZE];
+        encode_page(&node, &mut page).unwrap();
+
+        page[1] = 7;
+        let checksum = page_checksum(&page);
+        page[4..PAGE_HEADER_SIZE].copy_from_slice(&checksum.to_le_bytes());
+        assert!(matches!(
+            decode_page::<u32>(&page),
+            Err(Error::CorruptPage { .. })
+        ));
+    }
+
+    #[test]
+    fn test_file_header_round_trips() {
+        for root in [None, Some(1), Some(u64::MAX)] {
+            let header = FileHeader {
+                page_size: PAGE_SIZE as u32,
+                key_size: 8,
+                root,
+                page_count: 42,
+                free_list: root.map(|root| root | 2),
+            };
+            let mut page = vec![0; PAGE_SIZE];
+            header.encode(&mut page);
+            assert_eq!(FileHeader::decode(&page).unwrap(), header);
+        }
+    }
+
+    #[test]
+    fn test_version_1_file_header_is_read() {
+        let header = FileHeader {
+            page_size: PAGE_SIZE as u32,
+            key_size: 8,
+            root: Some(3),
+            page_count: 4,
+            free_list: None,
+        };
+        let mut page = vec![0; PAGE_SIZE];
+        header.encode(&mut page);
+
+        // A version 1 header ends with the checksum where the free list is.
+        page[8..12].copy_from_slice(&1u32.to_le_bytes());
+        let checksum = crc32(&[&page[..36]]);
+        page[36..40].copy_from_slice(&checksum.to_le_bytes());
+        page[40..48].fill(0);
+        assert_eq!(FileHeader::decode(&page).unwrap(), header);
+    }
+
+    #[test]
+    fn test_free_list_round_trips() {
+        let capacity = free_list_capacity(PAGE_SIZE);
+        let ids: Vec<PageId> = (1..=capacity as u64).collect();
+        let mut page = vec![0; PAGE_SIZE];
+        encode_free_list(&ids, Some(7), &mut page).unwrap();
+        assert_eq!(decode_free_list(&page).unwrap(), (ids.clone(), Some(7)));
+
+        encode_free_list(&[], None, &mut page).unwrap();
+        assert_eq!(decode_free_list(&page).unwrap(), (vec![], None));
+
+        let too_many: Vec<PageId> = (0..=capacity as u64).collect();
+        assert!(matches!(
+            encode_free_list(&too_many, None, &mut page),
+            Err(Error::PageOverflow { .. })
+        ));
+
+        // A node page is not taken for a free list.
+        let node = NodePage::<u64> {
+            keys: vec![1, 2],
+            children: vec![],
+        };
+        encode_page(&node, &mut page).unwrap();
+        assert!(matches!(
+            decode_free_list(&page),
+            Err(Error::CorruptPage { .. })
+        ));
+    }
+
+    #[test]
+    fn test_file_header_is_validated() {
+        let header = FileHeader {
+            page_size: PAGE_SIZE as u32,
+            key_size: 8,
+            root: Some(3),
+            page_count: 4,
+            free_list: None,
+        };
+        let mut page = vec![0; PAGE_SIZE];
+        header.encode(&mut page);
+
+        let mut bad_magic = page.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(
+            FileHeader::decode(&bad_magic),
+            Err(Error::CorruptPage { .. })
+        ));
+
+        let mut bad_version = page.clone();
+        bad_version[8] = 9;
+        assert!(matches!(
+            FileHeader::decode(&bad_version),
+            Err(Error::UnsupportedVersion(9))
+        ));
+
+        let mut bad_root = page.clone();
+        bad_root[20] ^= 1;
+        assert!(matches!(
+            FileHeader::decode(&bad_root),
+            Err(Error::CorruptPage { .. })
+        ));
+    }
+
+    #[test]
+    fn test_crc32_matches_known_value() {
+        assert_eq!(crc32(&[b"123456789"]), 0xcbf4_3926);
+        assert_eq!(crc32(&[b"1234", b"", b"56789"]), 0xcbf4_3926);
+    }
+}
@@ -0,0 +1,390 @@
+use super::PageId;
+use crate::{Context, Error, Result};
+use std::borrow::Cow;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+/// The page size used when none is given.
+pub const DEFAULT_PAGE_SIZE: usize = 4096;
+
+/// Reads and writes fixed-size pages of a backing store.
+///
+/// Pages are identified by their index, and allocated pages are numbered
+/// consecutively from zero. Freed pages are handed out again by later
+/// allocations.
+pub trait Pager {
+    fn page_size(&self) -> usize;
+
+    /// Returns the number of pages in the store, including the free ones.
+    fn page_count(&self) -> u64;
+
+    /// Reads the page into the buffer, which is exactly a page long.
+    fn read_page(&self, id: PageId, buf: &mut [u8]) -> Result<()>;
+
+    /// Returns the contents of the page. Pagers which can hand out a page
+    /// without copying it borrow it, the others read it into a buffer.
+    fn view_page(&self, id: PageId) -> Result<Cow<'_, [u8]>> {
+        let mut buf = vec![0; self.page_size()];
+        self.read_page(id, &mut buf)?;
+        Ok(Cow::Owned(buf))
+    }
+
+    /// Writes the buffer, which is exactly a page long, into the page.
+    fn write_page(&mut self, id: PageId, buf: &[u8]) -> Result<()>;
+
+    /// Allocates a page, whose contents are unspecified until it is written.
+    fn allocate(&mut self) -> Result<PageId>;
+
+    /// Gives the page back, so it can be allocated again.
+    fn free(&mut self, id: PageId) -> Result<()>;
+
+    /// Hints that the pages are about to be read, so pagers which can read
+    /// several pages at once may read them ahead.
+    fn prefetch(&self, _ids: &[PageId]) {}
+
+    /// Marks the end of a group of writes which must reach the backing store
+    /// together, or not at all.
+    fn commit(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Makes sure that every written page has reached the backing store.
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A pager whose pages can also be read from other threads while it is
+/// written.
+pub trait SharedPager: Pager {
+    type Reader: PageReader + Send + Sync + 'static;
+
+    /// Returns a handle which reads the pages as they are now. The writes
+    /// made afterwards may or may not be seen through it, so it must only
+    /// be used to read pages which are no longer written.
+    fn reader(&self) -> Result<Self::Reader>;
+}
+
+/// Reads the pages of a `SharedPager`, from any thread.
+pub trait PageReader {
+    fn page_size(&self) -> usize;
+
+    /// Reads the page into the buffer, which is exactly a page long.
+    fn read_page(&self, id: PageId, buf: &mut [u8]) -> Result<()>;
+}
+
+/// A pager which keeps its pages in memory. It is mostly useful for tests.
+///
+/// The pages are shared with its readers, and copied when they are written
+/// while a reader holds them.
+pub struct MemoryPager {
+    page_size: usize,
+    pages: Vec<Arc<[u8]>>,
+    free: Vec<PageId>,
+}
+
+/// Reads the pages a `MemoryPager` held when the reader was made.
+pub struct MemoryPageReader {
+    page_size: usize,
+    pages: Vec<Arc<[u8]>>,
+}
+
+impl MemoryPager {
+    pub fn new(page_size: usize) -> Self {
+        MemoryPager {
+            page_size,
+            pages: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    fn page(&self, id: PageId) -> Result<&[u8]> {
+        self.pages
+            .get(id as usize)
+            .map(|page| &page[..])
+            .ok_or_else(|| out_of_bounds(id))
+    }
+}
+
+impl Default for MemoryPager {
+    fn default() -> Self {
+        Self::new(DEFAULT_PAGE_SIZE)
+    }
+}
+
+impl Pager for MemoryPager {
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    fn page_count(&self) -> u64 {
+        self.pages.len() as u64
+    }
+
+    fn read_page(&self, id: PageId, buf: &mut [u8]) -> Result<()> {
+        buf.copy_from_slice(self.page(id)?);
+        Ok(())
+    }
+
+    fn view_page(&self, id: PageId) -> Result<Cow<'_, [u8]>> {
+        self.page(id).map(Cow::Borrowed)
+    }
+
+    fn write_page(&mut self, id: PageId, buf: &[u8]) -> Result<()> {
+        let page = self
+            .pages
+            .get_mut(id as usize)
+            .ok_or_else(|| out_of_bounds(id))?;
+        Arc::make_mut(page).copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn allocate(&mut self) -> Result<PageId> {
+        if let Some(id) = self.free.pop() {
+            return Ok(id);
+        }
+        self.pages.push(vec![0; self.page_size].into());
+        Ok(self.pages.len() as PageId - 1)
+    }
+
+    fn free(&mut self, id: PageId) -> Result<()> {
+        self.page(id)?;
+        self.free.push(id);
+        Ok(())
+    }
+}
+
+impl SharedPager for MemoryPager {
+    type Reader = MemoryPageReader;
+
+    fn reader(&self) -> Result<MemoryPageReader> {
+        Ok(MemoryPageReader {
+            page_size: self.page_size,
+            pages: self.pages.clone(),
+        })
+    }
+}
+
+impl PageReader for MemoryPageReader {
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    fn read_page(&self, id: PageId, buf: &mut [u8]) -> Result<()> {
+        let page = self
+            .pages
+            .get(id as usize)
+            .ok_or_else(|| out_of_bounds(id))?;
+        buf.copy_from_slice(page);
+        Ok(())
+    }
+}
+
+/// A pager which stores its pages in a file.
+///
+/// The list of free pages is only kept in memory. A `DiskBTreeSet` stores
+/// the list in the file, and hands it back to the pager when it is opened.
+pub struct FilePager {
+    file: File,
+    page_size: usize,
+    page_count: u64,
+    free: Vec<PageId>,
+}
+
+impl FilePager {
+    /// Opens the file at the given path, creating it if it does not exist.
+    pub fn open(path: impl AsRef<Path>, page_size: usize) -> Result<Self> {
+        let path = path.as_ref();
+        Self::open_file(path, page_size).with_key("FilePager::open", path)
+    }
+
+    fn open_file(path: &Path, page_size: usize) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        let len = file.metadata()?.len();
+        if len % page_size as u64 != 0 {
+            return Err(Error::CorruptPage {
+                reason: format!("file length {len} is not a multiple of the page size"),
+            });
+        }
+
+        Ok(FilePager {
+            file,
+            page_size,
+            page_count: len / page_size as u64,
+            free: Vec::new(),
+        })
+    }
+
+    fn seek_to(&self, id: PageId) -> Result<&File> {
+        if id >= self.page_count {
+            return Err(out_of_bounds(id));
+        }
+
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(id * self.page_size as u64))?;
+        Ok(file)
+    }
+}
+
+impl Pager for FilePager {
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    fn page_count(&self) -> u64 {
+        self.page_count
+    }
+
+    fn read_page(&self, id: PageId, buf: &mut [u8]) -> Result<()> {
+        self.seek_to(id)?.read_exact(buf)?;
+        Ok(())
+    }
+
+    fn write_page(&mut self, id: PageId, buf: &[u8]) -> Result<()> {
+        self.seek_to(id)?.write_all(buf)?;
+        Ok(())
+    }
+
+    fn allocate(&mut self) -> Result<PageId> {
+        if let Some(id) = self.free.pop() {
+            return Ok(id);
+        }
+
+        let id = self.page_count;
+        self.file.set_len((id + 1) * self.page_size as u64)?;
+        self.page_count += 1;
+        Ok(id)
+    }
+
+    fn free(&mut self, id: PageId) -> Result<()> {
+        if id >= self.page_count {
+            return Err(out_of_bounds(id));
+        }
+        self.free.push(id);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+/// Reads the pages of the file of a `FilePager` at their offsets, without
+/// moving the position the pager seeks from.
+#[cfg(any(unix, windows))]
+pub struct FilePageReader {
+    file: File,
+    page_size: usize,
+    page_count: u64,
+}
+
+#[cfg(any(unix, windows))]
+impl SharedPager for FilePager {
+    type Reader = FilePageReader;
+
+    fn reader(&self) -> Result<FilePageReader> {
+        Ok(FilePageReader {
+            file: self.file.try_clone()?,
+            page_size: self.page_size,
+            page_count: self.page_count,
+        })
+    }
+}
+
+#[cfg(any(unix, windows))]
+impl PageReader for FilePageReader {
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    fn read_page(&self, id: PageId, buf: &mut [u8]) -> Result<()> {
+        if id >= self.page_count {
+            return Err(out_of_bounds(id));
+        }
+
+        let offset = id * self.page_size as u64;
+        #[cfg(unix)]
+        std::os::unix::fs::FileExt::read_exact_at(&self.file, buf, offset)?;
+        #[cfg(windows)]
+        {
+            let mut read = 0;
+            while read < buf.len() {
+                let n = std::os::windows::fs::FileExt::seek_read(
+                    &self.file,
+                    &mut buf[read..],
+                    offset + read as u64,
+                )?;
+                if n == 0 {
+                    return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+                }
+                read += n;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(super) fn out_of_bounds(id: PageId) -> Error {
+    Error::CorruptPage {
+        reason: format!("page {id} is out of bounds"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exercise(pager: &mut impl Pager) {
+        let size = pager.page_size();
+        let first = pager.allocate().unwrap();
+        let second = pager.allocate().unwrap();
+        assert_eq!((first, second), (0, 1));
+
+        pager.write_page(first, &vec![1; size]).unwrap();
+        pager.write_page(second, &vec![2; size]).unwrap();
+
+        let mut buf = vec![0; size];
+        pager.read_page(first, &mut buf).unwrap();
+        assert!(buf.iter().all(|&byte| byte == 1));
+        assert_eq!(pager.view_page(first).unwrap()[..], buf[..]);
+        pager.read_page(second, &mut buf).unwrap();
+        assert!(buf.iter().all(|&byte| byte == 2));
+
+        pager.free(first).unwrap();
+        assert_eq!(pager.allocate().unwrap(), first);
+        assert_eq!(pager.allocate().unwrap(), 2);
+        assert_eq!(pager.page_count(), 3);
+
+        assert!(pager.read_page(3, &mut buf).is_err());
+        pager.sync().unwrap();
+    }
+
+    #[test]
+    fn test_memory_pager() {
+        exercise(&mut MemoryPager::new(64));
+    }
+
+    #[test]
+    fn test_file_pager() {
+        let path = crate::tests::temp_path("pager");
+
+        exercise(&mut FilePager::open(&path, 64).unwrap());
+
+        let pager = FilePager::open(&path, 64).unwrap();
+        assert_eq!(pager.page_count(), 3);
+        let mut buf = vec![0; 64];
+        pager.read_page(1, &mut buf).unwrap();
+        assert!(buf.iter().all(|&byte| byte == 2));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,228 @@
+use super::ObjectStore;
+use crate::{Error, Result};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::io::{self, Read};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An `ObjectStore` over a bucket of an S3-compatible object store, reached
+/// over HTTP with path-style URLs, and requests signed with AWS Signature
+/// Version 4.
+pub struct S3Store {
+    agent: ureq::Agent,
+    endpoint: String,
+    host: String,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+impl S3Store {
+    /// Returns a store over the bucket at the given endpoint, such as
+    /// `https://s3.eu-west-1.amazonaws.com` or `http://localhost:9000`.
+    pub fn new(
+        endpoint: impl Into<String>,
+        region: impl Into<String>,
+        bucket: impl Into<String>,
+        access_key: impl Into<String>,
+        secret_key: impl Into<String>,
+    ) -> Self {
+        let endpoint = endpoint.into().trim_end_matches('/').to_owned();
+        let host = endpoint
+            .split_once("://")
+            .map_or(&endpoint[..], |(_, rest)| rest)
+            .split('/')
+            .next()
+            .unwrap()
+            .to_owned();
+        S3Store {
+            agent: ureq::Agent::new(),
+            endpoint,
+            host,
+            bucket: bucket.into(),
+            region: region.into(),
+            access_key: access_key.into(),
+            secret_key: secret_key.into(),
+        }
+    }
+
+    /// Returns the request for the object, with the headers which sign it.
+    fn request(&self, method: &str, key: &str, payload: &[u8]) -> ureq::Request {
+        let path = format!("/{}/{}", self.bucket, uri_encode(key));
+        let hash = hex(&Sha256::digest(payload));
+        let (date, time) = timestamp(SystemTime::now());
+        let authorization = self.authorization(method, &path, &hash, &date, &time);
+
+        self.agent
+            .request(method, &format!("{}{path}", self.endpoint))
+            .set("x-amz-content-sha256", &hash)
+            .set("x-amz-date", &time)
+            .set("authorization", &authorization)
+    }
+
+    /// Returns the `authorization` header of a request, which signs its
+    /// `host`, `x-amz-content-sha256`, and `x-amz-date` headers.
+    fn authorization(
+        &self,
+        method: &str,
+        path: &str,
+        hash: &str,
+        date: &str,
+        time: &str,
+    ) -> String {
+        let headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{method}\n{path}\n\nhost:{}\nx-amz-content-sha256:{hash}\nx-amz-date:{time}\n\n{headers}\n{hash}",
+            self.host
+        );
+
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{time}\n{scope}\n{}",
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let key = signing_key(&self.secret_key, date, &self.region, "s3");
+        let signature = hex(&hmac(&key, string_to_sign.as_bytes()));
+
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={headers}, Signature={signature}",
+            self.access_key
+        )
+    }
+}
+
+impl ObjectStore for S3Store {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match self.request("GET", key, &[]).call() {
+            Ok(response) => {
+                let mut bytes = Vec::new();
+                response.into_reader().read_to_end(&mut bytes)?;
+                Ok(Some(bytes))
+            }
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(err) => Err(request_failed(err)),
+        }
+    }
+
+    fn put(&mut self, key: &str, bytes: &[u8]) -> Result<()> {
+        self.request("PUT", key, bytes)
+            .send_bytes(bytes)
+            .map_err(request_failed)?;
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &str) -> Result<()> {
+        match self.request("DELETE", key, &[]).call() {
+            Ok(_) | Err(ureq::Error::Status(404, _)) => Ok(()),
+            Err(err) => Err(request_failed(err)),
+        }
+    }
+}
+
+fn request_failed(err: ureq::Error) -> Error {
+    Error::Io(io::Error::other(err))
+}
+
+fn hmac(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Derives the key requests are signed with on the given day.
+fn signing_key(secret_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let key = hmac(format!("AWS4{secret_key}").as_bytes(), date.as_bytes());
+    let key = hmac(&key, region.as_bytes());
+    let key = hmac(&key, service.as_bytes());
+    hmac(&key, b"aws4_request")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Percent-encodes the key, leaving the slashes which separate its parts.
+fn uri_encode(key: &str) -> String {
+    key.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// Returns the date, as `YYYYMMDD`, and the time, as `YYYYMMDDTHHMMSSZ`, in
+/// UTC.
+fn timestamp(time: SystemTime) -> (String, String) {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs) = (secs / 86400, secs % 86400);
+
+    // The civil date of a day since the epoch, counting in eras of 400
+    // years which start on the 1st of March.
+    let days = days as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    let date = format!("{year:04}{month:02}{day:02}");
+    let time = format!(
+        "{date}T{:02}{:02}{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    );
+    (date, time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_signing_key() {
+        // The example of the AWS documentation on deriving a signing key.
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex(&key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let at = |secs| timestamp(UNIX_EPOCH + Duration::from_secs(secs));
+        assert_eq!(at(0), ("19700101".into(), "19700101T000000Z".into()));
+        assert_eq!(
+            at(951_827_696),
+            ("20000229".into(), "20000229T123456Z".into())
+        );
+        assert_eq!(
+            at(1_735_689_599),
+            ("20241231".into(), "20241231T235959Z".into())
+        );
+    }
+
+    #[test]
+    fn test_uri_encode() {
+        assert_eq!(uri_encode("tree/00ff"), "tree/00ff");
+        assert_eq!(uri_encode("a b+c"), "a%20b%2Bc");
+    }
+}
@@ -0,0 +1,290 @@
+use super::PageId;
+use super::pager::{Pager, out_of_bounds};
+use crate::{Context, Error, Result};
+use io_uring::{IoUring, opcode, squeue, types};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+
+/// The number of operations submitted to the ring at once.
+const QUEUE_DEPTH: u32 = 64;
+
+/// The maximum number of pages which are kept after being read ahead.
+const READ_AHEAD_LIMIT: usize = 256;
+
+/// A pager which stores its pages in a file, like `FilePager`, and reads and
+/// writes them through an io_uring, many at a time.
+///
+/// Writes are kept in memory until the next `commit`, which submits them all
+/// together. Pages announced with `prefetch` are read in one batch too, and
+/// kept until they are read, so a range scan waits once per level of the
+/// tree rather than once per page.
+///
+/// Like with `FilePager`, the list of free pages is only kept in memory.
+pub struct UringPager {
+    file: File,
+    ring: RefCell<IoUring>,
+    page_size: usize,
+    page_count: u64,
+    free: Vec<PageId>,
+    /// The pages written since the last commit.
+    pending: BTreeMap<PageId, Vec<u8>>,
+    /// The pages read ahead, which have not been read since.
+    read_ahead: RefCell<HashMap<PageId, Vec<u8>>>,
+}
+
+impl UringPager {
+    /// Opens the file at the given path, creating it if it does not exist.
+    pub fn open(path: impl AsRef<Path>, page_size: usize) -> Result<Self> {
+        let path = path.as_ref();
+        Self::open_file(path, page_size).with_key("UringPager::open", path)
+    }
+
+    fn open_file(path: &Path, page_size: usize) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        let len = file.metadata()?.len();
+        if len % page_size as u64 != 0 {
+            return Err(Error::CorruptPage {
+                reason: format!("file length {len} is not a multiple of the page size"),
+            });
+        }
+
+        Ok(UringPager {
+            file,
+            ring: RefCell::new(IoUring::new(QUEUE_DEPTH)?),
+            page_size,
+            page_count: len / page_size as u64,
+            free: Vec::new(),
+            pending: BTreeMap::new(),
+            read_ahead: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Reads the pages into the buffers, which are a page long each.
+    fn read_batch(&self, pages: &mut [(PageId, Vec<u8>)]) -> Result<()> {
+        let fd = types::Fd(self.file.as_raw_fd());
+        let entries = pages.iter_mut().map(|(id, buf)| {
+            let offset = *id * self.page_size as u64;
+            opcode::Read::new(fd, buf.as_mut_ptr(), buf.len() as u32)
+                .offset(offset)
+                .build()
+        });
+        self.submit(entries.collect())
+    }
+
+    /// Writes the pages, which are a page long each.
+    fn write_batch(&self, pages: &[(&PageId, &Vec<u8>)]) -> Result<()> {
+        let fd = types::Fd(self.file.as_raw_fd());
+        let entries = pages.iter().map(|(id, buf)| {
+            let offset = **id * self.page_size as u64;
+            opcode::Write::new(fd, buf.as_ptr(), buf.len() as u32)
+                .offset(offset)
+                .build()
+        });
+        self.submit(entries.collect())
+    }
+
+    /// Submits the reads or writes of whole pages, and waits for all of them
+    /// to complete.
+    ///
+    /// The buffers the entries point to must stay alive and in place until
+    /// this returns, which it only does once the ring is done with them.
+    fn submit(&self, entries: Vec<squeue::Entry>) -> Result<()> {
+        let mut ring = self.ring.borrow_mut();
+        for chunk in entries.chunks(QUEUE_DEPTH as usize) {
+            for entry in chunk {
+                // SAFETY: The caller keeps the buffers alive until every
+                // entry of the chunk has completed below.
+                unsafe { ring.submission().push(entry) }
+                    .map_err(|_| io::Error::other("the submission queue is full"))?;
+            }
+            loop {
+                match ring.submit_and_wait(chunk.len()) {
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    result => {
+                        result?;
+                        break;
+                    }
+                }
+            }
+
+            let mut result = Ok(());
+            for completion in ring.completion() {
+                match completion.result() {
+                    res if res < 0 => result = Err(io::Error::from_raw_os_error(-res)),
+                    res if res as usize != self.page_size => {
+                        result = Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+                    }
+                    _ => {}
+                }
+            }
+            result?;
+        }
+        Ok(())
+    }
+}
+
+impl Pager for UringPager {
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    fn page_count(&self) -> u64 {
+        self.page_count
+    }
+
+    fn read_page(&self, id: PageId, buf: &mut [u8]) -> Result<()> {
+        if id >= self.page_count {
+            return Err(out_of_bounds(id));
+        }
+        if let Some(page) = self.pending.get(&id) {
+            buf.copy_from_slice(page);
+            return Ok(());
+        }
+        if let Some(page) = self.read_ahead.borrow_mut().remove(&id) {
+            buf.copy_from_slice(&page);
+            return Ok(());
+        }
+
+        let mut pages = [(id, vec![0; self.page_size])];
+        self.read_batch(&mut pages)?;
+        buf.copy_from_slice(&pages[0].1);
+        Ok(())
+    }
+
+    fn write_page(&mut self, id: PageId, buf: &[u8]) -> Result<()> {
+        if id >= self.page_count {
+            return Err(out_of_bounds(id));
+        }
+        self.read_ahead.get_mut().remove(&id);
+        self.pending.insert(id, buf.to_vec());
+        Ok(())
+    }
+
+    fn allocate(&mut self) -> Result<PageId> {
+        if let Some(id) = self.free.pop() {
+            return Ok(id);
+        }
+
+        let id = self.page_count;
+        self.file.set_len((id + 1) * self.page_size as u64)?;
+        self.page_count += 1;
+        Ok(id)
+    }
+
+    fn free(&mut self, id: PageId) -> Result<()> {
+        if id >= self.page_count {
+            return Err(out_of_bounds(id));
+        }
+        self.read_ahead.get_mut().remove(&id);
+        self.free.push(id);
+        Ok(())
+    }
+
+    fn prefetch(&self, ids: &[PageId]) {
+        let mut read_ahead = self.read_ahead.borrow_mut();
+        let mut pages: Vec<_> = ids
+            .iter()
+            .filter(|&&id| {
+                id < self.page_count
+                    && !self.pending.contains_key(&id)
+                    && !read_ahead.contains_key(&id)
+            })
+            .map(|&id| (id, vec![0; self.page_size]))
+            .collect();
+        pages.truncate(READ_AHEAD_LIMIT);
+        if pages.is_empty() {
+            return;
+        }
+
+        // A failed read ahead is not an error: the pages are read again when
+        // they are needed, which reports it.
+        if self.read_batch(&mut pages).is_ok() {
+            if read_ahead.len() + pages.len() > READ_AHEAD_LIMIT {
+                read_ahead.clear();
+            }
+            read_ahead.extend(pages);
+        }
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        let pages: Vec<_> = self.pending.iter().collect();
+        self.write_batch(&pages)?;
+        self.pending.clear();
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.commit()?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BTreeSet;
+    use crate::btree::DiskBTreeSet;
+    use crate::tests::temp_path;
+
+    #[test]
+    fn test_uring_pager() {
+        let path = temp_path("uring-pager");
+        let mut pager = UringPager::open(&path, 64).unwrap();
+        let a = pager.allocate().unwrap();
+        let b = pager.allocate().unwrap();
+        pager.write_page(a, &[1; 64]).unwrap();
+        pager.write_page(b, &[2; 64]).unwrap();
+
+        // Pages written but not committed yet are read back.
+        let mut buf = [0; 64];
+        pager.read_page(a, &mut buf).unwrap();
+        assert_eq!(buf, [1; 64]);
+        pager.commit().unwrap();
+
+        pager.prefetch(&[a, b]);
+        pager.write_page(b, &[3; 64]).unwrap();
+        pager.commit().unwrap();
+        pager.read_page(b, &mut buf).unwrap();
+        assert_eq!(buf, [3; 64]);
+        assert!(pager.read_page(2, &mut buf).is_err());
+
+        pager.free(a).unwrap();
+        assert_eq!(pager.allocate().unwrap(), a);
+        drop(pager);
+
+        let pager = UringPager::open(&path, 64).unwrap();
+        assert_eq!(pager.page_count(), 2);
+        pager.read_page(b, &mut buf).unwrap();
+        assert_eq!(buf, [3; 64]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_tree_over_uring_pager() {
+        let path = temp_path("uring-tree");
+        let pager = UringPager::open(&path, 4096).unwrap();
+        let mut tree = DiskBTreeSet::<u64, 16, _>::open(pager).unwrap();
+        for key in 0..20_000 {
+            tree.insert(key * 7919 % 20_000).unwrap();
+        }
+        tree.sync().unwrap();
+        drop(tree);
+
+        let pager = UringPager::open(&path, 4096).unwrap();
+        let tree = DiskBTreeSet::<u64, 16, _>::open(pager).unwrap();
+        let keys = tree.range(1000..19_000).unwrap();
+        assert!(keys.into_iter().copied().eq(1000..19_000));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,438 @@
+//! A write-ahead log in front of another pager.
+//!
+//! Written pages are kept in memory and appended to the log when the writes
+//! are committed, and copied into the underlying pager only at checkpoints.
+//! The log is a header followed by a sequence of frames:
+//!
+//! ```text
+//! log header    magic (8) | page size (4)
+//!
+//! page frame    page id (8) | checksum (4) | page contents (page size)
+//! commit frame  u64::MAX (8) | checksum (4)
+//! ```
+//!
+//! The checksum of a page frame covers its page id and contents, and the
+//! checksum of a commit frame covers its marker and the number of page
+//! frames since the previous commit. When the log is opened, the pages of
+//! every complete commit are replayed into the underlying pager, and
+//! anything after the last one, such as a torn write, is discarded.
+
+use super::page::crc32;
+use super::pager::out_of_bounds;
+use super::{FilePager, PageId, Pager};
+use crate::{Context, Error, Result};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"BTREEWAL";
+const LOG_HEADER_SIZE: usize = 12;
+const FRAME_HEADER_SIZE: usize = 12;
+const COMMIT: PageId = u64::MAX;
+
+/// The number of logged page frames after which a checkpoint is made.
+pub const DEFAULT_CHECKPOINT_THRESHOLD: usize = 1024;
+
+/// A pager which logs every committed write before it reaches the
+/// underlying pager, so that a crash never leaves a group of writes half
+/// applied.
+///
+/// Writes become visible immediately, but they are only logged by `commit`,
+/// `sync` or `checkpoint`, each of which commits the pending writes first,
+/// and only durable once `sync` or `checkpoint` returns. Writes which are
+/// still pending when the pager is dropped are lost.
+pub struct WalPager<P = FilePager> {
+    inner: P,
+    log: File,
+    log_len: u64,
+    frames: usize,
+    checkpoint_threshold: usize,
+    dirty: HashMap<PageId, Box<[u8]>>,
+    pending: Vec<PageId>,
+    page_count: u64,
+    free: Vec<PageId>,
+}
+
+impl<P: Pager> WalPager<P> {
+    /// Opens the log at the given path in front of the pager, creating it
+    /// if it does not exist. The committed writes found in the log are
+    /// replayed into the pager.
+    pub fn open(inner: P, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        Self::open_log(inner, path).with_key("WalPager::open", path)
+    }
+
+    fn open_log(inner: P, path: &Path) -> Result<Self> {
+        let mut log = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        let mut contents = Vec::new();
+        log.read_to_end(&mut contents)?;
+
+        let page_size = inner.page_size();
+        let dirty = if contents.len() < LOG_HEADER_SIZE {
+            HashMap::new()
+        } else if &contents[..8] != MAGIC {
+            return Err(Error::CorruptPage {
+                reason: "log has an invalid magic number".to_string(),
+            });
+        } else {
+            let logged = u32::from_le_bytes(contents[8..12].try_into().unwrap());
+            if logged as usize != page_size {
+                return Err(Error::CorruptPage {
+                    reason: format!("log has {logged} byte pages, expected {page_size}"),
+                });
+            }
+            replay(&contents[LOG_HEADER_SIZE..], page_size)
+        };
+
+        let mut pager = WalPager {
+            page_count: inner.page_count(),
+            inner,
+            log,
+            log_len: 0,
+            frames: 0,
+            checkpoint_threshold: DEFAULT_CHECKPOINT_THRESHOLD,
+            dirty,
+            pending: Vec::new(),
+            free: Vec::new(),
+        };
+        pager.checkpoint()?;
+        pager.page_count = pager.inner.page_count();
+        Ok(pager)
+    }
+
+    /// Sets the number of logged page frames after which a checkpoint is
+    /// made.
+    pub fn set_checkpoint_threshold(&mut self, frames: usize) {
+        self.checkpoint_threshold = frames;
+    }
+
+    /// Commits the pending writes, copies every logged page into the
+    /// underlying pager, and empties the log.
+    pub fn checkpoint(&mut self) -> Result<()> {
+        let _span = debug_span!("checkpoint", frames = self.frames);
+        self.commit_pending()?;
+
+        let mut pages: Vec<_> = self.dirty.drain().collect();
+        debug_event!(pages = pages.len(), "copying logged pages");
+        pages.sort_unstable_by_key(|&(id, _)| id);
+        for (id, page) in pages {
+            while self.inner.page_count() <= id {
+                self.inner.allocate()?;
+            }
+            self.inner.write_page(id, &page)?;
+        }
+        self.inner.sync()?;
+
+        let mut header = [0; LOG_HEADER_SIZE];
+        header[..8].copy_from_slice(MAGIC);
+        header[8..].copy_from_slice(&(self.page_size() as u32).to_le_bytes());
+
+        self.log.set_len(0)?;
+        self.log.seek(SeekFrom::Start(0))?;
+        self.log.write_all(&header)?;
+        self.log.sync_data()?;
+        self.log_len = LOG_HEADER_SIZE as u64;
+        self.frames = 0;
+        Ok(())
+    }
+
+    /// Checkpoints the log, and returns the underlying pager.
+    pub fn into_inner(mut self) -> Result<P> {
+        self.checkpoint()?;
+        Ok(self.inner)
+    }
+
+    /// Appends the pending writes to the log, followed by a commit frame.
+    fn commit_pending(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        self.pending.sort_unstable();
+        self.pending.dedup();
+
+        let page_size = self.page_size();
+        let mut buf =
+            Vec::with_capacity((self.pending.len() + 1) * (FRAME_HEADER_SIZE + page_size));
+        for &id in &self.pending {
+            let page = &self.dirty[&id];
+            let id = id.to_le_bytes();
+            buf.extend_from_slice(&id);
+            buf.extend_from_slice(&crc32(&[&id, page]).to_le_bytes());
+            buf.extend_from_slice(page);
+        }
+
+        let marker = COMMIT.to_le_bytes();
+        let count = (self.pending.len() as u32).to_le_bytes();
+        buf.extend_from_slice(&marker);
+        buf.extend_from_slice(&crc32(&[&marker, &count]).to_le_bytes());
+
+        self.log.seek(SeekFrom::Start(self.log_len))?;
+        self.log.write_all(&buf)?;
+        self.log_len += buf.len() as u64;
+        self.frames += self.pending.len();
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+impl<P: Pager> Pager for WalPager<P> {
+    fn page_size(&self) -> usize {
+        self.inner.page_size()
+    }
+
+    fn page_count(&self) -> u64 {
+        self.page_count
+    }
+
+    fn read_page(&self, id: PageId, buf: &mut [u8]) -> Result<()> {
+        match self.dirty.get(&id) {
+            Some(page) => buf.copy_from_slice(page),
+            None => self.inner.read_page(id, buf)?,
+        }
+        Ok(())
+    }
+
+    fn view_page(&self, id: PageId) -> Result<Cow<'_, [u8]>> {
+        match self.dirty.get(&id) {
+            Some(page) => Ok(Cow::Borrowed(page)),
+            None => self.inner.view_page(id),
+        }
+    }
+
+    fn write_page(&mut self, id: PageId, buf: &[u8]) -> Result<()> {
+        if id >= self.page_count {
+            return Err(out_of_bounds(id));
+        }
+        self.dirty.insert(id, buf.into());
+        self.pending.push(id);
+        Ok(())
+    }
+
+    fn allocate(&mut self) -> Result<PageId> {
+        if let Some(id) = self.free.pop() {
+            return Ok(id);
+        }
+        self.page_count += 1;
+        Ok(self.page_count - 1)
+    }
+
+    fn free(&mut self, id: PageId) -> Result<()> {
+        if id >= self.page_count {
+            return Err(out_of_bounds(id));
+        }
+        self.free.push(id);
+        Ok(())
+    }
+
+    fn prefetch(&self, ids: &[PageId]) {
+        let ids: Vec<_> = ids
+            .iter()
+            .copied()
+            .filter(|id| !self.dirty.contains_key(id))
+            .collect();
+        self.inner.prefetch(&ids);
+    }
+
+    /// Logs the writes made since the last commit as a single group, and
+    /// makes a checkpoint if the log grew past the threshold.
+    fn commit(&mut self) -> Result<()> {
+        self.commit_pending()?;
+        if self.frames >= self.checkpoint_threshold {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.commit_pending()?;
+        self.log.sync_data()?;
+        Ok(())
+    }
+}
+
+/// Returns the pages written by the complete commits of the log frames.
+fn replay(mut frames: &[u8], page_size: usize) -> HashMap<PageId, Box<[u8]>> {
+    let mut committed = BTreeMap::new();
+    let mut group = BTreeMap::new();
+    let mut count = 0u32;
+
+    while frames.len() >= FRAME_HEADER_SIZE {
+        let (header, rest) = frames.split_at(FRAME_HEADER_SIZE);
+        let id = header[..8].try_into().unwrap();
+        let checksum = u32::from_le_bytes(header[8..].try_into().unwrap());
+
+        if PageId::from_le_bytes(id) == COMMIT {
+            if checksum != crc32(&[&id, &count.to_le_bytes()]) {
+                break;
+            }
+            committed.append(&mut group);
+            count = 0;
+            frames = rest;
+            continue;
+        }
+
+        if rest.len() < page_size {
+            break;
+        }
+        let (page, rest) = rest.split_at(page_size);
+        if checksum != crc32(&[&id, page]) {
+            break;
+        }
+        group.insert(PageId::from_le_bytes(id), Box::from(page));
+        count += 1;
+        frames = rest;
+    }
+
+    committed.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryPager;
+    use crate::tests::temp_path;
+    use crate::workload::Rng;
+
+    #[test]
+    fn test_committed_writes_are_replayed() {
+        let path = temp_path("wal-replay");
+
+        let mut pager = WalPager::open(MemoryPager::new(64), &path).unwrap();
+        for byte in 0..3 {
+            let id = pager.allocate().unwrap();
+            pager.write_page(id, &[byte; 64]).unwrap();
+        }
+        pager.commit().unwrap();
+        pager.write_page(0, &[9; 64]).unwrap();
+
+        let mut buf = [0; 64];
+        pager.read_page(0, &mut buf).unwrap();
+        assert_eq!(buf, [9; 64]);
+
+        // Nothing reached the underlying pager yet, so replaying the log into
+        // an empty one restores the committed writes, but not the last one.
+        drop(pager);
+
+        let pager = WalPager::open(MemoryPager::new(64), &path).unwrap();
+        assert_eq!(pager.page_count(), 3);
+        for id in 0..3 {
+            pager.read_page(id, &mut buf).unwrap();
+            assert_eq!(buf, [id as u8; 64]);
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoint_empties_the_log() {
+        let path = temp_path("wal-checkpoint");
+
+        let mut pager = WalPager::open(MemoryPager::new(64), &path).unwrap();
+        pager.set_checkpoint_threshold(4);
+        for byte in 0..4 {
+            let id = pager.allocate().unwrap();
+            pager.write_page(id, &[byte; 64]).unwrap();
+            pager.commit().unwrap();
+        }
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len(),
+            LOG_HEADER_SIZE as u64
+        );
+
+        let inner = pager.into_inner().unwrap();
+        let mut buf = [0; 64];
+        inner.read_page(3, &mut buf).unwrap();
+        assert_eq!(buf, [3; 64]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_incomplete_commits_are_discarded() {
+        let frame = (FRAME_HEADER_SIZE + 64) as u64;
+        let commit = FRAME_HEADER_SIZE as u64;
+        let first = LOG_HEADER_SIZE as u64 + 2 * frame + commit;
+
+        for (len, expected) in [
+            (first - 1, 0),
+            (first, 2),
+            (first + frame, 2),
+            (first + frame + commit - 1, 2),
+            (first + frame + commit, 3),
+        ] {
+            let path = temp_path(&format!("wal-torn-{len}"));
+
+            let mut pager = WalPager::open(MemoryPager::new(64), &path).unwrap();
+            for _ in 0..2 {
+                let id = pager.allocate().unwrap();
+                pager.write_page(id, &[1; 64]).unwrap();
+            }
+            pager.commit().unwrap();
+            let id = pager.allocate().unwrap();
+            pager.write_page(id, &[2; 64]).unwrap();
+            pager.sync().unwrap();
+            drop(pager);
+
+            let log = OpenOptions::new().write(true).open(&path).unwrap();
+            log.set_len(len).unwrap();
+            drop(log);
+
+            let pager = WalPager::open(MemoryPager::new(64), &path).unwrap();
+            assert_eq!(pager.page_count(), expected, "log truncated to {len}");
+
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_logs_torn_at_random_offsets_keep_their_complete_commits() {
+        let path = temp_path("wal-torn-random");
+
+        for seed in 0..64 {
+            let mut rng = Rng::new(seed);
+            let mut pager = WalPager::open(MemoryPager::new(64), &path).unwrap();
+            // The log length and page count after each commit.
+            let mut commits = vec![(pager.log_len, 0)];
+            for _ in 0..1 + rng.next() % 6 {
+                for _ in 0..1 + rng.next() % 4 {
+                    let id = pager.allocate().unwrap();
+                    pager.write_page(id, &[seed as u8; 64]).unwrap();
+                }
+                pager.commit().unwrap();
+                commits.push((pager.log_len, pager.page_count()));
+            }
+            pager.sync().unwrap();
+            let log_len = pager.log_len;
+            drop(pager);
+
+            let len = LOG_HEADER_SIZE as u64 + rng.next() % (log_len - LOG_HEADER_SIZE as u64 + 1);
+            let log = OpenOptions::new().write(true).open(&path).unwrap();
+            log.set_len(len).unwrap();
+            drop(log);
+
+            let expected = commits
+                .iter()
+                .take_while(|&&(end, _)| end <= len)
+                .last()
+                .unwrap()
+                .1;
+            let pager = WalPager::open(MemoryPager::new(64), &path).unwrap();
+            assert_eq!(
+                pager.page_count(),
+                expected,
+                "seed {seed}: log truncated to {len} of {log_len}"
+            );
+            drop(pager);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,332 @@
+//! A conformance suite for implementations of the `BTreeSet` trait.
+//!
+//! Every check takes a function which creates an empty tree, so trees which
+//! need arguments to be created, like the ones backed by a pager, can be
+//! checked as well. The checks panic on the first behavior which does not
+//! match the trait, naming the offending key.
+//!
+//! The suite is compiled with the `testsuite` feature, and is meant to be
+//! called from the tests of a crate implementing the trait:
+//!
+//! ```ignore
+//! #[test]
+//! fn my_tree_conforms() {
+//!     btree::testsuite::run_all(MyTree::<usize>::new);
+//! }
+//! ```
+
+use crate::{BTreeSet, Error};
+use std::collections::BTreeSet as StdBTreeSet;
+use std::fmt::Debug;
+
+/// Runs every check of the suite against the trees created by `new`.
+pub fn run_all<T: BTreeSet<Key = usize>>(new: impl Fn() -> T) {
+    empty_tree_does_not_contain_keys(&new);
+    contains_after_insertion_without_splits(&new);
+    contains_after_insertion_with_splits(&new);
+    contains_after_insertion_with_many_splits(&new);
+    duplicate_key_is_rejected_without_splits(&new);
+    duplicate_key_is_rejected_with_splits(&new);
+    duplicate_key_is_rejected_with_many_splits(&new);
+    search_existing_key_returns_it(&new);
+    search_missing_key_fails(&new);
+    remove_existing_key_returns_it(&new);
+    remove_missing_key_fails(&new);
+    multiple_insertions_and_removals(&new);
+    remove_all_keys_in_scrambled_order(&new);
+    stable_after_many_operations(&new);
+    adversarial_orders(&new);
+    mixed_workload(&new);
+}
+
+pub fn empty_tree_does_not_contain_keys<T: BTreeSet<Key = usize>>(new: impl Fn() -> T) {
+    let tree = new();
+    for key in [0, 420, usize::MAX / 2, usize::MAX] {
+        assert!(!tree.contains(&key), "empty tree contains {key}");
+    }
+}
+
+pub fn contains_after_insertion_without_splits<T: BTreeSet<Key = usize>>(new: impl Fn() -> T) {
+    let mut tree = new();
+    let n = tree.max_keys();
+    insert_and_check(&mut tree, 0..n);
+}
+
+pub fn contains_after_insertion_with_splits<T: BTreeSet<Key = usize>>(new: impl Fn() -> T) {
+    let mut tree = new();
+    let n = tree.max_keys() + 1;
+    insert_and_check(&mut tree, 0..n);
+}
+
+pub fn contains_after_insertion_with_many_splits<T: BTreeSet<Key = usize>>(new: impl Fn() -> T) {
+    let mut tree = new();
+    let n = tree.max_keys().pow(4);
+    insert_and_check(&mut tree, 0..n);
+}
+
+pub fn duplicate_key_is_rejected_without_splits<T: BTreeSet<Key = usize>>(new: impl Fn() -> T) {
+    let mut tree = new();
+    let n = tree.max_keys();
+    insert_twice(&mut tree, 0..n);
+}
+
+pub fn duplicate_key_is_rejected_with_splits<T: BTreeSet<Key = usize>>(new: impl Fn() -> T) {
+    let mut tree = new();
+    let n = tree.max_keys() + 1;
+    insert_twice(&mut tree, 0..n);
+}
+
+pub fn duplicate_key_is_rejected_with_many_splits<T: BTreeSet<Key = usize>>(new: impl Fn() -> T) {
+    let mut tree = new();
+    let n = tree.max_keys().pow(4);
+    insert_twice(&mut tree, 0..n);
+}
+
+pub fn search_existing_key_returns_it<T: BTreeSet<Key = usize>>(new: impl Fn() -> T) {
+    let mut tree = new();
+    tree.insert(50).unwrap();
+    assert_eq!(tree.search(&50).unwrap(), &50);
+}
+
+pub fn search_missing_key_fails<T: BTreeSet<Key = usize>>(new: impl Fn() -> T) {
+    let mut tree = new();
+    assert!(matches!(tree.search(&75), Err(Error::KeyNotFound)));
+
+    tree.insert(50).unwrap();
+    assert!(matches!(tree.search(&75), Err(Error::KeyNotFound)));
+}
+
+pub fn remove_existing_key_returns_it<T: BTreeSet<Key = usize>>(new: impl Fn() -> T) {
+    let mut tree = new();
+    tree.insert(20).unwrap();
+    assert!(tree.contains(&20));
+    assert_eq!(tree.remove(&20).unwrap(), 20);
+    assert!(!tree.contains(&20));
+}
+
+pub fn remove_missing_key_fails<T: BTreeSet<Key = usize>>(new: impl Fn() -> T) {
+    let mut tree = new();
+    assert!(matches!(tree.remove(&99), Err(Error::KeyNotFound)));
+
+    tree.insert(20).unwrap();
+    assert!(matches!(tree.remove(&99), Err(Error::KeyNotFound)));
+    assert!(tree.contains(&20));
+}
+
+pub fn multiple_insertions_and_removals<T: BTreeSet<Key = usize>>(new: impl Fn() -> T) {
+    let mut tree = new();
+    let keys = [10, 5, 15, 2, 7, 12, 18];
+    insert_and_check(&mut tree, keys);
+
+    assert_eq!(tree.remove(&7).unwrap(), 7);
+    assert!(!tree.contains(&7));
+    assert_eq!(tree.remove(&18).unwrap(), 18);
+    assert!(!tree.contains(&18));
+
+    for key in [10, 5, 15, 2, 12] {
+        assert!(tree.contains(&key), "tree lost {key}");
+    }
+    assert!(tree.remove(&7).is_err());
+}
+
+pub fn remove_all_keys_in_scrambled_order<T: BTreeSet<Key = usize>>(new: impl Fn() -> T) {
+    let mut tree = new();
+    let n = tree.max_keys().pow(4);
+    let scramble = |i: usize| (i * 7919) % n;
+
+    insert_and_check(&mut tree, (0..n).map(scramble));
+    remove_and_check(&mut tree, (0..n).rev().map(|i| scramble(i * 31 % n)));
+    for key in 0..n {
+        assert!(!tree.contains(&key), "tree still contains {key}");
+    }
+}
+
+pub fn stable_after_many_operations<T: BTreeSet<Key = usize>>(new: impl Fn() -> T) {
+    let mut tree = new();
+    insert_and_check(&mut tree, 0..1000);
+    remove_and_check(&mut tree, (0..1000).step_by(2));
+
+    for key in 0..1000 {
+        assert_eq!(tree.contains(&key), key % 2 == 1, "key {key}");
+    }
+}
+
+/// Inserts and removes keys in orders which are known to stress particular
+/// paths of a B-tree: always splitting the rightmost or leftmost node,
+/// alternating between both ends, and working from the middle outwards.
+pub fn adversarial_orders<T: BTreeSet<Key = usize>>(new: impl Fn() -> T) {
+    let n = new().max_keys().pow(3) + 1;
+    let orders: [(&str, Vec<usize>); 4] = [
+        ("ascending", (0..n).collect()),
+        ("descending", (0..n).rev().collect()),
+        (
+            "alternating",
+            (0..n)
+                .map(|i| if i % 2 == 0 { i / 2 } else { n - 1 - i / 2 })
+                .collect(),
+        ),
+        (
+            "outwards",
+            (0..n)
+                .map(|i| {
+                    let offset = i.div_ceil(2);
+                    if i % 2 == 0 {
+                        n / 2 + offset
+                    } else {
+                        n / 2 - offset
+                    }
+                })
+                .collect(),
+        ),
+    ];
+
+    for (insert_name, insert_order) in &orders {
+        for (remove_name, remove_order) in &orders {
+            let mut tree = new();
+            insert_and_check(&mut tree, insert_order.iter().copied());
+            for key in 0..n {
+                assert!(tree.contains(&key), "inserting {insert_name} lost {key}");
+            }
+
+            for (i, &key) in remove_order.iter().enumerate() {
+                assert_eq!(
+                    tree.remove(&key).unwrap(),
+                    key,
+                    "inserting {insert_name}, removing {remove_name}"
+                );
+                if i % 7 == 0 {
+                    insert_twice(&mut tree, [key]);
+                    tree.remove(&key).unwrap();
+                }
+            }
+            for key in 0..n {
+                assert!(
+                    !tree.contains(&key),
+                    "inserting {insert_name}, removing {remove_name} kept {key}"
+                );
+            }
+        }
+    }
+}
+
+/// Runs a deterministic mix of insertions, removals and searches, comparing
+/// every result against the standard library.
+pub fn mixed_workload<T: BTreeSet<Key = usize>>(new: impl Fn() -> T) {
+    let mut tree = new();
+    let mut reference = StdBTreeSet::new();
+    let range = tree.max_keys().pow(3) * 2;
+    let mut state: u64 = 0x853c_49e6_748f_ea9b;
+
+    for step in 0..range * 8 {
+        // A linear congruential generator, so the workload is the same on
+        // every run.
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        let key = (state >> 33) as usize % range;
+
+        match (state >> 29) % 4 {
+            0 | 1 => check_result(step, tree.insert(key), reference.insert(key).then_some(())),
+            2 => check_result(step, tree.remove(&key), reference.take(&key)),
+            _ => check_result(
+                step,
+                tree.search(&key).copied(),
+                reference.get(&key).copied(),
+            ),
+        }
+    }
+
+    for key in 0..range {
+        assert_eq!(tree.contains(&key), reference.contains(&key), "key {key}");
+    }
+}
+
+fn check_result<V: PartialEq + Debug>(step: usize, actual: crate::Result<V>, expected: Option<V>) {
+    match (actual, expected) {
+        (Ok(actual), Some(expected)) => assert_eq!(actual, expected, "step {step}"),
+        (Err(Error::KeyNotFound | Error::KeyAlreadyExists), None) => {}
+        (actual, expected) => panic!("step {step}: got {actual:?}, expected {expected:?}"),
+    }
+}
+
+fn insert_and_check<T: BTreeSet<Key = usize>>(tree: &mut T, keys: impl IntoIterator<Item = usize>) {
+    for key in keys {
+        assert!(
+            !tree.contains(&key),
+            "tree contains {key} before inserting it"
+        );
+        tree.insert(key).unwrap();
+        assert!(
+            tree.contains(&key),
+            "tree lost {key} right after inserting it"
+        );
+    }
+}
+
+fn insert_twice<T: BTreeSet<Key = usize>>(tree: &mut T, keys: impl IntoIterator<Item = usize>) {
+    for key in keys {
+        tree.insert(key).unwrap();
+        assert!(
+            tree.contains(&key),
+            "tree lost {key} right after inserting it"
+        );
+        assert!(
+            matches!(tree.insert(key), Err(Error::KeyAlreadyExists)),
+            "tree accepted {key} twice"
+        );
+    }
+}
+
+fn remove_and_check<T: BTreeSet<Key = usize>>(tree: &mut T, keys: impl IntoIterator<Item = usize>) {
+    for key in keys {
+        assert_eq!(tree.remove(&key).unwrap(), key);
+        assert!(
+            !tree.contains(&key),
+            "tree still contains {key} after removing it"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::SimpleBTreeSet;
+
+    /// A set which forgets the largest key whenever it holds more than a
+    /// hundred keys.
+    struct Forgetful(StdBTreeSet<usize>);
+
+    impl BTreeSet for Forgetful {
+        type Key = usize;
+        const B: usize = 6;
+
+        fn search(&self, key: &usize) -> crate::Result<&usize> {
+            self.0.get(key).ok_or(Error::KeyNotFound)
+        }
+
+        fn insert(&mut self, key: usize) -> crate::Result<()> {
+            if !self.0.insert(key) {
+                return Err(Error::KeyAlreadyExists);
+            }
+            if self.0.len() > 100 {
+                self.0.pop_last();
+            }
+            Ok(())
+        }
+
+        fn remove(&mut self, key: &usize) -> crate::Result<usize> {
+            self.0.take(key).ok_or(Error::KeyNotFound)
+        }
+    }
+
+    #[test]
+    fn test_run_all_accepts_a_conforming_tree() {
+        run_all(SimpleBTreeSet::<usize, 3>::new);
+    }
+
+    #[test]
+    #[should_panic(expected = "tree lost")]
+    fn test_run_all_rejects_a_tree_losing_keys() {
+        run_all(|| Forgetful(StdBTreeSet::new()));
+    }
+}
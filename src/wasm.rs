@@ -0,0 +1,138 @@
+//! JavaScript bindings, built with the `wasm` feature.
+//!
+//! The crate is not a `cdylib` by default, as that would break builds
+//! without `std`, so the module is built and bound with
+//!
+//! ```sh
+//! cargo rustc --release --lib --target wasm32-unknown-unknown \
+//!     --features wasm --crate-type cdylib
+//! wasm-bindgen --target web --out-dir pkg \
+//!     target/wasm32-unknown-unknown/release/btree.wasm
+//! ```
+//!
+//! The tests in `tests/wasm.rs` run in Node with
+//! `wasm-pack test --node -- --features wasm`.
+//!
+//! The bindings expose a `BTree` class holding numbers, with methods named
+//! after the ones of a JavaScript `Set`:
+//!
+//! ```js
+//! import { BTree } from "btree";
+//!
+//! const tree = new BTree();
+//! tree.insert(3);
+//! tree.has(3); // true
+//! tree.range(0, 10); // Float64Array [3]
+//! tree.delete(3); // true
+//! ```
+
+use crate::BTreeSet;
+use crate::btree::SimpleBTreeSet;
+use std::cmp::Ordering;
+use wasm_bindgen::prelude::*;
+
+/// Orders the numbers like `f64::total_cmp`, which is a total order, so the
+/// numbers can be keys.
+type TotalOrder = fn(&f64, &f64) -> Ordering;
+
+/// A B-tree of numbers, ordered ascending.
+///
+/// Like a JavaScript `Set`, `-0` and `0` are the same key, and so are all
+/// `NaN`s. `NaN` is ordered after every other number.
+#[wasm_bindgen]
+pub struct BTree {
+    tree: SimpleBTreeSet<f64, 6, crate::btree::Global, TotalOrder>,
+}
+
+/// Maps the numbers a `Set` treats as equal to a single key.
+fn normalize(key: f64) -> f64 {
+    if key.is_nan() {
+        f64::NAN
+    } else if key == 0.0 {
+        0.0
+    } else {
+        key
+    }
+}
+
+#[wasm_bindgen]
+impl BTree {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> BTree {
+        BTree {
+            tree: SimpleBTreeSet::with_comparator(f64::total_cmp),
+        }
+    }
+
+    /// Inserts the key, returning `false` if it was already in the tree.
+    pub fn insert(&mut self, key: f64) -> bool {
+        self.tree.insert(normalize(key)).is_ok()
+    }
+
+    /// Returns whether the key is in the tree.
+    pub fn has(&self, key: f64) -> bool {
+        self.tree.contains(&normalize(key))
+    }
+
+    /// Removes the key, returning `false` if it was not in the tree.
+    pub fn delete(&mut self, key: f64) -> bool {
+        self.tree.remove(&normalize(key)).is_ok()
+    }
+
+    /// Returns the keys from `start`, inclusive, to `end`, exclusive, in
+    /// ascending order.
+    pub fn range(&self, start: f64, end: f64) -> Vec<f64> {
+        let (start, end) = (normalize(start), normalize(end));
+        let mut cursor = self.tree.cursor();
+        cursor.seek(&start);
+
+        let mut keys = Vec::new();
+        while let Some(&key) = cursor.key()
+            && key.total_cmp(&end).is_lt()
+        {
+            keys.push(key);
+            cursor.move_next();
+        }
+        keys
+    }
+}
+
+impl Default for BTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_behave_like_a_set() {
+        let mut tree = BTree::new();
+        assert!(tree.insert(1.5));
+        assert!(!tree.insert(1.5));
+        assert!(tree.insert(-0.0));
+        assert!(!tree.insert(0.0));
+        assert!(tree.insert(f64::NAN));
+        assert!(!tree.insert(-f64::NAN));
+
+        assert!(tree.has(0.0) && tree.has(-0.0) && tree.has(f64::NAN));
+        assert!(tree.delete(1.5));
+        assert!(!tree.delete(1.5));
+        assert!(!tree.has(1.5));
+    }
+
+    #[test]
+    fn test_range_is_half_open() {
+        let mut tree = BTree::new();
+        for key in (-50..50).map(f64::from) {
+            tree.insert(key * 0.5);
+        }
+
+        assert_eq!(tree.range(-1.0, 1.0), [-1.0, -0.5, 0.0, 0.5]);
+        assert_eq!(tree.range(0.25, 0.5), Vec::<f64>::new());
+        assert_eq!(tree.range(24.0, f64::INFINITY), [24.0, 24.5]);
+        assert_eq!(tree.range(1.0, -1.0), Vec::<f64>::new());
+    }
+}
@@ -0,0 +1,300 @@
+//! Deterministic streams of tree operations, generated from a seed.
+//!
+//! The same kind and seed always produce the same operations, so a workload
+//! which breaks a tree can be replayed exactly by its seed. Benchmarks take
+//! the keys of a workload, while tests replay the whole operation stream with
+//! `Workload::check`, which reports the seed when it fails.
+
+use crate::BTreeSet;
+use std::collections::BTreeSet as StdBTreeSet;
+
+/// The number of distinct keys a workload draws from, unless told otherwise.
+pub const DEFAULT_KEY_SPACE: u64 = 1 << 20;
+
+/// Describes which keys a workload touches, and how it mixes the operations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// Inserts every key in ascending order.
+    Sequential,
+    /// Draws every key uniformly from the key space, and mostly inserts.
+    Uniform,
+    /// Draws keys from a zipfian distribution with exponent 1, so a few keys
+    /// are hit most of the time. The popular keys are spread across the key
+    /// space instead of being adjacent.
+    Zipfian,
+    /// Draws keys from short, dense runs around randomly chosen centers.
+    Clustered,
+    /// Fills the tree with a quarter of the key space first, and then
+    /// removes far more often than it inserts.
+    DeleteHeavy,
+}
+
+impl Kind {
+    pub const ALL: [Kind; 5] = [
+        Kind::Sequential,
+        Kind::Uniform,
+        Kind::Zipfian,
+        Kind::Clustered,
+        Kind::DeleteHeavy,
+    ];
+}
+
+/// A single operation of a workload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Insert(u64),
+    Remove(u64),
+    Search(u64),
+}
+
+impl Op {
+    /// Returns the key the operation targets.
+    pub fn key(&self) -> u64 {
+        match *self {
+            Op::Insert(key) | Op::Remove(key) | Op::Search(key) => key,
+        }
+    }
+}
+
+/// A seeded workload of a given kind.
+#[derive(Clone, Debug)]
+pub struct Workload {
+    kind: Kind,
+    seed: u64,
+    key_space: u64,
+}
+
+impl Workload {
+    pub fn new(kind: Kind, seed: u64) -> Self {
+        Workload {
+            kind,
+            seed,
+            key_space: DEFAULT_KEY_SPACE,
+        }
+    }
+
+    /// Limits the keys to `0..key_space`. Smaller key spaces revisit keys
+    /// more often.
+    pub fn with_key_space(mut self, key_space: u64) -> Self {
+        assert!(key_space > 0, "the key space must not be empty");
+        self.key_space = key_space;
+        self
+    }
+
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Returns the endless stream of operations of the workload.
+    pub fn ops(&self) -> Ops {
+        Ops {
+            kind: self.kind,
+            key_space: self.key_space,
+            rng: Rng::new(self.seed),
+            step: 0,
+            center: 0,
+            run: 0,
+        }
+    }
+
+    /// Returns the keys targeted by the first `n` operations.
+    pub fn keys(&self, n: usize) -> Vec<u64> {
+        self.ops().take(n).map(|op| op.key()).collect()
+    }
+
+    /// Applies the first `n` operations to the tree, and checks every result
+    /// against the standard library. On failure, including a panic within the
+    /// tree itself, the kind and the seed of the workload are printed.
+    pub fn check<T: BTreeSet<Key = u64>>(&self, tree: &mut T, n: usize) {
+        let _reporter = SeedReporter(self);
+        let mut reference = StdBTreeSet::new();
+
+        for (step, op) in self.ops().take(n).enumerate() {
+            let agrees = match op {
+                Op::Insert(key) => tree.insert(key).is_ok() == reference.insert(key),
+                Op::Remove(key) => tree.remove(&key).ok() == reference.take(&key),
+                Op::Search(key) => tree.search(&key).ok() == reference.get(&key),
+            };
+            assert!(agrees, "step {step}: {op:?} disagrees with the reference");
+        }
+    }
+}
+
+/// Prints the workload if it is dropped while panicking.
+struct SeedReporter<'a>(&'a Workload);
+
+impl Drop for SeedReporter<'_> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            let Workload {
+                kind,
+                seed,
+                key_space,
+            } = self.0;
+            eprintln!("{kind:?} workload failed with seed {seed:#x} over {key_space} keys");
+        }
+    }
+}
+
+/// The operations of a workload, as returned by `Workload::ops`.
+pub struct Ops {
+    kind: Kind,
+    key_space: u64,
+    rng: Rng,
+    step: u64,
+    /// The center of the current run of a clustered workload.
+    center: u64,
+    /// The number of keys left in the current run of a clustered workload.
+    run: u32,
+}
+
+impl Ops {
+    fn uniform_key(&mut self) -> u64 {
+        self.rng.next() % self.key_space
+    }
+
+    fn zipfian_key(&mut self) -> u64 {
+        // Inverting the continuous approximation of the distribution, whose
+        // density is proportional to 1 / rank.
+        let rank = (self.key_space as f64).powf(self.rng.next_f64()) as u64 - 1;
+        rank.wrapping_mul(0x9e37_79b9_7f4a_7c15) % self.key_space
+    }
+
+    fn clustered_key(&mut self) -> u64 {
+        if self.run == 0 {
+            self.center = self.uniform_key();
+            self.run = 1 + (self.rng.next() % 64) as u32;
+        }
+        self.run -= 1;
+        self.center.wrapping_add(self.rng.next() % 32) % self.key_space
+    }
+}
+
+impl Iterator for Ops {
+    type Item = Op;
+
+    fn next(&mut self) -> Option<Op> {
+        let step = self.step;
+        self.step += 1;
+
+        // The percentage of removals and searches, the rest being insertions.
+        let (removes, searches, key) = match self.kind {
+            Kind::Sequential => return Some(Op::Insert(step % self.key_space)),
+            Kind::Uniform => (20, 20, self.uniform_key()),
+            Kind::Zipfian => (20, 20, self.zipfian_key()),
+            Kind::Clustered => (20, 20, self.clustered_key()),
+            Kind::DeleteHeavy if step < self.key_space / 4 => (0, 0, self.uniform_key()),
+            Kind::DeleteHeavy => (70, 10, self.uniform_key()),
+        };
+
+        let roll = self.rng.next() % 100;
+        Some(if roll < removes {
+            Op::Remove(key)
+        } else if roll < removes + searches {
+            Op::Search(key)
+        } else {
+            Op::Insert(key)
+        })
+    }
+}
+
+/// A small xorshift generator, so the workloads are reproducible without
+/// pulling in a random number crate.
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    /// Scrambles the seed, so that similar seeds produce unrelated streams,
+    /// and the state is never zero.
+    pub(crate) fn new(seed: u64) -> Self {
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        Rng((z ^ (z >> 31)) | 1)
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::SimpleBTreeSet;
+
+    #[test]
+    fn test_same_seed_produces_same_ops() {
+        for kind in Kind::ALL {
+            let a = Workload::new(kind, 42).keys(1000);
+            let b = Workload::new(kind, 42).keys(1000);
+            let c = Workload::new(kind, 43).keys(1000);
+            assert_eq!(a, b, "{kind:?}");
+            if kind != Kind::Sequential {
+                assert_ne!(a, c, "{kind:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_keys_stay_within_key_space() {
+        for kind in Kind::ALL {
+            let workload = Workload::new(kind, 7).with_key_space(100);
+            assert!(
+                workload.keys(10_000).iter().all(|&key| key < 100),
+                "{kind:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_zipfian_keys_are_skewed() {
+        let keys = Workload::new(Kind::Zipfian, 1)
+            .with_key_space(10_000)
+            .keys(10_000);
+        let mut counts = std::collections::HashMap::new();
+        for key in keys {
+            *counts.entry(key).or_insert(0) += 1;
+        }
+
+        // The most popular key alone takes about a tenth of the operations.
+        let top = counts.values().max().unwrap();
+        assert!(*top > 500, "most popular key was hit {top} times");
+    }
+
+    #[test]
+    fn test_delete_heavy_mostly_removes_after_filling() {
+        let ops: Vec<_> = Workload::new(Kind::DeleteHeavy, 3)
+            .with_key_space(4000)
+            .ops()
+            .take(11_000)
+            .collect();
+
+        assert!(ops[..1000].iter().all(|op| matches!(op, Op::Insert(_))));
+        let removes = ops[1000..]
+            .iter()
+            .filter(|op| matches!(op, Op::Remove(_)))
+            .count();
+        assert!((6500..7500).contains(&removes), "{removes} removals");
+    }
+
+    #[test]
+    fn test_check_passes_for_a_correct_tree() {
+        for kind in Kind::ALL {
+            let mut tree = SimpleBTreeSet::<u64, 2>::new();
+            Workload::new(kind, 0xdead_beef)
+                .with_key_space(500)
+                .check(&mut tree, 5000);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Checks that the `heap_size` of the in-memory trees matches the bytes they
+//! actually hold on to, as counted by an instrumented global allocator.
+
+use btree::BTreeSet;
+use btree::btree::{
+    ArenaBTreeSet, BPlusTreeSet, EytzingerLayout, Global, OrdComparator, PersistentBTreeSet,
+    SimpleBTreeSet,
+};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+/// Counts the bytes allocated by each thread, so tests running in parallel
+/// do not see each other's allocations.
+struct Counting;
+
+thread_local! {
+    static ALLOCATED: Cell<isize> = const { Cell::new(0) };
+}
+
+fn track(bytes: isize) {
+    // The counter may already be gone while the thread is being torn down.
+    let _ = ALLOCATED.try_with(|allocated| allocated.set(allocated.get() + bytes));
+}
+
+// SAFETY: Every call is forwarded to the system allocator unchanged.
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        track(layout.size() as isize);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        track(-(layout.size() as isize));
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        track(new_size as isize - layout.size() as isize);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static COUNTING: Counting = Counting;
+
+/// Builds a tree, inserting and then removing keys so that it holds on to
+/// freed space as well, and returns it with the bytes it still allocates.
+fn built<T: BTreeSet<Key = u64>>(mut tree: T) -> (T, usize) {
+    let before = ALLOCATED.with(Cell::get);
+    for key in 0..2000 {
+        tree.insert(key * 7919 % 2003).unwrap();
+    }
+    for key in (0..2003).step_by(3) {
+        let _ = tree.remove(&key);
+    }
+    let allocated = ALLOCATED.with(Cell::get) - before;
+    (tree, allocated as usize)
+}
+
+#[test]
+fn simple_heap_size_is_exact() {
+    let (tree, allocated) = built(SimpleBTreeSet::<u64, 3>::new());
+    assert_eq!(tree.heap_size(), allocated);
+
+    let eytzinger = SimpleBTreeSet::<u64, 3, Global, OrdComparator, EytzingerLayout>::default();
+    let (tree, allocated) = built(eytzinger);
+    assert_eq!(tree.heap_size(), allocated);
+    assert!(tree.heap_size() > tree.stats().heap_bytes);
+}
+
+#[test]
+fn arena_heap_size_is_exact() {
+    let (tree, allocated) = built(ArenaBTreeSet::<u64, 3>::new());
+    assert_eq!(tree.heap_size(), allocated);
+
+    let (tree, allocated) = built(BPlusTreeSet::<u64, 3>::new());
+    assert_eq!(tree.heap_size(), allocated);
+}
+
+#[test]
+fn persistent_heap_size_is_exact() {
+    let (tree, allocated) = built(PersistentBTreeSet::<u64, 3>::new());
+    assert_eq!(tree.heap_size(), allocated);
+}
+
+#[test]
+fn empty_trees_allocate_nothing() {
+    assert_eq!(SimpleBTreeSet::<u64>::new().heap_size(), 0);
+    assert_eq!(ArenaBTreeSet::<u64>::new().heap_size(), 0);
+    assert_eq!(BPlusTreeSet::<u64>::new().heap_size(), 0);
+    assert_eq!(PersistentBTreeSet::<u64>::new().heap_size(), 0);
+}
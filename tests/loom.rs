@@ -0,0 +1,244 @@
+//! Model checks of the critical interleavings of the concurrent trees, run
+//! with `RUSTFLAGS="--cfg loom" cargo test --release --features shared --test
+//! loom`.
+//!
+//! Under `--cfg loom`, the concurrent trees synchronize through loom, which
+//! runs every test body once per interleaving of its threads, up to the
+//! preemption bound. Loom only reorders a load after a conflicting write of
+//! another thread if the load runs later on the first schedule, so reads are
+//! made by the spawned thread, which starts after the main one. Only this
+//! suite should be run with the cfg set, since the primitives of loom cannot
+//! be used outside of a model.
+//!
+//! The Bw-tree and the optimistic tree only ever split nodes, leaving emptied
+//! ones in place, so the only merges to race with a scan are the ones within
+//! a shard of a `ShardedBTreeSet`.
+
+#![cfg(loom)]
+
+#[cfg(feature = "shared")]
+use btree::btree::SharedBTreeSet;
+use btree::btree::{BwTreeSet, OlcBTreeSet, ShardedBTreeSet};
+use loom::model::Builder;
+use loom::sync::Arc;
+use loom::thread;
+
+/// Leaves hold at most three keys, and a leaf is consolidated, and split,
+/// by the eighth change made to it.
+type BwTree = BwTreeSet<u32, 2>;
+
+fn model(test: impl Fn() + Sync + Send + 'static) {
+    let mut builder = Builder::new();
+    builder.preemption_bound = Some(2);
+    builder.check(test);
+}
+
+/// Returns a tree whose single leaf is one change away from being split.
+fn tree_about_to_split() -> BwTree {
+    let tree = BwTree::with_mapping_table_size(8);
+    for key in 0..7 {
+        tree.insert(key * 10).unwrap();
+    }
+    tree
+}
+
+#[test]
+fn bw_concurrent_inserts_into_one_leaf() {
+    model(|| {
+        let tree = Arc::new(BwTree::with_mapping_table_size(8));
+
+        let other = Arc::clone(&tree);
+        let handle = thread::spawn(move || other.insert(1).unwrap());
+        tree.insert(2).unwrap();
+        handle.join().unwrap();
+
+        assert!(tree.iter().copied().eq([1, 2]));
+    });
+}
+
+#[test]
+fn bw_racing_inserts_of_one_key() {
+    model(|| {
+        let tree = Arc::new(BwTree::with_mapping_table_size(8));
+
+        let other = Arc::clone(&tree);
+        let handle = thread::spawn(move || other.insert(1).is_ok());
+        let inserted = tree.insert(1).is_ok();
+        let other_inserted = handle.join().unwrap();
+
+        assert!(inserted != other_inserted);
+        assert!(tree.contains(&1));
+    });
+}
+
+#[test]
+fn bw_search_during_split() {
+    model(|| {
+        let tree = Arc::new(tree_about_to_split());
+
+        let other = Arc::clone(&tree);
+        let handle = thread::spawn(move || {
+            for key in [0, 30, 60] {
+                assert_eq!(other.search(&key).unwrap(), &key);
+            }
+        });
+        tree.insert(35).unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(tree.search(&35).unwrap(), &35);
+    });
+}
+
+#[test]
+fn bw_scan_during_split() {
+    model(|| {
+        let tree = Arc::new(tree_about_to_split());
+
+        let other = Arc::clone(&tree);
+        let handle = thread::spawn(move || {
+            let keys: Vec<u32> = other.iter().copied().filter(|&key| key != 35).collect();
+            assert_eq!(keys, [0, 10, 20, 30, 40, 50, 60]);
+        });
+        tree.insert(35).unwrap();
+        handle.join().unwrap();
+    });
+}
+
+#[test]
+fn bw_removal_during_split() {
+    model(|| {
+        let tree = Arc::new(tree_about_to_split());
+
+        let other = Arc::clone(&tree);
+        let handle = thread::spawn(move || other.insert(35).unwrap());
+        assert_eq!(tree.remove(&60).unwrap(), 60);
+        handle.join().unwrap();
+
+        assert!(tree.iter().copied().eq([0, 10, 20, 30, 35, 40, 50]));
+    });
+}
+
+#[test]
+fn sharded_scan_during_removal() {
+    model(|| {
+        let tree = Arc::new(ShardedBTreeSet::<u32>::with_boundaries(vec![10]));
+        for key in [1, 5, 11, 15] {
+            tree.insert(key).unwrap();
+        }
+
+        let other = Arc::clone(&tree);
+        let handle = thread::spawn(move || {
+            let keys: Vec<u32> = other.iter().collect();
+            assert!(keys.is_sorted_by(|a, b| a < b));
+            assert!([1, 5, 15].iter().all(|key| keys.contains(key)));
+        });
+        tree.remove(&11).unwrap();
+        tree.insert(12).unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(tree.iter().collect::<Vec<_>>(), [1, 5, 12, 15]);
+    });
+}
+
+#[test]
+fn sharded_scan_during_merge() {
+    model(|| {
+        // The first shard holds three leaves under a root, and removing its
+        // first key merges two of them.
+        let tree = Arc::new(ShardedBTreeSet::<u32, 2>::with_boundaries(vec![10]));
+        for key in [1, 2, 3, 4, 5, 6, 11] {
+            tree.insert(key).unwrap();
+        }
+
+        let other = Arc::clone(&tree);
+        let handle = thread::spawn(move || {
+            let keys: Vec<u32> = other.iter().filter(|&key| key != 1).collect();
+            assert_eq!(keys, [2, 3, 4, 5, 6, 11]);
+        });
+        tree.remove(&1).unwrap();
+        handle.join().unwrap();
+
+        assert!(tree.iter().eq([2, 3, 4, 5, 6, 11]));
+    });
+}
+
+/// Returns an optimistic tree whose single leaf is full, so the next insert
+/// splits it.
+fn olc_about_to_split() -> OlcBTreeSet<u32, 2> {
+    let tree = OlcBTreeSet::new();
+    for key in 0..3 {
+        tree.insert(key * 10).unwrap();
+    }
+    tree
+}
+
+#[test]
+fn olc_search_during_split() {
+    model(|| {
+        let tree = Arc::new(olc_about_to_split());
+
+        let other = Arc::clone(&tree);
+        let handle = thread::spawn(move || {
+            for key in [0, 10, 20] {
+                assert!(other.contains(&key));
+            }
+        });
+        tree.insert(15).unwrap();
+        handle.join().unwrap();
+
+        assert!(tree.contains(&15));
+    });
+}
+
+#[test]
+fn olc_scan_during_split() {
+    model(|| {
+        let tree = Arc::new(olc_about_to_split());
+
+        let other = Arc::clone(&tree);
+        let handle = thread::spawn(move || {
+            let keys: Vec<u32> = other.iter().filter(|&key| key != 15).collect();
+            assert_eq!(keys, [0, 10, 20]);
+        });
+        tree.insert(15).unwrap();
+        handle.join().unwrap();
+    });
+}
+
+#[cfg(feature = "shared")]
+#[test]
+fn shared_racing_writers() {
+    model(|| {
+        let tree = Arc::new(SharedBTreeSet::<u32>::new());
+        tree.insert(1).unwrap();
+
+        let other = Arc::clone(&tree);
+        let handle = thread::spawn(move || {
+            other.insert(2).unwrap();
+            assert!(other.contains(&1));
+        });
+        tree.insert(3).unwrap();
+        handle.join().unwrap();
+
+        assert!(tree.snapshot().iter().copied().eq([1, 2, 3]));
+    });
+}
+
+#[cfg(feature = "shared")]
+#[test]
+fn shared_snapshot_during_write() {
+    model(|| {
+        let tree = Arc::new(SharedBTreeSet::<u32>::new());
+        tree.insert(1).unwrap();
+
+        let other = Arc::clone(&tree);
+        let handle = thread::spawn(move || {
+            let snapshot = other.snapshot();
+            let keys: Vec<u32> = snapshot.iter().copied().collect();
+            assert!(keys == [1] || keys == [1, 2] || keys == [2]);
+        });
+        tree.insert(2).unwrap();
+        tree.remove(&1).unwrap();
+        handle.join().unwrap();
+    });
+}
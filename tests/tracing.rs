@@ -0,0 +1,118 @@
+//! Checks the structural events the trees and pagers emit through `tracing`,
+//! run with `cargo test --features tracing --test tracing`.
+
+#![cfg(feature = "tracing")]
+
+use btree::BTreeSet;
+use btree::btree::SimpleBTreeSet;
+use btree::storage::{CachedPager, Eviction, MemoryPager, Pager, WalPager};
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+/// Records the messages of the events, and the names of the spans they were
+/// emitted in.
+#[derive(Clone, Default)]
+struct Recorder {
+    spans: Arc<Mutex<Vec<&'static str>>>,
+    entered: Arc<Mutex<Vec<u64>>>,
+    events: Arc<Mutex<Vec<String>>>,
+}
+
+struct Message<'a>(&'a mut String);
+
+impl Visit for Message<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            *self.0 = format!("{value:?}");
+        }
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut spans = self.spans.lock().unwrap();
+        spans.push(span.metadata().name());
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut message = String::new();
+        event.record(&mut Message(&mut message));
+        if let Some(&span) = self.entered.lock().unwrap().last() {
+            let name = self.spans.lock().unwrap()[span as usize - 1];
+            message = format!("{name}: {message}");
+        }
+        self.events.lock().unwrap().push(message);
+    }
+
+    fn enter(&self, span: &Id) {
+        self.entered.lock().unwrap().push(span.into_u64());
+    }
+
+    fn exit(&self, _span: &Id) {
+        self.entered.lock().unwrap().pop();
+    }
+}
+
+/// Runs the function with the recorder as the subscriber of this thread, and
+/// returns the events it emitted.
+fn events(f: impl FnOnce()) -> Vec<String> {
+    let recorder = Recorder::default();
+    tracing::subscriber::with_default(recorder.clone(), f);
+    recorder.events.lock().unwrap().clone()
+}
+
+#[test]
+fn simple_tree_reports_structural_changes() {
+    let mut tree = SimpleBTreeSet::<u32, 2>::new();
+    let inserted = events(|| {
+        for key in 0..4 {
+            tree.insert(key).unwrap();
+        }
+    });
+    assert_eq!(inserted, ["node split", "root grew"]);
+
+    let removed = events(|| {
+        tree.remove(&3).unwrap();
+        tree.remove(&2).unwrap();
+    });
+    assert_eq!(removed, ["nodes merged", "root shrank"]);
+}
+
+#[test]
+fn cache_reports_evictions() {
+    let mut pager = CachedPager::new(MemoryPager::new(64), 2 * 64, Eviction::Lru);
+    let evicted = events(|| {
+        for byte in 0..3 {
+            let id = pager.allocate().unwrap();
+            pager.write_page(id, &[byte; 64]).unwrap();
+        }
+    });
+    assert_eq!(evicted, ["page evicted"]);
+}
+
+#[test]
+fn wal_checkpoints_run_in_a_span() {
+    let path = std::env::temp_dir().join(format!("btree-tracing-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let mut pager = WalPager::open(MemoryPager::new(64), &path).unwrap();
+    let id = pager.allocate().unwrap();
+    pager.write_page(id, &[1; 64]).unwrap();
+    pager.commit().unwrap();
+    let checkpointed = events(|| pager.checkpoint().unwrap());
+    assert_eq!(checkpointed, ["checkpoint: copying logged pages"]);
+
+    drop(pager);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,28 @@
+//! Tests of the JavaScript bindings, run in Node with
+//! `wasm-pack test --node -- --features wasm`.
+
+#![cfg(all(target_arch = "wasm32", feature = "wasm"))]
+
+use btree::wasm::BTree;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn insert_has_delete() {
+    let mut tree = BTree::new();
+    for key in 0..1000 {
+        assert!(tree.insert(f64::from(key)));
+    }
+    assert!(!tree.insert(500.0));
+    assert!(tree.has(999.0));
+    assert!(tree.delete(999.0));
+    assert!(!tree.has(999.0));
+}
+
+#[wasm_bindgen_test]
+fn range_returns_sorted_keys() {
+    let mut tree = BTree::new();
+    for key in (0..100).rev() {
+        tree.insert(f64::from(key));
+    }
+    assert_eq!(tree.range(10.0, 15.0), [10.0, 11.0, 12.0, 13.0, 14.0]);
+}